@@ -7,22 +7,23 @@ use parking_lot::RwLock;
 use serde::{de::DeserializeOwned, Serialize};
 
 use primitives::hash::CryptoHash;
-use primitives::traits::Signer;
+use primitives::signer::InMemorySigner;
 use primitives::types::{BlockId, PartialSignature};
 use primitives::utils::index_to_bytes;
 use primitives::serialize::{Encode, Decode};
-use storage::{read_with_cache, write_with_cache, Storage};
+use storage::{read_with_cache, remove_with_cache, write_with_cache, Storage};
 use serde_derive::{Serialize, Deserialize};
 use log::info;
 
 pub use crate::types::{
     ShardBlock, ShardBlockHeader, SignedShardBlock, ReceiptBlock,
-    ChainPayload, SignedShardBlockHeader
+    ChainPayload, SignedShardBlockHeader, Challenge, ShardBlockPart
 };
 
 pub mod types;
 
 const BLOCKCHAIN_BEST_BLOCK: &[u8] = b"best";
+const BLOCKCHAIN_FINALIZED_BLOCK: &[u8] = b"final";
 
 /// Trait that abstracts ``Header"
 pub trait SignedHeader: Debug + Clone + Encode + Decode + Send + Sync + Eq + Serialize + DeserializeOwned + 'static
@@ -52,7 +53,7 @@ pub trait SignedBlock: Debug + Clone + Encode + Decode + Send + Sync + Eq + Seri
     fn block_hash(&self) -> CryptoHash;
 
     /// Signs this block with given signer and returns part of multi signature.
-    fn sign(&self, signer: &Signer) -> PartialSignature {
+    fn sign(&self, signer: &InMemorySigner) -> PartialSignature {
         signer.sign(&self.block_hash())
     }
 
@@ -71,6 +72,19 @@ pub struct BlockIndex<B> {
     pub cumulative_weight: u128,
 }
 
+/// Change of the canonical chain after a block is inserted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeadUpdate<B> {
+    /// The block didn't become the best block, e.g. it is on a lighter fork.
+    Unchanged,
+    /// The block extends the canonical chain.
+    Extended,
+    /// The block is the head of a heavier fork. `reverted` are the blocks that are no longer
+    /// canonical, from the old head down, and `applied` the new canonical blocks from the fork
+    /// point up, ending with the block.
+    Reorg { reverted: Vec<B>, applied: Vec<B> },
+}
+
 /// General BlockChain container.
 pub struct BlockChain<B: SignedBlock> {
     /// Storage backend.
@@ -80,14 +94,17 @@ pub struct BlockChain<B: SignedBlock> {
     /// Best block key of current blockchain. Key length is CryptoHash length + "best" bytes.
     /// This uses the genesis hash in order to support multiple chains.
     best_block_key: [u8; 36],
+    /// Key of the finalized block hash, built like `best_block_key`.
+    finalized_block_key: Vec<u8>,
     /// Tip of the known chain.
     best_block_index: RwLock<BlockIndex<B>>,
+    /// Index and hash of the last finalized block. The canonical chain always includes it.
+    finalized: RwLock<(u64, CryptoHash)>,
     /// Headers indexed by hash
     headers: RwLock<HashMap<Vec<u8>, B::SignedHeader>>,
     /// Blocks indexed by hash
     blocks: RwLock<HashMap<Vec<u8>, BlockIndex<B>>>,
-    /// Maps block index to hash.
-    // TODO: This doesn't handle forks at all and needs to be rewritten
+    /// Maps block index to the hash of the canonical block.
     index_to_hash: RwLock<HashMap<Vec<u8>, CryptoHash>>,
     // TODO: state?
 }
@@ -98,6 +115,8 @@ impl<B: SignedBlock> BlockChain<B> {
         let mut best_block_key = [0; 36];
         best_block_key[..32].copy_from_slice(genesis_hash.as_ref());
         best_block_key[32..].copy_from_slice(BLOCKCHAIN_BEST_BLOCK);
+        let mut finalized_block_key = genesis_hash.as_ref().to_vec();
+        finalized_block_key.extend_from_slice(BLOCKCHAIN_FINALIZED_BLOCK);
         let genesis_index = BlockIndex {
             block: genesis,
             cumulative_weight: 1,
@@ -106,7 +125,9 @@ impl<B: SignedBlock> BlockChain<B> {
             storage,
             genesis_hash,
             best_block_key,
+            finalized_block_key,
             best_block_index: RwLock::new(genesis_index.clone()),
+            finalized: RwLock::new((0, genesis_hash)),
             headers: RwLock::new(HashMap::new()),
             blocks: RwLock::new(HashMap::new()),
             index_to_hash: RwLock::new(HashMap::new()),
@@ -118,6 +139,7 @@ impl<B: SignedBlock> BlockChain<B> {
             _ => {
                 // Insert genesis block into cache.
                 bc.insert_block_index(&genesis_index);
+                bc.set_canonical(0, genesis_hash);
                 genesis_hash
             }
         };
@@ -128,6 +150,15 @@ impl<B: SignedBlock> BlockChain<B> {
                 .get_block_index(&BlockId::Hash(best_block_hash))
                 .expect("Not found best block in the chain");
         }
+        let finalized_hash = bc.storage.get(storage::COL_EXTRA, &bc.finalized_block_key);
+        if let Ok(Some(finalized_hash)) = finalized_hash {
+            let finalized_hash = CryptoHash::new(finalized_hash.as_ref());
+            let finalized_index = bc
+                .get_header(&BlockId::Hash(finalized_hash))
+                .expect("Not found finalized block in the chain")
+                .index();
+            *bc.finalized.write() = (finalized_index, finalized_hash);
+        }
 
         // Load best block into cache.
         bc
@@ -174,34 +205,131 @@ impl<B: SignedBlock> BlockChain<B> {
     /// Inserts a verified block.
     /// Returns true if block is disconnected.
     pub fn insert_block(&self, block: B) -> bool {
+        let disconnected = !self.is_known(&block.block_hash())
+            && self.get_block_index(&BlockId::Hash(block.header().parent_hash())).is_none();
+        self.insert_block_with_update(block);
+        disconnected
+    }
+
+    /// Inserts a verified block and makes it the best block if it is the head of the heaviest
+    /// chain that includes the finalized block.
+    pub fn insert_block_with_update(&self, block: B) -> HeadUpdate<B> {
         let block_hash = block.block_hash();
         if self.is_known(&block_hash) {
             // TODO: known header but not known block.
-            return false;
+            return HeadUpdate::Unchanged;
         }
+        let header = block.header();
 
         let mut cumulative_weight = 0;
-
-        let maybe_parent = self.get_block_index(&BlockId::Hash(block.header().parent_hash()));
-        let mut result = true;
+        let maybe_parent = self.get_block_index(&BlockId::Hash(header.parent_hash()));
         if let Some(parent_details) = maybe_parent {
             if parent_details.cumulative_weight > 0 {
                 cumulative_weight = block.weight() + parent_details.cumulative_weight;
             }
-            result = false
         }
         let block_index = BlockIndex { block, cumulative_weight };
         self.insert_block_index(&block_index);
-        if block_index.cumulative_weight > self.best_block_index.read().cumulative_weight {
-            self.update_best_block(block_index);
-        } else {
+        let best_block_index = self.best_block_index();
+        if block_index.cumulative_weight <= best_block_index.cumulative_weight {
             info!(target: "chain",
                   "Block #{:?} {:?} was declined via fork choice rule (best block weight = {} vs new block weight = {}).",
-                  block_index.block.header().index(), block_hash,
-                  self.best_block_index.read().cumulative_weight, block_index.cumulative_weight);
+                  header.index(), block_hash,
+                  best_block_index.cumulative_weight, block_index.cumulative_weight);
+            return HeadUpdate::Unchanged;
         }
 
-        result
+        let best_index = best_block_index.block.index();
+        if header.parent_hash() == best_block_index.block.block_hash() {
+            self.set_canonical(header.index(), block_hash);
+            self.update_best_block(block_index);
+            return HeadUpdate::Extended;
+        }
+        let (fork_index, applied) = match self.fork_branch(&block_index.block) {
+            Some(branch) => branch,
+            None => return HeadUpdate::Unchanged,
+        };
+        let finalized_index = self.finalized.read().0;
+        if fork_index < finalized_index {
+            info!(target: "chain",
+                  "Block #{:?} {:?} was declined since its fork doesn't include the finalized block #{}.",
+                  header.index(), block_hash, finalized_index);
+            return HeadUpdate::Unchanged;
+        }
+        let reverted: Vec<B> = (fork_index + 1..=best_index)
+            .rev()
+            .filter_map(|index| self.get_block(&BlockId::Number(index)))
+            .collect();
+        for block in applied.iter() {
+            self.set_canonical(block.index(), block.block_hash());
+        }
+        // The old canonical chain could be longer than the new one.
+        let stale: Vec<Vec<u8>> = (header.index() + 1..=best_index).map(index_to_bytes).collect();
+        remove_with_cache(&self.storage, storage::COL_BLOCK_INDEX, &self.index_to_hash, &stale);
+        info!(target: "chain",
+              "Reorg from #{} {:?} to #{} {:?}, {} blocks reverted.",
+              best_index, best_block_index.block.block_hash(), header.index(), block_hash,
+              reverted.len());
+        self.update_best_block(block_index);
+        HeadUpdate::Reorg { reverted, applied }
+    }
+
+    /// Index of the canonical block the fork of the block starts on and the blocks of the fork,
+    /// from the fork point up. `None` if the fork cannot be traced to the canonical chain.
+    fn fork_branch(&self, block: &B) -> Option<(u64, Vec<B>)> {
+        let mut branch = vec![block.clone()];
+        loop {
+            let parent_hash = branch[branch.len() - 1].header().parent_hash();
+            let parent = self.get_block(&BlockId::Hash(parent_hash))?;
+            if self.get_block_hash_by_index(parent.index()) == Some(parent_hash) {
+                branch.reverse();
+                return Some((parent.index(), branch));
+            }
+            branch.push(parent);
+        }
+    }
+
+    fn set_canonical(&self, index: u64, block_hash: CryptoHash) {
+        write_with_cache(
+            &self.storage,
+            storage::COL_BLOCK_INDEX,
+            &self.index_to_hash,
+            &index_to_bytes(index),
+            &block_hash,
+        );
+    }
+
+    /// Whether the block is on the canonical chain.
+    pub fn is_canonical(&self, hash: &CryptoHash) -> bool {
+        match self.get_header(&BlockId::Hash(*hash)) {
+            Some(header) => self.get_block_hash_by_index(header.index()) == Some(*hash),
+            None => false,
+        }
+    }
+
+    /// Marks the canonical block as final, the best block never moves to a fork that doesn't
+    /// include it. Returns false if the block is not canonical or precedes the finalized block.
+    pub fn finalize(&self, hash: &CryptoHash) -> bool {
+        let index = match self.get_header(&BlockId::Hash(*hash)) {
+            Some(header) => header.index(),
+            None => return false,
+        };
+        if !self.is_canonical(hash) || index < self.finalized.read().0 {
+            return false;
+        }
+        *self.finalized.write() = (index, *hash);
+        let mut db_transaction = self.storage.transaction();
+        db_transaction.put(storage::COL_EXTRA, &self.finalized_block_key, hash.as_ref());
+        self.storage.write(db_transaction).expect("Database write failed");
+        true
+    }
+
+    pub fn finalized_index(&self) -> u64 {
+        self.finalized.read().0
+    }
+
+    pub fn finalized_hash(&self) -> CryptoHash {
+        self.finalized.read().1
     }
 
     fn insert_block_index(&self, block_index: &BlockIndex<B>) {
@@ -223,13 +351,6 @@ impl<B: SignedBlock> BlockChain<B> {
             block_hash.as_ref(),
             &block.header(),
         );
-        write_with_cache(
-            &self.storage,
-            storage::COL_BLOCK_INDEX,
-            &self.index_to_hash,
-            &index_to_bytes(block.header().index()),
-            &block_hash,
-        );
     }
 
     fn get_block_hash_by_index(&self, index: u64) -> Option<CryptoHash> {
@@ -282,3 +403,93 @@ impl<B: SignedBlock> BlockChain<B> {
         Ok(blocks)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use primitives::hash::hash;
+    use storage::test_utils::create_memory_db;
+    use transaction::SignedTransaction;
+
+    use super::*;
+
+    fn block_on(parent: &SignedShardBlock, fork: u8) -> SignedShardBlock {
+        let index = parent.index() + 1;
+        SignedShardBlock::new(0, index, parent.hash, hash(&[fork]), hash(&[]), vec![], vec![])
+    }
+
+    #[test]
+    fn test_competing_forks() {
+        let genesis = SignedShardBlock::genesis(CryptoHash::default());
+        let chain = BlockChain::new(genesis.clone(), Arc::new(create_memory_db()));
+        let a1 = block_on(&genesis, 1);
+        let a2 = block_on(&a1, 1);
+        assert_eq!(chain.insert_block_with_update(a1.clone()), HeadUpdate::Extended);
+        assert_eq!(chain.insert_block_with_update(a2.clone()), HeadUpdate::Extended);
+
+        // The fork only becomes canonical once it is heavier.
+        let b1 = block_on(&genesis, 2);
+        let b2 = block_on(&b1, 2);
+        let b3 = block_on(&b2, 2);
+        assert_eq!(chain.insert_block_with_update(b1.clone()), HeadUpdate::Unchanged);
+        assert_eq!(chain.insert_block_with_update(b2.clone()), HeadUpdate::Unchanged);
+        assert_eq!(chain.best_hash(), a2.hash);
+        assert!(chain.is_canonical(&a1.hash));
+        assert!(!chain.is_canonical(&b1.hash));
+        let reverted = vec![a2.clone(), a1.clone()];
+        let applied = vec![b1.clone(), b2.clone(), b3.clone()];
+        let update = chain.insert_block_with_update(b3.clone());
+        assert_eq!(update, HeadUpdate::Reorg { reverted, applied });
+        assert_eq!(chain.best_hash(), b3.hash);
+        assert_eq!(chain.get_block(&BlockId::Number(1)).unwrap().hash, b1.hash);
+        assert!(!chain.is_canonical(&a1.hash));
+
+        // Forks that don't include the finalized block are never chosen.
+        assert!(chain.finalize(&b2.hash));
+        assert!(!chain.finalize(&a1.hash));
+        let a3 = block_on(&a2, 1);
+        let a4 = block_on(&a3, 1);
+        assert_eq!(chain.insert_block_with_update(a3), HeadUpdate::Unchanged);
+        assert_eq!(chain.insert_block_with_update(a4), HeadUpdate::Unchanged);
+        assert_eq!(chain.best_hash(), b3.hash);
+
+        // Forks after the finalized block can still take over.
+        let c3 = block_on(&b2, 3);
+        assert_eq!(chain.insert_block_with_update(c3.clone()), HeadUpdate::Unchanged);
+        let c4 = block_on(&c3, 3);
+        let update = chain.insert_block_with_update(c4.clone());
+        assert_eq!(update, HeadUpdate::Reorg { reverted: vec![b3], applied: vec![c3, c4.clone()] });
+        assert_eq!(chain.get_block(&BlockId::Number(4)).unwrap().hash, c4.hash);
+        // Blocks with an unknown parent are disconnected.
+        assert!(chain.insert_block(block_on(&block_on(&c4, 4), 4)));
+    }
+
+    #[test]
+    fn test_shard_block_parts() {
+        let transactions = vec![SignedTransaction::empty(); 5];
+        let block = SignedShardBlock::new_with_parts(
+            0,
+            1,
+            CryptoHash::default(),
+            hash(&[1]),
+            hash(&[]),
+            transactions,
+            vec![],
+            7,
+        );
+        let parts = block.parts();
+        assert_eq!(parts.len(), 7);
+        assert!(parts.iter().all(ShardBlockPart::verify));
+
+        // A third of the parts reconstruct the block.
+        let header = block.header();
+        assert_eq!(SignedShardBlock::from_parts(header.clone(), &parts[4..]), Some(block.clone()));
+        assert_eq!(SignedShardBlock::from_parts(header.clone(), &parts[5..]), None);
+
+        let mut tampered = parts[0].clone();
+        tampered.part[0] ^= 1;
+        assert!(!tampered.verify());
+        let mut tampered = parts[0].clone();
+        tampered.index = 1;
+        assert!(!tampered.verify());
+    }
+}
@@ -1,5 +1,10 @@
+use std::cmp;
+
 use crate::{SignedBlock, SignedHeader};
+use primitives::borsh;
+use primitives::erasure::{self, ErasureError, MAX_PARTS};
 use primitives::hash::{CryptoHash, hash_struct};
+use primitives::merkle::{merklize, verify_path, MerklePath};
 use primitives::types::{
     GroupSignature, MerkleHash, PartialSignature, ShardId,
 };
@@ -14,6 +19,15 @@ pub struct ShardBlockHeader {
     pub shard_id: ShardId,
     pub index: u64,
     pub merkle_root_state: MerkleHash,
+    /// Root of the Merkle tree over the outcomes of the receipts and the transactions of the
+    /// block, in the order they were applied.
+    pub outcome_root: MerkleHash,
+    /// Root of the Merkle tree over the transactions of the block, in the order they are applied.
+    pub transaction_root: MerkleHash,
+    /// Root of the Merkle tree over the erasure-coded parts of the transactions and receipts.
+    pub parts_root: MerkleHash,
+    /// Number of the parts, one for every seat of the authorities of the block.
+    pub total_parts: u64,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -37,6 +51,64 @@ pub struct SignedShardBlock {
     pub signature: GroupSignature,
 }
 
+/// Shard block whose state root is not the result of applying it, with the trie nodes of the
+/// parent state that applying it reads, so that any node can apply it again without the state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Challenge {
+    /// Header of the parent block, the block is applied to its state.
+    pub parent_header: SignedShardBlockHeader,
+    pub block: SignedShardBlock,
+    /// Encoded trie nodes, see `StateDb::from_witness`.
+    pub witness: Vec<Vec<u8>>,
+}
+
+/// Part of the erasure-coded transactions and receipts of a shard block, which the authority of
+/// the seat with the same index keeps, see `SignedShardBlock::parts`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardBlockPart {
+    pub header: SignedShardBlockHeader,
+    pub index: u64,
+    pub part: Vec<u8>,
+    /// Path from the part to the parts root of the header.
+    pub proof: MerklePath,
+}
+
+impl ShardBlockPart {
+    /// Checks that the part is one of the parts the header commits to.
+    pub fn verify(&self) -> bool {
+        hash_struct(&self.header.body) == self.header.hash
+            && self.index < self.header.body.total_parts
+            && verify_path(self.header.body.parts_root, &self.proof, &(self.index, &self.part))
+    }
+}
+
+/// Number of the parts of a block with the given number of authority seats.
+pub fn total_parts(num_seats: usize) -> u64 {
+    cmp::min(cmp::max(num_seats, 1), MAX_PARTS) as u64
+}
+
+/// Number of the parts that reconstruct a block: a third of the parts, so that the block stays
+/// available as long as a third of the authorities keep their parts.
+pub fn data_parts(total_parts: u64) -> usize {
+    cmp::max((total_parts as usize + 2) / 3, 1)
+}
+
+fn encode_body(
+    transactions: &[SignedTransaction],
+    receipts: &[ReceiptBlock],
+    total_parts: u64,
+) -> Result<Vec<Vec<u8>>, ErasureError> {
+    let data = borsh::to_vec(&(transactions, receipts)).expect("Error encoding shard block");
+    erasure::encode(&data, data_parts(total_parts), total_parts as usize)
+}
+
+/// Merkle tree over the parts with their indices, so that a part can't be passed as another one.
+fn merklize_parts(parts: &[Vec<u8>]) -> (MerkleHash, Vec<MerklePath>) {
+    let items: Vec<(u64, &Vec<u8>)> =
+        parts.iter().enumerate().map(|(index, part)| (index as u64, part)).collect();
+    merklize(&items)
+}
+
 #[derive(Debug, Clone, Eq, Serialize, Deserialize)]
 pub struct ReceiptBlock {
     pub header: SignedShardBlockHeader,
@@ -79,14 +151,47 @@ impl SignedShardBlock {
         index: u64,
         parent_hash: CryptoHash,
         merkle_root_state: MerkleHash,
+        outcome_root: MerkleHash,
+        transactions: Vec<SignedTransaction>,
+        receipts: Vec<ReceiptBlock>,
+    ) -> Self {
+        SignedShardBlock::new_with_parts(
+            shard_id,
+            index,
+            parent_hash,
+            merkle_root_state,
+            outcome_root,
+            transactions,
+            receipts,
+            1,
+        )
+    }
+
+    /// Creates the block, erasure-coded into `total_parts` parts.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_parts(
+        shard_id: ShardId,
+        index: u64,
+        parent_hash: CryptoHash,
+        merkle_root_state: MerkleHash,
+        outcome_root: MerkleHash,
         transactions: Vec<SignedTransaction>,
         receipts: Vec<ReceiptBlock>,
+        total_parts: u64,
     ) -> Self {
+        let (transaction_root, _) = merklize(&transactions);
+        let parts = encode_body(&transactions, &receipts, total_parts)
+            .expect("Number of the parts should be valid");
+        let (parts_root, _) = merklize_parts(&parts);
         let header = ShardBlockHeader {
             shard_id,
             index,
             parent_hash,
             merkle_root_state,
+            outcome_root,
+            transaction_root,
+            parts_root,
+            total_parts,
         };
         let hash = hash_struct(&header);
         SignedShardBlock {
@@ -102,7 +207,7 @@ impl SignedShardBlock {
 
     pub fn genesis(merkle_root_state: MerkleHash) -> SignedShardBlock {
         SignedShardBlock::new(
-            0, 0, CryptoHash::default(), merkle_root_state, vec![], vec![]
+            0, 0, CryptoHash::default(), merkle_root_state, MerkleHash::default(), vec![], vec![]
         )
     }
 
@@ -115,6 +220,64 @@ impl SignedShardBlock {
     pub fn shard_id(&self) -> ShardId {
         self.body.header.shard_id
     }
+
+    /// Erasure-coded parts of the transactions and receipts, as many as the header says.
+    fn encode_parts(&self) -> Result<Vec<Vec<u8>>, ErasureError> {
+        encode_body(&self.body.transactions, &self.body.receipts, self.body.header.total_parts)
+    }
+
+    /// Whether the parts root of the header is the root of the parts of the block.
+    pub fn verify_parts(&self) -> bool {
+        match self.encode_parts() {
+            Ok(parts) => merklize_parts(&parts).0 == self.body.header.parts_root,
+            Err(_) => false,
+        }
+    }
+
+    /// Parts of the block with the proofs that the header commits to them.
+    pub fn parts(&self) -> Vec<ShardBlockPart> {
+        let parts = self.encode_parts().expect("Number of the parts should be valid");
+        let (_, proofs) = merklize_parts(&parts);
+        parts
+            .into_iter()
+            .zip(proofs)
+            .enumerate()
+            .map(|(index, (part, proof))| ShardBlockPart {
+                header: self.header(),
+                index: index as u64,
+                part,
+                proof,
+            })
+            .collect()
+    }
+
+    /// Reconstructs the block from verified parts of its header. Returns None if there are not
+    /// enough parts or they don't encode a block the header commits to.
+    pub fn from_parts(header: SignedShardBlockHeader, parts: &[ShardBlockPart]) -> Option<Self> {
+        let total_parts = header.body.total_parts;
+        let mut present = vec![None; total_parts as usize];
+        for part in parts.iter().filter(|part| part.header.hash == header.hash) {
+            if let Some(slot) = present.get_mut(part.index as usize) {
+                *slot = Some(part.part.clone());
+            }
+        }
+        let data = erasure::decode(&present, data_parts(total_parts)).ok()?;
+        let (transactions, receipts): (Vec<SignedTransaction>, Vec<ReceiptBlock>) =
+            borsh::from_slice(&data).ok()?;
+        let block = SignedShardBlock {
+            body: ShardBlock { header: header.body, transactions, receipts },
+            hash: header.hash,
+            signature: header.signature,
+        };
+        // The producer may have encoded parts that decode differently depending on which of
+        // them are used, only the parts the header commits to make the block.
+        if !block.verify_parts()
+            || merklize(&block.body.transactions).0 != block.body.header.transaction_root
+        {
+            return None;
+        }
+        Some(block)
+    }
 }
 
 impl SignedBlock for SignedShardBlock {
@@ -88,6 +88,16 @@ impl<E: Engine> PublicKey<E> {
         CompressedPublicKey( self.point.into_compressed() )
     }
 
+    /// Decodes the compressed key, `None` if it is not a valid point.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut compressed = CompressedPublicKey::empty();
+        if bytes.len() != compressed.as_ref().len() {
+            return None;
+        }
+        compressed.as_mut().copy_from_slice(bytes);
+        compressed.decompress().ok()
+    }
+
     pub fn verify(&self, message: &[u8], signature: &Signature<E>) -> bool {
         self.verify_domain(message, DOMAIN_SIGNATURE, signature)
     }
@@ -122,6 +132,16 @@ impl<E: Engine> Signature<E> {
     pub fn empty() -> Self {
         Signature { point: E::G2Affine::zero() }
     }
+
+    /// Decodes the compressed signature, `None` if it is not a valid point.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut compressed = CompressedSignature::empty();
+        if bytes.len() != compressed.as_ref().len() {
+            return None;
+        }
+        compressed.as_mut().copy_from_slice(bytes);
+        compressed.decompress().ok()
+    }
 }
 
 // Note: deriving PartialEq and Eq doesn't work
@@ -355,6 +375,21 @@ mod tests {
         assert!(!pubkey[0].verify_proof_of_possession(&fake_proof));
     }
 
+    #[test]
+    fn from_bytes() {
+        let mut rng = XorShiftRng::from_seed([44444444, 55555555, 66666666, 77777777]);
+
+        let secret = BlsSecretKey::generate_from_rng(&mut rng);
+        let pubkey = BlsPublicKey::from_bytes(&Vec::from(&secret.get_public_key())).unwrap();
+        let proof = secret.get_proof_of_possession();
+        assert_eq!(BlsSignature::from_bytes(&Vec::from(&proof)), Some(proof.clone()));
+        assert!(pubkey.verify_proof_of_possession(&proof));
+
+        assert!(BlsPublicKey::from_bytes(&[1, 2, 3]).is_none());
+        assert!(BlsPublicKey::from_bytes(&[0xff; 48]).is_none());
+        assert_eq!(BlsSignature::from_bytes(&[0xff; 96]), None);
+    }
+
     #[test]
     fn aggregate_signature() {
         let mut rng = XorShiftRng::from_seed([33333333, 44444444, 55555555, 66666666]);
@@ -0,0 +1,810 @@
+//! Borsh encoding of the serde data model, for the data that is hashed or kept in the state,
+//! whose bytes must not change between versions or implementations. Unlike bincode, the format
+//! is specified and every value has a single encoding:
+//!
+//! * integers are little endian with their own width, booleans are a byte 0 or 1;
+//! * floats are little endian IEEE 754, NaN is not allowed;
+//! * strings, byte arrays and sequences are the length as u32 followed by the items;
+//! * options are a byte 0, or a byte 1 followed by the value;
+//! * enums are the index of the variant as u8 followed by its fields;
+//! * structs and tuples are their fields in the order of declaration, without names;
+//! * maps are the number of entries as u32 followed by the entries sorted by the bytes of the
+//!   keys, each key at most once.
+//!
+//! Chars are encoded as their u32 code point. The format is not self-describing, so the types
+//! that need `deserialize_any`, such as untagged enums, can't be decoded.
+use std::error;
+use std::fmt;
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+
+use crate::limited::{self, DecodeLimits, LimitError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    /// Error reported by the type being encoded or decoded.
+    Message(String),
+    /// Input ends before the value does.
+    UnexpectedEnd,
+    /// Input continues after the value.
+    TrailingBytes(usize),
+    /// Byte of a boolean or an option is neither 0 nor 1.
+    InvalidTag(u8),
+    /// Length doesn't fit in u32, or isn't known before the items are encoded.
+    InvalidLength,
+    /// Enum has more variants than a u8 can tell.
+    InvalidVariant(u32),
+    /// Float is NaN, which has several encodings.
+    NaN,
+    /// Keys of a map are not sorted, or a key is repeated.
+    UnsortedMap,
+    /// String is not UTF-8, or a char is not a code point.
+    InvalidUtf8,
+    /// Type needs a self-describing format.
+    NotSelfDescribing,
+    /// Input is over the limits it is decoded with.
+    Limit(LimitError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Message(e) => write!(f, "{}", e),
+            Error::UnexpectedEnd => write!(f, "Unexpected end of the input"),
+            Error::TrailingBytes(n) => write!(f, "{} bytes left after the value", n),
+            Error::InvalidTag(tag) => write!(f, "Invalid tag {}, expected 0 or 1", tag),
+            Error::InvalidLength => write!(f, "Length must be known and fit in u32"),
+            Error::InvalidVariant(index) => write!(f, "Variant index {} doesn't fit in u8", index),
+            Error::NaN => write!(f, "NaN cannot be encoded"),
+            Error::UnsortedMap => write!(f, "Map keys must be sorted and unique"),
+            Error::InvalidUtf8 => write!(f, "Invalid UTF-8"),
+            Error::NotSelfDescribing => write!(f, "Type needs a self-describing format"),
+            Error::Limit(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<LimitError> for Error {
+    fn from(e: LimitError) -> Self {
+        Error::Limit(e)
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Encodes the value.
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    let mut serializer = Serializer { output: vec![] };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.output)
+}
+
+/// Decodes the value, which must take all of the bytes.
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    let mut deserializer = Deserializer { input: bytes };
+    let value = T::deserialize(&mut deserializer)?;
+    match deserializer.input.len() {
+        0 => Ok(value),
+        n => Err(Error::TrailingBytes(n)),
+    }
+}
+
+/// Decodes the value of untrusted input within the limits, which must take all of the bytes.
+pub fn from_slice_limited<T: DeserializeOwned>(bytes: &[u8], limits: &DecodeLimits) -> Result<T> {
+    limited::decode(limits, bytes.len(), |seed| {
+        let mut deserializer = Deserializer { input: bytes };
+        let value = seed.deserialize(&mut deserializer)?;
+        match deserializer.input.len() {
+            0 => Ok(value),
+            n => Err(Error::TrailingBytes(n)),
+        }
+    })
+}
+
+fn length(len: usize) -> Result<u32> {
+    if len > u32::max_value() as usize {
+        Err(Error::InvalidLength)
+    } else {
+        Ok(len as u32)
+    }
+}
+
+fn variant(index: u32) -> Result<u8> {
+    if index > u32::from(u8::max_value()) {
+        Err(Error::InvalidVariant(index))
+    } else {
+        Ok(index as u8)
+    }
+}
+
+struct Serializer {
+    output: Vec<u8>,
+}
+
+impl Serializer {
+    fn write_length(&mut self, len: usize) -> Result<()> {
+        let len = length(len)?;
+        self.output.extend_from_slice(&len.to_le_bytes());
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = MapSerializer<'a>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.output.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.output.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        if v.is_nan() {
+            return Err(Error::NaN);
+        }
+        self.output.extend_from_slice(&v.to_bits().to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        if v.is_nan() {
+            return Err(Error::NaN);
+        }
+        self.output.extend_from_slice(&v.to_bits().to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.serialize_u32(v as u32)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.write_length(v.len())?;
+        self.output.extend_from_slice(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.output.push(0);
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        self.output.push(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<()> {
+        self.output.push(variant(variant_index)?);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.output.push(variant(variant_index)?);
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self> {
+        self.write_length(len.ok_or(Error::InvalidLength)?)?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self> {
+        self.output.push(variant(variant_index)?);
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer<'a>> {
+        Ok(MapSerializer { output: &mut self.output, entries: vec![], key: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self> {
+        self.output.push(variant(variant_index)?);
+        Ok(self)
+    }
+}
+
+impl<'a> ser::SerializeSeq for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTuple for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStruct for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Encodes the entries of a map apart, to write them sorted by their keys in the end.
+struct MapSerializer<'a> {
+    output: &'a mut Vec<u8>,
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+    key: Option<Vec<u8>>,
+}
+
+impl<'a> ser::SerializeMap for MapSerializer<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.key = Some(to_vec(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self.key.take().expect("serialize_key is called before serialize_value");
+        self.entries.push((key, to_vec(value)?));
+        Ok(())
+    }
+
+    fn end(mut self) -> Result<()> {
+        self.entries.sort();
+        if self.entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(Error::UnsortedMap);
+        }
+        self.output.extend_from_slice(&length(self.entries.len())?.to_le_bytes());
+        for (key, value) in self.entries {
+            self.output.extend_from_slice(&key);
+            self.output.extend_from_slice(&value);
+        }
+        Ok(())
+    }
+}
+
+struct Deserializer<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> Deserializer<'de> {
+    fn read(&mut self, len: usize) -> Result<&'de [u8]> {
+        if self.input.len() < len {
+            return Err(Error::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.input.split_at(len);
+        self.input = rest;
+        Ok(bytes)
+    }
+
+    fn read_array<A: AsMut<[u8]> + Default>(&mut self) -> Result<A> {
+        let mut array = A::default();
+        let len = array.as_mut().len();
+        array.as_mut().copy_from_slice(self.read(len)?);
+        Ok(array)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_tag(&mut self) -> Result<bool> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn read_bytes(&mut self) -> Result<&'de [u8]> {
+        let len = self.read_u32()? as usize;
+        self.read(len)
+    }
+
+    fn read_str(&mut self) -> Result<&'de str> {
+        std::str::from_utf8(self.read_bytes()?).map_err(|_| Error::InvalidUtf8)
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+    type Error = Error;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::NotSelfDescribing)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_bool(self.read_tag()?)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i8(i8::from_le_bytes(self.read_array()?))
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i16(i16::from_le_bytes(self.read_array()?))
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i32(i32::from_le_bytes(self.read_array()?))
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i64(i64::from_le_bytes(self.read_array()?))
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u8(self.read_u8()?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u16(u16::from_le_bytes(self.read_array()?))
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u32(self.read_u32()?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u64(u64::from_le_bytes(self.read_array()?))
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let v = f32::from_bits(u32::from_le_bytes(self.read_array()?));
+        if v.is_nan() {
+            return Err(Error::NaN);
+        }
+        visitor.visit_f32(v)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let v = f64::from_bits(u64::from_le_bytes(self.read_array()?));
+        if v.is_nan() {
+            return Err(Error::NaN);
+        }
+        visitor.visit_f64(v)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let v = std::char::from_u32(self.read_u32()?).ok_or(Error::InvalidUtf8)?;
+        visitor.visit_char(v)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_str(self.read_str()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_str(self.read_str()?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_bytes(self.read_bytes()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_bytes(self.read_bytes()?)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.read_tag()? {
+            visitor.visit_some(self)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let len = self.read_u32()? as usize;
+        visitor.visit_seq(Items { deserializer: self, len })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Items { deserializer: self, len })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_seq(Items { deserializer: self, len })
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let len = self.read_u32()? as usize;
+        visitor.visit_map(Entries { deserializer: self, len, last_key: None })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_seq(Items { deserializer: self, len: fields.len() })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::NotSelfDescribing)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::NotSelfDescribing)
+    }
+}
+
+/// Items of a sequence, or fields of a tuple or a struct.
+struct Items<'a, 'de: 'a> {
+    deserializer: &'a mut Deserializer<'de>,
+    len: usize,
+}
+
+impl<'de, 'a> de::SeqAccess<'de> for Items<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.len == 0 {
+            return Ok(None);
+        }
+        self.len -= 1;
+        seed.deserialize(&mut *self.deserializer).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+/// Entries of a map, whose keys are checked to be sorted.
+struct Entries<'a, 'de: 'a> {
+    deserializer: &'a mut Deserializer<'de>,
+    len: usize,
+    last_key: Option<&'de [u8]>,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for Entries<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if self.len == 0 {
+            return Ok(None);
+        }
+        self.len -= 1;
+        let start = self.deserializer.input;
+        let key = seed.deserialize(&mut *self.deserializer)?;
+        let key_bytes = &start[..start.len() - self.deserializer.input.len()];
+        if let Some(last_key) = self.last_key {
+            if last_key >= key_bytes {
+                return Err(Error::UnsortedMap);
+            }
+        }
+        self.last_key = Some(key_bytes);
+        Ok(Some(key))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        seed.deserialize(&mut *self.deserializer)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for &'a mut Deserializer<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let index = u32::from(self.read_u8()?);
+        let deserializer: de::value::U32Deserializer<Error> = index.into_deserializer();
+        let value = seed.deserialize(deserializer)?;
+        Ok((value, self))
+    }
+}
+
+impl<'de, 'a> de::VariantAccess<'de> for &'a mut Deserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Items { deserializer: self, len })
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_seq(Items { deserializer: self, len: fields.len() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Body {
+        Empty,
+        Transfer { amount: u64, memo: Option<String> },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Record {
+        flag: bool,
+        bodies: Vec<Body>,
+        balances: HashMap<String, u32>,
+    }
+
+    #[test]
+    fn test_encoding() {
+        let mut balances = HashMap::new();
+        balances.insert("b".to_string(), 2);
+        balances.insert("a".to_string(), 1);
+        let record = Record {
+            flag: true,
+            bodies: vec![Body::Empty, Body::Transfer { amount: 3, memo: Some("x".to_string()) }],
+            balances,
+        };
+        let bytes = to_vec(&record).unwrap();
+        #[rustfmt::skip]
+        let expected = vec![
+            1,
+            2, 0, 0, 0,
+            0,
+            1, 3, 0, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, b'x',
+            2, 0, 0, 0,
+            1, 0, 0, 0, b'a', 1, 0, 0, 0,
+            1, 0, 0, 0, b'b', 2, 0, 0, 0,
+        ];
+        assert_eq!(bytes, expected);
+        assert_eq!(from_slice::<Record>(&bytes).unwrap(), record);
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(from_slice::<Record>(&trailing), Err(Error::TrailingBytes(1)));
+        assert_eq!(from_slice::<Record>(&bytes[..10]), Err(Error::UnexpectedEnd));
+        let mut invalid_bool = bytes.clone();
+        invalid_bool[0] = 2;
+        assert_eq!(from_slice::<Record>(&invalid_bool), Err(Error::InvalidTag(2)));
+        let mut unsorted = bytes.clone();
+        let len = unsorted.len();
+        unsorted[len - 14] = b'b';
+        unsorted[len - 5] = b'a';
+        assert_eq!(from_slice::<Record>(&unsorted), Err(Error::UnsortedMap));
+        assert_eq!(to_vec(&std::f64::NAN), Err(Error::NaN));
+    }
+}
@@ -0,0 +1,214 @@
+//! Reed-Solomon erasure code over GF(2^8). The data is split into `data_parts` parts and extended
+//! with parity parts up to `total_parts`, so that any `data_parts` of the parts reconstruct it.
+//! Every byte position is a polynomial of degree below `data_parts`: the parts are its values at
+//! the points `0..total_parts`, and the data parts are the values at the first points.
+use std::fmt;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Number of points of the field, which limits the number of parts.
+pub const MAX_PARTS: usize = 256;
+
+/// Bytes of the length of the data, which is encoded in front of it.
+const LENGTH_BYTES: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErasureError {
+    /// Number of data parts is zero or above the total number of parts, or there are more parts
+    /// than points of the field.
+    InvalidParts { data_parts: usize, total_parts: usize },
+    /// Fewer parts than data parts are present.
+    NotEnoughParts { present: usize, data_parts: usize },
+    /// Parts have different lengths.
+    InvalidPartLength,
+    /// Length encoded in the data doesn't fit the parts.
+    InvalidDataLength,
+}
+
+impl fmt::Display for ErasureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErasureError::InvalidParts { data_parts, total_parts } => {
+                write!(f, "Cannot encode in {} data parts out of {}", data_parts, total_parts)
+            }
+            ErasureError::NotEnoughParts { present, data_parts } => {
+                write!(f, "{} parts are present, {} are needed", present, data_parts)
+            }
+            ErasureError::InvalidPartLength => write!(f, "Parts have different lengths"),
+            ErasureError::InvalidDataLength => write!(f, "Data length doesn't fit the parts"),
+        }
+    }
+}
+
+/// Logarithm and exponent tables of GF(2^8) with the polynomial x^8 + x^4 + x^3 + x^2 + 1.
+struct Field {
+    exp: [u8; 510],
+    log: [u8; 256],
+}
+
+impl Field {
+    fn new() -> Self {
+        let mut field = Field { exp: [0; 510], log: [0; 256] };
+        let mut value: u16 = 1;
+        for power in 0..255 {
+            field.exp[power] = value as u8;
+            field.exp[power + 255] = value as u8;
+            field.log[value as usize] = power as u8;
+            value <<= 1;
+            if value & 0x100 != 0 {
+                value ^= 0x11d;
+            }
+        }
+        field
+    }
+
+    fn mul(&self, a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {
+            return 0;
+        }
+        self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize]
+    }
+
+    fn div(&self, a: u8, b: u8) -> u8 {
+        debug_assert!(b != 0, "Division by zero");
+        if a == 0 {
+            return 0;
+        }
+        self.exp[self.log[a as usize] as usize + 255 - self.log[b as usize] as usize]
+    }
+
+    /// Coefficients of the values at the `points` in the value at `x` of the polynomial of
+    /// degree below the number of points, i.e. the Lagrange basis at `x`.
+    fn lagrange(&self, points: &[u8], x: u8) -> Vec<u8> {
+        points
+            .iter()
+            .map(|&point| {
+                points.iter().filter(|&&other| other != point).fold(1, |coefficient, &other| {
+                    // Subtraction is addition in the field.
+                    self.mul(coefficient, self.div(x ^ other, point ^ other))
+                })
+            })
+            .collect()
+    }
+
+    /// Value at `x` of the polynomial with the given values at the points.
+    fn interpolate(&self, points: &[u8], values: &[&[u8]], x: u8, length: usize) -> Vec<u8> {
+        let coefficients = self.lagrange(points, x);
+        let mut result = vec![0; length];
+        for (coefficient, value) in coefficients.iter().zip(values) {
+            for (byte, &v) in result.iter_mut().zip(value.iter()) {
+                *byte ^= self.mul(*coefficient, v);
+            }
+        }
+        result
+    }
+}
+
+fn check_parts(data_parts: usize, total_parts: usize) -> Result<(), ErasureError> {
+    if data_parts == 0 || data_parts > total_parts || total_parts > MAX_PARTS {
+        return Err(ErasureError::InvalidParts { data_parts, total_parts });
+    }
+    Ok(())
+}
+
+/// Splits the data into `total_parts` parts of the same length, any `data_parts` of which
+/// reconstruct it.
+pub fn encode(
+    data: &[u8],
+    data_parts: usize,
+    total_parts: usize,
+) -> Result<Vec<Vec<u8>>, ErasureError> {
+    check_parts(data_parts, total_parts)?;
+    let mut bytes = vec![0; LENGTH_BYTES];
+    LittleEndian::write_u64(&mut bytes, data.len() as u64);
+    bytes.extend_from_slice(data);
+    let part_length = (bytes.len() + data_parts - 1) / data_parts;
+    bytes.resize(part_length * data_parts, 0);
+    let mut parts: Vec<Vec<u8>> = bytes.chunks(part_length).map(|chunk| chunk.to_vec()).collect();
+
+    let field = Field::new();
+    let points: Vec<u8> = (0..data_parts).map(|point| point as u8).collect();
+    let parity: Vec<Vec<u8>> = {
+        let values: Vec<&[u8]> = parts.iter().map(Vec::as_slice).collect();
+        (data_parts..total_parts)
+            .map(|x| field.interpolate(&points, &values, x as u8, part_length))
+            .collect()
+    };
+    parts.extend(parity);
+    Ok(parts)
+}
+
+/// Reconstructs the data from the parts, given in the order of their indices, `None` for the
+/// parts that are missing.
+pub fn decode(parts: &[Option<Vec<u8>>], data_parts: usize) -> Result<Vec<u8>, ErasureError> {
+    check_parts(data_parts, parts.len())?;
+    let present: Vec<(u8, &[u8])> = parts
+        .iter()
+        .enumerate()
+        .filter_map(|(index, part)| part.as_ref().map(|part| (index as u8, part.as_slice())))
+        .take(data_parts)
+        .collect();
+    if present.len() < data_parts {
+        return Err(ErasureError::NotEnoughParts { present: present.len(), data_parts });
+    }
+    let part_length = present[0].1.len();
+    if present.iter().any(|(_, part)| part.len() != part_length) {
+        return Err(ErasureError::InvalidPartLength);
+    }
+
+    let field = Field::new();
+    let points: Vec<u8> = present.iter().map(|(point, _)| *point).collect();
+    let values: Vec<&[u8]> = present.iter().map(|(_, part)| *part).collect();
+    let mut bytes = Vec::with_capacity(part_length * data_parts);
+    for (index, part) in parts.iter().take(data_parts).enumerate() {
+        match part {
+            Some(part) => bytes.extend_from_slice(part),
+            None => bytes.extend(field.interpolate(&points, &values, index as u8, part_length)),
+        }
+    }
+
+    if bytes.len() < LENGTH_BYTES {
+        return Err(ErasureError::InvalidDataLength);
+    }
+    let length = LittleEndian::read_u64(&bytes[..LENGTH_BYTES]) as usize;
+    if length > bytes.len() - LENGTH_BYTES {
+        return Err(ErasureError::InvalidDataLength);
+    }
+    Ok(bytes[LENGTH_BYTES..LENGTH_BYTES + length].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_erasure_code() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7 % 256) as u8).collect();
+        let parts = encode(&data, 3, 7).unwrap();
+        assert_eq!(parts.len(), 7);
+        assert!(parts.iter().all(|part| part.len() == parts[0].len()));
+
+        // Any three parts reconstruct the data.
+        for missing in &[vec![0, 1, 2, 3], vec![1, 3, 5, 6], vec![4, 5, 6, 0], vec![]] {
+            let present: Vec<Option<Vec<u8>>> = parts
+                .iter()
+                .enumerate()
+                .map(|(i, part)| if missing.contains(&i) { None } else { Some(part.clone()) })
+                .collect();
+            assert_eq!(decode(&present, 3), Ok(data.clone()));
+        }
+        let mut present: Vec<Option<Vec<u8>>> = vec![None; 7];
+        present[6] = Some(parts[6].clone());
+        present[2] = Some(parts[2].clone());
+        assert_eq!(
+            decode(&present, 3),
+            Err(ErasureError::NotEnoughParts { present: 2, data_parts: 3 })
+        );
+
+        assert_eq!(decode(&[Some(encode(&[], 1, 1).unwrap()[0].clone())], 1), Ok(vec![]));
+        assert_eq!(
+            encode(&data, 4, 3),
+            Err(ErasureError::InvalidParts { data_parts: 4, total_parts: 3 })
+        );
+    }
+}
@@ -3,7 +3,9 @@ use exonum_sodiumoxide as sodiumoxide;
 use exonum_sodiumoxide::crypto::hash::sha256::Digest;
 use heapsize;
 use std::fmt;
-use crate::traits::Encode;
+use serde::Serialize;
+
+use crate::borsh;
 
 #[derive(Copy, Clone, Eq, PartialOrd, Ord, PartialEq, Serialize, Deserialize, Hash)]
 pub struct CryptoHash(pub Digest);
@@ -108,8 +110,8 @@ pub fn hash(data: &[u8]) -> CryptoHash {
     CryptoHash(sodiumoxide::crypto::hash::sha256::hash(data))
 }
 
-pub fn hash_struct<T: Encode>(obj: &T) -> CryptoHash {
-    hash(&obj.encode().expect("Serialization failed"))
+pub fn hash_struct<T: Serialize>(obj: &T) -> CryptoHash {
+    hash(&borsh::to_vec(obj).expect("Serialization failed"))
 }
 
 impl heapsize::HeapSizeOf for CryptoHash {
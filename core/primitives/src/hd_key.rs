@@ -0,0 +1,323 @@
+//! Seed phrases and hierarchical key derivation, so that the keys of many accounts can be derived
+//! from a single seed phrase, which is all there is to back up. The seed phrases are BIP-39
+//! mnemonics, and the ed25519 keys are derived from the seed with SLIP-0010, which only has
+//! hardened derivation, the way wallets and the Ledger derive them.
+use std::fmt;
+
+use dalek_sha2::Sha512;
+use exonum_sodiumoxide::crypto::sign::ed25519::{keypair_from_seed, Seed};
+use exonum_sodiumoxide::randombytes::randombytes;
+use hmac::{Hmac, Mac};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::hash::hash;
+use crate::signature::{PublicKey, SecretKey};
+
+/// HD path of the keys by default, with the coin type registered for NEAR.
+pub const DEFAULT_HD_PATH: &str = "44'/397'/0'/0'/1'";
+
+const HARDENED: u32 = 0x8000_0000;
+const PBKDF2_ROUNDS: u32 = 2048;
+const BITS_PER_WORD: usize = 11;
+
+lazy_static! {
+    static ref WORDS: Vec<&'static str> =
+        include_str!("../res/bip39-english.txt").lines().collect();
+}
+
+type HmacSha512 = Hmac<Sha512>;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum HdKeyError {
+    /// Entropy is not 16 to 32 bytes, in steps of 4.
+    InvalidEntropy(usize),
+    /// Mnemonic is not 12 to 24 words, in steps of 3.
+    InvalidWordCount(usize),
+    UnknownWord(String),
+    /// Mnemonic is mistyped, the checksum in its last word doesn't match.
+    InvalidChecksum,
+    InvalidPath(String),
+    /// Ed25519 keys can only be derived at hardened indices.
+    NonHardenedIndex(u32),
+}
+
+impl fmt::Display for HdKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HdKeyError::InvalidEntropy(size) => write!(f, "Invalid entropy size {}", size),
+            HdKeyError::InvalidWordCount(count) => {
+                write!(f, "Mnemonic has {} words, expected 12, 15, 18, 21 or 24", count)
+            }
+            HdKeyError::UnknownWord(word) => write!(f, "Unknown mnemonic word {}", word),
+            HdKeyError::InvalidChecksum => write!(f, "Invalid mnemonic checksum"),
+            HdKeyError::InvalidPath(path) => write!(f, "Invalid HD path: {}", path),
+            HdKeyError::NonHardenedIndex(index) => {
+                write!(f, "Index {} is not hardened, ed25519 keys only derive hardened", index)
+            }
+        }
+    }
+}
+
+/// Parses the HD path, with hardened indices marked by `'` and an optional `m/` in front.
+pub fn parse_hd_path(path: &str) -> Result<Vec<u32>, HdKeyError> {
+    let indices = if path.starts_with("m/") { &path[2..] } else { path };
+    indices
+        .split('/')
+        .map(|index| {
+            let (index, hardened) = if index.ends_with('\'') {
+                (&index[..index.len() - 1], HARDENED)
+            } else {
+                (index, 0)
+            };
+            match index.parse::<u32>() {
+                Ok(index) if index < HARDENED => Ok(index | hardened),
+                _ => Err(HdKeyError::InvalidPath(path.to_string())),
+            }
+        })
+        .collect()
+}
+
+fn bit(bytes: &[u8], i: usize) -> bool {
+    bytes[i / 8] & (0x80 >> (i % 8)) != 0
+}
+
+/// Mnemonic of the entropy, with the first bits of its hash as the checksum.
+pub fn entropy_to_mnemonic(entropy: &[u8]) -> Result<String, HdKeyError> {
+    if entropy.len() < 16 || entropy.len() > 32 || entropy.len() % 4 != 0 {
+        return Err(HdKeyError::InvalidEntropy(entropy.len()));
+    }
+    let mut bytes = entropy.to_vec();
+    bytes.push(hash(entropy).as_ref()[0]);
+    let word_count = (entropy.len() * 8 + entropy.len() / 4) / BITS_PER_WORD;
+    let words: Vec<_> = (0..word_count)
+        .map(|word| {
+            let index = (0..BITS_PER_WORD).fold(0, |index, i| {
+                (index << 1) | bit(&bytes, word * BITS_PER_WORD + i) as usize
+            });
+            WORDS[index]
+        })
+        .collect();
+    Ok(words.join(" "))
+}
+
+/// Generates a mnemonic of the number of words, 12 being 128 bits of entropy.
+pub fn generate_mnemonic(word_count: usize) -> Result<String, HdKeyError> {
+    if word_count % 3 != 0 {
+        return Err(HdKeyError::InvalidWordCount(word_count));
+    }
+    entropy_to_mnemonic(&randombytes(word_count / 3 * 4))
+        .map_err(|_| HdKeyError::InvalidWordCount(word_count))
+}
+
+/// Entropy of the mnemonic, once its words and checksum are checked.
+pub fn mnemonic_to_entropy(mnemonic: &str) -> Result<Vec<u8>, HdKeyError> {
+    let words: Vec<_> = mnemonic.split_whitespace().collect();
+    if words.len() < 12 || words.len() > 24 || words.len() % 3 != 0 {
+        return Err(HdKeyError::InvalidWordCount(words.len()));
+    }
+    let mut bytes = vec![0u8; (words.len() * BITS_PER_WORD + 7) / 8];
+    for (word_index, word) in words.iter().enumerate() {
+        let index = WORDS
+            .binary_search_by(|probe| probe.cmp(word))
+            .map_err(|_| HdKeyError::UnknownWord(word.to_string()))?;
+        for i in 0..BITS_PER_WORD {
+            if index & (1 << (BITS_PER_WORD - 1 - i)) != 0 {
+                let bit = word_index * BITS_PER_WORD + i;
+                bytes[bit / 8] |= 0x80 >> (bit % 8);
+            }
+        }
+    }
+    let checksum_bits = words.len() / 3;
+    let entropy = bytes[..checksum_bits * 4].to_vec();
+    let checksum = bytes[checksum_bits * 4] >> (8 - checksum_bits);
+    if hash(&entropy).as_ref()[0] >> (8 - checksum_bits) != checksum {
+        return Err(HdKeyError::InvalidChecksum);
+    }
+    Ok(entropy)
+}
+
+/// PBKDF2 with HMAC-SHA512, in a single block as the seed is the size of the hash.
+fn pbkdf2_sha512(password: &[u8], salt: &[u8], rounds: u32) -> [u8; 64] {
+    let mac = HmacSha512::new_varkey(password).expect("HMAC takes keys of any size");
+    let mut block = mac.clone();
+    block.input(salt);
+    block.input(&1u32.to_be_bytes());
+    let mut u = block.result().code();
+    let mut seed = [0; 64];
+    seed.copy_from_slice(&u);
+    for _ in 1..rounds {
+        let mut block = mac.clone();
+        block.input(&u);
+        u = block.result().code();
+        for (s, u) in seed.iter_mut().zip(u.iter()) {
+            *s ^= u;
+        }
+    }
+    seed
+}
+
+/// Seed of the mnemonic, protected by the passphrase, which may be empty.
+pub fn mnemonic_to_seed(mnemonic: &str, passphrase: &str) -> Result<[u8; 64], HdKeyError> {
+    mnemonic_to_entropy(mnemonic)?;
+    let words: Vec<_> = mnemonic.split_whitespace().collect();
+    let mnemonic: String = words.join(" ").nfkd().collect();
+    let salt: String = format!("mnemonic{}", passphrase).nfkd().collect();
+    Ok(pbkdf2_sha512(mnemonic.as_bytes(), salt.as_bytes(), PBKDF2_ROUNDS))
+}
+
+/// Secret key and chain code of a node of the derivation tree.
+#[derive(Clone)]
+pub struct ExtendedKey {
+    pub secret_key: [u8; 32],
+    pub chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    fn from_hmac(key: &[u8], data: &[&[u8]]) -> Self {
+        let mut mac = HmacSha512::new_varkey(key).expect("HMAC takes keys of any size");
+        for data in data {
+            mac.input(data);
+        }
+        let result = mac.result().code();
+        let mut extended_key = ExtendedKey { secret_key: [0; 32], chain_code: [0; 32] };
+        extended_key.secret_key.copy_from_slice(&result[..32]);
+        extended_key.chain_code.copy_from_slice(&result[32..]);
+        extended_key
+    }
+
+    pub fn master(seed: &[u8]) -> Self {
+        ExtendedKey::from_hmac(b"ed25519 seed", &[seed])
+    }
+
+    pub fn derive_child(&self, index: u32) -> Result<Self, HdKeyError> {
+        if index < HARDENED {
+            return Err(HdKeyError::NonHardenedIndex(index));
+        }
+        Ok(ExtendedKey::from_hmac(
+            &self.chain_code,
+            &[&[0], &self.secret_key, &index.to_be_bytes()],
+        ))
+    }
+
+    pub fn derive_path(seed: &[u8], hd_path: &[u32]) -> Result<Self, HdKeyError> {
+        hd_path.iter().try_fold(ExtendedKey::master(seed), |key, index| key.derive_child(*index))
+    }
+
+    pub fn key_pair(&self) -> (PublicKey, SecretKey) {
+        let (public_key, secret_key) = keypair_from_seed(&Seed(self.secret_key));
+        (PublicKey::ED25519(public_key), SecretKey::ED25519(secret_key))
+    }
+}
+
+/// Key pair at the HD path of the seed of the mnemonic.
+pub fn derive_key_pair(
+    mnemonic: &str,
+    passphrase: &str,
+    hd_path: &str,
+) -> Result<(PublicKey, SecretKey), HdKeyError> {
+    let seed = mnemonic_to_seed(mnemonic, passphrase)?;
+    Ok(ExtendedKey::derive_path(&seed, &parse_hd_path(hd_path)?)?.key_pair())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_hd_path() {
+        assert_eq!(
+            parse_hd_path(DEFAULT_HD_PATH),
+            Ok(vec![0x8000_002C, 0x8000_018D, 0x8000_0000, 0x8000_0000, 0x8000_0001])
+        );
+        assert_eq!(parse_hd_path("m/44/1"), Ok(vec![44, 1]));
+        assert!(parse_hd_path("44'/x").is_err());
+        assert!(parse_hd_path("2147483648").is_err());
+    }
+
+    /// Test vectors of BIP-39, with the passphrase TREZOR.
+    #[test]
+    fn test_mnemonic() {
+        assert_eq!(WORDS.len(), 2048);
+        let vectors = [
+            (
+                "00000000000000000000000000000000",
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                 abandon about",
+                "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264\
+                 c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+            ),
+            (
+                "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+                "legal winner thank year wave sausage worth useful legal winner thank yellow",
+                "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3\
+                 c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+            ),
+            (
+                "ffffffffffffffffffffffffffffffff",
+                "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo wrong",
+                "ac27495480225222079d7be181583751e86f571027b0497b5b5d11218e0a8a13332572917f0f8e5a58\
+                 9620c6f15b11c61dee327651a14c34e18231052e48c069",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                 abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                 abandon abandon abandon art",
+                "bda85446c68413707090a52022edd26a1c9462295029f2e60cd7c4f2bbd3097170af7a4d73245cafa9\
+                 c3cca8d561a7c3de6f5d4a10be8ed2a5e608d68f92fcc8",
+            ),
+        ];
+        for (entropy, mnemonic, seed) in vectors.iter() {
+            assert_eq!(entropy_to_mnemonic(&from_hex(entropy)), Ok(mnemonic.to_string()));
+            assert_eq!(mnemonic_to_entropy(mnemonic), Ok(from_hex(entropy)));
+            assert_eq!(mnemonic_to_seed(mnemonic, "TREZOR").unwrap().to_vec(), from_hex(seed));
+        }
+        assert_eq!(
+            mnemonic_to_entropy("zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo"),
+            Err(HdKeyError::InvalidChecksum)
+        );
+        assert_eq!(
+            mnemonic_to_entropy("zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo near"),
+            Err(HdKeyError::UnknownWord("near".to_string()))
+        );
+        let mnemonic = generate_mnemonic(24).unwrap();
+        assert_eq!(mnemonic_to_entropy(&mnemonic).unwrap().len(), 32);
+    }
+
+    /// Test vector 1 of SLIP-0010 for ed25519.
+    #[test]
+    fn test_derivation() {
+        let seed = from_hex("000102030405060708090a0b0c0d0e0f");
+        let master = ExtendedKey::master(&seed);
+        assert_eq!(
+            master.secret_key.to_vec(),
+            from_hex("2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7")
+        );
+        assert_eq!(
+            master.chain_code.to_vec(),
+            from_hex("90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb")
+        );
+        let hd_path = parse_hd_path("m/0'/1'/2'/2'/1000000000'").unwrap();
+        let key = ExtendedKey::derive_path(&seed, &hd_path).unwrap();
+        assert_eq!(
+            key.secret_key.to_vec(),
+            from_hex("8f94d394a8e8fd6b1bc2f3f49f5c47e385281d5c17e65324b0f62483e37e8793")
+        );
+        assert_eq!(
+            key.chain_code.to_vec(),
+            from_hex("68789923a0cac2cd5a29172a475fe9e0fb14cd6adb5ad98a3fa70333e7afa230")
+        );
+        assert_eq!(
+            key.key_pair().0.as_ref().to_vec(),
+            from_hex("3c24da049451555d51a7014a37337aa4e12d41e485abccfa46b47dfb2af54b7a")
+        );
+        assert_eq!(master.derive_child(1).err(), Some(HdKeyError::NonHardenedIndex(1)));
+    }
+}
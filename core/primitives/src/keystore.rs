@@ -0,0 +1,248 @@
+//! Encrypted key files. The secret keys are encrypted with XSalsa20-Poly1305 under a key derived
+//! from a passphrase with scrypt, the parameters of which are stored in the file, so that they can
+//! be raised later without breaking the existing files. The public key stays in the clear to find
+//! the file by. Plain key files are still read, so that they can be imported, and a decrypted key
+//! file is in the plain format, so that it can be exported to the tools that read it.
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use exonum_sodiumoxide::crypto::pwhash::{self, MemLimit, OpsLimit, Salt};
+use exonum_sodiumoxide::crypto::secretbox::{self, Key, Nonce};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Environment variable with the passphrase of the keystore. Keys are written in the clear if
+/// it's not set.
+pub const PASSPHRASE_ENV_VAR: &str = "NEAR_KEYSTORE_PASSPHRASE";
+
+const VERSION: u32 = 1;
+const KDF: &str = "scrypt";
+const CIPHER: &str = "xsalsa20poly1305";
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum KeystoreError {
+    /// Key file cannot be read or written.
+    Io(String),
+    /// Key file is neither a plain nor an encrypted key file of a known version.
+    InvalidFormat(String),
+    /// Key file is encrypted, but no passphrase is given.
+    PassphraseRequired,
+    /// Passphrase doesn't decrypt the key file, or the file was tampered with.
+    WrongPassphrase,
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeystoreError::Io(e) => write!(f, "Cannot access the key file: {}", e),
+            KeystoreError::InvalidFormat(e) => write!(f, "Invalid key file: {}", e),
+            KeystoreError::PassphraseRequired => write!(
+                f,
+                "Key file is encrypted, set the passphrase in {}",
+                PASSPHRASE_ENV_VAR
+            ),
+            KeystoreError::WrongPassphrase => {
+                write!(f, "Passphrase doesn't decrypt the key file")
+            }
+        }
+    }
+}
+
+/// Key file with the encrypted key. Binary fields are base58 encoded.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EncryptedKeyFile {
+    pub version: u32,
+    pub public_key: String,
+    pub kdf: String,
+    pub salt: String,
+    pub opslimit: u64,
+    pub memlimit: u64,
+    pub cipher: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// Passphrase of the keystore from the environment, if set.
+pub fn passphrase_from_env() -> Option<String> {
+    env::var(PASSPHRASE_ENV_VAR).ok().filter(|passphrase| !passphrase.is_empty())
+}
+
+/// Derives the key with the scrypt parameters of the file. Parameters above the sensitive ones
+/// of libsodium are rejected, so that a crafted file cannot make the node spin or exhaust memory.
+fn derive_key(
+    passphrase: &str,
+    salt: &Salt,
+    opslimit: u64,
+    memlimit: u64,
+) -> Result<Key, KeystoreError> {
+    if opslimit > pwhash::OPSLIMIT_SENSITIVE.0 as u64
+        || memlimit > pwhash::MEMLIMIT_SENSITIVE.0 as u64
+    {
+        return Err(KeystoreError::InvalidFormat(format!(
+            "Key derivation parameters {} and {} are too large",
+            opslimit, memlimit,
+        )));
+    }
+    let mut key = Key([0; secretbox::KEYBYTES]);
+    pwhash::derive_key(
+        &mut key.0,
+        passphrase.as_bytes(),
+        salt,
+        OpsLimit(opslimit as usize),
+        MemLimit(memlimit as usize),
+    )
+    .map_err(|_| KeystoreError::InvalidFormat("Cannot derive the key".to_string()))?;
+    Ok(key)
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, KeystoreError> {
+    bs58::decode(value)
+        .into_vec()
+        .map_err(|e| KeystoreError::InvalidFormat(format!("Invalid {}: {}", field, e)))
+}
+
+/// Encrypts the plain key file with the passphrase.
+pub fn encrypt(plaintext: &[u8], public_key: &str, passphrase: &str) -> EncryptedKeyFile {
+    let salt = pwhash::gen_salt();
+    let (opslimit, memlimit) = (pwhash::OPSLIMIT_INTERACTIVE, pwhash::MEMLIMIT_INTERACTIVE);
+    let key = derive_key(passphrase, &salt, opslimit.0 as u64, memlimit.0 as u64)
+        .expect("Key derivation with the default parameters should succeed");
+    let nonce = secretbox::gen_nonce();
+    let ciphertext = secretbox::seal(plaintext, &nonce, &key);
+    EncryptedKeyFile {
+        version: VERSION,
+        public_key: public_key.to_string(),
+        kdf: KDF.to_string(),
+        salt: bs58::encode(salt.0).into_string(),
+        opslimit: opslimit.0 as u64,
+        memlimit: memlimit.0 as u64,
+        cipher: CIPHER.to_string(),
+        nonce: bs58::encode(nonce.0).into_string(),
+        ciphertext: bs58::encode(ciphertext).into_string(),
+    }
+}
+
+/// Decrypts the key file into the plain key file.
+pub fn decrypt(key_file: &EncryptedKeyFile, passphrase: &str) -> Result<Vec<u8>, KeystoreError> {
+    if key_file.version != VERSION || key_file.kdf != KDF || key_file.cipher != CIPHER {
+        return Err(KeystoreError::InvalidFormat(format!(
+            "Unsupported version {} with {} and {}",
+            key_file.version, key_file.kdf, key_file.cipher,
+        )));
+    }
+    let salt = Salt::from_slice(&decode("salt", &key_file.salt)?)
+        .ok_or_else(|| KeystoreError::InvalidFormat("Invalid salt length".to_string()))?;
+    let nonce = Nonce::from_slice(&decode("nonce", &key_file.nonce)?)
+        .ok_or_else(|| KeystoreError::InvalidFormat("Invalid nonce length".to_string()))?;
+    let ciphertext = decode("ciphertext", &key_file.ciphertext)?;
+    let key = derive_key(passphrase, &salt, key_file.opslimit, key_file.memlimit)?;
+    secretbox::open(&ciphertext, &nonce, &key).map_err(|_| KeystoreError::WrongPassphrase)
+}
+
+/// Reads the key file in the plain format, decrypting it with the passphrase if it's encrypted.
+pub fn export_key_file(path: &Path, passphrase: Option<&str>) -> Result<String, KeystoreError> {
+    let content = fs::read_to_string(path).map_err(|e| KeystoreError::Io(e.to_string()))?;
+    match serde_json::from_str::<EncryptedKeyFile>(&content) {
+        Ok(key_file) => {
+            let passphrase = passphrase.ok_or(KeystoreError::PassphraseRequired)?;
+            String::from_utf8(decrypt(&key_file, passphrase)?)
+                .map_err(|e| KeystoreError::InvalidFormat(e.to_string()))
+        }
+        Err(_) => Ok(content),
+    }
+}
+
+/// Reads the key file, decrypting it with the passphrase if it's encrypted.
+pub fn read_key_file<T: DeserializeOwned>(
+    path: &Path,
+    passphrase: Option<&str>,
+) -> Result<T, KeystoreError> {
+    let plaintext = export_key_file(path, passphrase)?;
+    serde_json::from_str(&plaintext).map_err(|e| KeystoreError::InvalidFormat(e.to_string()))
+}
+
+/// Writes the key file, encrypted with the passphrase if there is one. Only the owner can read
+/// the file.
+pub fn write_key_file<T: Serialize>(
+    path: &Path,
+    key: &T,
+    public_key: &str,
+    passphrase: Option<&str>,
+) -> Result<(), KeystoreError> {
+    let plaintext = serde_json::to_vec(key).map_err(|e| KeystoreError::Io(e.to_string()))?;
+    let content = match passphrase {
+        Some(passphrase) => serde_json::to_vec(&encrypt(&plaintext, public_key, passphrase))
+            .map_err(|e| KeystoreError::Io(e.to_string()))?,
+        None => plaintext,
+    };
+    fs::write(path, content).map_err(|e| KeystoreError::Io(e.to_string()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+            .map_err(|e| KeystoreError::Io(e.to_string()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct TestKey {
+        public_key: String,
+        secret_key: String,
+    }
+
+    fn key_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("near-keystore-test-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_encrypted_key_file() {
+        let key = TestKey { public_key: "public".to_string(), secret_key: "secret".to_string() };
+        let path = key_path("encrypted");
+        write_key_file(&path, &key, "public", Some("passphrase")).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("secret"));
+        let key_file: EncryptedKeyFile = serde_json::from_str(&content).unwrap();
+        assert_eq!(key_file.public_key, "public");
+
+        assert_eq!(read_key_file::<TestKey>(&path, Some("passphrase")), Ok(key));
+        let exported = export_key_file(&path, Some("passphrase")).unwrap();
+        assert_eq!(exported, r#"{"public_key":"public","secret_key":"secret"}"#);
+        assert_eq!(
+            read_key_file::<TestKey>(&path, Some("other")),
+            Err(KeystoreError::WrongPassphrase)
+        );
+        assert_eq!(read_key_file::<TestKey>(&path, None), Err(KeystoreError::PassphraseRequired));
+
+        let mut tampered = key_file.clone();
+        let mut ciphertext = decode("ciphertext", &key_file.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        tampered.ciphertext = bs58::encode(ciphertext).into_string();
+        assert_eq!(decrypt(&tampered, "passphrase"), Err(KeystoreError::WrongPassphrase));
+        let mut expensive = key_file.clone();
+        expensive.memlimit = u64::max_value();
+        assert!(match decrypt(&expensive, "passphrase") {
+            Err(KeystoreError::InvalidFormat(_)) => true,
+            _ => false,
+        });
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_plain_key_file() {
+        let key = TestKey { public_key: "public".to_string(), secret_key: "secret".to_string() };
+        let path = key_path("plain");
+        write_key_file(&path, &key, "public", None).unwrap();
+        // Plain key files are read with or without the passphrase.
+        assert_eq!(read_key_file::<TestKey>(&path, Some("passphrase")), Ok(key));
+        fs::remove_file(path).unwrap();
+    }
+}
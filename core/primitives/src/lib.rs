@@ -1,22 +1,38 @@
 extern crate bincode;
 extern crate bs58;
 extern crate byteorder;
+extern crate dalek_sha2;
+extern crate ed25519_dalek;
 extern crate exonum_sodiumoxide;
 extern crate heapsize;
+extern crate hmac;
+#[macro_use]
+extern crate lazy_static;
 extern crate pairing;
 extern crate rand;
 extern crate regex;
+extern crate secp256k1;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate unicode_normalization;
 
 pub mod aggregate_signature;
+pub mod borsh;
+pub mod erasure;
 pub mod hash;
+pub mod hd_key;
+pub mod keystore;
+pub mod limited;
+pub mod merkle;
+pub mod remote_signer;
+pub mod schema;
 pub mod serialize;
 pub mod signature;
 pub mod signer;
 pub mod traits;
 pub mod types;
 pub mod utils;
+pub mod vrf;
 pub mod test_utils;
@@ -0,0 +1,494 @@
+//! Limits of the values decoded from untrusted input, such as the messages of the peers or the
+//! transactions and receipts in them. The decoders only check that the input doesn't end before
+//! the value does, so a few bytes can claim a sequence of billions of items or nest an enum in
+//! itself until the stack overflows. The limits are checked while the value is decoded, by
+//! wrapping the deserializer of any format, so the decoding stops at the first item over them.
+use std::cell::Cell;
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{
+    self, Deserialize, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+
+/// Limits of a decoded value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Max size of the input, in bytes.
+    pub max_size: usize,
+    /// Max number of items of a sequence or entries of a map, and of bytes of a string or a byte
+    /// array.
+    pub max_len: usize,
+    /// Max nesting of the sequences, maps, structs, enums and options.
+    pub max_depth: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits { max_size: 16 * 1024 * 1024, max_len: 4 * 1024 * 1024, max_depth: 64 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitError {
+    /// Input is larger than `max_size`.
+    TooLarge { size: usize, limit: usize },
+    /// Sequence, map, string or byte array is longer than `max_len`.
+    TooLong { len: usize, limit: usize },
+    /// Value is nested deeper than `max_depth`.
+    TooDeep { limit: usize },
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitError::TooLarge { size, limit } => {
+                write!(f, "Input of {} bytes is over the limit of {} bytes", size, limit)
+            }
+            LimitError::TooLong { len, limit } => {
+                write!(f, "Length {} is over the limit of {}", len, limit)
+            }
+            LimitError::TooDeep { limit } => write!(f, "Value is nested deeper than {}", limit),
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+/// Limits of the value being decoded, and the first of them it went over. The error of the
+/// format only has the message, so the limit is kept here to be returned typed.
+struct State {
+    limits: DecodeLimits,
+    depth: Cell<usize>,
+    error: Cell<Option<LimitError>>,
+}
+
+impl State {
+    fn fail<E: de::Error>(&self, error: LimitError) -> E {
+        self.error.set(Some(error));
+        E::custom(error)
+    }
+
+    fn check_len<E: de::Error>(&self, len: usize) -> Result<(), E> {
+        if len > self.limits.max_len {
+            return Err(self.fail(LimitError::TooLong { len, limit: self.limits.max_len }));
+        }
+        Ok(())
+    }
+
+    fn enter<E: de::Error>(&self) -> Result<(), E> {
+        let depth = self.depth.get() + 1;
+        if depth > self.limits.max_depth {
+            return Err(self.fail(LimitError::TooDeep { limit: self.limits.max_depth }));
+        }
+        self.depth.set(depth);
+        Ok(())
+    }
+
+    fn exit(&self) {
+        self.depth.set(self.depth.get() - 1);
+    }
+}
+
+/// Decodes the value of `size` bytes within the limits. `decode` deserializes the value with the
+/// given seed from the deserializer of the format, and an error of it that comes from a limit is
+/// returned as the `LimitError`.
+pub fn decode<T, E, F>(limits: &DecodeLimits, size: usize, decode: F) -> Result<T, E>
+where
+    T: for<'de> Deserialize<'de>,
+    E: From<LimitError>,
+    F: for<'s> FnOnce(LimitedSeed<'s, PhantomData<T>>) -> Result<T, E>,
+{
+    if size > limits.max_size {
+        return Err(LimitError::TooLarge { size, limit: limits.max_size }.into());
+    }
+    let state = State { limits: *limits, depth: Cell::new(0), error: Cell::new(None) };
+    let result = decode(LimitedSeed { seed: PhantomData, state: &state });
+    match state.error.get() {
+        Some(error) => Err(error.into()),
+        None => result,
+    }
+}
+
+/// Seed that deserializes with the limits.
+pub struct LimitedSeed<'s, S> {
+    seed: S,
+    state: &'s State,
+}
+
+impl<'de, 's, S: DeserializeSeed<'de>> DeserializeSeed<'de> for LimitedSeed<'s, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<S::Value, D::Error> {
+        self.seed.deserialize(Limited { inner: deserializer, state: self.state })
+    }
+}
+
+struct Limited<'s, D> {
+    inner: D,
+    state: &'s State,
+}
+
+impl<'s, D> Limited<'s, D> {
+    fn visitor<V>(&self, visitor: V) -> LimitedVisitor<'s, V> {
+        LimitedVisitor { visitor, state: self.state }
+    }
+}
+
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*),)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(
+                self,
+                $($arg: $ty,)*
+                visitor: V,
+            ) -> Result<V::Value, D::Error> {
+                let visitor = self.visitor(visitor);
+                self.inner.$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 's, D: Deserializer<'de>> Deserializer<'de> for Limited<'s, D> {
+    type Error = D::Error;
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+
+    forward_deserialize! {
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_i128(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_u128(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+        deserialize_identifier(),
+        deserialize_ignored_any(),
+    }
+}
+
+struct LimitedVisitor<'s, V> {
+    visitor: V,
+    state: &'s State,
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty),)*) => {
+        $(
+            fn $method<E: de::Error>(self, v: $ty) -> Result<V::Value, E> {
+                self.visitor.$method(v)
+            }
+        )*
+    };
+}
+
+impl<'de, 's, V: Visitor<'de>> Visitor<'de> for LimitedVisitor<'s, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.visitor.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(bool),
+        visit_i8(i8),
+        visit_i16(i16),
+        visit_i32(i32),
+        visit_i64(i64),
+        visit_i128(i128),
+        visit_u8(u8),
+        visit_u16(u16),
+        visit_u32(u32),
+        visit_u64(u64),
+        visit_u128(u128),
+        visit_f32(f32),
+        visit_f64(f64),
+        visit_char(char),
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<V::Value, E> {
+        self.state.check_len(v.len())?;
+        self.visitor.visit_str(v)
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<V::Value, E> {
+        self.state.check_len(v.len())?;
+        self.visitor.visit_borrowed_str(v)
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<V::Value, E> {
+        self.state.check_len(v.len())?;
+        self.visitor.visit_string(v)
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<V::Value, E> {
+        self.state.check_len(v.len())?;
+        self.visitor.visit_bytes(v)
+    }
+
+    fn visit_borrowed_bytes<E: de::Error>(self, v: &'de [u8]) -> Result<V::Value, E> {
+        self.state.check_len(v.len())?;
+        self.visitor.visit_borrowed_bytes(v)
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<V::Value, E> {
+        self.state.check_len(v.len())?;
+        self.visitor.visit_byte_buf(v)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+        self.visitor.visit_none()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+        self.state.enter()?;
+        let value = self.visitor.visit_some(Limited { inner: deserializer, state: self.state });
+        self.state.exit();
+        value
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+        self.visitor.visit_unit()
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<V::Value, D::Error> {
+        self.state.enter()?;
+        let limited = Limited { inner: deserializer, state: self.state };
+        let value = self.visitor.visit_newtype_struct(limited);
+        self.state.exit();
+        value
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+        if let Some(len) = seq.size_hint() {
+            self.state.check_len(len)?;
+        }
+        self.state.enter()?;
+        let value = self.visitor.visit_seq(Access { inner: seq, state: self.state, len: 0 });
+        self.state.exit();
+        value
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        if let Some(len) = map.size_hint() {
+            self.state.check_len(len)?;
+        }
+        self.state.enter()?;
+        let value = self.visitor.visit_map(Access { inner: map, state: self.state, len: 0 });
+        self.state.exit();
+        value
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<V::Value, A::Error> {
+        self.state.enter()?;
+        let value = self.visitor.visit_enum(Access { inner: data, state: self.state, len: 0 });
+        self.state.exit();
+        value
+    }
+}
+
+/// Items of a sequence or a map, which are counted in case the length isn't known ahead, or the
+/// variant of an enum.
+struct Access<'s, A> {
+    inner: A,
+    state: &'s State,
+    len: usize,
+}
+
+impl<'s, A> Access<'s, A> {
+    fn seed<S>(&self, seed: S) -> LimitedSeed<'s, S> {
+        LimitedSeed { seed, state: self.state }
+    }
+}
+
+impl<'de, 's, A: SeqAccess<'de>> SeqAccess<'de> for Access<'s, A> {
+    type Error = A::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, A::Error> {
+        let seed = self.seed(seed);
+        let element = self.inner.next_element_seed(seed)?;
+        if element.is_some() {
+            self.len += 1;
+            self.state.check_len(self.len)?;
+        }
+        Ok(element)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, 's, A: MapAccess<'de>> MapAccess<'de> for Access<'s, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        let seed = self.seed(seed);
+        let key = self.inner.next_key_seed(seed)?;
+        if key.is_some() {
+            self.len += 1;
+            self.state.check_len(self.len)?;
+        }
+        Ok(key)
+    }
+
+    fn next_value_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value, A::Error> {
+        let seed = self.seed(seed);
+        self.inner.next_value_seed(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, 's, A: EnumAccess<'de>> EnumAccess<'de> for Access<'s, A> {
+    type Error = A::Error;
+    type Variant = Access<'s, A::Variant>;
+
+    fn variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<(T::Value, Self::Variant), A::Error> {
+        let state = self.state;
+        let seed = self.seed(seed);
+        let (value, variant) = self.inner.variant_seed(seed)?;
+        Ok((value, Access { inner: variant, state, len: 0 }))
+    }
+}
+
+impl<'de, 's, A: VariantAccess<'de>> VariantAccess<'de> for Access<'s, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.inner.unit_variant()
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, A::Error> {
+        let seed = self.seed(seed);
+        self.inner.newtype_variant_seed(seed)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        let visitor = LimitedVisitor { visitor, state: self.state };
+        self.inner.tuple_variant(len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        let visitor = LimitedVisitor { visitor, state: self.state };
+        self.inner.struct_variant(fields, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::borsh;
+    use crate::serialize::{DecodeError, DecodeLimited, Encode};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Nested {
+        Leaf(Vec<u8>),
+        Node(Box<Nested>),
+    }
+
+    fn nested(depth: usize) -> Nested {
+        (0..depth).fold(Nested::Leaf(vec![1, 2]), |nested, _| Nested::Node(Box::new(nested)))
+    }
+
+    const LIMITS: DecodeLimits = DecodeLimits { max_size: 64, max_len: 8, max_depth: 8 };
+
+    #[test]
+    fn test_limits() {
+        let value = nested(3);
+        let bytes = borsh::to_vec(&value).unwrap();
+        assert_eq!(borsh::from_slice_limited::<Nested>(&bytes, &LIMITS), Ok(value));
+        let deep = borsh::to_vec(&nested(10)).unwrap();
+        assert_eq!(
+            borsh::from_slice_limited::<Nested>(&deep, &LIMITS),
+            Err(borsh::Error::Limit(LimitError::TooDeep { limit: 8 }))
+        );
+        let long = borsh::to_vec(&Nested::Leaf(vec![0; 9])).unwrap();
+        assert_eq!(
+            borsh::from_slice_limited::<Nested>(&long, &LIMITS),
+            Err(borsh::Error::Limit(LimitError::TooLong { len: 9, limit: 8 }))
+        );
+        // Five bytes claim a leaf of 2^32 - 1 bytes.
+        assert_eq!(
+            borsh::from_slice_limited::<Nested>(&[0, 255, 255, 255, 255], &LIMITS),
+            Err(borsh::Error::Limit(LimitError::TooLong { len: 4294967295, limit: 8 }))
+        );
+        assert_eq!(
+            borsh::from_slice_limited::<Nested>(&[0; 65], &LIMITS),
+            Err(borsh::Error::Limit(LimitError::TooLarge { size: 65, limit: 64 }))
+        );
+
+        let value = nested(3);
+        assert_eq!(Nested::decode_limited(&value.encode().unwrap(), &LIMITS), Ok(value));
+        assert_eq!(
+            Nested::decode_limited(&nested(10).encode().unwrap(), &LIMITS),
+            Err(DecodeError::Limit(LimitError::TooDeep { limit: 8 }))
+        );
+        assert!(match Nested::decode_limited(&[7], &LIMITS) {
+            Err(DecodeError::Invalid(_)) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_mutated_input() {
+        let borsh_bytes = borsh::to_vec(&nested(5)).unwrap();
+        let bincode_bytes = nested(5).encode().unwrap();
+        for bytes in &[borsh_bytes, bincode_bytes] {
+            for i in 0..bytes.len() {
+                for byte in &[0, 1, 2, 0x7f, 0x80, 0xff] {
+                    let mut mutated = bytes.clone();
+                    mutated[i] = *byte;
+                    let _ = borsh::from_slice_limited::<Nested>(&mutated, &LIMITS);
+                    let _ = Nested::decode_limited(&mutated, &LIMITS);
+                    let _ = Nested::decode_limited(&mutated[..i], &LIMITS);
+                }
+            }
+        }
+    }
+}
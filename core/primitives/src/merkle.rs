@@ -0,0 +1,85 @@
+//! Binary Merkle tree over serializable items, with paths that prove that an item is included.
+use serde::Serialize;
+
+use crate::hash::{hash, hash_struct};
+use crate::types::MerkleHash;
+
+/// Side of the sibling that is combined with the hash on the way to the root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerklePathItem {
+    pub hash: MerkleHash,
+    pub direction: Direction,
+}
+
+/// Siblings from the item up to the root.
+pub type MerklePath = Vec<MerklePathItem>;
+
+fn combine_hash(left: MerkleHash, right: MerkleHash) -> MerkleHash {
+    let mut data = left.as_ref().to_vec();
+    data.extend_from_slice(right.as_ref());
+    hash(&data)
+}
+
+/// Returns the root of the tree over the items and the path of every item. A node without a
+/// sibling is carried to the next level as is. The root of no items is the default hash.
+pub fn merklize<T: Serialize>(items: &[T]) -> (MerkleHash, Vec<MerklePath>) {
+    if items.is_empty() {
+        return (MerkleHash::default(), vec![]);
+    }
+    let mut level: Vec<MerkleHash> = items.iter().map(hash_struct).collect();
+    let mut paths: Vec<MerklePath> = vec![vec![]; items.len()];
+    // Position of the node on the current level that each item is under.
+    let mut positions: Vec<usize> = (0..items.len()).collect();
+    while level.len() > 1 {
+        for (path, position) in paths.iter_mut().zip(positions.iter_mut()) {
+            let sibling = *position ^ 1;
+            if sibling < level.len() {
+                let direction =
+                    if sibling < *position { Direction::Left } else { Direction::Right };
+                path.push(MerklePathItem { hash: level[sibling], direction });
+            }
+            *position /= 2;
+        }
+        level = level
+            .chunks(2)
+            .map(|pair| if pair.len() == 2 { combine_hash(pair[0], pair[1]) } else { pair[0] })
+            .collect();
+    }
+    (level[0], paths)
+}
+
+/// Checks that the path leads from the item to the root.
+pub fn verify_path<T: Serialize>(root: MerkleHash, path: &[MerklePathItem], item: &T) -> bool {
+    let hash = path.iter().fold(hash_struct(item), |hash, sibling| match sibling.direction {
+        Direction::Left => combine_hash(sibling.hash, hash),
+        Direction::Right => combine_hash(hash, sibling.hash),
+    });
+    hash == root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merklize() {
+        assert_eq!(merklize::<u64>(&[]), (MerkleHash::default(), vec![]));
+        for len in 1..10u64 {
+            let items: Vec<u64> = (0..len).collect();
+            let (root, paths) = merklize(&items);
+            assert_eq!(paths.len(), items.len());
+            for (item, path) in items.iter().zip(paths.iter()) {
+                assert!(verify_path(root, path, item));
+                assert!(!verify_path(root, path, &(item + len)));
+            }
+        }
+        let (root, _) = merklize(&[1u64]);
+        assert_eq!(root, hash_struct(&1u64));
+    }
+}
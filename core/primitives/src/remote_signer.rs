@@ -0,0 +1,461 @@
+//! Signing of the blocks by a separate service, so that the BLS secret key of an authority is
+//! kept in an HSM or on another machine rather than by the node. The node sends the kind, index
+//! and hash of each block to sign. The service keeps the last block of each kind it signed on
+//! disk and refuses to sign a second block at the index or a block before it, so that a
+//! compromised node can't get the authority slashed for double signing. It also gives out the VRF
+//! proofs of the producer, for which it builds the input itself, and signs the node id the
+//! authority runs in the network.
+//!
+//! Requests and responses are JSON over HTTP, authenticated both ways with a key the node and the
+//! service share: the request carries the HMAC of its body, the response the HMAC of the request's
+//! one and its body, so that it can't be replayed for another request. Replaying a request only
+//! returns the signature already given out.
+use std::fs;
+use std::io::{self, BufRead, BufReader, Error, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use exonum_sodiumoxide::crypto::auth::hmacsha256::{self, Tag};
+
+use crate::aggregate_signature::BlsPublicKey;
+use crate::hash::CryptoHash;
+use crate::signature::bs58_serializer;
+use crate::signer::{node_id_message, BlockKind, InMemorySigner, SignerError};
+use crate::traits::Signer;
+use crate::types::{AccountId, PartialSignature};
+use crate::vrf::{self, VrfProof};
+
+/// Key shared by the node and the signing service.
+pub type AuthKey = hmacsha256::Key;
+
+const AUTH_HEADER: &str = "x-signer-auth";
+const TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+enum Request {
+    PublicKey,
+    SignBlock { kind: BlockKind, index: u64, hash: CryptoHash },
+    VrfProve { randomness: CryptoHash, index: u64 },
+    SignNodeId { node_id: CryptoHash },
+}
+
+#[derive(Serialize, Deserialize)]
+enum Response {
+    PublicKey {
+        #[serde(with = "bs58_serializer")]
+        public_key: BlsPublicKey,
+    },
+    Signature {
+        #[serde(with = "bs58_serializer")]
+        signature: PartialSignature,
+    },
+    Error(SignerError),
+}
+
+pub fn generate_auth_key() -> AuthKey {
+    hmacsha256::gen_key()
+}
+
+/// Writes the key base58 encoded, readable by the owner only.
+pub fn write_auth_key(path: &Path, key: &AuthKey) -> io::Result<()> {
+    fs::write(path, bs58::encode(&key.0).into_string())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+pub fn read_auth_key(path: &Path) -> io::Result<AuthKey> {
+    let content = fs::read_to_string(path)?;
+    let bytes = bs58::decode(content.trim())
+        .into_vec()
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    AuthKey::from_slice(&bytes)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid auth key length"))
+}
+
+fn io_error(e: io::Error) -> SignerError {
+    SignerError::Io(e.to_string())
+}
+
+/// Message the response HMAC is computed over, which binds the response to its request.
+fn response_message(request_tag: &Tag, body: &[u8]) -> Vec<u8> {
+    [&request_tag.0[..], body].concat()
+}
+
+fn parse_tag(auth: Option<&str>) -> Option<Tag> {
+    bs58::decode(auth?).into_vec().ok().and_then(|bytes| Tag::from_slice(&bytes))
+}
+
+struct HttpMessage {
+    auth: Option<String>,
+    body: Vec<u8>,
+}
+
+fn read_message(stream: &TcpStream) -> io::Result<HttpMessage> {
+    let mut reader = BufReader::new(stream);
+    let mut start_line = String::new();
+    reader.read_line(&mut start_line)?;
+    let (mut content_length, mut auth) = (0, None);
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Incomplete HTTP headers"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(colon) = line.find(':') {
+            let (name, value) = (line[..colon].trim().to_lowercase(), line[colon + 1..].trim());
+            if name == "content-length" {
+                content_length =
+                    value.parse().map_err(|_| Error::new(ErrorKind::InvalidData, line))?;
+            } else if name == AUTH_HEADER {
+                auth = Some(value.to_string());
+            }
+        }
+    }
+    if content_length > MAX_BODY_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, "HTTP body is too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(HttpMessage { auth, body })
+}
+
+fn write_message(
+    mut stream: &TcpStream,
+    start_line: &str,
+    auth: Option<&Tag>,
+    body: &[u8],
+) -> io::Result<()> {
+    let mut message = format!(
+        "{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        start_line,
+        body.len()
+    );
+    if let Some(tag) = auth {
+        message += &format!("{}: {}\r\n", AUTH_HEADER, bs58::encode(&tag.0).into_string());
+    }
+    message += "\r\n";
+    stream.write_all(message.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+fn connect(addr: &SocketAddr) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(addr, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    Ok(stream)
+}
+
+fn call(addr: &SocketAddr, key: &AuthKey, request: &Request) -> Result<Response, SignerError> {
+    let body = serde_json::to_vec(request).expect("Requests are serializable");
+    let tag = hmacsha256::authenticate(&body, key);
+    let stream = connect(addr).map_err(io_error)?;
+    write_message(&stream, "POST / HTTP/1.1", Some(&tag), &body).map_err(io_error)?;
+    let response = read_message(&stream).map_err(io_error)?;
+    match parse_tag(response.auth.as_ref().map(String::as_str)) {
+        Some(response_tag)
+            if hmacsha256::verify(&response_tag, &response_message(&tag, &response.body), key) =>
+        {
+            serde_json::from_slice(&response.body)
+                .map_err(|e| SignerError::InvalidMessage(e.to_string()))
+        }
+        _ => Err(SignerError::Unauthorized),
+    }
+}
+
+/// Signer of the blocks that asks the signing service for the signatures.
+pub struct RemoteSigner {
+    account_id: AccountId,
+    addr: SocketAddr,
+    auth_key: AuthKey,
+    public_key: BlsPublicKey,
+}
+
+impl RemoteSigner {
+    /// Connects to the signing service and gets the public key it signs with.
+    pub fn connect(
+        account_id: AccountId,
+        addr: &str,
+        auth_key: AuthKey,
+    ) -> Result<Self, SignerError> {
+        let addr = addr
+            .to_socket_addrs()
+            .map_err(io_error)?
+            .next()
+            .ok_or_else(|| SignerError::Io(format!("Cannot resolve {}", addr)))?;
+        match call(&addr, &auth_key, &Request::PublicKey)? {
+            Response::PublicKey { public_key } => {
+                Ok(RemoteSigner { account_id, addr, auth_key, public_key })
+            }
+            Response::Error(e) => Err(e),
+            Response::Signature { .. } => {
+                Err(SignerError::InvalidMessage("Expected the public key".to_string()))
+            }
+        }
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn public_key(&self) -> BlsPublicKey {
+        self.public_key.clone()
+    }
+
+    fn sign_block(
+        &self,
+        kind: BlockKind,
+        index: u64,
+        hash: &CryptoHash,
+    ) -> Result<PartialSignature, SignerError> {
+        let request = Request::SignBlock { kind, index, hash: *hash };
+        match call(&self.addr, &self.auth_key, &request)? {
+            Response::Signature { signature } => {
+                if !self.public_key.verify(hash.as_ref(), &signature) {
+                    return Err(SignerError::InvalidMessage("Invalid signature".to_string()));
+                }
+                Ok(signature)
+            }
+            Response::Error(e) => Err(e),
+            Response::PublicKey { .. } => {
+                Err(SignerError::InvalidMessage("Expected a signature".to_string()))
+            }
+        }
+    }
+
+    fn vrf_prove(&self, randomness: &CryptoHash, index: u64) -> Result<VrfProof, SignerError> {
+        let request = Request::VrfProve { randomness: *randomness, index };
+        match call(&self.addr, &self.auth_key, &request)? {
+            Response::Signature { signature } => {
+                let input = vrf::vrf_input(randomness, index);
+                if vrf::verify(&self.public_key, &input, &signature).is_none() {
+                    return Err(SignerError::InvalidMessage("Invalid VRF proof".to_string()));
+                }
+                Ok(signature)
+            }
+            Response::Error(e) => Err(e),
+            Response::PublicKey { .. } => {
+                Err(SignerError::InvalidMessage("Expected a VRF proof".to_string()))
+            }
+        }
+    }
+
+    fn sign_node_id(&self, node_id: &CryptoHash) -> Result<PartialSignature, SignerError> {
+        let request = Request::SignNodeId { node_id: *node_id };
+        match call(&self.addr, &self.auth_key, &request)? {
+            Response::Signature { signature } => {
+                if !self.public_key.verify(&node_id_message(node_id), &signature) {
+                    return Err(SignerError::InvalidMessage("Invalid signature".to_string()));
+                }
+                Ok(signature)
+            }
+            Response::Error(e) => Err(e),
+            Response::PublicKey { .. } => {
+                Err(SignerError::InvalidMessage("Expected a signature".to_string()))
+            }
+        }
+    }
+
+    fn account_id(&self) -> AccountId {
+        self.account_id.clone()
+    }
+}
+
+/// Last block of each kind the signing service signed.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SigningState {
+    beacon: Option<(u64, CryptoHash)>,
+    shard: Option<(u64, CryptoHash)>,
+}
+
+impl SigningState {
+    fn last_signed(&mut self, kind: BlockKind) -> &mut Option<(u64, CryptoHash)> {
+        match kind {
+            BlockKind::Beacon => &mut self.beacon,
+            BlockKind::Shard => &mut self.shard,
+        }
+    }
+}
+
+/// Service that signs the blocks with the key of the authority, at most one block of a kind at
+/// an index and only after the last one it signed.
+pub struct SigningService {
+    signer: InMemorySigner,
+    auth_key: AuthKey,
+    state_path: PathBuf,
+    state: SigningState,
+}
+
+impl SigningService {
+    /// Picks up the blocks signed before, kept at the state path.
+    pub fn new(signer: InMemorySigner, auth_key: AuthKey, state_path: &Path) -> io::Result<Self> {
+        let state = if state_path.exists() {
+            serde_json::from_str(&fs::read_to_string(state_path)?)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?
+        } else {
+            SigningState::default()
+        };
+        Ok(SigningService { signer, auth_key, state_path: state_path.to_path_buf(), state })
+    }
+
+    /// Writes the state to a temporary file first, so that it's never left half written. Both the
+    /// file and the rename are synced to the disk before the signature is given out, so that the
+    /// signed block isn't forgotten on a power loss.
+    fn save(&self, state: &SigningState) -> io::Result<()> {
+        let content = serde_json::to_string(state).map_err(|e| Error::new(ErrorKind::Other, e))?;
+        let tmp_path = self.state_path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(tmp_path, &self.state_path)?;
+        #[cfg(unix)]
+        {
+            let dir = self.state_path.parent().filter(|dir| !dir.as_os_str().is_empty());
+            fs::File::open(dir.unwrap_or_else(|| Path::new(".")))?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn sign_block(
+        &mut self,
+        kind: BlockKind,
+        index: u64,
+        hash: &CryptoHash,
+    ) -> Result<PartialSignature, SignerError> {
+        let last_signed = *self.state.last_signed(kind);
+        if let Some((signed_index, signed_hash)) = last_signed {
+            if index < signed_index || (index == signed_index && *hash != signed_hash) {
+                return Err(SignerError::DoubleSign { kind, index, signed_index });
+            }
+        }
+        if last_signed != Some((index, *hash)) {
+            // The block is recorded before the signature is given out.
+            let mut state = self.state.clone();
+            *state.last_signed(kind) = Some((index, *hash));
+            self.save(&state).map_err(io_error)?;
+            self.state = state;
+        }
+        Ok(self.signer.sign(hash))
+    }
+
+    fn handle(&mut self, request: Request) -> Response {
+        match request {
+            Request::PublicKey => Response::PublicKey { public_key: self.signer.public_key() },
+            Request::SignBlock { kind, index, hash } => match self.sign_block(kind, index, &hash) {
+                Ok(signature) => Response::Signature { signature },
+                Err(e) => Response::Error(e),
+            },
+            Request::VrfProve { randomness, index } => {
+                let input = vrf::vrf_input(&randomness, index);
+                Response::Signature { signature: vrf::prove(&self.signer.secret_key, &input) }
+            }
+            Request::SignNodeId { node_id } => match self.signer.sign_node_id(&node_id) {
+                Ok(signature) => Response::Signature { signature },
+                Err(e) => Response::Error(e),
+            },
+        }
+    }
+
+    fn serve_connection(&mut self, stream: &TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let request = read_message(stream)?;
+        let tag = match parse_tag(request.auth.as_ref().map(String::as_str)) {
+            Some(tag) if hmacsha256::verify(&tag, &request.body, &self.auth_key) => tag,
+            _ => return write_message(stream, "HTTP/1.1 401 Unauthorized", None, &[]),
+        };
+        let response = match serde_json::from_slice(&request.body) {
+            Ok(request) => self.handle(request),
+            Err(e) => Response::Error(SignerError::InvalidMessage(e.to_string())),
+        };
+        let body = serde_json::to_vec(&response).expect("Responses are serializable");
+        let tag = hmacsha256::authenticate(&response_message(&tag, &body), &self.auth_key);
+        write_message(stream, "HTTP/1.1 200 OK", Some(&tag), &body)
+    }
+
+    /// Serves the requests one at a time, so that the blocks are signed in order. The errors of
+    /// the connections are passed to `on_error`, the service keeps serving the next ones.
+    pub fn serve(mut self, listener: TcpListener, mut on_error: impl FnMut(io::Error)) {
+        for stream in listener.incoming() {
+            if let Err(e) = stream.and_then(|stream| self.serve_connection(&stream)) {
+                on_error(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::thread;
+
+    use super::*;
+
+    fn state_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("near-remote-signer-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_double_sign_protection() {
+        let path = state_path("state");
+        let _ = fs::remove_file(&path);
+        let signer = InMemorySigner::default();
+        let mut service =
+            SigningService::new(InMemorySigner::default(), generate_auth_key(), &path).unwrap();
+        let (hash1, hash2) = (CryptoHash::new(&[1; 32]), CryptoHash::new(&[2; 32]));
+
+        assert!(service.sign_block(BlockKind::Beacon, 2, &hash1).is_ok());
+        // The same block is signed again, e.g. endorsed by its producer.
+        assert!(service.sign_block(BlockKind::Beacon, 2, &hash1).is_ok());
+        let double_sign =
+            SignerError::DoubleSign { kind: BlockKind::Beacon, index: 2, signed_index: 2 };
+        assert_eq!(service.sign_block(BlockKind::Beacon, 2, &hash2), Err(double_sign));
+        assert!(service.sign_block(BlockKind::Shard, 2, &hash2).is_ok());
+        assert!(service.sign_block(BlockKind::Beacon, 1, &hash2).is_err());
+
+        // The signed blocks survive restarts.
+        let mut service = SigningService::new(signer, generate_auth_key(), &path).unwrap();
+        assert!(service.sign_block(BlockKind::Beacon, 2, &hash2).is_err());
+        assert!(service.sign_block(BlockKind::Beacon, 3, &hash2).is_ok());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_remote_signer() {
+        let path = state_path("service");
+        let _ = fs::remove_file(&path);
+        let signer = InMemorySigner::default();
+        let public_key = signer.public_key();
+        let auth_key = generate_auth_key();
+        let service = SigningService::new(signer, auth_key.clone(), &path).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || service.serve(listener, |_| ()));
+
+        let account_id = "alice.near".to_string();
+        let remote = RemoteSigner::connect(account_id.clone(), &addr, auth_key).unwrap();
+        assert_eq!(remote.public_key().to_string(), public_key.to_string());
+        let hash = CryptoHash::new(&[1; 32]);
+        let signature = remote.sign_block(BlockKind::Beacon, 1, &hash).unwrap();
+        assert!(public_key.verify(hash.as_ref(), &signature));
+        match remote.sign_block(BlockKind::Beacon, 1, &CryptoHash::default()) {
+            Err(SignerError::DoubleSign { .. }) => {}
+            result => panic!("Expected the double sign to be refused, got {:?}", result),
+        }
+        let proof = remote.vrf_prove(&hash, 2).unwrap();
+        assert!(vrf::verify(&public_key, &vrf::vrf_input(&hash, 2), &proof).is_some());
+        let signature = remote.sign_node_id(&hash).unwrap();
+        assert!(public_key.verify(&node_id_message(&hash), &signature));
+
+        let unauthorized = RemoteSigner::connect(account_id, &addr, generate_auth_key());
+        assert_eq!(unauthorized.err(), Some(SignerError::Unauthorized));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,688 @@
+//! Schema of the types in the serde data model, traced from their `Deserialize` impls, so that
+//! the SDKs in other languages can generate the types and their borsh encoding, see
+//! `primitives::borsh`. Tracing deserializes each type from sample values, once per variant of
+//! each enum it contains, and records the structs, tuples and enums it asks for.
+//!
+//! Types that validate their bytes, such as keys and signatures, are retried with sample bytes of
+//! the usual lengths until one is accepted. Types that need a self-describing format can't be
+//! traced, and neither can recursive enums whose first variant contains the enum itself.
+//! Variants are listed in the order of their tags.
+use std::collections::{BTreeMap, BTreeSet};
+use std::error;
+use std::fmt;
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+
+/// Lengths of the sample bytes, tried in this order: empty, hashes and raw keys, tagged keys,
+/// raw signatures and tagged signatures.
+const SAMPLE_BYTES_LENGTHS: &[usize] = &[0, 32, 33, 64, 65];
+
+/// Format of a value.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum Format {
+    /// Not traced yet.
+    Unknown,
+    /// Struct, tuple struct or enum of the given name, see `Registry`.
+    TypeName(String),
+    Unit,
+    Bool,
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+    Char,
+    Str,
+    Bytes,
+    Option(Box<Format>),
+    Seq(Box<Format>),
+    Map { key: Box<Format>, value: Box<Format> },
+    Tuple(Vec<Format>),
+}
+
+/// Named field of a struct or variant.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Named<T> {
+    pub name: String,
+    pub value: T,
+}
+
+/// Format of the fields of an enum variant.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum VariantFormat {
+    Unit,
+    NewType(Format),
+    Tuple(Vec<Format>),
+    Struct(Vec<Named<Format>>),
+}
+
+/// Format of a named type.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum ContainerFormat {
+    UnitStruct,
+    NewTypeStruct(Format),
+    TupleStruct(Vec<Format>),
+    Struct(Vec<Named<Format>>),
+    Enum(Vec<Named<VariantFormat>>),
+}
+
+/// Formats of the named types, by name.
+pub type Registry = BTreeMap<String, ContainerFormat>;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    /// Error reported by the type being traced, e.g. for bytes none of the samples are valid for.
+    Message(String),
+    /// Type needs a self-describing format.
+    NotSelfDescribing,
+    /// Some variants of the enum were never reached.
+    Incomplete(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Message(e) => write!(f, "{}", e),
+            Error::NotSelfDescribing => write!(f, "Type needs a self-describing format"),
+            Error::Incomplete(name) => write!(f, "Not every variant of {} was reached", name),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Traces the types into a registry.
+#[derive(Default)]
+pub struct Tracer {
+    registry: Registry,
+    /// Variants of the enums, `None` until reached.
+    variants: BTreeMap<&'static str, Vec<Option<Named<VariantFormat>>>>,
+    /// Containers being traced, to end the recursion through the enums.
+    stack: Vec<&'static str>,
+    /// Position in the value being traced, made of the container and field names.
+    path: Vec<String>,
+    /// Index in `SAMPLE_BYTES_LENGTHS` of the bytes at each path that were rejected before.
+    sample_bytes: BTreeMap<String, usize>,
+    /// Path of the last bytes deserialized, which are the likely cause of an error.
+    last_bytes: Option<String>,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Tracer::default()
+    }
+
+    /// Traces the type and the types it contains, and returns its format.
+    pub fn trace<T: DeserializeOwned>(&mut self) -> Result<Format> {
+        loop {
+            let reached = self.reached_variants();
+            self.path.clear();
+            self.last_bytes = None;
+            let mut format = Format::Unknown;
+            match T::deserialize(Deserializer { tracer: self, format: &mut format }) {
+                Ok(_) => {
+                    match self.incomplete_enum() {
+                        None => return Ok(format),
+                        // Every run reaches a new variant until all of them are.
+                        Some(name) if self.reached_variants() == reached => {
+                            return Err(Error::Incomplete(name.to_string()));
+                        }
+                        Some(_) => {}
+                    }
+                }
+                Err(e) => self.next_sample_bytes().ok_or(e)?,
+            }
+        }
+    }
+
+    /// Returns the formats of the traced types.
+    pub fn registry(self) -> Result<Registry> {
+        if let Some(name) = self.incomplete_enum() {
+            return Err(Error::Incomplete(name.to_string()));
+        }
+        Ok(self.registry)
+    }
+
+    fn reached_variants(&self) -> usize {
+        self.variants.values().flatten().filter(|v| v.is_some()).count()
+    }
+
+    fn incomplete_enum(&self) -> Option<&'static str> {
+        self.variants
+            .iter()
+            .find(|(_, variants)| variants.iter().any(Option::is_none))
+            .map(|(name, _)| *name)
+    }
+
+    /// Moves to the next sample of the last bytes, if the samples for them aren't exhausted.
+    fn next_sample_bytes(&mut self) -> Option<()> {
+        let path = self.last_bytes.take()?;
+        let index = self.sample_bytes.entry(path).or_insert(0);
+        *index += 1;
+        if *index < SAMPLE_BYTES_LENGTHS.len() {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn sample_bytes(&mut self) -> Vec<u8> {
+        let path = self.path.join(".");
+        let index = self.sample_bytes.get(&path).cloned().unwrap_or(0);
+        self.last_bytes = Some(path);
+        vec![0; SAMPLE_BYTES_LENGTHS[index]]
+    }
+
+    /// Whether the format contains an enum with variants that weren't reached.
+    fn reaches_incomplete(&self, format: &Format, seen: &mut BTreeSet<String>) -> bool {
+        match format {
+            Format::TypeName(name) => {
+                if !seen.insert(name.clone()) {
+                    return false;
+                }
+                if let Some(variants) = self.variants.get(name.as_str()) {
+                    if variants.iter().any(Option::is_none) {
+                        return true;
+                    }
+                }
+                match self.registry.get(name) {
+                    Some(ContainerFormat::UnitStruct) | None => false,
+                    Some(ContainerFormat::NewTypeStruct(format)) => {
+                        self.reaches_incomplete(format, seen)
+                    }
+                    Some(ContainerFormat::TupleStruct(formats)) => {
+                        formats.iter().any(|format| self.reaches_incomplete(format, seen))
+                    }
+                    Some(ContainerFormat::Struct(fields)) => {
+                        fields.iter().any(|field| self.reaches_incomplete(&field.value, seen))
+                    }
+                    Some(ContainerFormat::Enum(variants)) => variants
+                        .iter()
+                        .any(|variant| self.variant_reaches_incomplete(&variant.value, seen)),
+                }
+            }
+            Format::Option(format) | Format::Seq(format) => self.reaches_incomplete(format, seen),
+            Format::Map { key, value } => {
+                self.reaches_incomplete(key, seen) || self.reaches_incomplete(value, seen)
+            }
+            Format::Tuple(formats) => {
+                formats.iter().any(|format| self.reaches_incomplete(format, seen))
+            }
+            _ => false,
+        }
+    }
+
+    fn variant_reaches_incomplete(
+        &self,
+        format: &VariantFormat,
+        seen: &mut BTreeSet<String>,
+    ) -> bool {
+        match format {
+            VariantFormat::Unit => false,
+            VariantFormat::NewType(format) => self.reaches_incomplete(format, seen),
+            VariantFormat::Tuple(formats) => {
+                formats.iter().any(|format| self.reaches_incomplete(format, seen))
+            }
+            VariantFormat::Struct(fields) => {
+                fields.iter().any(|field| self.reaches_incomplete(&field.value, seen))
+            }
+        }
+    }
+
+    /// Picks the variant of the enum to trace: one that wasn't reached yet, else one that leads
+    /// to an enum with such variants, else the first one, which also ends the recursion.
+    fn pick_variant(&mut self, name: &'static str, names: &'static [&'static str]) -> usize {
+        let variants = self.variants.entry(name).or_insert_with(|| vec![None; names.len()]);
+        if self.stack.contains(&name) {
+            return 0;
+        }
+        if let Some(index) = variants.iter().position(Option::is_none) {
+            return index;
+        }
+        let variants = &self.variants[name];
+        variants
+            .iter()
+            .position(|variant| {
+                let variant = variant.as_ref().expect("variants are reached");
+                self.variant_reaches_incomplete(&variant.value, &mut BTreeSet::new())
+            })
+            .unwrap_or(0)
+    }
+}
+
+/// Deserializes a sample value and records its format.
+struct Deserializer<'a> {
+    tracer: &'a mut Tracer,
+    format: &'a mut Format,
+}
+
+impl<'a> Deserializer<'a> {
+    fn record(self, name: &'static str, container: ContainerFormat) {
+        self.tracer.registry.insert(name.to_string(), container);
+        *self.format = Format::TypeName(name.to_string());
+    }
+}
+
+/// Visits the fields of a container, or the items of a sequence, recording their formats.
+fn visit_fields<'de, V: Visitor<'de>>(
+    tracer: &mut Tracer,
+    formats: &mut [Format],
+    names: Option<&'static [&'static str]>,
+    visitor: V,
+) -> Result<V::Value> {
+    visitor.visit_seq(Fields { tracer, formats, names, index: 0 })
+}
+
+/// Traces the content of the named container, which ends the recursion if it is an enum.
+fn visit_container<T, F>(tracer: &mut Tracer, name: &'static str, visit: F) -> Result<T>
+where
+    F: FnOnce(&mut Tracer) -> Result<T>,
+{
+    tracer.stack.push(name);
+    tracer.path.push(name.to_string());
+    let value = visit(&mut *tracer);
+    tracer.path.pop();
+    tracer.stack.pop();
+    value
+}
+
+fn named(names: &'static [&'static str], formats: Vec<Format>) -> Vec<Named<Format>> {
+    names
+        .iter()
+        .zip(formats)
+        .map(|(name, value)| Named { name: name.to_string(), value })
+        .collect()
+}
+
+macro_rules! trace_primitive {
+    ($($deserialize:ident => $format:ident, $visit:ident($($sample:expr)?),)*) => {
+        $(fn $deserialize<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+            *self.format = Format::$format;
+            visitor.$visit($($sample)?)
+        })*
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Deserializer<'a> {
+    type Error = Error;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    trace_primitive! {
+        deserialize_bool => Bool, visit_bool(false),
+        deserialize_i8 => I8, visit_i8(0),
+        deserialize_i16 => I16, visit_i16(0),
+        deserialize_i32 => I32, visit_i32(0),
+        deserialize_i64 => I64, visit_i64(0),
+        deserialize_u8 => U8, visit_u8(0),
+        deserialize_u16 => U16, visit_u16(0),
+        deserialize_u32 => U32, visit_u32(0),
+        deserialize_u64 => U64, visit_u64(0),
+        deserialize_f32 => F32, visit_f32(0.0),
+        deserialize_f64 => F64, visit_f64(0.0),
+        deserialize_char => Char, visit_char('\0'),
+        deserialize_str => Str, visit_str(""),
+        deserialize_string => Str, visit_string(String::new()),
+        deserialize_unit => Unit, visit_unit(),
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::NotSelfDescribing)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        *self.format = Format::Bytes;
+        visitor.visit_bytes(&self.tracer.sample_bytes())
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        *self.format = Format::Bytes;
+        visitor.visit_byte_buf(self.tracer.sample_bytes())
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut format = Format::Unknown;
+        let value = visitor.visit_some(Deserializer { tracer: self.tracer, format: &mut format });
+        *self.format = Format::Option(Box::new(format));
+        value
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        let value = visitor.visit_unit()?;
+        self.record(name, ContainerFormat::UnitStruct);
+        Ok(value)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        let mut format = Format::Unknown;
+        let value = visit_container(self.tracer, name, |tracer| {
+            visitor.visit_newtype_struct(Deserializer { tracer, format: &mut format })
+        })?;
+        self.record(name, ContainerFormat::NewTypeStruct(format));
+        Ok(value)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut formats = vec![Format::Unknown];
+        let value = visit_fields(self.tracer, &mut formats, None, visitor);
+        *self.format = Format::Seq(Box::new(formats.remove(0)));
+        value
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        let mut formats = vec![Format::Unknown; len];
+        let value = visit_fields(self.tracer, &mut formats, None, visitor);
+        *self.format = Format::Tuple(formats);
+        value
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        let mut formats = vec![Format::Unknown; len];
+        let value = visit_container(self.tracer, name, |tracer| {
+            visit_fields(tracer, &mut formats, None, visitor)
+        })?;
+        self.record(name, ContainerFormat::TupleStruct(formats));
+        Ok(value)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut key = Format::Unknown;
+        let mut value = Format::Unknown;
+        let result = visitor.visit_map(Entry {
+            tracer: &mut *self.tracer,
+            key: &mut key,
+            value: &mut value,
+            done: false,
+        });
+        *self.format = Format::Map { key: Box::new(key), value: Box::new(value) };
+        result
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        let mut formats = vec![Format::Unknown; fields.len()];
+        let value = visit_container(self.tracer, name, |tracer| {
+            visit_fields(tracer, &mut formats, Some(fields), visitor)
+        })?;
+        self.record(name, ContainerFormat::Struct(named(fields, formats)));
+        Ok(value)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        let index = self.tracer.pick_variant(name, variants);
+        let mut format = VariantFormat::Unit;
+        let value = visit_container(self.tracer, name, |tracer| {
+            tracer.path.push(variants[index].to_string());
+            let name = variants[index];
+            let variant = Variant { tracer: &mut *tracer, name, format: &mut format };
+            let value = visitor.visit_enum(variant);
+            tracer.path.pop();
+            value
+        })?;
+        let traced = self.tracer.variants.get_mut(name).expect("variants are picked");
+        traced[index] = Some(Named { name: variants[index].to_string(), value: format });
+        let container = traced
+            .iter()
+            .zip(variants)
+            .map(|(variant, name)| match variant {
+                Some(variant) => variant.clone(),
+                None => Named { name: name.to_string(), value: VariantFormat::Unit },
+            })
+            .collect();
+        self.record(name, ContainerFormat::Enum(container));
+        Ok(value)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::NotSelfDescribing)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::NotSelfDescribing)
+    }
+}
+
+/// Fields of a container or items of a sequence, as many as there are formats to record.
+struct Fields<'a> {
+    tracer: &'a mut Tracer,
+    formats: &'a mut [Format],
+    names: Option<&'static [&'static str]>,
+    index: usize,
+}
+
+impl<'de, 'a> de::SeqAccess<'de> for Fields<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.index == self.formats.len() {
+            return Ok(None);
+        }
+        let name = match self.names {
+            Some(names) => names[self.index].to_string(),
+            None => self.index.to_string(),
+        };
+        self.tracer.path.push(name);
+        let format = &mut self.formats[self.index];
+        let value = seed.deserialize(Deserializer { tracer: &mut *self.tracer, format });
+        self.tracer.path.pop();
+        self.index += 1;
+        value.map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.formats.len() - self.index)
+    }
+}
+
+/// Single entry of a map.
+struct Entry<'a> {
+    tracer: &'a mut Tracer,
+    key: &'a mut Format,
+    value: &'a mut Format,
+    done: bool,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for Entry<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if self.done {
+            return Ok(None);
+        }
+        self.tracer.path.push("key".to_string());
+        let key =
+            seed.deserialize(Deserializer { tracer: &mut *self.tracer, format: &mut *self.key });
+        self.tracer.path.pop();
+        key.map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        self.done = true;
+        self.tracer.path.push("value".to_string());
+        let value =
+            seed.deserialize(Deserializer { tracer: &mut *self.tracer, format: &mut *self.value });
+        self.tracer.path.pop();
+        value
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(if self.done { 0 } else { 1 })
+    }
+}
+
+/// Variant picked for the enum, given to the visitor by its name.
+struct Variant<'a> {
+    tracer: &'a mut Tracer,
+    name: &'static str,
+    format: &'a mut VariantFormat,
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for Variant<'a> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let name: de::value::StrDeserializer<Error> = self.name.into_deserializer();
+        Ok((seed.deserialize(name)?, self))
+    }
+}
+
+impl<'de, 'a> de::VariantAccess<'de> for Variant<'a> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        *self.format = VariantFormat::Unit;
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        let mut format = Format::Unknown;
+        let value = seed.deserialize(Deserializer { tracer: self.tracer, format: &mut format })?;
+        *self.format = VariantFormat::NewType(format);
+        Ok(value)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        let mut formats = vec![Format::Unknown; len];
+        let value = visit_fields(self.tracer, &mut formats, None, visitor)?;
+        *self.format = VariantFormat::Tuple(formats);
+        Ok(value)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        let mut formats = vec![Format::Unknown; fields.len()];
+        let value = visit_fields(self.tracer, &mut formats, Some(fields), visitor)?;
+        *self.format = VariantFormat::Struct(named(fields, formats));
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Transaction {
+        _nonce: u64,
+        _body: Body,
+    }
+
+    #[derive(Deserialize)]
+    enum Body {
+        Send(u64),
+        Delegate(Box<Transaction>),
+        Call { _method: Option<String>, _args: Vec<u8> },
+        Cancel,
+    }
+
+    /// Validates its bytes, like a key.
+    struct Key;
+
+    impl<'de> serde::Deserialize<'de> for Key {
+        fn deserialize<D>(deserializer: D) -> std::result::Result<Key, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            struct KeyVisitor;
+
+            impl<'de> Visitor<'de> for KeyVisitor {
+                type Value = Key;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    write!(f, "32 bytes")
+                }
+
+                fn visit_bytes<E: de::Error>(self, v: &[u8]) -> std::result::Result<Key, E> {
+                    if v.len() == 32 {
+                        Ok(Key)
+                    } else {
+                        Err(E::invalid_length(v.len(), &self))
+                    }
+                }
+            }
+
+            deserializer.deserialize_bytes(KeyVisitor)
+        }
+    }
+
+    fn field<T>(name: &str, value: T) -> Named<T> {
+        Named { name: name.to_string(), value }
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut tracer = Tracer::new();
+        let transaction = tracer.trace::<Transaction>().unwrap();
+        assert_eq!(transaction, Format::TypeName("Transaction".to_string()));
+        // Retried until the sample bytes have the length of the key.
+        assert_eq!(tracer.trace::<Key>(), Ok(Format::Bytes));
+        assert_eq!(tracer.trace::<(Key, Option<Key>)>().map(|_| ()), Ok(()));
+        let registry = tracer.registry().unwrap();
+        assert_eq!(
+            registry["Transaction"],
+            ContainerFormat::Struct(vec![
+                field("_nonce", Format::U64),
+                field("_body", Format::TypeName("Body".to_string())),
+            ])
+        );
+        assert_eq!(
+            registry["Body"],
+            ContainerFormat::Enum(vec![
+                field("Send", VariantFormat::NewType(Format::U64)),
+                field(
+                    "Delegate",
+                    VariantFormat::NewType(Format::TypeName("Transaction".to_string())),
+                ),
+                field(
+                    "Call",
+                    VariantFormat::Struct(vec![
+                        field("_method", Format::Option(Box::new(Format::Str))),
+                        field("_args", Format::Seq(Box::new(Format::U8))),
+                    ]),
+                ),
+                field("Cancel", VariantFormat::Unit),
+            ])
+        );
+    }
+}
@@ -1,7 +1,12 @@
+use std::error;
+use std::fmt;
 use std::io;
 
 use serde::{de::DeserializeOwned, Serialize};
 
+use crate::borsh;
+use crate::limited::{self, DecodeLimits, LimitError};
+
 pub type EncodeResult = Result<Vec<u8>, io::Error>;
 pub type DecodeResult<T> = Result<T, io::Error>;
 
@@ -15,6 +20,37 @@ pub trait Decode: Sized {
     fn decode(data: &[u8]) -> DecodeResult<Self>;
 }
 
+/// Error of decoding untrusted input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Input is over the limits it is decoded with.
+    Limit(LimitError),
+    /// Input is not an encoding of the type.
+    Invalid(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Limit(e) => write!(f, "{}", e),
+            DecodeError::Invalid(e) => write!(f, "Failed to deserialize: {}", e),
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+impl From<LimitError> for DecodeError {
+    fn from(e: LimitError) -> Self {
+        DecodeError::Limit(e)
+    }
+}
+
+// decode from untrusted byte array, such as a message of a peer, within the limits
+pub trait DecodeLimited: Sized {
+    fn decode_limited(data: &[u8], limits: &DecodeLimits) -> Result<Self, DecodeError>;
+}
+
 impl<T: Serialize> Encode for T {
     fn encode(&self) -> EncodeResult {
         bincode::serialize(&self)
@@ -31,3 +67,66 @@ impl<T> Decode for T
             .map_err(|_| io::Error::new(io::ErrorKind::Other, "Failed to deserialize"))
     }
 }
+
+impl<T> DecodeLimited for T
+    where
+        T: DeserializeOwned,
+{
+    fn decode_limited(data: &[u8], limits: &DecodeLimits) -> Result<Self, DecodeError> {
+        limited::decode(limits, data.len(), |seed| {
+            bincode::config()
+                .deserialize_seed(seed, data)
+                .map_err(|e| DecodeError::Invalid(e.to_string()))
+        })
+    }
+}
+
+/// Prefix of the state values encoded with borsh, followed by the version of the encoding.
+const STATE_VALUE_PREFIX: [u8; 2] = [0xb0, 0x01];
+
+/// Encodes the value to keep in the state, with borsh.
+pub fn to_state_value<T: Serialize>(value: &T) -> EncodeResult {
+    let mut data = STATE_VALUE_PREFIX.to_vec();
+    data.extend(borsh::to_vec(value).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?);
+    Ok(data)
+}
+
+/// Decodes the value kept in the state. Values written with bincode, before the state was encoded
+/// with borsh, are still read, and are written back with borsh the next time they change.
+pub fn from_state_value<T: DeserializeOwned>(data: &[u8]) -> DecodeResult<T> {
+    if data.starts_with(&STATE_VALUE_PREFIX) {
+        if let Ok(value) = borsh::from_slice(&data[STATE_VALUE_PREFIX.len()..]) {
+            return Ok(value);
+        }
+    }
+    Decode::decode(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Value {
+        amount: u64,
+        keys: HashMap<String, Vec<u8>>,
+    }
+
+    #[test]
+    fn test_state_value() {
+        let mut keys = HashMap::new();
+        keys.insert("a".to_string(), vec![1, 2]);
+        keys.insert("b".to_string(), vec![]);
+        let value = Value { amount: 10, keys };
+        let data = to_state_value(&value).unwrap();
+        assert_eq!(data[..2], STATE_VALUE_PREFIX);
+        assert_eq!(from_state_value::<Value>(&data).unwrap(), value);
+        // Values written with bincode are migrated.
+        let legacy = value.encode().unwrap();
+        assert_ne!(legacy, data);
+        assert_eq!(from_state_value::<Value>(&legacy).unwrap(), value);
+        assert!(from_state_value::<Value>(&data[..data.len() - 1]).is_err());
+    }
+}
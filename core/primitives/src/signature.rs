@@ -2,98 +2,465 @@ extern crate exonum_sodiumoxide as sodiumoxide;
 
 use bs58;
 use crate::hash;
+use dalek_sha2::Sha512;
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
+use crate::signature::sodiumoxide::crypto::sign::ed25519;
 pub use crate::signature::sodiumoxide::crypto::sign::ed25519::Seed;
 
-#[derive(Copy, Clone, Eq, PartialOrd, Ord, PartialEq, Serialize, Deserialize)]
-pub struct PublicKey(pub sodiumoxide::crypto::sign::ed25519::PublicKey);
+const SECP256K1_PUBLIC_KEY_SIZE: usize = 33;
+const SECP256K1_UNCOMPRESSED_PUBLIC_KEY_SIZE: usize = 65;
+const SECP256K1_SECRET_KEY_SIZE: usize = 32;
+const SECP256K1_SIGNATURE_SIZE: usize = 65;
 
-#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
-pub struct SecretKey(pub sodiumoxide::crypto::sign::ed25519::SecretKey);
+lazy_static! {
+    static ref SECP256K1: secp256k1::Secp256k1 = secp256k1::Secp256k1::new();
+}
+
+/// Type of the account keys. Secp256k1 keys are the keys of Ethereum, so that its users can reuse
+/// their keys.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum KeyType {
+    ED25519,
+    SECP256K1,
+}
+
+impl KeyType {
+    /// Byte in front of the keys and signatures of the type in their binary encoding.
+    pub fn tag(self) -> u8 {
+        match self {
+            KeyType::ED25519 => 0,
+            KeyType::SECP256K1 => 1,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Result<KeyType, String> {
+        match tag {
+            0 => Ok(KeyType::ED25519),
+            1 => Ok(KeyType::SECP256K1),
+            _ => Err(format!("unknown key type {}", tag)),
+        }
+    }
+
+    /// Name in front of the keys and signatures of the type in their text encoding.
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyType::ED25519 => "ed25519",
+            KeyType::SECP256K1 => "secp256k1",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<KeyType, String> {
+        match name {
+            "ed25519" => Ok(KeyType::ED25519),
+            "secp256k1" => Ok(KeyType::SECP256K1),
+            _ => Err(format!("unknown key type {}", name)),
+        }
+    }
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
 
-#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Hash)]
-pub struct Signature(pub sodiumoxide::crypto::sign::ed25519::Signature);
+/// Splits the self-describing binary encoding into the key type and the bytes.
+fn split_tag(bytes: &[u8]) -> Result<(KeyType, &[u8]), String> {
+    match bytes.split_first() {
+        Some((tag, bytes)) => Ok((KeyType::from_tag(*tag)?, bytes)),
+        None => Err("empty bytes".to_string()),
+    }
+}
+
+/// Splits the self-describing text encoding `<key type>:<base58>` into the key type and the
+/// bytes. Keys and signatures without the key type, written before they had it, are ed25519 or
+/// told apart by their size, which is returned as no key type.
+fn split_name(s: &str) -> Result<(Option<KeyType>, Vec<u8>), String> {
+    let (key_type, data) = match s.find(':') {
+        Some(i) => (Some(KeyType::from_name(&s[..i])?), &s[i + 1..]),
+        None => (None, s),
+    };
+    let bytes = bs58::decode(data).into_vec().map_err(|e| format!("invalid base58: {}", e))?;
+    Ok((key_type, bytes))
+}
+
+/// Compressed secp256k1 public key.
+#[derive(Copy, Clone)]
+pub struct Secp256k1PublicKey([u8; SECP256K1_PUBLIC_KEY_SIZE]);
+
+/// Recoverable secp256k1 signature: `r`, `s` and the recovery id, like in Ethereum.
+#[derive(Copy, Clone)]
+pub struct Secp256k1Signature([u8; SECP256K1_SIGNATURE_SIZE]);
+
+#[derive(Copy, Clone, Eq, PartialOrd, Ord, PartialEq)]
+pub enum PublicKey {
+    ED25519(ed25519::PublicKey),
+    SECP256K1(Secp256k1PublicKey),
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum SecretKey {
+    ED25519(ed25519::SecretKey),
+    SECP256K1([u8; SECP256K1_SECRET_KEY_SIZE]),
+}
+
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub enum Signature {
+    ED25519(ed25519::Signature),
+    SECP256K1(Secp256k1Signature),
+}
+
+/// Secp256k1 signs the SHA-256 hash of the data, which is what the messages are.
+fn secp256k1_message(data: &[u8]) -> secp256k1::Message {
+    secp256k1::Message::from_slice(hash::hash(data).as_ref()).expect("Hashes are 32 bytes")
+}
+
+fn secp256k1_secret_key(bytes: &[u8]) -> Result<secp256k1::key::SecretKey, String> {
+    secp256k1::key::SecretKey::from_slice(&SECP256K1, bytes)
+        .map_err(|e| format!("invalid secp256k1 secret key: {:?}", e))
+}
+
+fn verify_secp256k1(
+    data: &[u8],
+    signature: &Secp256k1Signature,
+    public_key: &Secp256k1PublicKey,
+) -> bool {
+    let signature = secp256k1::RecoveryId::from_i32(i32::from(signature.0[64])).and_then(|id| {
+        secp256k1::RecoverableSignature::from_compact(&SECP256K1, &signature.0[..64], id)
+    });
+    let public_key = secp256k1::key::PublicKey::from_slice(&SECP256K1, &public_key.0);
+    match (signature, public_key) {
+        (Ok(signature), Ok(public_key)) => SECP256K1
+            .verify(&secp256k1_message(data), &signature.to_standard(&SECP256K1), &public_key)
+            .is_ok(),
+        _ => false,
+    }
+}
 
 pub fn sign(data: &[u8], secret_key: &SecretKey) -> Signature {
-    Signature(sodiumoxide::crypto::sign::ed25519::sign_detached(data, &secret_key.0))
+    match secret_key {
+        SecretKey::ED25519(secret_key) => {
+            Signature::ED25519(ed25519::sign_detached(data, secret_key))
+        }
+        SecretKey::SECP256K1(secret_key) => {
+            let secret_key =
+                secp256k1_secret_key(secret_key).expect("Secret keys are checked when decoded");
+            let signature = SECP256K1
+                .sign_recoverable(&secp256k1_message(data), &secret_key)
+                .expect("Signing with a valid secret key succeeds");
+            let (recovery_id, rs) = signature.serialize_compact(&SECP256K1);
+            let mut array = [0; SECP256K1_SIGNATURE_SIZE];
+            array[..64].copy_from_slice(&rs);
+            array[64] = recovery_id.to_i32() as u8;
+            Signature::SECP256K1(Secp256k1Signature(array))
+        }
+    }
 }
 
+/// Signatures only verify against the public keys of their type.
 pub fn verify(data: &[u8], signature: &Signature, public_key: &PublicKey) -> bool {
-    sodiumoxide::crypto::sign::ed25519::verify_detached(&signature.0, data, &public_key.0)
+    match (signature, public_key) {
+        (Signature::ED25519(signature), PublicKey::ED25519(public_key)) => {
+            ed25519::verify_detached(signature, data, public_key)
+        }
+        (Signature::SECP256K1(signature), PublicKey::SECP256K1(public_key)) => {
+            verify_secp256k1(data, signature, public_key)
+        }
+        _ => false,
+    }
+}
+
+fn verify_ed25519_batch(items: &[(&[u8], &ed25519::Signature, &ed25519::PublicKey)]) -> bool {
+    let mut signatures = Vec::with_capacity(items.len());
+    let mut public_keys = Vec::with_capacity(items.len());
+    for (_, signature, public_key) in items {
+        match (
+            ed25519_dalek::Signature::from_bytes(&signature.0),
+            ed25519_dalek::PublicKey::from_bytes(&public_key.0),
+        ) {
+            (Ok(signature), Ok(public_key)) => {
+                signatures.push(signature);
+                public_keys.push(public_key);
+            }
+            _ => return false,
+        }
+    }
+    let messages: Vec<&[u8]> = items.iter().map(|(data, _, _)| *data).collect();
+    ed25519_dalek::verify_batch::<Sha512>(&messages, &signatures, &public_keys).is_ok()
+}
+
+/// Verifies the signatures of the data by the public keys and returns whether each one is valid.
+/// The ed25519 signatures are verified in a single batch, which costs much less than verifying
+/// them one at a time. If the batch fails, they are verified one at a time to find the invalid
+/// ones.
+pub fn verify_batch(items: &[(&[u8], &Signature, &PublicKey)]) -> Vec<bool> {
+    let ed25519_items: Vec<_> = items
+        .iter()
+        .filter_map(|(data, signature, public_key)| match (signature, public_key) {
+            (Signature::ED25519(signature), PublicKey::ED25519(public_key)) => {
+                Some((*data, signature, public_key))
+            }
+            _ => None,
+        })
+        .collect();
+    let batch_valid = !ed25519_items.is_empty() && verify_ed25519_batch(&ed25519_items);
+    items
+        .iter()
+        .map(|(data, signature, public_key)| match (signature, public_key) {
+            (Signature::ED25519(_), PublicKey::ED25519(_)) if batch_valid => true,
+            _ => verify(data, signature, public_key),
+        })
+        .collect()
 }
 
 pub fn get_key_pair() -> (PublicKey, SecretKey) {
-    let (public_key, secret_key) = sodiumoxide::crypto::sign::ed25519::gen_keypair();
-    (PublicKey(public_key), SecretKey(secret_key))
+    let (public_key, secret_key) = ed25519::gen_keypair();
+    (PublicKey::ED25519(public_key), SecretKey::ED25519(secret_key))
+}
+
+pub fn get_secp256k1_key_pair() -> (PublicKey, SecretKey) {
+    use rand::Rng;
+    let mut rng = rand::OsRng::new().expect("Failed to access the OS randomness");
+    loop {
+        // Almost all 32 bytes are valid secret keys.
+        let bytes: [u8; SECP256K1_SECRET_KEY_SIZE] = rng.gen();
+        if let Ok(secret_key) = secp256k1_secret_key(&bytes) {
+            let public_key = secp256k1::key::PublicKey::from_secret_key(&SECP256K1, &secret_key)
+                .expect("Valid secret keys have a public key");
+            return (PublicKey::from_secp256k1(&public_key), SecretKey::SECP256K1(bytes));
+        }
+    }
 }
 
 pub fn verify_signature(signature: &Signature, hash: &hash::CryptoHash, pubkey: &PublicKey) -> bool {
-    sodiumoxide::crypto::sign::ed25519::verify_detached(&signature.0, hash.as_ref(), &pubkey.0)
+    verify(hash.as_ref(), signature, pubkey)
 }
 
-const SIG: [u8; sodiumoxide::crypto::sign::ed25519::SIGNATUREBYTES] =
-    [0u8; sodiumoxide::crypto::sign::ed25519::SIGNATUREBYTES];
+const SIG: [u8; ed25519::SIGNATUREBYTES] = [0u8; ed25519::SIGNATUREBYTES];
 
-pub const DEFAULT_SIGNATURE: Signature = Signature(sodiumoxide::crypto::sign::ed25519::Signature(SIG));
+pub const DEFAULT_SIGNATURE: Signature = Signature::ED25519(ed25519::Signature(SIG));
 
 impl PublicKey {
+    /// Decodes the bytes of the key of the type. Secp256k1 keys are compressed or not, and
+    /// uncompressed keys may come without the prefix, like the keys of Ethereum.
+    pub fn from_raw(key_type: KeyType, bytes: &[u8]) -> Result<PublicKey, String> {
+        match (key_type, bytes.len()) {
+            (KeyType::ED25519, ed25519::PUBLICKEYBYTES) => {
+                let mut array = [0; ed25519::PUBLICKEYBYTES];
+                array.copy_from_slice(bytes);
+                Ok(PublicKey::ED25519(ed25519::PublicKey(array)))
+            }
+            (KeyType::SECP256K1, SECP256K1_PUBLIC_KEY_SIZE)
+            | (KeyType::SECP256K1, SECP256K1_UNCOMPRESSED_PUBLIC_KEY_SIZE)
+            | (KeyType::SECP256K1, 64) => {
+                let mut uncompressed = vec![];
+                let bytes = if bytes.len() == 64 {
+                    uncompressed.push(4);
+                    uncompressed.extend_from_slice(bytes);
+                    &uncompressed[..]
+                } else {
+                    bytes
+                };
+                let public_key = secp256k1::key::PublicKey::from_slice(&SECP256K1, bytes)
+                    .map_err(|e| format!("invalid secp256k1 public key: {:?}", e))?;
+                Ok(PublicKey::from_secp256k1(&public_key))
+            }
+            _ => Err(format!("bytes not the size of a {} public key", key_type)),
+        }
+    }
+
+    /// Decodes the raw bytes of the key, which don't say its type, by their size: 32 bytes of
+    /// ed25519 or a secp256k1 key. The keys in transactions and in the state say their type, see
+    /// `from_bytes`.
     pub fn new(bytes: &[u8]) -> Result<PublicKey, String> {
-        if bytes.len() != sodiumoxide::crypto::sign::ed25519::PUBLICKEYBYTES {
-            return Err("bytes not the size of a public key".to_string())
+        let key_type = if bytes.len() == ed25519::PUBLICKEYBYTES {
+            KeyType::ED25519
+        } else {
+            KeyType::SECP256K1
+        };
+        PublicKey::from_raw(key_type, bytes)
+    }
+
+    /// Self-describing binary encoding of the key: the tag of its type, then its bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.key_type().tag()];
+        bytes.extend_from_slice(self.as_ref());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<PublicKey, String> {
+        let (key_type, bytes) = split_tag(bytes)?;
+        PublicKey::from_raw(key_type, bytes)
+    }
+
+    /// Decodes the key from its text encoding, with or without the key type.
+    pub fn try_from_str(s: &str) -> Result<PublicKey, String> {
+        match split_name(s)? {
+            (Some(key_type), bytes) => PublicKey::from_raw(key_type, &bytes),
+            (None, bytes) => PublicKey::new(&bytes),
         }
-        let mut array = [0; sodiumoxide::crypto::sign::ed25519::PUBLICKEYBYTES];
-        array.copy_from_slice(bytes);
-        let public_key = sodiumoxide::crypto::sign::ed25519::PublicKey(array);
-        Ok(PublicKey(public_key))
     }
 
     pub fn from(s: &str) -> PublicKey {
-        let mut array = [0; sodiumoxide::crypto::sign::ed25519::PUBLICKEYBYTES];
-        let bytes = bs58::decode(s).into_vec().expect("Failed to convert public key from base58");
-        assert_eq!(bytes.len(), array.len(), "decoded {} is not long enough for public key", s);
-        let bytes_arr = &bytes[..array.len()];
-        array.copy_from_slice(bytes_arr);
-        let public_key = sodiumoxide::crypto::sign::ed25519::PublicKey(array);
-        PublicKey(public_key)
+        PublicKey::try_from_str(s).unwrap_or_else(|e| panic!("{} is not a public key: {}", s, e))
+    }
+
+    fn from_secp256k1(public_key: &secp256k1::key::PublicKey) -> PublicKey {
+        let mut array = [0; SECP256K1_PUBLIC_KEY_SIZE];
+        array.copy_from_slice(&public_key.serialize_vec(&SECP256K1, true)[..]);
+        PublicKey::SECP256K1(Secp256k1PublicKey(array))
+    }
+
+    pub fn key_type(&self) -> KeyType {
+        match self {
+            PublicKey::ED25519(_) => KeyType::ED25519,
+            PublicKey::SECP256K1(_) => KeyType::SECP256K1,
+        }
     }
 }
 
 impl SecretKey {
+    /// Decodes the secret key by its size: 64 bytes of ed25519 or 32 bytes of secp256k1.
+    pub fn new(bytes: &[u8]) -> Result<SecretKey, String> {
+        match bytes.len() {
+            ed25519::SECRETKEYBYTES => {
+                let mut array = [0; ed25519::SECRETKEYBYTES];
+                array.copy_from_slice(bytes);
+                Ok(SecretKey::ED25519(ed25519::SecretKey(array)))
+            }
+            SECP256K1_SECRET_KEY_SIZE => {
+                secp256k1_secret_key(bytes)?;
+                let mut array = [0; SECP256K1_SECRET_KEY_SIZE];
+                array.copy_from_slice(bytes);
+                Ok(SecretKey::SECP256K1(array))
+            }
+            _ => Err("bytes not the size of a secret key".to_string()),
+        }
+    }
+
     pub fn from(s: &str) -> SecretKey {
-        let mut array = [0; sodiumoxide::crypto::sign::ed25519::SECRETKEYBYTES];
         let bytes = bs58::decode(s).into_vec().expect("Failed to convert secret key from base58");
-        assert_eq!(bytes.len(), array.len(), "decoded {} is not long enough for secret key", s);
-        let bytes_arr = &bytes[..array.len()];
-        array.copy_from_slice(bytes_arr);
-        let secret_key = sodiumoxide::crypto::sign::ed25519::SecretKey(array);
-        SecretKey(secret_key)
+        SecretKey::new(&bytes)
+            .unwrap_or_else(|e| panic!("decoded {} is not a secret key: {}", s, e))
     }
 }
 
 impl Signature {
+    /// Decodes the bytes of the signature of the type.
+    pub fn from_raw(key_type: KeyType, bytes: &[u8]) -> Result<Signature, String> {
+        match (key_type, bytes.len()) {
+            (KeyType::ED25519, ed25519::SIGNATUREBYTES) => {
+                let mut array = [0; ed25519::SIGNATUREBYTES];
+                array.copy_from_slice(bytes);
+                Ok(Signature::ED25519(ed25519::Signature(array)))
+            }
+            (KeyType::SECP256K1, SECP256K1_SIGNATURE_SIZE) => {
+                let mut array = [0; SECP256K1_SIGNATURE_SIZE];
+                array.copy_from_slice(bytes);
+                Ok(Signature::SECP256K1(Secp256k1Signature(array)))
+            }
+            _ => Err(format!("bytes not the size of a {} signature", key_type)),
+        }
+    }
+
+    /// Decodes the raw bytes of the signature by their size: 64 bytes of ed25519 or 65 bytes of
+    /// secp256k1. The signatures of transactions say their type, see `from_bytes`.
+    pub fn try_new(bytes: &[u8]) -> Result<Signature, String> {
+        let key_type = if bytes.len() == ed25519::SIGNATUREBYTES {
+            KeyType::ED25519
+        } else {
+            KeyType::SECP256K1
+        };
+        Signature::from_raw(key_type, bytes)
+    }
+
     pub fn new(bytes: &[u8]) -> Signature {
-        assert!(bytes.len() == sodiumoxide::crypto::sign::ed25519::SIGNATUREBYTES);
-        let mut array = [0; sodiumoxide::crypto::sign::ed25519::SIGNATUREBYTES];
-        array.copy_from_slice(bytes);
-        let signature = sodiumoxide::crypto::sign::ed25519::Signature(array);
-        Signature(signature)
+        Signature::try_new(bytes).expect("bytes not the size of a signature")
+    }
+
+    /// Self-describing binary encoding of the signature: the tag of its type, then its bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.key_type().tag()];
+        bytes.extend_from_slice(self.as_ref());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Signature, String> {
+        let (key_type, bytes) = split_tag(bytes)?;
+        Signature::from_raw(key_type, bytes)
+    }
+
+    /// Decodes the signature from its text encoding, with or without the key type.
+    pub fn try_from_str(s: &str) -> Result<Signature, String> {
+        match split_name(s)? {
+            (Some(key_type), bytes) => Signature::from_raw(key_type, &bytes),
+            (None, bytes) => Signature::try_new(&bytes),
+        }
     }
 
     pub fn from(s: &str) -> Signature {
-        let mut array = [0; sodiumoxide::crypto::sign::ed25519::SIGNATUREBYTES];
-        let bytes = bs58::decode(s).into_vec().expect("Failed to convert signature from base58");
-        assert_eq!(bytes.len(), array.len(), "decoded {} is not long enough for signature", s);
-        let bytes_arr = &bytes[..array.len()];
-        array.copy_from_slice(bytes_arr);
-        let signature = sodiumoxide::crypto::sign::ed25519::Signature(array);
-        Signature(signature)
+        Signature::try_from_str(s).unwrap_or_else(|e| panic!("{} is not a signature: {}", s, e))
+    }
+
+    pub fn key_type(&self) -> KeyType {
+        match self {
+            Signature::ED25519(_) => KeyType::ED25519,
+            Signature::SECP256K1(_) => KeyType::SECP256K1,
+        }
+    }
+}
+
+impl PartialEq for Secp256k1PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0[..] == other.0[..]
+    }
+}
+
+impl Eq for Secp256k1PublicKey {}
+
+impl PartialOrd for Secp256k1PublicKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Secp256k1PublicKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0[..].cmp(&other.0[..])
+    }
+}
+
+impl PartialEq for Secp256k1Signature {
+    fn eq(&self, other: &Self) -> bool {
+        self.0[..] == other.0[..]
+    }
+}
+
+impl Eq for Secp256k1Signature {}
+
+impl Hash for Secp256k1Signature {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0[..].hash(state)
+    }
+}
+
+impl std::convert::AsRef<[u8]> for PublicKey {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            PublicKey::ED25519(public_key) => &public_key.0[..],
+            PublicKey::SECP256K1(public_key) => &public_key.0[..],
+        }
     }
 }
 
 impl<'a> From<&'a PublicKey> for String {
     fn from(h: &'a PublicKey) -> Self {
-        bs58::encode(h.0).into_string()
+        format!("{}:{}", h.key_type(), bs58::encode(h).into_string())
     }
 }
 
@@ -111,7 +478,10 @@ impl fmt::Display for PublicKey {
 
 impl std::convert::AsRef<[u8]> for SecretKey {
     fn as_ref(&self) -> &[u8] {
-        &self.0[..]
+        match self {
+            SecretKey::ED25519(secret_key) => &secret_key.0[..],
+            SecretKey::SECP256K1(secret_key) => &secret_key[..],
+        }
     }
 }
 
@@ -123,7 +493,10 @@ impl<'a> From<&'a SecretKey> for String {
 
 impl std::convert::AsRef<[u8]> for Signature {
     fn as_ref(&self) -> &[u8] {
-        &self.0[..]
+        match self {
+            Signature::ED25519(signature) => &signature.0[..],
+            Signature::SECP256K1(signature) => &signature.0[..],
+        }
     }
 }
 
@@ -141,7 +514,7 @@ impl fmt::Display for SecretKey {
 
 impl<'a> From<&'a Signature> for String {
     fn from(h: &'a Signature) -> Self {
-        bs58::encode(h).into_string()
+        format!("{}:{}", h.key_type(), bs58::encode(h).into_string())
     }
 }
 
@@ -157,6 +530,83 @@ impl fmt::Display for Signature {
     }
 }
 
+/// Keys and signatures are serialized as their bytes, decoded by their size.
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("bytes")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(v)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = vec![];
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+impl Serialize for PublicKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.to_bytes())
+    }
+}
+
+/// The keys and signatures in the state are in the self-describing encoding. Those stored before
+/// were the raw bytes of ed25519, a size the self-describing encoding never has, so they are still
+/// read, and are written back in the new encoding with the accounts that have them.
+impl<'de> serde::Deserialize<'de> for PublicKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+        if bytes.len() == ed25519::PUBLICKEYBYTES {
+            PublicKey::from_raw(KeyType::ED25519, &bytes).map_err(de::Error::custom)
+        } else {
+            PublicKey::from_bytes(&bytes).map_err(de::Error::custom)
+        }
+    }
+}
+
+impl Serialize for SecretKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.as_ref())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for SecretKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+        SecretKey::new(&bytes).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Signature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.to_bytes())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Signature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+        if bytes.len() == ed25519::SIGNATUREBYTES {
+            Signature::from_raw(KeyType::ED25519, &bytes).map_err(de::Error::custom)
+        } else {
+            Signature::from_bytes(&bytes).map_err(de::Error::custom)
+        }
+    }
+}
+
 pub mod bs58_pub_key_format {
     use super::PublicKey;
     use serde::{Deserialize, Deserializer, Serializer};
@@ -252,4 +702,71 @@ mod tests {
         let signature = sign(data, &private_key);
         assert!(verify(data, &signature, &public_key));
     }
+
+    #[test]
+    fn test_verify_batch() {
+        let keys = vec![get_key_pair(), get_key_pair(), get_secp256k1_key_pair()];
+        let data: Vec<Vec<u8>> = (0..keys.len()).map(|i| vec![i as u8; 10]).collect();
+        let mut signatures: Vec<_> =
+            keys.iter().zip(&data).map(|((_, secret_key), data)| sign(data, secret_key)).collect();
+        let verify_all = |signatures: &[Signature]| -> Vec<bool> {
+            let items: Vec<_> = data
+                .iter()
+                .zip(signatures)
+                .zip(&keys)
+                .map(|((data, signature), (public_key, _))| (&data[..], signature, public_key))
+                .collect();
+            verify_batch(&items)
+        };
+        assert_eq!(verify_all(&signatures), vec![true, true, true]);
+        // The batch fails, the invalid signature is found one at a time.
+        signatures[1] = sign(&data[0], &keys[1].1);
+        assert_eq!(verify_all(&signatures), vec![true, false, true]);
+        assert!(verify_batch(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_self_describing_encoding() {
+        let (public_key, secret_key) = get_key_pair();
+        let signature = sign(b"123", &secret_key);
+        assert_eq!(public_key.to_bytes()[0], KeyType::ED25519.tag());
+        assert_eq!(PublicKey::from_bytes(&public_key.to_bytes()), Ok(public_key));
+        assert_eq!(Signature::from_bytes(&signature.to_bytes()), Ok(signature.clone()));
+        assert!(PublicKey::from_bytes(public_key.as_ref()).is_err());
+        let mut unknown = public_key.to_bytes();
+        unknown[0] = 7;
+        assert!(PublicKey::from_bytes(&unknown).is_err());
+
+        let text = String::from(&public_key);
+        assert!(text.starts_with("ed25519:"));
+        assert_eq!(PublicKey::from(&text), public_key);
+        // Keys written before they had the key type are still read.
+        assert_eq!(PublicKey::from(&bs58::encode(&public_key).into_string()), public_key);
+        assert_eq!(Signature::from(&String::from(&signature)), signature);
+
+        // Keys stored in the raw encoding are migrated.
+        let legacy = bincode::serialize(&public_key.as_ref().to_vec()).unwrap();
+        assert_eq!(bincode::deserialize::<PublicKey>(&legacy).unwrap(), public_key);
+        let stored = bincode::serialize(&public_key).unwrap();
+        assert_eq!(bincode::deserialize::<PublicKey>(&stored).unwrap(), public_key);
+        assert_ne!(stored, legacy);
+    }
+
+    #[test]
+    fn test_verify_secp256k1() {
+        let (public_key, secret_key) = get_secp256k1_key_pair();
+        assert_eq!(public_key.key_type(), KeyType::SECP256K1);
+        let data = b"123";
+        let signature = sign(data, &secret_key);
+        assert!(verify(data, &signature, &public_key));
+        assert!(!verify(b"124", &signature, &public_key));
+        let (other_public_key, _) = get_key_pair();
+        assert!(!verify(data, &signature, &other_public_key));
+
+        assert_eq!(PublicKey::from(&String::from(&public_key)), public_key);
+        assert_eq!(SecretKey::from(&String::from(&secret_key)), secret_key);
+        assert_eq!(Signature::from(&String::from(&signature)), signature);
+        let bytes = bincode::serialize(&signature).unwrap();
+        assert_eq!(bincode::deserialize::<Signature>(&bytes).unwrap(), signature);
+    }
 }
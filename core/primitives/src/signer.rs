@@ -1,12 +1,18 @@
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process;
 
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
 use crate::aggregate_signature::{BlsSecretKey, BlsPublicKey};
 use crate::hash;
+use crate::keystore;
 use crate::signature::{self, PublicKey, SecretKey};
 use crate::traits;
 use crate::types;
+use crate::vrf::{self, VrfProof};
 
 #[derive(Serialize, Deserialize)]
 pub struct KeyFile {
@@ -26,13 +32,24 @@ pub fn write_key_file(
     }
 
     let key_file = KeyFile { public_key, secret_key };
-    let key_file_path = key_store_path.join(Path::new(&public_key.to_string()));
-    let serialized = serde_json::to_string(&key_file).unwrap();
-    fs::write(key_file_path, serialized).unwrap();
+    write_to_key_store(key_store_path, &key_file, &public_key.to_string());
     public_key.to_string()
 }
 
-pub fn get_key_file(key_store_path: &Path, public_key: Option<String>) -> KeyFile {
+/// Writes the key file into the key store, encrypted with the passphrase from the environment if
+/// it's set.
+fn write_to_key_store<T: Serialize>(key_store_path: &Path, key_file: &T, public_key: &str) {
+    let key_file_path = key_store_path.join(Path::new(public_key));
+    let passphrase = keystore::passphrase_from_env();
+    let passphrase = passphrase.as_ref().map(String::as_str);
+    if let Err(e) = keystore::write_key_file(&key_file_path, key_file, public_key, passphrase) {
+        println!("{}", e);
+        process::exit(5);
+    }
+}
+
+/// Path of the key file with the public key, which may be omitted if it's the only key file.
+fn key_file_path(key_store_path: &Path, public_key: Option<String>) -> PathBuf {
     if !key_store_path.exists() {
         println!("Key store path does not exist: {:?}", &key_store_path);
         process::exit(3);
@@ -40,20 +57,63 @@ pub fn get_key_file(key_store_path: &Path, public_key: Option<String>) -> KeyFil
 
     let mut key_files = fs::read_dir(key_store_path).unwrap();
     let key_file = key_files.next();
-    let key_file_string = if key_files.count() != 0 {
+    if key_files.count() != 0 {
         if let Some(p) = public_key {
-            let key_file_path = key_store_path.join(Path::new(&p));
-            fs::read_to_string(key_file_path).unwrap()
+            let path = key_store_path.join(Path::new(&p));
+            match PublicKey::try_from_str(&p) {
+                // Key files written before the keys had their type are named without it.
+                Ok(key) if !path.exists() => {
+                    let prefixed = key_store_path.join(String::from(&key));
+                    if prefixed.exists() {
+                        prefixed
+                    } else {
+                        key_store_path.join(bs58::encode(&key).into_string())
+                    }
+                }
+                _ => path,
+            }
         } else {
             println!("Public key must be specified when there is more than one \
             file in the keystore");
             process::exit(4);
         }
     } else {
-        fs::read_to_string(key_file.unwrap().unwrap().path()).unwrap()
-    };
+        key_file.unwrap().unwrap().path()
+    }
+}
 
-    serde_json::from_str(&key_file_string).unwrap()
+/// Reads the key file from the key store, decrypting it with the passphrase from the environment
+/// if it's encrypted.
+fn read_from_key_store<T: DeserializeOwned>(key_store_path: &Path, public_key: Option<String>) -> T {
+    read_key_file(&key_file_path(key_store_path, public_key))
+}
+
+fn read_key_file<T: DeserializeOwned>(key_file_path: &Path) -> T {
+    let passphrase = keystore::passphrase_from_env();
+    match keystore::read_key_file(key_file_path, passphrase.as_ref().map(String::as_str)) {
+        Ok(key_file) => key_file,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(5);
+        }
+    }
+}
+
+/// Key file from the key store in the plain format, which the other tools read.
+pub fn export_key_file(key_store_path: &Path, public_key: Option<String>) -> String {
+    let key_file_path = key_file_path(key_store_path, public_key);
+    let passphrase = keystore::passphrase_from_env();
+    match keystore::export_key_file(&key_file_path, passphrase.as_ref().map(String::as_str)) {
+        Ok(key_file) => key_file,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(5);
+        }
+    }
+}
+
+pub fn get_key_file(key_store_path: &Path, public_key: Option<String>) -> KeyFile {
+    read_from_key_store(key_store_path, public_key)
 }
 
 #[derive(Serialize, Deserialize)]
@@ -74,34 +134,24 @@ pub fn write_bls_key_file(
     }
 
     let key_file = BlsKeyFile { public_key, secret_key };
-    let key_file_path = key_store_path.join(Path::new(&key_file.public_key.to_string()));
-    let serialized = serde_json::to_string(&key_file).unwrap();
-    fs::write(key_file_path, serialized).unwrap();
+    write_to_key_store(key_store_path, &key_file, &key_file.public_key.to_string());
     key_file.public_key.to_string()
 }
 
 pub fn get_bls_key_file(key_store_path: &Path, public_key: Option<String>) -> BlsKeyFile {
-    if !key_store_path.exists() {
-        println!("Key store path does not exist: {:?}", &key_store_path);
-        process::exit(3);
-    }
+    read_from_key_store(key_store_path, public_key)
+}
 
-    let mut key_files = fs::read_dir(key_store_path).unwrap();
-    let key_file = key_files.next();
-    let key_file_string = if key_files.count() != 0 {
-        if let Some(p) = public_key {
-            let key_file_path = key_store_path.join(Path::new(&p));
-            fs::read_to_string(key_file_path).unwrap()
-        } else {
-            println!("Public key must be specified when there is more than one \
-            file in the keystore");
-            process::exit(4);
-        }
-    } else {
-        fs::read_to_string(key_file.unwrap().unwrap().path()).unwrap()
-    };
+/// Imports the key file, plain or encrypted with the passphrase from the environment, into the
+/// key store.
+pub fn import_key_file(key_store_path: &Path, key_file_path: &Path) -> String {
+    let key_file: KeyFile = read_key_file(key_file_path);
+    write_key_file(key_store_path, key_file.public_key, key_file.secret_key)
+}
 
-    serde_json::from_str(&key_file_string).unwrap()
+pub fn import_bls_key_file(key_store_path: &Path, key_file_path: &Path) -> String {
+    let key_file: BlsKeyFile = read_key_file(key_file_path);
+    write_bls_key_file(key_store_path, key_file.public_key, key_file.secret_key)
 }
 
 pub fn get_or_create_key_file(key_store_path: &Path, public_key: Option<String>) -> BlsKeyFile {
@@ -115,6 +165,50 @@ pub fn get_or_create_key_file(key_store_path: &Path, public_key: Option<String>)
     }
 }
 
+const NODE_ID_DOMAIN: &[u8] = b"near-node-id";
+
+/// Message the account signs to claim the node id in the network. It's prefixed, so that it's
+/// never a block hash nor a VRF input.
+pub fn node_id_message(node_id: &hash::CryptoHash) -> Vec<u8> {
+    let mut message = NODE_ID_DOMAIN.to_vec();
+    message.extend_from_slice(node_id.as_ref());
+    message
+}
+
+/// Kind of the signed block, the producer signs a beacon and a shard block at every index.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlockKind {
+    Beacon,
+    Shard,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum SignerError {
+    /// Signer cannot be reached or cannot keep the blocks it signed.
+    Io(String),
+    /// Request or response is not authenticated with the key shared with the signer.
+    Unauthorized,
+    /// Request or response cannot be parsed.
+    InvalidMessage(String),
+    /// Block is at an index a different block was signed at, or before the last signed block.
+    DoubleSign { kind: BlockKind, index: u64, signed_index: u64 },
+}
+
+impl fmt::Display for SignerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignerError::Io(e) => write!(f, "Signer is unavailable: {}", e),
+            SignerError::Unauthorized => write!(f, "Signer message is not authenticated"),
+            SignerError::InvalidMessage(e) => write!(f, "Invalid signer message: {}", e),
+            SignerError::DoubleSign { kind, index, signed_index } => write!(
+                f,
+                "Refused to sign the {:?} block {}, the block {} is already signed",
+                kind, index, signed_index
+            ),
+        }
+    }
+}
+
 pub struct InMemorySigner {
     pub account_id: types::AccountId,
     pub public_key: BlsPublicKey,
@@ -140,14 +234,41 @@ impl Default for InMemorySigner {
     }
 }
 
+impl InMemorySigner {
+    pub fn sign(&self, hash: &hash::CryptoHash) -> types::PartialSignature {
+        self.secret_key.sign(hash.as_ref())
+    }
+}
+
 impl traits::Signer for InMemorySigner {
     #[inline]
     fn public_key(&self) -> BlsPublicKey {
         self.public_key.clone()
     }
 
-    fn sign(&self, hash: &hash::CryptoHash) -> types::PartialSignature {
-        self.secret_key.sign(hash.as_ref())
+    /// Signs any block, the node is trusted not to ask for a second one at an index.
+    fn sign_block(
+        &self,
+        _kind: BlockKind,
+        _index: u64,
+        hash: &hash::CryptoHash,
+    ) -> Result<types::PartialSignature, SignerError> {
+        Ok(self.sign(hash))
+    }
+
+    fn vrf_prove(
+        &self,
+        randomness: &hash::CryptoHash,
+        index: u64,
+    ) -> Result<VrfProof, SignerError> {
+        Ok(vrf::prove(&self.secret_key, &vrf::vrf_input(randomness, index)))
+    }
+
+    fn sign_node_id(
+        &self,
+        node_id: &hash::CryptoHash,
+    ) -> Result<types::PartialSignature, SignerError> {
+        Ok(self.secret_key.sign(&node_id_message(node_id)))
     }
 
     #[inline]
@@ -1,5 +1,7 @@
 use exonum_sodiumoxide::crypto::sign::ed25519::{keypair_from_seed, Seed};
 
+use crate::aggregate_signature::{BlsPublicKey, BlsSecretKey};
+use crate::hash::hash;
 use crate::signature::{PublicKey, SecretKey};
 
 pub fn get_key_pair_from_seed(seed_string: &str) -> (PublicKey, SecretKey) {
@@ -8,5 +10,14 @@ pub fn get_key_pair_from_seed(seed_string: &str) -> (PublicKey, SecretKey) {
     seed[..len].copy_from_slice(&seed_string.as_bytes()[..len]);
 
     let (public_key, secret_key) = keypair_from_seed(&Seed(seed));
-    (PublicKey(public_key), SecretKey(secret_key))
+    (PublicKey::ED25519(public_key), SecretKey::ED25519(secret_key))
+}
+
+/// BLS key pair derived from the hash of the seed. The top bits of the hash are cleared, so that
+/// it is below the order of the group.
+pub fn get_bls_key_pair_from_seed(seed_string: &str) -> (BlsPublicKey, BlsSecretKey) {
+    let mut bytes = hash(seed_string.as_bytes()).as_ref().to_vec();
+    bytes[0] &= 0x3f;
+    let secret_key = BlsSecretKey::from(bytes);
+    (secret_key.get_public_key(), secret_key)
 }
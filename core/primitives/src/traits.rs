@@ -3,6 +3,8 @@ use std::fmt::Debug;
 use std::hash::Hash;
 
 use crate::hash::CryptoHash;
+use crate::signer::{BlockKind, SignerError};
+use crate::vrf::VrfProof;
 
 use super::aggregate_signature;
 use super::types;
@@ -12,7 +14,19 @@ pub use super::serialize::{Encode, Decode};
 /// Can be used to not keep private key in the given binary via cross-process communication.
 pub trait Signer: Sync + Send {
     fn public_key(&self) -> aggregate_signature::BlsPublicKey;
-    fn sign(&self, hash: &CryptoHash) -> types::PartialSignature;
+    /// Signs the hash of the block at the index. Signers that keep the blocks they signed refuse
+    /// to sign a second block of the kind at an index, which gets the authority slashed.
+    fn sign_block(
+        &self,
+        kind: BlockKind,
+        index: u64,
+        hash: &CryptoHash,
+    ) -> Result<types::PartialSignature, SignerError>;
+    /// VRF proof of the producer of the block at the index, given the randomness of the parent
+    /// block, see `vrf`. Signers build the input themselves, so that it's never a block hash.
+    fn vrf_prove(&self, randomness: &CryptoHash, index: u64) -> Result<VrfProof, SignerError>;
+    /// Signature of the node id the account runs in the network, see `signer::node_id_message`.
+    fn sign_node_id(&self, node_id: &CryptoHash) -> Result<types::PartialSignature, SignerError>;
     fn account_id(&self) -> types::AccountId;
 }
 
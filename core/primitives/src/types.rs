@@ -2,7 +2,11 @@ use std::borrow::Borrow;
 use std::collections::HashSet;
 use std::hash::{Hash, Hasher};
 
-use crate::aggregate_signature::{BlsAggregateSignature, BlsSignature};
+use near_protos::receipt as receipt_proto;
+
+use crate::aggregate_signature::{
+    BlsAggregatePublicKey, BlsAggregateSignature, BlsPublicKey, BlsSignature,
+};
 use crate::hash::CryptoHash;
 use crate::signature::{bs58_serializer, PublicKey, Signature};
 
@@ -10,6 +14,8 @@ use crate::signature::{bs58_serializer, PublicKey, Signature};
 pub type UID = u64;
 /// Public key alias. Used to human readable public key.
 pub type ReadablePublicKey = String;
+/// Base58 encoded BLS public key, which the authorities sign the blocks with.
+pub type ReadableBlsPublicKey = String;
 /// Account identifier. Provides access to user's state.
 pub type AccountId = String;
 // TODO: Separate cryptographic hash from the hashmap hash.
@@ -33,12 +39,22 @@ pub type BlockIndex = u64;
 
 pub type ShardId = u32;
 
+/// Version of the transactions and the receipts that the chain accepts.
+pub type ProtocolVersion = u32;
+
 impl<'a> From<&'a ReadablePublicKey> for PublicKey {
     fn from(alias: &ReadablePublicKey) -> Self {
         PublicKey::from(alias)
     }
 }
 
+impl<'a> From<&'a ReadableBlsPublicKey> for BlsPublicKey {
+    fn from(alias: &ReadableBlsPublicKey) -> Self {
+        let bytes = bs58::decode(alias).into_vec().expect("Failed to convert from base58");
+        BlsPublicKey::from(bytes)
+    }
+}
+
 pub type ReceiptId = Vec<u8>;
 pub type CallbackId = Vec<u8>;
 
@@ -70,6 +86,20 @@ impl GroupSignature {
     pub fn authority_count(&self) -> usize {
         self.authority_mask.iter().filter(|&x| *x).count()
     }
+
+    /// Whether the signature aggregates the signatures of the message by the seats in the mask,
+    /// given the authorities of the seats. Authorities with several seats sign once per seat.
+    pub fn verify(&self, authorities: &[AuthorityStake], message: &[u8]) -> bool {
+        if self.authority_mask.len() > authorities.len() {
+            return false;
+        }
+        let mut public_key = BlsAggregatePublicKey::new();
+        let seats = authorities.iter().zip(&self.authority_mask);
+        for (authority, _) in seats.filter(|(_, set)| **set) {
+            public_key.aggregate(&authority.bls_public_key);
+        }
+        public_key.get_key().verify(message, &self.signature)
+    }
 }
 
 impl Default for GroupSignature {
@@ -97,6 +127,26 @@ pub struct AccountingInfo {
     // TODO(#260): Add QuotaID to identify which quota was used for the call.
 }
 
+impl From<receipt_proto::AccountingInfo> for AccountingInfo {
+    fn from(info: receipt_proto::AccountingInfo) -> Self {
+        AccountingInfo {
+            originator: info.originator,
+            contract_id: if info.contract_id.is_empty() { None } else { Some(info.contract_id) },
+        }
+    }
+}
+
+impl Into<receipt_proto::AccountingInfo> for AccountingInfo {
+    fn into(self) -> receipt_proto::AccountingInfo {
+        receipt_proto::AccountingInfo {
+            originator: self.originator,
+            contract_id: self.contract_id.unwrap_or_default(),
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
 #[derive(Hash, Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
 pub struct ManaAccounting {
     pub accounting_info: AccountingInfo,
@@ -104,6 +154,29 @@ pub struct ManaAccounting {
     pub gas_used: Gas,
 }
 
+impl From<receipt_proto::ManaAccounting> for ManaAccounting {
+    fn from(accounting: receipt_proto::ManaAccounting) -> Self {
+        ManaAccounting {
+            accounting_info: accounting.accounting_info.unwrap_or_default().into(),
+            mana_refund: accounting.mana_refund,
+            gas_used: accounting.gas_used,
+        }
+    }
+}
+
+impl Into<receipt_proto::ManaAccounting> for ManaAccounting {
+    fn into(self) -> receipt_proto::ManaAccounting {
+        let accounting_info: receipt_proto::AccountingInfo = self.accounting_info.into();
+        receipt_proto::ManaAccounting {
+            accounting_info: Some(accounting_info).into(),
+            mana_refund: self.mana_refund,
+            gas_used: self.gas_used,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Hash, Clone)]
 pub enum BlockId {
     Number(BlockIndex),
@@ -240,6 +313,10 @@ pub struct AuthorityStake {
     pub account_id: AccountId,
     /// Public key of the proposed authority.
     pub public_key: PublicKey,
+    /// BLS public key the authority signs the blocks with. Its proof of possession is checked
+    /// when it is staked, so that the signatures of the authorities can be aggregated.
+    #[serde(with = "bs58_serializer")]
+    pub bls_public_key: BlsPublicKey,
     /// Stake / weight of the authority.
     pub amount: u64,
 }
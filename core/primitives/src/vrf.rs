@@ -0,0 +1,58 @@
+//! Verifiable random function, which selects the block producers. BLS signatures are unique: a key
+//! has a single signature of a message, so the hash of the signature of an input is random to
+//! anyone without the secret key, yet anyone with the public key checks that it's the one. The BLS
+//! keys of the authorities are their VRF keys. The inputs are prefixed and are not the size of a
+//! block hash, so that a VRF proof is never the signature of a block, nor the other way around.
+use crate::aggregate_signature::{BlsPublicKey, BlsSecretKey, BlsSignature};
+use crate::hash::{hash, CryptoHash};
+
+const VRF_DOMAIN: &[u8] = b"near-vrf";
+
+pub type VrfProof = BlsSignature;
+
+/// Input of the producer of the block at the index, given the randomness of the parent block.
+pub fn vrf_input(randomness: &CryptoHash, index: u64) -> Vec<u8> {
+    let mut input = VRF_DOMAIN.to_vec();
+    input.extend_from_slice(randomness.as_ref());
+    input.extend_from_slice(&index.to_le_bytes());
+    input
+}
+
+pub fn prove(secret_key: &BlsSecretKey, input: &[u8]) -> VrfProof {
+    secret_key.sign(input)
+}
+
+/// Random output of the proof.
+pub fn proof_to_output(proof: &VrfProof) -> CryptoHash {
+    hash(proof.compress().as_ref())
+}
+
+/// Output of the proof, if it's the proof of the input by the key.
+pub fn verify(public_key: &BlsPublicKey, input: &[u8], proof: &VrfProof) -> Option<CryptoHash> {
+    if public_key.verify(input, proof) {
+        Some(proof_to_output(proof))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vrf() {
+        let secret_key = BlsSecretKey::generate();
+        let public_key = secret_key.get_public_key();
+        let input = vrf_input(&CryptoHash::default(), 1);
+        let proof = prove(&secret_key, &input);
+        assert_eq!(proof, prove(&secret_key, &input));
+        assert_eq!(verify(&public_key, &input, &proof), Some(proof_to_output(&proof)));
+
+        let other_input = vrf_input(&CryptoHash::default(), 2);
+        assert_eq!(verify(&public_key, &other_input, &proof), None);
+        let other_key = BlsSecretKey::generate().get_public_key();
+        assert_eq!(verify(&other_key, &input, &proof), None);
+        assert_ne!(proof_to_output(&prove(&secret_key, &other_input)), proof_to_output(&proof));
+    }
+}
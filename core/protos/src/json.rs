@@ -0,0 +1,367 @@
+//! Proto3 JSON mapping of the field values, which the serde impls generated for the messages are
+//! made of, see `protos/builder/src/json.rs`. Integers of 64 bits are written as strings, since
+//! JavaScript numbers can't hold all of them, and bytes as standard base64. Parsing is lenient as
+//! the mapping says: integers may be numbers or strings, bytes may be URL-safe base64 and null is
+//! the default value.
+use std::fmt;
+use std::marker::PhantomData;
+
+use protobuf::{RepeatedField, SingularPtrField};
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+/// Value of a field in the proto3 JSON mapping.
+pub trait JsonValue: Sized {
+    /// Whether the value is the default one, which is left out of the JSON object.
+    fn is_default(&self) -> bool;
+
+    fn serialize_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+
+    fn deserialize_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
+}
+
+/// Serializes the value with its JSON mapping.
+pub struct Json<'a, T: 'a>(pub &'a T);
+
+impl<'a, T: JsonValue> Serialize for Json<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize_json(serializer)
+    }
+}
+
+/// Deserializes the value with its JSON mapping, null being the default value.
+pub struct FromJson<T>(pub T);
+
+/// Deserializes the value with its JSON mapping, null not allowed.
+struct NotNull<T>(T);
+
+impl<'de, T: JsonValue> Deserialize<'de> for NotNull<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize_json(deserializer).map(NotNull)
+    }
+}
+
+impl<'de, T: JsonValue + Default> Deserialize<'de> for FromJson<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Option::<NotNull<T>>::deserialize(deserializer)?;
+        Ok(FromJson(value.map(|value| value.0).unwrap_or_default()))
+    }
+}
+
+/// Parses integers from numbers or strings, checking that they fit in the type.
+struct IntegerVisitor<T>(PhantomData<T>);
+
+macro_rules! integer_value {
+    ($ty:ty, $as_string:expr) => {
+        impl<'de> Visitor<'de> for IntegerVisitor<$ty> {
+            type Value = $ty;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{} as a number or a string", stringify!($ty))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<$ty, E> {
+                if v < <$ty>::min_value() as i64 || v as i128 > <$ty>::max_value() as i128 {
+                    return Err(E::custom(format!("{} is out of range of {}", v, stringify!($ty))));
+                }
+                Ok(v as $ty)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<$ty, E> {
+                if v as u128 > <$ty>::max_value() as u128 {
+                    return Err(E::custom(format!("{} is out of range of {}", v, stringify!($ty))));
+                }
+                Ok(v as $ty)
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<$ty, E> {
+                let in_range = v >= <$ty>::min_value() as f64 && v <= <$ty>::max_value() as f64;
+                if v.fract() != 0.0 || !in_range {
+                    return Err(E::custom(format!("{} is not a {}", v, stringify!($ty))));
+                }
+                Ok(v as $ty)
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<$ty, E> {
+                v.parse().map_err(|_| E::custom(format!("{} is not a {}", v, stringify!($ty))))
+            }
+        }
+
+        impl JsonValue for $ty {
+            fn is_default(&self) -> bool {
+                *self == 0
+            }
+
+            fn serialize_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                if $as_string {
+                    serializer.serialize_str(&self.to_string())
+                } else {
+                    self.serialize(serializer)
+                }
+            }
+
+            fn deserialize_json<'de, D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<Self, D::Error> {
+                deserializer.deserialize_any(IntegerVisitor::<$ty>(PhantomData))
+            }
+        }
+    };
+}
+
+integer_value!(i32, false);
+integer_value!(u32, false);
+integer_value!(i64, true);
+integer_value!(u64, true);
+
+/// Parses floats from numbers, or from the strings of the values that are not numbers.
+struct FloatVisitor;
+
+impl<'de> Visitor<'de> for FloatVisitor {
+    type Value = f64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "number, \"NaN\", \"Infinity\" or \"-Infinity\"")
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<f64, E> {
+        Ok(v as f64)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<f64, E> {
+        Ok(v as f64)
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<f64, E> {
+        Ok(v)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<f64, E> {
+        match v {
+            "NaN" => Ok(std::f64::NAN),
+            "Infinity" => Ok(std::f64::INFINITY),
+            "-Infinity" => Ok(std::f64::NEG_INFINITY),
+            _ => v.parse().map_err(|_| E::custom(format!("{} is not a number", v))),
+        }
+    }
+}
+
+fn serialize_float<S: Serializer>(v: f64, serializer: S) -> Result<S::Ok, S::Error> {
+    if v.is_nan() {
+        serializer.serialize_str("NaN")
+    } else if v.is_infinite() {
+        serializer.serialize_str(if v > 0.0 { "Infinity" } else { "-Infinity" })
+    } else {
+        serializer.serialize_f64(v)
+    }
+}
+
+impl JsonValue for f64 {
+    fn is_default(&self) -> bool {
+        *self == 0.0
+    }
+
+    fn serialize_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_float(*self, serializer)
+    }
+
+    fn deserialize_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(FloatVisitor)
+    }
+}
+
+impl JsonValue for f32 {
+    fn is_default(&self) -> bool {
+        *self == 0.0
+    }
+
+    fn serialize_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_float(f64::from(*self), serializer)
+    }
+
+    fn deserialize_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(FloatVisitor).map(|v| v as f32)
+    }
+}
+
+impl JsonValue for bool {
+    fn is_default(&self) -> bool {
+        !*self
+    }
+
+    fn serialize_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bool(*self)
+    }
+
+    fn deserialize_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        bool::deserialize(deserializer)
+    }
+}
+
+impl JsonValue for String {
+    fn is_default(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn serialize_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self)
+    }
+
+    fn deserialize_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)
+    }
+}
+
+impl JsonValue for Vec<u8> {
+    fn is_default(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn serialize_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(self))
+    }
+
+    fn deserialize_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        base64::decode(&s)
+            .or_else(|_| base64::decode_config(&s, base64::URL_SAFE))
+            .map_err(de::Error::custom)
+    }
+}
+
+impl<T: JsonValue> JsonValue for SingularPtrField<T> {
+    fn is_default(&self) -> bool {
+        self.is_none()
+    }
+
+    fn serialize_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.as_ref() {
+            Some(value) => value.serialize_json(serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    fn deserialize_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize_json(deserializer).map(SingularPtrField::some)
+    }
+}
+
+impl<T: JsonValue> JsonValue for RepeatedField<T> {
+    fn is_default(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn serialize_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for value in self.iter() {
+            seq.serialize_element(&Json(value))?;
+        }
+        seq.end()
+    }
+
+    fn deserialize_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SeqVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: JsonValue> Visitor<'de> for SeqVisitor<T> {
+            type Value = RepeatedField<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "array")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut values = vec![];
+                while let Some(NotNull(value)) = seq.next_element()? {
+                    values.push(value);
+                }
+                Ok(RepeatedField::from_vec(values))
+            }
+        }
+
+        deserializer.deserialize_seq(SeqVisitor(PhantomData))
+    }
+}
+
+/// Writes the name of the enum value.
+pub fn serialize_enum<E: PartialEq, S: Serializer>(
+    value: &E,
+    values: &[(&str, E)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let name = values
+        .iter()
+        .find(|(_, known)| known == value)
+        .map(|(name, _)| *name)
+        .expect("Enum value has a name");
+    serializer.serialize_str(name)
+}
+
+/// Parses the enum value from its name, or from its number.
+pub fn deserialize_enum<'de, E: protobuf::ProtobufEnum, D: Deserializer<'de>>(
+    values: &'static [(&'static str, E)],
+    deserializer: D,
+) -> Result<E, D::Error> {
+    struct EnumVisitor<E: 'static>(&'static [(&'static str, E)]);
+
+    impl<'de, E: protobuf::ProtobufEnum> Visitor<'de> for EnumVisitor<E> {
+        type Value = E;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "enum value name or number")
+        }
+
+        fn visit_i64<E2: de::Error>(self, v: i64) -> Result<E, E2> {
+            if v < i64::from(i32::min_value()) || v > i64::from(i32::max_value()) {
+                return Err(E2::custom(format!("Unknown enum value {}", v)));
+            }
+            E::from_i32(v as i32).ok_or_else(|| E2::custom(format!("Unknown enum value {}", v)))
+        }
+
+        fn visit_u64<E2: de::Error>(self, v: u64) -> Result<E, E2> {
+            if v > i32::max_value() as u64 {
+                return Err(E2::custom(format!("Unknown enum value {}", v)));
+            }
+            self.visit_i64(v as i64)
+        }
+
+        fn visit_str<E2: de::Error>(self, v: &str) -> Result<E, E2> {
+            self.0
+                .iter()
+                .find(|(name, _)| *name == v)
+                .map(|(_, value)| *value)
+                .ok_or_else(|| E2::custom(format!("Unknown enum value {}", v)))
+        }
+    }
+
+    deserializer.deserialize_any(EnumVisitor(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use protobuf::{RepeatedField, SingularPtrField};
+
+    use crate::outcome::{TransactionResult, TransactionStatus};
+    use crate::receipt::{AsyncCall, CallbackInfo};
+
+    #[test]
+    fn test_json_mapping() {
+        let mut result = TransactionResult::new();
+        result.status = TransactionStatus::COMPLETED;
+        result.logs = RepeatedField::from_vec(vec!["log".to_string()]);
+        result.tip = 1 << 60;
+        let json = serde_json::to_string(&result).unwrap();
+        assert_eq!(json, r#"{"status":"COMPLETED","logs":["log"],"tip":"1152921504606846976"}"#);
+        assert_eq!(serde_json::from_str::<TransactionResult>(&json).unwrap(), result);
+
+        let mut call = AsyncCall::new();
+        call.method_name = b"run".to_vec();
+        call.callback = SingularPtrField::some(CallbackInfo::new());
+        let json = serde_json::to_string(&call).unwrap();
+        assert_eq!(json, r#"{"methodName":"cnVu","callback":{}}"#);
+        let parsed: AsyncCall =
+            serde_json::from_str(r#"{"method_name":"cnVu","callback":{},"mana":"0","args":null}"#)
+                .unwrap();
+        assert_eq!(parsed, call);
+        assert!(serde_json::from_str::<AsyncCall>(r#"{"unknown":1}"#).is_err());
+    }
+}
@@ -3,5 +3,26 @@ pub use autogenerated::*;
 
 pub use protobuf::Message;
 
+/// Serialized `FileDescriptorSet` of the protos, for tools like grpcurl or the code generators of
+/// other languages. Also written to `core/protos/src/autogenerated/descriptor_set.pb`.
+pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!("autogenerated/descriptor_set.pb");
+
+#[cfg(feature = "with-serde")]
+pub mod json;
 #[cfg(feature = "with-serde")]
 pub mod serde;
+
+#[cfg(test)]
+mod tests {
+    use protobuf::descriptor::FileDescriptorSet;
+
+    use super::*;
+
+    #[test]
+    fn test_file_descriptor_set() {
+        let set: FileDescriptorSet = protobuf::parse_from_bytes(FILE_DESCRIPTOR_SET).unwrap();
+        let names: Vec<&str> = set.get_file().iter().map(|file| file.get_name()).collect();
+        assert!(names.contains(&"signed_transaction.proto"));
+        assert!(names.contains(&"node_api.proto"));
+    }
+}
@@ -21,3 +21,23 @@ pub mod b64_format {
         Ok(protobuf::parse_from_bytes(&bytes).map_err(serde::de::Error::custom)?)
     }
 }
+
+/// Encoded message in base64, left encoded, e.g. so that its size can be checked before it is
+/// decoded.
+pub mod b64_bytes_format {
+    use base64;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer,
+    {
+        serializer.serialize_str(&base64::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+        where D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        base64::decode(&s).map_err(serde::de::Error::custom)
+    }
+}
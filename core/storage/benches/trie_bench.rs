@@ -26,7 +26,7 @@ fn trie_lookup(bench: &mut Bencher) {
         changes.push((rand_bytes(), Some(rand_bytes())));
     }
     let other_changes = changes.clone();
-    let (db_changes, root) = trie.update(&root, changes.drain(..));
+    let (db_changes, root) = trie.update(&root, changes.drain(..)).unwrap();
     apply_changes(&storage, Some(0), db_changes).expect("Failed to commit");
 
     bench.iter(|| {
@@ -49,7 +49,7 @@ fn trie_update(bench: &mut Bencher) {
 
     bench.iter(|| {
         let mut this_changes = changes.clone();
-        let (_, _) = trie.update(&root, this_changes.drain(..));
+        trie.update(&root, this_changes.drain(..)).unwrap();
     });
 }
 
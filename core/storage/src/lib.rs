@@ -10,7 +10,7 @@ extern crate log;
 extern crate primitives;
 extern crate serde;
 
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::iter::Peekable;
 use std::sync::Arc;
 
@@ -20,7 +20,7 @@ use parking_lot::RwLock;
 
 use primitives::traits::{Decode, Encode};
 use primitives::types::MerkleHash;
-pub use crate::trie::DBChanges;
+pub use crate::trie::{DBChanges, KeyChange, StatePart};
 
 mod nibble_slice;
 
@@ -32,7 +32,8 @@ pub const COL_EXTRA: Option<u32> = Some(1);
 pub const COL_BLOCKS: Option<u32> = Some(2);
 pub const COL_HEADERS: Option<u32> = Some(3);
 pub const COL_BLOCK_INDEX: Option<u32> = Some(4);
-pub const TOTAL_COLUMNS: Option<u32> = Some(5);
+pub const COL_TRANSACTION_POOL: Option<u32> = Some(5);
+pub const TOTAL_COLUMNS: Option<u32> = Some(6);
 
 /// Provides a way to access Storage and record changes with future commit.
 pub struct StateDbUpdate {
@@ -67,16 +68,22 @@ impl StateDbUpdate {
         self.prospective.insert(key.to_vec(), None);
     }
     pub fn for_keys_with_prefix<F: FnMut(&[u8])>(&self, prefix: &[u8], mut f: F) {
+        self.for_keys_with_prefix_while(prefix, |key| {
+            f(key);
+            true
+        })
+    }
+    /// Calls `f` with the keys with the given prefix in order, until it returns false.
+    pub fn for_keys_with_prefix_while<F: FnMut(&[u8]) -> bool>(&self, prefix: &[u8], mut f: F) {
         // TODO: join with iterating over committed / perspective overlay here.
         let mut iter = move || -> Result<(), String> {
             let mut iter = self.state_db.trie.iter(&self.root)?;
             iter.seek(prefix)?;
             for x in iter {
                 let (key, _) = x?;
-                if !key.starts_with(prefix) {
+                if !key.starts_with(prefix) || !f(&key) {
                     break;
                 }
-                f(&key);
             }
             Ok(())
         };
@@ -84,6 +91,26 @@ impl StateDbUpdate {
             debug!(target: "trie", "Error while iterating by prefix: {}", e);
         }
     }
+    /// Keys with the given prefix changed by this update, committed or not.
+    pub fn changed_keys_with_prefix(&self, prefix: &[u8]) -> BTreeSet<Vec<u8>> {
+        self.committed.keys()
+            .chain(self.prospective.keys())
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+    /// Keys with the given prefix changed by the uncommitted changes.
+    pub fn prospective_keys_with_prefix(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+        self.prospective.keys().filter(|key| key.starts_with(prefix)).cloned().collect()
+    }
+    /// Value of the key without the uncommitted changes.
+    pub fn get_committed(&self, key: &[u8]) -> Option<DBValue> {
+        if let Some(value) = self.committed.get(key) {
+            Some(DBValue::from_slice(value.as_ref()?))
+        } else {
+            self.state_db.trie.get(&self.root, key).map(|x| DBValue::from_slice(&x))
+        }
+    }
     pub fn commit(&mut self) {
         if self.committed.is_empty() {
             ::std::mem::swap(&mut self.prospective, &mut self.committed);
@@ -97,7 +124,9 @@ impl StateDbUpdate {
     pub fn rollback(&mut self) {
         self.prospective.clear();
     }
-    pub fn finalize(mut self) -> (DBChanges, MerkleHash) {
+    /// Changes to the db and the new root. Fails if the trie misses the nodes the changes go
+    /// through.
+    pub fn finalize(mut self) -> Result<(DBChanges, MerkleHash), String> {
         if !self.prospective.is_empty() {
             self.commit();
         }
@@ -282,9 +311,68 @@ impl StateDb {
         StateDb { trie: trie::Trie::new(storage.clone(), COL_STATE), storage }
     }
 
+    /// State db that records the trie nodes it reads, see `Trie::new_recording`.
+    pub fn new_recording(storage: Arc<KeyValueDB>) -> Self {
+        StateDb { trie: trie::Trie::new_recording(storage.clone(), COL_STATE), storage }
+    }
+
+    /// State db with only the given trie nodes, e.g. those recorded while applying a block. It
+    /// records its reads too, so that the nodes missing from the witness are noticed.
+    pub fn from_witness(witness: &[Vec<u8>]) -> Result<Self, String> {
+        let storage: Arc<KeyValueDB> = Arc::new(kvdb_memorydb::create(TOTAL_COLUMNS.unwrap()));
+        let state_db = StateDb::new_recording(storage);
+        let changes = state_db.trie.state_part_changes(&StatePart { nodes: witness.to_vec() })?;
+        state_db.commit(changes).map_err(|e| e.to_string())?;
+        Ok(state_db)
+    }
+
+    /// Trie nodes read so far, if the state db records its reads.
+    pub fn recorded_nodes(&self) -> Vec<Vec<u8>> {
+        self.trie.recorded_nodes()
+    }
+
+    /// Whether some trie nodes were not found, e.g. because the witness misses them.
+    pub fn has_missing_nodes(&self) -> bool {
+        self.trie.has_missing_nodes()
+    }
+
     pub fn commit(&self, transaction: DBChanges) -> std::io::Result<()> {
         trie::apply_changes(&self.storage, COL_STATE, transaction)
     }
+
+    /// Keys changed between the states, see `Trie::diff`.
+    pub fn diff(
+        &self,
+        old_root: &MerkleHash,
+        new_root: &MerkleHash,
+        new_nodes: &DBChanges,
+    ) -> Result<Vec<KeyChange>, String> {
+        self.trie.diff(old_root, new_root, new_nodes)
+    }
+
+    /// Part of the state for the other nodes to sync from, see `StatePart`.
+    pub fn get_state_part(
+        &self,
+        root: &MerkleHash,
+        part_id: u64,
+        num_parts: u64,
+    ) -> Result<StatePart, String> {
+        self.trie.get_state_part(root, part_id, num_parts)
+    }
+
+    /// Checks the part received from another node against the root and writes its nodes. The
+    /// state is complete once all the parts are applied.
+    pub fn apply_state_part(
+        &self,
+        root: &MerkleHash,
+        part_id: u64,
+        num_parts: u64,
+        part: &StatePart,
+    ) -> Result<(), String> {
+        trie::Trie::validate_state_part(root, part_id, num_parts, part)?;
+        let changes = self.trie.state_part_changes(part)?;
+        self.commit(changes).map_err(|e| e.to_string())
+    }
 }
 
 pub fn open_database(storage_path: &str) -> Database {
@@ -322,6 +410,22 @@ pub fn extend_with_cache<T: Clone + Encode>(
     storage.write(db_transaction).expect("Database write failed");
 }
 
+/// Removes the keys from the storage and the cache.
+pub fn remove_with_cache<T>(
+    storage: &Arc<Storage>,
+    col: Option<u32>,
+    cache: &RwLock<HashMap<Vec<u8>, T>>,
+    keys: &[Vec<u8>],
+) {
+    let mut db_transaction = storage.transaction();
+    let mut cache = cache.write();
+    for key in keys {
+        cache.remove(key);
+        db_transaction.delete(col, key);
+    }
+    storage.write(db_transaction).expect("Database write failed");
+}
+
 pub fn read_with_cache<T: Clone + Decode>(
     storage: &Arc<Storage>,
     col: Option<u32>,
@@ -347,7 +451,9 @@ pub fn read_with_cache<T: Clone + Decode>(
 
 #[cfg(test)]
 mod tests {
-    use crate::test_utils::create_state_db;
+    use std::time::{Duration, Instant};
+
+    use crate::test_utils::{create_state_db, FaultyStorage, Faults};
 
     use super::*;
 
@@ -359,7 +465,7 @@ mod tests {
         state_db_update.set(b"dog", &DBValue::from_slice(b"puppy"));
         state_db_update.set(b"dog2", &DBValue::from_slice(b"puppy"));
         state_db_update.set(b"xxx", &DBValue::from_slice(b"puppy"));
-        let (transaction, new_root) = state_db_update.finalize();
+        let (transaction, new_root) = state_db_update.finalize().unwrap();
         state_db.commit(transaction).ok();
         let state_db_update2 = StateDbUpdate::new(state_db.clone(), new_root);
         assert_eq!(state_db_update2.get(b"dog").unwrap(), DBValue::from_slice(b"puppy"));
@@ -374,7 +480,7 @@ mod tests {
         let mut state_db_update = StateDbUpdate::new(state_db.clone(), MerkleHash::default());
         state_db_update.set(b"dog", &DBValue::from_slice(b"puppy"));
         state_db_update.set(b"aaa", &DBValue::from_slice(b"puppy"));
-        let (transaction, new_root) = state_db_update.finalize();
+        let (transaction, new_root) = state_db_update.finalize().unwrap();
         state_db.commit(transaction).ok();
 
         let mut state_db_update = StateDbUpdate::new(state_db.clone(), new_root);
@@ -417,4 +523,35 @@ mod tests {
         let values: Vec<Vec<u8>> = state_db_update.range(b"do", b"", b"xyz").unwrap().collect();
         assert_eq!(values, vec![b"dog".to_vec(), b"dog2".to_vec(), b"dog3".to_vec()]);
     }
+
+    #[test]
+    fn state_db_faults() {
+        let storage = Arc::new(FaultyStorage::new(COL_STATE));
+        let state_db = Arc::new(StateDb::new(storage.clone()));
+        let mut state_db_update = StateDbUpdate::new(state_db.clone(), MerkleHash::default());
+        state_db_update.set(b"dog", &DBValue::from_slice(b"puppy"));
+        let (transaction, root) = state_db_update.finalize().unwrap();
+        state_db.commit(transaction).unwrap();
+
+        let mut state_db_update = StateDbUpdate::new(state_db.clone(), root);
+        state_db_update.set(b"dog", &DBValue::from_slice(b"doggy"));
+        state_db_update.set(b"cat", &DBValue::from_slice(b"kitten"));
+        let (transaction, new_root) = state_db_update.finalize().unwrap();
+        storage.set_faults(Faults { writes_before_failure: Some(0), ..Default::default() });
+        assert!(state_db.commit(transaction.clone()).is_err());
+        // Nothing of the failed commit is written, the old state is intact.
+        storage.heal();
+        assert_eq!(StateDbUpdate::new(state_db.clone(), new_root).get(b"cat"), None);
+        let state_db_update = StateDbUpdate::new(state_db.clone(), root);
+        assert_eq!(state_db_update.get(b"dog").unwrap(), DBValue::from_slice(b"puppy"));
+
+        storage.set_faults(Faults { reads_before_failure: Some(0), ..Default::default() });
+        assert_eq!(state_db_update.get(b"dog"), None);
+        storage.set_faults(Faults { latency: Duration::from_millis(10), ..Default::default() });
+        let started = Instant::now();
+        state_db.commit(transaction).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(10));
+        let state_db_update = StateDbUpdate::new(state_db.clone(), new_root);
+        assert_eq!(state_db_update.get(b"cat").unwrap(), DBValue::from_slice(b"kitten"));
+    }
 }
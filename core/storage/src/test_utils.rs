@@ -1,6 +1,11 @@
+use std::io;
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
+use kvdb::{DBOp, DBTransaction, DBValue, KeyValueDB};
 use kvdb_memorydb::InMemory;
+use parking_lot::Mutex;
 
 use crate::{StateDb, TOTAL_COLUMNS};
 
@@ -14,3 +19,122 @@ pub fn create_state_db() -> StateDb {
     let storage = Arc::new(create_memory_db());
     StateDb::new(storage)
 }
+
+/// Faults that `FaultyStorage` injects into its column.
+#[derive(Debug, Default, Clone)]
+pub struct Faults {
+    /// Reads that succeed before all the next ones fail, `None` for no read failures.
+    pub reads_before_failure: Option<usize>,
+    /// Writes that succeed before all the next ones fail, `None` for no write failures.
+    pub writes_before_failure: Option<usize>,
+    /// Delay of every read and write.
+    pub latency: Duration,
+}
+
+enum Access {
+    Read,
+    Write,
+}
+
+/// Memory storage that injects faults into the reads and the writes of one column, e.g.
+/// `COL_STATE`, to test that they are handled. The other columns work as usual. A write that
+/// fails writes nothing, not even to the other columns. The trie takes a failed read for a
+/// missing node.
+pub struct FaultyStorage {
+    storage: MemoryStorage,
+    column: Option<u32>,
+    faults: Mutex<Faults>,
+}
+
+impl FaultyStorage {
+    pub fn new(column: Option<u32>) -> Self {
+        FaultyStorage { storage: create_memory_db(), column, faults: Mutex::new(Faults::default()) }
+    }
+
+    /// Faults to inject from now on.
+    pub fn set_faults(&self, faults: Faults) {
+        *self.faults.lock() = faults;
+    }
+
+    /// Stops injecting faults.
+    pub fn heal(&self) {
+        self.set_faults(Faults::default());
+    }
+
+    fn inject(&self, access: Access) -> io::Result<()> {
+        let latency = {
+            let mut faults = self.faults.lock();
+            let (remaining, name) = match access {
+                Access::Read => (&mut faults.reads_before_failure, "read"),
+                Access::Write => (&mut faults.writes_before_failure, "write"),
+            };
+            match remaining {
+                Some(0) => {
+                    let message = format!("Injected {} failure", name);
+                    return Err(io::Error::new(io::ErrorKind::Other, message));
+                }
+                Some(n) => *n -= 1,
+                None => {}
+            }
+            faults.latency
+        };
+        if latency > Duration::default() {
+            thread::sleep(latency);
+        }
+        Ok(())
+    }
+
+    fn inject_read(&self, col: Option<u32>) -> io::Result<()> {
+        if col == self.column {
+            self.inject(Access::Read)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl KeyValueDB for FaultyStorage {
+    fn get(&self, col: Option<u32>, key: &[u8]) -> io::Result<Option<DBValue>> {
+        self.inject_read(col)?;
+        self.storage.get(col, key)
+    }
+
+    fn get_by_prefix(&self, col: Option<u32>, prefix: &[u8]) -> Option<Box<[u8]>> {
+        self.inject_read(col).ok()?;
+        self.storage.get_by_prefix(col, prefix)
+    }
+
+    fn write_buffered(&self, transaction: DBTransaction) {
+        self.storage.write_buffered(transaction)
+    }
+
+    fn write(&self, transaction: DBTransaction) -> io::Result<()> {
+        let in_column = transaction.ops.iter().any(|op| match op {
+            DBOp::Insert { col, .. } | DBOp::Delete { col, .. } => *col == self.column,
+        });
+        if in_column {
+            self.inject(Access::Write)?;
+        }
+        self.storage.write(transaction)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.storage.flush()
+    }
+
+    fn iter<'a>(&'a self, col: Option<u32>) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.storage.iter(col)
+    }
+
+    fn iter_from_prefix<'a>(
+        &'a self,
+        col: Option<u32>,
+        prefix: &'a [u8],
+    ) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.storage.iter_from_prefix(col, prefix)
+    }
+
+    fn restore(&self, new_db: &str) -> io::Result<()> {
+        self.storage.restore(new_db)
+    }
+}
@@ -1,10 +1,13 @@
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 pub use kvdb::{DBValue, KeyValueDB};
 use primitives::hash::{hash, CryptoHash};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{Cursor, Read, Write};
 use std::sync::Arc;
 
+use parking_lot::RwLock;
+use serde_derive::{Deserialize, Serialize};
+
 use crate::nibble_slice::NibbleSlice;
 
 #[derive(Clone, Hash, Debug)]
@@ -172,17 +175,161 @@ impl RcTrieNode {
     }
 }
 
+/// Nodes the trie read, and the ones it didn't find, see `Trie::new_recording`.
+#[derive(Default)]
+struct Recorder {
+    nodes: HashMap<CryptoHash, Vec<u8>>,
+    missing: HashSet<CryptoHash>,
+}
+
 pub struct Trie {
     storage: Arc<KeyValueDB>,
     column: Option<u32>,
     null_node: CryptoHash,
+    recorder: Option<RwLock<Recorder>>,
 }
 
 pub type DBChanges = HashMap<Vec<u8>, Option<Vec<u8>>>;
 
+/// Key that has different values in two states, with the old and the new value.
+pub type KeyChange = (Vec<u8>, Option<Vec<u8>>, Option<Vec<u8>>);
+
+/// Part of a trie under some key prefix, see `Trie::diff`.
+#[derive(Clone, PartialEq)]
+enum DiffView {
+    Node(CryptoHash),
+    /// Remaining nibbles of the key and the value.
+    Leaf(Vec<u8>, Vec<u8>),
+    /// Remaining nibbles of the key and the child.
+    Extension(Vec<u8>, CryptoHash),
+}
+
+fn decode_nibbles(encoded: &[u8]) -> Vec<u8> {
+    NibbleSlice::from_encoded(encoded).0.iter().collect()
+}
+
+fn nibbles_to_key(nibbles: &[u8]) -> Vec<u8> {
+    nibbles.chunks(2).filter(|pair| pair.len() == 2).map(|pair| pair[0] * 16 + pair[1]).collect()
+}
+
+/// Nodes of the trie on the paths to the keys whose first byte falls into the range of one
+/// of `num_parts` equal parts of the key space, including the nodes above these keys, so
+/// that the part can be checked against the root on its own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatePart {
+    /// Encoded nodes, without the reference counts.
+    pub nodes: Vec<Vec<u8>>,
+}
+
+/// Range of the first bytes of the keys covered by the part, end exclusive.
+fn part_range(part_id: u64, num_parts: u64) -> Result<(u16, u16), String> {
+    if num_parts == 0 || num_parts > 256 || part_id >= num_parts {
+        return Err(format!("Invalid state part {} of {}", part_id, num_parts));
+    }
+    Ok(((part_id * 256 / num_parts) as u16, ((part_id + 1) * 256 / num_parts) as u16))
+}
+
+/// Whether some keys starting with the nibbles have the first byte in the range.
+fn prefix_in_range(prefix: &[u8], (start, end): (u16, u16)) -> bool {
+    match prefix {
+        [] => true,
+        [first] => {
+            let lowest = u16::from(*first) * 16;
+            lowest < end && lowest + 16 > start
+        }
+        _ => {
+            let byte = u16::from(prefix[0]) * 16 + u16::from(prefix[1]);
+            start <= byte && byte < end
+        }
+    }
+}
+
+/// Visits the nodes of the part with their hashes, each one once. `get_node` returns the
+/// encoded node by its hash.
+fn visit_part<F>(root: &CryptoHash, range: (u16, u16), mut get_node: F) -> Result<(), String>
+where
+    F: FnMut(&CryptoHash) -> Result<Vec<u8>, String>,
+{
+    let mut visited = HashSet::new();
+    let mut stack = vec![(*root, vec![])];
+    while let Some((hash, prefix)) = stack.pop() {
+        if hash == Trie::empty_root() || !visited.insert(hash) {
+            continue;
+        }
+        let data = get_node(&hash)?;
+        let node =
+            RawTrieNode::decode(&data).map_err(|_| format!("Failed to decode node {}", hash))?;
+        match node {
+            RawTrieNode::Leaf(_, _) => {}
+            RawTrieNode::Branch(children, _) => {
+                for (i, child) in children.iter().enumerate() {
+                    if let Some(child) = child {
+                        let mut child_prefix = prefix.clone();
+                        child_prefix.push(i as u8);
+                        if prefix_in_range(&child_prefix, range) {
+                            stack.push((*child, child_prefix));
+                        }
+                    }
+                }
+            }
+            RawTrieNode::Extension(key, child) => {
+                let mut child_prefix = prefix;
+                child_prefix.extend(decode_nibbles(&key));
+                if prefix_in_range(&child_prefix, range) {
+                    stack.push((child, child_prefix));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 impl Trie {
     pub fn new(storage: Arc<KeyValueDB>, column: Option<u32>) -> Self {
-        Trie { storage, column, null_node: Trie::empty_root() }
+        Trie { storage, column, null_node: Trie::empty_root(), recorder: None }
+    }
+
+    /// Trie that records the nodes it reads, so that the same reads can be repeated without
+    /// the rest of the state, e.g. to prove that applying a block gives some other state.
+    pub fn new_recording(storage: Arc<KeyValueDB>, column: Option<u32>) -> Self {
+        let recorder = Some(RwLock::new(Recorder::default()));
+        Trie { storage, column, null_node: Trie::empty_root(), recorder }
+    }
+
+    /// Encoded nodes read so far, without the reference counts, ordered by hash.
+    pub fn recorded_nodes(&self) -> Vec<Vec<u8>> {
+        let recorder = match &self.recorder {
+            Some(recorder) => recorder.read(),
+            None => return vec![],
+        };
+        let mut nodes: Vec<(&CryptoHash, &Vec<u8>)> = recorder.nodes.iter().collect();
+        nodes.sort_by_key(|(hash, _)| *hash);
+        nodes.into_iter().map(|(_, data)| data.clone()).collect()
+    }
+
+    /// Whether the trie failed to find some of the nodes it tried to read.
+    pub fn has_missing_nodes(&self) -> bool {
+        self.recorder.as_ref().map_or(false, |recorder| !recorder.read().missing.is_empty())
+    }
+
+    /// Reads the node with its reference count.
+    fn read_node(&self, hash: &CryptoHash) -> Option<Vec<u8>> {
+        let bytes = match self.storage.get(self.column, hash.as_ref()) {
+            Ok(Some(bytes)) => Some(bytes.to_vec()),
+            _ => None,
+        };
+        if let Some(recorder) = &self.recorder {
+            let mut recorder = recorder.write();
+            match &bytes {
+                Some(bytes) if bytes.len() >= 4 => {
+                    recorder.nodes.insert(*hash, bytes[..bytes.len() - 4].to_vec());
+                }
+                _ => {
+                    recorder.missing.insert(*hash);
+                }
+            }
+        }
+        bytes
     }
 
     pub fn empty_root() -> CryptoHash {
@@ -193,8 +340,8 @@ impl Trie {
         if *hash == self.null_node {
             return Ok(TrieNode::Empty);
         }
-        if let Ok(Some(bytes)) = self.storage.get(self.column, hash.as_ref()) {
-            match RcTrieNode::decode(&bytes.to_vec()) {
+        if let Some(bytes) = self.read_node(hash) {
+            match RcTrieNode::decode(&bytes) {
                 Ok((value, _)) => Ok(TrieNode::new(value)),
                 Err(_) => Err(format!("Failed to decode node {}", hash)),
             }
@@ -210,11 +357,11 @@ impl Trie {
             if hash == self.null_node {
                 return Ok(None);
             }
-            let node = match self.storage.get(self.column, hash.as_ref()) {
-                Ok(Some(bytes)) => RcTrieNode::decode(&bytes.to_vec())
+            let node = match self.read_node(&hash) {
+                Some(bytes) => RcTrieNode::decode(&bytes)
                     .map(|trie_node| trie_node.0)
                     .map_err(|_| "Failed to decode node".to_string())?,
-                _ => return Err(format!("Node {} not found in storage", hash)),
+                None => return Err(format!("Node {} not found in storage", hash)),
             };
 
             match node {
@@ -513,25 +660,28 @@ impl Trie {
         key
     }
 
-    pub fn update<I>(&self, root: &CryptoHash, changes: I) -> (DBChanges, CryptoHash)
+    /// Changes to the db and the new root after applying the changes to the state with the
+    /// given root. Fails if the nodes the changes go through are missing.
+    pub fn update<I>(
+        &self,
+        root: &CryptoHash,
+        changes: I,
+    ) -> Result<(DBChanges, CryptoHash), String>
     where
         I: Iterator<Item = (Vec<u8>, Option<Vec<u8>>)>,
     {
         let mut death_row: HashMap<CryptoHash, u32> = HashMap::default();
         let mut last_root = Some(*root);
-        let mut root_node = self.retrieve_node(root).expect("Root not found");
+        let mut root_node = self.retrieve_node(root)?;
         for (key, value) in changes {
             let key = NibbleSlice::new(&key);
             match value {
                 Some(arr) => {
-                    root_node = self.insert(root_node, key, arr).expect("Failed to insert");
+                    root_node = self.insert(root_node, key, arr)?;
                     last_root = None;
                 }
                 None => {
-                    root_node = match self
-                        .delete(root_node, last_root, key, &mut death_row)
-                        .expect("Failed to delete")
-                    {
+                    root_node = match self.delete(root_node, last_root, key, &mut death_row)? {
                         Some(value) => value,
                         None => TrieNode::Empty,
                     };
@@ -550,14 +700,187 @@ impl Trie {
             db_changes.insert(key.as_ref().to_vec(), Some(bytes));
         }
         for (hash, _) in death_row {
-            db_changes.insert(hash.as_ref().to_vec(), None);
+            // The update may recreate a node it replaced, e.g. when a value is set back.
+            db_changes.entry(hash.as_ref().to_vec()).or_insert(None);
         }
-        (db_changes, new_root)
+        Ok((db_changes, new_root))
     }
 
     pub fn iter<'a>(&'a self, root: &CryptoHash) -> Result<TrieIterator<'a>, String> {
         TrieIterator::new(self, root)
     }
+
+    /// Keys whose values differ between the states with the given roots, in key order.
+    /// Nodes are looked up in `new_nodes` first, so that the changes that produced `new_root`
+    /// can be diffed before they are committed and the replaced nodes are removed.
+    pub fn diff(
+        &self,
+        old_root: &CryptoHash,
+        new_root: &CryptoHash,
+        new_nodes: &DBChanges,
+    ) -> Result<Vec<KeyChange>, String> {
+        let mut changes = vec![];
+        self.diff_views(
+            Some(DiffView::Node(*old_root)),
+            Some(DiffView::Node(*new_root)),
+            new_nodes,
+            &mut vec![],
+            &mut changes,
+        )?;
+        Ok(changes)
+    }
+
+    fn retrieve_raw_node(
+        &self,
+        hash: &CryptoHash,
+        new_nodes: &DBChanges,
+    ) -> Result<Option<RawTrieNode>, String> {
+        if *hash == self.null_node {
+            return Ok(None);
+        }
+        let bytes = match new_nodes.get(hash.as_ref()) {
+            Some(Some(bytes)) => bytes.clone(),
+            _ => match self.storage.get(self.column, hash.as_ref()) {
+                Ok(Some(bytes)) => bytes.to_vec(),
+                _ => return Err(format!("Node {} not found in storage", hash)),
+            },
+        };
+        RcTrieNode::decode(&bytes)
+            .map(|(node, _)| Some(node))
+            .map_err(|_| format!("Failed to decode node {}", hash))
+    }
+
+    fn retrieve_encoded_node(&self, hash: &CryptoHash) -> Result<Vec<u8>, String> {
+        match self.storage.get(self.column, hash.as_ref()) {
+            Ok(Some(bytes)) if bytes.len() >= 4 => Ok(bytes[..bytes.len() - 4].to_vec()),
+            _ => Err(format!("Node {} not found in storage", hash)),
+        }
+    }
+
+    /// Part `part_id` of `num_parts` of the state with the given root, see `StatePart`.
+    pub fn get_state_part(
+        &self,
+        root: &CryptoHash,
+        part_id: u64,
+        num_parts: u64,
+    ) -> Result<StatePart, String> {
+        let mut nodes = vec![];
+        visit_part(root, part_range(part_id, num_parts)?, |hash| {
+            let data = self.retrieve_encoded_node(hash)?;
+            nodes.push(data.clone());
+            Ok(data)
+        })?;
+        Ok(StatePart { nodes })
+    }
+
+    /// Checks that the part has exactly the nodes of part `part_id` of `num_parts` of the state
+    /// with the given root, the nodes being identified by their hashes.
+    pub fn validate_state_part(
+        root: &CryptoHash,
+        part_id: u64,
+        num_parts: u64,
+        part: &StatePart,
+    ) -> Result<(), String> {
+        let nodes: HashMap<CryptoHash, &Vec<u8>> =
+            part.nodes.iter().map(|data| (hash(data), data)).collect();
+        let mut num_visited = 0;
+        visit_part(root, part_range(part_id, num_parts)?, |hash| {
+            num_visited += 1;
+            match nodes.get(hash) {
+                Some(data) => Ok(data.to_vec()),
+                None => Err(format!("Missing node {}", hash)),
+            }
+        })?;
+        if num_visited != nodes.len() || nodes.len() != part.nodes.len() {
+            return Err("State part has extra nodes".to_string());
+        }
+        Ok(())
+    }
+
+    /// Changes that write the nodes of a validated part into storage. The nodes that the
+    /// storage already has are kept as they are, with their reference counts.
+    pub fn state_part_changes(&self, part: &StatePart) -> Result<DBChanges, String> {
+        let mut changes = HashMap::default();
+        for data in &part.nodes {
+            let key = hash(data);
+            if let Ok(Some(_)) = self.storage.get(self.column, key.as_ref()) {
+                continue;
+            }
+            let bytes = RcTrieNode::encode(data, 1).map_err(|e| e.to_string())?;
+            changes.insert(key.as_ref().to_vec(), Some(bytes));
+        }
+        Ok(changes)
+    }
+
+    /// Splits the view into the value at its prefix and the views of the 16 children.
+    fn split_view(
+        &self,
+        view: DiffView,
+        new_nodes: &DBChanges,
+    ) -> Result<(Option<Vec<u8>>, Vec<Option<DiffView>>), String> {
+        let mut children = vec![None; 16];
+        let view = match view {
+            DiffView::Node(hash) => match self.retrieve_raw_node(&hash, new_nodes)? {
+                None => return Ok((None, children)),
+                Some(RawTrieNode::Leaf(key, value)) => DiffView::Leaf(decode_nibbles(&key), value),
+                Some(RawTrieNode::Extension(key, child)) => {
+                    DiffView::Extension(decode_nibbles(&key), child)
+                }
+                Some(RawTrieNode::Branch(node_children, value)) => {
+                    for (i, child) in node_children.iter().enumerate() {
+                        children[i] = child.map(DiffView::Node);
+                    }
+                    return Ok((value, children));
+                }
+            },
+            view => view,
+        };
+        match view {
+            DiffView::Leaf(key, value) => {
+                if key.is_empty() {
+                    return Ok((Some(value), children));
+                }
+                children[key[0] as usize] = Some(DiffView::Leaf(key[1..].to_vec(), value));
+            }
+            DiffView::Extension(key, child) => {
+                if key.is_empty() {
+                    return self.split_view(DiffView::Node(child), new_nodes);
+                }
+                children[key[0] as usize] = Some(DiffView::Extension(key[1..].to_vec(), child));
+            }
+            DiffView::Node(_) => unreachable!(),
+        }
+        Ok((None, children))
+    }
+
+    fn diff_views(
+        &self,
+        old: Option<DiffView>,
+        new: Option<DiffView>,
+        new_nodes: &DBChanges,
+        prefix: &mut Vec<u8>,
+        changes: &mut Vec<KeyChange>,
+    ) -> Result<(), String> {
+        // Identical subtrees have the same hash or the same remaining key and contents.
+        if old == new {
+            return Ok(());
+        }
+        let split = |view: Option<DiffView>| match view {
+            Some(view) => self.split_view(view, new_nodes),
+            None => Ok((None, vec![None; 16])),
+        };
+        let (old_value, old_children) = split(old)?;
+        let (new_value, new_children) = split(new)?;
+        if old_value != new_value {
+            changes.push((nibbles_to_key(prefix), old_value, new_value));
+        }
+        for (i, (old, new)) in old_children.into_iter().zip(new_children).enumerate() {
+            prefix.push(i as u8);
+            self.diff_views(old, new, new_nodes, prefix, changes)?;
+            prefix.pop();
+        }
+        Ok(())
+    }
 }
 
 pub type TrieItem<'a> = Result<(Vec<u8>, DBValue), String>;
@@ -811,7 +1134,7 @@ mod tests {
         changes: TrieChanges,
     ) -> CryptoHash {
         let mut other_changes = changes.clone();
-        let (db_changes, root) = trie.update(root, other_changes.drain(..));
+        let (db_changes, root) = trie.update(root, other_changes.drain(..)).unwrap();
         apply_changes(storage, Some(0), db_changes).is_ok();
         for (key, value) in changes {
             assert_eq!(trie.get(&root, &key), value);
@@ -828,7 +1151,7 @@ mod tests {
         let delete_changes: TrieChanges =
             changes.iter().map(|(key, _)| (key.clone(), None)).collect();
         let mut other_delete_changes = delete_changes.clone();
-        let (db_changes, root) = trie.update(root, other_delete_changes.drain(..));
+        let (db_changes, root) = trie.update(root, other_delete_changes.drain(..)).unwrap();
         apply_changes(storage, Some(0), db_changes).is_ok();
         for (key, _) in delete_changes {
             assert_eq!(trie.get(&root, &key), None);
@@ -925,6 +1248,103 @@ mod tests {
         test_populate_trie(&storage, &trie, &Trie::empty_root(), changes);
     }
 
+    #[test]
+    fn test_trie_state_parts() {
+        let storage: Arc<KeyValueDB> = Arc::new(create_memory_db());
+        let trie = Trie::new(storage.clone(), Some(0));
+        let changes: TrieChanges = (0..100u8)
+            .map(|i| (vec![i.wrapping_mul(37), i, 1], Some(vec![i])))
+            .chain(vec![(b"doge".to_vec(), Some(b"coin".to_vec()))])
+            .collect();
+        let root = test_populate_trie(&storage, &trie, &Trie::empty_root(), changes.clone());
+
+        let new_storage: Arc<KeyValueDB> = Arc::new(create_memory_db());
+        let new_trie = Trie::new(new_storage.clone(), Some(0));
+        for part_id in 0..3 {
+            let part = trie.get_state_part(&root, part_id, 3).unwrap();
+            assert!(Trie::validate_state_part(&root, part_id, 3, &part).is_ok());
+            assert!(Trie::validate_state_part(&root, (part_id + 1) % 3, 3, &part).is_err());
+            let mut truncated = part.clone();
+            truncated.nodes.pop();
+            assert!(Trie::validate_state_part(&root, part_id, 3, &truncated).is_err());
+            apply_changes(&new_storage, Some(0), new_trie.state_part_changes(&part).unwrap())
+                .unwrap();
+        }
+        for (key, value) in changes {
+            assert_eq!(new_trie.get(&root, &key), value);
+        }
+        assert!(trie.get_state_part(&root, 3, 3).is_err());
+    }
+
+    #[test]
+    fn test_trie_diff() {
+        let storage: Arc<KeyValueDB> = Arc::new(create_memory_db());
+        let trie = Trie::new(storage.clone(), Some(0));
+        let initial = vec![
+            (b"do".to_vec(), Some(b"verb".to_vec())),
+            (b"dog".to_vec(), Some(b"puppy".to_vec())),
+            (b"doge".to_vec(), Some(b"coin".to_vec())),
+            (b"horse".to_vec(), Some(b"stallion".to_vec())),
+        ];
+        let root = test_populate_trie(&storage, &trie, &Trie::empty_root(), initial);
+        let changes = vec![
+            (b"dog".to_vec(), Some(b"puppy".to_vec())),
+            (b"doge".to_vec(), None),
+            (b"dot".to_vec(), Some(b"point".to_vec())),
+            (b"horse".to_vec(), Some(b"pony".to_vec())),
+        ];
+        let (db_changes, new_root) = trie.update(&root, changes.into_iter()).unwrap();
+        let diff = trie.diff(&root, &new_root, &db_changes).unwrap();
+        assert_eq!(diff, vec![
+            (b"doge".to_vec(), Some(b"coin".to_vec()), None),
+            (b"dot".to_vec(), None, Some(b"point".to_vec())),
+            (b"horse".to_vec(), Some(b"stallion".to_vec()), Some(b"pony".to_vec())),
+        ]);
+        assert!(trie.diff(&root, &root, &HashMap::new()).unwrap().is_empty());
+        let from_empty = trie.diff(&Trie::empty_root(), &root, &HashMap::new()).unwrap();
+        assert_eq!(from_empty.len(), 4);
+    }
+
+    #[test]
+    fn test_trie_recording() {
+        let storage: Arc<KeyValueDB> = Arc::new(create_memory_db());
+        let trie = Trie::new(storage.clone(), Some(0));
+        let initial = vec![
+            (b"doge".to_vec(), Some(b"coin".to_vec())),
+            (b"dog".to_vec(), Some(b"puppy".to_vec())),
+            (b"horse".to_vec(), Some(b"stallion".to_vec())),
+        ];
+        let root = test_populate_trie(&storage, &trie, &Trie::empty_root(), initial);
+        let recording = Trie::new_recording(storage.clone(), Some(0));
+        assert_eq!(recording.get(&root, b"dog"), Some(b"puppy".to_vec()));
+        let changes = vec![(b"horse".to_vec(), Some(b"pony".to_vec()))];
+        let (_, new_root) = recording.update(&root, changes.clone().into_iter()).unwrap();
+        assert!(!recording.has_missing_nodes());
+
+        // The recorded nodes are enough to repeat the reads and the update.
+        let witness = StatePart { nodes: recording.recorded_nodes() };
+        let witness_storage: Arc<KeyValueDB> = Arc::new(create_memory_db());
+        let witness_trie = Trie::new_recording(witness_storage.clone(), Some(0));
+        let witness_changes = witness_trie.state_part_changes(&witness).unwrap();
+        apply_changes(&witness_storage, Some(0), witness_changes).unwrap();
+        assert_eq!(witness_trie.get(&root, b"dog"), Some(b"puppy".to_vec()));
+        assert_eq!(witness_trie.update(&root, changes.into_iter()).unwrap().1, new_root);
+        assert!(!witness_trie.has_missing_nodes());
+        assert_eq!(witness_trie.get(&root, b"doge"), None);
+        assert!(witness_trie.has_missing_nodes());
+    }
+
+    #[test]
+    fn test_update_missing_nodes() {
+        let storage: Arc<KeyValueDB> = Arc::new(create_memory_db());
+        let trie = Trie::new(storage.clone(), Some(0));
+        let initial = vec![(b"dog".to_vec(), Some(b"puppy".to_vec()))];
+        let root = test_populate_trie(&storage, &trie, &Trie::empty_root(), initial);
+        let other_trie = Trie::new(Arc::new(create_memory_db()), Some(0));
+        let changes = vec![(b"dog".to_vec(), None)];
+        assert!(other_trie.update(&root, changes.into_iter()).is_err());
+    }
+
     #[test]
     fn test_trie_iter_seek_stop_at_extension() {
         let storage: Arc<KeyValueDB> = Arc::new(create_memory_db());
@@ -1,29 +1,159 @@
 #[macro_use]
 extern crate serde_derive;
 
+#[macro_use]
+mod versioned;
+
+use std::cmp;
+use std::convert::TryFrom;
+use std::error;
 use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::Arc;
 
 use near_protos::Message as ProtoMessage;
+use near_protos::outcome as outcome_proto;
+use near_protos::receipt as receipt_proto;
 use near_protos::signed_transaction as transaction_proto;
 use primitives::hash::{CryptoHash, hash};
+use primitives::limited::{DecodeLimits, LimitError};
+use primitives::serialize::{DecodeError, DecodeLimited, Encode};
 use primitives::signature::{DEFAULT_SIGNATURE, PublicKey, Signature, verify};
 use primitives::types::{
-    AccountId, AccountingInfo, Balance, CallbackId, Mana,
-    ManaAccounting, StructSignature, ShardId,
+    AccountId, AccountingInfo, Balance, BlockIndex, CallbackId, Mana,
+    ManaAccounting, ProtocolVersion, StructSignature, ShardId,
 };
 use primitives::utils::account_to_shard_id;
+use serde::{de, Deserialize, Deserializer};
 
 pub type LogEntry = String;
 
-#[derive(Hash, PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
-pub enum TransactionBody {
-    CreateAccount(CreateAccountTransaction),
-    DeployContract(DeployContractTransaction),
-    FunctionCall(FunctionCallTransaction),
-    SendMoney(SendMoneyTransaction),
-    Stake(StakeTransaction),
-    SwapKey(SwapKeyTransaction),
+/// Latest protocol version, the one that introduced the newest kinds of transactions and receipts.
+pub const PROTOCOL_VERSION: ProtocolVersion = 1;
+
+/// Size of the send money memo that costs one extra mana.
+const MEMO_BYTES_PER_MANA: usize = 32;
+
+/// Hash field of the proto transaction. Malformed hash is replaced with the default one,
+/// so the transaction is rejected by the runtime instead of failing to decode.
+fn hash_from_proto(bytes: Vec<u8>) -> CryptoHash {
+    if bytes.len() == 32 {
+        CryptoHash::new(&bytes)
+    } else {
+        CryptoHash::default()
+    }
+}
+
+/// Proto message that doesn't decode to a transaction or a receipt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtoError {
+    /// Field that the message can't be without is not set, e.g. the body.
+    MissingField(&'static str),
+    /// Delegate transaction wraps another delegate transaction.
+    NestedDelegate,
+    /// Message is over the limits it is decoded with.
+    Limit(LimitError),
+    /// Bytes are not an encoding of the message.
+    Invalid(String),
+}
+
+impl fmt::Display for ProtoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtoError::MissingField(field) => write!(f, "Message has no {}", field),
+            ProtoError::NestedDelegate => {
+                write!(f, "Delegate transaction cannot wrap another delegate transaction")
+            }
+            ProtoError::Limit(e) => write!(f, "{}", e),
+            ProtoError::Invalid(e) => write!(f, "Invalid message: {}", e),
+        }
+    }
+}
+
+impl error::Error for ProtoError {}
+
+impl From<DecodeError> for ProtoError {
+    fn from(e: DecodeError) -> Self {
+        match e {
+            DecodeError::Limit(e) => ProtoError::Limit(e),
+            DecodeError::Invalid(e) => ProtoError::Invalid(e),
+        }
+    }
+}
+
+versioned_enum! {
+    #[derive(Hash, PartialEq, Eq, Debug, Clone)]
+    pub enum TransactionBody {
+        CreateAccount(CreateAccountTransaction) = 0, since 1,
+        DeployContract(DeployContractTransaction) = 1, since 1,
+        FunctionCall(FunctionCallTransaction) = 2, since 1,
+        SendMoney(SendMoneyTransaction) = 3, since 1,
+        Stake(StakeTransaction) = 4, since 1,
+        SwapKey(SwapKeyTransaction) = 5, since 1,
+        Delegate(DelegateTransaction) = 6, since 1,
+        MultiSendMoney(MultiSendMoneyTransaction) = 7, since 1,
+        SignaturePolicy(SignaturePolicyTransaction) = 8, since 1,
+        SetGuardians(SetGuardiansTransaction) = 9, since 1,
+        ProposeRecovery(ProposeRecoveryTransaction) = 10, since 1,
+        CancelRecovery(CancelRecoveryTransaction) = 11, since 1,
+        SetKeyPermissions(SetKeyPermissionsTransaction) = 12, since 1,
+        CreateContractAccount(CreateContractAccountTransaction) = 13, since 1,
+        RotateKey(RotateKeyTransaction) = 14, since 1,
+    }
+}
+
+/// Kind of the transaction body, used to restrict which transactions a key may sign. The kinds
+/// are kept in the state by their index, which is the discriminant of their `TransactionBody`
+/// variant, so a new kind goes at the end.
+#[derive(Hash, Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum TransactionKind {
+    CreateAccount = 0,
+    DeployContract = 1,
+    FunctionCall = 2,
+    SendMoney = 3,
+    Stake = 4,
+    SwapKey = 5,
+    Delegate = 6,
+    MultiSendMoney = 7,
+    SignaturePolicy = 8,
+    SetGuardians = 9,
+    ProposeRecovery = 10,
+    CancelRecovery = 11,
+    SetKeyPermissions = 12,
+    CreateContractAccount = 13,
+    RotateKey = 14,
+}
+
+impl fmt::Display for TransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for TransactionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "CreateAccount" => Ok(TransactionKind::CreateAccount),
+            "DeployContract" => Ok(TransactionKind::DeployContract),
+            "FunctionCall" => Ok(TransactionKind::FunctionCall),
+            "SendMoney" => Ok(TransactionKind::SendMoney),
+            "Stake" => Ok(TransactionKind::Stake),
+            "SwapKey" => Ok(TransactionKind::SwapKey),
+            "Delegate" => Ok(TransactionKind::Delegate),
+            "MultiSendMoney" => Ok(TransactionKind::MultiSendMoney),
+            "SignaturePolicy" => Ok(TransactionKind::SignaturePolicy),
+            "SetGuardians" => Ok(TransactionKind::SetGuardians),
+            "ProposeRecovery" => Ok(TransactionKind::ProposeRecovery),
+            "CancelRecovery" => Ok(TransactionKind::CancelRecovery),
+            "SetKeyPermissions" => Ok(TransactionKind::SetKeyPermissions),
+            "CreateContractAccount" => Ok(TransactionKind::CreateContractAccount),
+            "RotateKey" => Ok(TransactionKind::RotateKey),
+            _ => Err(format!("Unknown transaction kind {}", s)),
+        }
+    }
 }
 
 #[derive(Hash, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
@@ -32,7 +162,16 @@ pub struct CreateAccountTransaction {
     pub originator: AccountId,
     pub new_account_id: AccountId,
     pub amount: u64,
+    /// Keys in the transactions are prefixed with their type, see `PublicKey::to_bytes`.
     pub public_key: Vec<u8>,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
 }
 
 impl From<transaction_proto::CreateAccountTransaction> for CreateAccountTransaction {
@@ -43,6 +182,10 @@ impl From<transaction_proto::CreateAccountTransaction> for CreateAccountTransact
             new_account_id: t.new_account_id,
             amount: t.amount,
             public_key: t.public_key,
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
         }
     }
 }
@@ -55,6 +198,10 @@ impl Into<transaction_proto::CreateAccountTransaction> for CreateAccountTransact
             new_account_id: self.new_account_id,
             amount: self.amount,
             public_key: self.public_key,
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
             unknown_fields: Default::default(),
             cached_size: Default::default(),
         }
@@ -67,7 +214,17 @@ pub struct DeployContractTransaction {
     pub originator: AccountId,
     pub contract_id: AccountId,
     pub wasm_byte_array: Vec<u8>,
+    /// Key of the contract account if the deploy creates it. The contract of an existing account
+    /// is only redeployed by the account itself, which needs no key.
     pub public_key: Vec<u8>,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
 }
 
 impl fmt::Debug for DeployContractTransaction {
@@ -84,6 +241,10 @@ impl From<transaction_proto::DeployContractTransaction> for DeployContractTransa
             contract_id: t.contract_id,
             wasm_byte_array: t.wasm_byte_array,
             public_key: t.public_key,
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
         }
     }
 }
@@ -96,6 +257,90 @@ impl Into<transaction_proto::DeployContractTransaction> for DeployContractTransa
             contract_id: self.contract_id,
             public_key: self.public_key,
             wasm_byte_array: self.wasm_byte_array,
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
+/// Creates a new account, deploys the contract to it and calls its init method in a single
+/// receipt. If any of the steps fails, the account is not created and the amount is refunded.
+#[derive(Hash, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct CreateContractAccountTransaction {
+    pub nonce: u64,
+    pub originator: AccountId,
+    pub new_account_id: AccountId,
+    pub amount: Balance,
+    pub public_key: Vec<u8>,
+    pub wasm_byte_array: Vec<u8>,
+    /// Method called right after the deploy. Not called if empty.
+    pub init_method: Vec<u8>,
+    pub init_args: Vec<u8>,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
+}
+
+impl fmt::Debug for CreateContractAccountTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CreateContractAccountTransaction {{ nonce: {}, originator: {}, new_account_id: {}, amount: {}, wasm_byte_array: ... }}",
+            self.nonce,
+            self.originator,
+            self.new_account_id,
+            self.amount,
+        )
+    }
+}
+
+impl From<transaction_proto::CreateContractAccountTransaction>
+    for CreateContractAccountTransaction
+{
+    fn from(t: transaction_proto::CreateContractAccountTransaction) -> Self {
+        CreateContractAccountTransaction {
+            nonce: t.nonce,
+            originator: t.originator,
+            new_account_id: t.new_account_id,
+            amount: t.amount,
+            public_key: t.public_key,
+            wasm_byte_array: t.wasm_byte_array,
+            init_method: t.init_method,
+            init_args: t.init_args,
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
+        }
+    }
+}
+
+impl Into<transaction_proto::CreateContractAccountTransaction>
+    for CreateContractAccountTransaction
+{
+    fn into(self) -> transaction_proto::CreateContractAccountTransaction {
+        transaction_proto::CreateContractAccountTransaction {
+            nonce: self.nonce,
+            originator: self.originator,
+            new_account_id: self.new_account_id,
+            amount: self.amount,
+            public_key: self.public_key,
+            wasm_byte_array: self.wasm_byte_array,
+            init_method: self.init_method,
+            init_args: self.init_args,
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
             unknown_fields: Default::default(),
             cached_size: Default::default(),
         }
@@ -110,6 +355,14 @@ pub struct FunctionCallTransaction {
     pub method_name: Vec<u8>,
     pub args: Vec<u8>,
     pub amount: Balance,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
 }
 
 impl From<transaction_proto::FunctionCallTransaction> for FunctionCallTransaction {
@@ -121,6 +374,10 @@ impl From<transaction_proto::FunctionCallTransaction> for FunctionCallTransactio
             method_name: t.method_name,
             args: t.args,
             amount: t.amount,
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
         }
     }
 }
@@ -134,6 +391,10 @@ impl Into<transaction_proto::FunctionCallTransaction> for FunctionCallTransactio
             method_name: self.method_name,
             args: self.args,
             amount: self.amount,
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
             unknown_fields: Default::default(),
             cached_size: Default::default(),
         }
@@ -152,6 +413,16 @@ pub struct SendMoneyTransaction {
     pub originator: AccountId,
     pub receiver: AccountId,
     pub amount: Balance,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
+    /// Data for the receiver, e.g. to tell which customer the deposit is for.
+    pub memo: Vec<u8>,
 }
 
 impl From<transaction_proto::SendMoneyTransaction> for SendMoneyTransaction {
@@ -160,7 +431,12 @@ impl From<transaction_proto::SendMoneyTransaction> for SendMoneyTransaction {
             nonce: t.nonce,
             originator: t.originator,
             receiver: t.receiver,
-            amount: 0
+            amount: t.amount,
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
+            memo: t.memo,
         }
     }
 }
@@ -172,6 +448,11 @@ impl Into<transaction_proto::SendMoneyTransaction> for SendMoneyTransaction {
             originator: self.originator,
             receiver: self.receiver,
             amount: self.amount,
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
+            memo: self.memo,
             unknown_fields: Default::default(),
             cached_size: Default::default(),
         }
@@ -183,6 +464,19 @@ pub struct StakeTransaction {
     pub nonce: u64,
     pub originator: AccountId,
     pub amount: Balance,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
+    /// Compressed BLS public key the authority signs the blocks with.
+    pub bls_public_key: Vec<u8>,
+    /// Signature of the BLS public key by its secret key, which proves that the authority owns
+    /// the key rather than deriving it from the keys of other authorities.
+    pub bls_proof_of_possession: Vec<u8>,
 }
 
 impl From<transaction_proto::StakeTransaction> for StakeTransaction {
@@ -191,6 +485,12 @@ impl From<transaction_proto::StakeTransaction> for StakeTransaction {
             nonce: t.nonce,
             originator: t.originator,
             amount: t.amount,
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
+            bls_public_key: t.bls_public_key,
+            bls_proof_of_possession: t.bls_proof_of_possession,
         }
     }
 }
@@ -201,6 +501,68 @@ impl Into<transaction_proto::StakeTransaction> for StakeTransaction {
             nonce: self.nonce,
             originator: self.originator,
             amount: self.amount,
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
+            bls_public_key: self.bls_public_key,
+            bls_proof_of_possession: self.bls_proof_of_possession,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
+/// Changes the keys of a staked authority without unstaking it. The authority signs with the new
+/// keys from the next epoch whose authorities are not announced yet.
+#[derive(Hash, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct RotateKeyTransaction {
+    pub nonce: u64,
+    pub originator: AccountId,
+    /// New public key of the authority.
+    pub public_key: Vec<u8>,
+    /// New compressed BLS public key the authority signs the blocks with.
+    pub bls_public_key: Vec<u8>,
+    /// Signature of the new BLS public key by its secret key, see `StakeTransaction`.
+    pub bls_proof_of_possession: Vec<u8>,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
+}
+
+impl From<transaction_proto::RotateKeyTransaction> for RotateKeyTransaction {
+    fn from(t: transaction_proto::RotateKeyTransaction) -> Self {
+        RotateKeyTransaction {
+            nonce: t.nonce,
+            originator: t.originator,
+            public_key: t.public_key,
+            bls_public_key: t.bls_public_key,
+            bls_proof_of_possession: t.bls_proof_of_possession,
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
+        }
+    }
+}
+
+impl Into<transaction_proto::RotateKeyTransaction> for RotateKeyTransaction {
+    fn into(self) -> transaction_proto::RotateKeyTransaction {
+        transaction_proto::RotateKeyTransaction {
+            nonce: self.nonce,
+            originator: self.originator,
+            public_key: self.public_key,
+            bls_public_key: self.bls_public_key,
+            bls_proof_of_possession: self.bls_proof_of_possession,
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
             unknown_fields: Default::default(),
             cached_size: Default::default(),
         }
@@ -215,6 +577,14 @@ pub struct SwapKeyTransaction {
     // originator must sign the transaction with this key
     pub cur_key: Vec<u8>,
     pub new_key: Vec<u8>,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
 }
 
 impl From<transaction_proto::SwapKeyTransaction> for SwapKeyTransaction {
@@ -224,6 +594,10 @@ impl From<transaction_proto::SwapKeyTransaction> for SwapKeyTransaction {
             originator: t.originator,
             cur_key: t.cur_key,
             new_key: t.new_key,
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
         }
     }
 }
@@ -235,6 +609,382 @@ impl Into<transaction_proto::SwapKeyTransaction> for SwapKeyTransaction {
             originator: self.originator,
             cur_key: self.cur_key,
             new_key: self.new_key,
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct Transfer {
+    pub receiver: AccountId,
+    pub amount: Balance,
+}
+
+impl From<transaction_proto::Transfer> for Transfer {
+    fn from(t: transaction_proto::Transfer) -> Self {
+        Transfer { receiver: t.receiver, amount: t.amount }
+    }
+}
+
+impl Into<transaction_proto::Transfer> for Transfer {
+    fn into(self) -> transaction_proto::Transfer {
+        transaction_proto::Transfer {
+            receiver: self.receiver,
+            amount: self.amount,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
+/// Replaces the originator keys and sets how many of them have to sign its transactions.
+/// Like any other transaction, it has to be signed according to the current policy.
+#[derive(Hash, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct SignaturePolicyTransaction {
+    pub nonce: u64,
+    pub originator: AccountId,
+    pub public_keys: Vec<Vec<u8>>,
+    pub threshold: u32,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
+}
+
+impl From<transaction_proto::SignaturePolicyTransaction> for SignaturePolicyTransaction {
+    fn from(t: transaction_proto::SignaturePolicyTransaction) -> Self {
+        SignaturePolicyTransaction {
+            nonce: t.nonce,
+            originator: t.originator,
+            public_keys: t.public_keys.into_vec(),
+            threshold: t.threshold,
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
+        }
+    }
+}
+
+impl Into<transaction_proto::SignaturePolicyTransaction> for SignaturePolicyTransaction {
+    fn into(self) -> transaction_proto::SignaturePolicyTransaction {
+        transaction_proto::SignaturePolicyTransaction {
+            nonce: self.nonce,
+            originator: self.originator,
+            public_keys: self.public_keys.into(),
+            threshold: self.threshold,
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
+/// Sets the accounts that can propose a key replacement for the originator.
+#[derive(Hash, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct SetGuardiansTransaction {
+    pub nonce: u64,
+    pub originator: AccountId,
+    pub guardians: Vec<AccountId>,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
+}
+
+impl From<transaction_proto::SetGuardiansTransaction> for SetGuardiansTransaction {
+    fn from(t: transaction_proto::SetGuardiansTransaction) -> Self {
+        SetGuardiansTransaction {
+            nonce: t.nonce,
+            originator: t.originator,
+            guardians: t.guardians.into_vec(),
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
+        }
+    }
+}
+
+impl Into<transaction_proto::SetGuardiansTransaction> for SetGuardiansTransaction {
+    fn into(self) -> transaction_proto::SetGuardiansTransaction {
+        transaction_proto::SetGuardiansTransaction {
+            nonce: self.nonce,
+            originator: self.originator,
+            guardians: self.guardians.into(),
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
+/// Sent by a guardian of `account_id` to replace its keys with `public_key`.
+/// The replacement is staged and happens only if the owner does not cancel it in time.
+#[derive(Hash, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct ProposeRecoveryTransaction {
+    pub nonce: u64,
+    pub originator: AccountId,
+    pub account_id: AccountId,
+    pub public_key: Vec<u8>,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
+}
+
+impl From<transaction_proto::ProposeRecoveryTransaction> for ProposeRecoveryTransaction {
+    fn from(t: transaction_proto::ProposeRecoveryTransaction) -> Self {
+        ProposeRecoveryTransaction {
+            nonce: t.nonce,
+            originator: t.originator,
+            account_id: t.account_id,
+            public_key: t.public_key,
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
+        }
+    }
+}
+
+impl Into<transaction_proto::ProposeRecoveryTransaction> for ProposeRecoveryTransaction {
+    fn into(self) -> transaction_proto::ProposeRecoveryTransaction {
+        transaction_proto::ProposeRecoveryTransaction {
+            nonce: self.nonce,
+            originator: self.originator,
+            account_id: self.account_id,
+            public_key: self.public_key,
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
+/// Cancels the recovery staged for the originator. Needs a signature of just one of its keys.
+#[derive(Hash, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct CancelRecoveryTransaction {
+    pub nonce: u64,
+    pub originator: AccountId,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
+}
+
+impl From<transaction_proto::CancelRecoveryTransaction> for CancelRecoveryTransaction {
+    fn from(t: transaction_proto::CancelRecoveryTransaction) -> Self {
+        CancelRecoveryTransaction {
+            nonce: t.nonce,
+            originator: t.originator,
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
+        }
+    }
+}
+
+impl Into<transaction_proto::CancelRecoveryTransaction> for CancelRecoveryTransaction {
+    fn into(self) -> transaction_proto::CancelRecoveryTransaction {
+        transaction_proto::CancelRecoveryTransaction {
+            nonce: self.nonce,
+            originator: self.originator,
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
+/// Restricts the transaction kinds the given key of the originator may sign.
+/// An empty list lifts the restriction.
+#[derive(Hash, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct SetKeyPermissionsTransaction {
+    pub nonce: u64,
+    pub originator: AccountId,
+    pub public_key: Vec<u8>,
+    /// Names of the allowed transaction kinds, see `TransactionKind`.
+    pub allowed: Vec<String>,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
+}
+
+impl From<transaction_proto::SetKeyPermissionsTransaction> for SetKeyPermissionsTransaction {
+    fn from(t: transaction_proto::SetKeyPermissionsTransaction) -> Self {
+        SetKeyPermissionsTransaction {
+            nonce: t.nonce,
+            originator: t.originator,
+            public_key: t.public_key,
+            allowed: t.allowed.into_vec(),
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
+        }
+    }
+}
+
+impl Into<transaction_proto::SetKeyPermissionsTransaction> for SetKeyPermissionsTransaction {
+    fn into(self) -> transaction_proto::SetKeyPermissionsTransaction {
+        transaction_proto::SetKeyPermissionsTransaction {
+            nonce: self.nonce,
+            originator: self.originator,
+            public_key: self.public_key,
+            allowed: self.allowed.into(),
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
+/// Sends money from the originator to several receivers at once.
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct MultiSendMoneyTransaction {
+    pub nonce: u64,
+    pub originator: AccountId,
+    pub transfers: Vec<Transfer>,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
+}
+
+impl From<transaction_proto::MultiSendMoneyTransaction> for MultiSendMoneyTransaction {
+    fn from(t: transaction_proto::MultiSendMoneyTransaction) -> Self {
+        MultiSendMoneyTransaction {
+            nonce: t.nonce,
+            originator: t.originator,
+            transfers: t.transfers.into_vec().into_iter().map(Transfer::from).collect(),
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
+        }
+    }
+}
+
+impl Into<transaction_proto::MultiSendMoneyTransaction> for MultiSendMoneyTransaction {
+    fn into(self) -> transaction_proto::MultiSendMoneyTransaction {
+        let transfers: Vec<transaction_proto::Transfer> =
+            self.transfers.into_iter().map(Into::into).collect();
+        transaction_proto::MultiSendMoneyTransaction {
+            nonce: self.nonce,
+            originator: self.originator,
+            transfers: transfers.into(),
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
+/// Transaction signed by a user and submitted by a relayer. The relayer pays mana and gas,
+/// while the inner transaction is executed on behalf of the user. Only the tip of the
+/// relayer is charged, the one of the inner transaction is ignored.
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct DelegateTransaction {
+    pub nonce: u64,
+    /// Relayer that submits the transaction.
+    pub originator: AccountId,
+    pub transaction: Box<SignedTransaction>,
+    /// Hash of a recent block, the transaction expires some time after it.
+    pub block_hash: CryptoHash,
+    /// Hash of the genesis block of the chain the transaction is meant for.
+    pub genesis_hash: CryptoHash,
+    /// Earliest block index the transaction is executed at. Until then it waits in the state.
+    pub execute_at: BlockIndex,
+    /// Extra amount paid to get the transaction included sooner. It is burnt.
+    pub tip: Balance,
+}
+
+impl TryFrom<transaction_proto::DelegateTransaction> for DelegateTransaction {
+    type Error = ProtoError;
+
+    /// The inner transaction is checked not to be a delegate before it is decoded, so that the
+    /// decoding never recurses.
+    fn try_from(t: transaction_proto::DelegateTransaction) -> Result<Self, Self::Error> {
+        let transaction =
+            t.transaction.into_option().ok_or(ProtoError::MissingField("transaction"))?;
+        if let Some(transaction_proto::SignedTransaction_oneof_body::delegate(_)) =
+            transaction.body
+        {
+            return Err(ProtoError::NestedDelegate);
+        }
+        Ok(DelegateTransaction {
+            nonce: t.nonce,
+            originator: t.originator,
+            transaction: Box::new(SignedTransaction::try_from(transaction)?),
+            block_hash: hash_from_proto(t.block_hash),
+            genesis_hash: hash_from_proto(t.genesis_hash),
+            execute_at: t.execute_at,
+            tip: t.tip,
+        })
+    }
+}
+
+impl Into<transaction_proto::DelegateTransaction> for DelegateTransaction {
+    fn into(self) -> transaction_proto::DelegateTransaction {
+        let transaction: transaction_proto::SignedTransaction = (*self.transaction).into();
+        transaction_proto::DelegateTransaction {
+            nonce: self.nonce,
+            originator: self.originator,
+            transaction: Some(transaction).into(),
+            block_hash: self.block_hash.as_ref().to_vec(),
+            genesis_hash: self.genesis_hash.as_ref().to_vec(),
+            execute_at: self.execute_at,
+            tip: self.tip,
             unknown_fields: Default::default(),
             cached_size: Default::default(),
         }
@@ -242,6 +992,26 @@ impl Into<transaction_proto::SwapKeyTransaction> for SwapKeyTransaction {
 }
 
 impl TransactionBody {
+    pub fn kind(&self) -> TransactionKind {
+        match self {
+            TransactionBody::CreateAccount(_) => TransactionKind::CreateAccount,
+            TransactionBody::DeployContract(_) => TransactionKind::DeployContract,
+            TransactionBody::FunctionCall(_) => TransactionKind::FunctionCall,
+            TransactionBody::SendMoney(_) => TransactionKind::SendMoney,
+            TransactionBody::Stake(_) => TransactionKind::Stake,
+            TransactionBody::SwapKey(_) => TransactionKind::SwapKey,
+            TransactionBody::Delegate(_) => TransactionKind::Delegate,
+            TransactionBody::MultiSendMoney(_) => TransactionKind::MultiSendMoney,
+            TransactionBody::SignaturePolicy(_) => TransactionKind::SignaturePolicy,
+            TransactionBody::SetGuardians(_) => TransactionKind::SetGuardians,
+            TransactionBody::ProposeRecovery(_) => TransactionKind::ProposeRecovery,
+            TransactionBody::CancelRecovery(_) => TransactionKind::CancelRecovery,
+            TransactionBody::SetKeyPermissions(_) => TransactionKind::SetKeyPermissions,
+            TransactionBody::CreateContractAccount(_) => TransactionKind::CreateContractAccount,
+            TransactionBody::RotateKey(_) => TransactionKind::RotateKey,
+        }
+    }
+
     pub fn get_nonce(&self) -> u64 {
         match self {
             TransactionBody::Stake(t) => t.nonce,
@@ -250,17 +1020,208 @@ impl TransactionBody {
             TransactionBody::FunctionCall(t) => t.nonce,
             TransactionBody::CreateAccount(t) => t.nonce,
             TransactionBody::SwapKey(t) => t.nonce,
+            TransactionBody::Delegate(t) => t.nonce,
+            TransactionBody::MultiSendMoney(t) => t.nonce,
+            TransactionBody::SignaturePolicy(t) => t.nonce,
+            TransactionBody::SetGuardians(t) => t.nonce,
+            TransactionBody::ProposeRecovery(t) => t.nonce,
+            TransactionBody::CancelRecovery(t) => t.nonce,
+            TransactionBody::SetKeyPermissions(t) => t.nonce,
+            TransactionBody::CreateContractAccount(t) => t.nonce,
+            TransactionBody::RotateKey(t) => t.nonce,
+        }
+    }
+
+    pub fn get_originator(&self) -> AccountId {
+        match self {
+            TransactionBody::Stake(t) => t.originator.clone(),
+            TransactionBody::SendMoney(t) => t.originator.clone(),
+            TransactionBody::DeployContract(t) => t.originator.clone(),
+            TransactionBody::FunctionCall(t) => t.originator.clone(),
+            TransactionBody::CreateAccount(t) => t.originator.clone(),
+            TransactionBody::SwapKey(t) => t.originator.clone(),
+            TransactionBody::Delegate(t) => t.originator.clone(),
+            TransactionBody::MultiSendMoney(t) => t.originator.clone(),
+            TransactionBody::SignaturePolicy(t) => t.originator.clone(),
+            TransactionBody::SetGuardians(t) => t.originator.clone(),
+            TransactionBody::ProposeRecovery(t) => t.originator.clone(),
+            TransactionBody::CancelRecovery(t) => t.originator.clone(),
+            TransactionBody::SetKeyPermissions(t) => t.originator.clone(),
+            TransactionBody::CreateContractAccount(t) => t.originator.clone(),
+            TransactionBody::RotateKey(t) => t.originator.clone(),
+        }
+    }
+
+    pub fn get_block_hash(&self) -> CryptoHash {
+        match self {
+            TransactionBody::Stake(t) => t.block_hash,
+            TransactionBody::SendMoney(t) => t.block_hash,
+            TransactionBody::DeployContract(t) => t.block_hash,
+            TransactionBody::FunctionCall(t) => t.block_hash,
+            TransactionBody::CreateAccount(t) => t.block_hash,
+            TransactionBody::SwapKey(t) => t.block_hash,
+            TransactionBody::Delegate(t) => t.block_hash,
+            TransactionBody::MultiSendMoney(t) => t.block_hash,
+            TransactionBody::SignaturePolicy(t) => t.block_hash,
+            TransactionBody::SetGuardians(t) => t.block_hash,
+            TransactionBody::ProposeRecovery(t) => t.block_hash,
+            TransactionBody::CancelRecovery(t) => t.block_hash,
+            TransactionBody::SetKeyPermissions(t) => t.block_hash,
+            TransactionBody::CreateContractAccount(t) => t.block_hash,
+            TransactionBody::RotateKey(t) => t.block_hash,
+        }
+    }
+
+    pub fn get_genesis_hash(&self) -> CryptoHash {
+        match self {
+            TransactionBody::Stake(t) => t.genesis_hash,
+            TransactionBody::SendMoney(t) => t.genesis_hash,
+            TransactionBody::DeployContract(t) => t.genesis_hash,
+            TransactionBody::FunctionCall(t) => t.genesis_hash,
+            TransactionBody::CreateAccount(t) => t.genesis_hash,
+            TransactionBody::SwapKey(t) => t.genesis_hash,
+            TransactionBody::Delegate(t) => t.genesis_hash,
+            TransactionBody::MultiSendMoney(t) => t.genesis_hash,
+            TransactionBody::SignaturePolicy(t) => t.genesis_hash,
+            TransactionBody::SetGuardians(t) => t.genesis_hash,
+            TransactionBody::ProposeRecovery(t) => t.genesis_hash,
+            TransactionBody::CancelRecovery(t) => t.genesis_hash,
+            TransactionBody::SetKeyPermissions(t) => t.genesis_hash,
+            TransactionBody::CreateContractAccount(t) => t.genesis_hash,
+            TransactionBody::RotateKey(t) => t.genesis_hash,
         }
     }
 
-    pub fn get_originator(&self) -> AccountId {
+    pub fn get_execute_at(&self) -> BlockIndex {
+        match self {
+            TransactionBody::Stake(t) => t.execute_at,
+            TransactionBody::SendMoney(t) => t.execute_at,
+            TransactionBody::DeployContract(t) => t.execute_at,
+            TransactionBody::FunctionCall(t) => t.execute_at,
+            TransactionBody::CreateAccount(t) => t.execute_at,
+            TransactionBody::SwapKey(t) => t.execute_at,
+            TransactionBody::Delegate(t) => t.execute_at,
+            TransactionBody::MultiSendMoney(t) => t.execute_at,
+            TransactionBody::SignaturePolicy(t) => t.execute_at,
+            TransactionBody::SetGuardians(t) => t.execute_at,
+            TransactionBody::ProposeRecovery(t) => t.execute_at,
+            TransactionBody::CancelRecovery(t) => t.execute_at,
+            TransactionBody::SetKeyPermissions(t) => t.execute_at,
+            TransactionBody::CreateContractAccount(t) => t.execute_at,
+            TransactionBody::RotateKey(t) => t.execute_at,
+        }
+    }
+
+    pub fn get_tip(&self) -> Balance {
+        match self {
+            TransactionBody::Stake(t) => t.tip,
+            TransactionBody::SendMoney(t) => t.tip,
+            TransactionBody::DeployContract(t) => t.tip,
+            TransactionBody::FunctionCall(t) => t.tip,
+            TransactionBody::CreateAccount(t) => t.tip,
+            TransactionBody::SwapKey(t) => t.tip,
+            TransactionBody::Delegate(t) => t.tip,
+            TransactionBody::MultiSendMoney(t) => t.tip,
+            TransactionBody::SignaturePolicy(t) => t.tip,
+            TransactionBody::SetGuardians(t) => t.tip,
+            TransactionBody::ProposeRecovery(t) => t.tip,
+            TransactionBody::CancelRecovery(t) => t.tip,
+            TransactionBody::SetKeyPermissions(t) => t.tip,
+            TransactionBody::CreateContractAccount(t) => t.tip,
+            TransactionBody::RotateKey(t) => t.tip,
+        }
+    }
+
+    /// Bytes that the keys of the originator sign the SHA-256 hash of. They are the proto
+    /// encoding of the message of the body in `signed_transaction.proto`, without the
+    /// `SignedTransaction` around it: the fields in the order of their numbers, each field
+    /// with its default value left out and no unknown fields. This is the encoding that the
+    /// proto libraries produce, so that the transactions can be signed in other languages.
+    pub fn signable_bytes(&self) -> Vec<u8> {
+        let bytes = match self.clone() {
+            TransactionBody::CreateAccount(t) => {
+                let proto: transaction_proto::CreateAccountTransaction = t.into();
+                proto.write_to_bytes()
+            },
+            TransactionBody::DeployContract(t) => {
+                let proto: transaction_proto::DeployContractTransaction = t.into();
+                proto.write_to_bytes()
+            },
+            TransactionBody::FunctionCall(t) => {
+                let proto: transaction_proto::FunctionCallTransaction = t.into();
+                proto.write_to_bytes()
+            },
+            TransactionBody::SendMoney(t) => {
+                let proto: transaction_proto::SendMoneyTransaction = t.into();
+                proto.write_to_bytes()
+            },
+            TransactionBody::Stake(t) => {
+                let proto: transaction_proto::StakeTransaction = t.into();
+                proto.write_to_bytes()
+            },
+            TransactionBody::SwapKey(t) => {
+                let proto: transaction_proto::SwapKeyTransaction = t.into();
+                proto.write_to_bytes()
+            },
+            TransactionBody::Delegate(t) => {
+                let proto: transaction_proto::DelegateTransaction = t.into();
+                proto.write_to_bytes()
+            },
+            TransactionBody::MultiSendMoney(t) => {
+                let proto: transaction_proto::MultiSendMoneyTransaction = t.into();
+                proto.write_to_bytes()
+            },
+            TransactionBody::SignaturePolicy(t) => {
+                let proto: transaction_proto::SignaturePolicyTransaction = t.into();
+                proto.write_to_bytes()
+            },
+            TransactionBody::SetGuardians(t) => {
+                let proto: transaction_proto::SetGuardiansTransaction = t.into();
+                proto.write_to_bytes()
+            },
+            TransactionBody::ProposeRecovery(t) => {
+                let proto: transaction_proto::ProposeRecoveryTransaction = t.into();
+                proto.write_to_bytes()
+            },
+            TransactionBody::CancelRecovery(t) => {
+                let proto: transaction_proto::CancelRecoveryTransaction = t.into();
+                proto.write_to_bytes()
+            },
+            TransactionBody::SetKeyPermissions(t) => {
+                let proto: transaction_proto::SetKeyPermissionsTransaction = t.into();
+                proto.write_to_bytes()
+            },
+            TransactionBody::CreateContractAccount(t) => {
+                let proto: transaction_proto::CreateContractAccountTransaction = t.into();
+                proto.write_to_bytes()
+            },
+            TransactionBody::RotateKey(t) => {
+                let proto: transaction_proto::RotateKeyTransaction = t.into();
+                proto.write_to_bytes()
+            },
+        };
+        bytes.expect("Transaction body should encode as a proto message")
+    }
+
+    /// Returns the amount of money the transaction sends away from the originator, without
+    /// the tip. Of the delegate transaction it is the amount of the delegated transaction.
+    pub fn get_amount(&self) -> Balance {
         match self {
-            TransactionBody::Stake(t) => t.originator.clone(),
-            TransactionBody::SendMoney(t) => t.originator.clone(),
-            TransactionBody::DeployContract(t) => t.originator.clone(),
-            TransactionBody::FunctionCall(t) => t.originator.clone(),
-            TransactionBody::CreateAccount(t) => t.originator.clone(),
-            TransactionBody::SwapKey(t) => t.originator.clone(),
+            TransactionBody::CreateAccount(t) => t.amount,
+            TransactionBody::FunctionCall(t) => t.amount,
+            TransactionBody::SendMoney(t) => t.amount,
+            TransactionBody::Delegate(t) => t.transaction.body.get_amount(),
+            TransactionBody::MultiSendMoney(t) => t.transfers.iter().map(|t| t.amount).sum(),
+            TransactionBody::CreateContractAccount(t) => t.amount,
+            TransactionBody::DeployContract(_)
+            | TransactionBody::Stake(_)
+            | TransactionBody::SwapKey(_)
+            | TransactionBody::SignaturePolicy(_)
+            | TransactionBody::SetGuardians(_)
+            | TransactionBody::ProposeRecovery(_)
+            | TransactionBody::CancelRecovery(_)
+            | TransactionBody::SetKeyPermissions(_)
+            | TransactionBody::RotateKey(_) => 0,
         }
     }
 
@@ -273,6 +1234,15 @@ impl TransactionBody {
             TransactionBody::SendMoney(t) => Some(t.receiver.clone()),
             TransactionBody::Stake(_) => None,
             TransactionBody::SwapKey(_) => None,
+            TransactionBody::Delegate(t) => t.transaction.body.get_contract_id(),
+            TransactionBody::MultiSendMoney(_) => None,
+            TransactionBody::SignaturePolicy(_) => None,
+            TransactionBody::SetGuardians(_) => None,
+            TransactionBody::ProposeRecovery(_) => None,
+            TransactionBody::CancelRecovery(_) => None,
+            TransactionBody::SetKeyPermissions(_) => None,
+            TransactionBody::CreateContractAccount(t) => Some(t.new_account_id.clone()),
+            TransactionBody::RotateKey(_) => None,
         }
     }
 
@@ -283,55 +1253,69 @@ impl TransactionBody {
             TransactionBody::DeployContract(_) => 1,
             // TODO(#344): DEFAULT_MANA_LIMIT is 20. Need to check that the value is at least 1 mana.
             TransactionBody::FunctionCall(_t) => 20,
-            TransactionBody::SendMoney(_) => 1,
+            TransactionBody::SendMoney(t) => 1 + (t.memo.len() / MEMO_BYTES_PER_MANA) as Mana,
             TransactionBody::Stake(_) => 1,
             TransactionBody::SwapKey(_) => 1,
+            TransactionBody::Delegate(t) => t.transaction.body.get_mana(),
+            TransactionBody::MultiSendMoney(t) => cmp::max(1, t.transfers.len() as Mana),
+            TransactionBody::SignaturePolicy(_) => 1,
+            TransactionBody::SetGuardians(_) => 1,
+            TransactionBody::ProposeRecovery(_) => 1,
+            TransactionBody::CancelRecovery(_) => 1,
+            TransactionBody::SetKeyPermissions(_) => 1,
+            TransactionBody::CreateContractAccount(t) => {
+                if t.init_method.is_empty() { 1 } else { 20 }
+            },
+            TransactionBody::RotateKey(_) => 1,
         }
     }
 }
 
-#[derive(Eq, Debug, Clone, Serialize, Deserialize)]
+#[derive(Eq, Debug, Clone, Serialize)]
 pub struct SignedTransaction {
     pub body: TransactionBody,
     pub signature: StructSignature,
+    /// Signatures by other keys of the originator, for accounts that require several.
+    pub extra_signatures: Vec<StructSignature>,
+    /// Hash of the signable bytes of the body, see `TransactionBody::signable_bytes`.
     hash: CryptoHash,
 }
 
+/// Serialized form of `SignedTransaction`. The hash in it is not trusted, the signatures are
+/// verified against the hash of the body.
+#[derive(Deserialize)]
+#[serde(rename = "SignedTransaction")]
+struct SerializedSignedTransaction {
+    body: TransactionBody,
+    signature: StructSignature,
+    extra_signatures: Vec<StructSignature>,
+    #[serde(rename = "hash")]
+    _hash: CryptoHash,
+}
+
+impl<'de> Deserialize<'de> for SignedTransaction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let t = SerializedSignedTransaction::deserialize(deserializer)?;
+        if let TransactionBody::Delegate(delegate) = &t.body {
+            if let TransactionBody::Delegate(_) = delegate.transaction.body {
+                return Err(de::Error::custom(ProtoError::NestedDelegate));
+            }
+        }
+        let mut transaction = SignedTransaction::new(t.signature, t.body);
+        transaction.extra_signatures = t.extra_signatures;
+        Ok(transaction)
+    }
+}
+
 impl SignedTransaction {
     pub fn new(
         signature: StructSignature,
         body: TransactionBody,
     ) -> Self {
-        let bytes = match body.clone() {
-            TransactionBody::CreateAccount(t) => {
-                let proto: transaction_proto::CreateAccountTransaction = t.into();
-                proto.write_to_bytes()
-            },
-            TransactionBody::DeployContract(t) => {
-                let proto: transaction_proto::DeployContractTransaction = t.into();
-                proto.write_to_bytes()
-            },
-            TransactionBody::FunctionCall(t) => {
-                let proto: transaction_proto::FunctionCallTransaction = t.into();
-                proto.write_to_bytes()
-            },
-            TransactionBody::SendMoney(t) => {
-                let proto: transaction_proto::SendMoneyTransaction = t.into();
-                proto.write_to_bytes()
-            },
-            TransactionBody::Stake(t) => {
-                let proto: transaction_proto::StakeTransaction = t.into();
-                proto.write_to_bytes()
-            },
-            TransactionBody::SwapKey(t) => {
-                let proto: transaction_proto::SwapKeyTransaction = t.into();
-                proto.write_to_bytes()
-            },
-        };
-        let bytes = bytes.unwrap();
-        let hash = hash(&bytes);
+        let hash = hash(&body.signable_bytes());
         Self {
             signature,
+            extra_signatures: vec![],
             body,
             hash,
         }
@@ -339,6 +1323,17 @@ impl SignedTransaction {
 
     pub fn get_hash(&self) -> CryptoHash { self.hash }
 
+    /// Bytes that the hash is of, see `TransactionBody::signable_bytes`.
+    pub fn signable_bytes(&self) -> Vec<u8> {
+        self.body.signable_bytes()
+    }
+
+    /// Size of the transaction encoded as a proto message.
+    pub fn get_size(&self) -> usize {
+        let proto: transaction_proto::SignedTransaction = self.clone().into();
+        proto.compute_size() as usize
+    }
+
     // this is for tests
     pub fn empty() -> SignedTransaction {
         let body = TransactionBody::SendMoney(SendMoneyTransaction {
@@ -346,8 +1341,50 @@ impl SignedTransaction {
             originator: AccountId::default(),
             receiver: AccountId::default(),
             amount: 0,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
         });
-        SignedTransaction { signature: DEFAULT_SIGNATURE, body, hash: CryptoHash::default()}
+        SignedTransaction {
+            signature: DEFAULT_SIGNATURE,
+            extra_signatures: vec![],
+            body,
+            hash: CryptoHash::default(),
+        }
+    }
+
+    /// Converts the proto transaction of an untrusted source, such as an RPC request. The lengths
+    /// and the nesting of the transaction are checked against the limits in the encoding it is
+    /// gossiped in, so that the peers decoding it with the same limits accept it.
+    pub fn try_from_proto_limited(
+        t: transaction_proto::SignedTransaction,
+        limits: &DecodeLimits,
+    ) -> Result<Self, ProtoError> {
+        let transaction = SignedTransaction::try_from(t)?;
+        let data = Encode::encode(&transaction).map_err(|e| ProtoError::Invalid(e.to_string()))?;
+        // The size is checked on the proto bytes, the gossiped encoding may differ.
+        let limits = DecodeLimits { max_size: usize::max_value(), ..*limits };
+        SignedTransaction::decode_limited(&data, &limits)?;
+        Ok(transaction)
+    }
+
+    /// Decodes the proto transaction of untrusted bytes, checking their size before they are
+    /// parsed, see `try_from_proto_limited`.
+    pub fn from_proto_bytes_limited(
+        bytes: &[u8],
+        limits: &DecodeLimits,
+    ) -> Result<Self, ProtoError> {
+        if bytes.len() > limits.max_size {
+            return Err(ProtoError::Limit(LimitError::TooLarge {
+                size: bytes.len(),
+                limit: limits.max_size,
+            }));
+        }
+        let mut t = transaction_proto::SignedTransaction::new();
+        t.merge_from_bytes(bytes).map_err(|e| ProtoError::Invalid(e.to_string()))?;
+        SignedTransaction::try_from_proto_limited(t, limits)
     }
 }
 
@@ -361,43 +1398,71 @@ impl PartialEq for SignedTransaction {
     }
 }
 
-impl From<transaction_proto::SignedTransaction> for SignedTransaction {
-    fn from(t: transaction_proto::SignedTransaction) -> Self {
-        let mut bytes;
+impl TryFrom<transaction_proto::SignedTransaction> for SignedTransaction {
+    type Error = ProtoError;
+
+    fn try_from(t: transaction_proto::SignedTransaction) -> Result<Self, Self::Error> {
         let body = match t.body {
             Some(transaction_proto::SignedTransaction_oneof_body::create_account(t)) => {
-                bytes = t.write_to_bytes();
                 TransactionBody::CreateAccount(CreateAccountTransaction::from(t))
             },
             Some(transaction_proto::SignedTransaction_oneof_body::deploy_contract(t)) => {
-                bytes = t.write_to_bytes();
                 TransactionBody::DeployContract(DeployContractTransaction::from(t))
             },
             Some(transaction_proto::SignedTransaction_oneof_body::function_call(t)) => {
-                bytes = t.write_to_bytes();
                 TransactionBody::FunctionCall(FunctionCallTransaction::from(t))
             },
             Some(transaction_proto::SignedTransaction_oneof_body::send_money(t)) => {
-                bytes = t.write_to_bytes();
                 TransactionBody::SendMoney(SendMoneyTransaction::from(t))
             },
             Some(transaction_proto::SignedTransaction_oneof_body::stake(t)) => {
-                bytes = t.write_to_bytes();
                 TransactionBody::Stake(StakeTransaction::from(t))
             },
             Some(transaction_proto::SignedTransaction_oneof_body::swap_key(t)) => {
-                bytes = t.write_to_bytes();
                 TransactionBody::SwapKey(SwapKeyTransaction::from(t))
             },
-            _ => unreachable!(),
+            Some(transaction_proto::SignedTransaction_oneof_body::delegate(t)) => {
+                TransactionBody::Delegate(DelegateTransaction::try_from(t)?)
+            },
+            Some(transaction_proto::SignedTransaction_oneof_body::multi_send_money(t)) => {
+                TransactionBody::MultiSendMoney(MultiSendMoneyTransaction::from(t))
+            },
+            Some(transaction_proto::SignedTransaction_oneof_body::signature_policy(t)) => {
+                TransactionBody::SignaturePolicy(SignaturePolicyTransaction::from(t))
+            },
+            Some(transaction_proto::SignedTransaction_oneof_body::set_guardians(t)) => {
+                TransactionBody::SetGuardians(SetGuardiansTransaction::from(t))
+            },
+            Some(transaction_proto::SignedTransaction_oneof_body::propose_recovery(t)) => {
+                TransactionBody::ProposeRecovery(ProposeRecoveryTransaction::from(t))
+            },
+            Some(transaction_proto::SignedTransaction_oneof_body::cancel_recovery(t)) => {
+                TransactionBody::CancelRecovery(CancelRecoveryTransaction::from(t))
+            },
+            Some(transaction_proto::SignedTransaction_oneof_body::set_key_permissions(t)) => {
+                TransactionBody::SetKeyPermissions(SetKeyPermissionsTransaction::from(t))
+            },
+            Some(transaction_proto::SignedTransaction_oneof_body::create_contract_account(t)) => {
+                TransactionBody::CreateContractAccount(CreateContractAccountTransaction::from(t))
+            },
+            Some(transaction_proto::SignedTransaction_oneof_body::rotate_key(t)) => {
+                TransactionBody::RotateKey(RotateKeyTransaction::from(t))
+            },
+            None => return Err(ProtoError::MissingField("body")),
         };
-        let bytes = bytes.unwrap();
-        let hash = hash(&bytes);
-        SignedTransaction {
+        // Unknown fields are not signed.
+        let hash = hash(&body.signable_bytes());
+        Ok(SignedTransaction {
             body,
-            signature: Signature::new(&t.signature),
+            // Signatures that don't decode don't verify either.
+            signature: Signature::from_bytes(&t.signature).unwrap_or(DEFAULT_SIGNATURE),
+            extra_signatures: t
+                .extra_signatures
+                .iter()
+                .map(|s| Signature::from_bytes(s).unwrap_or(DEFAULT_SIGNATURE))
+                .collect(),
             hash,
-        }
+        })
     }
 }
 
@@ -422,22 +1487,52 @@ impl Into<transaction_proto::SignedTransaction> for SignedTransaction {
             TransactionBody::SwapKey(t) => {
                 transaction_proto::SignedTransaction_oneof_body::swap_key(t.into())
             },
+            TransactionBody::Delegate(t) => {
+                transaction_proto::SignedTransaction_oneof_body::delegate(t.into())
+            },
+            TransactionBody::MultiSendMoney(t) => {
+                transaction_proto::SignedTransaction_oneof_body::multi_send_money(t.into())
+            },
+            TransactionBody::SignaturePolicy(t) => {
+                transaction_proto::SignedTransaction_oneof_body::signature_policy(t.into())
+            },
+            TransactionBody::SetGuardians(t) => {
+                transaction_proto::SignedTransaction_oneof_body::set_guardians(t.into())
+            },
+            TransactionBody::ProposeRecovery(t) => {
+                transaction_proto::SignedTransaction_oneof_body::propose_recovery(t.into())
+            },
+            TransactionBody::CancelRecovery(t) => {
+                transaction_proto::SignedTransaction_oneof_body::cancel_recovery(t.into())
+            },
+            TransactionBody::SetKeyPermissions(t) => {
+                transaction_proto::SignedTransaction_oneof_body::set_key_permissions(t.into())
+            },
+            TransactionBody::CreateContractAccount(t) => {
+                transaction_proto::SignedTransaction_oneof_body::create_contract_account(t.into())
+            },
+            TransactionBody::RotateKey(t) => {
+                transaction_proto::SignedTransaction_oneof_body::rotate_key(t.into())
+            },
         };
         transaction_proto::SignedTransaction {
             body: Some(body),
-            signature: self.signature.as_ref().to_vec(),
+            signature: self.signature.to_bytes(),
+            extra_signatures: self.extra_signatures.iter().map(Signature::to_bytes).collect(),
             unknown_fields: Default::default(),
             cached_size: Default::default(),
         }
     }
 }
 
-#[derive(Hash, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
-pub enum ReceiptBody {
-    NewCall(AsyncCall),
-    Callback(CallbackResult),
-    Refund(u64),
-    ManaAccounting(ManaAccounting),
+versioned_enum! {
+    #[derive(Hash, Clone, Debug, PartialEq, Eq)]
+    pub enum ReceiptBody {
+        NewCall(AsyncCall) = 0, since 1,
+        Callback(CallbackResult) = 1, since 1,
+        Refund(u64) = 2, since 1,
+        ManaAccounting(ManaAccounting) = 3, since 1,
+    }
 }
 
 #[derive(Hash, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -445,9 +1540,12 @@ pub struct AsyncCall {
     pub amount: Balance,
     pub mana: Mana,
     pub method_name: Vec<u8>,
-    pub args: Vec<u8>,
+    /// Shared, so that passing arguments along doesn't copy them.
+    pub args: Arc<[u8]>,
     pub callback: Option<CallbackInfo>,
     pub accounting_info: AccountingInfo,
+    /// Memo of the money transfer that this call deposits, empty otherwise.
+    pub memo: Vec<u8>,
 }
 
 impl AsyncCall {
@@ -462,9 +1560,10 @@ impl AsyncCall {
             amount,
             mana,
             method_name,
-            args,
+            args: Arc::from(args),
             callback: None,
             accounting_info,
+            memo: vec![],
         }
     }
 }
@@ -481,10 +1580,42 @@ impl fmt::Debug for AsyncCall {
     }
 }
 
+impl From<receipt_proto::AsyncCall> for AsyncCall {
+    fn from(call: receipt_proto::AsyncCall) -> Self {
+        AsyncCall {
+            amount: call.amount,
+            mana: call.mana,
+            method_name: call.method_name,
+            args: Arc::from(call.args),
+            callback: call.callback.into_option().map(CallbackInfo::from),
+            accounting_info: call.accounting_info.unwrap_or_default().into(),
+            memo: call.memo,
+        }
+    }
+}
+
+impl Into<receipt_proto::AsyncCall> for AsyncCall {
+    fn into(self) -> receipt_proto::AsyncCall {
+        let callback: Option<receipt_proto::CallbackInfo> = self.callback.map(Into::into);
+        let accounting_info: receipt_proto::AccountingInfo = self.accounting_info.into();
+        receipt_proto::AsyncCall {
+            amount: self.amount,
+            mana: self.mana,
+            method_name: self.method_name,
+            args: self.args.to_vec(),
+            callback: callback.into(),
+            accounting_info: Some(accounting_info).into(),
+            memo: self.memo,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Callback {
     pub method_name: Vec<u8>,
-    pub args: Vec<u8>,
+    pub args: Arc<[u8]>,
     pub results: Vec<Option<Vec<u8>>>,
     pub mana: Mana,
     pub callback: Option<CallbackInfo>,
@@ -496,7 +1627,7 @@ impl Callback {
     pub fn new(method_name: Vec<u8>, args: Vec<u8>, mana: Mana, accounting_info: AccountingInfo) -> Self {
         Callback {
             method_name,
-            args,
+            args: Arc::from(args),
             results: vec![],
             mana,
             callback: None,
@@ -534,6 +1665,28 @@ impl CallbackInfo {
     }
 }
 
+impl From<receipt_proto::CallbackInfo> for CallbackInfo {
+    fn from(info: receipt_proto::CallbackInfo) -> Self {
+        CallbackInfo {
+            id: info.id,
+            result_index: info.result_index as usize,
+            receiver: info.receiver,
+        }
+    }
+}
+
+impl Into<receipt_proto::CallbackInfo> for CallbackInfo {
+    fn into(self) -> receipt_proto::CallbackInfo {
+        receipt_proto::CallbackInfo {
+            id: self.id,
+            result_index: self.result_index as u64,
+            receiver: self.receiver,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
 #[derive(Hash, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct CallbackResult {
     // callback id
@@ -548,6 +1701,32 @@ impl CallbackResult {
     }
 }
 
+impl From<receipt_proto::CallbackResult> for CallbackResult {
+    fn from(result: receipt_proto::CallbackResult) -> Self {
+        CallbackResult {
+            info: result.info.unwrap_or_default().into(),
+            result: match result.optional_result {
+                Some(receipt_proto::CallbackResult_oneof_optional_result::result(r)) => Some(r),
+                None => None,
+            },
+        }
+    }
+}
+
+impl Into<receipt_proto::CallbackResult> for CallbackResult {
+    fn into(self) -> receipt_proto::CallbackResult {
+        let info: receipt_proto::CallbackInfo = self.info.into();
+        receipt_proto::CallbackResult {
+            info: Some(info).into(),
+            optional_result: self
+                .result
+                .map(receipt_proto::CallbackResult_oneof_optional_result::result),
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
 #[derive(Hash, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct ReceiptTransaction {
     // sender is the immediate predecessor
@@ -578,6 +1757,61 @@ impl ReceiptTransaction {
     }
 }
 
+impl TryFrom<receipt_proto::ReceiptTransaction> for ReceiptTransaction {
+    type Error = ProtoError;
+
+    fn try_from(r: receipt_proto::ReceiptTransaction) -> Result<Self, Self::Error> {
+        let body = match r.body {
+            Some(receipt_proto::ReceiptTransaction_oneof_body::new_call(call)) => {
+                ReceiptBody::NewCall(call.into())
+            }
+            Some(receipt_proto::ReceiptTransaction_oneof_body::callback(result)) => {
+                ReceiptBody::Callback(result.into())
+            }
+            Some(receipt_proto::ReceiptTransaction_oneof_body::refund(amount)) => {
+                ReceiptBody::Refund(amount)
+            }
+            Some(receipt_proto::ReceiptTransaction_oneof_body::mana_accounting(accounting)) => {
+                ReceiptBody::ManaAccounting(accounting.into())
+            }
+            None => return Err(ProtoError::MissingField("body")),
+        };
+        Ok(ReceiptTransaction {
+            originator: r.originator,
+            receiver: r.receiver,
+            nonce: hash_from_proto(r.nonce),
+            body,
+        })
+    }
+}
+
+impl Into<receipt_proto::ReceiptTransaction> for ReceiptTransaction {
+    fn into(self) -> receipt_proto::ReceiptTransaction {
+        let body = match self.body {
+            ReceiptBody::NewCall(call) => {
+                receipt_proto::ReceiptTransaction_oneof_body::new_call(call.into())
+            }
+            ReceiptBody::Callback(result) => {
+                receipt_proto::ReceiptTransaction_oneof_body::callback(result.into())
+            }
+            ReceiptBody::Refund(amount) => {
+                receipt_proto::ReceiptTransaction_oneof_body::refund(amount)
+            }
+            ReceiptBody::ManaAccounting(accounting) => {
+                receipt_proto::ReceiptTransaction_oneof_body::mana_accounting(accounting.into())
+            }
+        };
+        receipt_proto::ReceiptTransaction {
+            originator: self.originator,
+            receiver: self.receiver,
+            nonce: self.nonce.as_ref().to_vec(),
+            body: Some(body),
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
 #[derive(Hash, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum TransactionStatus {
     Unknown,
@@ -599,6 +1833,321 @@ impl Default for TransactionStatus {
     }
 }
 
+impl From<outcome_proto::TransactionStatus> for TransactionStatus {
+    fn from(status: outcome_proto::TransactionStatus) -> Self {
+        match status {
+            outcome_proto::TransactionStatus::UNKNOWN => TransactionStatus::Unknown,
+            outcome_proto::TransactionStatus::COMPLETED => TransactionStatus::Completed,
+            outcome_proto::TransactionStatus::FAILED => TransactionStatus::Failed,
+        }
+    }
+}
+
+impl Into<outcome_proto::TransactionStatus> for TransactionStatus {
+    fn into(self) -> outcome_proto::TransactionStatus {
+        match self {
+            TransactionStatus::Unknown => outcome_proto::TransactionStatus::UNKNOWN,
+            TransactionStatus::Completed => outcome_proto::TransactionStatus::COMPLETED,
+            TransactionStatus::Failed => outcome_proto::TransactionStatus::FAILED,
+        }
+    }
+}
+
+/// Reason why a signed transaction was rejected before any of its actions were executed.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum InvalidTxError {
+    /// Originator account id does not match the requirements.
+    InvalidOriginator(AccountId),
+    /// Contract account id does not match the requirements.
+    InvalidContractId(AccountId),
+    /// Originator account does not exist.
+    OriginatorDoesNotExist(AccountId),
+    /// Transaction nonce is not allowed by the nonce policy given the originator account nonce.
+    InvalidNonce { tx_nonce: u64, account_nonce: u64 },
+    /// Originator does not have enough mana to pay for the transaction.
+    NotEnoughMana { originator: AccountId, mana: Mana },
+    /// Transaction references an unknown block or a block older than the validity period.
+    Expired(CryptoHash),
+    /// Encoded transaction is larger than allowed.
+    TransactionTooLarge { size: usize, limit: usize },
+    /// Arguments of the function call are larger than allowed.
+    ArgsTooLarge { size: usize, limit: usize },
+    /// Memo of the send money transaction is longer than allowed.
+    MemoTooLong { length: usize, limit: usize },
+    /// Transaction was signed for a chain with a different genesis.
+    WrongChain { expected: CryptoHash, found: CryptoHash },
+    /// Transaction is scheduled for a later block than the runtime keeps transactions for.
+    ScheduledTooFar { execute_at: BlockIndex, limit: BlockIndex },
+    /// Transaction is signed by fewer originator keys than the account requires.
+    NotEnoughSignatures { originator: AccountId, signers: usize, threshold: u32 },
+    /// None of the keys that signed the transaction may sign transactions of its kind.
+    KeyNotAllowed { originator: AccountId, kind: TransactionKind },
+    /// Transaction is not signed with any of the originator public keys.
+    InvalidSignature(AccountId),
+    /// Kind of the transaction is introduced by a later protocol version than the chain runs.
+    UnsupportedKind { kind: TransactionKind, protocol_version: ProtocolVersion },
+}
+
+impl fmt::Display for InvalidTxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidTxError::InvalidOriginator(account_id) =>
+                write!(f, "Invalid originator account_id {}", account_id),
+            InvalidTxError::InvalidContractId(account_id) =>
+                write!(f, "Invalid contract_id {}", account_id),
+            InvalidTxError::OriginatorDoesNotExist(account_id) =>
+                write!(f, "sender {} does not exist", account_id),
+            InvalidTxError::InvalidNonce { tx_nonce, account_nonce } => write!(
+                f,
+                "Transaction nonce {} is not allowed for sender nonce {}",
+                tx_nonce,
+                account_nonce,
+            ),
+            InvalidTxError::NotEnoughMana { originator, mana } =>
+                write!(f, "sender {} does not have enough mana {}", originator, mana),
+            InvalidTxError::Expired(block_hash) =>
+                write!(f, "Transaction references expired or unknown block {:?}", block_hash),
+            InvalidTxError::TransactionTooLarge { size, limit } =>
+                write!(f, "Transaction has {} bytes, at most {} are allowed", size, limit),
+            InvalidTxError::ArgsTooLarge { size, limit } =>
+                write!(f, "Arguments have {} bytes, at most {} are allowed", size, limit),
+            InvalidTxError::MemoTooLong { length, limit } =>
+                write!(f, "Memo has {} bytes, at most {} are allowed", length, limit),
+            InvalidTxError::ScheduledTooFar { execute_at, limit } => write!(
+                f,
+                "Transaction is scheduled for block {}, the latest allowed is {}",
+                execute_at,
+                limit,
+            ),
+            InvalidTxError::NotEnoughSignatures { originator, signers, threshold } => write!(
+                f,
+                "Transaction of {} is signed by {} keys, but {} are required",
+                originator,
+                signers,
+                threshold,
+            ),
+            InvalidTxError::KeyNotAllowed { originator, kind } => write!(
+                f,
+                "Keys of {} that signed the transaction may not sign {} transactions",
+                originator,
+                kind,
+            ),
+            InvalidTxError::WrongChain { expected, found } => write!(
+                f,
+                "Transaction is signed for genesis {:?}, but the chain genesis is {:?}",
+                found,
+                expected,
+            ),
+            InvalidTxError::InvalidSignature(account_id) => write!(
+                f,
+                "Transaction is not signed with a public key of the originator {}",
+                account_id,
+            ),
+            InvalidTxError::UnsupportedKind { kind, protocol_version } => write!(
+                f,
+                "{} transactions are introduced by protocol version {}",
+                kind,
+                protocol_version,
+            ),
+        }
+    }
+}
+
+/// Reason why an action of a transaction or a receipt failed.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum ActionError {
+    /// Sending 0 amount of money.
+    ZeroTransfer,
+    /// Account does not have enough balance to send, stake, call or create an account with.
+    InsufficientBalance { account_id: AccountId, amount: Balance, balance: Balance, staked: Balance },
+    /// Account has already staked.
+    AlreadyStaked(AccountId),
+    /// Account is not staked, so it has no authority keys to rotate.
+    NotStaked(AccountId),
+    /// Proof of possession doesn't prove that the account owns the staked BLS public key.
+    InvalidProofOfPossession(AccountId),
+    /// Account id of the account to create does not match the requirements.
+    InvalidNewAccountId(AccountId),
+    /// Public key could not be decoded.
+    InvalidPublicKey,
+    /// Account does not have the given public key.
+    PublicKeyNotFound { account_id: AccountId, public_key: PublicKey },
+    /// Account already has the public key that is being added.
+    PublicKeyAlreadyExists { account_id: AccountId, public_key: PublicKey },
+    /// Contract of an existing account can only be redeployed by the account itself.
+    DeployNotAllowed { originator: AccountId, account_id: AccountId },
+    /// Multi send money transaction has more transfers than allowed.
+    TooManyTransfers { count: usize, limit: usize },
+    /// Delegate transaction wraps another delegate transaction.
+    NestedDelegate,
+    /// Inner transaction of a delegate transaction is scheduled, only the relayer's one can be.
+    ScheduledDelegatedTransaction { execute_at: BlockIndex },
+    /// Inner transaction of a delegate transaction pays a tip, only the relayer's one can.
+    DelegatedTransactionTip { tip: Balance },
+    /// Signature threshold must be between one and the number of the new account keys.
+    InvalidThreshold { account_id: AccountId, threshold: u32, num_keys: usize },
+    /// Guardian account id does not match requirements.
+    InvalidGuardian(AccountId),
+    /// Recovery was proposed by an account that is not a guardian of the recovered account.
+    NotGuardian { guardian: AccountId, account_id: AccountId },
+    /// Account sets more guardians than allowed.
+    TooManyGuardians { count: usize, limit: usize },
+    /// Account does not have a staged recovery to cancel.
+    RecoveryNotFound(AccountId),
+    /// Key permission names a transaction kind that does not exist.
+    UnknownTransactionKind(String),
+    /// Arguments of a system call could not be encoded or decoded.
+    InvalidSystemCallArgs,
+    /// Receiver of the receipt does not exist.
+    ReceiverDoesNotExist(AccountId),
+    /// Receipt was routed to a shard that does not own its receiver.
+    WrongShard,
+    /// Account does not have contract code.
+    ContractCodeNotFound(AccountId),
+    /// Callback with the given id does not exist.
+    CallbackNotFound(CallbackId),
+    /// Contract could not be prepared for execution.
+    WasmPreparation(String),
+    /// Contract execution failed.
+    WasmExecution(String),
+    /// Contract returned a promise, which already has a callback attached.
+    ReceiptAlreadyHasCallback,
+    /// Contract returned a promise, which is not a callback or a receipt.
+    NonCallbackPromise,
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ActionError::ZeroTransfer => write!(f, "Sending 0 amount of money"),
+            ActionError::InsufficientBalance { account_id, amount, balance, staked } => write!(
+                f,
+                "Account {} tries to use {}, but has staked {} and only has {}",
+                account_id,
+                amount,
+                staked,
+                balance,
+            ),
+            ActionError::AlreadyStaked(account_id) =>
+                write!(f, "Account {} already staked", account_id),
+            ActionError::NotStaked(account_id) =>
+                write!(f, "Account {} is not staked", account_id),
+            ActionError::InvalidProofOfPossession(account_id) =>
+                write!(f, "Account {} does not prove it owns the BLS public key", account_id),
+            ActionError::InvalidNewAccountId(account_id) =>
+                write!(f, "Account {} does not match requirements", account_id),
+            ActionError::InvalidPublicKey => write!(f, "cannot decode public key"),
+            ActionError::PublicKeyNotFound { account_id, public_key } =>
+                write!(f, "Account {} does not have public key {}", account_id, public_key),
+            ActionError::PublicKeyAlreadyExists { account_id, public_key } =>
+                write!(f, "Account {} already has public key {}", account_id, public_key),
+            ActionError::DeployNotAllowed { originator, account_id } => write!(
+                f,
+                "Account {} is not allowed to deploy a contract to account {}",
+                originator,
+                account_id,
+            ),
+            ActionError::TooManyTransfers { count, limit } =>
+                write!(f, "Transaction has {} transfers, at most {} are allowed", count, limit),
+            ActionError::NestedDelegate =>
+                write!(f, "Delegate transaction cannot wrap another delegate transaction"),
+            ActionError::ScheduledDelegatedTransaction { execute_at } => write!(
+                f,
+                "Delegated transaction cannot be scheduled at block {}, only the delegate one",
+                execute_at,
+            ),
+            ActionError::DelegatedTransactionTip { tip } => write!(
+                f,
+                "Delegated transaction cannot pay a tip of {}, only the delegate one",
+                tip,
+            ),
+            ActionError::InvalidThreshold { account_id, threshold, num_keys } => write!(
+                f,
+                "Account {} cannot require {} signatures, it has {} keys",
+                account_id,
+                threshold,
+                num_keys,
+            ),
+            ActionError::InvalidGuardian(account_id) =>
+                write!(f, "Guardian {} does not match requirements", account_id),
+            ActionError::NotGuardian { guardian, account_id } =>
+                write!(f, "Account {} is not a guardian of account {}", guardian, account_id),
+            ActionError::TooManyGuardians { count, limit } =>
+                write!(f, "Account sets {} guardians, at most {} are allowed", count, limit),
+            ActionError::RecoveryNotFound(account_id) =>
+                write!(f, "Account {} does not have a recovery to cancel", account_id),
+            ActionError::UnknownTransactionKind(kind) =>
+                write!(f, "Unknown transaction kind {}", kind),
+            ActionError::InvalidSystemCallArgs => write!(f, "cannot decode args"),
+            ActionError::ReceiverDoesNotExist(account_id) =>
+                write!(f, "receiver {} does not exist", account_id),
+            ActionError::WrongShard => write!(f, "receipt sent to the wrong shard"),
+            ActionError::ContractCodeNotFound(account_id) =>
+                write!(f, "cannot find contract code for account {}", account_id),
+            ActionError::CallbackNotFound(id) => write!(f, "callback id: {:?} not found", id),
+            ActionError::WasmPreparation(e) =>
+                write!(f, "wasm preparation failed with error: {}", e),
+            ActionError::WasmExecution(e) =>
+                write!(f, "wasm execution failed with error: {}", e),
+            ActionError::ReceiptAlreadyHasCallback => write!(f, "receipt already has callback"),
+            ActionError::NonCallbackPromise => write!(f, "return data is a non-callback promise"),
+        }
+    }
+}
+
+/// Error of applying a transaction or a receipt.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum RuntimeError {
+    InvalidTx(InvalidTxError),
+    Action(ActionError),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::InvalidTx(e) => write!(f, "{}", e),
+            RuntimeError::Action(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<InvalidTxError> for RuntimeError {
+    fn from(e: InvalidTxError) -> Self {
+        RuntimeError::InvalidTx(e)
+    }
+}
+
+impl From<ActionError> for RuntimeError {
+    fn from(e: ActionError) -> Self {
+        RuntimeError::Action(e)
+    }
+}
+
+/// Why the balance of an account changed. Runtime doesn't mint rewards or charge rent yet, so
+/// these are the only causes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceChangeCause {
+    /// Money sent to or received from another account, including the deposits of calls.
+    Transfer,
+    /// Tip burnt for the transaction.
+    Fee,
+    /// Money locked for staking or released from it.
+    Stake,
+    /// Deposit returned because it couldn't be delivered.
+    Refund,
+}
+
+/// Change of the spendable balance of an account.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct BalanceChange {
+    pub account_id: AccountId,
+    pub cause: BalanceChangeCause,
+    pub amount: Balance,
+    /// Whether the amount was added to the balance, otherwise it was taken from it.
+    pub credit: bool,
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default)]
 pub struct TransactionResult {
     /// Transaction status.
@@ -606,7 +2155,33 @@ pub struct TransactionResult {
     /// Logs from this transaction.
     pub logs: Vec<LogEntry>,
     /// Receipt ids generated by this transaction.
-    pub receipts: Vec<CryptoHash>
+    pub receipts: Vec<CryptoHash>,
+    /// Error, if the transaction or receipt failed.
+    pub error: Option<RuntimeError>,
+    /// Memo of the money transfer, if the transaction or the deposit receipt has one.
+    pub memo: Option<Vec<u8>>,
+    /// Tip burnt for the transaction.
+    pub tip: Balance,
+    /// Changes of the balances caused by the transaction or receipt, in the order of accounts.
+    pub balance_changes: Vec<BalanceChange>,
+}
+
+/// The error is sent as its description, so the result can't be decoded back.
+impl Into<outcome_proto::TransactionResult> for TransactionResult {
+    fn into(self) -> outcome_proto::TransactionResult {
+        outcome_proto::TransactionResult {
+            status: self.status.into(),
+            logs: self.logs.into(),
+            receipts: self.receipts.iter().map(|r| r.as_ref().to_vec()).collect(),
+            error: self.error.map(|e| e.to_string()).unwrap_or_default(),
+            optional_memo: self
+                .memo
+                .map(outcome_proto::TransactionResult_oneof_optional_memo::memo),
+            tip: self.tip,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
 }
 
 /// Logs for transaction or receipt with given hash.
@@ -617,6 +2192,14 @@ pub struct TransactionLogs {
     pub receipts: Vec<CryptoHash>
 }
 
+/// Cause of the failure of a transaction or one of its receipts.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TransactionFailure {
+    /// Hash of the transaction or id of the receipt that failed.
+    pub hash: CryptoHash,
+    pub error: RuntimeError,
+}
+
 /// Result of transaction and all of subsequent the receipts.
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct FinalTransactionResult {
@@ -624,6 +2207,8 @@ pub struct FinalTransactionResult {
     pub status: FinalTransactionStatus,
     /// Logs per transaction / receipt ids ordered in DFS manner.
     pub logs: Vec<TransactionLogs>,
+    /// Why the transaction failed, if it did.
+    pub failure: Option<TransactionFailure>,
 }
 
 pub fn verify_transaction_signature(
@@ -637,12 +2222,395 @@ pub fn verify_transaction_signature(
     })
 }
 
+/// The given public keys that signed the transaction with either its main or one
+/// of its extra signatures.
+pub fn transaction_signers(
+    transaction: &SignedTransaction,
+    public_keys: &[PublicKey],
+) -> Vec<PublicKey> {
+    let hash = transaction.get_hash();
+    let hash = hash.as_ref();
+    public_keys.iter().filter(|key| {
+        std::iter::once(&transaction.signature)
+            .chain(transaction.extra_signatures.iter())
+            .any(|signature| verify(&hash, signature, key))
+    }).cloned().collect()
+}
+
+/// Number of the given public keys that signed the transaction.
+pub fn count_transaction_signers(
+    transaction: &SignedTransaction,
+    public_keys: &[PublicKey],
+) -> usize {
+    transaction_signers(transaction, public_keys).len()
+}
+
 #[cfg(test)]
 mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use primitives::borsh;
+    use primitives::serialize::Decode;
     use primitives::signature::{get_key_pair, sign};
 
     use super::*;
 
+    /// A body of every kind of transaction, in the order of their discriminants, with fields
+    /// that are not empty, so that each field is in the encodings.
+    fn sample_transaction_bodies() -> Vec<TransactionBody> {
+        let originator = "alice.near".to_string();
+        let block_hash = hash(b"block");
+        let genesis_hash = hash(b"genesis");
+        let public_key = vec![0, 1, 2, 3];
+        let new_key = vec![0, 4, 5, 6];
+        let wasm = b"\0asm".to_vec();
+        let send_money = TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce: 1,
+            originator: originator.clone(),
+            receiver: "bob.near".to_string(),
+            amount: 10,
+            block_hash,
+            genesis_hash,
+            execute_at: 5,
+            tip: 2,
+            memo: b"memo".to_vec(),
+        });
+        vec![
+            TransactionBody::CreateAccount(CreateAccountTransaction {
+                nonce: 1,
+                originator: originator.clone(),
+                new_account_id: "bob.near".to_string(),
+                amount: 10,
+                public_key: public_key.clone(),
+                block_hash,
+                genesis_hash,
+                execute_at: 5,
+                tip: 2,
+            }),
+            TransactionBody::DeployContract(DeployContractTransaction {
+                nonce: 1,
+                originator: originator.clone(),
+                contract_id: originator.clone(),
+                wasm_byte_array: wasm.clone(),
+                public_key: public_key.clone(),
+                block_hash,
+                genesis_hash,
+                execute_at: 5,
+                tip: 2,
+            }),
+            TransactionBody::FunctionCall(FunctionCallTransaction {
+                nonce: 1,
+                originator: originator.clone(),
+                contract_id: "bob.near".to_string(),
+                method_name: b"run".to_vec(),
+                args: vec![1, 2],
+                amount: 10,
+                block_hash,
+                genesis_hash,
+                execute_at: 5,
+                tip: 2,
+            }),
+            send_money.clone(),
+            TransactionBody::Stake(StakeTransaction {
+                nonce: 1,
+                originator: originator.clone(),
+                amount: 10,
+                block_hash,
+                genesis_hash,
+                execute_at: 5,
+                tip: 2,
+                bls_public_key: vec![4, 5],
+                bls_proof_of_possession: vec![6, 7],
+            }),
+            TransactionBody::SwapKey(SwapKeyTransaction {
+                nonce: 1,
+                originator: originator.clone(),
+                cur_key: public_key.clone(),
+                new_key: new_key.clone(),
+                block_hash,
+                genesis_hash,
+                execute_at: 5,
+                tip: 2,
+            }),
+            TransactionBody::Delegate(DelegateTransaction {
+                nonce: 1,
+                originator: "relayer.near".to_string(),
+                transaction: Box::new(SignedTransaction::new(DEFAULT_SIGNATURE, send_money)),
+                block_hash,
+                genesis_hash,
+                execute_at: 5,
+                tip: 2,
+            }),
+            TransactionBody::MultiSendMoney(MultiSendMoneyTransaction {
+                nonce: 1,
+                originator: originator.clone(),
+                transfers: vec![
+                    Transfer { receiver: "bob.near".to_string(), amount: 10 },
+                    Transfer { receiver: "carol.near".to_string(), amount: 20 },
+                ],
+                block_hash,
+                genesis_hash,
+                execute_at: 5,
+                tip: 2,
+            }),
+            TransactionBody::SignaturePolicy(SignaturePolicyTransaction {
+                nonce: 1,
+                originator: originator.clone(),
+                public_keys: vec![public_key.clone(), new_key.clone()],
+                threshold: 2,
+                block_hash,
+                genesis_hash,
+                execute_at: 5,
+                tip: 2,
+            }),
+            TransactionBody::SetGuardians(SetGuardiansTransaction {
+                nonce: 1,
+                originator: originator.clone(),
+                guardians: vec!["bob.near".to_string(), "carol.near".to_string()],
+                block_hash,
+                genesis_hash,
+                execute_at: 5,
+                tip: 2,
+            }),
+            TransactionBody::ProposeRecovery(ProposeRecoveryTransaction {
+                nonce: 1,
+                originator: "bob.near".to_string(),
+                account_id: originator.clone(),
+                public_key: new_key.clone(),
+                block_hash,
+                genesis_hash,
+                execute_at: 5,
+                tip: 2,
+            }),
+            TransactionBody::CancelRecovery(CancelRecoveryTransaction {
+                nonce: 1,
+                originator: originator.clone(),
+                block_hash,
+                genesis_hash,
+                execute_at: 5,
+                tip: 2,
+            }),
+            TransactionBody::SetKeyPermissions(SetKeyPermissionsTransaction {
+                nonce: 1,
+                originator: originator.clone(),
+                public_key: public_key.clone(),
+                allowed: vec!["SendMoney".to_string(), "Stake".to_string()],
+                block_hash,
+                genesis_hash,
+                execute_at: 5,
+                tip: 2,
+            }),
+            TransactionBody::CreateContractAccount(CreateContractAccountTransaction {
+                nonce: 1,
+                originator: originator.clone(),
+                new_account_id: "bob.near".to_string(),
+                amount: 10,
+                public_key: public_key.clone(),
+                wasm_byte_array: wasm,
+                init_method: b"init".to_vec(),
+                init_args: vec![1, 2],
+                block_hash,
+                genesis_hash,
+                execute_at: 5,
+                tip: 2,
+            }),
+            TransactionBody::RotateKey(RotateKeyTransaction {
+                nonce: 1,
+                originator,
+                public_key: new_key,
+                bls_public_key: vec![4, 5],
+                bls_proof_of_possession: vec![6, 7],
+                block_hash,
+                genesis_hash,
+                execute_at: 5,
+                tip: 2,
+            }),
+        ]
+    }
+
+    /// A receipt with every kind of body, in the order of their discriminants, named by the kind.
+    fn sample_receipts() -> Vec<(&'static str, ReceiptTransaction)> {
+        let accounting_info =
+            AccountingInfo { originator: "alice.near".to_string(), contract_id: None };
+        let mut call =
+            AsyncCall::new(b"run".to_vec(), vec![1, 2], 10, 5, accounting_info.clone());
+        call.callback = Some(CallbackInfo::new(vec![3], 1, "bob.near".to_string()));
+        let bodies = vec![
+            ("NewCall", ReceiptBody::NewCall(call)),
+            (
+                "Callback",
+                ReceiptBody::Callback(CallbackResult::new(
+                    CallbackInfo::new(vec![4], 0, "alice.near".to_string()),
+                    Some(vec![6]),
+                )),
+            ),
+            ("Refund", ReceiptBody::Refund(7)),
+            (
+                "ManaAccounting",
+                ReceiptBody::ManaAccounting(ManaAccounting {
+                    accounting_info: AccountingInfo {
+                        contract_id: Some("contract.near".to_string()),
+                        ..accounting_info
+                    },
+                    mana_refund: 2,
+                    gas_used: 100,
+                }),
+            ),
+        ];
+        bodies
+            .into_iter()
+            .map(|(name, body)| {
+                let receipt = ReceiptTransaction::new(
+                    "alice.near".to_string(),
+                    "bob.near".to_string(),
+                    hash(b"nonce"),
+                    body,
+                );
+                (name, receipt)
+            })
+            .collect()
+    }
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Lines of `res/encodings.txt`: the bytes that are signed and the borsh bytes that are
+    /// hashed, of each sample transaction and receipt.
+    fn encodings() -> Vec<String> {
+        let mut lines = vec![];
+        for body in sample_transaction_bodies() {
+            let kind = body.kind();
+            let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, body);
+            lines.push(format!("{} signable {}", kind, to_hex(&transaction.signable_bytes())));
+            lines.push(format!("{} borsh {}", kind, to_hex(&borsh::to_vec(&transaction).unwrap())));
+        }
+        for (name, receipt) in sample_receipts() {
+            lines.push(format!("{} borsh {}", name, to_hex(&borsh::to_vec(&receipt).unwrap())));
+        }
+        lines
+    }
+
+    #[test]
+    fn test_encodings_are_unchanged() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res/encodings.txt");
+        let encodings = encodings();
+        if std::env::var("NEAR_UPDATE_ENCODINGS").is_ok() {
+            fs::write(&path, encodings.join("\n") + "\n").unwrap();
+        }
+        let stored = fs::read_to_string(&path).unwrap();
+        let stored: Vec<_> = stored.lines().collect();
+        assert_eq!(
+            encodings,
+            stored,
+            "Bytes that are signed or hashed changed, which invalidates the existing transactions \
+             and blocks. Encode new fields and kinds with a new discriminant or protocol version, \
+             and only then update res/encodings.txt with NEAR_UPDATE_ENCODINGS=1"
+        );
+    }
+
+    #[test]
+    fn test_discriminants() {
+        let bodies = sample_transaction_bodies();
+        let discriminants: Vec<_> = bodies.iter().map(TransactionBody::discriminant).collect();
+        assert_eq!(discriminants, (0..15).collect::<Vec<u32>>());
+        for body in &bodies {
+            let kind = body.kind();
+            assert_eq!(kind as u32, body.discriminant());
+            assert_eq!(borsh::to_vec(&kind).unwrap(), vec![kind as u8]);
+            assert_eq!(kind.to_string().parse::<TransactionKind>(), Ok(kind));
+            assert_eq!(borsh::to_vec(body).unwrap()[0], body.discriminant() as u8);
+            assert_eq!(body.encode().unwrap()[..4], body.discriminant().to_le_bytes());
+        }
+        let receipts = sample_receipts();
+        let discriminants: Vec<_> = receipts.iter().map(|(_, r)| r.body.discriminant()).collect();
+        assert_eq!(discriminants, (0..4).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_transaction_roundtrip() {
+        for body in sample_transaction_bodies() {
+            let mut transaction = SignedTransaction::new(DEFAULT_SIGNATURE, body);
+            transaction.extra_signatures = vec![DEFAULT_SIGNATURE];
+
+            let proto: transaction_proto::SignedTransaction = transaction.clone().into();
+            let bytes = proto.write_to_bytes().unwrap();
+            let mut decoded = transaction_proto::SignedTransaction::new();
+            decoded.merge_from_bytes(&bytes).unwrap();
+            let decoded = SignedTransaction::try_from(decoded).unwrap();
+            assert_eq!(decoded.body, transaction.body);
+            assert_eq!(decoded.extra_signatures, transaction.extra_signatures);
+            assert_eq!(decoded.get_hash(), transaction.get_hash());
+
+            let decoded: SignedTransaction =
+                borsh::from_slice(&borsh::to_vec(&transaction).unwrap()).unwrap();
+            assert_eq!(decoded.body, transaction.body);
+            assert_eq!(decoded.extra_signatures, transaction.extra_signatures);
+
+            let decoded = SignedTransaction::decode(&transaction.encode().unwrap()).unwrap();
+            assert_eq!(decoded.body, transaction.body);
+            assert_eq!(decoded.extra_signatures, transaction.extra_signatures);
+        }
+    }
+
+    #[test]
+    fn test_proto_limits() {
+        let mut transaction = SignedTransaction::empty();
+        transaction.extra_signatures = vec![DEFAULT_SIGNATURE; 100];
+        let proto: transaction_proto::SignedTransaction = transaction.clone().into();
+        let bytes = proto.write_to_bytes().unwrap();
+        let limits = DecodeLimits { max_size: bytes.len(), max_len: 100, max_depth: 64 };
+        let decoded = SignedTransaction::from_proto_bytes_limited(&bytes, &limits).unwrap();
+        assert_eq!(decoded.extra_signatures, transaction.extra_signatures);
+
+        let small = DecodeLimits { max_size: bytes.len() - 1, ..limits };
+        let error = LimitError::TooLarge { size: bytes.len(), limit: bytes.len() - 1 };
+        let result = SignedTransaction::from_proto_bytes_limited(&bytes, &small);
+        assert_eq!(result, Err(ProtoError::Limit(error)));
+        let short = DecodeLimits { max_len: 99, ..limits };
+        let error = LimitError::TooLong { len: 100, limit: 99 };
+        let result = SignedTransaction::try_from_proto_limited(proto, &short);
+        assert_eq!(result, Err(ProtoError::Limit(error)));
+        let result = SignedTransaction::from_proto_bytes_limited(&[0xff], &limits);
+        assert!(match result {
+            Err(ProtoError::Invalid(_)) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_invalid_proto_transaction() {
+        let proto = transaction_proto::SignedTransaction::new();
+        assert_eq!(SignedTransaction::try_from(proto), Err(ProtoError::MissingField("body")));
+
+        let delegate = sample_transaction_bodies()
+            .into_iter()
+            .find(|body| body.kind() == TransactionKind::Delegate)
+            .unwrap();
+        let inner = SignedTransaction::new(DEFAULT_SIGNATURE, delegate.clone());
+        let nested = match delegate {
+            TransactionBody::Delegate(t) => {
+                TransactionBody::Delegate(DelegateTransaction { transaction: Box::new(inner), ..t })
+            }
+            _ => unreachable!(),
+        };
+        let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, nested);
+        let proto: transaction_proto::SignedTransaction = transaction.clone().into();
+        assert_eq!(SignedTransaction::try_from(proto), Err(ProtoError::NestedDelegate));
+        assert!(SignedTransaction::decode(&transaction.encode().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_receipt_roundtrip() {
+        for (_, receipt) in sample_receipts() {
+            let decoded: ReceiptTransaction =
+                borsh::from_slice(&borsh::to_vec(&receipt).unwrap()).unwrap();
+            assert_eq!(decoded, receipt);
+            assert_eq!(ReceiptTransaction::decode(&receipt.encode().unwrap()).unwrap(), receipt);
+        }
+    }
+
     #[test]
     fn test_verify_transaction() {
         let (public_key, private_key) = get_key_pair();
@@ -658,4 +2626,104 @@ mod tests {
         let invalid_keys = vec![wrong_public_key];
         assert!(!verify_transaction_signature(&transaction, &invalid_keys));
     }
+
+    #[test]
+    fn test_signable_bytes() {
+        let body = TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce: 1,
+            originator: "alice".to_string(),
+            receiver: "bob".to_string(),
+            amount: 10,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
+        });
+        let mut expected = vec![0x08, 1, 0x12, 5];
+        expected.extend_from_slice(b"alice");
+        expected.extend_from_slice(&[0x1a, 3]);
+        expected.extend_from_slice(b"bob");
+        expected.extend_from_slice(&[0x20, 10, 0x2a, 32]);
+        expected.extend_from_slice(&[0; 32]);
+        expected.extend_from_slice(&[0x32, 32]);
+        expected.extend_from_slice(&[0; 32]);
+        assert_eq!(body.signable_bytes(), expected);
+
+        let (_, secret_key) = get_key_pair();
+        let signature = sign(hash(&expected).as_ref(), &secret_key);
+        let transaction = SignedTransaction::new(signature, body);
+        assert_eq!(transaction.get_hash(), hash(&transaction.signable_bytes()));
+        let proto: transaction_proto::SignedTransaction = transaction.clone().into();
+        assert_eq!(SignedTransaction::try_from(proto).unwrap().get_hash(), transaction.get_hash());
+
+        // The hash is computed again rather than taken from the serialized transaction.
+        let mut forged = transaction.clone();
+        forged.hash = CryptoHash::default();
+        let decoded = SignedTransaction::decode(&forged.encode().unwrap()).unwrap();
+        assert_eq!(decoded.get_hash(), transaction.get_hash());
+    }
+
+    #[test]
+    fn test_count_transaction_signers() {
+        let keys: Vec<_> = (0..3).map(|_| get_key_pair()).collect();
+        let public_keys: Vec<_> = keys.iter().map(|(public_key, _)| *public_key).collect();
+        let mut transaction = SignedTransaction::empty();
+        transaction.signature = sign(&transaction.hash.as_ref(), &keys[0].1);
+        assert_eq!(count_transaction_signers(&transaction, &public_keys), 1);
+
+        // Repeated signatures of the same key are counted once.
+        transaction.extra_signatures = vec![
+            sign(&transaction.hash.as_ref(), &keys[2].1),
+            sign(&transaction.hash.as_ref(), &keys[2].1),
+        ];
+        assert_eq!(count_transaction_signers(&transaction, &public_keys), 2);
+        assert_eq!(count_transaction_signers(&transaction, &public_keys[1..2]), 0);
+    }
+
+    #[test]
+    fn test_receipt_proto_roundtrip() {
+        let accounting_info =
+            AccountingInfo { originator: "alice.near".to_string(), contract_id: None };
+        let mut call =
+            AsyncCall::new(b"run".to_vec(), vec![1, 2], 10, 5, accounting_info.clone());
+        call.callback = Some(CallbackInfo::new(vec![3], 1, "bob.near".to_string()));
+        let bodies = vec![
+            ReceiptBody::NewCall(call),
+            ReceiptBody::Callback(CallbackResult::new(
+                CallbackInfo::new(vec![4], 0, "alice.near".to_string()),
+                Some(vec![]),
+            )),
+            ReceiptBody::Callback(CallbackResult::new(
+                CallbackInfo::new(vec![5], 2, "alice.near".to_string()),
+                None,
+            )),
+            ReceiptBody::Refund(7),
+            ReceiptBody::ManaAccounting(ManaAccounting {
+                accounting_info: AccountingInfo {
+                    contract_id: Some("contract.near".to_string()),
+                    ..accounting_info
+                },
+                mana_refund: 2,
+                gas_used: 100,
+            }),
+        ];
+        for body in bodies {
+            let receipt = ReceiptTransaction::new(
+                "alice.near".to_string(),
+                "bob.near".to_string(),
+                hash(b"nonce"),
+                body,
+            );
+            let proto: receipt_proto::ReceiptTransaction = receipt.clone().into();
+            let bytes = proto.write_to_bytes().unwrap();
+            let mut decoded = receipt_proto::ReceiptTransaction::new();
+            decoded.merge_from_bytes(&bytes).unwrap();
+            assert_eq!(ReceiptTransaction::try_from(decoded), Ok(receipt));
+        }
+        assert_eq!(
+            ReceiptTransaction::try_from(receipt_proto::ReceiptTransaction::new()),
+            Err(ProtoError::MissingField("body"))
+        );
+    }
 }
@@ -0,0 +1,167 @@
+//! Enums of the transaction and receipt bodies, whose variants are encoded with explicit
+//! discriminants instead of their position. A new kind of transaction or receipt takes a new
+//! discriminant and the protocol version that introduces it, so the encoding of the other kinds
+//! and of the blocks that have them doesn't change. Retired discriminants are never reused.
+use std::fmt;
+
+use serde::de::{self, DeserializeSeed, Deserializer, Visitor};
+
+/// Declares an enum of newtype variants, each with its discriminant and the protocol version
+/// that introduced it, with `discriminant` and `protocol_version` methods and the serde impls
+/// that encode the discriminant. Text formats name the variant, as the derived impls do.
+macro_rules! versioned_enum {
+    (
+        $(#[$attr:meta])*
+        pub enum $name:ident {
+            $($variant:ident($body:ty) = $discriminant:expr, since $version:expr,)*
+        }
+    ) => {
+        $(#[$attr])*
+        pub enum $name {
+            $($variant($body),)*
+        }
+
+        impl $name {
+            /// Discriminant the variant is encoded with.
+            pub fn discriminant(&self) -> u32 {
+                match self {
+                    $($name::$variant(_) => $discriminant,)*
+                }
+            }
+
+            /// Protocol version that introduced the variant.
+            pub fn protocol_version(&self) -> primitives::types::ProtocolVersion {
+                match self {
+                    $($name::$variant(_) => $version,)*
+                }
+            }
+        }
+
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                match self {
+                    $($name::$variant(body) => serializer.serialize_newtype_variant(
+                        stringify!($name),
+                        $discriminant,
+                        stringify!($variant),
+                        body,
+                    ),)*
+                }
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                const VARIANTS: &[(&str, u32)] = &[$((stringify!($variant), $discriminant),)*];
+                const NAMES: &[&str] = &[$(stringify!($variant),)*];
+
+                struct EnumVisitor;
+
+                impl<'de> serde::de::Visitor<'de> for EnumVisitor {
+                    type Value = $name;
+
+                    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        write!(f, "enum {}", stringify!($name))
+                    }
+
+                    fn visit_enum<A: serde::de::EnumAccess<'de>>(
+                        self,
+                        data: A,
+                    ) -> Result<$name, A::Error> {
+                        use serde::de::VariantAccess;
+                        let seed = $crate::versioned::DiscriminantSeed(VARIANTS);
+                        let (discriminant, variant) = data.variant_seed(seed)?;
+                        $(if discriminant == $discriminant {
+                            return variant.newtype_variant().map($name::$variant);
+                        })*
+                        Err(serde::de::Error::custom(
+                            $crate::versioned::UnknownDiscriminant(stringify!($name), discriminant),
+                        ))
+                    }
+                }
+
+                deserializer.deserialize_enum(stringify!($name), NAMES, EnumVisitor)
+            }
+        }
+    };
+}
+
+/// Error of a discriminant that no variant has, which may be of a later protocol version.
+pub struct UnknownDiscriminant(pub &'static str, pub u32);
+
+impl fmt::Display for UnknownDiscriminant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Unknown discriminant {} of {}, is it of a later protocol version?",
+            self.1, self.0
+        )
+    }
+}
+
+/// Reads the discriminant of the variant, given as a number by the binary formats and as the name
+/// of the variant by the text ones.
+pub struct DiscriminantSeed(pub &'static [(&'static str, u32)]);
+
+impl<'de> DeserializeSeed<'de> for DiscriminantSeed {
+    type Value = u32;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<u32, D::Error> {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl<'de> Visitor<'de> for DiscriminantSeed {
+    type Value = u32;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "variant discriminant or name")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<u32, E> {
+        if v > u64::from(u32::max_value()) {
+            return Err(E::custom(format!("Discriminant {} doesn't fit in u32", v)));
+        }
+        Ok(v as u32)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<u32, E> {
+        self.0
+            .iter()
+            .find(|(name, _)| *name == v)
+            .map(|(_, discriminant)| *discriminant)
+            .ok_or_else(|| E::unknown_variant(v, &[]))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<u32, E> {
+        match std::str::from_utf8(v) {
+            Ok(v) => self.visit_str(v),
+            Err(_) => Err(E::custom("Variant name is not UTF-8")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use primitives::borsh;
+
+    versioned_enum! {
+        #[derive(Debug, PartialEq)]
+        pub enum Body {
+            Call(u64) = 0, since 1,
+            Refund(u8) = 2, since 2,
+        }
+    }
+
+    #[test]
+    fn test_versioned_enum() {
+        let refund = Body::Refund(7);
+        assert_eq!(refund.discriminant(), 2);
+        assert_eq!(refund.protocol_version(), 2);
+        assert_eq!(borsh::to_vec(&refund).unwrap(), vec![2, 7]);
+        assert_eq!(borsh::from_slice::<Body>(&[2, 7]).unwrap(), refund);
+        assert_eq!(borsh::from_slice::<Body>(&[0, 1, 0, 0, 0, 0, 0, 0, 0]).unwrap(), Body::Call(1));
+        // Discriminant 1 is retired, or of a later version.
+        assert!(borsh::from_slice::<Body>(&[1, 7]).is_err());
+    }
+}
@@ -78,6 +78,8 @@ pub enum Error {
     PromiseAlreadyHasCallback,
     TrieIteratorError,
     TrieIteratorMissing,
+    ArgumentsTooLarge,
+    ReceiptTooLarge,
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
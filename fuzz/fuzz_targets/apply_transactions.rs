@@ -0,0 +1,135 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+
+use node_runtime::state_viewer::StateDbViewer;
+use node_runtime::test_utils::{
+    generate_test_chain_spec, get_runtime_and_state_db_viewer_from_chain_spec,
+    signed_transaction, to_receipt_block,
+};
+use node_runtime::ApplyState;
+use primitives::hash::CryptoHash;
+use primitives::test_utils::get_key_pair_from_seed;
+use primitives::types::MerkleHash;
+use transaction::{
+    CreateAccountTransaction, FunctionCallTransaction, ReceiptTransaction, SendMoneyTransaction,
+    SignedTransaction, TransactionBody,
+};
+
+/// Accounts the transactions are between. The first two are in the genesis, with the test
+/// contract deployed to them, the others can be created.
+const ACCOUNTS: [&str; 4] = ["alice.near", "bob.near", "carol.near", "dave.near"];
+/// Methods of the test contract that change its state, move money or call other contracts.
+const METHODS: [&str; 4] = [
+    "run_test_with_storage_change",
+    "transfer_to_bob",
+    "create_promises_and_join",
+    "log_something",
+];
+/// Blocks applied at most after the last one of the input, for the receipts left in flight.
+const MAX_RECEIPT_BLOCKS: usize = 10;
+
+/// Blocks of transactions, one from every 4 bytes of the input: kind, originator, receiver and
+/// amount. A kind that is not a transaction ends the block. Transactions are signed with the
+/// keys of their originators and have increasing nonces, so that they are valid, but they can
+/// still fail, e.g. for lack of money.
+fn blocks(data: &[u8]) -> Vec<Vec<SignedTransaction>> {
+    let mut blocks = vec![vec![]];
+    for (nonce, op) in (1..).zip(data.chunks(4).filter(|op| op.len() == 4)) {
+        let originator = ACCOUNTS[op[1] as usize % ACCOUNTS.len()].to_string();
+        let receiver = ACCOUNTS[op[2] as usize % ACCOUNTS.len()].to_string();
+        let amount = u64::from(op[3]);
+        let tip = amount % 4;
+        let body = match op[0] % 4 {
+            0 => TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce,
+                originator,
+                receiver,
+                amount,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip,
+                memo: vec![],
+            }),
+            1 => TransactionBody::CreateAccount(CreateAccountTransaction {
+                nonce,
+                originator,
+                public_key: get_key_pair_from_seed(&receiver).0.to_bytes(),
+                new_account_id: receiver,
+                amount,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip,
+            }),
+            2 => TransactionBody::FunctionCall(FunctionCallTransaction {
+                nonce,
+                originator,
+                contract_id: receiver,
+                method_name: METHODS[op[3] as usize % METHODS.len()].as_bytes().to_vec(),
+                args: vec![],
+                amount,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip,
+            }),
+            _ => {
+                blocks.push(vec![]);
+                continue;
+            }
+        };
+        blocks.last_mut().unwrap().push(signed_transaction(body));
+    }
+    blocks
+}
+
+/// Sum of the balances and the stakes of the accounts, in which an underflow shows up.
+fn total_balance(viewer: &StateDbViewer, root: MerkleHash) -> u128 {
+    ACCOUNTS
+        .iter()
+        .filter_map(|account_id| viewer.view_account(root, &account_id.to_string()).ok())
+        .map(|account| u128::from(account.amount) + u128::from(account.stake))
+        .sum()
+}
+
+/// Applies the blocks on a new genesis state, and then the receipts left in flight, checking
+/// that no money is created. Returns the roots of the blocks.
+fn apply_blocks(blocks: &[Vec<SignedTransaction>]) -> Vec<MerkleHash> {
+    let (chain_spec, _) = generate_test_chain_spec();
+    let (mut runtime, viewer, mut root) =
+        get_runtime_and_state_db_viewer_from_chain_spec(&chain_spec);
+    runtime.config.check_balance_conservation = true;
+    let genesis_balance = total_balance(&viewer, root);
+    let mut receipts: Vec<ReceiptTransaction> = vec![];
+    let mut roots = vec![];
+    let empty_blocks = vec![vec![]; MAX_RECEIPT_BLOCKS];
+    for (index, transactions) in blocks.iter().chain(empty_blocks.iter()).enumerate() {
+        if index >= blocks.len() && receipts.is_empty() {
+            break;
+        }
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            block_index: index as u64 + 1,
+            parent_block_hash: CryptoHash::default(),
+        };
+        let result =
+            runtime.apply(&apply_state, &[to_receipt_block(receipts)], transactions).unwrap();
+        runtime.state_db.commit(result.db_changes).unwrap();
+        root = result.root;
+        receipts = result.new_receipts.into_iter().flat_map(|(_, receipts)| receipts).collect();
+        // Money is only moved, burnt or carried by the receipts.
+        let balance = total_balance(&viewer, root);
+        assert!(balance <= genesis_balance, "{} after block {}", balance, index + 1);
+        roots.push(root);
+    }
+    roots
+}
+
+fuzz_target!(|data: &[u8]| {
+    let blocks = blocks(data);
+    // The same blocks give the same roots on another state.
+    assert_eq!(apply_blocks(&blocks), apply_blocks(&blocks));
+});
@@ -0,0 +1,11 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+
+use network::message::Message;
+use network::protocol::MESSAGE_LIMITS;
+use primitives::serialize::DecodeLimited;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Message::decode_limited(data, &MESSAGE_LIMITS);
+});
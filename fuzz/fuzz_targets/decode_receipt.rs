@@ -0,0 +1,18 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+
+use primitives::borsh;
+use primitives::limited::DecodeLimits;
+use primitives::serialize::DecodeLimited;
+use transaction::ReceiptTransaction;
+
+fuzz_target!(|data: &[u8]| {
+    let limits = DecodeLimits::default();
+    let _ = ReceiptTransaction::decode_limited(data, &limits);
+    // Receipts are also kept in the state and hashed, encoded with borsh.
+    if let Ok(receipt) = borsh::from_slice_limited::<ReceiptTransaction>(data, &limits) {
+        let encoded = borsh::to_vec(&receipt).unwrap();
+        assert_eq!(borsh::from_slice_limited(&encoded, &limits), Ok(receipt));
+    }
+});
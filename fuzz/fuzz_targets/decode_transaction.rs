@@ -0,0 +1,16 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+
+use primitives::limited::DecodeLimits;
+use primitives::serialize::{DecodeLimited, Encode};
+use transaction::SignedTransaction;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(transaction) = SignedTransaction::decode_limited(data, &DecodeLimits::default()) {
+        // What is decoded is encoded again, and decodes to the same transaction.
+        let encoded = transaction.encode().unwrap();
+        let decoded = SignedTransaction::decode_limited(&encoded, &DecodeLimits::default());
+        assert_eq!(decoded.unwrap(), transaction);
+    }
+});
@@ -20,12 +20,15 @@ class Account {
      */
     async createAccount (newAccountId, publicKey, amount, originator) {
         const nonce = await this.nearClient.getNonce(originator);
+        const { genesisHash, blockHash } = await this.nearClient.getChainHashes();
         publicKey = bs58.decode(publicKey);
         const createAccount = CreateAccountTransaction.create({
             originator,
             newAccountId,
             amount,
             publicKey,
+            genesisHash,
+            blockHash,
         });
         // Integers with value of 0 must be omitted
         // https://github.com/dcodeIO/protobuf.js/issues/1138
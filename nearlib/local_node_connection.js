@@ -8,6 +8,10 @@ class LocalNodeConnection {
     async request(methodName, params) {
         return await sendJson('POST', `${this.baseUrl}/${methodName}`, params);
     }
+
+    async get(path) {
+        return await sendJson('GET', `${this.baseUrl}/${path}`);
+    }
 }
 
 module.exports = LocalNodeConnection;
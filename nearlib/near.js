@@ -72,11 +72,14 @@ class Near {
         methodName = new Uint8Array(Buffer.from(methodName));
         args = new Uint8Array(Buffer.from(JSON.stringify(args)));
         const nonce = await this.nearClient.getNonce(originator);
+        const { genesisHash, blockHash } = await this.nearClient.getChainHashes();
         const functionCall = FunctionCallTransaction.create({
             originator,
             contractId,
             methodName,
             args,
+            genesisHash,
+            blockHash,
         });
         // Integers with value of 0 must be omitted
         // https://github.com/dcodeIO/protobuf.js/issues/1138
@@ -105,19 +108,23 @@ class Near {
      * @param {string} sender account id of the sender
      * @param {string} contractAccountId account id of the contract
      * @param {Uint8Array} wasmArray wasm binary
+     * @param {string} publicKey key of the contract account if the deploy creates it. The contract
+     * of an existing account is only redeployed by the account itself, without a key.
      */
-    async deployContract(originator, contractId, wasmByteArray) {
+    async deployContract(originator, contractId, wasmByteArray, publicKey) {
         const nonce = await this.nearClient.getNonce(originator);
+        const { genesisHash, blockHash } = await this.nearClient.getChainHashes();
 
-        // This parameter is not working properly yet. Use some fake value
-        var publicKey = '9AhWenZ3JddamBoyMqnTbp7yVbRuvqAv3zwfrWgfVRJE';
-        publicKey = bs58.decode(publicKey);
         const deployContract = DeployContractTransaction.create({
             originator,
             contractId,
             wasmByteArray,
-            publicKey,
+            genesisHash,
+            blockHash,
         });
+        if (publicKey) {
+            deployContract.publicKey = bs58.decode(publicKey);
+        }
         // Integers with value of 0 must be omitted
         // https://github.com/dcodeIO/protobuf.js/issues/1138
         if (nonce !== 0) {
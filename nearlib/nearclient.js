@@ -1,3 +1,5 @@
+const bs58 = require('bs58');
+
 const { SignedTransaction } = require('./protos');
 
 /**
@@ -39,6 +41,18 @@ class NearClient {
         return (await this.viewAccount(account_id)).nonce + 1;
     }
 
+    /**
+     * Hashes that the transactions are signed with: the genesis hash of the chain and the hash
+     * of its latest block, the transactions expire some blocks after the block they reference.
+     */
+    async getChainHashes () {
+        const status = await this.nearConnection.get('status');
+        return {
+            genesisHash: bs58.decode(status.genesis_hash),
+            blockHash: bs58.decode(status.sync_info.latest_block_hash),
+        };
+    }
+
     async request (methodName, params) {
         return await this.nearConnection.request(methodName, params);
     }
@@ -74,8 +74,9 @@ describe('with deployed contract', () => {
     beforeAll(async () => {
         // See README.md for details about this contract source code location.
         const data = [...fs.readFileSync('../tests/hello.wasm')];
-        await nearjs.waitForTransactionResult(
-            await nearjs.deployContract(aliceAccountName, contractName, data));
+        const contractKey = await KeyPair.fromRandomSeed();
+        await nearjs.waitForTransactionResult(await nearjs.deployContract(
+            aliceAccountName, contractName, data, contractKey.getPublicKey()));
         contract = await nearjs.loadContract(contractName, {
             sender: aliceAccountName,
             viewMethods: ['getAllKeys'],
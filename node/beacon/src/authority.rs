@@ -7,7 +7,7 @@ use std::mem;
 
 use chain::SignedBlock;
 use primitives::hash::CryptoHash;
-use primitives::types::{AuthorityMask, BlockId, AuthorityStake};
+use primitives::types::{AccountId, AuthorityMask, BlockId, AuthorityStake};
 use configs::AuthorityConfig;
 use crate::types::{BeaconBlockChainStorage, SignedBeaconBlockHeader};
 
@@ -45,15 +45,22 @@ fn find_threshold(stakes: &[u64], num_seats: u64) -> Result<u64, String> {
 /// Those authorities that submitted proposals in epoch E-2 and those that participated in epoch E-2
 /// are used in authority selection for epoch E. For each authority the stake used in selection is
 /// computed as: <amount staked in E-2> - <amount not used in E-2> + <proposed amount in E-2>.
+/// A proposal without stake rotates the keys of an authority instead. It applies from the next
+/// epoch whose authorities are not committed to yet, that is from epoch E+1 if it is made in
+/// epoch E, or from E+2 if it is made in the last block of E, which commits to those of E+1.
 pub struct Authority {
     /// Authority configuration.
     authority_config: AuthorityConfig,
     /// Proposals per slot in which they occur.
     proposals: HashMap<Slot, Vec<AuthorityStake>>,
+    /// Key rotations per slot in which they occur.
+    key_rotations: HashMap<Slot, Vec<AuthorityStake>>,
     /// Participation of authorities per slot in which they have happened.
     participation: HashMap<Slot, AuthorityMask>,
     /// Records the blocks that it processed for the given blocks.
     processed_blocks: HashMap<Epoch, HashSet<Slot>>,
+    /// Authorities slashed per slot in which they have been slashed.
+    slashed: HashMap<Slot, Vec<AccountId>>,
 
     // The following is a derived information which we do not want to recompute.
     /// Computed thresholds for each epoch.
@@ -78,6 +85,10 @@ impl Authority {
             ..=(epoch + 1) * self.authority_config.epoch_length  // Without ..= it needs + 1.
     }
 
+    pub fn epoch_length(&self) -> u64 {
+        self.authority_config.epoch_length
+    }
+
     /// Initializes authorities from the config and the past blocks in the beaconchain.
     pub fn new(
         authority_config: AuthorityConfig,
@@ -87,8 +98,10 @@ impl Authority {
         let mut result = Self {
             authority_config,
             proposals: HashMap::new(),
+            key_rotations: HashMap::new(),
             participation: HashMap::new(),
             processed_blocks: HashMap::new(),
+            slashed: HashMap::new(),
             thresholds: HashMap::new(),
             accepted_authorities: HashMap::new(),
         };
@@ -181,6 +194,7 @@ impl Authority {
             curr.push(AuthorityStake {
                 account_id: proposal.account_id,
                 public_key: proposal.public_key,
+                bls_public_key: proposal.bls_public_key,
                 amount: threshold,
             });
             if curr.len() == self.authority_config.num_seats_per_slot as usize {
@@ -226,8 +240,19 @@ impl Authority {
                     }
                 }
             }
+            // Slashed authorities are not selected again, by the slashes up to epoch-2.
+            let last_slot = (epoch - 1) * self.authority_config.epoch_length;
+            let slashed: HashSet<AccountId> = self
+                .slashed
+                .iter()
+                .filter(|(slot, _)| **slot <= last_slot)
+                .flat_map(|(_, accounts)| accounts.iter().cloned())
+                .collect();
             // Apply penalties.
             let rollovers: Vec<_> = ordered_rollovers.drain(..).filter(|r| {
+                if slashed.contains(&r.account_id) {
+                    return false;
+                }
                 if let Some(p) = penalties.get(&r.account_id) {
                     if *p > r.amount {
                         return false;
@@ -241,7 +266,8 @@ impl Authority {
             let (mut accepted_authorities, new_threshold) = {
                 let proposals = self
                     .epoch_to_slots(epoch - 2)
-                    .flat_map(|s| self.proposals[&s].iter().cloned());
+                    .flat_map(|s| self.proposals[&s].iter().cloned())
+                    .filter(|p| !slashed.contains(&p.account_id));
                 self.compute_threshold_accepted(&CryptoHash::default(), proposals.collect(), rollovers)
             };
             self.thresholds.insert(epoch, new_threshold);
@@ -251,13 +277,64 @@ impl Authority {
         }
     }
 
-    /// Record proposals and participation from the given block.
+    /// First epoch that the key rotations of the slot apply to.
+    fn rotation_epoch(&self, slot: Slot) -> Epoch {
+        let epoch = self.slot_to_epoch(slot);
+        if slot % self.authority_config.epoch_length == 0 {
+            epoch + 2
+        } else {
+            epoch + 1
+        }
+    }
+
+    /// Sets the keys of the accepted authorities of the epoch to the last ones they proposed,
+    /// with a stake or a key rotation, that apply to the epoch.
+    fn rotate_keys(&mut self, epoch: Epoch) {
+        let mut announced: Vec<(Slot, &AuthorityStake)> = vec![];
+        for (slot, proposals) in self.proposals.iter() {
+            if self.slot_to_epoch(*slot) + 2 <= epoch {
+                announced.extend(proposals.iter().map(|p| (*slot, p)));
+            }
+        }
+        for (slot, rotations) in self.key_rotations.iter() {
+            if self.rotation_epoch(*slot) <= epoch {
+                announced.extend(rotations.iter().map(|r| (*slot, r)));
+            }
+        }
+        // Stable sort, so that the rotations override the proposals of the same slot.
+        announced.sort_by_key(|(slot, _)| *slot);
+        let mut keys = HashMap::new();
+        for (_, p) in announced {
+            keys.insert(p.account_id.clone(), (p.public_key, p.bls_public_key.clone()));
+        }
+        for slot in self.epoch_to_slots(epoch) {
+            if let Some(authorities) = self.accepted_authorities.get_mut(&slot) {
+                for authority in authorities.iter_mut() {
+                    if let Some((public_key, bls_public_key)) = keys.get(&authority.account_id) {
+                        authority.public_key = *public_key;
+                        authority.bls_public_key = bls_public_key.clone();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Record proposals, key rotations and participation from the given block.
     pub fn process_block_header(&mut self, header: &SignedBeaconBlockHeader) {
         // Skip genesis block or if this block was already recorded.
         let slot = header.body.index;
         if slot > 0 && !self.proposals.contains_key(&slot) {
-            self.proposals.insert(slot, header.body.authority_proposal.to_vec());
+            let (rotations, proposals): (Vec<_>, Vec<_>) =
+                header.body.authority_proposal.iter().cloned().partition(|p| p.amount == 0);
+            self.proposals.insert(slot, proposals);
+            let has_rotations = !rotations.is_empty();
+            if has_rotations {
+                self.key_rotations.insert(slot, rotations);
+            }
             self.participation.insert(slot, header.signature.authority_mask.to_vec());
+            if !header.body.slashed.is_empty() {
+                self.slashed.insert(slot, header.body.slashed.to_vec());
+            }
 
             // Update the tracker of processed slots.
             let epoch = self.slot_to_epoch(slot);
@@ -271,6 +348,12 @@ impl Authority {
             if all_slots_processed {
                 // Compute accepted authorities for epoch+2.
                 self.compute_accepted_authorities(epoch + 2);
+                self.rotate_keys(epoch + 2);
+            }
+            if has_rotations {
+                for epoch in self.rotation_epoch(slot)..=epoch + 2 {
+                    self.rotate_keys(epoch);
+                }
             }
         }
     }
@@ -295,6 +378,19 @@ impl Authority {
         }
     }
 
+    /// Authorities of every slot of the epoch, if they are known.
+    pub fn get_epoch_authorities(&self, epoch: Epoch) -> Option<Vec<Vec<AuthorityStake>>> {
+        self.epoch_to_slots(epoch).map(|s| self.get_authorities(s).ok()).collect()
+    }
+
+    /// Authorities of every slot of the next epoch, if the slot is the last of its epoch and
+    /// they are known. The last block of an epoch commits to them.
+    pub fn next_epoch_authorities(&self, slot: Slot) -> Option<Vec<Vec<AuthorityStake>>> {
+        if slot == 0 || slot % self.authority_config.epoch_length != 0 {
+            return None;
+        }
+        self.get_epoch_authorities(self.slot_to_epoch(slot) + 1)
+    }
 }
 
 #[cfg(test)]
@@ -304,6 +400,7 @@ mod test {
     use crate::types::SignedBeaconBlock;
     use chain::SignedHeader;
     use primitives::hash::CryptoHash;
+    use primitives::aggregate_signature::BlsSecretKey;
     use primitives::signature::get_key_pair;
     use storage::test_utils::MemoryStorage;
 
@@ -317,19 +414,29 @@ mod test {
         let mut initial_authorities = vec![];
         for i in 0..num_authorities {
             let (public_key, _) = get_key_pair();
-            initial_authorities.push(AuthorityStake { account_id: i.to_string(), public_key, amount: 100 });
+            let bls_public_key = BlsSecretKey::generate().get_public_key();
+            initial_authorities.push(AuthorityStake {
+                account_id: i.to_string(),
+                public_key,
+                bls_public_key,
+                amount: 100,
+            });
         }
         AuthorityConfig { initial_proposals: initial_authorities, epoch_length, num_seats_per_slot }
     }
 
     fn test_blockchain(num_blocks: u64) -> BeaconBlockChainStorage {
         let storage = Arc::new(MemoryStorage::default());
-        let mut last_block =
-            SignedBeaconBlock::new(0, CryptoHash::default(), vec![], CryptoHash::default());
+        let mut last_block = SignedBeaconBlock::genesis(CryptoHash::default());
         let bc = BeaconBlockChainStorage::new(last_block.clone(), storage);
         for i in 1..num_blocks {
-            let block =
-                SignedBeaconBlock::new(i, last_block.block_hash(), vec![], CryptoHash::default());
+            let block = SignedBeaconBlock::new(
+                i,
+                last_block.block_hash(),
+                vec![],
+                CryptoHash::default(),
+                CryptoHash::default(),
+            );
             bc.insert_block(block.clone());
             last_block = block;
         }
@@ -345,7 +452,13 @@ mod test {
         let mut prev_hash = bc.genesis_hash;
         let num_seats = authority.get_authorities(1).unwrap().iter().map(|x| x.account_id == initial_authorities[0].account_id).count();
         for i in 1..11 {
-            let block = SignedBeaconBlock::new(i, prev_hash, vec![], CryptoHash::default());
+            let block = SignedBeaconBlock::new(
+                i,
+                prev_hash,
+                vec![],
+                CryptoHash::default(),
+                CryptoHash::default(),
+            );
             let mut header = block.header();
             header.signature.authority_mask = (0..num_seats).map(|_| true).collect();
             authority.process_block_header(&header);
@@ -377,11 +490,29 @@ mod test {
             vec![initial_authorities[1].clone(), initial_authorities[2].clone()]
         );
         assert!(authority.get_authorities(5).is_err());
-        let block1 = SignedBeaconBlock::new(1, bc.genesis_hash, vec![], CryptoHash::default());
+        assert_eq!(authority.next_epoch_authorities(1), None);
+        assert_eq!(
+            authority.next_epoch_authorities(2),
+            Some(vec![authority.get_authorities(3).unwrap(), authority.get_authorities(4).unwrap()])
+        );
+        assert_eq!(authority.next_epoch_authorities(4), None);
+        let block1 = SignedBeaconBlock::new(
+            1,
+            bc.genesis_hash,
+            vec![],
+            CryptoHash::default(),
+            CryptoHash::default(),
+        );
         let mut header1 = block1.header();
         // Authority #1 didn't show up.
         header1.signature.authority_mask = vec![true, false];
-        let block2 = SignedBeaconBlock::new(2, header1.block_hash(), vec![], CryptoHash::default());
+        let block2 = SignedBeaconBlock::new(
+            2,
+            header1.block_hash(),
+            vec![],
+            CryptoHash::default(),
+            CryptoHash::default(),
+        );
         let mut header2 = block2.header();
         header2.signature.authority_mask = vec![true, true];
         authority.process_block_header(&header1);
@@ -394,6 +525,93 @@ mod test {
             authority.get_authorities(6).unwrap(),
             vec![initial_authorities[2].clone(), initial_authorities[1].clone()]
         );
+        assert_eq!(authority.next_epoch_authorities(4).map(|a| a.len()), Some(2));
+    }
+
+    #[test]
+    fn test_authority_slashing() {
+        let authority_config = get_test_config(4, 2, 2);
+        let bc = test_blockchain(0);
+        let mut authority = Authority::new(authority_config, &bc);
+        let block1 = SignedBeaconBlock::new(
+            1,
+            bc.genesis_hash,
+            vec![],
+            CryptoHash::default(),
+            CryptoHash::default(),
+        );
+        let mut header1 = block1.header();
+        header1.signature.authority_mask = vec![true, true];
+        // Authority #2 produced an invalid block.
+        let block2 = SignedBeaconBlock::new_with_challenges(
+            2,
+            header1.block_hash(),
+            vec![],
+            CryptoHash::default(),
+            CryptoHash::default(),
+            CryptoHash::default(),
+            vec![],
+            vec!["2".to_string()],
+        );
+        let mut header2 = block2.header();
+        header2.signature.authority_mask = vec![true, true];
+        authority.process_block_header(&header1);
+        authority.process_block_header(&header2);
+        for slot in 5..=6 {
+            let authorities = authority.get_authorities(slot).unwrap();
+            assert!(!authorities.is_empty());
+            assert!(authorities.iter().all(|a| a.account_id != "2"));
+        }
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let authority_config = get_test_config(4, 2, 2);
+        let initial_authorities = authority_config.initial_proposals.to_vec();
+        let bc = test_blockchain(0);
+        let mut authority = Authority::new(authority_config, &bc);
+        let rotation = |account_id: &str| AuthorityStake {
+            account_id: account_id.to_string(),
+            public_key: get_key_pair().0,
+            bls_public_key: BlsSecretKey::generate().get_public_key(),
+            amount: 0,
+        };
+        let keys = |a: &AuthorityStake| (a.public_key, a.bls_public_key.clone());
+        let rotation0 = rotation("0");
+        let rotation1 = rotation("1");
+        let block1 = SignedBeaconBlock::new(
+            1,
+            bc.genesis_hash,
+            vec![rotation0.clone()],
+            CryptoHash::default(),
+            CryptoHash::default(),
+        );
+        let mut header1 = block1.header();
+        header1.signature.authority_mask = vec![true, true];
+        // The last block of the epoch commits to the authorities of the next epoch.
+        let block2 = SignedBeaconBlock::new(
+            2,
+            header1.block_hash(),
+            vec![rotation1.clone()],
+            CryptoHash::default(),
+            CryptoHash::default(),
+        );
+        let mut header2 = block2.header();
+        header2.signature.authority_mask = vec![true, true];
+        authority.process_block_header(&header1);
+        assert_eq!(authority.get_authorities(2).unwrap()[0], initial_authorities[1]);
+        assert_eq!(keys(&authority.get_authorities(3).unwrap()[0]), keys(&rotation0));
+        authority.process_block_header(&header2);
+        assert_eq!(authority.get_authorities(4).unwrap()[0], initial_authorities[1]);
+        for slot in 5..=6 {
+            for a in authority.get_authorities(slot).unwrap() {
+                match a.account_id.as_str() {
+                    "0" => assert_eq!(keys(&a), keys(&rotation0)),
+                    "1" => assert_eq!(keys(&a), keys(&rotation1)),
+                    _ => {}
+                }
+            }
+        }
     }
 
     #[test]
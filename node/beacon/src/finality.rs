@@ -0,0 +1,79 @@
+//! Finality of the beacon blocks. The authorities approve a block by signing it, and by signing a
+//! block they also approve all of its ancestors. Once more than two thirds of the stake approved
+//! blocks built on top of a block, the block is final and the chain never reverts it.
+use std::cmp;
+use std::collections::HashSet;
+
+use primitives::types::{AccountId, AuthorityStake};
+
+/// Number of blocks on top of a block whose approvals count towards its finality, so that a
+/// block is final within this many blocks or not at all.
+pub const FINALITY_WINDOW: u64 = 8;
+
+/// Whether more than two thirds of the stake of the authorities are in `approvers`.
+fn is_approved(authorities: &[AuthorityStake], approvers: &HashSet<AccountId>) -> bool {
+    let total: u128 = authorities.iter().map(|a| u128::from(a.amount)).sum();
+    let approved: u128 = authorities
+        .iter()
+        .filter(|a| approvers.contains(&a.account_id))
+        .map(|a| u128::from(a.amount))
+        .sum();
+    total > 0 && approved * 3 > total * 2
+}
+
+/// Index of the last block after `final_index` and before `best_index` that is final: the
+/// authorities of the block after it approved the blocks of the window after it. `authorities`
+/// and `signers` give the authorities of the block with the given index and those that signed it.
+pub fn last_final_index<A, S>(
+    final_index: u64,
+    best_index: u64,
+    authorities: A,
+    signers: S,
+) -> Option<u64>
+where
+    A: Fn(u64) -> Vec<AuthorityStake>,
+    S: Fn(u64) -> Vec<AccountId>,
+{
+    (final_index + 1..best_index).rev().find(|index| {
+        let last = cmp::min(index + FINALITY_WINDOW, best_index);
+        let approvers: HashSet<AccountId> = (index + 1..=last).flat_map(&signers).collect();
+        is_approved(&authorities(index + 1), &approvers)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use primitives::aggregate_signature::BlsSecretKey;
+    use primitives::signature::get_key_pair;
+
+    use super::*;
+
+    fn authority(account_id: &str, amount: u64) -> AuthorityStake {
+        AuthorityStake {
+            account_id: account_id.to_string(),
+            public_key: get_key_pair().0,
+            bls_public_key: BlsSecretKey::generate().get_public_key(),
+            amount,
+        }
+    }
+
+    #[test]
+    fn test_last_final_index() {
+        let authorities =
+            |_: u64| vec![authority("alice", 3), authority("bob", 1), authority("eve", 1)];
+        // Alice signs every block, bob every fourth, eve never.
+        let signers = |index: u64| {
+            let mut signers = vec!["alice".to_string()];
+            if index % 4 == 0 {
+                signers.push("bob".to_string());
+            }
+            signers
+        };
+        // Alice alone has 3 out of 5, not enough.
+        assert_eq!(last_final_index(0, 3, authorities, signers), None);
+        // Bob signed block 4, which approves the blocks before it.
+        assert_eq!(last_final_index(0, 5, authorities, signers), Some(3));
+        assert_eq!(last_final_index(3, 5, authorities, signers), None);
+        assert_eq!(last_final_index(3, 9, authorities, signers), Some(7));
+    }
+}
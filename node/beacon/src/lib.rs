@@ -8,4 +8,7 @@ extern crate primitives;
 extern crate storage;
 
 pub mod authority;
+pub mod finality;
+pub mod light_client;
+pub mod producer;
 pub mod types;
@@ -0,0 +1,365 @@
+//! Verification of beacon block headers for light clients, which follow the chain without
+//! downloading blocks or state. Every header commits to the authorities that approve the next
+//! block, so starting from a trusted header the client checks that each next header is approved
+//! by more than 2/3 of the stake of the authorities its parent committed to. The approvals are a
+//! single signature aggregated from the BLS signatures of the approving seats. The last block of
+//! an epoch also commits to the authorities of the next epoch, so a node can skip from epoch to
+//! epoch given the proof of each, see `verify_epoch_proof`. The client also checks that the
+//! header is produced by the authority whose turn it was, see `producer`.
+use std::fmt;
+
+use chain::SignedHeader;
+use primitives::hash::{hash_struct, CryptoHash};
+use primitives::types::AuthorityStake;
+
+use crate::producer::verify_producer;
+use crate::types::SignedBeaconBlockHeader;
+
+/// Hash of the authorities that a beacon block header commits to.
+pub fn authorities_hash(authorities: &[AuthorityStake]) -> CryptoHash {
+    hash_struct(&authorities)
+}
+
+/// Hash of the authorities of every slot of an epoch, which the last block before it commits to.
+pub fn epoch_authorities_hash(authorities: &[Vec<AuthorityStake>]) -> CryptoHash {
+    hash_struct(&authorities)
+}
+
+/// Beacon block header with the authorities that approve the next block.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LightClientBlockHeader {
+    pub header: SignedBeaconBlockHeader,
+    pub next_authorities: Vec<AuthorityStake>,
+}
+
+/// Last block of an epoch with the authorities of every slot of the next epoch.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct EpochProof {
+    pub header: SignedBeaconBlockHeader,
+    pub next_epoch_authorities: Vec<Vec<AuthorityStake>>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LightClientError {
+    /// Hash of the header doesn't match its body.
+    InvalidHash(CryptoHash),
+    /// Header is not the child of the head of the light client.
+    NotNext { index: u64, parent_hash: CryptoHash },
+    /// Authorities don't match the hash the header commits to.
+    InvalidAuthorities(CryptoHash),
+    /// Header is approved by more seats than there are authorities.
+    InvalidApprovals { approvals: usize, authorities: usize },
+    /// Authorities that approved the header have at most 2/3 of the stake.
+    NotEnoughApprovals { approved: u64, total: u64 },
+    /// Aggregated signature is not the one of the authorities that approved the header.
+    InvalidSignature,
+    /// Header is not produced by the authority whose turn it was, or its VRF proof is invalid.
+    InvalidProducer(CryptoHash),
+    /// Header of the epoch proof is not the last block of the epoch.
+    NotEpochEnd { index: u64, expected: u64 },
+    /// Authorities of the next epoch don't match the hash the header commits to.
+    InvalidEpochAuthorities(CryptoHash),
+}
+
+impl fmt::Display for LightClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LightClientError::InvalidHash(hash) => {
+                write!(f, "Hash {:?} doesn't match the header", hash)
+            }
+            LightClientError::NotNext { index, parent_hash } => write!(
+                f,
+                "Header {} with parent {:?} doesn't follow the head",
+                index,
+                parent_hash,
+            ),
+            LightClientError::InvalidAuthorities(hash) => {
+                write!(f, "Authorities don't match the authorities hash {:?}", hash)
+            }
+            LightClientError::InvalidApprovals { approvals, authorities } => write!(
+                f,
+                "Header has approvals of {} seats, but there are {} authorities",
+                approvals,
+                authorities,
+            ),
+            LightClientError::NotEnoughApprovals { approved, total } => write!(
+                f,
+                "Header is approved by {} out of {} stake, more than 2/3 is required",
+                approved,
+                total,
+            ),
+            LightClientError::InvalidSignature => {
+                write!(f, "Signature doesn't match the approving authorities")
+            }
+            LightClientError::InvalidProducer(hash) => {
+                write!(f, "Header {:?} is not produced by the authority in turn", hash)
+            }
+            LightClientError::NotEpochEnd { index, expected } => {
+                write!(f, "Header {} is not the last block of the epoch, {}", index, expected)
+            }
+            LightClientError::InvalidEpochAuthorities(hash) => {
+                write!(f, "Authorities don't match the epoch authorities hash {:?}", hash)
+            }
+        }
+    }
+}
+
+/// Checks that more than 2/3 of the stake of the authorities approved the header.
+fn verify_approvals(
+    header: &SignedBeaconBlockHeader,
+    authorities: &[AuthorityStake],
+) -> Result<(), LightClientError> {
+    let mask = &header.signature.authority_mask;
+    if mask.len() > authorities.len() {
+        return Err(LightClientError::InvalidApprovals {
+            approvals: mask.len(),
+            authorities: authorities.len(),
+        });
+    }
+    let total: u64 = authorities.iter().map(|a| a.amount).sum();
+    let approved: u64 = authorities
+        .iter()
+        .zip(mask)
+        .filter(|(_, set)| **set)
+        .map(|(authority, _)| authority.amount)
+        .sum();
+    if u128::from(approved) * 3 <= u128::from(total) * 2 {
+        return Err(LightClientError::NotEnoughApprovals { approved, total });
+    }
+    if !header.signature.verify(authorities, header.hash.as_ref()) {
+        return Err(LightClientError::InvalidSignature);
+    }
+    Ok(())
+}
+
+/// Checks that the proof is the last block of the epoch, approved by the authorities of its slot
+/// out of `authorities`, those of every slot of the epoch, and that it commits to the authorities
+/// of the next epoch. The proofs don't link up, the handoff of the authorities chains them.
+pub fn verify_epoch_proof(
+    proof: &EpochProof,
+    epoch: u64,
+    epoch_length: u64,
+    authorities: &[Vec<AuthorityStake>],
+) -> Result<(), LightClientError> {
+    let header = &proof.header;
+    if hash_struct(&header.body) != header.hash {
+        return Err(LightClientError::InvalidHash(header.hash));
+    }
+    let expected = (epoch + 1) * epoch_length;
+    if header.index() != expected {
+        return Err(LightClientError::NotEpochEnd { index: header.index(), expected });
+    }
+    let next_authorities = &proof.next_epoch_authorities;
+    if next_authorities.len() as u64 != epoch_length
+        || epoch_authorities_hash(next_authorities) != header.body.epoch_authorities_hash
+    {
+        return Err(LightClientError::InvalidEpochAuthorities(header.body.epoch_authorities_hash));
+    }
+    // A light client continues from the proof with the authorities of the first slot.
+    if authorities_hash(&next_authorities[0]) != header.body.authorities_hash {
+        return Err(LightClientError::InvalidAuthorities(header.body.authorities_hash));
+    }
+    verify_approvals(header, authorities.last().map_or(&[][..], Vec::as_slice))
+}
+
+/// Follows the beacon chain given only the headers.
+pub struct LightClient {
+    head: SignedBeaconBlockHeader,
+    next_authorities: Vec<AuthorityStake>,
+}
+
+impl LightClient {
+    /// Starts from a trusted header and the authorities that approve the block after it.
+    pub fn new(head: SignedBeaconBlockHeader, next_authorities: Vec<AuthorityStake>) -> Self {
+        LightClient { head, next_authorities }
+    }
+
+    pub fn head(&self) -> &SignedBeaconBlockHeader {
+        &self.head
+    }
+
+    pub fn next_authorities(&self) -> &[AuthorityStake] {
+        &self.next_authorities
+    }
+
+    /// Checks that the header is the child of the head, is approved by its authorities and is
+    /// produced by the one whose turn it was.
+    pub fn verify(&self, header: &LightClientBlockHeader) -> Result<(), LightClientError> {
+        let signed_header = &header.header;
+        if hash_struct(&signed_header.body) != signed_header.hash {
+            return Err(LightClientError::InvalidHash(signed_header.hash));
+        }
+        if signed_header.parent_hash() != self.head.block_hash()
+            || signed_header.index() != self.head.index() + 1
+        {
+            return Err(LightClientError::NotNext {
+                index: signed_header.index(),
+                parent_hash: signed_header.parent_hash(),
+            });
+        }
+        if authorities_hash(&header.next_authorities) != signed_header.body.authorities_hash {
+            return Err(LightClientError::InvalidAuthorities(signed_header.body.authorities_hash));
+        }
+        verify_approvals(signed_header, &self.next_authorities)?;
+        if !verify_producer(&self.head, signed_header, &self.next_authorities) {
+            return Err(LightClientError::InvalidProducer(signed_header.hash));
+        }
+        Ok(())
+    }
+
+    /// Verifies the header and makes it the new head.
+    pub fn follow(&mut self, header: LightClientBlockHeader) -> Result<(), LightClientError> {
+        self.verify(&header)?;
+        self.head = header.header;
+        self.next_authorities = header.next_authorities;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chain::SignedBlock;
+    use primitives::signature::get_key_pair;
+    use primitives::signer::InMemorySigner;
+    use primitives::traits::Signer;
+
+    use crate::producer::producer_seat;
+    use crate::types::SignedBeaconBlock;
+
+    use super::*;
+
+    fn signer(account_id: &str) -> InMemorySigner {
+        InMemorySigner { account_id: account_id.to_string(), ..InMemorySigner::default() }
+    }
+
+    fn stake(signer: &InMemorySigner) -> AuthorityStake {
+        AuthorityStake {
+            account_id: signer.account_id(),
+            public_key: get_key_pair().0,
+            bls_public_key: signer.public_key(),
+            amount: 1,
+        }
+    }
+
+    /// Child of the parent with the proof of the producer among the signers, in the first turn.
+    fn child(
+        parent: &SignedBeaconBlockHeader,
+        authorities: &[AuthorityStake],
+        signers: &[InMemorySigner],
+    ) -> SignedBeaconBlock {
+        let mut block = SignedBeaconBlock::new(
+            parent.index() + 1,
+            parent.block_hash(),
+            vec![],
+            CryptoHash::default(),
+            authorities_hash(authorities),
+        );
+        let randomness = parent.body.randomness();
+        let seat = producer_seat(signers.len(), &randomness, 0).unwrap();
+        let vrf_proof = signers[seat].vrf_prove(&randomness, parent.index() + 1).unwrap();
+        block.set_producer(0, vrf_proof);
+        block
+    }
+
+    fn light_header(
+        block: &SignedBeaconBlock,
+        next_authorities: &[AuthorityStake],
+    ) -> LightClientBlockHeader {
+        let next_authorities = next_authorities.to_vec();
+        LightClientBlockHeader { header: block.header(), next_authorities }
+    }
+
+    #[test]
+    fn test_follow() {
+        let signers: Vec<_> =
+            ["alice.near", "bob.near", "carol.near"].iter().map(|a| signer(a)).collect();
+        let authorities: Vec<_> = signers.iter().map(stake).collect();
+        let genesis = SignedBeaconBlock::genesis(CryptoHash::default());
+        let mut client = LightClient::new(genesis.header(), authorities.clone());
+
+        let mut block = child(client.head(), &authorities, &signers);
+        for (i, s) in signers.iter().enumerate().take(2) {
+            block.add_signature(&block.sign(s), i);
+        }
+        let next_authorities = authorities[..2].to_vec();
+        let header = light_header(&block, &next_authorities);
+        assert_eq!(
+            client.verify(&header),
+            Err(LightClientError::InvalidAuthorities(authorities_hash(&authorities)))
+        );
+        let header = light_header(&block, &authorities);
+        assert_eq!(
+            client.verify(&header),
+            Err(LightClientError::NotEnoughApprovals { approved: 2, total: 3 })
+        );
+        block.add_signature(&block.sign(&signers[2]), 2);
+        let header = light_header(&block, &authorities);
+        client.follow(header.clone()).unwrap();
+        assert_eq!(client.head(), &header.header);
+        assert_eq!(
+            client.verify(&header),
+            Err(LightClientError::NotNext { index: 1, parent_hash: genesis.block_hash() })
+        );
+
+        // The producer of the first turn claims the second one.
+        let mut block = child(client.head(), &authorities, &signers);
+        let vrf_proof = block.body.header.vrf_proof.clone();
+        block.set_producer(1, vrf_proof);
+        for (i, s) in signers.iter().enumerate() {
+            block.add_signature(&block.sign(s), i);
+        }
+        let header = light_header(&block, &authorities);
+        assert_eq!(client.verify(&header), Err(LightClientError::InvalidProducer(block.hash)));
+
+        // Carol's approval is signed by Alice.
+        let mut block = child(client.head(), &next_authorities, &signers);
+        block.add_signature(&block.sign(&signers[0]), 0);
+        block.add_signature(&block.sign(&signers[1]), 1);
+        block.add_signature(&block.sign(&signers[0]), 2);
+        let header = light_header(&block, &next_authorities);
+        assert_eq!(client.verify(&header), Err(LightClientError::InvalidSignature));
+    }
+
+    #[test]
+    fn test_verify_epoch_proof() {
+        let signers: Vec<_> =
+            ["alice.near", "bob.near", "carol.near"].iter().map(|a| signer(a)).collect();
+        let authorities: Vec<_> = signers.iter().map(stake).collect();
+        let epoch_authorities = vec![authorities.clone(), authorities.clone()];
+        let next_epoch_authorities = vec![authorities[..2].to_vec(), authorities[1..].to_vec()];
+        let mut block = SignedBeaconBlock::new_with_challenges(
+            2,
+            CryptoHash::default(),
+            vec![],
+            CryptoHash::default(),
+            authorities_hash(&next_epoch_authorities[0]),
+            epoch_authorities_hash(&next_epoch_authorities),
+            vec![],
+            vec![],
+        );
+        for (i, s) in signers.iter().enumerate() {
+            block.add_signature(&block.sign(s), i);
+        }
+        let proof = EpochProof { header: block.header(), next_epoch_authorities };
+        assert_eq!(verify_epoch_proof(&proof, 0, 2, &epoch_authorities), Ok(()));
+        assert_eq!(
+            verify_epoch_proof(&proof, 1, 2, &epoch_authorities),
+            Err(LightClientError::NotEpochEnd { index: 2, expected: 4 })
+        );
+
+        // The proof hands off to other authorities than the block commits to.
+        let mut forged = proof.clone();
+        forged.next_epoch_authorities.reverse();
+        assert_eq!(
+            verify_epoch_proof(&forged, 0, 2, &epoch_authorities),
+            Err(LightClientError::InvalidEpochAuthorities(block.body.header.epoch_authorities_hash))
+        );
+
+        // The authorities of the last slot of the epoch didn't approve the block.
+        let other_authorities = vec![authorities.clone(), authorities[..1].to_vec()];
+        assert_eq!(
+            verify_epoch_proof(&proof, 0, 2, &other_authorities),
+            Err(LightClientError::InvalidApprovals { approvals: 3, authorities: 1 })
+        );
+    }
+}
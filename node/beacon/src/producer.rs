@@ -0,0 +1,114 @@
+//! Selection of the block producers. The seats of the authorities of a block take turns producing
+//! it, in an order shuffled by the randomness of the parent block: the output of the VRF of the
+//! producer of the parent, see `primitives::vrf`. The producer can't choose it, and no one knows
+//! the order before the parent is produced, yet everyone who has the parent checks it. Seats are
+//! given out by stake, so the authorities with more stake produce more blocks. The shard block is
+//! produced with the beacon block that commits to it, by the same producer.
+use primitives::hash::{hash, CryptoHash};
+use primitives::types::AuthorityStake;
+use primitives::vrf::{verify, vrf_input};
+
+use crate::types::SignedBeaconBlockHeader;
+
+/// Seat of the authorities of the block that produces it after the given number of skipped
+/// heights, once the parent block with the randomness is known. The seats are ordered by the hash
+/// of the randomness followed by the seat as little endian u64, which any implementation can
+/// reproduce, unlike the output of a particular rng.
+pub fn producer_seat(
+    num_seats: usize,
+    randomness: &CryptoHash,
+    skipped_heights: u64,
+) -> Option<usize> {
+    if num_seats == 0 {
+        return None;
+    }
+    let mut seats: Vec<(CryptoHash, usize)> = (0..num_seats)
+        .map(|seat| {
+            let mut data = randomness.as_ref().to_vec();
+            data.extend_from_slice(&(seat as u64).to_le_bytes());
+            (hash(&data), seat)
+        })
+        .collect();
+    seats.sort();
+    Some(seats[(skipped_heights % num_seats as u64) as usize].1)
+}
+
+/// Whether the block is produced by the seat whose turn it is after the heights it skipped: the
+/// seat signed the block and its VRF proof is the one of the input of the block.
+pub fn verify_producer(
+    parent: &SignedBeaconBlockHeader,
+    header: &SignedBeaconBlockHeader,
+    authorities: &[AuthorityStake],
+) -> bool {
+    let randomness = parent.body.randomness();
+    let seat = match producer_seat(authorities.len(), &randomness, header.body.skipped_heights) {
+        Some(seat) => seat,
+        None => return false,
+    };
+    let signed = header.signature.authority_mask.get(seat).cloned().unwrap_or(false);
+    let input = vrf_input(&randomness, header.body.index);
+    signed && verify(&authorities[seat].bls_public_key, &input, &header.body.vrf_proof).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use chain::{SignedBlock, SignedHeader};
+    use primitives::signature::get_key_pair;
+    use primitives::signer::InMemorySigner;
+    use primitives::traits::Signer;
+
+    use crate::types::SignedBeaconBlock;
+
+    use super::*;
+
+    fn stake(signer: &InMemorySigner) -> AuthorityStake {
+        AuthorityStake {
+            account_id: signer.account_id(),
+            public_key: get_key_pair().0,
+            bls_public_key: signer.public_key(),
+            amount: 1,
+        }
+    }
+
+    #[test]
+    fn test_producer_seat() {
+        let randomness = CryptoHash::new(&[1; 32]);
+        let order: Vec<_> = (0..4).map(|k| producer_seat(4, &randomness, k).unwrap()).collect();
+        let mut seats = order.clone();
+        seats.sort();
+        assert_eq!(seats, vec![0, 1, 2, 3]);
+        assert_eq!(producer_seat(4, &randomness, 4), Some(order[0]));
+        assert_eq!(producer_seat(0, &randomness, 0), None);
+        // The order is part of the protocol, it must not change.
+        assert_eq!(order, vec![2, 0, 1, 3]);
+    }
+
+    #[test]
+    fn test_verify_producer() {
+        let signers: Vec<_> = (0..3).map(|_| InMemorySigner::default()).collect();
+        let authorities: Vec<_> = signers.iter().map(stake).collect();
+        let genesis = SignedBeaconBlock::genesis(CryptoHash::default()).header();
+        let randomness = genesis.body.randomness();
+        let seat = producer_seat(authorities.len(), &randomness, 1).unwrap();
+        let producer = &signers[seat];
+        let block = |skipped_heights, signer: &InMemorySigner| {
+            let mut block = SignedBeaconBlock::new(
+                1,
+                genesis.block_hash(),
+                vec![],
+                CryptoHash::default(),
+                CryptoHash::default(),
+            );
+            block.set_producer(skipped_heights, signer.vrf_prove(&randomness, 1).unwrap());
+            block.add_signature(&block.sign(producer), seat);
+            block.header()
+        };
+        assert!(verify_producer(&genesis, &block(1, producer), &authorities));
+        // It's the turn of another seat.
+        let other = (0..3).find(|k| producer_seat(3, &randomness, *k) != Some(seat)).unwrap();
+        assert!(!verify_producer(&genesis, &block(other, producer), &authorities));
+        // The proof is not of the producer.
+        let impostor = &signers[(seat + 1) % signers.len()];
+        assert!(!verify_producer(&genesis, &block(1, impostor), &authorities));
+    }
+}
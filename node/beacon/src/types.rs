@@ -1,14 +1,21 @@
 use parking_lot::RwLock;
 use std::sync::Arc;
 
-use chain::{SignedBlock, SignedHeader};
+use chain::{Challenge, SignedBlock, SignedHeader};
 use primitives::hash::{hash_struct, CryptoHash};
-use primitives::types::{GroupSignature, PartialSignature, AuthorityStake};
+use primitives::merkle::merklize;
+use primitives::signature::bs58_serializer;
+use primitives::types::{
+    AccountId, AuthorityStake, BlockId, GroupSignature, MerkleHash, PartialSignature,
+};
+use primitives::vrf::{proof_to_output, VrfProof};
 use storage::Storage;
 use configs::ChainSpec;
 use configs::authority::get_authority_config;
 
 use crate::authority::{Authority};
+use crate::finality::last_final_index;
+use crate::light_client::{EpochProof, LightClientBlockHeader};
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct BeaconBlockHeader {
@@ -20,6 +27,30 @@ pub struct BeaconBlockHeader {
     pub authority_proposal: Vec<AuthorityStake>,
     /// Hash of the shard block.
     pub shard_block_hash: CryptoHash,
+    /// Hash of the authorities that approve the next block, so that light clients can follow
+    /// the changes of the authorities.
+    pub authorities_hash: CryptoHash,
+    /// Hash of the authorities of every slot of the next epoch in the last block of an epoch,
+    /// default in the other blocks, so that nodes can sync the epochs without the headers.
+    pub epoch_authorities_hash: CryptoHash,
+    /// Root of the Merkle tree over the challenges of the block.
+    pub challenges_root: MerkleHash,
+    /// Authorities that produced the blocks the challenges prove invalid. They lose their
+    /// stake and are not selected as authorities again.
+    pub slashed: Vec<AccountId>,
+    /// Heights skipped since the parent because their producers were offline, which tells whose
+    /// turn it was to produce the block, see `producer`.
+    pub skipped_heights: u64,
+    /// VRF proof of the producer on the randomness of the parent, the randomness of the block.
+    #[serde(with = "bs58_serializer")]
+    pub vrf_proof: VrfProof,
+}
+
+impl BeaconBlockHeader {
+    /// Randomness that the producers of the blocks after it are selected by.
+    pub fn randomness(&self) -> CryptoHash {
+        proof_to_output(&self.vrf_proof)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -32,6 +63,7 @@ pub struct SignedBeaconBlockHeader {
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct BeaconBlock {
     pub header: BeaconBlockHeader,
+    pub challenges: Vec<Challenge>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -62,18 +94,67 @@ impl SignedBeaconBlock {
         parent_hash: CryptoHash,
         authority_proposal: Vec<AuthorityStake>,
         shard_block_hash: CryptoHash,
+        authorities_hash: CryptoHash,
+    ) -> SignedBeaconBlock {
+        SignedBeaconBlock::new_with_challenges(
+            index,
+            parent_hash,
+            authority_proposal,
+            shard_block_hash,
+            authorities_hash,
+            CryptoHash::default(),
+            vec![],
+            vec![],
+        )
+    }
+
+    pub fn new_with_challenges(
+        index: u64,
+        parent_hash: CryptoHash,
+        authority_proposal: Vec<AuthorityStake>,
+        shard_block_hash: CryptoHash,
+        authorities_hash: CryptoHash,
+        epoch_authorities_hash: CryptoHash,
+        challenges: Vec<Challenge>,
+        slashed: Vec<AccountId>,
     ) -> SignedBeaconBlock {
-        let header = BeaconBlockHeader { index, parent_hash, authority_proposal, shard_block_hash };
+        let (challenges_root, _) = merklize(&challenges);
+        let header = BeaconBlockHeader {
+            index,
+            parent_hash,
+            authority_proposal,
+            shard_block_hash,
+            authorities_hash,
+            epoch_authorities_hash,
+            challenges_root,
+            slashed,
+            skipped_heights: 0,
+            vrf_proof: VrfProof::empty(),
+        };
         let hash = hash_struct(&header);
         SignedBeaconBlock {
-            body: BeaconBlock { header },
+            body: BeaconBlock { header, challenges },
             hash,
             signature: GroupSignature::default()
         }
     }
 
+    /// Sets the turn and the VRF proof of the producer, before the block is signed.
+    pub fn set_producer(&mut self, skipped_heights: u64, vrf_proof: VrfProof) {
+        self.body.header.skipped_heights = skipped_heights;
+        self.body.header.vrf_proof = vrf_proof;
+        self.hash = hash_struct(&self.body.header);
+    }
+
+    /// Light clients start from a trusted header, so the genesis doesn't commit to authorities.
     pub fn genesis(shard_block_hash: CryptoHash) -> SignedBeaconBlock {
-        SignedBeaconBlock::new(0, CryptoHash::default(), vec![], shard_block_hash)
+        SignedBeaconBlock::new(
+            0,
+            CryptoHash::default(),
+            vec![],
+            shard_block_hash,
+            CryptoHash::default(),
+        )
     }
 }
 
@@ -108,8 +189,29 @@ impl SignedBlock for SignedBeaconBlock {
     }
 }
 
+/// Signature of an authority on the block it adopted as the best one. The producer of the next
+/// block waits for the endorsements of the authorities before building on top of the block.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Endorsement {
+    pub index: u64,
+    pub block_hash: CryptoHash,
+    pub account_id: AccountId,
+    #[serde(with = "bs58_serializer")]
+    pub signature: PartialSignature,
+}
+
 pub type BeaconBlockChainStorage = chain::BlockChain<SignedBeaconBlock>;
 
+/// Whether a block of the best chain can still be replaced by a fork.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Finality {
+    /// Block is on the best chain, but a heavier fork may still replace it.
+    Optimistic,
+    /// The authorities approved the blocks built on top of the block, it is never reverted.
+    Final,
+}
+
 pub struct BeaconBlockChain {
     pub chain: BeaconBlockChainStorage,
     pub authority: RwLock<Authority>,
@@ -125,6 +227,81 @@ impl BeaconBlockChain {
             authority,
         }
     }
+
+    /// Index of the last final block of the best chain.
+    pub fn final_index(&self) -> u64 {
+        self.chain.finalized_index()
+    }
+
+    /// Finalizes the last block of the best chain that the authorities approved, see
+    /// `finality::last_final_index`. Returns the header of the block if the finality advanced.
+    pub fn update_finality(&self) -> Option<SignedBeaconBlockHeader> {
+        let index = {
+            let authority = self.authority.read();
+            let authorities = |index| authority.get_authorities(index).unwrap_or_default();
+            let signers = |index| -> Vec<AccountId> {
+                let header = match self.chain.get_header(&BlockId::Number(index)) {
+                    Some(header) => header,
+                    None => return vec![],
+                };
+                authorities(index)
+                    .into_iter()
+                    .zip(header.signature.authority_mask.iter())
+                    .filter(|(_, signed)| **signed)
+                    .map(|(authority, _)| authority.account_id)
+                    .collect()
+            };
+            last_final_index(self.final_index(), self.chain.best_index(), authorities, signers)?
+        };
+        let header = self.chain.get_header(&BlockId::Number(index))?;
+        if self.chain.finalize(&header.hash) {
+            Some(header)
+        } else {
+            None
+        }
+    }
+
+    /// Finality of the block, `None` if it's not on the best chain.
+    pub fn block_finality(&self, hash: &CryptoHash, index: u64) -> Option<Finality> {
+        let header = self.chain.get_header(&BlockId::Number(index))?;
+        if header.hash != *hash {
+            return None;
+        }
+        Some(if index <= self.final_index() { Finality::Final } else { Finality::Optimistic })
+    }
+
+    /// Finality of the beacon block that includes the shard block. Shard blocks have the index of
+    /// the beacon block that includes them.
+    pub fn shard_block_finality(
+        &self,
+        shard_block_hash: &CryptoHash,
+        index: u64,
+    ) -> Option<Finality> {
+        let header = self.chain.get_header(&BlockId::Number(index))?;
+        if header.body.shard_block_hash != *shard_block_hash {
+            return None;
+        }
+        self.block_finality(&header.hash, index)
+    }
+
+    /// Header with the given index and the authorities that approve the block after it.
+    pub fn get_light_client_block_header(&self, index: u64) -> Option<LightClientBlockHeader> {
+        let header = self.chain.get_header(&BlockId::Number(index))?;
+        let next_authorities = self.authority.read().get_authorities(index + 1).ok()?;
+        Some(LightClientBlockHeader { header, next_authorities })
+    }
+
+    /// Proof of the epoch: its last block, once it's final, and the authorities of the next epoch.
+    pub fn get_epoch_proof(&self, epoch: u64) -> Option<EpochProof> {
+        let authority = self.authority.read();
+        let index = (epoch + 1) * authority.epoch_length();
+        if index > self.final_index() {
+            return None;
+        }
+        let header = self.chain.get_header(&BlockId::Number(index))?;
+        let next_epoch_authorities = authority.next_epoch_authorities(index)?;
+        Some(EpochProof { header, next_epoch_authorities })
+    }
 }
 
 #[cfg(test)]
@@ -135,7 +312,6 @@ mod tests {
     use primitives::aggregate_signature::BlsSignature;
     use primitives::hash::hash;
     use primitives::signer::InMemorySigner;
-    use primitives::types::BlockId;
     use std::collections::HashMap;
     use storage::test_utils::create_memory_db;
 
@@ -145,7 +321,7 @@ mod tests {
     fn test_genesis() {
         let storage = Arc::new(create_memory_db());
         let genesis =
-            SignedBeaconBlock::new(0, CryptoHash::default(), vec![], CryptoHash::default());
+            SignedBeaconBlock::genesis(CryptoHash::default());
         let bc = BlockChain::new(genesis.clone(), storage);
         assert_eq!(bc.get_block(&BlockId::Hash(genesis.block_hash())).unwrap(), genesis);
         assert_eq!(bc.get_block(&BlockId::Number(0)).unwrap(), genesis);
@@ -155,9 +331,15 @@ mod tests {
     fn test_restart_chain() {
         let storage = Arc::new(create_memory_db());
         let genesis =
-            SignedBeaconBlock::new(0, CryptoHash::default(), vec![], CryptoHash::default());
+            SignedBeaconBlock::genesis(CryptoHash::default());
         let bc = BlockChain::new(genesis.clone(), storage.clone());
-        let mut block1 = SignedBeaconBlock::new(1, genesis.block_hash(), vec![], CryptoHash::default());
+        let mut block1 = SignedBeaconBlock::new(
+            1,
+            genesis.block_hash(),
+            vec![],
+            CryptoHash::default(),
+            CryptoHash::default(),
+        );
         let signer = InMemorySigner::default();
         let sig = block1.sign(&signer);
         block1.add_signature(&sig, 0);
@@ -178,9 +360,9 @@ mod tests {
     fn test_two_chains() {
         let storage = Arc::new(create_memory_db());
         let genesis1 =
-            SignedBeaconBlock::new(0, CryptoHash::default(), vec![], CryptoHash::default());
+            SignedBeaconBlock::genesis(CryptoHash::default());
         let genesis2 =
-            SignedBeaconBlock::new(0, CryptoHash::default(), vec![], genesis1.block_hash());
+            SignedBeaconBlock::genesis(genesis1.block_hash());
         let bc1 = BlockChain::new(genesis1.clone(), storage.clone());
         let bc2 = BlockChain::new(genesis2.clone(), storage.clone());
         assert_eq!(bc1.best_block().block_hash(), genesis1.block_hash());
@@ -190,7 +372,7 @@ mod tests {
     fn test_fork_choice_rule_helper(graph: Vec<(u32,u32,usize)>, expect: u32) {
         let storage = Arc::new(create_memory_db());
 
-        let genesis = SignedBeaconBlock::new(0, CryptoHash::default(), vec![], CryptoHash::default());
+        let genesis = SignedBeaconBlock::genesis(CryptoHash::default());
         let bc = BlockChain::new(genesis.clone(), storage);
         let mut blocks: HashMap<u32, SignedBeaconBlock> = HashMap::new();
         blocks.insert(0, genesis.clone());
@@ -199,7 +381,13 @@ mod tests {
             let mut block;
             {
                 let parent = blocks.get(parent_id).unwrap();
-                block = SignedBeaconBlock::new(parent.body.header.index + 1, parent.block_hash(), vec![], hash(&[*self_id as u8]));
+                block = SignedBeaconBlock::new(
+                    parent.body.header.index + 1,
+                    parent.block_hash(),
+                    vec![],
+                    hash(&[*self_id as u8]),
+                    CryptoHash::default(),
+                );
             }
             for i in 0..*sign_count {
                 // Having proper signing here is far too slow, and unnecessary for this test
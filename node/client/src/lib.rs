@@ -7,38 +7,97 @@ extern crate parking_lot;
 extern crate primitives;
 extern crate serde;
 
+pub mod orphans;
+pub mod parts;
+pub mod production;
 pub mod test_utils;
+pub mod validation;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io;
 use std::io::prelude::*;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Instant;
 use std::{cmp, env, fs};
 
 use env_logger::Builder;
 use parking_lot::RwLock;
 
-use beacon::types::{BeaconBlockChain, SignedBeaconBlock, SignedBeaconBlockHeader};
-use chain::{SignedBlock, ChainPayload, SignedShardBlock};
+use beacon::light_client::{authorities_hash, epoch_authorities_hash};
+use beacon::producer::producer_seat;
+use beacon::types::{BeaconBlockChain, Endorsement, SignedBeaconBlock, SignedBeaconBlockHeader};
+use chain::types::total_parts;
+use chain::{Challenge, SignedBlock, ChainPayload, ShardBlockPart, SignedShardBlock};
 use configs::ClientConfig;
+use orphans::{OrphanPool, OrphanPoolConfig};
+use parts::PartPool;
+use production::{BlockProduction, ProductionConfig};
 use primitives::hash::CryptoHash;
-use primitives::signer::InMemorySigner;
-use primitives::types::{AccountId, AuthorityStake, ConsensusBlockBody, UID};
+use primitives::remote_signer::{read_auth_key, RemoteSigner};
+use primitives::signer::{BlockKind, InMemorySigner};
+use primitives::traits::Signer;
+use primitives::types::{AccountId, AuthorityStake, BlockId, ConsensusBlockBody, UID};
+use shard::pool::TransactionPoolConfig;
 use shard::{ShardBlockChain};
 use storage::Storage;
+use validation::ValidationError;
+
+/// Peer the node is connected to, as reported by the network protocol.
+#[derive(Clone, Debug)]
+pub struct ConnectedPeer {
+    pub account_id: Option<AccountId>,
+    pub protocol_version: u32,
+    /// Name and version of the node software of the peer.
+    pub node_version: String,
+    /// Best beacon block the peer announced when connecting.
+    pub best_index: u64,
+    pub best_hash: CryptoHash,
+}
+
+/// State of the network, kept up to date by the network protocol so that the RPC can report it.
+#[derive(Clone, Debug, Default)]
+pub struct NetworkInfo {
+    /// Version of the network protocol. `None` if the node doesn't run the network, e.g. DevNet.
+    pub protocol_version: Option<u32>,
+    /// Connected peers by their network node index.
+    pub peers: HashMap<usize, ConnectedPeer>,
+    /// Progress of the block synchronization, if the node is catching up with the peers.
+    pub sync_progress: Option<SyncProgress>,
+}
+
+/// Progress of catching up with the peers, headers are downloaded ahead of the blocks.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyncProgress {
+    /// Best beacon block index announced by the peers.
+    pub target_index: u64,
+    /// Index of the last downloaded and validated header.
+    pub header_index: u64,
+    /// Index of the last block handed over for import.
+    pub block_index: u64,
+}
 
 pub struct Client {
     pub account_id: AccountId,
-    pub signer: InMemorySigner,
+    /// Signs the blocks the node produces and endorses, possibly in a separate service.
+    pub signer: Arc<Signer>,
 
     pub shard_chain: ShardBlockChain,
     pub beacon_chain: BeaconBlockChain,
-
-    // TODO: The following logic might need to be hidden somewhere.
-    /// Stores blocks that cannot be added yet.
-    pending_beacon_blocks: RwLock<HashMap<CryptoHash, SignedBeaconBlock>>,
-    pending_shard_blocks: RwLock<HashMap<CryptoHash, SignedShardBlock>>,
+    pub network_info: RwLock<NetworkInfo>,
+
+    /// Blocks that cannot be added yet because their parent is not known.
+    orphans: RwLock<OrphanPool>,
+    /// Decides when the node produces the next block.
+    block_production: RwLock<BlockProduction>,
+    /// Verified challenges that no block includes yet, by the hash of the block they challenge.
+    challenges: RwLock<HashMap<CryptoHash, Challenge>>,
+    /// Hashes of the blocks whose challenges are already included in a block.
+    challenged_blocks: RwLock<HashSet<CryptoHash>>,
+    /// Challenges the node created, to be sent to the peers.
+    outgoing_challenges: RwLock<Vec<Challenge>>,
+    /// Parts of the shard blocks the node keeps as an authority or collects to reconstruct them.
+    block_parts: RwLock<PartPool>,
 }
 
 fn configure_logging(log_level: log::LevelFilter) {
@@ -72,7 +131,8 @@ pub const DEFAULT_LOG_LEVEL: log::LevelFilter = log::LevelFilter::Info;
 const STORAGE_PATH: &str = "storage/db";
 const KEY_STORE_PATH: &str = "storage/keystore";
 
-fn get_storage(base_path: &Path) -> Arc<Storage> {
+/// Opens the storage of the node with the given base path.
+pub fn get_storage(base_path: &Path) -> Arc<Storage> {
     let mut storage_path = base_path.to_owned();
     storage_path.push(STORAGE_PATH);
     match fs::canonicalize(storage_path.clone()) {
@@ -82,6 +142,30 @@ fn get_storage(base_path: &Path) -> Arc<Storage> {
     Arc::new(storage::open_database(&storage_path.to_string_lossy()))
 }
 
+/// Signer of the blocks, the signing service if the node is configured with one, otherwise the key
+/// from the keystore.
+fn get_signer(config: &ClientConfig) -> Arc<Signer> {
+    if let Some(addr) = &config.remote_signer_addr {
+        let auth_key_file = config
+            .remote_signer_auth_key_file
+            .as_ref()
+            .expect("The remote signer requires the auth key file");
+        let auth_key = read_auth_key(auth_key_file)
+            .unwrap_or_else(|e| panic!("Cannot read the remote signer auth key: {}", e));
+        let signer = RemoteSigner::connect(config.account_id.clone(), addr, auth_key)
+            .unwrap_or_else(|e| panic!("Cannot connect to the remote signer: {}", e));
+        info!(target: "client", "Signing the blocks with the remote signer at {}", addr);
+        return Arc::new(signer);
+    }
+    let mut key_file_path = config.base_path.to_path_buf();
+    key_file_path.push(KEY_STORE_PATH);
+    Arc::new(InMemorySigner::from_key_file(
+        config.account_id.clone(),
+        key_file_path.as_path(),
+        config.public_key.clone(),
+    ))
+}
+
 pub type ChainConsensusBlockBody = ConsensusBlockBody<ChainPayload>;
 
 impl Client {
@@ -89,28 +173,42 @@ impl Client {
         let storage = get_storage(&config.base_path);
 
         let chain_spec = &config.chain_spec;
-        let shard_chain = ShardBlockChain::new(chain_spec, storage.clone());
+        let pool_config = TransactionPoolConfig {
+            persist: config.persist_transaction_pool,
+            ..Default::default()
+        };
+        let shard_chain =
+            ShardBlockChain::new_with_pool_config(chain_spec, storage.clone(), pool_config);
         let genesis = SignedBeaconBlock::genesis(shard_chain.chain.genesis_hash);
         let beacon_chain = BeaconBlockChain::new(genesis, &chain_spec, storage.clone());
         info!(target: "client", "Genesis root: {:?}", beacon_chain.chain.genesis_hash);
 
-        let mut key_file_path = config.base_path.to_path_buf();
-        key_file_path.push(KEY_STORE_PATH);
-        let signer = InMemorySigner::from_key_file(
-            config.account_id.clone(),
-            key_file_path.as_path(),
-            config.public_key.clone(),
-        );
-
         configure_logging(config.log_level);
+        let signer = get_signer(config);
+
+        let production_config = ProductionConfig {
+            min_delay: config.min_block_production_delay,
+            max_delay: config.max_block_production_delay,
+        };
+        let best_block = beacon_chain.chain.best_block();
+        let block_production = BlockProduction::new(
+            production_config,
+            (best_block.index(), best_block.hash),
+            Instant::now(),
+        );
 
         Self {
             account_id: config.account_id.clone(),
             signer,
             shard_chain,
             beacon_chain,
-            pending_beacon_blocks: RwLock::new(HashMap::new()),
-            pending_shard_blocks: RwLock::new(HashMap::new()),
+            network_info: RwLock::new(NetworkInfo::default()),
+            orphans: RwLock::new(OrphanPool::new(OrphanPoolConfig::default())),
+            block_production: RwLock::new(block_production),
+            challenges: RwLock::new(HashMap::new()),
+            challenged_blocks: RwLock::new(HashSet::new()),
+            outgoing_challenges: RwLock::new(vec![]),
+            block_parts: RwLock::new(PartPool::new(parts::DEFAULT_MAX_BLOCKS)),
         }
     }
 
@@ -131,26 +229,82 @@ impl Client {
         }
 
         let last_block = self.beacon_chain.chain.best_block();
+        let last_shard_block_hash = last_block.body.header.shard_block_hash;
+        self.shard_chain.add_transactions(transactions);
+        let transactions = self.shard_chain.prepare_transactions(last_shard_block_hash);
         let authorities = self
             .beacon_chain
             .authority
             .read()
             .get_authorities(last_block.body.header.index + 1)
             .expect("Authorities should be present for given block to produce it");
+        // Heights are counted again here, the turn of the node may have passed since it checked.
+        let skipped_heights = self.block_production.read().skipped_heights(Instant::now());
+        let randomness = last_block.body.header.randomness();
+        match producer_seat(authorities.len(), &randomness, skipped_heights) {
+            Some(seat) if authorities[seat].account_id == self.account_id => {}
+            _ => return None,
+        }
         let (mut shard_block, (transaction, authority_proposals, tx_results, new_receipts)) =
-            self
-            .shard_chain
-            .prepare_new_block(last_block.body.header.shard_block_hash, receipts, transactions);
-        let mut block = SignedBeaconBlock::new(
+            self.shard_chain.prepare_new_block_with_parts(
+                last_shard_block_hash,
+                receipts,
+                transactions,
+                total_parts(authorities.len()),
+            );
+        // Light clients can't follow the chain past a block that doesn't commit to the authorities
+        // of the next one.
+        let next_authorities_hash = self
+            .beacon_chain
+            .authority
+            .read()
+            .get_authorities(last_block.body.header.index + 2)
+            .map(|authorities| authorities_hash(&authorities))
+            .unwrap_or_default();
+        let next_epoch_authorities_hash = self
+            .beacon_chain
+            .authority
+            .read()
+            .next_epoch_authorities(last_block.body.header.index + 1)
+            .map(|authorities| epoch_authorities_hash(&authorities))
+            .unwrap_or_default();
+        let challenges: Vec<Challenge> = self.challenges.read().values().cloned().collect();
+        let slashed = self.slashed(&challenges);
+        let mut block = SignedBeaconBlock::new_with_challenges(
             last_block.body.header.index + 1,
             last_block.block_hash(),
             authority_proposals,
             shard_block.block_hash(),
+            next_authorities_hash,
+            next_epoch_authorities_hash,
+            challenges,
+            slashed,
         );
-        let shard_block_signature = shard_block.sign(&self.signer);
-        let block_signature = block.sign(&self.signer);
+        let index = block.index();
+        match self.signer.vrf_prove(&randomness, index) {
+            Ok(vrf_proof) => block.set_producer(skipped_heights, vrf_proof),
+            Err(e) => {
+                warn!(target: "client", "Cannot get the VRF proof of block {}: {}", index, e);
+                return None;
+            }
+        }
+        let signatures = self
+            .signer
+            .sign_block(BlockKind::Shard, index, &shard_block.block_hash())
+            .and_then(|shard_block_signature| {
+                let block_signature =
+                    self.signer.sign_block(BlockKind::Beacon, index, &block.block_hash())?;
+                Ok((shard_block_signature, block_signature))
+            });
+        let (shard_block_signature, block_signature) = match signatures {
+            Ok(signatures) => signatures,
+            Err(e) => {
+                warn!(target: "client", "Cannot sign the block {}: {}", index, e);
+                return None;
+            }
+        };
         for (i, authority) in authorities.iter().enumerate() {
-            if authority.account_id == self.signer.account_id {
+            if authority.account_id == self.account_id {
                 shard_block.add_signature(&shard_block_signature, i);
                 block.add_signature(&block_signature, i);
             }
@@ -161,8 +315,18 @@ impl Client {
             io::stdout().flush().expect("Could not flush stdout");
             None
         } else {
-            self.shard_chain.insert_block(&shard_block.clone(), transaction, tx_results, new_receipts);
+            let inserted = self.shard_chain.insert_block(
+                &shard_block.clone(),
+                transaction,
+                tx_results,
+                new_receipts,
+            );
+            if let Err(e) = inserted {
+                warn!(target: "client", "Cannot write the state of the block {}: {}", index, e);
+                return None;
+            }
             self.beacon_chain.chain.insert_block(block.clone());
+            self.remove_included_challenges(&block);
             info!(target: "client",
                   "Producing block index: {:?}, beacon = {:?}, shard = {:?}",
                   block.body.header.index, block.hash, shard_block.hash);
@@ -172,83 +336,71 @@ impl Client {
             assert_eq!(self.beacon_chain.chain.best_block().hash, block.hash);
             // Update the authority.
             self.update_authority(&block.header());
+            self.update_finality();
             Some((block, shard_block))
         }
     }
 
-    fn blocks_to_process(
-        &self,
-    ) -> (Vec<SignedBeaconBlock>, HashMap<CryptoHash, SignedBeaconBlock>) {
-        let mut part_add = vec![];
-        let mut part_pending = HashMap::default();
-        for (hash, other) in self.pending_beacon_blocks.write().drain() {
-            if self.beacon_chain.chain.is_known(&other.body.header.parent_hash)
-                && (self.shard_chain.chain.is_known(&other.body.header.shard_block_hash)
-                    || self
-                        .pending_shard_blocks
-                        .read()
-                        .contains_key(&other.body.header.shard_block_hash))
-            {
-                part_add.push(other);
-            } else {
-                part_pending.insert(hash, other);
-            }
-        }
-        (part_add, part_pending)
-    }
-
-    /// Attempts to import a beacon block. Fails to import if there are no known parent blocks.
-    /// If succeeds might unlock more blocks that were waiting for this parent. If import changes
-    /// the best block then it returns it, otherwise it returns None.
+    /// Attempts to import a beacon block. If its parent is not known yet it waits in the orphan
+    /// pool until the parent is imported. If succeeds might unlock more blocks that were waiting
+    /// for this parent. If import changes the best block then it returns it, otherwise it
+    /// returns None.
     pub fn import_blocks(
         &self,
         beacon_block: SignedBeaconBlock,
         shard_block: SignedShardBlock,
     ) -> Option<SignedBeaconBlock> {
-        // Check if this block was either already added, or it is already pending, or it has
-        // invalid signature.
+        // Check if this block was either already added, or it is already an orphan, or it
+        // doesn't come with its shard block.
         let hash = beacon_block.block_hash();
         info!(target: "client", "Importing block index: {:?}, beacon = {:?}, shard = {:?}", beacon_block.body.header.index, beacon_block.hash, shard_block.hash);
-        if self.beacon_chain.chain.is_known(&hash)
-            || self.pending_beacon_blocks.write().contains_key(&hash)
-        {
+        if beacon_block.body.header.shard_block_hash != shard_block.hash {
+            warn!(target: "client", "Shard block {:?} doesn't match beacon block {:?}", shard_block.hash, hash);
             return None;
         }
-        self.pending_shard_blocks.write().insert(shard_block.hash, shard_block);
-        self.pending_beacon_blocks.write().insert(hash, beacon_block);
+        let now = Instant::now();
+        {
+            let mut orphans = self.orphans.write();
+            orphans.remove_expired(now);
+            if self.beacon_chain.chain.is_known(&hash) || orphans.contains(&hash) {
+                return None;
+            }
+            if !self.beacon_chain.chain.is_known(&beacon_block.body.header.parent_hash) {
+                debug!(target: "client", "Block {:?} is an orphan, parent {:?} is not known", hash, beacon_block.body.header.parent_hash);
+                orphans.insert(beacon_block, shard_block, now);
+                return None;
+            }
+        }
         let best_block_hash = self.beacon_chain.chain.best_hash();
 
-        let mut blocks_to_add: Vec<SignedBeaconBlock> = vec![];
-        // Loop until we run out of blocks to add.
-        loop {
-            // Only keep those blocks in `pending_blocks` that are still pending.
-            // Otherwise put it in `blocks_to_add`.
-            let (part_add, part_pending) = self.blocks_to_process();
-            blocks_to_add.extend(part_add);
-            *self.pending_beacon_blocks.write() = part_pending;
-
-            // Get the next block to add, unless there are no more blocks left.
-            let next_beacon_block = match blocks_to_add.pop() {
-                Some(b) => b,
-                None => break,
-            };
+        // Add the block and then the orphans that were waiting for it, until we run out of
+        // blocks to add.
+        let mut blocks_to_add = vec![(beacon_block, shard_block)];
+        while let Some((next_beacon_block, next_shard_block)) = blocks_to_add.pop() {
             let hash = next_beacon_block.block_hash();
             if self.beacon_chain.chain.is_known(&hash) {
                 continue;
             }
-
-            let next_shard_block = self
-                .pending_shard_blocks
-                .write()
-                .remove(&next_beacon_block.body.header.shard_block_hash)
-                .expect("Expected to have shard block present when processing beacon block");
-
+            if let Err(err) = self.validate_blocks(&next_beacon_block, &next_shard_block) {
+                warn!(target: "client", "Rejecting block {:?}: {}", hash, err);
+                continue;
+            }
             if self.shard_chain.apply_block(&next_shard_block) {
                 self.beacon_chain.chain.insert_block(next_beacon_block.clone());
+                self.remove_included_challenges(&next_beacon_block);
+            } else if let Some(challenge) = self.shard_chain.challenge_block(&next_shard_block) {
+                info!(target: "client", "Challenging block {:?}, its state root is invalid", next_shard_block.hash);
+                if self.insert_challenge(challenge.clone()) {
+                    self.outgoing_challenges.write().push(challenge);
+                }
             }
             // Update the authority.
             self.update_authority(&next_beacon_block.header());
+            if self.beacon_chain.chain.is_known(&hash) {
+                blocks_to_add.extend(self.orphans.write().take_children(&hash));
+            }
         }
+        self.update_finality();
         let new_best_block = self.beacon_chain.chain.best_block();
         if new_best_block.block_hash() == best_block_hash {
             None
@@ -257,11 +409,280 @@ impl Client {
         }
     }
 
+    /// Checks the blocks against their parents, which are known, before applying them.
+    fn validate_blocks(
+        &self,
+        beacon_block: &SignedBeaconBlock,
+        shard_block: &SignedShardBlock,
+    ) -> Result<(), ValidationError> {
+        let parent_hash = beacon_block.body.header.parent_hash;
+        let parent = self
+            .beacon_chain
+            .chain
+            .get_header(&BlockId::Hash(parent_hash))
+            .expect("Parent of the block should be known");
+        // A block on top of the best one can't skip the heights that didn't pass yet, give or take
+        // one for the clock of its producer.
+        let skipped_heights = beacon_block.body.header.skipped_heights;
+        let elapsed = self.block_production.read().elapsed_heights(&parent_hash, Instant::now());
+        if let Some(elapsed_heights) = elapsed {
+            if skipped_heights > elapsed_heights + 1 {
+                return Err(ValidationError::EarlyBlock { skipped_heights, elapsed_heights });
+            }
+        }
+        let index = beacon_block.index();
+        let (authorities, next_authorities, next_epoch_authorities) = {
+            let authority = self.beacon_chain.authority.read();
+            let authorities = authority
+                .get_authorities(index)
+                .map_err(|_| ValidationError::UnknownAuthorities(index))?;
+            let next_authorities = authority.get_authorities(index + 1).ok();
+            (authorities, next_authorities, authority.next_epoch_authorities(index))
+        };
+        validation::validate_beacon_block(
+            &parent,
+            beacon_block,
+            &authorities,
+            next_authorities.as_ref().map(Vec::as_slice),
+            next_epoch_authorities.as_ref().map(Vec::as_slice),
+        )?;
+        let parent_shard = self
+            .shard_chain
+            .chain
+            .get_header(&BlockId::Hash(parent.body.shard_block_hash))
+            .ok_or_else(|| ValidationError::InvalidShardParent(shard_block.hash))?;
+        validation::validate_shard_block(&parent_shard, beacon_block, shard_block, &authorities)?;
+        self.validate_challenges(beacon_block)
+    }
+
+    /// Checks that the challenges of the block prove the challenged blocks invalid and that the
+    /// block slashes the authorities that signed them.
+    fn validate_challenges(&self, beacon_block: &SignedBeaconBlock) -> Result<(), ValidationError> {
+        for challenge in &beacon_block.body.challenges {
+            let hash = challenge.block.hash;
+            // Challenges the node already verified are not applied again.
+            let is_verified = self.challenges.read().get(&hash) == Some(challenge);
+            if !is_verified && !self.verify_challenge(challenge) {
+                return Err(ValidationError::InvalidChallenge(hash));
+            }
+        }
+        if self.slashed(&beacon_block.body.challenges) != beacon_block.body.header.slashed {
+            return Err(ValidationError::InvalidSlashed(beacon_block.hash));
+        }
+        Ok(())
+    }
+
+    /// Whether the challenge proves its block, signed by the authorities of its index, invalid.
+    fn verify_challenge(&self, challenge: &Challenge) -> bool {
+        let hash = challenge.block.hash;
+        let index = challenge.block.index();
+        let authorities = match self.beacon_chain.authority.read().get_authorities(index) {
+            Ok(authorities) => authorities,
+            Err(_) => {
+                debug!(target: "client", "Authorities of challenged block {:?} are unknown", hash);
+                return false;
+            }
+        };
+        match self.shard_chain.verify_challenge(challenge, &authorities) {
+            Ok(()) => true,
+            Err(err) => {
+                debug!(target: "client", "Challenge of block {:?} is invalid: {}", hash, err);
+                false
+            }
+        }
+    }
+
+    /// Authorities that signed the challenged block. Empty if the authorities of the block are
+    /// not known or their aggregated signature doesn't verify, so that the seats set in the mask
+    /// of a forged signature are not slashed.
+    fn offenders(&self, challenge: &Challenge) -> Vec<AccountId> {
+        let block = &challenge.block;
+        let authorities = match self.beacon_chain.authority.read().get_authorities(block.index()) {
+            Ok(authorities) => authorities,
+            Err(_) => return vec![],
+        };
+        if !block.signature.verify(&authorities, block.hash.as_ref()) {
+            return vec![];
+        }
+        let mask = &block.signature.authority_mask;
+        authorities
+            .into_iter()
+            .zip(mask)
+            .filter(|(_, signed)| **signed)
+            .map(|(authority, _)| authority.account_id)
+            .collect()
+    }
+
+    /// Sorted offenders of the challenges, which the block that includes them slashes.
+    fn slashed(&self, challenges: &[Challenge]) -> Vec<AccountId> {
+        let mut slashed: Vec<AccountId> =
+            challenges.iter().flat_map(|challenge| self.offenders(challenge)).collect();
+        slashed.sort();
+        slashed.dedup();
+        slashed
+    }
+
+    /// Adds a verified challenge to be included in the next block. Returns false if the block is
+    /// already challenged or none of its authorities signed it.
+    fn insert_challenge(&self, challenge: Challenge) -> bool {
+        let hash = challenge.block.hash;
+        if self.challenged_blocks.read().contains(&hash) || self.offenders(&challenge).is_empty() {
+            return false;
+        }
+        self.challenges.write().insert(hash, challenge).is_none()
+    }
+
+    /// Adds a challenge received from a peer. Returns false if it doesn't prove the block invalid
+    /// or the block is already challenged.
+    pub fn add_challenge(&self, challenge: Challenge) -> bool {
+        let hash = challenge.block.hash;
+        if self.challenges.read().contains_key(&hash)
+            || self.challenged_blocks.read().contains(&hash)
+        {
+            return false;
+        }
+        if !self.verify_challenge(&challenge) {
+            return false;
+        }
+        self.insert_challenge(challenge)
+    }
+
+    /// Takes the challenges the node created since the last call, to be sent to the peers.
+    pub fn take_outgoing_challenges(&self) -> Vec<Challenge> {
+        self.outgoing_challenges.write().drain(..).collect()
+    }
+
+    fn remove_included_challenges(&self, beacon_block: &SignedBeaconBlock) {
+        let mut challenges = self.challenges.write();
+        let mut challenged_blocks = self.challenged_blocks.write();
+        for challenge in &beacon_block.body.challenges {
+            challenges.remove(&challenge.block.hash);
+            challenged_blocks.insert(challenge.block.hash);
+        }
+    }
+
+    /// Hashes of the blocks the orphans are waiting for, to be requested from the peers.
+    pub fn missing_parents(&self) -> Vec<CryptoHash> {
+        self.orphans.read().missing_parents()
+    }
+
+    /// Whether it is the turn of the node to produce the block on top of the best one.
+    pub fn should_produce_block(&self, now: Instant) -> bool {
+        let best_block = self.beacon_chain.chain.best_block();
+        let index = best_block.index();
+        let mut block_production = self.block_production.write();
+        block_production.on_tip(index, best_block.hash, now);
+        let authority = self.beacon_chain.authority.read();
+        let authorities = authority.get_authorities(index).unwrap_or_default();
+        match authority.get_authorities(index + 1) {
+            Ok(next_authorities) => block_production.should_produce(
+                &self.account_id,
+                &authorities,
+                &next_authorities,
+                &best_block.body.header.randomness(),
+                now,
+            ),
+            Err(_) => false,
+        }
+    }
+
+    /// Endorses the best block if the node is one of its authorities. Returns the endorsement to
+    /// be sent to the other authorities, unless the block was already endorsed.
+    pub fn endorse_best_block(&self) -> Option<Endorsement> {
+        let best_block = self.beacon_chain.chain.best_block();
+        let index = best_block.index();
+        let authorities = self.beacon_chain.authority.read().get_authorities(index).ok()?;
+        if !authorities.iter().any(|a| a.account_id == self.account_id) {
+            return None;
+        }
+        let signature =
+            match self.signer.sign_block(BlockKind::Beacon, index, &best_block.block_hash()) {
+                Ok(signature) => signature,
+                Err(e) => {
+                    warn!(target: "client", "Cannot endorse the block {}: {}", index, e);
+                    return None;
+                }
+            };
+        let endorsement = Endorsement {
+            index,
+            block_hash: best_block.hash,
+            account_id: self.account_id.clone(),
+            signature,
+        };
+        if self.add_endorsement(endorsement.clone()) {
+            Some(endorsement)
+        } else {
+            None
+        }
+    }
+
+    /// Adds the endorsement of an authority. Returns false if it is not signed by an authority of
+    /// the endorsed block, old or already known.
+    pub fn add_endorsement(&self, endorsement: Endorsement) -> bool {
+        let authorities = self.beacon_chain.authority.read().get_authorities(endorsement.index);
+        let is_authority = match authorities {
+            Ok(authorities) => authorities.iter().any(|a| {
+                a.account_id == endorsement.account_id
+                    && a.bls_public_key
+                        .verify(endorsement.block_hash.as_ref(), &endorsement.signature)
+            }),
+            Err(_) => false,
+        };
+        is_authority && self.block_production.write().add_endorsement(endorsement)
+    }
+
+    /// Splits the shard block into the parts of the seats of its authorities and keeps the parts
+    /// of the seats of the node. Returns the parts to be sent to the other authorities.
+    pub fn distribute_parts(
+        &self,
+        shard_block: &SignedShardBlock,
+    ) -> Vec<(AccountId, ShardBlockPart)> {
+        let authorities = self
+            .beacon_chain
+            .authority
+            .read()
+            .get_authorities(shard_block.index())
+            .unwrap_or_default();
+        let mut parts = vec![];
+        for (part, authority) in shard_block.parts().into_iter().zip(authorities) {
+            if authority.account_id == self.account_id {
+                self.block_parts.write().insert(part);
+            } else {
+                parts.push((authority.account_id, part));
+            }
+        }
+        parts
+    }
+
+    /// Adds a part of a shard block. Returns false if the header doesn't commit to it or it is
+    /// already known.
+    pub fn add_block_part(&self, part: ShardBlockPart) -> bool {
+        part.verify() && self.block_parts.write().insert(part)
+    }
+
+    /// Parts of the shard block the node has, to be served to the peers.
+    pub fn get_block_parts(&self, hash: &CryptoHash) -> Vec<ShardBlockPart> {
+        self.block_parts.read().get(hash)
+    }
+
+    /// Reconstructs the shard block from the collected parts, if there are enough of them.
+    pub fn reconstruct_shard_block(&self, hash: &CryptoHash) -> Option<SignedShardBlock> {
+        self.block_parts.read().reconstruct(hash)
+    }
+
     // Authority-related code. Consider hiding it inside the shard chain.
     fn update_authority(&self, beacon_header: &SignedBeaconBlockHeader) {
         self.beacon_chain.authority.write().process_block_header(beacon_header);
     }
 
+    /// Moves the finality of both chains to the last block the authorities approved.
+    fn update_finality(&self) {
+        if let Some(header) = self.beacon_chain.update_finality() {
+            info!(target: "client", "Block #{} {:?} is final", header.body.index, header.hash);
+            self.shard_chain.finalize(&header.body.shard_block_hash);
+        }
+    }
+
     /// Returns own UID and UID to authority map for the given block number.
     /// If the owner is not participating in the block then it returns None.
     pub fn get_uid_to_authority_map(
@@ -284,8 +705,47 @@ impl Client {
         (owner_uid, uid_to_authority_map)
     }
 
+    /// Whether the node is one of the authorities of the next block.
+    pub fn is_authority(&self) -> bool {
+        let index = self.beacon_chain.chain.best_block().index() + 1;
+        match self.beacon_chain.authority.read().get_authorities(index) {
+            Ok(authorities) => authorities.iter().any(|a| a.account_id == self.account_id),
+            Err(_) => false,
+        }
+    }
+
+    /// Whether a peer announced a better beacon block than the best one of the node.
+    pub fn is_syncing(&self) -> bool {
+        let best_index = self.beacon_chain.chain.best_index();
+        self.network_info.read().peers.values().any(|peer| peer.best_index > best_index)
+    }
+
     pub fn get_recent_uid_to_authority_map(&self) -> HashMap<UID, AuthorityStake> {
         let index = self.beacon_chain.chain.best_block().index() + 1;
         self.get_uid_to_authority_map(index).1
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::get_client;
+
+    #[test]
+    fn test_is_syncing() {
+        let client = get_client();
+        assert!(!client.is_syncing());
+        let best_hash = client.beacon_chain.chain.best_block().block_hash();
+        let peer = |best_index| ConnectedPeer {
+            account_id: None,
+            protocol_version: 1,
+            node_version: "near/0.0.1".to_string(),
+            best_index,
+            best_hash,
+        };
+        client.network_info.write().peers.insert(0, peer(0));
+        assert!(!client.is_syncing());
+        client.network_info.write().peers.insert(1, peer(5));
+        assert!(client.is_syncing());
+    }
+}
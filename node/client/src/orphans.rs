@@ -0,0 +1,183 @@
+//! Blocks that arrived before their parents, e.g. because of a gossip race or while syncing.
+//! They wait until their parent is imported, in a limited number and for a limited time, so that
+//! blocks that never connect to the chain don't pile up.
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use beacon::types::SignedBeaconBlock;
+use chain::SignedShardBlock;
+use primitives::hash::CryptoHash;
+
+#[derive(Clone, Debug)]
+pub struct OrphanPoolConfig {
+    /// Max number of orphans, the oldest one is dropped to make room for a new one.
+    pub max_size: usize,
+    /// How long an orphan waits for its parent.
+    pub expiry: Duration,
+}
+
+impl Default for OrphanPoolConfig {
+    fn default() -> Self {
+        OrphanPoolConfig { max_size: 1024, expiry: Duration::from_secs(600) }
+    }
+}
+
+struct Orphan {
+    beacon_block: SignedBeaconBlock,
+    shard_block: SignedShardBlock,
+    received: Instant,
+}
+
+pub struct OrphanPool {
+    config: OrphanPoolConfig,
+    /// Orphans by the hash of their beacon block.
+    orphans: HashMap<CryptoHash, Orphan>,
+    /// Hashes of the orphans by the hash of their parent.
+    children: HashMap<CryptoHash, HashSet<CryptoHash>>,
+}
+
+impl OrphanPool {
+    pub fn new(config: OrphanPoolConfig) -> Self {
+        OrphanPool { config, orphans: HashMap::new(), children: HashMap::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty()
+    }
+
+    pub fn contains(&self, hash: &CryptoHash) -> bool {
+        self.orphans.contains_key(hash)
+    }
+
+    /// Adds the blocks, dropping the oldest orphan if the pool is full. Returns false if the
+    /// blocks are already in the pool.
+    pub fn insert(
+        &mut self,
+        beacon_block: SignedBeaconBlock,
+        shard_block: SignedShardBlock,
+        now: Instant,
+    ) -> bool {
+        let hash = beacon_block.hash;
+        if self.orphans.contains_key(&hash) {
+            return false;
+        }
+        if self.orphans.len() >= self.config.max_size {
+            let oldest = self.orphans.iter().min_by_key(|(_, o)| o.received).map(|(h, _)| *h);
+            if let Some(oldest) = oldest {
+                self.remove(&oldest);
+            }
+        }
+        let parent_hash = beacon_block.body.header.parent_hash;
+        self.children.entry(parent_hash).or_insert_with(HashSet::new).insert(hash);
+        self.orphans.insert(hash, Orphan { beacon_block, shard_block, received: now });
+        true
+    }
+
+    fn remove(&mut self, hash: &CryptoHash) -> Option<Orphan> {
+        let orphan = self.orphans.remove(hash)?;
+        let parent_hash = orphan.beacon_block.body.header.parent_hash;
+        let no_children = match self.children.get_mut(&parent_hash) {
+            Some(children) => {
+                children.remove(hash);
+                children.is_empty()
+            }
+            None => false,
+        };
+        if no_children {
+            self.children.remove(&parent_hash);
+        }
+        Some(orphan)
+    }
+
+    /// Takes the orphans whose parent is the given block, once it is imported.
+    pub fn take_children(
+        &mut self,
+        parent_hash: &CryptoHash,
+    ) -> Vec<(SignedBeaconBlock, SignedShardBlock)> {
+        let hashes = self.children.remove(parent_hash).unwrap_or_default();
+        hashes
+            .iter()
+            .filter_map(|hash| self.orphans.remove(hash))
+            .map(|orphan| (orphan.beacon_block, orphan.shard_block))
+            .collect()
+    }
+
+    /// Drops the orphans that waited for their parent for too long.
+    pub fn remove_expired(&mut self, now: Instant) {
+        let expiry = self.config.expiry;
+        let expired: Vec<CryptoHash> = self
+            .orphans
+            .iter()
+            .filter(|(_, orphan)| now > orphan.received + expiry)
+            .map(|(hash, _)| *hash)
+            .collect();
+        for hash in expired {
+            self.remove(&hash);
+        }
+    }
+
+    /// Hashes of the missing parents of the orphans, not counting the parents that are
+    /// orphans themselves.
+    pub fn missing_parents(&self) -> Vec<CryptoHash> {
+        self.children.keys().filter(|hash| !self.orphans.contains_key(hash)).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use primitives::types::MerkleHash;
+
+    use super::*;
+
+    fn blocks(index: u64, parent_hash: CryptoHash) -> (SignedBeaconBlock, SignedShardBlock) {
+        let shard_block = SignedShardBlock::new(
+            0,
+            index,
+            CryptoHash::default(),
+            MerkleHash::default(),
+            MerkleHash::default(),
+            vec![],
+            vec![],
+        );
+        let beacon_block =
+            SignedBeaconBlock::new(index, parent_hash, vec![], shard_block.hash, CryptoHash::default());
+        (beacon_block, shard_block)
+    }
+
+    #[test]
+    fn test_orphan_pool() {
+        let config = OrphanPoolConfig { max_size: 3, expiry: Duration::from_secs(10) };
+        let mut pool = OrphanPool::new(config);
+        let now = Instant::now();
+        let parent_hash = CryptoHash::default();
+        let (b1, s1) = blocks(1, parent_hash);
+        let (b2, s2) = blocks(2, b1.hash);
+        let (b3, s3) = blocks(3, b2.hash);
+        assert!(pool.insert(b3.clone(), s3.clone(), now));
+        assert!(pool.insert(b2.clone(), s2.clone(), now));
+        assert!(!pool.insert(b2.clone(), s2.clone(), now));
+        assert_eq!(pool.missing_parents(), vec![b1.hash]);
+
+        // The parent arrives and its children follow one by one.
+        assert_eq!(pool.take_children(&b1.hash), vec![(b2.clone(), s2)]);
+        assert_eq!(pool.take_children(&b2.hash), vec![(b3.clone(), s3.clone())]);
+        assert!(pool.is_empty());
+
+        // The oldest orphan makes room for a new one.
+        let later = now + Duration::from_secs(5);
+        pool.insert(b3.clone(), s3, now);
+        pool.insert(b2.clone(), blocks(2, b1.hash).1, later);
+        pool.insert(blocks(5, CryptoHash::default()).0, s1.clone(), later);
+        pool.insert(blocks(6, CryptoHash::default()).0, s1, later);
+        assert_eq!(pool.len(), 3);
+        assert!(!pool.contains(&b3.hash));
+
+        pool.remove_expired(later + Duration::from_secs(11));
+        assert!(pool.is_empty());
+        assert!(pool.missing_parents().is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+//! Erasure-coded parts of the recent shard blocks. The authorities keep the parts of their seats,
+//! so that the blocks stay available to the nodes that don't track the shard, and a block can be
+//! reconstructed from the parts collected from a third of the authorities.
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use chain::{ShardBlockPart, SignedShardBlock};
+use primitives::hash::CryptoHash;
+
+/// Number of the blocks whose parts are kept by default.
+pub const DEFAULT_MAX_BLOCKS: usize = 256;
+
+pub struct PartPool {
+    /// Max number of blocks, the parts of the oldest block are dropped to make room for new ones.
+    max_blocks: usize,
+    /// Parts by the hash of their block and their index.
+    parts: HashMap<CryptoHash, BTreeMap<u64, ShardBlockPart>>,
+    /// Hashes of the blocks in the order their first part arrived.
+    order: VecDeque<CryptoHash>,
+}
+
+impl PartPool {
+    pub fn new(max_blocks: usize) -> Self {
+        PartPool { max_blocks, parts: HashMap::new(), order: VecDeque::new() }
+    }
+
+    /// Adds a verified part. Returns false if the part is already known.
+    pub fn insert(&mut self, part: ShardBlockPart) -> bool {
+        let hash = part.header.hash;
+        if !self.parts.contains_key(&hash) {
+            if self.order.len() >= self.max_blocks {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.parts.remove(&oldest);
+                }
+            }
+            self.order.push_back(hash);
+        }
+        let parts = self.parts.entry(hash).or_insert_with(BTreeMap::new);
+        if parts.contains_key(&part.index) {
+            return false;
+        }
+        parts.insert(part.index, part);
+        true
+    }
+
+    /// Parts of the block the pool has.
+    pub fn get(&self, hash: &CryptoHash) -> Vec<ShardBlockPart> {
+        self.parts.get(hash).map(|parts| parts.values().cloned().collect()).unwrap_or_default()
+    }
+
+    /// Reconstructs the block, if the pool has enough of its parts.
+    pub fn reconstruct(&self, hash: &CryptoHash) -> Option<SignedShardBlock> {
+        let parts = self.get(hash);
+        let header = parts.first()?.header.clone();
+        SignedShardBlock::from_parts(header, &parts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use primitives::types::MerkleHash;
+
+    use super::*;
+
+    fn block(index: u64) -> SignedShardBlock {
+        SignedShardBlock::new_with_parts(
+            0,
+            index,
+            CryptoHash::default(),
+            MerkleHash::default(),
+            MerkleHash::default(),
+            vec![],
+            vec![],
+            4,
+        )
+    }
+
+    #[test]
+    fn test_part_pool() {
+        let mut pool = PartPool::new(2);
+        let (b1, b2, b3) = (block(1), block(2), block(3));
+        let parts = b1.parts();
+        assert!(pool.insert(parts[3].clone()));
+        assert!(!pool.insert(parts[3].clone()));
+        assert_eq!(pool.reconstruct(&b1.hash), None);
+        assert!(pool.insert(parts[1].clone()));
+        assert_eq!(pool.get(&b1.hash), vec![parts[1].clone(), parts[3].clone()]);
+        assert_eq!(pool.reconstruct(&b1.hash), Some(b1.clone()));
+
+        // The parts of the oldest block make room for the parts of new blocks.
+        assert!(pool.insert(b2.parts()[0].clone()));
+        assert!(pool.insert(b3.parts()[0].clone()));
+        assert!(pool.get(&b1.hash).is_empty());
+        assert_eq!(pool.get(&b3.hash).len(), 1);
+    }
+}
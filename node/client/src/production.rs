@@ -0,0 +1,219 @@
+//! Timing of the block production. The authorities of a block take turns producing it, in the
+//! order the randomness of the previous block gives, see `beacon::producer`, and the producer
+//! waits until more than two thirds of the stake endorsed the previous block, so that the new
+//! block builds on the block the authorities adopted. Every `max_delay` without a new block skips
+//! a height: the producer of that height is considered offline and the next authority in turn
+//! produces the block instead.
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use beacon::producer::producer_seat;
+use beacon::types::Endorsement;
+use primitives::hash::CryptoHash;
+use primitives::types::{AccountId, AuthorityStake};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProductionConfig {
+    /// Time between the blocks, even when all the endorsements arrive earlier.
+    pub min_delay: Duration,
+    /// Time the producer of a height has before the height is skipped.
+    pub max_delay: Duration,
+}
+
+impl Default for ProductionConfig {
+    fn default() -> Self {
+        ProductionConfig {
+            min_delay: Duration::from_millis(600),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+pub struct BlockProduction {
+    config: ProductionConfig,
+    /// Index and hash of the best block, which the next block builds on.
+    tip: (u64, CryptoHash),
+    /// When the best block was adopted, the heights are counted from it.
+    tip_received: Instant,
+    /// Endorsements of the best block and of the blocks after it, which may arrive before the
+    /// blocks themselves, by block hash and authority.
+    endorsements: HashMap<CryptoHash, HashMap<AccountId, Endorsement>>,
+}
+
+impl BlockProduction {
+    pub fn new(config: ProductionConfig, tip: (u64, CryptoHash), now: Instant) -> Self {
+        BlockProduction { config, tip, tip_received: now, endorsements: HashMap::new() }
+    }
+
+    pub fn tip(&self) -> (u64, CryptoHash) {
+        self.tip
+    }
+
+    /// Starts counting the heights from the new best block.
+    pub fn on_tip(&mut self, index: u64, hash: CryptoHash, now: Instant) {
+        if self.tip.1 == hash {
+            return;
+        }
+        self.tip = (index, hash);
+        self.tip_received = now;
+        self.endorsements.retain(|_, endorsements| {
+            endorsements.values().next().map_or(false, |e| e.index >= index)
+        });
+    }
+
+    /// Adds the endorsement, unless it endorses a block before the best one. Returns false if
+    /// the endorsement is old or already known.
+    pub fn add_endorsement(&mut self, endorsement: Endorsement) -> bool {
+        if endorsement.index < self.tip.0 {
+            return false;
+        }
+        let endorsements = self.endorsements.entry(endorsement.block_hash).or_default();
+        if endorsements.contains_key(&endorsement.account_id) {
+            return false;
+        }
+        endorsements.insert(endorsement.account_id.clone(), endorsement);
+        true
+    }
+
+    /// Whether more than two thirds of the stake of the authorities of the best block endorsed
+    /// it. The genesis has no authorities and needs no endorsements.
+    pub fn has_quorum(&self, authorities: &[AuthorityStake]) -> bool {
+        if authorities.is_empty() {
+            return true;
+        }
+        let endorsements = match self.endorsements.get(&self.tip.1) {
+            Some(endorsements) => endorsements,
+            None => return false,
+        };
+        let total: u128 = authorities.iter().map(|a| u128::from(a.amount)).sum();
+        let endorsed: u128 = authorities
+            .iter()
+            .filter(|a| endorsements.contains_key(&a.account_id))
+            .map(|a| u128::from(a.amount))
+            .sum();
+        endorsed * 3 > total * 2
+    }
+
+    /// Number of heights skipped since the best block because their producers were offline.
+    pub fn skipped_heights(&self, now: Instant) -> u64 {
+        let max_delay = millis(self.config.max_delay);
+        if max_delay == 0 {
+            return 0;
+        }
+        millis(now.duration_since(self.tip_received)) / max_delay
+    }
+
+    /// Heights that passed since the block was adopted as the best one, if it is the best one.
+    pub fn elapsed_heights(&self, hash: &CryptoHash, now: Instant) -> Option<u64> {
+        if self.tip.1 == *hash {
+            Some(self.skipped_heights(now))
+        } else {
+            None
+        }
+    }
+
+    /// Authority that produces the next block after the given number of skipped heights, given
+    /// the randomness of the best block.
+    pub fn producer<'a>(
+        &self,
+        next_authorities: &'a [AuthorityStake],
+        randomness: &CryptoHash,
+        skipped_heights: u64,
+    ) -> Option<&'a AuthorityStake> {
+        let seat = producer_seat(next_authorities.len(), randomness, skipped_heights)?;
+        next_authorities.get(seat)
+    }
+
+    /// Whether the account produces the next block now: it is its height, the previous block
+    /// waited for at least `min_delay` and the authorities endorsed it.
+    pub fn should_produce(
+        &self,
+        account_id: &AccountId,
+        authorities: &[AuthorityStake],
+        next_authorities: &[AuthorityStake],
+        randomness: &CryptoHash,
+        now: Instant,
+    ) -> bool {
+        if now.duration_since(self.tip_received) < self.config.min_delay {
+            return false;
+        }
+        let producer = self.producer(next_authorities, randomness, self.skipped_heights(now));
+        producer.map_or(false, |p| p.account_id == *account_id) && self.has_quorum(authorities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use primitives::aggregate_signature::{BlsSecretKey, BlsSignature};
+    use primitives::signature::get_key_pair;
+
+    use super::*;
+
+    fn authority(account_id: &str) -> AuthorityStake {
+        let public_key = get_key_pair().0;
+        let bls_public_key = BlsSecretKey::generate().get_public_key();
+        AuthorityStake { account_id: account_id.to_string(), public_key, bls_public_key, amount: 1 }
+    }
+
+    fn endorsement(index: u64, block_hash: CryptoHash, account_id: &str) -> Endorsement {
+        Endorsement {
+            index,
+            block_hash,
+            account_id: account_id.to_string(),
+            signature: BlsSignature::empty(),
+        }
+    }
+
+    #[test]
+    fn test_block_production() {
+        let config = ProductionConfig {
+            min_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        let now = Instant::now();
+        let tip = CryptoHash::default();
+        let mut production = BlockProduction::new(config, (3, tip), now);
+        let authorities = vec![authority("alice"), authority("bob"), authority("carol")];
+        let randomness = CryptoHash::new(&[2; 32]);
+        let producer = |skipped_heights| {
+            let producer = production.producer(&authorities, &randomness, skipped_heights);
+            producer.unwrap().account_id.clone()
+        };
+        let (first, second) = (producer(0), producer(1));
+        assert_ne!(first, second);
+        let later = now + Duration::from_millis(200);
+        let should_produce = |production: &BlockProduction, account_id: &AccountId, now| {
+            production.should_produce(account_id, &authorities, &authorities, &randomness, now)
+        };
+
+        // The first producer produces block 4 once two thirds endorsed block 3.
+        assert!(!should_produce(&production, &first, later));
+        assert!(production.add_endorsement(endorsement(3, tip, "alice")));
+        assert!(!production.add_endorsement(endorsement(3, tip, "alice")));
+        assert!(production.add_endorsement(endorsement(3, tip, "bob")));
+        assert!(!production.has_quorum(&authorities));
+        assert!(production.add_endorsement(endorsement(3, tip, "carol")));
+        assert!(!should_produce(&production, &first, now));
+        assert!(should_produce(&production, &first, later));
+        assert!(!should_produce(&production, &second, later));
+
+        // The first producer is offline, the second takes over once its height is skipped.
+        let skipped = now + Duration::from_millis(1500);
+        assert_eq!(production.skipped_heights(skipped), 1);
+        assert_eq!(production.elapsed_heights(&tip, skipped), Some(1));
+        assert!(!should_produce(&production, &first, skipped));
+        assert!(should_produce(&production, &second, skipped));
+
+        // Endorsements of the new best block may arrive before it.
+        let next_tip = CryptoHash::new(&[1; 32]);
+        assert!(production.add_endorsement(endorsement(4, next_tip, "alice")));
+        production.on_tip(4, next_tip, later);
+        assert!(!production.add_endorsement(endorsement(3, tip, "alice")));
+        assert_eq!(production.endorsements.len(), 1);
+        assert!(!production.has_quorum(&authorities));
+    }
+}
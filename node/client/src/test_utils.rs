@@ -1,8 +1,12 @@
 use parking_lot::RwLock;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Instant;
 
-use crate::Client;
+use crate::orphans::{OrphanPool, OrphanPoolConfig};
+use crate::parts::{PartPool, DEFAULT_MAX_BLOCKS};
+use crate::production::{BlockProduction, ProductionConfig};
+use crate::{Client, NetworkInfo};
 use beacon::types::{BeaconBlockChain, SignedBeaconBlock};
 use configs::ChainSpec;
 use node_runtime::test_utils::generate_test_chain_spec;
@@ -18,13 +22,21 @@ pub fn get_client_from_cfg(chain_spec: &ChainSpec, signer: InMemorySigner) -> Cl
     let shard_chain = ShardBlockChain::new(chain_spec, storage.clone());
     let genesis = SignedBeaconBlock::genesis(shard_chain.chain.genesis_hash);
     let beacon_chain = BeaconBlockChain::new(genesis, chain_spec, storage.clone());
+    let genesis_hash = beacon_chain.chain.genesis_hash;
+    let block_production =
+        BlockProduction::new(ProductionConfig::default(), (0, genesis_hash), Instant::now());
     Client {
         account_id: signer.account_id.clone(),
-        signer,
+        signer: Arc::new(signer),
         shard_chain,
         beacon_chain,
-        pending_beacon_blocks: RwLock::new(HashMap::new()),
-        pending_shard_blocks: RwLock::new(HashMap::new()),
+        network_info: RwLock::new(NetworkInfo::default()),
+        orphans: RwLock::new(OrphanPool::new(OrphanPoolConfig::default())),
+        block_production: RwLock::new(block_production),
+        challenges: RwLock::new(HashMap::new()),
+        challenged_blocks: RwLock::new(HashSet::new()),
+        outgoing_challenges: RwLock::new(vec![]),
+        block_parts: RwLock::new(PartPool::new(DEFAULT_MAX_BLOCKS)),
     }
 }
 
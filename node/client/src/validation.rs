@@ -0,0 +1,410 @@
+//! Checks of the blocks received from the peers, before their transactions are applied. The state
+//! and outcome roots depend on the results of the transactions, the shard chain checks them once
+//! it applied the block.
+use std::fmt;
+
+use beacon::light_client::{authorities_hash, epoch_authorities_hash};
+use beacon::producer::verify_producer;
+use beacon::types::{SignedBeaconBlock, SignedBeaconBlockHeader};
+use chain::types::total_parts;
+use chain::{SignedBlock, SignedHeader, SignedShardBlock, SignedShardBlockHeader};
+use primitives::hash::{hash_struct, CryptoHash};
+use primitives::merkle::merklize;
+use primitives::types::{AuthorityStake, GroupSignature};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ValidationError {
+    /// Hash of the block doesn't match its header.
+    InvalidHash(CryptoHash),
+    /// Beacon block doesn't commit to the shard block it comes with.
+    ShardBlockMismatch { beacon_hash: CryptoHash, shard_hash: CryptoHash },
+    /// Block doesn't follow its parent.
+    InvalidIndex { index: u64, parent_index: u64 },
+    /// Shard block doesn't follow the shard block of the parent beacon block.
+    InvalidShardParent(CryptoHash),
+    /// Authorities of the block are not known yet.
+    UnknownAuthorities(u64),
+    /// Authorities of the next block don't match the hash the block commits to.
+    InvalidAuthorities(CryptoHash),
+    /// Authorities of the next epoch don't match the hash the block commits to.
+    InvalidEpochAuthorities(CryptoHash),
+    /// Block is signed by more seats than there are authorities.
+    InvalidApprovals { approvals: usize, authorities: usize },
+    /// Block is not signed by any of the authorities of its index, so none of them produced it.
+    NotSigned(CryptoHash),
+    /// Aggregated signature is not the one of the seats that approved the block.
+    InvalidSignature(CryptoHash),
+    /// Block is not produced by the seat whose turn it was, or its VRF proof is invalid.
+    InvalidProducer(CryptoHash),
+    /// Block skips more heights than the time since its parent allows.
+    EarlyBlock { skipped_heights: u64, elapsed_heights: u64 },
+    /// Transactions of the shard block don't match its transaction root.
+    InvalidTransactionRoot(CryptoHash),
+    /// Receipts don't come from a shard block that precedes the block.
+    InvalidReceipts(CryptoHash),
+    /// Shard block is not split into a part per seat or its parts don't match its parts root.
+    InvalidParts(CryptoHash),
+    /// Challenges of the beacon block don't match its challenges root.
+    InvalidChallengesRoot(CryptoHash),
+    /// Challenge doesn't prove the challenged block invalid.
+    InvalidChallenge(CryptoHash),
+    /// Block doesn't slash the authorities that signed the blocks it challenges.
+    InvalidSlashed(CryptoHash),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::InvalidHash(hash) => {
+                write!(f, "Hash {:?} doesn't match the header", hash)
+            }
+            ValidationError::ShardBlockMismatch { beacon_hash, shard_hash } => write!(
+                f,
+                "Beacon block {:?} doesn't commit to shard block {:?}",
+                beacon_hash,
+                shard_hash,
+            ),
+            ValidationError::InvalidIndex { index, parent_index } => {
+                write!(f, "Block {} doesn't follow its parent {}", index, parent_index)
+            }
+            ValidationError::InvalidShardParent(hash) => {
+                write!(f, "Shard block {:?} doesn't follow the parent shard block", hash)
+            }
+            ValidationError::UnknownAuthorities(index) => {
+                write!(f, "Authorities of block {} are not known", index)
+            }
+            ValidationError::InvalidAuthorities(hash) => {
+                write!(f, "Authorities don't match the authorities hash {:?}", hash)
+            }
+            ValidationError::InvalidEpochAuthorities(hash) => {
+                write!(f, "Authorities don't match the epoch authorities hash {:?}", hash)
+            }
+            ValidationError::InvalidApprovals { approvals, authorities } => write!(
+                f,
+                "Block has approvals of {} seats, but there are {} authorities",
+                approvals,
+                authorities,
+            ),
+            ValidationError::NotSigned(hash) => {
+                write!(f, "Block {:?} is not signed by its authorities", hash)
+            }
+            ValidationError::InvalidSignature(hash) => {
+                write!(f, "Signature of block {:?} doesn't match the approving seats", hash)
+            }
+            ValidationError::InvalidProducer(hash) => {
+                write!(f, "Block {:?} is not produced by the authority in turn", hash)
+            }
+            ValidationError::EarlyBlock { skipped_heights, elapsed_heights } => write!(
+                f,
+                "Block skips {} heights, but only {} passed since its parent",
+                skipped_heights,
+                elapsed_heights,
+            ),
+            ValidationError::InvalidTransactionRoot(hash) => {
+                write!(f, "Transactions of shard block {:?} don't match its root", hash)
+            }
+            ValidationError::InvalidReceipts(hash) => {
+                write!(f, "Receipts of shard block {:?} don't precede the block", hash)
+            }
+            ValidationError::InvalidParts(hash) => {
+                write!(f, "Parts of shard block {:?} don't match its parts root", hash)
+            }
+            ValidationError::InvalidChallengesRoot(hash) => {
+                write!(f, "Challenges of block {:?} don't match its root", hash)
+            }
+            ValidationError::InvalidChallenge(hash) => {
+                write!(f, "Challenge doesn't prove block {:?} invalid", hash)
+            }
+            ValidationError::InvalidSlashed(hash) => {
+                write!(f, "Block {:?} doesn't slash the signers of the challenged blocks", hash)
+            }
+        }
+    }
+}
+
+/// Checks that the seats that signed the block are seats of its authorities, that at least one of
+/// them did, the one that produced the block, and that the aggregated signature is theirs.
+fn validate_signature(
+    hash: CryptoHash,
+    signature: &GroupSignature,
+    authorities: &[AuthorityStake],
+) -> Result<(), ValidationError> {
+    let mask = &signature.authority_mask;
+    if mask.len() > authorities.len() {
+        return Err(ValidationError::InvalidApprovals {
+            approvals: mask.len(),
+            authorities: authorities.len(),
+        });
+    }
+    if signature.authority_count() == 0 {
+        return Err(ValidationError::NotSigned(hash));
+    }
+    if !signature.verify(authorities, hash.as_ref()) {
+        return Err(ValidationError::InvalidSignature(hash));
+    }
+    Ok(())
+}
+
+/// Checks the beacon block against its parent. `authorities` are the authorities of the block,
+/// `next_authorities` those of the block after it, if they are known yet, and
+/// `next_epoch_authorities` those of the next epoch, if the block is the last of its epoch.
+pub fn validate_beacon_block(
+    parent: &SignedBeaconBlockHeader,
+    block: &SignedBeaconBlock,
+    authorities: &[AuthorityStake],
+    next_authorities: Option<&[AuthorityStake]>,
+    next_epoch_authorities: Option<&[Vec<AuthorityStake>]>,
+) -> Result<(), ValidationError> {
+    if hash_struct(&block.body.header) != block.hash {
+        return Err(ValidationError::InvalidHash(block.hash));
+    }
+    let header = &block.body.header;
+    if header.parent_hash != parent.block_hash() || header.index != parent.index() + 1 {
+        return Err(ValidationError::InvalidIndex {
+            index: header.index,
+            parent_index: parent.index(),
+        });
+    }
+    // The producer doesn't commit to the next authorities if it doesn't know them.
+    if let Some(next_authorities) = next_authorities {
+        if authorities_hash(next_authorities) != header.authorities_hash {
+            return Err(ValidationError::InvalidAuthorities(header.authorities_hash));
+        }
+    }
+    let expected = next_epoch_authorities.map(epoch_authorities_hash).unwrap_or_default();
+    if header.epoch_authorities_hash != expected {
+        return Err(ValidationError::InvalidEpochAuthorities(header.epoch_authorities_hash));
+    }
+    if merklize(&block.body.challenges).0 != header.challenges_root {
+        return Err(ValidationError::InvalidChallengesRoot(block.hash));
+    }
+    validate_signature(block.hash, &block.signature, authorities)?;
+    if !verify_producer(parent, &block.header(), authorities) {
+        return Err(ValidationError::InvalidProducer(block.hash));
+    }
+    Ok(())
+}
+
+/// Checks the shard block against the beacon block that commits to it and the shard block of the
+/// parent beacon block.
+pub fn validate_shard_block(
+    parent: &SignedShardBlockHeader,
+    beacon_block: &SignedBeaconBlock,
+    block: &SignedShardBlock,
+    authorities: &[AuthorityStake],
+) -> Result<(), ValidationError> {
+    if hash_struct(&block.body.header) != block.hash {
+        return Err(ValidationError::InvalidHash(block.hash));
+    }
+    if beacon_block.body.header.shard_block_hash != block.hash {
+        return Err(ValidationError::ShardBlockMismatch {
+            beacon_hash: beacon_block.hash,
+            shard_hash: block.hash,
+        });
+    }
+    let header = &block.body.header;
+    if header.parent_hash != parent.block_hash()
+        || header.index != beacon_block.index()
+        || header.shard_id != parent.body.shard_id
+    {
+        return Err(ValidationError::InvalidShardParent(block.hash));
+    }
+    if merklize(&block.body.transactions).0 != header.transaction_root {
+        return Err(ValidationError::InvalidTransactionRoot(block.hash));
+    }
+    if header.total_parts != total_parts(authorities.len()) || !block.verify_parts() {
+        return Err(ValidationError::InvalidParts(block.hash));
+    }
+    // Receipts are produced by the blocks before, the shard chain checks their results.
+    for receipt_block in &block.body.receipts {
+        let receipt_header = &receipt_block.header;
+        if hash_struct(&receipt_header.body) != receipt_header.hash
+            || receipt_header.index() >= header.index
+        {
+            return Err(ValidationError::InvalidReceipts(block.hash));
+        }
+    }
+    validate_signature(block.hash, &block.signature, authorities)
+}
+
+#[cfg(test)]
+mod tests {
+    use chain::Challenge;
+    use primitives::signature::get_key_pair;
+    use primitives::signer::InMemorySigner;
+    use primitives::traits::Signer;
+    use primitives::types::MerkleHash;
+    use primitives::vrf::VrfProof;
+    use transaction::SignedTransaction;
+
+    use super::*;
+
+    fn authority(signer: &InMemorySigner) -> AuthorityStake {
+        AuthorityStake {
+            account_id: signer.account_id.clone(),
+            public_key: get_key_pair().0,
+            bls_public_key: signer.public_key.clone(),
+            amount: 1,
+        }
+    }
+
+    /// Sets the VRF proof of the signer, the only authority.
+    fn set_producer(
+        block: &mut SignedBeaconBlock,
+        parent: &SignedBeaconBlock,
+        signer: &InMemorySigner,
+    ) {
+        let vrf_proof = signer.vrf_prove(&parent.body.header.randomness(), block.index()).unwrap();
+        block.set_producer(0, vrf_proof);
+    }
+
+    fn blocks(
+        parent: &SignedBeaconBlock,
+        parent_shard: &SignedShardBlock,
+        next_authorities: &[AuthorityStake],
+        signer: &InMemorySigner,
+    ) -> (SignedBeaconBlock, SignedShardBlock) {
+        let shard_block = SignedShardBlock::new(
+            0,
+            parent.index() + 1,
+            parent_shard.hash,
+            MerkleHash::default(),
+            MerkleHash::default(),
+            vec![],
+            vec![],
+        );
+        let mut beacon_block = SignedBeaconBlock::new(
+            parent.index() + 1,
+            parent.hash,
+            vec![],
+            shard_block.hash,
+            authorities_hash(next_authorities),
+        );
+        set_producer(&mut beacon_block, parent, signer);
+        (beacon_block, shard_block)
+    }
+
+    #[test]
+    fn test_validate_blocks() {
+        let signer = InMemorySigner::default();
+        let authorities = vec![authority(&signer)];
+        let genesis_shard = SignedShardBlock::genesis(MerkleHash::default());
+        let genesis = SignedBeaconBlock::genesis(genesis_shard.hash);
+        let (mut block, mut shard_block) = blocks(&genesis, &genesis_shard, &authorities, &signer);
+        let validate = |block: &SignedBeaconBlock,
+                        shard_block: &SignedShardBlock|
+         -> Result<(), ValidationError> {
+            let next_authorities = Some(authorities.as_slice());
+            validate_beacon_block(&genesis.header(), block, &authorities, next_authorities, None)?;
+            validate_shard_block(&genesis_shard.header(), block, shard_block, &authorities)
+        };
+        assert_eq!(validate(&block, &shard_block), Err(ValidationError::NotSigned(block.hash)));
+
+        block.add_signature(&block.sign(&signer), 0);
+        shard_block.add_signature(&shard_block.sign(&signer), 0);
+        assert_eq!(validate(&block, &shard_block), Ok(()));
+
+        // The block is produced without the VRF proof.
+        let mut unproven = block.clone();
+        unproven.set_producer(0, VrfProof::empty());
+        unproven.signature = Default::default();
+        unproven.add_signature(&unproven.sign(&signer), 0);
+        assert_eq!(
+            validate(&unproven, &shard_block),
+            Err(ValidationError::InvalidProducer(unproven.hash))
+        );
+
+        // The producer signs for a seat that doesn't exist.
+        let mut extra_seat = block.clone();
+        extra_seat.add_signature(&block.sign(&signer), 1);
+        assert_eq!(
+            validate(&extra_seat, &shard_block),
+            Err(ValidationError::InvalidApprovals { approvals: 2, authorities: 1 })
+        );
+
+        // The seat is signed by another key.
+        let mut forged = blocks(&genesis, &genesis_shard, &authorities, &signer).0;
+        forged.add_signature(&forged.sign(&InMemorySigner::default()), 0);
+        assert_eq!(
+            validate(&forged, &shard_block),
+            Err(ValidationError::InvalidSignature(forged.hash))
+        );
+
+        // The block commits to other authorities.
+        let (mut other, _) = blocks(&genesis, &genesis_shard, &[], &signer);
+        other.add_signature(&other.sign(&signer), 0);
+        assert_eq!(
+            validate(&other, &shard_block),
+            Err(ValidationError::InvalidAuthorities(authorities_hash(&[])))
+        );
+
+        // The block is not the last of its epoch, but commits to the authorities of the next one.
+        let mut committed = SignedBeaconBlock::new_with_challenges(
+            1,
+            genesis.hash,
+            vec![],
+            shard_block.hash,
+            authorities_hash(&authorities),
+            epoch_authorities_hash(&[authorities.clone()]),
+            vec![],
+            vec![],
+        );
+        committed.add_signature(&committed.sign(&signer), 0);
+        let hash = committed.body.header.epoch_authorities_hash;
+        assert_eq!(
+            validate(&committed, &shard_block),
+            Err(ValidationError::InvalidEpochAuthorities(hash))
+        );
+
+        // The challenges are not the ones the header commits to.
+        let mut challenged = block.clone();
+        challenged.body.challenges.push(Challenge {
+            parent_header: genesis_shard.header(),
+            block: shard_block.clone(),
+            witness: vec![],
+        });
+        assert_eq!(
+            validate(&challenged, &shard_block),
+            Err(ValidationError::InvalidChallengesRoot(block.hash))
+        );
+
+        // The block is built on top of itself.
+        let (mut child, mut child_shard) = blocks(&block, &shard_block, &authorities, &signer);
+        child.add_signature(&child.sign(&signer), 0);
+        child_shard.add_signature(&child_shard.sign(&signer), 0);
+        assert_eq!(
+            validate(&child, &child_shard),
+            Err(ValidationError::InvalidIndex { index: 2, parent_index: 0 })
+        );
+
+        // The transactions are not the ones the header commits to.
+        let mut tampered = shard_block.clone();
+        tampered.body.transactions.push(SignedTransaction::empty());
+        assert_eq!(
+            validate(&block, &tampered),
+            Err(ValidationError::InvalidTransactionRoot(tampered.hash))
+        );
+
+        // The block is not split into a part per authority.
+        let mut tampered = shard_block.clone();
+        tampered.body.header.total_parts = 2;
+        tampered.hash = hash_struct(&tampered.body.header);
+        let mut committed = SignedBeaconBlock::new(
+            1,
+            genesis.hash,
+            vec![],
+            tampered.hash,
+            authorities_hash(&authorities),
+        );
+        set_producer(&mut committed, &genesis, &signer);
+        committed.add_signature(&committed.sign(&signer), 0);
+        assert_eq!(
+            validate(&committed, &tampered),
+            Err(ValidationError::InvalidParts(tampered.hash))
+        );
+
+        let mut tampered = shard_block.clone();
+        tampered.body.header.index = 2;
+        assert_eq!(validate(&block, &tampered), Err(ValidationError::InvalidHash(tampered.hash)));
+    }
+}
@@ -16,9 +16,10 @@ pub fn get_authority_config(chain_spec: &ChainSpec) -> AuthorityConfig {
     let initial_authorities: Vec<AuthorityStake> = chain_spec
         .initial_authorities
         .iter()
-        .map(|(account_id, key, amount)| AuthorityStake {
+        .map(|(account_id, key, bls_key, amount)| AuthorityStake {
             account_id: account_id.clone(),
             public_key: key.into(),
+            bls_public_key: bls_key.into(),
             amount: *amount,
         })
         .collect();
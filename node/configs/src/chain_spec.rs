@@ -4,7 +4,7 @@ use std::path::PathBuf;
 
 use serde_json;
 
-use primitives::types::{AccountId, Balance, ReadablePublicKey};
+use primitives::types::{AccountId, Balance, BlockIndex, ReadableBlsPublicKey, ReadablePublicKey};
 
 /// Specification of the blockchain in general.
 pub struct ChainSpec {
@@ -14,24 +14,30 @@ pub struct ChainSpec {
     /// Genesis smart contract code.
     pub genesis_wasm: Vec<u8>,
 
-    /// Genesis state authorities that bootstrap the chain.
-    pub initial_authorities: Vec<(AccountId, ReadablePublicKey, Balance)>,
+    /// Genesis state authorities that bootstrap the chain: (AccountId, PK, BLS PK, Stake)
+    pub initial_authorities: Vec<(AccountId, ReadablePublicKey, ReadableBlsPublicKey, Balance)>,
 
     pub beacon_chain_epoch_length: u64,
     pub beacon_chain_num_seats_per_slot: u64,
 
     pub boot_nodes: Vec<String>,
+
+    /// Number of blocks after the block a transaction references during which the transaction
+    /// is valid. `None` disables the expiry.
+    pub transaction_validity_period: Option<BlockIndex>,
 }
 
 #[derive(Serialize, Deserialize)]
 #[serde(remote = "ChainSpec")]
 struct ChainSpecRef {
     accounts: Vec<(AccountId, ReadablePublicKey, u64, u64)>,
-    initial_authorities: Vec<(AccountId, ReadablePublicKey, u64)>,
+    initial_authorities: Vec<(AccountId, ReadablePublicKey, ReadableBlsPublicKey, u64)>,
     genesis_wasm: Vec<u8>,
     beacon_chain_epoch_length: u64,
     beacon_chain_num_seats_per_slot: u64,
     boot_nodes: Vec<String>,
+    #[serde(default)]
+    transaction_validity_period: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -71,15 +77,26 @@ pub fn read_or_default_chain_spec(chain_spec_path: &Option<PathBuf>) -> ChainSpe
 fn test_deserialize() {
     let data = json!({
         "accounts": [["alice.near", "6fgp5mkRgsTWfd5UWw1VwHbNLLDYeLxrxw3jrkCeXNWq", 100, 10]],
-        "initial_authorities": [("alice.near", "6fgp5mkRgsTWfd5UWw1VwHbNLLDYeLxrxw3jrkCeXNWq", 50)],
+        "initial_authorities": [(
+            "alice.near",
+            "6fgp5mkRgsTWfd5UWw1VwHbNLLDYeLxrxw3jrkCeXNWq",
+            "6VddMZHbtwkzwHnG1S8vFjYm9TB3gS6UMREA1tJVb8muJ4MgQmsc1Mxv8wHAKBvonC",
+            50,
+        )],
         "genesis_wasm": [0,1],
         "beacon_chain_epoch_length": 10,
         "beacon_chain_num_seats_per_slot": 100,
         "boot_nodes": [],
     });
     let spec = deserialize_chain_spec(&data.to_string());
+    assert_eq!(spec.transaction_validity_period, None);
     assert_eq!(
         spec.initial_authorities[0],
-        ("alice.near".to_string(), "6fgp5mkRgsTWfd5UWw1VwHbNLLDYeLxrxw3jrkCeXNWq".to_string(), 50)
+        (
+            "alice.near".to_string(),
+            "6fgp5mkRgsTWfd5UWw1VwHbNLLDYeLxrxw3jrkCeXNWq".to_string(),
+            "6VddMZHbtwkzwHnG1S8vFjYm9TB3gS6UMREA1tJVb8muJ4MgQmsc1Mxv8wHAKBvonC".to_string(),
+            50
+        )
     );
 }
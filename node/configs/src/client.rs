@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
 use clap::{Arg, ArgMatches};
 
@@ -9,6 +10,9 @@ use primitives::types::AccountId;
 
 const DEFAULT_BASE_PATH: &str = ".";
 const DEFAULT_LOG_LEVEL: &str = "Info";
+const DEFAULT_EVENT_BUS_TOPIC_PREFIX: &str = "near";
+const DEFAULT_MIN_BLOCK_PRODUCTION_DELAY_MS: &str = "600";
+const DEFAULT_MAX_BLOCK_PRODUCTION_DELAY_MS: &str = "2000";
 
 pub struct ClientConfig {
     pub base_path: PathBuf,
@@ -16,6 +20,23 @@ pub struct ClientConfig {
     pub public_key: Option<String>,
     pub chain_spec: ChainSpec,
     pub log_level: log::LevelFilter,
+    /// Whether pending transactions are kept in the storage across restarts.
+    pub persist_transaction_pool: bool,
+    /// Postgres database the blocks are exported into for explorers. No export if not set.
+    pub explorer_db_url: Option<String>,
+    /// Event bus the blocks are published to, e.g. `kafka://host:9092`. Not published if not set.
+    pub event_bus_url: Option<String>,
+    /// Prefix of the event bus topics, e.g. `near` for `near.blocks`.
+    pub event_bus_topic_prefix: String,
+    /// Time between the blocks, even when the authorities endorse the previous block earlier.
+    pub min_block_production_delay: Duration,
+    /// Time the producer of a block has before the next authority in turn produces it instead.
+    pub max_block_production_delay: Duration,
+    /// Address of the service that signs the blocks, e.g. `10.0.0.2:3040`. The blocks are signed
+    /// with the key from the keystore if not set.
+    pub remote_signer_addr: Option<String>,
+    /// File with the key shared with the signing service, which authenticates it and the node.
+    pub remote_signer_auth_key_file: Option<PathBuf>,
 }
 
 impl Default for ClientConfig {
@@ -26,6 +47,18 @@ impl Default for ClientConfig {
             public_key: None,
             chain_spec: read_or_default_chain_spec(&None),
             log_level: log::LevelFilter::Info,
+            persist_transaction_pool: false,
+            explorer_db_url: None,
+            event_bus_url: None,
+            event_bus_topic_prefix: DEFAULT_EVENT_BUS_TOPIC_PREFIX.to_string(),
+            min_block_production_delay: Duration::from_millis(
+                DEFAULT_MIN_BLOCK_PRODUCTION_DELAY_MS.parse().unwrap(),
+            ),
+            max_block_production_delay: Duration::from_millis(
+                DEFAULT_MAX_BLOCK_PRODUCTION_DELAY_MS.parse().unwrap(),
+            ),
+            remote_signer_addr: None,
+            remote_signer_auth_key_file: None,
         }
     }
 }
@@ -67,6 +100,49 @@ pub fn get_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
             .possible_values(&["Debug", "Info", "Warn"])
             .default_value(DEFAULT_LOG_LEVEL)
             .takes_value(true),
+        Arg::with_name("persist_transaction_pool")
+            .long("persist-transaction-pool")
+            .help("Keep pending transactions in the storage, so that they survive restarts.")
+            .takes_value(false),
+        Arg::with_name("explorer_db_url")
+            .long("explorer-db-url")
+            .value_name("URL")
+            .help("Export the blocks into the Postgres database, e.g. postgres://user@host/db.")
+            .takes_value(true),
+        Arg::with_name("event_bus_url")
+            .long("event-bus-url")
+            .value_name("URL")
+            .help("Publish the blocks to the event bus, kafka://host:port,.. or nats://host:port.")
+            .takes_value(true),
+        Arg::with_name("event_bus_topic_prefix")
+            .long("event-bus-topic-prefix")
+            .value_name("PREFIX")
+            .help("Prefix of the event bus topics.")
+            .default_value(DEFAULT_EVENT_BUS_TOPIC_PREFIX)
+            .takes_value(true),
+        Arg::with_name("min_block_production_delay")
+            .long("min-block-production-delay")
+            .value_name("MILLIS")
+            .help("Minimum time between the blocks, in milliseconds.")
+            .default_value(DEFAULT_MIN_BLOCK_PRODUCTION_DELAY_MS)
+            .takes_value(true),
+        Arg::with_name("max_block_production_delay")
+            .long("max-block-production-delay")
+            .value_name("MILLIS")
+            .help("Time the producer of a block has before its turn is skipped, in milliseconds.")
+            .default_value(DEFAULT_MAX_BLOCK_PRODUCTION_DELAY_MS)
+            .takes_value(true),
+        Arg::with_name("remote_signer_addr")
+            .long("remote-signer-addr")
+            .value_name("HOST:PORT")
+            .help("Sign the blocks with the signing service instead of the key in the keystore.")
+            .requires("remote_signer_auth_key_file")
+            .takes_value(true),
+        Arg::with_name("remote_signer_auth_key_file")
+            .long("remote-signer-auth-key-file")
+            .value_name("PATH")
+            .help("File with the key shared with the signing service.")
+            .takes_value(true),
     ]
 }
 
@@ -75,8 +151,35 @@ pub fn from_matches(matches: &ArgMatches) -> ClientConfig {
     let account_id = matches.value_of("account_id").map(String::from).unwrap();
     let public_key = matches.value_of("public_key").map(String::from);
     let log_level = matches.value_of("log_level").map(log::LevelFilter::from_str).unwrap().unwrap();
+    let persist_transaction_pool = matches.is_present("persist_transaction_pool");
+    let explorer_db_url = matches.value_of("explorer_db_url").map(String::from);
+    let event_bus_url = matches.value_of("event_bus_url").map(String::from);
+    let event_bus_topic_prefix =
+        matches.value_of("event_bus_topic_prefix").map(String::from).unwrap();
+    let millis = |name| {
+        matches.value_of(name).map(|x| Duration::from_millis(x.parse::<u64>().unwrap())).unwrap()
+    };
+    let min_block_production_delay = millis("min_block_production_delay");
+    let max_block_production_delay = millis("max_block_production_delay");
+    let remote_signer_addr = matches.value_of("remote_signer_addr").map(String::from);
+    let remote_signer_auth_key_file =
+        matches.value_of("remote_signer_auth_key_file").map(PathBuf::from);
 
     let chain_spec_path = matches.value_of("chain_spec_file").map(PathBuf::from);
     let chain_spec = read_or_default_chain_spec(&chain_spec_path);
-    ClientConfig { base_path, account_id, public_key, chain_spec, log_level }
+    ClientConfig {
+        base_path,
+        account_id,
+        public_key,
+        chain_spec,
+        log_level,
+        persist_transaction_pool,
+        explorer_db_url,
+        event_bus_url,
+        event_bus_topic_prefix,
+        min_block_production_delay,
+        max_block_production_delay,
+        remote_signer_addr,
+        remote_signer_auth_key_file,
+    }
 }
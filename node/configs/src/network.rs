@@ -2,15 +2,25 @@ use clap::{Arg, ArgMatches};
 use std::iter;
 use std::mem;
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
 
 use crate::ClientConfig;
 use libp2p::Multiaddr;
 use substrate_network_libp2p::{NetworkConfiguration, Protocol, Secret};
 
 const DEFAULT_P2P_PORT: &str = "30333";
+const DEFAULT_OUT_PEERS: &str = "8";
 const NETWORK_CONFIG_PATH: &str = "storage";
+const NODE_KEY_FILE: &str = "node_key.json";
 
-pub type NetworkConfig = NetworkConfiguration;
+pub struct NetworkConfig {
+    /// Config of the libp2p service.
+    pub service: NetworkConfiguration,
+    /// File of the key the node authenticates the connections with, generated if missing.
+    pub node_key_path: PathBuf,
+    /// Whether to map the listening port on the gateway with UPnP or NAT-PMP.
+    pub port_mapping: bool,
+}
 
 pub fn get_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
     vec![
@@ -28,6 +38,20 @@ pub fn get_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
             .help("Specify a list of boot nodes.")
             .multiple(true)
             .takes_value(true),
+        Arg::with_name("out_peers")
+            .long("out-peers")
+            .value_name("NUM")
+            .help("Number of connections to keep, dialing the peers learned from the other peers.")
+            .default_value(DEFAULT_OUT_PEERS)
+            .takes_value(true),
+        Arg::with_name("node_key_path")
+            .long("node-key-path")
+            .value_name("PATH")
+            .help("File of the node key, generated if missing. Defaults to storage/node_key.json.")
+            .takes_value(true),
+        Arg::with_name("no_port_mapping")
+            .long("no-port-mapping")
+            .help("Don't ask the gateway to forward the p2p port with UPnP or NAT-PMP."),
         Arg::with_name("test_network_key_seed")
             .long("test-network-key-seed")
             .value_name("TEST_NETWORK_KEY_SEED")
@@ -57,6 +81,7 @@ pub fn get_test_secret_from_network_key_seed(test_network_key_seed: u32) -> Secr
 
 pub fn from_matches(client_config: &ClientConfig, matches: &ArgMatches) -> NetworkConfig {
     let p2p_port = matches.value_of("p2p_port").map(|x| x.parse::<u16>().unwrap()).unwrap();
+    let out_peers = matches.value_of("out_peers").map(|x| x.parse::<u32>().unwrap()).unwrap();
     let test_network_key_seed =
         matches.value_of("test_network_key_seed").map(|x| x.parse::<u32>().unwrap());
 
@@ -77,7 +102,13 @@ pub fn from_matches(client_config: &ClientConfig, matches: &ArgMatches) -> Netwo
     network_config.net_config_path = Some(network_config_path.to_string_lossy().to_string());
     network_config.boot_nodes = boot_nodes;
     network_config.listen_addresses = vec![get_multiaddr(Ipv4Addr::UNSPECIFIED, p2p_port)];
+    network_config.out_peers = out_peers;
 
     network_config.use_secret = test_network_key_seed.map(get_test_secret_from_network_key_seed);
-    network_config
+    let node_key_path = matches
+        .value_of("node_key_path")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| network_config_path.join(NODE_KEY_FILE));
+    let port_mapping = !matches.is_present("no_port_mapping");
+    NetworkConfig { service: network_config, node_key_path, port_mapping }
 }
@@ -4,24 +4,36 @@ const DEFAULT_RPC_PORT: &str = "3030";
 
 pub struct RPCConfig {
     pub rpc_port: u16,
+    /// Port of the gRPC server, which is not started if not set.
+    pub grpc_port: Option<u16>,
 }
 
 impl Default for RPCConfig {
     fn default() -> Self {
-        Self { rpc_port: DEFAULT_RPC_PORT.parse::<u16>().unwrap() }
+        Self { rpc_port: DEFAULT_RPC_PORT.parse::<u16>().unwrap(), grpc_port: None }
     }
 }
 
 pub fn get_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
-    vec![Arg::with_name("rpc_port")
-        .short("r")
-        .long("rpc_port")
-        .value_name("PORT")
-        .help("Specify the rpc protocol TCP port.")
-        .default_value(DEFAULT_RPC_PORT)
-        .takes_value(true)]
+    vec![
+        Arg::with_name("rpc_port")
+            .short("r")
+            .long("rpc_port")
+            .value_name("PORT")
+            .help("Specify the rpc protocol TCP port.")
+            .default_value(DEFAULT_RPC_PORT)
+            .takes_value(true),
+        Arg::with_name("grpc_port")
+            .long("grpc_port")
+            .value_name("PORT")
+            .help("Specify the gRPC TCP port. The gRPC server is disabled if not set.")
+            .takes_value(true),
+    ]
 }
 
 pub fn from_matches(matches: &ArgMatches) -> RPCConfig {
-    RPCConfig { rpc_port: matches.value_of("rpc_port").map(|x| x.parse::<u16>().unwrap()).unwrap() }
+    RPCConfig {
+        rpc_port: matches.value_of("rpc_port").map(|x| x.parse::<u16>().unwrap()).unwrap(),
+        grpc_port: matches.value_of("grpc_port").map(|x| x.parse::<u16>().unwrap()),
+    }
 }
@@ -25,6 +25,7 @@ mod tests {
     use futures::sync::mpsc::channel;
     use futures::{lazy, stream};
     use primitives::signature::DEFAULT_SIGNATURE;
+    use primitives::hash::CryptoHash;
     use transaction::{SendMoneyTransaction, SignedTransaction, TransactionBody};
 
     #[test]
@@ -44,6 +45,11 @@ mod tests {
                     originator: "alice.near".to_string(),
                     receiver: "bob".to_string(),
                     amount: i,
+                    block_hash: CryptoHash::default(),
+                    genesis_hash: CryptoHash::default(),
+                    execute_at: 0,
+                    tip: 0,
+                    memo: vec![],
                 };
                 let t = TransactionBody::SendMoney(t);
                 transactions.push(SignedTransaction::new(DEFAULT_SIGNATURE, t));
@@ -7,8 +7,10 @@ extern crate log;
 extern crate primitives;
 extern crate shard;
 extern crate tokio;
+extern crate transaction;
 extern crate txflow;
 
 pub mod producer;
 pub mod importer;
+pub mod rebroadcaster;
 mod control_builder;
@@ -1,23 +1,48 @@
 //! ConsensusHandler consumes consensuses, retrieves the most recent state, computes the new
-//! state, signs it and puts in on the BeaconChain.
+//! state, signs it and puts in on the BeaconChain. The consensuses are merged until it is the turn
+//! of the node to produce the block, see `client::production`.
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use futures::sync::mpsc::{Receiver, Sender};
 use futures::{future, Future, Sink, Stream};
+use tokio::timer::Interval;
 
 use crate::control_builder::get_control;
-use beacon::types::SignedBeaconBlock;
+use beacon::types::{Endorsement, SignedBeaconBlock};
 use chain::{SignedBlock, SignedHeader, SignedShardBlock, ReceiptBlock};
 use client::{ChainConsensusBlockBody, Client};
 use txflow::txflow_task::beacon_witness_selector::BeaconWitnessSelector;
 use txflow::txflow_task::Control;
 
+/// How often the producer checks whether it is its turn to produce the block.
+const PRODUCTION_CHECK_PERIOD_MS: u64 = 50;
+
+enum Event {
+    Body(ChainConsensusBlockBody),
+    Tick,
+}
+
+fn send_endorsement(client: &Client, endorsement_tx: &Sender<Endorsement>) {
+    if let Some(endorsement) = client.endorse_best_block() {
+        tokio::spawn(
+            endorsement_tx
+                .clone()
+                .send(endorsement)
+                .map(|_| ())
+                // In DevNet there is no network to send the endorsement to.
+                .map_err(|_| ()),
+        );
+    }
+}
+
 pub fn spawn_block_producer(
     client: Arc<Client>,
     receiver: Receiver<ChainConsensusBlockBody>,
     block_announce_tx: Sender<(SignedBeaconBlock, SignedShardBlock)>,
     new_receipts_tx: Sender<ReceiptBlock>,
     control_tx: Sender<Control<BeaconWitnessSelector>>,
+    endorsement_tx: Sender<Endorsement>,
 ) {
     let control = get_control(&*client, client.beacon_chain.chain.best_block().header().index() + 1);
     let kickoff_task = control_tx
@@ -25,10 +50,61 @@ pub fn spawn_block_producer(
         .send(control)
         .map(|_| ())
         .map_err(|e| error!("Error sending kick-off control to TxFlow: {}", e));
+    send_endorsement(&client, &endorsement_tx);
 
+    let ticks = Interval::new_interval(Duration::from_millis(PRODUCTION_CHECK_PERIOD_MS))
+        .map(|_| Event::Tick)
+        .map_err(|e| error!("Block production timer failed: {}", e));
+    // Consensuses received while waiting for the turn of the node.
+    let mut pending: Option<ChainConsensusBlockBody> = None;
+    let mut best_hash = client.beacon_chain.chain.best_hash();
     let task = receiver
-        .for_each(move |body| {
+        .map(Event::Body)
+        .select(ticks)
+        .for_each(move |event| {
+            if let Event::Body(body) = event {
+                match pending {
+                    Some(ref mut pending) => pending.messages.extend(body.messages),
+                    None => pending = Some(body),
+                }
+            }
+
+            let best_block = client.beacon_chain.chain.best_block();
+            if best_block.hash != best_hash {
+                // A block from a peer became the best one. Its producer already included the
+                // consensus, the transactions that it didn't include go back to the pool.
+                best_hash = best_block.hash;
+                if let Some(body) = pending.take() {
+                    let transactions = body
+                        .messages
+                        .into_iter()
+                        .flat_map(|message| message.body.payload.transactions)
+                        .collect();
+                    client.shard_chain.add_transactions(transactions);
+                }
+                send_endorsement(&client, &endorsement_tx);
+                let control = get_control(&*client, best_block.header().index() + 1);
+                tokio::spawn(
+                    control_tx
+                        .clone()
+                        .send(control)
+                        .map(|_| ())
+                        .map_err(|e| error!("Error sending control to TxFlow: {}", e)),
+                );
+            }
+
+            // Checked on every tick, so that the heights are counted from when the tip changed.
+            let should_produce = client.should_produce_block(Instant::now());
+            let mut body = match pending.take() {
+                Some(body) if should_produce => body,
+                body => {
+                    pending = body;
+                    return future::ok(());
+                }
+            };
+            body.beacon_block_index = best_block.header().index() + 1;
             if let Some((new_beacon_block, new_shard_block)) = client.produce_block(body) {
+                best_hash = new_beacon_block.hash;
                 // Send beacon block to network
                 tokio::spawn({
                     block_announce_tx
@@ -39,6 +115,7 @@ pub fn spawn_block_producer(
                         // the announcements cannot be make. In TestNet the failure should not be silent.
                         .map_err(|_| ())
                 });
+                send_endorsement(&client, &endorsement_tx);
 
                 let control = get_control(&*client, new_beacon_block.header().index() + 1);
                 let needs_receipt_rerouting = match control {
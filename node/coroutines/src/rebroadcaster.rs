@@ -0,0 +1,34 @@
+//! Rebroadcaster periodically resubmits the pending transactions of the pool, so that they are
+//! not lost if the peers missed them.
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::sync::mpsc::Sender;
+use futures::{future, stream, Future, Sink, Stream};
+use tokio::timer::Interval;
+
+use client::Client;
+use transaction::SignedTransaction;
+
+/// How often, in milliseconds, the pool is checked for transactions due for a rebroadcast.
+const REBROADCAST_CHECK_PERIOD_MS: u64 = 1000;
+
+pub fn spawn_rebroadcaster(client: Arc<Client>, transactions_tx: Sender<SignedTransaction>) {
+    let task = Interval::new_interval(Duration::from_millis(REBROADCAST_CHECK_PERIOD_MS))
+        .map_err(|e| error!("Rebroadcast timer error: {}", e))
+        .for_each(move |_| {
+            let transactions = client.shard_chain.transactions_to_rebroadcast();
+            if transactions.is_empty() {
+                return future::Either::A(future::ok(()));
+            }
+            debug!("Rebroadcasting {} pending transactions", transactions.len());
+            future::Either::B(
+                transactions_tx
+                    .clone()
+                    .send_all(stream::iter_ok(transactions))
+                    .map(|_| ())
+                    .map_err(|e| error!("Error rebroadcasting transactions: {}", e)),
+            )
+        });
+    tokio::spawn(task);
+}
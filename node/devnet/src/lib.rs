@@ -17,13 +17,39 @@ pub fn start() {
     start_from_configs(client_cfg, devnet_cfg, rpc_cfg);
 }
 
-pub fn start_from_configs(client_cfg: ClientConfig, devnet_cfg: DevNetConfig, rpc_cfg: RPCConfig) {
+pub fn start_from_configs(
+    mut client_cfg: ClientConfig,
+    devnet_cfg: DevNetConfig,
+    rpc_cfg: RPCConfig,
+) {
+    // The only authority endorses its own blocks, so they follow each other at the block period.
+    client_cfg.min_block_production_delay = devnet_cfg.block_period;
     let client = Arc::new(Client::new(&client_cfg));
     tokio::run(future::lazy(move || {
         // TODO: TxFlow should be listening on these transactions.
         let (transactions_tx, transactions_rx) = channel(1024);
         let (receipts_tx, receipts_rx) = channel(1024);
         spawn_rpc_server_task(transactions_tx.clone(), &rpc_cfg, client.clone());
+        coroutines::rebroadcaster::spawn_rebroadcaster(client.clone(), transactions_tx.clone());
+        if let Some(db_url) = client_cfg.explorer_db_url.clone() {
+            #[cfg(feature = "postgres-exporter")]
+            indexer::exporter::spawn_exporter(client.clone(), db_url);
+            #[cfg(not(feature = "postgres-exporter"))]
+            panic!("Node is built without the postgres-exporter feature to export to {}", db_url);
+        }
+        if let Some(url) = client_cfg.event_bus_url.clone() {
+            let publisher =
+                indexer::bus::connect(&url).expect("Failed to connect to the event bus");
+            let high_water_mark = indexer::bus::HighWaterMark::new(
+                client_cfg.base_path.join(indexer::bus::HIGH_WATER_MARK_PATH),
+            );
+            indexer::bus::spawn_publisher(
+                client.clone(),
+                publisher,
+                client_cfg.event_bus_topic_prefix.clone(),
+                high_water_mark,
+            );
+        }
 
         // Create a task that receives new blocks from importer/producer
         // and send the authority information to consensus
@@ -33,6 +59,7 @@ pub fn start_from_configs(client_cfg: ClientConfig, devnet_cfg: DevNetConfig, rp
         // and produces the beacon chain blocks.
         let (beacon_block_consensus_body_tx, beacon_block_consensus_body_rx) = channel(1024);
         let (outgoing_block_tx, _) = channel(1024);
+        let (outgoing_endorsements_tx, _) = channel(1024);
         // Block producer is also responsible for re-submitting receipts from the previous block
         // into the next block.
         coroutines::producer::spawn_block_producer(
@@ -41,6 +68,7 @@ pub fn start_from_configs(client_cfg: ClientConfig, devnet_cfg: DevNetConfig, rp
             outgoing_block_tx,
             receipts_tx.clone(),
             consensus_control_tx,
+            outgoing_endorsements_tx,
         );
 
         // Spawn consensus tasks.
@@ -56,13 +84,7 @@ pub fn start_from_configs(client_cfg: ClientConfig, devnet_cfg: DevNetConfig, rp
             payload_tx.clone()
         );
 
-        spawn_consensus(
-            payload_rx,
-            consensus_control_rx,
-            beacon_block_consensus_body_tx,
-            client.beacon_chain.chain.best_index(),
-            devnet_cfg.block_period,
-        );
+        spawn_consensus(payload_rx, consensus_control_rx, beacon_block_consensus_body_tx);
         Ok(())
     }));
 }
@@ -73,6 +95,10 @@ fn spawn_rpc_server_task(
     client: Arc<Client>,
 ) {
     let http_addr = Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), rpc_config.rpc_port));
+    if let Some(grpc_port) = rpc_config.grpc_port {
+        let grpc_api = node_http::api::HttpApi::new(client.clone(), transactions_tx.clone());
+        node_grpc::spawn_grpc_server(grpc_api, grpc_port);
+    }
     let http_api = node_http::api::HttpApi::new(client, transactions_tx);
     node_http::server::spawn_server(http_api, http_addr);
 }
@@ -0,0 +1,367 @@
+//! gRPC interface of the node, see `protos/protos/node_api.proto`. Requests are served by
+//! `HttpApi`, so they go through the same checks as the ones of the RPC server. The streams are
+//! written as fast as the clients read them; a client that falls `MAX_PENDING_BLOCKS` behind gets
+//! `ResourceExhausted` and can catch up with the RPC before subscribing again.
+extern crate futures;
+extern crate grpcio;
+#[macro_use]
+extern crate log;
+extern crate tokio;
+
+extern crate chain;
+extern crate near_protos;
+extern crate node_http;
+extern crate primitives;
+extern crate shard;
+extern crate transaction;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use futures::sync::mpsc::{channel, UnboundedReceiver};
+use futures::{future, Future, Sink, Stream};
+use grpcio::{
+    Environment, RpcContext, RpcStatus, RpcStatusCode, ServerBuilder, ServerStreamingSink,
+    UnarySink, WriteFlags,
+};
+
+use chain::{SignedBlock, SignedShardBlock};
+use near_protos::node_api::{
+    AccountEvents, BalanceChange, BalanceChangeCause, Block, BlockOutcomes,
+    CallViewFunctionRequest, CallViewFunctionResponse, StreamAccountEventsRequest,
+    StreamBlocksRequest, StreamOutcomesRequest, SubmitTransactionRequest,
+    SubmitTransactionResponse, ViewAccountRequest, ViewAccountResponse,
+};
+use near_protos::node_api_grpc::{create_node_api, NodeApi};
+use near_protos::outcome::ExecutionOutcome;
+use near_protos::Message;
+use node_http::api::{HttpApi, RPCError};
+use node_http::types;
+use primitives::utils::is_valid_account_id;
+use shard::{BlockEvent, Outcome};
+
+/// Number of threads polling the gRPC completion queues.
+const GRPC_THREADS: usize = 2;
+/// Number of the blocks a stream keeps for a client that reads it slower than they are added.
+const MAX_PENDING_BLOCKS: usize = 64;
+
+#[derive(Clone)]
+struct NodeApiService {
+    http_api: Arc<HttpApi>,
+}
+
+fn rpc_status(error: RPCError) -> RpcStatus {
+    let (code, message) = match error {
+        RPCError::BadRequest(msg) => (RpcStatusCode::InvalidArgument, msg),
+        RPCError::TransactionRejected(reason) => {
+            (RpcStatusCode::FailedPrecondition, reason.to_string())
+        }
+        RPCError::NotFound => (RpcStatusCode::NotFound, "Not found".to_string()),
+        RPCError::ServiceUnavailable(msg) => (RpcStatusCode::Unavailable, msg),
+        RPCError::Timeout(hash) => (RpcStatusCode::DeadlineExceeded, String::from(&hash)),
+    };
+    RpcStatus::new(code, Some(message))
+}
+
+fn reply<T>(ctx: &RpcContext, sink: UnarySink<T>, result: Result<T, RpcStatus>) {
+    let f = match result {
+        Ok(response) => sink.success(response),
+        Err(status) => sink.fail(status),
+    };
+    ctx.spawn(f.map_err(|e| warn!(target: "near-grpc", "Failed to reply: {:?}", e)));
+}
+
+fn to_proto_block(block: &SignedShardBlock) -> Block {
+    let mut proto = Block::new();
+    proto.set_hash(block.block_hash().as_ref().to_vec());
+    proto.set_index(block.index());
+    proto.set_merkle_root_state(block.merkle_root_state().as_ref().to_vec());
+    for transaction in &block.body.transactions {
+        let transaction: near_protos::signed_transaction::SignedTransaction =
+            transaction.clone().into();
+        proto.mut_transactions().push(transaction.write_to_bytes().unwrap());
+    }
+    proto
+}
+
+fn to_proto_outcome(outcome: &Outcome) -> Vec<u8> {
+    let outcome: ExecutionOutcome = outcome.clone().into();
+    outcome.write_to_bytes().unwrap()
+}
+
+/// Outcomes of the block executed by the account, or all of them if it is empty. Blocks without
+/// outcomes of the account are skipped.
+fn to_proto_outcomes(event: &BlockEvent, account_id: &str) -> Option<BlockOutcomes> {
+    let mut proto = BlockOutcomes::new();
+    proto.set_block_hash(event.block.block_hash().as_ref().to_vec());
+    proto.set_block_index(event.block.index());
+    for outcome in &event.outcomes {
+        if account_id.is_empty() || outcome.account_id == account_id {
+            proto.mut_outcomes().push(to_proto_outcome(outcome));
+        }
+    }
+    if !account_id.is_empty() && proto.get_outcomes().is_empty() {
+        return None;
+    }
+    Some(proto)
+}
+
+fn to_proto_cause(cause: transaction::BalanceChangeCause) -> BalanceChangeCause {
+    match cause {
+        transaction::BalanceChangeCause::Transfer => BalanceChangeCause::TRANSFER,
+        transaction::BalanceChangeCause::Fee => BalanceChangeCause::FEE,
+        transaction::BalanceChangeCause::Stake => BalanceChangeCause::STAKE,
+        transaction::BalanceChangeCause::Refund => BalanceChangeCause::REFUND,
+    }
+}
+
+/// Outcomes of the block executed by the account and the changes of its balance, if any.
+fn to_proto_account_events(event: &BlockEvent, account_id: &str) -> Option<AccountEvents> {
+    let mut proto = AccountEvents::new();
+    for outcome in &event.outcomes {
+        if outcome.account_id == account_id {
+            proto.mut_outcomes().push(to_proto_outcome(outcome));
+        }
+        for change in &outcome.result.balance_changes {
+            if change.account_id == account_id {
+                let mut balance_change = BalanceChange::new();
+                balance_change.set_id(outcome.id.as_ref().to_vec());
+                balance_change.set_cause(to_proto_cause(change.cause));
+                balance_change.set_amount(change.amount);
+                balance_change.set_credit(change.credit);
+                proto.mut_balance_changes().push(balance_change);
+            }
+        }
+    }
+    if proto.get_outcomes().is_empty() && proto.get_balance_changes().is_empty() {
+        return None;
+    }
+    proto.set_block_hash(event.block.block_hash().as_ref().to_vec());
+    proto.set_block_index(event.block.index());
+    Some(proto)
+}
+
+/// Streams the items of the blocks to the client. The blocks are buffered while the client reads
+/// the previous ones, and when more than `MAX_PENDING_BLOCKS` of them are, the client gets what
+/// is buffered and then `ResourceExhausted`, instead of the node keeping all of them.
+fn stream_events<T, F>(
+    ctx: &RpcContext,
+    sink: ServerStreamingSink<T>,
+    blocks: UnboundedReceiver<BlockEvent>,
+    mut items: F,
+) where
+    T: Send + 'static,
+    F: FnMut(&BlockEvent) -> Option<T> + Send + 'static,
+{
+    let (mut sender, receiver) = channel(MAX_PENDING_BLOCKS);
+    let lagging = Arc::new(AtomicBool::new(false));
+    let lagging_sender = lagging.clone();
+    // Ends when the node stops, the client goes away or falls behind.
+    let forward = blocks.filter_map(move |event| items(&event)).for_each(move |item| {
+        sender.try_send(item).map_err(|e| {
+            if e.is_full() {
+                lagging_sender.store(true, Ordering::SeqCst);
+            }
+        })
+    });
+    ctx.spawn(forward);
+    let f = receiver
+        .map_err(|_| grpcio::Error::RemoteStopped)
+        .fold(sink, |sink, item| sink.send((item, WriteFlags::default())))
+        .and_then(move |mut sink| {
+            if lagging.load(Ordering::SeqCst) {
+                let message = format!("Client is over {} blocks behind", MAX_PENDING_BLOCKS);
+                let status = RpcStatus::new(RpcStatusCode::ResourceExhausted, Some(message));
+                future::Either::A(sink.fail(status))
+            } else {
+                future::Either::B(future::poll_fn(move || sink.close()))
+            }
+        })
+        .map_err(|e| debug!(target: "near-grpc", "Stream closed: {:?}", e));
+    ctx.spawn(f);
+}
+
+impl NodeApi for NodeApiService {
+    fn submit_transaction(
+        &mut self,
+        ctx: RpcContext,
+        req: SubmitTransactionRequest,
+        sink: UnarySink<SubmitTransactionResponse>,
+    ) {
+        let request = types::SubmitTransactionRequest { transaction: req.get_transaction().to_vec() };
+        let result = self
+            .http_api
+            .submit_transaction(&request)
+            .map_err(rpc_status)
+            .map(|response| {
+                let mut proto = SubmitTransactionResponse::new();
+                proto.set_hash(response.hash.as_ref().to_vec());
+                proto
+            });
+        reply(&ctx, sink, result);
+    }
+
+    fn view_account(
+        &mut self,
+        ctx: RpcContext,
+        req: ViewAccountRequest,
+        sink: UnarySink<ViewAccountResponse>,
+    ) {
+        let request = types::ViewAccountRequest { account_id: req.get_account_id().to_string() };
+        let result = self
+            .http_api
+            .view_account(&request)
+            .map_err(|e| RpcStatus::new(RpcStatusCode::InvalidArgument, Some(e)))
+            .map(|response| {
+                let mut proto = ViewAccountResponse::new();
+                proto.set_account_id(response.account_id);
+                proto.set_amount(response.amount);
+                proto.set_stake(response.stake);
+                proto.set_nonce(response.nonce);
+                proto.set_code_hash(response.code_hash.as_ref().to_vec());
+                proto
+            });
+        reply(&ctx, sink, result);
+    }
+
+    fn call_view_function(
+        &mut self,
+        ctx: RpcContext,
+        req: CallViewFunctionRequest,
+        sink: UnarySink<CallViewFunctionResponse>,
+    ) {
+        let request = types::CallViewFunctionRequest {
+            contract_account_id: req.get_contract_account_id().to_string(),
+            method_name: req.get_method_name().to_string(),
+            args: req.get_args().to_vec(),
+        };
+        let result = self
+            .http_api
+            .call_view_function(&request)
+            .map_err(|e| RpcStatus::new(RpcStatusCode::InvalidArgument, Some(e)))
+            .map(|response| {
+                let mut proto = CallViewFunctionResponse::new();
+                proto.set_result(response.result);
+                proto
+            });
+        reply(&ctx, sink, result);
+    }
+
+    fn stream_blocks(
+        &mut self,
+        ctx: RpcContext,
+        _req: StreamBlocksRequest,
+        sink: ServerStreamingSink<Block>,
+    ) {
+        let blocks = self.http_api.subscribe_to_blocks();
+        stream_events(&ctx, sink, blocks, |event| Some(to_proto_block(&event.block)));
+    }
+
+    fn stream_outcomes(
+        &mut self,
+        ctx: RpcContext,
+        req: StreamOutcomesRequest,
+        sink: ServerStreamingSink<BlockOutcomes>,
+    ) {
+        let account_id = req.get_account_id().to_string();
+        if !account_id.is_empty() && !is_valid_account_id(&account_id) {
+            let status = RpcStatus::new(
+                RpcStatusCode::InvalidArgument,
+                Some(format!("Invalid account id {}", account_id)),
+            );
+            ctx.spawn(sink.fail(status).map_err(|_| ()));
+            return;
+        }
+        let blocks = self.http_api.subscribe_to_blocks();
+        stream_events(&ctx, sink, blocks, move |event| to_proto_outcomes(event, &account_id));
+    }
+
+    fn stream_account_events(
+        &mut self,
+        ctx: RpcContext,
+        req: StreamAccountEventsRequest,
+        sink: ServerStreamingSink<AccountEvents>,
+    ) {
+        let account_id = req.get_account_id().to_string();
+        if !is_valid_account_id(&account_id) {
+            let status = RpcStatus::new(
+                RpcStatusCode::InvalidArgument,
+                Some(format!("Invalid account id {}", account_id)),
+            );
+            ctx.spawn(sink.fail(status).map_err(|_| ()));
+            return;
+        }
+        let blocks = self.http_api.subscribe_to_blocks();
+        stream_events(&ctx, sink, blocks, move |event| {
+            to_proto_account_events(event, &account_id)
+        });
+    }
+}
+
+/// Starts the gRPC server on the given port. It runs for as long as the spawned task.
+pub fn spawn_grpc_server(http_api: HttpApi, port: u16) {
+    let service = create_node_api(NodeApiService { http_api: Arc::new(http_api) });
+    let env = Arc::new(Environment::new(GRPC_THREADS));
+    let mut server = ServerBuilder::new(env)
+        .register_service(service)
+        .bind("0.0.0.0", port)
+        .build()
+        .expect("Failed to create the gRPC server");
+    server.start();
+    info!(target: "near-grpc", "gRPC server listening on port {}", port);
+    // The server shuts down when dropped, so the never-ending task holds on to it.
+    tokio::spawn(future::empty::<(), ()>().then(move |result| {
+        drop(server);
+        result
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use primitives::hash::{hash, CryptoHash};
+
+    #[test]
+    fn test_to_proto_block() {
+        let root = CryptoHash::default();
+        let block = SignedShardBlock::new(0, 3, CryptoHash::default(), root, root, vec![], vec![]);
+        let proto = to_proto_block(&block);
+        assert_eq!(proto.get_hash(), block.hash.as_ref());
+        assert_eq!(proto.get_index(), 3);
+        assert!(proto.get_transactions().is_empty());
+    }
+
+    #[test]
+    fn test_to_proto_account_events() {
+        let root = CryptoHash::default();
+        let block = SignedShardBlock::new(0, 3, CryptoHash::default(), root, root, vec![], vec![]);
+        let change = |account_id: &str, credit| transaction::BalanceChange {
+            account_id: account_id.to_string(),
+            cause: transaction::BalanceChangeCause::Transfer,
+            amount: 5,
+            credit,
+        };
+        let mut result = transaction::TransactionResult::default();
+        result.balance_changes = vec![change("alice.near", false), change("bob.near", true)];
+        let account_id = "alice.near".to_string();
+        let outcome = Outcome { id: hash(b"transfer"), account_id, result };
+        let event = BlockEvent { block, outcomes: vec![outcome.clone()] };
+
+        let outcomes = to_proto_outcomes(&event, "").unwrap();
+        assert_eq!(outcomes.get_block_index(), 3);
+        assert_eq!(outcomes.get_outcomes(), &[to_proto_outcome(&outcome)]);
+        assert!(to_proto_outcomes(&event, "bob.near").is_none());
+
+        let alice = to_proto_account_events(&event, "alice.near").unwrap();
+        assert_eq!(alice.get_outcomes().len(), 1);
+        assert!(!alice.get_balance_changes()[0].get_credit());
+        // Bob only receives the money, the transaction is executed by Alice.
+        let bob = to_proto_account_events(&event, "bob.near").unwrap();
+        assert!(bob.get_outcomes().is_empty());
+        assert_eq!(bob.get_balance_changes()[0].get_id(), outcome.id.as_ref());
+        assert_eq!(bob.get_balance_changes()[0].get_cause(), BalanceChangeCause::TRANSFER);
+        assert!(bob.get_balance_changes()[0].get_credit());
+        assert!(to_proto_account_events(&event, "carol.near").is_none());
+    }
+}
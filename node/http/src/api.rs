@@ -1,23 +1,46 @@
 use std::sync::Arc;
+use std::time::Duration;
 
-use futures::sync::mpsc::Sender;
+use futures::sync::mpsc::{Sender, UnboundedReceiver};
+use futures::{future, stream, Future, Stream};
+use tokio::timer::Timeout;
 
-use chain::SignedBlock;
+use beacon::light_client::LightClientBlockHeader;
+use beacon::types::{Finality, SignedBeaconBlock};
+use chain::{SignedBlock, SignedShardBlock};
 use client::Client;
+use primitives::hash::CryptoHash;
+use primitives::limited::DecodeLimits;
 use primitives::types::BlockId;
 use primitives::utils::bs58_vec2str;
-use transaction::{SignedTransaction, verify_transaction_signature};
+use node_runtime::state_changes::classify_changes;
+use shard::{AdmissionError, BlockEvent, OutcomeProof, TransactionProof};
+use transaction::{
+    FinalTransactionResult, FinalTransactionStatus, FunctionCallTransaction, InvalidTxError,
+    SignedTransaction,
+};
 
 use crate::types::{
-    CallViewFunctionRequest, CallViewFunctionResponse, GetBlockByHashRequest,
-    GetBlocksByIndexRequest, GetTransactionRequest, SignedBeaconBlockResponse,
-    SignedShardBlockResponse, SignedShardBlocksResponse, SubmitTransactionRequest,
-    SubmitTransactionResponse, TransactionInfoResponse, TransactionResultResponse,
-    ViewAccountRequest, ViewAccountResponse, ViewStateRequest, ViewStateResponse,
+    encode_transaction, AccessKeyResponse, AccountTransactionResponse, AccountTransactionsResponse,
+    BalanceChangeResponse, BalanceChangesResponse, BlockReference, BroadcastTxCommitRequest,
+    CallViewFunctionRequest, CallViewFunctionResponse, GetAccountTransactionsRequest,
+    GetBalanceChangesRequest, GetBlockByHashRequest, GetBlockByIndexRequest,
+    GetBlocksByIndexRequest, GetStateChangesRequest, GetTransactionRequest, JsonTransactionRequest,
+    NetworkInfoResponse, PeerResponse, Query, QueryRequest, QueryResponse, QueryResult,
+    ReceiptInfoResponse, SignableBytesResponse, SignedBeaconBlockResponse, SignedShardBlockResponse, SignedShardBlocksResponse,
+    SimulateFunctionCallRequest, SimulateFunctionCallResponse, StateChangesResponse, StatusResponse,
+    SubmitTransactionRequest, SubmitTransactionResponse, SuggestNonceRequest, SuggestNonceResponse,
+    SyncInfoResponse, SyncProgressResponse, TransactionInfoResponse, TransactionResultResponse,
+    TransactionStatusRequest, VersionResponse, ViewAccountRequest, ViewAccountResponse,
+    ViewAuthoritiesRequest, ViewAuthoritiesResponse, ViewCodeResponse, ViewPoolRequest,
+    ViewPoolResponse, ViewStateRequest, ViewStateResponse,
 };
 
+/// Version of the node software reported by `status`.
+const NODE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
 pub struct HttpApi {
-    client: Arc<Client>,
+    pub(crate) client: Arc<Client>,
     submit_txn_sender: Sender<SignedTransaction>,
 }
 
@@ -27,12 +50,66 @@ impl HttpApi {
     }
 }
 
+/// Finality of the block that includes the transaction or the receipt.
+fn outcome_finality(client: &Client, id: &CryptoHash) -> Option<Finality> {
+    let block = client.shard_chain.get_outcome_block(id)?;
+    client.beacon_chain.shard_block_finality(&block.hash, block.index())
+}
+
+fn is_executed(result: &FinalTransactionResult) -> bool {
+    match result.status {
+        FinalTransactionStatus::Completed | FinalTransactionStatus::Failed => true,
+        FinalTransactionStatus::Unknown | FinalTransactionStatus::Started => false,
+    }
+}
+
+/// Finality of the least final block the transaction and its receipts were executed in.
+fn result_finality(client: &Client, result: &FinalTransactionResult) -> Option<Finality> {
+    if !is_executed(result) {
+        return None;
+    }
+    let mut finality = Finality::Final;
+    for logs in &result.logs {
+        finality = std::cmp::min(finality, outcome_finality(client, &logs.hash)?);
+    }
+    Some(finality)
+}
+
+/// Limits of the submitted transactions, those of the peers for all but the size, which is the
+/// max size of a transaction of the runtime.
+fn transaction_limits(client: &Client) -> DecodeLimits {
+    let max_size = client.shard_chain.runtime.read().config.max_transaction_size;
+    DecodeLimits { max_size, ..DecodeLimits::default() }
+}
+
+/// Transaction of the JSON request, which must have a body.
+fn transaction_from_json(
+    client: &Client,
+    r: &JsonTransactionRequest,
+) -> Result<SignedTransaction, RPCError> {
+    SignedTransaction::try_from_proto_limited(r.transaction.clone(), &transaction_limits(client))
+        .map_err(|e| RPCError::BadRequest(e.to_string()))
+}
+
 pub enum RPCError {
     BadRequest(String),
+    /// Submitted transaction was not admitted into the transaction pool.
+    TransactionRejected(AdmissionError),
     NotFound,
     ServiceUnavailable(String),
+    /// Submitted transaction with the given hash didn't finish executing in time.
+    Timeout(CryptoHash),
 }
 
+/// How long `broadcast_tx_commit` waits for the execution by default.
+const DEFAULT_COMMIT_TIMEOUT_MS: u64 = 10_000;
+/// Longest wait of `broadcast_tx_commit` that can be requested.
+const MAX_COMMIT_TIMEOUT_MS: u64 = 60_000;
+/// Page size of the listings when it isn't requested.
+pub(crate) const DEFAULT_PAGE_LIMIT: usize = 25;
+/// Largest page size of the listings that can be requested.
+pub(crate) const MAX_PAGE_LIMIT: usize = 100;
+
 impl HttpApi {
     pub fn view_account(&self, r: &ViewAccountRequest) -> Result<ViewAccountResponse, String> {
         debug!(target: "near-rpc", "View account {:?}", r.account_id);
@@ -51,6 +128,86 @@ impl HttpApi {
         }
     }
 
+    /// Queries the state at the given block with any of the state viewer methods.
+    pub fn query(&self, r: &QueryRequest) -> Result<QueryResponse, RPCError> {
+        let shard_chain = &self.client.shard_chain;
+        let block = match &r.block {
+            None => Some(shard_chain.chain.best_block()),
+            Some(BlockReference::BlockIndex(index)) => {
+                shard_chain.chain.get_block(&BlockId::Number(*index))
+            }
+            Some(BlockReference::BlockHash(hash)) => {
+                shard_chain.chain.get_block(&BlockId::Hash(*hash))
+            }
+            Some(BlockReference::StateRoot(_)) => Some(shard_chain.chain.best_block()),
+        };
+        let block = block.ok_or(RPCError::NotFound)?;
+        let (block_index, state_root) = match &r.block {
+            Some(BlockReference::StateRoot(root)) => (block.index(), *root),
+            _ => (block.index(), block.merkle_root_state()),
+        };
+        let viewer = &shard_chain.statedb_viewer;
+        let result = match &r.query {
+            Query::ViewAccount { account_id } => {
+                viewer.view_account(state_root, account_id).map(|r| {
+                    QueryResult::ViewAccount(ViewAccountResponse {
+                        account_id: r.account,
+                        amount: r.amount,
+                        stake: r.stake,
+                        code_hash: r.code_hash,
+                        nonce: r.nonce,
+                    })
+                })
+            }
+            Query::ViewAccessKey { account_id, public_key } => {
+                viewer.view_access_key(state_root, account_id, public_key).map(|r| {
+                    QueryResult::ViewAccessKey(AccessKeyResponse {
+                        account_id: r.account,
+                        public_key: r.public_key,
+                        nonce: r.nonce,
+                        allowed: r.allowed.iter().map(ToString::to_string).collect(),
+                    })
+                })
+            }
+            Query::ViewCode { account_id } => viewer.view_code(state_root, account_id).map(|r| {
+                QueryResult::ViewCode(ViewCodeResponse {
+                    account_id: r.account,
+                    code_hash: r.code_hash,
+                    code: r.code,
+                })
+            }),
+            Query::ViewState { account_id } => viewer.view_state(state_root, account_id).map(|r| {
+                QueryResult::ViewState(ViewStateResponse {
+                    contract_account_id: account_id.clone(),
+                    values: r.values.iter().map(|(k, v)| (bs58_vec2str(k), v.clone())).collect(),
+                })
+            }),
+            Query::CallFunction { account_id, method_name, args } => viewer
+                .call_function(state_root, block_index, account_id, method_name, args)
+                .map(|result| QueryResult::CallFunction(CallViewFunctionResponse { result })),
+        };
+        let result = result.map_err(RPCError::BadRequest)?;
+        Ok(QueryResponse { block_index, state_root, result })
+    }
+
+    pub fn suggest_nonce(&self, r: &SuggestNonceRequest) -> Result<SuggestNonceResponse, String> {
+        debug!(target: "near-rpc", "Suggest nonce {:?}", r.account_id);
+        let shard_chain = &self.client.shard_chain;
+        let access_key = shard_chain.statedb_viewer.view_access_key(
+            shard_chain.chain.best_block().merkle_root_state(),
+            &r.account_id,
+            &r.public_key,
+        )?;
+        let nonce = shard_chain.pool.read().next_nonce(&r.account_id, access_key.nonce);
+        Ok(SuggestNonceResponse {
+            account_id: access_key.account,
+            public_key: access_key.public_key,
+            committed_nonce: access_key.nonce,
+            nonce,
+            allowed: access_key.allowed.iter().map(ToString::to_string).collect(),
+        })
+    }
+
     pub fn call_view_function(
         &self,
         r: &CallViewFunctionRequest,
@@ -71,22 +228,55 @@ impl HttpApi {
         }
     }
 
+    /// Dry-runs the function call on top of the best block, so that wallets can preview
+    /// its outcome and the gas it burns before signing it.
+    pub fn simulate_function_call(
+        &self,
+        r: &SimulateFunctionCallRequest,
+    ) -> Result<SimulateFunctionCallResponse, RPCError> {
+        debug!(
+            target: "near-rpc",
+            "Simulate function call {:?}{:?}",
+            r.contract_id,
+            r.method_name,
+        );
+        let transaction = FunctionCallTransaction {
+            nonce: 0,
+            originator: r.originator.clone(),
+            contract_id: r.contract_id.clone(),
+            method_name: r.method_name.as_bytes().to_vec(),
+            args: r.args.clone(),
+            amount: r.amount,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+        };
+        self.client
+            .shard_chain
+            .simulate_function_call(&transaction)
+            .map(SimulateFunctionCallResponse::from)
+            .map_err(|e| RPCError::BadRequest(e.to_string()))
+    }
+
     pub fn submit_transaction(
         &self,
         r: &SubmitTransactionRequest,
     ) -> Result<SubmitTransactionResponse, RPCError> {
-        let transaction: SignedTransaction = r.transaction.clone().into();
-        debug!(target: "near-rpc", "Received transaction {:?}", transaction);
-        let originator = transaction.body.get_originator();
-        let root_state = self.client.shard_chain.chain.best_block().merkle_root_state();
-        let public_keys = self.client.shard_chain.statedb_viewer
-            .get_public_keys_for_account(root_state, &originator)
-            .map_err(RPCError::BadRequest)?;
-        if !verify_transaction_signature(&transaction.clone(), &public_keys) {
-            let msg =
-                format!("transaction not signed with a public key of originator {:?}", originator,);
-            return Err(RPCError::BadRequest(msg));
+        let size = r.transaction.len();
+        let limit = self.client.shard_chain.runtime.read().config.max_transaction_size;
+        if size > limit {
+            return Err(RPCError::TransactionRejected(AdmissionError::Invalid(
+                InvalidTxError::TransactionTooLarge { size, limit },
+            )));
         }
+        let limits = transaction_limits(&self.client);
+        let transaction = SignedTransaction::from_proto_bytes_limited(&r.transaction, &limits)
+            .map_err(|e| RPCError::BadRequest(e.to_string()))?;
+        debug!(target: "near-rpc", "Received transaction {:?}", transaction);
+        self.client.shard_chain
+            .check_transaction(&transaction)
+            .map_err(RPCError::TransactionRejected)?;
 
         self.submit_txn_sender
             .clone()
@@ -95,6 +285,95 @@ impl HttpApi {
         Ok(SubmitTransactionResponse { hash: transaction.get_hash() })
     }
 
+    /// Returns the bytes to sign of the transaction given in JSON, and its hash.
+    pub fn signable_bytes(
+        &self,
+        r: &JsonTransactionRequest,
+    ) -> Result<SignableBytesResponse, RPCError> {
+        let transaction = transaction_from_json(&self.client, r)?;
+        Ok(SignableBytesResponse {
+            signable_bytes: base64::encode(&transaction.signable_bytes()),
+            hash: transaction.get_hash(),
+        })
+    }
+
+    /// Submits the signed transaction given in JSON, like `submit_transaction`.
+    pub fn submit_transaction_json(
+        &self,
+        r: &JsonTransactionRequest,
+    ) -> Result<SubmitTransactionResponse, RPCError> {
+        let transaction = transaction_from_json(&self.client, r)?;
+        self.submit_transaction(&SubmitTransactionRequest {
+            transaction: encode_transaction(transaction),
+        })
+    }
+
+    /// Returns the hash of the transaction right after it is admitted into the pool.
+    pub fn broadcast_tx_async(
+        &self,
+        r: &SubmitTransactionRequest,
+    ) -> Result<SubmitTransactionResponse, RPCError> {
+        self.submit_transaction(r)
+    }
+
+    /// Submits the transaction and waits until it and all the receipts it produced are
+    /// executed, or the timeout elapses.
+    pub fn broadcast_tx_commit(
+        &self,
+        r: &BroadcastTxCommitRequest,
+    ) -> Box<Future<Item = TransactionResultResponse, Error = RPCError> + Send> {
+        // Subscribes before submitting, so that the block with the transaction isn't missed.
+        let blocks = self.subscribe_to_blocks();
+        let request = SubmitTransactionRequest { transaction: r.transaction.clone() };
+        let hash = match self.submit_transaction(&request) {
+            Ok(response) => response.hash,
+            Err(e) => return Box::new(future::err(e)),
+        };
+        self.wait_for_result(hash, blocks, r.wait_until, r.timeout_ms)
+    }
+
+    /// Returns the result of the transaction once it is executed and, if asked, final.
+    pub fn tx_status(
+        &self,
+        r: &TransactionStatusRequest,
+    ) -> Box<Future<Item = TransactionResultResponse, Error = RPCError> + Send> {
+        self.wait_for_result(r.hash, self.subscribe_to_blocks(), r.wait_until, r.timeout_ms)
+    }
+
+    /// Waits until the transaction and all the receipts it produced are executed and, for
+    /// `Finality::Final`, the blocks they are in are final. Checks on every new block.
+    fn wait_for_result(
+        &self,
+        hash: CryptoHash,
+        blocks: UnboundedReceiver<BlockEvent>,
+        wait_until: Option<Finality>,
+        timeout_ms: Option<u64>,
+    ) -> Box<Future<Item = TransactionResultResponse, Error = RPCError> + Send> {
+        let timeout_ms =
+            std::cmp::min(timeout_ms.unwrap_or(DEFAULT_COMMIT_TIMEOUT_MS), MAX_COMMIT_TIMEOUT_MS);
+        let client = self.client.clone();
+        let result = stream::once(Ok(()))
+            .chain(blocks.map(|_| ()))
+            .filter_map(move |_| {
+                let result = client.shard_chain.get_transaction_final_result(&hash);
+                if !is_executed(&result) {
+                    return None;
+                }
+                let finality = result_finality(&client, &result);
+                if wait_until == Some(Finality::Final) && finality != Some(Finality::Final) {
+                    return None;
+                }
+                Some(TransactionResultResponse { result, finality })
+            })
+            .into_future()
+            .map_err(|_| ())
+            .and_then(|(result, _)| result.ok_or(()));
+        Box::new(
+            Timeout::new(result, Duration::from_millis(timeout_ms))
+                .map_err(move |_| RPCError::Timeout(hash)),
+        )
+    }
+
     pub fn view_state(&self, r: &ViewStateRequest) -> Result<ViewStateResponse, String> {
         debug!(target: "near-rpc", "View state {:?}", r.contract_account_id);
         let result = self.client.shard_chain.statedb_viewer
@@ -106,8 +385,65 @@ impl HttpApi {
         Ok(response)
     }
 
+    pub fn status(&self) -> Result<StatusResponse, ()> {
+        let shard_block = self.client.shard_chain.chain.best_block();
+        let network_info = self.client.network_info.read();
+        Ok(StatusResponse {
+            version: VersionResponse {
+                version: NODE_VERSION.to_string(),
+                protocol_version: network_info.protocol_version,
+            },
+            genesis_hash: self.client.shard_chain.chain.genesis_hash,
+            account_id: self.client.account_id.clone(),
+            is_authority: self.client.is_authority(),
+            num_peers: network_info.peers.len(),
+            sync_info: SyncInfoResponse {
+                latest_block_index: shard_block.index(),
+                latest_block_hash: shard_block.block_hash(),
+                latest_state_root: shard_block.merkle_root_state(),
+                latest_beacon_block_index: self.client.beacon_chain.chain.best_index(),
+                final_beacon_block_index: self.client.beacon_chain.final_index(),
+                syncing: self.client.is_syncing(),
+                sync_progress: network_info.sync_progress.clone().map(SyncProgressResponse::from),
+            },
+            dropped_mana_refunds: self.client.shard_chain.dropped_mana_refunds(),
+        })
+    }
+
+    /// Fails while the node is catching up with its peers, so that load balancers don't
+    /// route requests to a node with a stale state.
+    pub fn health(&self) -> Result<(), RPCError> {
+        if self.client.is_syncing() {
+            Err(RPCError::ServiceUnavailable("Node is syncing".to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn network_info(&self) -> Result<NetworkInfoResponse, ()> {
+        let network_info = self.client.network_info.read().clone();
+        let mut peers: Vec<PeerResponse> =
+            network_info.peers.into_iter().map(|(_, peer)| peer.into()).collect();
+        peers.sort_by(|a, b| a.account_id.cmp(&b.account_id));
+        Ok(NetworkInfoResponse {
+            protocol_version: network_info.protocol_version,
+            num_peers: peers.len(),
+            peers,
+        })
+    }
+
+    fn beacon_block_response(&self, block: SignedBeaconBlock) -> SignedBeaconBlockResponse {
+        let finality = self.client.beacon_chain.block_finality(&block.hash, block.index());
+        SignedBeaconBlockResponse { finality, ..block.into() }
+    }
+
+    fn shard_block_response(&self, block: SignedShardBlock) -> SignedShardBlockResponse {
+        let finality = self.client.beacon_chain.shard_block_finality(&block.hash, block.index());
+        SignedShardBlockResponse { finality, ..block.into() }
+    }
+
     pub fn view_latest_beacon_block(&self) -> Result<SignedBeaconBlockResponse, ()> {
-        Ok(self.client.beacon_chain.chain.best_block().into())
+        Ok(self.beacon_block_response(self.client.beacon_chain.chain.best_block()))
     }
 
     pub fn get_beacon_block_by_hash(
@@ -115,13 +451,41 @@ impl HttpApi {
         r: &GetBlockByHashRequest,
     ) -> Result<SignedBeaconBlockResponse, &str> {
         match self.client.beacon_chain.chain.get_block(&BlockId::Hash(r.hash)) {
-            Some(block) => Ok(block.into()),
+            Some(block) => Ok(self.beacon_block_response(block)),
             None => Err("block not found"),
         }
     }
 
+    /// Returns the authorities of the block with the given index. The authorities of the next
+    /// epoch have the keys they rotated to so far.
+    pub fn view_authorities(
+        &self,
+        r: &ViewAuthoritiesRequest,
+    ) -> Result<ViewAuthoritiesResponse, RPCError> {
+        let index = r.index.unwrap_or_else(|| self.client.beacon_chain.chain.best_index() + 1);
+        let authorities = self
+            .client
+            .beacon_chain
+            .authority
+            .read()
+            .get_authorities(index)
+            .map_err(RPCError::BadRequest)?;
+        Ok(ViewAuthoritiesResponse {
+            index,
+            authorities: authorities.into_iter().map(|a| a.into()).collect(),
+        })
+    }
+
+    /// Returns the beacon block header with the given index for light clients to verify.
+    pub fn get_light_client_block_header(
+        &self,
+        r: &GetBlockByIndexRequest,
+    ) -> Result<LightClientBlockHeader, RPCError> {
+        self.client.beacon_chain.get_light_client_block_header(r.index).ok_or(RPCError::NotFound)
+    }
+
     pub fn view_latest_shard_block(&self) -> Result<SignedShardBlockResponse, ()> {
-        Ok(self.client.shard_chain.chain.best_block().into())
+        Ok(self.shard_block_response(self.client.shard_chain.chain.best_block()))
     }
 
     pub fn get_shard_block_by_hash(
@@ -129,7 +493,7 @@ impl HttpApi {
         r: &GetBlockByHashRequest,
     ) -> Result<SignedShardBlockResponse, &str> {
         match self.client.shard_chain.chain.get_block(&BlockId::Hash(r.hash)) {
-            Some(block) => Ok(block.into()),
+            Some(block) => Ok(self.shard_block_response(block)),
             None => Err("block not found"),
         }
     }
@@ -141,7 +505,8 @@ impl HttpApi {
         let start = r.start.unwrap_or_else(|| self.client.shard_chain.chain.best_index());
         let limit = r.limit.unwrap_or(25);
         self.client.shard_chain.chain.get_blocks_by_index(start, limit).map(|blocks| {
-            SignedShardBlocksResponse { blocks: blocks.into_iter().map(|x| x.into()).collect() }
+            let blocks = blocks.into_iter().map(|x| self.shard_block_response(x)).collect();
+            SignedShardBlocksResponse { blocks }
         })
     }
 
@@ -153,19 +518,152 @@ impl HttpApi {
             Some(info) => Ok(TransactionInfoResponse {
                 transaction: info.transaction.into(),
                 block_index: info.block_index,
-                result: info.result
+                result: info.result,
+                finality: outcome_finality(&self.client, &r.hash),
             }),
             None => Err(RPCError::NotFound),
         }
 
     }
 
+    pub fn get_shard_block_by_index(
+        &self,
+        r: &GetBlockByIndexRequest,
+    ) -> Result<SignedShardBlockResponse, RPCError> {
+        match self.client.shard_chain.chain.get_block(&BlockId::Number(r.index)) {
+            Some(block) => Ok(self.shard_block_response(block)),
+            None => Err(RPCError::NotFound),
+        }
+    }
+
+    /// Returns the outcome of the transaction or the receipt with its Merkle path, so that light
+    /// clients can verify it against the outcome root of the shard block.
+    pub fn get_outcome_proof(&self, r: &GetTransactionRequest) -> Result<OutcomeProof, RPCError> {
+        self.client.shard_chain.get_outcome_proof(&r.hash).ok_or(RPCError::NotFound)
+    }
+
+    /// Returns the transaction with its Merkle path, so that light clients can verify that it is
+    /// included into the shard block.
+    pub fn get_transaction_proof(
+        &self,
+        r: &GetTransactionRequest,
+    ) -> Result<TransactionProof, RPCError> {
+        self.client.shard_chain.get_transaction_proof(&r.hash).ok_or(RPCError::NotFound)
+    }
+
+    /// Returns the changes of the state caused by the block, optionally only of one account
+    /// or of some kinds.
+    pub fn get_state_changes(
+        &self,
+        r: &GetStateChangesRequest,
+    ) -> Result<StateChangesResponse, RPCError> {
+        let shard_chain = &self.client.shard_chain;
+        let block =
+            shard_chain.chain.get_block(&BlockId::Hash(r.block_hash)).ok_or(RPCError::NotFound)?;
+        let changes = shard_chain.get_state_changes(&r.block_hash).ok_or(RPCError::NotFound)?;
+        let changes = classify_changes(&changes)
+            .into_iter()
+            .filter(|c| r.account_id.is_none() || c.account_id == r.account_id)
+            .filter(|c| r.kinds.as_ref().map_or(true, |kinds| kinds.contains(&c.kind)))
+            .map(Into::into)
+            .collect();
+        Ok(StateChangesResponse { block_hash: r.block_hash, block_index: block.index(), changes })
+    }
+
+    /// Lists the changes of the balances made by the block, in the order they were applied.
+    pub fn get_balance_changes(
+        &self,
+        r: &GetBalanceChangesRequest,
+    ) -> Result<BalanceChangesResponse, RPCError> {
+        let shard_chain = &self.client.shard_chain;
+        let block =
+            shard_chain.chain.get_block(&BlockId::Hash(r.block_hash)).ok_or(RPCError::NotFound)?;
+        let changes = shard_chain
+            .get_block_outcomes(&block)
+            .into_iter()
+            .flat_map(|outcome| {
+                let id = outcome.id;
+                outcome.result.balance_changes.into_iter().map(move |change| {
+                    BalanceChangeResponse {
+                        id,
+                        account_id: change.account_id,
+                        cause: change.cause,
+                        amount: change.amount,
+                        credit: change.credit,
+                    }
+                })
+            })
+            .filter(|c| r.account_id.as_ref().map_or(true, |a| *a == c.account_id))
+            .collect();
+        Ok(BalanceChangesResponse { block_hash: r.block_hash, block_index: block.index(), changes })
+    }
+
+    pub fn get_receipt_info(
+        &self,
+        r: &GetTransactionRequest,
+    ) -> Result<ReceiptInfoResponse, RPCError> {
+        match self.client.shard_chain.get_receipt_info(&r.hash) {
+            Some(info) => Ok(info.into()),
+            None => Err(RPCError::NotFound),
+        }
+    }
+
+    /// Lists the transactions and receipts that involve the account, most recent first.
+    pub fn get_account_transactions(
+        &self,
+        r: &GetAccountTransactionsRequest,
+    ) -> Result<AccountTransactionsResponse, RPCError> {
+        let offset = r.offset.unwrap_or(0);
+        let limit = std::cmp::min(r.limit.unwrap_or(DEFAULT_PAGE_LIMIT), MAX_PAGE_LIMIT);
+        let ids = self.client.shard_chain.get_account_transactions(&r.account_id);
+        let transactions = ids
+            .iter()
+            .rev()
+            .skip(offset)
+            .take(limit)
+            .filter_map(|id| {
+                let request = GetTransactionRequest { hash: *id };
+                match self.get_transaction_info(&request) {
+                    Ok(info) => Some(AccountTransactionResponse::Transaction(info)),
+                    Err(_) => self
+                        .get_receipt_info(&request)
+                        .ok()
+                        .map(AccountTransactionResponse::Receipt),
+                }
+            })
+            .collect();
+        Ok(AccountTransactionsResponse {
+            account_id: r.account_id.clone(),
+            total: ids.len(),
+            offset,
+            limit,
+            transactions,
+        })
+    }
+
     pub fn get_transaction_result(
         &self,
         r: &GetTransactionRequest,
     ) -> Result<TransactionResultResponse, ()> {
         let result = self.client.shard_chain.get_transaction_final_result(&r.hash);
-        Ok(TransactionResultResponse { result })
+        let finality = result_finality(&self.client, &result);
+        Ok(TransactionResultResponse { result, finality })
+    }
+
+    pub fn subscribe_to_blocks(&self) -> UnboundedReceiver<BlockEvent> {
+        self.client.shard_chain.subscribe_to_blocks()
+    }
+
+    pub fn view_pool(&self, r: &ViewPoolRequest) -> Result<ViewPoolResponse, ()> {
+        let pool = self.client.shard_chain.pool.read();
+        let pending = pool
+            .pending(r.account_id.as_ref())
+            .into_iter()
+            .map(|(account_id, infos)| {
+                (account_id, infos.into_iter().map(Into::into).collect())
+            })
+            .collect();
+        Ok(ViewPoolResponse { stats: pool.stats(), pending })
     }
 }
 
@@ -0,0 +1,176 @@
+//! REST endpoints for explorers and other simple frontends:
+//! `GET /accounts/{id}`, `/accounts/{id}/transactions?offset=..&limit=..`, `/blocks/{index}`
+//! and `/receipts/{nonce}`. Responses carry an `ETag`, so that clients can revalidate them
+//! with `If-None-Match` without downloading unchanged resources again.
+use hyper::header::{CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+use hyper::{Body, Request, Response, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+
+use primitives::hash::{bs58_format, hash, CryptoHash};
+use primitives::types::AccountId;
+
+use crate::api::{HttpApi, RPCError};
+use crate::server::{build_response, generate_error_response};
+use crate::types::{
+    GetAccountTransactionsRequest, GetBlockByIndexRequest, GetTransactionRequest,
+    ViewAccountRequest,
+};
+
+#[derive(Debug, PartialEq)]
+enum Route {
+    Account(AccountId),
+    AccountTransactions(AccountId),
+    Block(u64),
+    Receipt(CryptoHash),
+}
+
+/// Whether the path belongs to one of the explorer endpoints.
+pub fn is_explorer_path(path: &str) -> bool {
+    ["/accounts/", "/blocks/", "/receipts/"].iter().any(|prefix| path.starts_with(prefix))
+}
+
+fn parse_route(path: &str) -> Result<Route, RPCError> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["accounts", id] => Ok(Route::Account(id.to_string())),
+        ["accounts", id, "transactions"] => Ok(Route::AccountTransactions(id.to_string())),
+        ["blocks", index] => index
+            .parse::<u64>()
+            .map(Route::Block)
+            .map_err(|_| RPCError::BadRequest(format!("Invalid block index {}", index))),
+        ["receipts", nonce] => bs58_format::deserialize(Value::from(*nonce))
+            .map(Route::Receipt)
+            .map_err(|e| RPCError::BadRequest(format!("Invalid receipt nonce {}: {}", nonce, e))),
+        _ => Err(RPCError::NotFound),
+    }
+}
+
+/// Reads an unsigned integer parameter of the query string.
+fn query_param(query: Option<&str>, name: &str) -> Result<Option<usize>, RPCError> {
+    let value = query
+        .unwrap_or("")
+        .split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key == name => Some(value),
+                _ => None,
+            }
+        })
+        .last();
+    match value {
+        Some(value) => value
+            .parse::<usize>()
+            .map(Some)
+            .map_err(|_| RPCError::BadRequest(format!("Invalid {} {}", name, value))),
+        None => Ok(None),
+    }
+}
+
+fn to_body<T: Serialize>(response: T) -> Vec<u8> {
+    serde_json::to_vec(&response).unwrap()
+}
+
+fn call(api: &HttpApi, route: Route, query: Option<&str>) -> Result<Vec<u8>, RPCError> {
+    match route {
+        Route::Account(account_id) => api
+            .view_account(&ViewAccountRequest { account_id })
+            .map(to_body)
+            .map_err(|_| RPCError::NotFound),
+        Route::AccountTransactions(account_id) => {
+            let request = GetAccountTransactionsRequest {
+                account_id,
+                offset: query_param(query, "offset")?,
+                limit: query_param(query, "limit")?,
+            };
+            api.get_account_transactions(&request).map(to_body)
+        }
+        Route::Block(index) => {
+            api.get_shard_block_by_index(&GetBlockByIndexRequest { index }).map(to_body)
+        }
+        Route::Receipt(hash) => api.get_receipt_info(&GetTransactionRequest { hash }).map(to_body),
+    }
+}
+
+fn etag(body: &[u8]) -> String {
+    format!("\"{}\"", String::from(&hash(body)))
+}
+
+/// Whether the value of `If-None-Match` matches the entity tag.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+pub fn serve(api: &HttpApi, req: &Request<Body>) -> Response<Body> {
+    let body = match parse_route(req.uri().path()).and_then(|r| call(api, r, req.uri().query())) {
+        Ok(body) => body,
+        Err(e) => return generate_error_response(e),
+    };
+    let etag = etag(&body);
+    let not_modified = req
+        .headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map_or(false, |v| etag_matches(v, &etag));
+    if not_modified {
+        return build_response()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(ETAG, etag.as_str())
+            .body(Body::empty())
+            .unwrap();
+    }
+    build_response()
+        .header(CONTENT_TYPE, "application/json")
+        .header(ETAG, etag.as_str())
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_route() {
+        let route = |path: &str| parse_route(path).ok();
+        assert_eq!(route("/accounts/alice.near"), Some(Route::Account("alice.near".to_string())));
+        assert_eq!(
+            route("/accounts/alice.near/transactions"),
+            Some(Route::AccountTransactions("alice.near".to_string()))
+        );
+        assert_eq!(route("/blocks/10"), Some(Route::Block(10)));
+        let nonce = hash(b"receipt");
+        let path = format!("/receipts/{}", String::from(&nonce));
+        assert_eq!(route(&path), Some(Route::Receipt(nonce)));
+        assert!(is_explorer_path("/blocks/x"));
+        match parse_route("/blocks/x") {
+            Err(RPCError::BadRequest(_)) => {}
+            _ => panic!("expected a bad request"),
+        }
+        match parse_route("/accounts/alice.near/keys") {
+            Err(RPCError::NotFound) => {}
+            _ => panic!("expected not found"),
+        }
+    }
+
+    #[test]
+    fn test_query_param() {
+        assert_eq!(query_param(Some("offset=5&limit=10"), "limit").ok(), Some(Some(10)));
+        assert_eq!(query_param(Some("offset=5"), "limit").ok(), Some(None));
+        assert_eq!(query_param(None, "limit").ok(), Some(None));
+        assert!(query_param(Some("limit=x"), "limit").is_err());
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        let tag = etag(b"body");
+        assert!(etag_matches(&tag, &tag));
+        assert!(etag_matches(&format!("\"other\", W/{}", tag), &tag));
+        assert!(etag_matches("*", &tag));
+        assert!(!etag_matches(&etag(b"other body"), &tag));
+    }
+}
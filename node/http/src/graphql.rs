@@ -0,0 +1,421 @@
+//! GraphQL interface over the same indexes as the REST endpoints: accounts, transactions,
+//! receipts, blocks and the events, i.e. the log lines, of the contracts. Queries are served
+//! at `POST /graphql`, `GET /graphql` serves GraphiQL to explore the schema.
+use std::sync::Arc;
+
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Response, StatusCode};
+use juniper::http::graphiql::graphiql_source;
+use juniper::http::GraphQLRequest;
+use juniper::{
+    DefaultScalarValue, EmptyMutation, FieldResult, ParseScalarResult, ParseScalarValue,
+    RootNode, Value,
+};
+
+use chain::SignedBlock;
+use primitives::hash::{bs58_format, CryptoHash};
+use primitives::types::{AccountId, BlockId};
+use shard::{ReceiptInfo, SignedTransactionInfo};
+use transaction::{ReceiptBody, TransactionResult};
+
+use crate::api::{HttpApi, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use crate::server::build_response;
+use crate::types::ViewAccountRequest;
+
+pub struct Context {
+    pub api: Arc<HttpApi>,
+}
+
+impl juniper::Context for Context {}
+
+/// GraphQL integers are 32-bit, so balances, nonces and block indexes are decimal strings.
+pub struct U64(pub u64);
+
+graphql_scalar!(U64 {
+    description: "Unsigned 64-bit integer as a decimal string."
+
+    resolve(&self) -> Value {
+        Value::scalar(self.0.to_string())
+    }
+
+    from_input_value(v: &InputValue) -> Option<U64> {
+        v.as_scalar_value::<String>().and_then(|s| s.parse::<u64>().ok()).map(U64)
+    }
+
+    from_str<'a>(value: ScalarToken<'a>) -> ParseScalarResult<'a, DefaultScalarValue> {
+        <String as ParseScalarValue>::from_str(value)
+    }
+});
+
+#[derive(GraphQLEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OutcomeKind {
+    Transaction,
+    Receipt,
+}
+
+#[derive(GraphQLInputObject, Default)]
+pub struct OutcomeFilter {
+    kind: Option<OutcomeKind>,
+    /// First block to include.
+    from_block: Option<U64>,
+    /// Last block to include.
+    to_block: Option<U64>,
+}
+
+impl OutcomeFilter {
+    fn matches(&self, kind: OutcomeKind, block_index: u64) -> bool {
+        self.kind.map_or(true, |k| k == kind)
+            && self.from_block.as_ref().map_or(true, |from| block_index >= from.0)
+            && self.to_block.as_ref().map_or(true, |to| block_index <= to.0)
+    }
+}
+
+#[derive(GraphQLObject)]
+pub struct Account {
+    account_id: String,
+    amount: U64,
+    stake: U64,
+    nonce: U64,
+    code_hash: String,
+}
+
+#[derive(GraphQLObject)]
+pub struct Transaction {
+    hash: String,
+    block_index: U64,
+    kind: String,
+    originator: String,
+    /// Contract or receiver of the money, if the transaction has one.
+    receiver: Option<String>,
+    nonce: U64,
+    amount: U64,
+    status: String,
+    logs: Vec<String>,
+    /// Nonces of the receipts the transaction produced.
+    receipts: Vec<String>,
+}
+
+#[derive(GraphQLObject)]
+pub struct Receipt {
+    nonce: String,
+    block_index: U64,
+    kind: String,
+    originator: String,
+    receiver: String,
+    amount: U64,
+    status: String,
+    logs: Vec<String>,
+}
+
+/// Transaction or receipt, whichever `kind` says.
+#[derive(GraphQLObject)]
+pub struct Outcome {
+    kind: OutcomeKind,
+    transaction: Option<Transaction>,
+    receipt: Option<Receipt>,
+}
+
+#[derive(GraphQLObject)]
+pub struct OutcomePage {
+    /// Number of the matching outcomes.
+    total: i32,
+    offset: i32,
+    limit: i32,
+    /// Most recent first.
+    items: Vec<Outcome>,
+}
+
+/// Log line of a transaction or a receipt.
+#[derive(GraphQLObject)]
+pub struct Event {
+    outcome_id: String,
+    position: i32,
+    block_index: U64,
+    message: String,
+}
+
+#[derive(GraphQLObject)]
+pub struct EventPage {
+    total: i32,
+    offset: i32,
+    limit: i32,
+    /// Most recent first.
+    items: Vec<Event>,
+}
+
+#[derive(GraphQLObject)]
+pub struct Block {
+    index: U64,
+    hash: String,
+    parent_hash: String,
+    state_root: String,
+    transactions: Vec<Transaction>,
+    receipts: Vec<Receipt>,
+}
+
+fn status(result: &TransactionResult) -> String {
+    format!("{:?}", result.status)
+}
+
+fn to_transaction(info: SignedTransactionInfo) -> Transaction {
+    let body = &info.transaction.body;
+    Transaction {
+        hash: String::from(&info.transaction.get_hash()),
+        block_index: U64(info.block_index),
+        kind: body.kind().to_string(),
+        originator: body.get_originator(),
+        receiver: body.get_contract_id(),
+        nonce: U64(body.get_nonce()),
+        amount: U64(body.get_amount()),
+        status: status(&info.result),
+        receipts: info.result.receipts.iter().map(String::from).collect(),
+        logs: info.result.logs,
+    }
+}
+
+fn to_receipt(info: ReceiptInfo) -> Receipt {
+    let (kind, amount) = match &info.receipt.body {
+        ReceiptBody::NewCall(call) => ("NewCall", call.amount),
+        ReceiptBody::Callback(_) => ("Callback", 0),
+        ReceiptBody::Refund(amount) => ("Refund", *amount),
+        ReceiptBody::ManaAccounting(_) => ("ManaAccounting", 0),
+    };
+    Receipt {
+        nonce: String::from(&info.receipt.nonce),
+        block_index: U64(info.block_index),
+        kind: kind.to_string(),
+        originator: info.receipt.originator,
+        receiver: info.receipt.receiver,
+        amount: U64(amount),
+        status: status(&info.result),
+        logs: info.result.logs,
+    }
+}
+
+fn parse_hash(value: &str) -> FieldResult<CryptoHash> {
+    bs58_format::deserialize(serde_json::Value::from(value))
+        .map_err(|e| format!("Invalid hash {}: {}", value, e).into())
+}
+
+/// Offset and limit of the page, with the same bounds as the REST listings.
+fn page(offset: Option<i32>, limit: Option<i32>) -> FieldResult<(usize, usize)> {
+    let offset = offset.unwrap_or(0);
+    let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT as i32);
+    if offset < 0 || limit < 0 {
+        return Err("Offset and limit can't be negative".into());
+    }
+    Ok((offset as usize, std::cmp::min(limit as usize, MAX_PAGE_LIMIT)))
+}
+
+/// Outcomes that involve the account and match the filter, most recent first.
+fn account_outcomes(api: &HttpApi, account_id: &AccountId, filter: &OutcomeFilter) -> Vec<Outcome> {
+    let shard_chain = &api.client.shard_chain;
+    shard_chain
+        .get_account_transactions(account_id)
+        .iter()
+        .rev()
+        .filter_map(|id| match shard_chain.get_transaction_info(id) {
+            Some(info) => Some((OutcomeKind::Transaction, info.block_index, Some(info), None)),
+            None => shard_chain
+                .get_receipt_info(id)
+                .map(|info| (OutcomeKind::Receipt, info.block_index, None, Some(info))),
+        })
+        .filter(|(kind, block_index, _, _)| filter.matches(*kind, *block_index))
+        .map(|(kind, _, transaction, receipt)| Outcome {
+            kind,
+            transaction: transaction.map(to_transaction),
+            receipt: receipt.map(to_receipt),
+        })
+        .collect()
+}
+
+fn outcome_events(outcome: Outcome) -> Vec<Event> {
+    let (outcome_id, block_index, logs) = match (outcome.transaction, outcome.receipt) {
+        (Some(t), _) => (t.hash, t.block_index, t.logs),
+        (_, Some(r)) => (r.nonce, r.block_index, r.logs),
+        (None, None) => return vec![],
+    };
+    logs.into_iter()
+        .enumerate()
+        .map(|(position, message)| Event {
+            outcome_id: outcome_id.clone(),
+            position: position as i32,
+            block_index: U64(block_index.0),
+            message,
+        })
+        .collect()
+}
+
+pub struct Query;
+
+graphql_object!(Query: Context |&self| {
+    field account(&executor, account_id: String) -> Option<Account> {
+        let request = ViewAccountRequest { account_id };
+        executor.context().api.view_account(&request).ok().map(|account| Account {
+            account_id: account.account_id,
+            amount: U64(account.amount),
+            stake: U64(account.stake),
+            nonce: U64(account.nonce),
+            code_hash: String::from(&account.code_hash),
+        })
+    }
+
+    field transaction(&executor, hash: String) -> FieldResult<Option<Transaction>> {
+        let hash = parse_hash(&hash)?;
+        let shard_chain = &executor.context().api.client.shard_chain;
+        Ok(shard_chain.get_transaction_info(&hash).map(to_transaction))
+    }
+
+    field receipt(&executor, nonce: String) -> FieldResult<Option<Receipt>> {
+        let nonce = parse_hash(&nonce)?;
+        Ok(executor.context().api.client.shard_chain.get_receipt_info(&nonce).map(to_receipt))
+    }
+
+    field block(&executor, index: Option<U64>, hash: Option<String>) -> FieldResult<Option<Block>> {
+        let block_id = match (index, hash) {
+            (Some(index), None) => BlockId::Number(index.0),
+            (None, Some(hash)) => BlockId::Hash(parse_hash(&hash)?),
+            _ => return Err("Exactly one of index and hash is required".into()),
+        };
+        let shard_chain = &executor.context().api.client.shard_chain;
+        Ok(shard_chain.chain.get_block(&block_id).map(|block| Block {
+            index: U64(block.index()),
+            hash: String::from(&block.hash),
+            parent_hash: String::from(&block.body.header.parent_hash),
+            state_root: String::from(&block.body.header.merkle_root_state),
+            transactions: block
+                .body
+                .transactions
+                .iter()
+                .filter_map(|t| shard_chain.get_transaction_info(&t.get_hash()))
+                .map(to_transaction)
+                .collect(),
+            receipts: block
+                .body
+                .receipts
+                .iter()
+                .flat_map(|b| b.receipts.iter())
+                .filter_map(|r| shard_chain.get_receipt_info(&r.nonce))
+                .map(to_receipt)
+                .collect(),
+        }))
+    }
+
+    field account_transactions(
+        &executor,
+        account_id: String,
+        filter: Option<OutcomeFilter>,
+        offset: Option<i32>,
+        limit: Option<i32>,
+    ) -> FieldResult<OutcomePage> {
+        let (offset, limit) = page(offset, limit)?;
+        let filter = filter.unwrap_or_default();
+        let outcomes = account_outcomes(&executor.context().api, &account_id, &filter);
+        Ok(OutcomePage {
+            total: outcomes.len() as i32,
+            offset: offset as i32,
+            limit: limit as i32,
+            items: outcomes.into_iter().skip(offset).take(limit).collect(),
+        })
+    }
+
+    field account_events(
+        &executor,
+        account_id: String,
+        filter: Option<OutcomeFilter>,
+        offset: Option<i32>,
+        limit: Option<i32>,
+    ) -> FieldResult<EventPage> {
+        let (offset, limit) = page(offset, limit)?;
+        let filter = filter.unwrap_or_default();
+        // Most recent first, including the log lines of an outcome.
+        let events: Vec<Event> = account_outcomes(&executor.context().api, &account_id, &filter)
+            .into_iter()
+            .flat_map(|outcome| outcome_events(outcome).into_iter().rev())
+            .collect();
+        Ok(EventPage {
+            total: events.len() as i32,
+            offset: offset as i32,
+            limit: limit as i32,
+            items: events.into_iter().skip(offset).take(limit).collect(),
+        })
+    }
+});
+
+pub type Schema = RootNode<'static, Query, EmptyMutation<Context>>;
+
+pub fn create_schema() -> Schema {
+    Schema::new(Query, EmptyMutation::new())
+}
+
+/// Executes the query in the body. The schema is cheap to build compared to the queries.
+pub fn handle(api: Arc<HttpApi>, body: &[u8]) -> Response<Body> {
+    let request: GraphQLRequest = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => {
+            return build_response()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap();
+        }
+    };
+    let response = request.execute(&create_schema(), &Context { api });
+    let status = if response.is_ok() { StatusCode::OK } else { StatusCode::BAD_REQUEST };
+    build_response()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&response).unwrap()))
+        .unwrap()
+}
+
+pub fn graphiql() -> Response<Body> {
+    build_response()
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(Body::from(graphiql_source("/graphql")))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outcome_filter() {
+        let filter = OutcomeFilter {
+            kind: Some(OutcomeKind::Receipt),
+            from_block: Some(U64(2)),
+            to_block: None,
+        };
+        assert!(filter.matches(OutcomeKind::Receipt, 2));
+        assert!(!filter.matches(OutcomeKind::Receipt, 1));
+        assert!(!filter.matches(OutcomeKind::Transaction, 3));
+        assert!(OutcomeFilter::default().matches(OutcomeKind::Transaction, 0));
+    }
+
+    #[test]
+    fn test_page() {
+        assert_eq!(page(None, None).ok(), Some((0, DEFAULT_PAGE_LIMIT)));
+        assert_eq!(page(Some(5), Some(1000)).ok(), Some((5, MAX_PAGE_LIMIT)));
+        assert!(page(Some(-1), None).is_err());
+    }
+
+    #[test]
+    fn test_outcome_events() {
+        let receipt = Receipt {
+            nonce: "nonce".to_string(),
+            block_index: U64(3),
+            kind: "NewCall".to_string(),
+            originator: "alice.near".to_string(),
+            receiver: "bob.near".to_string(),
+            amount: U64(0),
+            status: "Completed".to_string(),
+            logs: vec!["a".to_string(), "b".to_string()],
+        };
+        let outcome =
+            Outcome { kind: OutcomeKind::Receipt, transaction: None, receipt: Some(receipt) };
+        let events = outcome_events(outcome);
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[1].position, events[1].message.as_str()), (1, "b"));
+        assert_eq!(events[1].block_index.0, 3);
+    }
+}
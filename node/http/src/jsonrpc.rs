@@ -0,0 +1,290 @@
+//! JSON-RPC 2.0 interface on top of `HttpApi`. Parameters of every method are the same
+//! objects as the bodies of the corresponding REST endpoints.
+use futures::{future, Future};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::api::{HttpApi, RPCError};
+
+/// Maximum size in bytes of the body of a JSON-RPC request, and of the requests to the other
+/// endpoints.
+pub const MAX_REQUEST_SIZE: usize = 10 * 1024 * 1024;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// Request body is larger than `MAX_REQUEST_SIZE`.
+pub const REQUEST_TOO_LARGE: i64 = -32000;
+/// Requested block, transaction or account does not exist.
+pub const NOT_FOUND: i64 = -32001;
+/// Submitted transaction was not admitted into the pool. The data holds the reason.
+pub const TRANSACTION_REJECTED: i64 = -32002;
+pub const SERVICE_UNAVAILABLE: i64 = -32003;
+/// Request is well formed, but can't be served, e.g. an invalid account id.
+pub const BAD_REQUEST: i64 = -32004;
+/// Submitted transaction didn't finish executing in time. The data holds its hash.
+pub const TIMEOUT: i64 = -32005;
+
+#[derive(Serialize, Deserialize)]
+pub struct JsonRpcRequest {
+    pub jsonrpc: String,
+    #[serde(default)]
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl JsonRpcError {
+    pub fn new(code: i64, message: String) -> Self {
+        JsonRpcError { code, message, data: None }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsonRpcResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+}
+
+impl JsonRpcResponse {
+    pub fn new(id: Value, result: Result<Value, JsonRpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        JsonRpcResponse { jsonrpc: "2.0".to_string(), id, result, error }
+    }
+}
+
+/// Errors of the `HttpApi` methods, which differ between the methods.
+trait IntoJsonRpcError {
+    fn into_json_rpc_error(self) -> JsonRpcError;
+}
+
+impl IntoJsonRpcError for RPCError {
+    fn into_json_rpc_error(self) -> JsonRpcError {
+        match self {
+            RPCError::BadRequest(msg) => JsonRpcError::new(BAD_REQUEST, msg),
+            RPCError::TransactionRejected(reason) => JsonRpcError {
+                code: TRANSACTION_REJECTED,
+                message: reason.to_string(),
+                data: serde_json::to_value(&reason).ok(),
+            },
+            RPCError::NotFound => JsonRpcError::new(NOT_FOUND, "Not found".to_string()),
+            RPCError::ServiceUnavailable(msg) => JsonRpcError::new(SERVICE_UNAVAILABLE, msg),
+            RPCError::Timeout(hash) => JsonRpcError {
+                code: TIMEOUT,
+                message: "Transaction didn't finish executing in time".to_string(),
+                data: Some(Value::from(String::from(&hash))),
+            },
+        }
+    }
+}
+
+impl IntoJsonRpcError for String {
+    fn into_json_rpc_error(self) -> JsonRpcError {
+        JsonRpcError::new(BAD_REQUEST, self)
+    }
+}
+
+impl<'a> IntoJsonRpcError for &'a str {
+    fn into_json_rpc_error(self) -> JsonRpcError {
+        JsonRpcError::new(NOT_FOUND, self.to_string())
+    }
+}
+
+impl IntoJsonRpcError for () {
+    fn into_json_rpc_error(self) -> JsonRpcError {
+        JsonRpcError::new(INTERNAL_ERROR, "Internal error".to_string())
+    }
+}
+
+fn parse_params<P: DeserializeOwned>(params: Value) -> Result<P, JsonRpcError> {
+    // Methods without parameters accept a missing `params`.
+    let params = if params.is_null() { Value::Object(Default::default()) } else { params };
+    serde_json::from_value(params)
+        .map_err(|e| JsonRpcError::new(INVALID_PARAMS, format!("Invalid params: {}", e)))
+}
+
+fn to_value<T, E>(result: Result<T, E>) -> Result<Value, JsonRpcError>
+where
+    T: Serialize,
+    E: IntoJsonRpcError,
+{
+    let response = result.map_err(IntoJsonRpcError::into_json_rpc_error)?;
+    serde_json::to_value(&response)
+        .map_err(|e| JsonRpcError::new(INTERNAL_ERROR, e.to_string()))
+}
+
+/// Parses and checks the request envelope.
+pub fn parse_request(body: &[u8]) -> Result<JsonRpcRequest, JsonRpcError> {
+    if body.len() > MAX_REQUEST_SIZE {
+        return Err(JsonRpcError::new(
+            REQUEST_TOO_LARGE,
+            format!("Request of {} bytes exceeds the limit of {}", body.len(), MAX_REQUEST_SIZE),
+        ));
+    }
+    let value: Value = serde_json::from_slice(body)
+        .map_err(|e| JsonRpcError::new(PARSE_ERROR, format!("Parse error: {}", e)))?;
+    let request: JsonRpcRequest = serde_json::from_value(value)
+        .map_err(|e| JsonRpcError::new(INVALID_REQUEST, format!("Invalid request: {}", e)))?;
+    if request.jsonrpc != "2.0" {
+        return Err(JsonRpcError::new(
+            INVALID_REQUEST,
+            format!("Unsupported JSON-RPC version {}", request.jsonrpc),
+        ));
+    }
+    Ok(request)
+}
+
+fn call(api: &HttpApi, method: &str, params: Value) -> Result<Value, JsonRpcError> {
+    match method {
+        "submit_transaction" => to_value(api.submit_transaction(&parse_params(params)?)),
+        "broadcast_tx_async" => to_value(api.broadcast_tx_async(&parse_params(params)?)),
+        "submit_transaction_json" => {
+            to_value(api.submit_transaction_json(&parse_params(params)?))
+        }
+        "signable_bytes" => to_value(api.signable_bytes(&parse_params(params)?)),
+        "query" => to_value(api.query(&parse_params(params)?)),
+        "view_account" => to_value(api.view_account(&parse_params(params)?)),
+        "view_state" => to_value(api.view_state(&parse_params(params)?)),
+        "call_view_function" => to_value(api.call_view_function(&parse_params(params)?)),
+        "simulate_function_call" => {
+            to_value(api.simulate_function_call(&parse_params(params)?))
+        }
+        "suggest_nonce" => to_value(api.suggest_nonce(&parse_params(params)?)),
+        "view_pool" => to_value(api.view_pool(&parse_params(params)?)),
+        "status" => to_value(api.status()),
+        "health" => to_value(api.health()),
+        "network_info" => to_value(api.network_info()),
+        "view_latest_beacon_block" => to_value(api.view_latest_beacon_block()),
+        "get_beacon_block_by_hash" => {
+            to_value(api.get_beacon_block_by_hash(&parse_params(params)?))
+        }
+        "view_authorities" => to_value(api.view_authorities(&parse_params(params)?)),
+        "get_light_client_block_header" => {
+            to_value(api.get_light_client_block_header(&parse_params(params)?))
+        }
+        "view_latest_shard_block" => to_value(api.view_latest_shard_block()),
+        "get_shard_block_by_hash" => to_value(api.get_shard_block_by_hash(&parse_params(params)?)),
+        "get_shard_blocks_by_index" => {
+            to_value(api.get_shard_blocks_by_index(&parse_params(params)?))
+        }
+        "get_shard_block_by_index" => {
+            to_value(api.get_shard_block_by_index(&parse_params(params)?))
+        }
+        "get_transaction_info" => to_value(api.get_transaction_info(&parse_params(params)?)),
+        "get_receipt_info" => to_value(api.get_receipt_info(&parse_params(params)?)),
+        "get_state_changes" => to_value(api.get_state_changes(&parse_params(params)?)),
+        "get_balance_changes" => to_value(api.get_balance_changes(&parse_params(params)?)),
+        "get_account_transactions" => {
+            to_value(api.get_account_transactions(&parse_params(params)?))
+        }
+        "get_outcome_proof" => to_value(api.get_outcome_proof(&parse_params(params)?)),
+        "get_transaction_proof" => to_value(api.get_transaction_proof(&parse_params(params)?)),
+        "get_transaction_result" => to_value(api.get_transaction_result(&parse_params(params)?)),
+        _ => Err(JsonRpcError::new(METHOD_NOT_FOUND, format!("Method {} not found", method))),
+    }
+}
+
+/// Handles a JSON-RPC request body and returns the response to it. The future doesn't fail.
+pub fn handle(
+    api: &HttpApi,
+    body: &[u8],
+) -> Box<Future<Item = JsonRpcResponse, Error = ()> + Send> {
+    let request = match parse_request(body) {
+        Ok(request) => request,
+        Err(error) => return Box::new(future::ok(JsonRpcResponse::new(Value::Null, Err(error)))),
+    };
+    let id = request.id;
+    let result: Box<Future<Item = Value, Error = JsonRpcError> + Send> =
+        match request.method.as_str() {
+            // Waits for the execution of the transaction.
+            "broadcast_tx_commit" => match parse_params(request.params) {
+                Ok(params) => Box::new(api.broadcast_tx_commit(&params).then(to_value)),
+                Err(e) => Box::new(future::err(e)),
+            },
+            // Waits for the execution of the transaction and, if asked, for its finality.
+            "tx_status" => match parse_params(request.params) {
+                Ok(params) => Box::new(api.tx_status(&params).then(to_value)),
+                Err(e) => Box::new(future::err(e)),
+            },
+            method => Box::new(future::result(call(api, method, request.params))),
+        };
+    Box::new(result.then(move |result| Ok(JsonRpcResponse::new(id, result))))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let request =
+            parse_request(br#"{"jsonrpc": "2.0", "id": 1, "method": "view_pool"}"#).unwrap();
+        assert_eq!(request.id, Value::from(1));
+        assert_eq!(request.method, "view_pool");
+        assert!(request.params.is_null());
+
+        let code = |body: &[u8]| parse_request(body).err().map(|e| e.code);
+        assert_eq!(code(b"{"), Some(PARSE_ERROR));
+        assert_eq!(code(br#"{"jsonrpc": "2.0", "id": 1}"#), Some(INVALID_REQUEST));
+        assert_eq!(code(br#"{"jsonrpc": "1.0", "id": 1, "method": "a"}"#), Some(INVALID_REQUEST));
+        assert_eq!(code(&vec![b' '; MAX_REQUEST_SIZE + 1]), Some(REQUEST_TOO_LARGE));
+    }
+
+    #[test]
+    fn test_parse_params() {
+        let params: Result<crate::types::ViewAccountRequest, _> =
+            parse_params(serde_json::json!({ "account_id": 1 }));
+        assert_eq!(params.err().map(|e| e.code), Some(INVALID_PARAMS));
+    }
+
+    #[test]
+    fn test_parse_json_transaction() {
+        let params: crate::types::JsonTransactionRequest = parse_params(serde_json::json!({
+            "transaction": {
+                "sendMoney": {
+                    "nonce": "1",
+                    "originator": "alice.near",
+                    "receiver": "bob.near",
+                    "amount": "10",
+                    "memo": "bWVtbw==",
+                },
+            },
+        }))
+        .unwrap();
+        let transaction =
+            transaction::SignedTransaction::try_from(params.transaction).unwrap();
+        match transaction.body {
+            transaction::TransactionBody::SendMoney(ref t) => {
+                assert_eq!(t.originator, "alice.near");
+                assert_eq!(t.receiver, "bob.near");
+                assert_eq!(t.memo, b"memo".to_vec());
+            }
+            _ => panic!("Expected a send money transaction"),
+        }
+        let value = parse_params::<crate::types::JsonTransactionRequest>(
+            serde_json::json!({ "transaction": { "sendMoney": { "nonce": 1.5 } } }),
+        );
+        assert_eq!(value.err().map(|e| e.code), Some(INVALID_PARAMS));
+    }
+}
@@ -1,11 +1,19 @@
+extern crate base64;
+extern crate byteorder;
 extern crate hyper;
 extern crate futures;
+#[cfg(feature = "graphql")]
+#[macro_use]
+extern crate juniper;
 #[macro_use]
 extern crate log;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate parking_lot;
+extern crate sha1;
+extern crate tokio;
 
 extern crate beacon;
 extern crate client;
@@ -14,5 +22,10 @@ extern crate node_runtime;
 extern crate primitives;
 
 pub mod api;
+pub mod explorer;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod jsonrpc;
 pub mod server;
 pub mod types;
+pub mod ws;
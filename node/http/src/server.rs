@@ -11,10 +11,16 @@ use hyper::rt::{Future, Stream};
 use hyper::service::service_fn;
 
 use crate::api::{HttpApi, RPCError};
+use crate::explorer;
+#[cfg(feature = "graphql")]
+use crate::graphql;
+use crate::jsonrpc;
+use crate::ws;
+use crate::types::{TimeoutResponse, TransactionRejectedResponse};
 
 type BoxFut = Box<Future<Item=Response<Body>, Error=hyper::Error> + Send>;
 
-fn build_response() -> Builder {
+pub(crate) fn build_response() -> Builder {
     let mut builder = Response::builder();
     builder
         .header("Access-Control-Allow-Origin", "*")
@@ -22,11 +28,22 @@ fn build_response() -> Builder {
     builder
 }
 
-fn generate_error_response(error: RPCError) -> Response<Body> {
+pub(crate) fn generate_error_response(error: RPCError) -> Response<Body> {
     let (body, error_code) = match error {
         RPCError::BadRequest(msg) => (Body::from(msg), StatusCode::BAD_REQUEST),
+        RPCError::TransactionRejected(reason) => {
+            let response = TransactionRejectedResponse { message: reason.to_string(), reason };
+            (Body::from(serde_json::to_string(&response).unwrap()), StatusCode::BAD_REQUEST)
+        }
         RPCError::NotFound => (Body::from(""), StatusCode::NOT_FOUND),
         RPCError::ServiceUnavailable(msg) => (Body::from(msg), StatusCode::SERVICE_UNAVAILABLE),
+        RPCError::Timeout(hash) => {
+            let response = TimeoutResponse {
+                message: "Transaction didn't finish executing in time".to_string(),
+                hash,
+            };
+            (Body::from(serde_json::to_string(&response).unwrap()), StatusCode::GATEWAY_TIMEOUT)
+        }
     };
     build_response()
         .status(error_code)
@@ -34,18 +51,71 @@ fn generate_error_response(error: RPCError) -> Response<Body> {
         .unwrap()
 }
 
+enum BodyError {
+    TooLarge,
+    Read(hyper::Error),
+}
+
+/// Reads the body of the request and responds with what `f` returns for it. Stops reading at
+/// the first chunk past `MAX_REQUEST_SIZE`, whatever the `Content-Length` says, and responds
+/// with `PAYLOAD_TOO_LARGE`.
+fn with_body_future<F, R>(req: Request<Body>, f: F) -> BoxFut
+where
+    F: FnOnce(Vec<u8>) -> R + Send + 'static,
+    R: Future<Item = Response<Body>, Error = hyper::Error> + Send + 'static,
+{
+    let body = req.into_body().map_err(BodyError::Read).fold(vec![], |mut body, chunk| {
+        if body.len() + chunk.len() > jsonrpc::MAX_REQUEST_SIZE {
+            return Err(BodyError::TooLarge);
+        }
+        body.extend_from_slice(&chunk);
+        Ok(body)
+    });
+    Box::new(body.then(move |result| -> BoxFut {
+        match result {
+            Ok(body) => Box::new(f(body)),
+            Err(BodyError::TooLarge) => Box::new(future::ok(
+                build_response()
+                    .status(StatusCode::PAYLOAD_TOO_LARGE)
+                    .body(Body::empty())
+                    .unwrap()
+            )),
+            Err(BodyError::Read(e)) => Box::new(future::err(e)),
+        }
+    }))
+}
+
+/// Same as `with_body_future`, for the responses that are ready once the body is read.
+fn with_body<F>(req: Request<Body>, f: F) -> BoxFut
+where
+    F: FnOnce(Vec<u8>) -> Response<Body> + Send + 'static,
+{
+    with_body_future(req, move |body| future::ok(f(body)))
+}
+
 fn serve(http_api: Arc<HttpApi>, req: Request<Body>) -> BoxFut {
     match (req.method(), req.uri().path()) {
         (&Method::OPTIONS, _) => {
             // Pre-flight response for cross site access.
-            Box::new(req.into_body().concat2().map(move |_| {
+            with_body(req, move |_| {
                 build_response()
                     .body(Body::empty())
                     .unwrap()
-            }))
+            })
+        }
+        (&Method::POST, "/") => {
+            with_body_future(req, move |chunk| {
+                jsonrpc::handle(&http_api, &chunk).then(|response| match response {
+                    Ok(response) => Ok(build_response()
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(serde_json::to_string(&response).unwrap()))
+                        .unwrap()),
+                    Err(_) => unreachable!(),
+                })
+            })
         }
         (&Method::POST, "/submit_transaction") => {
-            Box::new(req.into_body().concat2().map(move |chunk| {
+            with_body(req, move |chunk| {
                 match serde_json::from_slice(&chunk) {
                     Ok(data) => {
                         match http_api.submit_transaction(&data) {
@@ -64,11 +134,102 @@ fn serve(http_api: Arc<HttpApi>, req: Request<Body>) -> BoxFut {
                             .unwrap()
                     }
                 }
-            }))
+            })
+        }
+
+        (&Method::POST, "/broadcast_tx_async") => {
+            with_body(req, move |chunk| {
+                match serde_json::from_slice(&chunk) {
+                    Ok(data) => {
+                        match http_api.broadcast_tx_async(&data) {
+                            Ok(response) => {
+                                build_response()
+                                    .body(Body::from(serde_json::to_string(&response).unwrap()))
+                                    .unwrap()
+                            }
+                            Err(e) => generate_error_response(e)
+                        }
+                    }
+                    Err(e) => {
+                        build_response()
+                            .status(StatusCode::BAD_REQUEST)
+                            .body(Body::from(e.to_string()))
+                            .unwrap()
+                    }
+                }
+            })
+        }
+        (&Method::POST, "/submit_transaction_json") => {
+            with_body(req, move |chunk| {
+                match serde_json::from_slice(&chunk) {
+                    Ok(data) => {
+                        match http_api.submit_transaction_json(&data) {
+                            Ok(response) => {
+                                build_response()
+                                    .body(Body::from(serde_json::to_string(&response).unwrap()))
+                                    .unwrap()
+                            }
+                            Err(e) => generate_error_response(e)
+                        }
+                    }
+                    Err(e) => {
+                        build_response()
+                            .status(StatusCode::BAD_REQUEST)
+                            .body(Body::from(e.to_string()))
+                            .unwrap()
+                    }
+                }
+            })
+        }
+        (&Method::POST, "/signable_bytes") => {
+            with_body(req, move |chunk| {
+                match serde_json::from_slice(&chunk) {
+                    Ok(data) => {
+                        match http_api.signable_bytes(&data) {
+                            Ok(response) => {
+                                build_response()
+                                    .body(Body::from(serde_json::to_string(&response).unwrap()))
+                                    .unwrap()
+                            }
+                            Err(e) => generate_error_response(e)
+                        }
+                    }
+                    Err(e) => {
+                        build_response()
+                            .status(StatusCode::BAD_REQUEST)
+                            .body(Body::from(e.to_string()))
+                            .unwrap()
+                    }
+                }
+            })
+        }
+        (&Method::POST, "/broadcast_tx_commit") => {
+            with_body_future(req, move |chunk| {
+                let response: Box<Future<Item = Response<Body>, Error = hyper::Error> + Send> =
+                    match serde_json::from_slice(&chunk) {
+                        Ok(data) => Box::new(http_api.broadcast_tx_commit(&data).then(|result| {
+                            Ok(match result {
+                                Ok(response) => {
+                                    build_response()
+                                        .body(Body::from(serde_json::to_string(&response).unwrap()))
+                                        .unwrap()
+                                }
+                                Err(e) => generate_error_response(e),
+                            })
+                        })),
+                        Err(e) => Box::new(future::ok(
+                            build_response()
+                                .status(StatusCode::BAD_REQUEST)
+                                .body(Body::from(e.to_string()))
+                                .unwrap()
+                        )),
+                    };
+                response
+            })
         }
 
         (&Method::POST, "/call_view_function") => {
-            Box::new(req.into_body().concat2().map(move |chunk| {
+            with_body(req, move |chunk| {
                 match serde_json::from_slice(&chunk) {
                     Ok(data) => {
                         match http_api.call_view_function(&data) {
@@ -92,10 +253,10 @@ fn serve(http_api: Arc<HttpApi>, req: Request<Body>) -> BoxFut {
                             .unwrap()
                     }
                 }
-            }))
+            })
         }
         (&Method::POST, "/view_account") => {
-            Box::new(req.into_body().concat2().map(move |chunk| {
+            with_body(req, move |chunk| {
                 match serde_json::from_slice(&chunk) {
                     Ok(data) => {
                         match http_api.view_account(&data) {
@@ -119,10 +280,59 @@ fn serve(http_api: Arc<HttpApi>, req: Request<Body>) -> BoxFut {
                             .unwrap()
                     }
                 }
-            }))
+            })
+        }
+        (&Method::POST, "/suggest_nonce") => {
+            with_body(req, move |chunk| {
+                match serde_json::from_slice(&chunk) {
+                    Ok(data) => {
+                        match http_api.suggest_nonce(&data) {
+                            Ok(response) => {
+                                build_response()
+                                    .body(Body::from(serde_json::to_string(&response).unwrap()))
+                                    .unwrap()
+                            }
+                            Err(e) => {
+                                build_response()
+                                    .status(StatusCode::BAD_REQUEST)
+                                    .body(Body::from(e.to_string()))
+                                    .unwrap()
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        build_response()
+                            .status(StatusCode::BAD_REQUEST)
+                            .body(Body::from(e.to_string()))
+                            .unwrap()
+                    }
+                }
+            })
+        }
+        (&Method::POST, "/query") => {
+            with_body(req, move |chunk| {
+                match serde_json::from_slice(&chunk) {
+                    Ok(data) => {
+                        match http_api.query(&data) {
+                            Ok(response) => {
+                                build_response()
+                                    .body(Body::from(serde_json::to_string(&response).unwrap()))
+                                    .unwrap()
+                            }
+                            Err(e) => generate_error_response(e)
+                        }
+                    }
+                    Err(e) => {
+                        build_response()
+                            .status(StatusCode::BAD_REQUEST)
+                            .body(Body::from(e.to_string()))
+                            .unwrap()
+                    }
+                }
+            })
         }
         (&Method::POST, "/view_state") => {
-            Box::new(req.into_body().concat2().map(move |chunk| {
+            with_body(req, move |chunk| {
                 match serde_json::from_slice(&chunk) {
                     Ok(data) => {
                         match http_api.view_state(&data) {
@@ -146,7 +356,7 @@ fn serve(http_api: Arc<HttpApi>, req: Request<Body>) -> BoxFut {
                             .unwrap()
                     }
                 }
-            }))
+            })
         }
         (&Method::POST, "/view_latest_beacon_block") => {
             Box::new(future::ok(
@@ -161,7 +371,7 @@ fn serve(http_api: Arc<HttpApi>, req: Request<Body>) -> BoxFut {
             ))
         }
         (&Method::POST, "/get_beacon_block_by_hash") => {
-            Box::new(req.into_body().concat2().map(move |chunk| {
+            with_body(req, move |chunk| {
                 match serde_json::from_slice(&chunk) {
                     Ok(data) => {
                         match http_api.get_beacon_block_by_hash(&data) {
@@ -185,7 +395,7 @@ fn serve(http_api: Arc<HttpApi>, req: Request<Body>) -> BoxFut {
                             .unwrap()
                     }
                 }
-            }))
+            })
         }
         (&Method::POST, "/view_latest_shard_block") => {
             Box::new(future::ok(
@@ -200,7 +410,7 @@ fn serve(http_api: Arc<HttpApi>, req: Request<Body>) -> BoxFut {
             ))
         }
         (&Method::POST, "/get_shard_block_by_hash") => {
-            Box::new(req.into_body().concat2().map(move |chunk| {
+            with_body(req, move |chunk| {
                 match serde_json::from_slice(&chunk) {
                     Ok(data) => {
                         match http_api.get_shard_block_by_hash(&data) {
@@ -224,10 +434,10 @@ fn serve(http_api: Arc<HttpApi>, req: Request<Body>) -> BoxFut {
                             .unwrap()
                     }
                 }
-            }))
+            })
         }
         (&Method::POST, "/get_shard_blocks_by_index") => {
-            Box::new(req.into_body().concat2().map(move |chunk| {
+            with_body(req, move |chunk| {
                 match serde_json::from_slice(&chunk) {
                     Ok(data) => {
                         match http_api.get_shard_blocks_by_index(&data) {
@@ -251,10 +461,10 @@ fn serve(http_api: Arc<HttpApi>, req: Request<Body>) -> BoxFut {
                             .unwrap()
                     }
                 }
-            }))
+            })
         }
         (&Method::POST, "/get_transaction_info") => {
-            Box::new(req.into_body().concat2().map(move |chunk| {
+            with_body(req, move |chunk| {
                 match serde_json::from_slice(&chunk) {
                     Ok(data) => {
                         match http_api.get_transaction_info(&data) {
@@ -273,10 +483,54 @@ fn serve(http_api: Arc<HttpApi>, req: Request<Body>) -> BoxFut {
                             .unwrap()
                     }
                 }
-            }))
+            })
+        }
+        (&Method::POST, "/simulate_function_call") => {
+            with_body(req, move |chunk| {
+                match serde_json::from_slice(&chunk) {
+                    Ok(data) => {
+                        match http_api.simulate_function_call(&data) {
+                            Ok(response) => {
+                                build_response()
+                                    .body(Body::from(serde_json::to_string(&response).unwrap()))
+                                    .unwrap()
+                            }
+                            Err(e) => generate_error_response(e)
+                        }
+                    }
+                    Err(e) => {
+                        build_response()
+                            .status(StatusCode::BAD_REQUEST)
+                            .body(Body::from(e.to_string()))
+                            .unwrap()
+                    }
+                }
+            })
+        }
+        (&Method::POST, "/get_state_changes") => {
+            with_body(req, move |chunk| {
+                match serde_json::from_slice(&chunk) {
+                    Ok(data) => {
+                        match http_api.get_state_changes(&data) {
+                            Ok(response) => {
+                                build_response()
+                                    .body(Body::from(serde_json::to_string(&response).unwrap()))
+                                    .unwrap()
+                            }
+                            Err(e) => generate_error_response(e)
+                        }
+                    }
+                    Err(e) => {
+                        build_response()
+                            .status(StatusCode::BAD_REQUEST)
+                            .body(Body::from(e.to_string()))
+                            .unwrap()
+                    }
+                }
+            })
         }
         (&Method::POST, "/get_transaction_result") => {
-            Box::new(req.into_body().concat2().map(move |chunk| {
+            with_body(req, move |chunk| {
                 match serde_json::from_slice(&chunk) {
                     Ok(data) => {
                         match http_api.get_transaction_result(&data) {
@@ -295,17 +549,72 @@ fn serve(http_api: Arc<HttpApi>, req: Request<Body>) -> BoxFut {
                             .unwrap()
                     }
                 }
-            }))
+            })
+        }
+        (&Method::POST, "/view_pool") => {
+            with_body(req, move |chunk| {
+                match serde_json::from_slice(&chunk) {
+                    Ok(data) => {
+                        match http_api.view_pool(&data) {
+                            Ok(response) => {
+                                build_response()
+                                    .body(Body::from(serde_json::to_string(&response).unwrap()))
+                                    .unwrap()
+                            }
+                            Err(_) => unreachable!()
+                        }
+                    }
+                    Err(e) => {
+                        build_response()
+                            .status(StatusCode::BAD_REQUEST)
+                            .body(Body::from(e.to_string()))
+                            .unwrap()
+                    }
+                }
+            })
+        }
+        #[cfg(feature = "graphql")]
+        (&Method::POST, "/graphql") => {
+            with_body(req, move |chunk| graphql::handle(http_api, &chunk))
+        }
+        #[cfg(feature = "graphql")]
+        (&Method::GET, "/graphql") => Box::new(future::ok(graphql::graphiql())),
+        (&Method::GET, "/ws") => Box::new(future::ok(ws::upgrade(http_api, req))),
+        (&Method::GET, path) if explorer::is_explorer_path(path) => {
+            Box::new(future::ok(explorer::serve(&http_api, &req)))
         }
         (&Method::GET, "/healthz") => {
-            // Assume that, if we can get a latest block, things are healthy
             Box::new(future::ok(
-                match http_api.view_latest_beacon_block() {
-                    Ok(_) => {
+                match http_api.health() {
+                    Ok(()) => {
                         build_response()
                             .body(Body::from(""))
                             .unwrap()
                     }
+                    Err(e) => generate_error_response(e)
+                }
+            ))
+        }
+        (&Method::GET, "/status") => {
+            Box::new(future::ok(
+                match http_api.status() {
+                    Ok(response) => {
+                        build_response()
+                            .body(Body::from(serde_json::to_string(&response).unwrap()))
+                            .unwrap()
+                    }
+                    Err(_) => unreachable!()
+                }
+            ))
+        }
+        (&Method::GET, "/network_info") => {
+            Box::new(future::ok(
+                match http_api.network_info() {
+                    Ok(response) => {
+                        build_response()
+                            .body(Body::from(serde_json::to_string(&response).unwrap()))
+                            .unwrap()
+                    }
                     Err(_) => unreachable!()
                 }
             ))
@@ -1,15 +1,24 @@
 use std::collections::HashMap;
 
-use beacon::types::{BeaconBlock, BeaconBlockHeader, SignedBeaconBlock};
-use near_protos::serde::b64_format as protos_b64_format;
+use beacon::types::{BeaconBlock, BeaconBlockHeader, Finality, SignedBeaconBlock};
+use near_protos::serde::{b64_bytes_format, b64_format as protos_b64_format};
+use near_protos::Message;
 use primitives::hash::{bs58_format, CryptoHash};
-use primitives::signature::{bs58_pub_key_format, PublicKey};
+use primitives::aggregate_signature::BlsPublicKey;
+use primitives::signature::{bs58_pub_key_format, bs58_serializer, PublicKey};
+use primitives::utils::bs58_vec2str;
 use primitives::types::{
-    AccountId, AuthorityStake, Balance, GroupSignature, MerkleHash, ShardId
+    AccountId, AuthorityStake, Balance, Gas, GroupSignature, MerkleHash, ShardId
 };
 use chain::{ShardBlock, ShardBlockHeader, SignedShardBlock, ReceiptBlock};
+use client::{ConnectedPeer, SyncProgress};
+use node_runtime::state_changes::{StateChange, StateChangeKind};
+use node_runtime::FunctionCallSimulation;
+use shard::{AdmissionError, Outcome, ReceiptInfo};
+use shard::pool::{PendingTransactionInfo, PoolStats};
 use transaction::{
-    FinalTransactionResult, SignedTransaction, TransactionResult,
+    BalanceChangeCause, FinalTransactionResult, ReceiptTransaction, SignedTransaction,
+    TransactionResult,
 };
 
 #[derive(Serialize, Deserialize)]
@@ -27,6 +36,105 @@ pub struct ViewAccountResponse {
     pub code_hash: CryptoHash,
 }
 
+/// Block at which the state is queried.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockReference {
+    BlockIndex(u64),
+    BlockHash(#[serde(with = "bs58_format")] CryptoHash),
+    /// Queries the given state root directly. Function calls see the best block index.
+    StateRoot(#[serde(with = "bs58_format")] MerkleHash),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "request_type", rename_all = "snake_case")]
+pub enum Query {
+    ViewAccount {
+        account_id: AccountId,
+    },
+    ViewAccessKey {
+        account_id: AccountId,
+        #[serde(with = "bs58_pub_key_format")]
+        public_key: PublicKey,
+    },
+    ViewCode {
+        account_id: AccountId,
+    },
+    ViewState {
+        account_id: AccountId,
+    },
+    CallFunction {
+        account_id: AccountId,
+        method_name: String,
+        args: Vec<u8>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct QueryRequest {
+    #[serde(flatten)]
+    pub query: Query,
+    /// The best block if missing.
+    pub block: Option<BlockReference>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AccessKeyResponse {
+    pub account_id: AccountId,
+    #[serde(with = "bs58_pub_key_format")]
+    pub public_key: PublicKey,
+    pub nonce: u64,
+    /// Kinds of transactions the key may sign. Empty if the key is unrestricted.
+    pub allowed: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ViewCodeResponse {
+    pub account_id: AccountId,
+    #[serde(with = "bs58_format")]
+    pub code_hash: CryptoHash,
+    pub code: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "request_type", rename_all = "snake_case")]
+pub enum QueryResult {
+    ViewAccount(ViewAccountResponse),
+    ViewAccessKey(AccessKeyResponse),
+    ViewCode(ViewCodeResponse),
+    ViewState(ViewStateResponse),
+    CallFunction(CallViewFunctionResponse),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct QueryResponse {
+    /// Index of the queried block, or of the best block if a state root was queried.
+    pub block_index: u64,
+    #[serde(with = "bs58_format")]
+    pub state_root: MerkleHash,
+    pub result: QueryResult,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SuggestNonceRequest {
+    pub account_id: AccountId,
+    #[serde(with = "bs58_pub_key_format")]
+    pub public_key: PublicKey,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SuggestNonceResponse {
+    pub account_id: AccountId,
+    #[serde(with = "bs58_pub_key_format")]
+    pub public_key: PublicKey,
+    /// Nonce of the account in the best block.
+    pub committed_nonce: u64,
+    /// Nonce to use for the next transaction, after the pending ones in the pool.
+    pub nonce: u64,
+    /// Kinds of transactions the key may sign. Empty if the key is unrestricted.
+    pub allowed: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct CallViewFunctionRequest {
     pub contract_account_id: AccountId,
@@ -39,6 +147,38 @@ pub struct CallViewFunctionResponse {
     pub result: Vec<u8>,
 }
 
+/// Function call to dry-run on top of the best block. It doesn't have to be signed.
+#[derive(Serialize, Deserialize)]
+pub struct SimulateFunctionCallRequest {
+    pub originator: AccountId,
+    pub contract_id: AccountId,
+    pub method_name: String,
+    pub args: Vec<u8>,
+    #[serde(default)]
+    pub amount: Balance,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SimulateFunctionCallResponse {
+    pub result: Vec<u8>,
+    pub logs: Vec<String>,
+    /// Receipts the call would create.
+    pub receipts: Vec<ReceiptTransaction>,
+    /// Gas that would be burnt by the call.
+    pub gas_used: Gas,
+}
+
+impl From<FunctionCallSimulation> for SimulateFunctionCallResponse {
+    fn from(simulation: FunctionCallSimulation) -> Self {
+        SimulateFunctionCallResponse {
+            result: simulation.result,
+            logs: simulation.logs,
+            receipts: simulation.receipts,
+            gas_used: simulation.gas_used,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ViewStateRequest {
     pub contract_account_id: AccountId,
@@ -55,6 +195,9 @@ pub struct AuthorityProposalResponse {
     pub account_id: AccountId,
     #[serde(with = "bs58_pub_key_format")]
     pub public_key: PublicKey,
+    #[serde(with = "bs58_serializer")]
+    pub bls_public_key: BlsPublicKey,
+    /// Proposals of no amount rotate the keys of the authority.
     pub amount: u64,
 }
 
@@ -63,11 +206,25 @@ impl From<AuthorityStake> for AuthorityProposalResponse {
         AuthorityProposalResponse {
             account_id: proposal.account_id,
             public_key: proposal.public_key,
+            bls_public_key: proposal.bls_public_key,
             amount: proposal.amount,
         }
     }
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct ViewAuthoritiesRequest {
+    /// Index of the block, the next one if not given.
+    pub index: Option<u64>,
+}
+
+/// Authorities of a block, one per seat, with the keys they sign it with.
+#[derive(Serialize, Deserialize)]
+pub struct ViewAuthoritiesResponse {
+    pub index: u64,
+    pub authorities: Vec<AuthorityProposalResponse>,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct BeaconBlockHeaderResponse {
     #[serde(with = "bs58_format")]
@@ -76,10 +233,21 @@ pub struct BeaconBlockHeaderResponse {
     pub authority_proposal: Vec<AuthorityProposalResponse>,
     #[serde(with = "bs58_format")]
     pub shard_block_hash: CryptoHash,
+    #[serde(with = "bs58_format")]
+    pub authorities_hash: CryptoHash,
+    #[serde(with = "bs58_format")]
+    pub epoch_authorities_hash: CryptoHash,
+    #[serde(with = "bs58_format")]
+    pub challenges_root: MerkleHash,
+    pub slashed: Vec<AccountId>,
+    pub skipped_heights: u64,
+    #[serde(with = "bs58_format")]
+    pub randomness: CryptoHash,
 }
 
 impl From<BeaconBlockHeader> for BeaconBlockHeaderResponse {
     fn from(header: BeaconBlockHeader) -> Self {
+        let randomness = header.randomness();
         let authority_proposal = header.authority_proposal.into_iter()
             .map(|x| x.into())
             .collect();
@@ -88,6 +256,12 @@ impl From<BeaconBlockHeader> for BeaconBlockHeaderResponse {
             index: header.index,
             authority_proposal,
             shard_block_hash: header.shard_block_hash,
+            authorities_hash: header.authorities_hash,
+            epoch_authorities_hash: header.epoch_authorities_hash,
+            challenges_root: header.challenges_root,
+            slashed: header.slashed,
+            skipped_heights: header.skipped_heights,
+            randomness,
         }
     }
 }
@@ -111,6 +285,9 @@ pub struct SignedBeaconBlockResponse {
     #[serde(with = "bs58_format")]
     pub hash: CryptoHash,
     pub signature: GroupSignature,
+    /// `None` if the block is not on the best chain.
+    #[serde(default)]
+    pub finality: Option<Finality>,
 }
 
 impl From<SignedBeaconBlock> for SignedBeaconBlockResponse {
@@ -119,6 +296,7 @@ impl From<SignedBeaconBlock> for SignedBeaconBlockResponse {
             body: block.body.into(),
             hash: block.hash,
             signature: block.signature,
+            finality: None,
         }
     }
 }
@@ -131,6 +309,13 @@ pub struct ShardBlockHeaderResponse {
     pub index: u64,
     #[serde(with = "bs58_format")]
     pub merkle_root_state: MerkleHash,
+    #[serde(with = "bs58_format")]
+    pub outcome_root: MerkleHash,
+    #[serde(with = "bs58_format")]
+    pub transaction_root: MerkleHash,
+    #[serde(with = "bs58_format")]
+    pub parts_root: MerkleHash,
+    pub total_parts: u64,
 }
 
 impl From<ShardBlockHeader> for ShardBlockHeaderResponse {
@@ -140,6 +325,10 @@ impl From<ShardBlockHeader> for ShardBlockHeaderResponse {
             shard_id: header.shard_id,
             index: header.index,
             merkle_root_state: header.merkle_root_state,
+            outcome_root: header.outcome_root,
+            transaction_root: header.transaction_root,
+            parts_root: header.parts_root,
+            total_parts: header.total_parts,
         }
     }
 }
@@ -187,6 +376,10 @@ pub struct SignedShardBlockResponse {
     #[serde(with = "bs58_format")]
     pub hash: CryptoHash,
     pub signature: GroupSignature,
+    /// Finality of the beacon block that includes the block, `None` if the block is not on the
+    /// best chain.
+    #[serde(default)]
+    pub finality: Option<Finality>,
 }
 
 impl From<SignedShardBlock> for SignedShardBlockResponse {
@@ -195,6 +388,7 @@ impl From<SignedShardBlock> for SignedShardBlockResponse {
             body: block.body.into(),
             hash: block.hash,
             signature: block.signature,
+            finality: None,
         }
     }
 }
@@ -205,6 +399,11 @@ pub struct GetBlockByHashRequest {
     pub hash: CryptoHash,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct GetBlockByIndexRequest {
+    pub index: u64,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct GetBlocksByIndexRequest {
     pub start: Option<u64>,
@@ -226,6 +425,21 @@ pub struct GetTransactionRequest {
 pub struct TransactionResultResponse {
     /// Final result of given transaction, including it's receipts.
     pub result: FinalTransactionResult,
+    /// Finality of the least final block the transaction and its receipts were executed in,
+    /// `None` until they are executed on the best chain.
+    pub finality: Option<Finality>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TransactionStatusRequest {
+    #[serde(with = "bs58_format")]
+    pub hash: CryptoHash,
+    /// Finality to wait for, by default only until the transaction is executed.
+    #[serde(default)]
+    pub wait_until: Option<Finality>,
+    /// How long to wait, in milliseconds.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -234,15 +448,335 @@ pub struct SubmitTransactionResponse {
     pub hash: CryptoHash,
 }
 
+/// Body of the response to a submitted transaction that was rejected.
+#[derive(Serialize, Deserialize)]
+pub struct TransactionRejectedResponse {
+    pub message: String,
+    pub reason: AdmissionError,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ViewPoolRequest {
+    /// Only list the pending transactions of this account.
+    pub account_id: Option<AccountId>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PendingTransactionResponse {
+    #[serde(with = "bs58_format")]
+    pub hash: CryptoHash,
+    pub nonce: u64,
+    pub tip: Balance,
+    pub size: usize,
+    /// Milliseconds since the transaction was added to the pool.
+    pub age_ms: u64,
+}
+
+impl From<PendingTransactionInfo> for PendingTransactionResponse {
+    fn from(info: PendingTransactionInfo) -> Self {
+        let age = info.age;
+        PendingTransactionResponse {
+            hash: info.hash,
+            nonce: info.nonce,
+            tip: info.tip,
+            size: info.size,
+            age_ms: age.as_secs() * 1000 + u64::from(age.subsec_millis()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ViewPoolResponse {
+    pub stats: PoolStats,
+    pub pending: HashMap<AccountId, Vec<PendingTransactionResponse>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OutcomeResponse {
+    #[serde(with = "bs58_format")]
+    pub id: CryptoHash,
+    pub account_id: AccountId,
+    pub result: TransactionResult,
+}
+
+impl From<Outcome> for OutcomeResponse {
+    fn from(outcome: Outcome) -> Self {
+        OutcomeResponse { id: outcome.id, account_id: outcome.account_id, result: outcome.result }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LogsResponse {
+    #[serde(with = "bs58_format")]
+    pub id: CryptoHash,
+    pub account_id: AccountId,
+    pub logs: Vec<String>,
+}
+
+impl From<Outcome> for LogsResponse {
+    fn from(outcome: Outcome) -> Self {
+        LogsResponse {
+            id: outcome.id,
+            account_id: outcome.account_id,
+            logs: outcome.result.logs,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct TransactionInfoResponse {
     pub transaction: SignedTransactionResponse,
     pub block_index: u64,
     pub result: TransactionResult,
+    pub finality: Option<Finality>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetStateChangesRequest {
+    #[serde(with = "bs58_format")]
+    pub block_hash: CryptoHash,
+    /// Only return the changes of this account.
+    pub account_id: Option<AccountId>,
+    /// Only return the changes of these kinds.
+    pub kinds: Option<Vec<StateChangeKind>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StateChangeResponse {
+    pub kind: StateChangeKind,
+    pub account_id: Option<AccountId>,
+    /// Base58 of the raw key.
+    pub key: String,
+    /// New value, `None` if the record was removed.
+    pub value: Option<Vec<u8>>,
+}
+
+impl From<StateChange> for StateChangeResponse {
+    fn from(change: StateChange) -> Self {
+        StateChangeResponse {
+            kind: change.kind,
+            account_id: change.account_id,
+            key: bs58_vec2str(&change.key),
+            value: change.value,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StateChangesResponse {
+    #[serde(with = "bs58_format")]
+    pub block_hash: CryptoHash,
+    pub block_index: u64,
+    pub changes: Vec<StateChangeResponse>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetBalanceChangesRequest {
+    #[serde(with = "bs58_format")]
+    pub block_hash: CryptoHash,
+    /// Only return the changes of this account.
+    pub account_id: Option<AccountId>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BalanceChangeResponse {
+    /// Hash of the transaction or nonce of the receipt that made the change.
+    #[serde(with = "bs58_format")]
+    pub id: CryptoHash,
+    pub account_id: AccountId,
+    pub cause: BalanceChangeCause,
+    pub amount: Balance,
+    pub credit: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BalanceChangesResponse {
+    #[serde(with = "bs58_format")]
+    pub block_hash: CryptoHash,
+    pub block_index: u64,
+    pub changes: Vec<BalanceChangeResponse>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReceiptInfoResponse {
+    pub receipt: ReceiptTransaction,
+    pub block_index: u64,
+    pub result: TransactionResult,
+}
+
+impl From<ReceiptInfo> for ReceiptInfoResponse {
+    fn from(info: ReceiptInfo) -> Self {
+        ReceiptInfoResponse {
+            receipt: info.receipt,
+            block_index: info.block_index,
+            result: info.result,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetAccountTransactionsRequest {
+    pub account_id: AccountId,
+    /// Number of the most recent transactions to skip.
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+/// Transaction or receipt that involves an account.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AccountTransactionResponse {
+    Transaction(TransactionInfoResponse),
+    Receipt(ReceiptInfoResponse),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AccountTransactionsResponse {
+    pub account_id: AccountId,
+    /// Number of the transactions and receipts that involve the account.
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    /// Most recent first.
+    pub transactions: Vec<AccountTransactionResponse>,
+}
+
+/// Proto `SignedTransaction` encoded as the requests that submit it carry it.
+pub fn encode_transaction(transaction: SignedTransaction) -> Vec<u8> {
+    let proto: near_protos::signed_transaction::SignedTransaction = transaction.into();
+    proto.write_to_bytes().expect("Transaction proto has no required fields")
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SubmitTransactionRequest {
-    #[serde(with = "protos_b64_format")]
+    /// Encoded proto `SignedTransaction`, decoded only after its size is checked.
+    #[serde(with = "b64_bytes_format")]
+    pub transaction: Vec<u8>,
+}
+
+/// Transaction in the proto3 JSON mapping of `SignedTransaction`, for clients without a proto
+/// library: the body is under the name of its kind, e.g. `sendMoney`, bytes are in base64 and
+/// integers of 64 bits are strings. The signatures may be left out to get the signable bytes.
+#[derive(Serialize, Deserialize)]
+pub struct JsonTransactionRequest {
     pub transaction: near_protos::signed_transaction::SignedTransaction,
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct SignableBytesResponse {
+    /// Bytes the keys sign the SHA-256 hash of, in base64.
+    pub signable_bytes: String,
+    /// Hash of the signable bytes, which is also the hash of the transaction.
+    #[serde(with = "bs58_format")]
+    pub hash: CryptoHash,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BroadcastTxCommitRequest {
+    /// Encoded proto `SignedTransaction`, like in `SubmitTransactionRequest`.
+    #[serde(with = "b64_bytes_format")]
+    pub transaction: Vec<u8>,
+    /// How long to wait for the execution, in milliseconds.
+    pub timeout_ms: Option<u64>,
+    /// Finality to wait for, by default only until the transaction is executed.
+    #[serde(default)]
+    pub wait_until: Option<Finality>,
+}
+
+/// Body of the response to a transaction that didn't finish executing in time.
+#[derive(Serialize, Deserialize)]
+pub struct TimeoutResponse {
+    pub message: String,
+    #[serde(with = "bs58_format")]
+    pub hash: CryptoHash,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VersionResponse {
+    /// Version of the node software.
+    pub version: String,
+    /// Version of the network protocol, `None` if the node doesn't run the network.
+    pub protocol_version: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SyncInfoResponse {
+    pub latest_block_index: u64,
+    #[serde(with = "bs58_format")]
+    pub latest_block_hash: CryptoHash,
+    #[serde(with = "bs58_format")]
+    pub latest_state_root: MerkleHash,
+    pub latest_beacon_block_index: u64,
+    /// Index of the last final beacon block, the blocks up to it are never reverted.
+    #[serde(default)]
+    pub final_beacon_block_index: u64,
+    /// Whether a peer knows of a better block than the node.
+    pub syncing: bool,
+    /// Progress of catching up with the peers, if the node is syncing.
+    #[serde(default)]
+    pub sync_progress: Option<SyncProgressResponse>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SyncProgressResponse {
+    /// Best beacon block index announced by the peers.
+    pub target_index: u64,
+    /// Index of the last downloaded header, headers are downloaded ahead of the blocks.
+    pub header_index: u64,
+    /// Index of the last downloaded block.
+    pub block_index: u64,
+}
+
+impl From<SyncProgress> for SyncProgressResponse {
+    fn from(progress: SyncProgress) -> Self {
+        SyncProgressResponse {
+            target_index: progress.target_index,
+            header_index: progress.header_index,
+            block_index: progress.block_index,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StatusResponse {
+    pub version: VersionResponse,
+    /// Hash of the genesis shard block, that the transactions are signed for.
+    #[serde(with = "bs58_format")]
+    pub genesis_hash: CryptoHash,
+    pub account_id: AccountId,
+    /// Whether the node is one of the authorities of the next block.
+    pub is_authority: bool,
+    pub num_peers: usize,
+    pub sync_info: SyncInfoResponse,
+    /// Mana accounting refunds dropped since the start, because their TX stake was gone.
+    pub dropped_mana_refunds: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PeerResponse {
+    pub account_id: Option<AccountId>,
+    pub protocol_version: u32,
+    pub node_version: String,
+    pub best_index: u64,
+    #[serde(with = "bs58_format")]
+    pub best_hash: CryptoHash,
+}
+
+impl From<ConnectedPeer> for PeerResponse {
+    fn from(peer: ConnectedPeer) -> Self {
+        PeerResponse {
+            account_id: peer.account_id,
+            protocol_version: peer.protocol_version,
+            node_version: peer.node_version,
+            best_index: peer.best_index,
+            best_hash: peer.best_hash,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NetworkInfoResponse {
+    pub protocol_version: Option<u32>,
+    pub num_peers: usize,
+    pub peers: Vec<PeerResponse>,
+}
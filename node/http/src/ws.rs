@@ -0,0 +1,358 @@
+//! WebSocket endpoint for subscriptions to the chain events. Clients send JSON-RPC requests:
+//! `subscribe` with `{"kind": "blocks"}`, `{"kind": "outcomes", "account_id": ...}` or
+//! `{"kind": "logs", "account_id": ...}` (the account is optional for logs) returns the id of the
+//! subscription, `unsubscribe` with `{"subscription": id}` cancels it. Events are pushed as
+//! `subscription` notifications with the id and the result.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use byteorder::{BigEndian, ByteOrder};
+use futures::sync::mpsc::{unbounded, UnboundedSender};
+use futures::{future, stream, Future, Stream};
+use hyper::{Body, Request, Response, StatusCode};
+use parking_lot::Mutex;
+use serde_json::Value;
+use tokio::io::{read_exact, write_all, AsyncRead, AsyncWrite};
+
+use primitives::types::AccountId;
+use shard::BlockEvent;
+
+use crate::api::HttpApi;
+use crate::jsonrpc::{
+    parse_request, JsonRpcError, JsonRpcResponse, INVALID_PARAMS, METHOD_NOT_FOUND,
+};
+use crate::types::{LogsResponse, OutcomeResponse, SignedShardBlockResponse};
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Maximum size in bytes of a message from the client.
+const MAX_MESSAGE_SIZE: u64 = 64 * 1024;
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Subscription {
+    Blocks,
+    /// Outcomes of the transactions and receipts executed by the account.
+    Outcomes { account_id: AccountId },
+    /// Logs of the contracts, of the given one or of all of them.
+    Logs { account_id: Option<AccountId> },
+}
+
+impl Subscription {
+    fn notifications(&self, event: &BlockEvent) -> Vec<Value> {
+        match self {
+            Subscription::Blocks => {
+                let block: SignedShardBlockResponse = event.block.clone().into();
+                serde_json::to_value(&block).into_iter().collect()
+            }
+            Subscription::Outcomes { account_id } => event
+                .outcomes
+                .iter()
+                .filter(|o| &o.account_id == account_id)
+                .filter_map(|o| serde_json::to_value(&OutcomeResponse::from(o.clone())).ok())
+                .collect(),
+            Subscription::Logs { account_id } => event
+                .outcomes
+                .iter()
+                .filter(|o| !o.result.logs.is_empty())
+                .filter(|o| account_id.as_ref().map_or(true, |a| a == &o.account_id))
+                .filter_map(|o| serde_json::to_value(&LogsResponse::from(o.clone())).ok())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct UnsubscribeParams {
+    subscription: u64,
+}
+
+#[derive(Serialize)]
+struct Notification {
+    jsonrpc: String,
+    method: String,
+    params: NotificationParams,
+}
+
+#[derive(Serialize)]
+struct NotificationParams {
+    subscription: u64,
+    result: Value,
+}
+
+/// Subscriptions of a single connection.
+#[derive(Default)]
+struct Subscriptions {
+    next_id: u64,
+    active: HashMap<u64, Subscription>,
+}
+
+/// Value of the `Sec-WebSocket-Accept` header for the given `Sec-WebSocket-Key`.
+fn accept_key(key: &[u8]) -> String {
+    let mut sha1 = sha1::Sha1::new();
+    sha1.update(key);
+    sha1.update(ACCEPT_GUID.as_bytes());
+    base64::encode(&sha1.digest().bytes())
+}
+
+/// Encodes an unmasked frame, as sent by the server.
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    let len = payload.len();
+    if len < 126 {
+        frame.push(len as u8);
+    } else if len <= 0xFFFF {
+        let mut bytes = [0u8; 2];
+        BigEndian::write_u16(&mut bytes, len as u16);
+        frame.push(126);
+        frame.extend_from_slice(&bytes);
+    } else {
+        let mut bytes = [0u8; 8];
+        BigEndian::write_u64(&mut bytes, len as u64);
+        frame.push(127);
+        frame.extend_from_slice(&bytes);
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Decoded header of a frame sent by the client.
+#[derive(Debug, PartialEq)]
+struct FrameHeader {
+    opcode: u8,
+    /// Length of the payload, or the number of the extended length bytes that follow if it
+    /// is 126 or 127.
+    len: u8,
+}
+
+fn parse_frame_header(bytes: [u8; 2]) -> Result<FrameHeader, String> {
+    if bytes[0] & 0x80 == 0 {
+        return Err("Fragmented messages are not supported".to_string());
+    }
+    if bytes[1] & 0x80 == 0 {
+        return Err("Client frames must be masked".to_string());
+    }
+    Ok(FrameHeader { opcode: bytes[0] & 0x0F, len: bytes[1] & 0x7F })
+}
+
+fn unmask(mask: [u8; 4], payload: &mut [u8]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+type BoxIoFuture<T> = Box<Future<Item = T, Error = String> + Send>;
+
+/// Reads a single frame and returns its opcode and the unmasked payload.
+fn read_frame<R: AsyncRead + Send + 'static>(reader: R) -> BoxIoFuture<(R, u8, Vec<u8>)> {
+    let task = read_exact(reader, [0u8; 2])
+        .map_err(|e| e.to_string())
+        .and_then(|(reader, header)| parse_frame_header(header).map(|h| (reader, h)))
+        .and_then(|(reader, header)| -> BoxIoFuture<(R, u8, u64)> {
+            let opcode = header.opcode;
+            match header.len {
+                126 => Box::new(
+                    read_exact(reader, [0u8; 2])
+                        .map_err(|e| e.to_string())
+                        .map(move |(reader, len)| {
+                            (reader, opcode, u64::from(BigEndian::read_u16(&len)))
+                        }),
+                ),
+                127 => Box::new(
+                    read_exact(reader, [0u8; 8])
+                        .map_err(|e| e.to_string())
+                        .map(move |(reader, len)| (reader, opcode, BigEndian::read_u64(&len))),
+                ),
+                len => Box::new(future::ok((reader, opcode, u64::from(len)))),
+            }
+        })
+        .and_then(|(reader, opcode, len)| {
+            if len > MAX_MESSAGE_SIZE {
+                return Err(format!("Message of {} bytes exceeds the limit", len));
+            }
+            Ok((reader, opcode, len))
+        })
+        .and_then(|(reader, opcode, len)| {
+            read_exact(reader, [0u8; 4])
+                .and_then(move |(reader, mask)| {
+                    read_exact(reader, vec![0u8; len as usize]).map(move |(reader, mut payload)| {
+                        unmask(mask, &mut payload);
+                        (reader, opcode, payload)
+                    })
+                })
+                .map_err(|e| e.to_string())
+        });
+    Box::new(task)
+}
+
+/// Handles a text message and returns the response to it.
+fn handle_message(subscriptions: &Mutex<Subscriptions>, message: &[u8]) -> JsonRpcResponse {
+    let request = match parse_request(message) {
+        Ok(request) => request,
+        Err(error) => return JsonRpcResponse::new(Value::Null, Err(error)),
+    };
+    let invalid_params = |e: serde_json::Error| {
+        JsonRpcError::new(INVALID_PARAMS, format!("Invalid params: {}", e))
+    };
+    let result = match request.method.as_str() {
+        "subscribe" => serde_json::from_value(request.params).map_err(invalid_params).map(
+            |subscription: Subscription| {
+                let mut subscriptions = subscriptions.lock();
+                let id = subscriptions.next_id;
+                subscriptions.next_id += 1;
+                subscriptions.active.insert(id, subscription);
+                Value::from(id)
+            },
+        ),
+        "unsubscribe" => serde_json::from_value(request.params).map_err(invalid_params).map(
+            |params: UnsubscribeParams| {
+                Value::from(subscriptions.lock().active.remove(&params.subscription).is_some())
+            },
+        ),
+        method => {
+            Err(JsonRpcError::new(METHOD_NOT_FOUND, format!("Method {} not found", method)))
+        }
+    };
+    JsonRpcResponse::new(request.id, result)
+}
+
+/// Serves a single connection after the upgrade.
+fn serve_connection<S>(http_api: Arc<HttpApi>, socket: S)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, writer) = socket.split();
+    let (frames_tx, frames_rx) = unbounded::<Vec<u8>>();
+    let subscriptions = Arc::new(Mutex::new(Subscriptions::default()));
+
+    let write_task = frames_rx
+        .fold(writer, |writer, frame| write_all(writer, frame).map(|(w, _)| w).map_err(|_| ()))
+        .map(|_| ());
+    tokio::spawn(write_task);
+
+    let events_tx = frames_tx.clone();
+    let events_subscriptions = subscriptions.clone();
+    let events_task = http_api.subscribe_to_blocks().for_each(move |event| {
+        let subscriptions = events_subscriptions.lock();
+        for (&id, subscription) in subscriptions.active.iter() {
+            for result in subscription.notifications(&event) {
+                let notification = Notification {
+                    jsonrpc: "2.0".to_string(),
+                    method: "subscription".to_string(),
+                    params: NotificationParams { subscription: id, result },
+                };
+                let text = serde_json::to_string(&notification).unwrap();
+                let frame = encode_frame(OPCODE_TEXT, text.as_bytes());
+                // Fails once the connection is closed, which ends this task.
+                events_tx.unbounded_send(frame).map_err(|_| ())?;
+            }
+        }
+        Ok(())
+    });
+    tokio::spawn(events_task);
+
+    let read_task = stream::unfold(Some(reader), |reader| {
+        reader.map(|reader| read_frame(reader).map(|(reader, opcode, payload)| {
+            let next = if opcode == OPCODE_CLOSE { None } else { Some(reader) };
+            ((opcode, payload), next)
+        }))
+    })
+    .for_each(move |(opcode, payload)| {
+        let frame = match opcode {
+            OPCODE_TEXT => {
+                let response = handle_message(&subscriptions, &payload);
+                encode_frame(OPCODE_TEXT, serde_json::to_string(&response).unwrap().as_bytes())
+            }
+            OPCODE_PING => encode_frame(OPCODE_PONG, &payload),
+            OPCODE_CLOSE => encode_frame(OPCODE_CLOSE, &[]),
+            _ => return Ok(()),
+        };
+        frames_tx.unbounded_send(frame).map_err(|e| e.to_string())
+    })
+    .map_err(|e| debug!(target: "near-rpc", "WebSocket connection closed: {}", e));
+    tokio::spawn(read_task);
+}
+
+/// Upgrades the request to a WebSocket connection.
+pub fn upgrade(http_api: Arc<HttpApi>, req: Request<Body>) -> Response<Body> {
+    let key = match req.headers().get(hyper::header::SEC_WEBSOCKET_KEY) {
+        Some(key) => accept_key(key.as_bytes()),
+        None => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from("Expected a WebSocket upgrade"))
+                .unwrap();
+        }
+    };
+    let task = req
+        .into_body()
+        .on_upgrade()
+        .map(move |upgraded| serve_connection(http_api, upgraded))
+        .map_err(|e| error!(target: "near-rpc", "WebSocket upgrade failed: {}", e));
+    tokio::spawn(task);
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(hyper::header::UPGRADE, "websocket")
+        .header(hyper::header::CONNECTION, "Upgrade")
+        .header(hyper::header::SEC_WEBSOCKET_ACCEPT, key)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_key() {
+        // Example from RFC 6455.
+        assert_eq!(accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn test_frames() {
+        assert_eq!(encode_frame(OPCODE_TEXT, b"hi"), vec![0x81, 2, b'h', b'i']);
+        let frame = encode_frame(OPCODE_TEXT, &[0; 300]);
+        assert_eq!(&frame[..4], &[0x81, 126, 1, 44]);
+        assert_eq!(frame.len(), 304);
+
+        assert_eq!(
+            parse_frame_header([0x81, 0x85]),
+            Ok(FrameHeader { opcode: OPCODE_TEXT, len: 5 })
+        );
+        assert!(parse_frame_header([0x01, 0x85]).is_err());
+        assert!(parse_frame_header([0x81, 0x05]).is_err());
+
+        // Masked "Hello" from RFC 6455.
+        let mut payload = vec![0x7f, 0x9f, 0x4d, 0x51, 0x58];
+        unmask([0x37, 0xfa, 0x21, 0x3d], &mut payload);
+        assert_eq!(payload, b"Hello");
+    }
+
+    #[test]
+    fn test_subscriptions() {
+        let subscriptions = Mutex::new(Subscriptions::default());
+        let request = br#"{"jsonrpc": "2.0", "id": 1, "method": "subscribe",
+            "params": {"kind": "outcomes", "account_id": "alice.near"}}"#;
+        let response = handle_message(&subscriptions, request);
+        assert_eq!(response.result, Some(Value::from(0)));
+        assert_eq!(
+            subscriptions.lock().active[&0],
+            Subscription::Outcomes { account_id: "alice.near".to_string() }
+        );
+
+        let request = br#"{"jsonrpc": "2.0", "id": 2, "method": "unsubscribe",
+            "params": {"subscription": 0}}"#;
+        assert_eq!(handle_message(&subscriptions, request).result, Some(Value::from(true)));
+        assert!(subscriptions.lock().active.is_empty());
+
+        let request = br#"{"jsonrpc": "2.0", "id": 3, "method": "subscribe",
+            "params": {"kind": "unknown"}}"#;
+        let response = handle_message(&subscriptions, request);
+        assert_eq!(response.error.map(|e| e.code), Some(INVALID_PARAMS));
+    }
+}
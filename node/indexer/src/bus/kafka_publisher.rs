@@ -0,0 +1,33 @@
+//! Publishes to Kafka, waiting for all in-sync replicas to acknowledge every message.
+use std::time::Duration;
+
+use kafka::producer::{Producer, Record, RequiredAcks};
+
+use super::Publisher;
+
+const ACK_TIMEOUT_MS: u64 = 5000;
+
+pub struct KafkaPublisher {
+    producer: Producer,
+}
+
+impl KafkaPublisher {
+    /// Connects to the comma-separated brokers, e.g. `host1:9092,host2:9092`.
+    pub fn new(brokers: &str) -> Result<Self, String> {
+        let hosts = brokers.split(',').map(String::from).collect();
+        Producer::from_hosts(hosts)
+            .with_ack_timeout(Duration::from_millis(ACK_TIMEOUT_MS))
+            .with_required_acks(RequiredAcks::All)
+            .create()
+            .map(|producer| KafkaPublisher { producer })
+            .map_err(|e| format!("Failed to connect to Kafka at {}: {}", brokers, e))
+    }
+}
+
+impl Publisher for KafkaPublisher {
+    fn publish(&mut self, topic: &str, key: &str, payload: &[u8]) -> Result<(), String> {
+        self.producer
+            .send(&Record::from_key_value(topic, key.as_bytes(), payload))
+            .map_err(|e| e.to_string())
+    }
+}
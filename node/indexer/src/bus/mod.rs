@@ -0,0 +1,259 @@
+//! Publishes the streamed blocks to an event bus, e.g. Kafka or NATS, for processing outside
+//! of the node. Every block is published to `<prefix>.blocks`, followed by a message per
+//! receipt and transaction to `<prefix>.outcomes` and a message per log line to
+//! `<prefix>.events`. Delivery is at least once: the high-water mark is persisted only after
+//! all messages of the block were accepted, and after a restart publishing resumes after it.
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use futures::Stream;
+use serde::Serialize;
+
+use client::Client;
+use transaction::LogEntry;
+
+use crate::{IndexerReceipt, IndexerTransaction, StreamerMessage};
+
+#[cfg(feature = "kafka-publisher")]
+pub mod kafka_publisher;
+#[cfg(feature = "nats-publisher")]
+pub mod nats_publisher;
+
+/// Path of the high-water mark relative to the base path of the node.
+pub const HIGH_WATER_MARK_PATH: &str = "storage/event_bus_high_water_mark";
+
+/// Delay before publishing a message again after the bus failed to accept it.
+const RETRY_DELAY_MS: u64 = 1000;
+
+/// Event bus the messages are published to.
+pub trait Publisher: Send {
+    /// Returns once the bus has accepted the message.
+    fn publish(&mut self, topic: &str, key: &str, payload: &[u8]) -> Result<(), String>;
+}
+
+#[derive(Debug, PartialEq)]
+pub struct BusMessage {
+    pub topic: String,
+    /// Hash of the block or id of the outcome, so that the events of an outcome share the key.
+    pub key: String,
+    /// JSON of the message.
+    pub payload: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct OutcomeMessage<'a> {
+    block_index: u64,
+    id: &'a str,
+    transaction: Option<&'a IndexerTransaction>,
+    receipt: Option<&'a IndexerReceipt>,
+}
+
+#[derive(Serialize)]
+struct EventMessage<'a> {
+    block_index: u64,
+    outcome_id: &'a str,
+    position: usize,
+    message: &'a str,
+}
+
+fn to_payload<T: Serialize>(value: &T) -> Vec<u8> {
+    serde_json::to_vec(value).expect("Failed to serialize the bus message")
+}
+
+/// Messages of the block in the order they are published. Receipts come before the
+/// transactions, as in the block.
+pub fn bus_messages(topic_prefix: &str, message: &StreamerMessage) -> Vec<BusMessage> {
+    let topic = |name| format!("{}.{}", topic_prefix, name);
+    let block_index = message.index();
+    let mut result = vec![BusMessage {
+        topic: topic("blocks"),
+        key: String::from(&message.header.hash),
+        payload: to_payload(message),
+    }];
+    for shard in &message.shards {
+        let receipts =
+            shard.receipts.iter().map(|r| (String::from(&r.receipt.nonce), None, Some(r)));
+        let transactions = shard
+            .transactions
+            .iter()
+            .map(|t| (String::from(&t.transaction.get_hash()), Some(t), None));
+        let outcomes: Vec<(String, Option<&IndexerTransaction>, Option<&IndexerReceipt>)> =
+            receipts.chain(transactions).collect();
+        for (id, transaction, receipt) in outcomes {
+            let outcome = OutcomeMessage { block_index, id: &id, transaction, receipt };
+            result.push(BusMessage {
+                topic: topic("outcomes"),
+                key: id.clone(),
+                payload: to_payload(&outcome),
+            });
+            let logs: &[LogEntry] = match (transaction, receipt) {
+                (Some(t), _) => &t.result.logs,
+                (_, Some(r)) => &r.result.logs,
+                (None, None) => &[],
+            };
+            for (position, log) in logs.iter().enumerate() {
+                let event = EventMessage { block_index, outcome_id: &id, position, message: log };
+                result.push(BusMessage {
+                    topic: topic("events"),
+                    key: id.clone(),
+                    payload: to_payload(&event),
+                });
+            }
+        }
+    }
+    result
+}
+
+/// Index of the last block whose messages were all published, persisted in a file.
+pub struct HighWaterMark {
+    path: PathBuf,
+}
+
+impl HighWaterMark {
+    pub fn new(path: PathBuf) -> Self {
+        HighWaterMark { path }
+    }
+
+    /// `None` if nothing was published yet.
+    pub fn load(&self) -> io::Result<Option<u64>> {
+        match fs::read_to_string(&self.path) {
+            Ok(value) => value
+                .trim()
+                .parse::<u64>()
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replaces the file by renaming, so that a crash leaves either the old or the new mark.
+    pub fn store(&self, index: u64) -> io::Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, index.to_string())?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+/// Connects to the bus with the given url, `kafka://host:port[,host:port...]` or
+/// `nats://host:port`, if the node was built with its support.
+pub fn connect(url: &str) -> Result<Box<Publisher>, String> {
+    #[cfg(feature = "kafka-publisher")]
+    {
+        if url.starts_with("kafka://") {
+            let brokers = &url["kafka://".len()..];
+            return Ok(Box::new(kafka_publisher::KafkaPublisher::new(brokers)?));
+        }
+    }
+    #[cfg(feature = "nats-publisher")]
+    {
+        if url.starts_with("nats://") {
+            return Ok(Box::new(nats_publisher::NatsPublisher::new(url)?));
+        }
+    }
+    Err(format!("Unsupported event bus {}", url))
+}
+
+fn publish_with_retry(publisher: &mut Publisher, message: &BusMessage) {
+    let mut attempts = 0;
+    while let Err(e) = publisher.publish(&message.topic, &message.key, &message.payload) {
+        attempts += 1;
+        warn!(
+            target: "indexer",
+            "Failed to publish to {} ({} attempts): {}",
+            message.topic,
+            attempts,
+            e
+        );
+        thread::sleep(Duration::from_millis(RETRY_DELAY_MS));
+    }
+}
+
+/// Starts publishing the blocks on a separate thread, from the one after the high-water mark.
+/// A message the bus fails to accept is retried until it succeeds, so that the order is kept.
+pub fn spawn_publisher(
+    client: Arc<Client>,
+    mut publisher: Box<Publisher>,
+    topic_prefix: String,
+    high_water_mark: HighWaterMark,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let start_index = high_water_mark
+            .load()
+            .expect("Failed to read the event bus high-water mark")
+            .map_or(0, |index| index + 1);
+        info!(target: "indexer", "Publishing blocks to the event bus from {}", start_index);
+        for message in crate::start(client, start_index).wait() {
+            let message = match message {
+                Ok(message) => message,
+                Err(()) => break,
+            };
+            for bus_message in bus_messages(&topic_prefix, &message) {
+                publish_with_retry(publisher.as_mut(), &bus_message);
+            }
+            if let Err(e) = high_water_mark.store(message.index()) {
+                error!(target: "indexer", "Failed to store the event bus high-water mark: {}", e);
+                break;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chain::{SignedBlock, SignedShardBlock};
+    use primitives::hash::CryptoHash;
+    use transaction::{ReceiptBody, ReceiptTransaction, TransactionResult};
+
+    use crate::IndexerShard;
+
+    #[test]
+    fn test_bus_messages() {
+        let root = CryptoHash::default();
+        let block = SignedShardBlock::new(0, 3, CryptoHash::default(), root, root, vec![], vec![]);
+        let receipt = ReceiptTransaction::new(
+            "alice.near".to_string(),
+            "bob.near".to_string(),
+            CryptoHash::default(),
+            ReceiptBody::Refund(10),
+        );
+        let logs = vec!["a".to_string(), "b".to_string()];
+        let result = TransactionResult { logs, ..Default::default() };
+        let message = StreamerMessage {
+            header: block.header(),
+            shards: vec![IndexerShard {
+                shard_id: 0,
+                transactions: vec![],
+                receipts: vec![IndexerReceipt { receipt: receipt.clone(), result }],
+                state_changes: vec![],
+            }],
+        };
+        let messages = bus_messages("near", &message);
+        let topics: Vec<_> = messages.iter().map(|m| m.topic.as_str()).collect();
+        assert_eq!(topics, vec!["near.blocks", "near.outcomes", "near.events", "near.events"]);
+        assert_eq!(messages[0].key, String::from(&block.hash));
+        assert!(messages[1..].iter().all(|m| m.key == String::from(&receipt.nonce)));
+        let event: serde_json::Value = serde_json::from_slice(&messages[3].payload).unwrap();
+        assert_eq!(event["block_index"], 3);
+        assert_eq!(event["position"], 1);
+        assert_eq!(event["message"], "b");
+    }
+
+    #[test]
+    fn test_high_water_mark() {
+        let path =
+            std::env::temp_dir().join(format!("near-high-water-mark-{}", std::process::id()));
+        let mark = HighWaterMark::new(path.clone());
+        assert_eq!(mark.load().unwrap(), None);
+        mark.store(5).unwrap();
+        mark.store(7).unwrap();
+        assert_eq!(mark.load().unwrap(), Some(7));
+        fs::remove_file(path).unwrap();
+    }
+}
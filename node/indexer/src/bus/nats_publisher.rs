@@ -0,0 +1,25 @@
+//! Publishes to NATS. The client runs in the synchronous mode, so that a message counts as
+//! published only after the server acknowledged it.
+use nats::Client;
+
+use super::Publisher;
+
+pub struct NatsPublisher {
+    client: Client,
+}
+
+impl NatsPublisher {
+    pub fn new(url: &str) -> Result<Self, String> {
+        let mut client = Client::new(url)
+            .map_err(|e| format!("Failed to connect to NATS at {}: {}", url, e))?;
+        client.set_synchronous(true);
+        Ok(NatsPublisher { client })
+    }
+}
+
+impl Publisher for NatsPublisher {
+    /// NATS has no keys, the subject is the topic.
+    fn publish(&mut self, topic: &str, _key: &str, payload: &[u8]) -> Result<(), String> {
+        self.client.publish(topic, payload).map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,235 @@
+//! Exports the streamed blocks into a normalized Postgres schema, so that an explorer can run
+//! on top of the database. Every block is written in one database transaction together with
+//! the checkpoint, so that after a restart the export resumes after the last exported block.
+use std::sync::Arc;
+use std::thread;
+
+use futures::Stream;
+use postgres::{Connection, TlsMode};
+
+use client::Client;
+use node_runtime::state_changes::StateChangeKind;
+use primitives::types::AccountId;
+use transaction::{LogEntry, ReceiptBody};
+
+use crate::{IndexerShard, StreamerMessage};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS blocks (
+    block_index BIGINT PRIMARY KEY,
+    hash TEXT NOT NULL UNIQUE,
+    parent_hash TEXT NOT NULL,
+    shard_id BIGINT NOT NULL,
+    state_root TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS transactions (
+    hash TEXT PRIMARY KEY,
+    block_index BIGINT NOT NULL REFERENCES blocks (block_index),
+    position INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    originator TEXT NOT NULL,
+    contract_id TEXT,
+    nonce BIGINT NOT NULL,
+    status TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS transactions_originator ON transactions (originator);
+CREATE TABLE IF NOT EXISTS receipts (
+    nonce TEXT PRIMARY KEY,
+    block_index BIGINT NOT NULL REFERENCES blocks (block_index),
+    position INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    originator TEXT NOT NULL,
+    receiver TEXT NOT NULL,
+    status TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS receipts_receiver ON receipts (receiver);
+CREATE TABLE IF NOT EXISTS accounts (
+    account_id TEXT PRIMARY KEY,
+    created_at_block_index BIGINT NOT NULL,
+    updated_at_block_index BIGINT NOT NULL,
+    deleted BOOLEAN NOT NULL
+);
+CREATE TABLE IF NOT EXISTS events (
+    outcome_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    block_index BIGINT NOT NULL REFERENCES blocks (block_index),
+    message TEXT NOT NULL,
+    PRIMARY KEY (outcome_id, position)
+);
+CREATE TABLE IF NOT EXISTS checkpoint (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    block_index BIGINT NOT NULL
+);
+";
+
+fn receipt_kind(body: &ReceiptBody) -> &'static str {
+    match body {
+        ReceiptBody::NewCall(_) => "NewCall",
+        ReceiptBody::Callback(_) => "Callback",
+        ReceiptBody::Refund(_) => "Refund",
+        ReceiptBody::ManaAccounting(_) => "ManaAccounting",
+    }
+}
+
+/// Accounts whose record changed in the shard, with whether the record was removed.
+fn account_updates(shard: &IndexerShard) -> Vec<(AccountId, bool)> {
+    let mut result: Vec<(AccountId, bool)> = vec![];
+    let updates = shard.state_changes.iter().filter(|c| c.kind == StateChangeKind::AccountUpdate);
+    for change in updates {
+        if let Some(account_id) = &change.account_id {
+            result.retain(|(id, _)| id != account_id);
+            result.push((account_id.clone(), change.value.is_none()));
+        }
+    }
+    result
+}
+
+fn last_exported_index(conn: &Connection) -> postgres::Result<Option<u64>> {
+    let rows = conn.query("SELECT block_index FROM checkpoint WHERE id = 0", &[])?;
+    Ok(rows.iter().next().map(|row| row.get::<_, i64>(0) as u64))
+}
+
+fn insert_events(
+    tx: &postgres::transaction::Transaction,
+    block_index: i64,
+    outcome_id: &str,
+    logs: &[LogEntry],
+) -> postgres::Result<()> {
+    for (position, message) in logs.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO events (outcome_id, position, block_index, message)
+             VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+            &[&outcome_id, &(position as i32), &block_index, message],
+        )?;
+    }
+    Ok(())
+}
+
+fn export_block(conn: &Connection, message: &StreamerMessage) -> postgres::Result<()> {
+    let tx = conn.transaction()?;
+    let block_index = message.index() as i64;
+    let header = &message.header.body;
+    tx.execute(
+        "INSERT INTO blocks (block_index, hash, parent_hash, shard_id, state_root)
+         VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+        &[
+            &block_index,
+            &String::from(&message.header.hash),
+            &String::from(&header.parent_hash),
+            &i64::from(header.shard_id),
+            &String::from(&header.merkle_root_state),
+        ],
+    )?;
+    for shard in &message.shards {
+        for (position, t) in shard.transactions.iter().enumerate() {
+            let hash = String::from(&t.transaction.get_hash());
+            let body = &t.transaction.body;
+            tx.execute(
+                "INSERT INTO transactions
+                 (hash, block_index, position, kind, originator, contract_id, nonce, status)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT DO NOTHING",
+                &[
+                    &hash,
+                    &block_index,
+                    &(position as i32),
+                    &body.kind().to_string(),
+                    &body.get_originator(),
+                    &body.get_contract_id(),
+                    &(body.get_nonce() as i64),
+                    &format!("{:?}", t.result.status),
+                ],
+            )?;
+            insert_events(&tx, block_index, &hash, &t.result.logs)?;
+        }
+        for (position, r) in shard.receipts.iter().enumerate() {
+            let nonce = String::from(&r.receipt.nonce);
+            tx.execute(
+                "INSERT INTO receipts
+                 (nonce, block_index, position, kind, originator, receiver, status)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT DO NOTHING",
+                &[
+                    &nonce,
+                    &block_index,
+                    &(position as i32),
+                    &receipt_kind(&r.receipt.body),
+                    &r.receipt.originator,
+                    &r.receipt.receiver,
+                    &format!("{:?}", r.result.status),
+                ],
+            )?;
+            insert_events(&tx, block_index, &nonce, &r.result.logs)?;
+        }
+        for (account_id, deleted) in account_updates(shard) {
+            tx.execute(
+                "INSERT INTO accounts
+                 (account_id, created_at_block_index, updated_at_block_index, deleted)
+                 VALUES ($1, $2, $2, $3)
+                 ON CONFLICT (account_id)
+                 DO UPDATE SET updated_at_block_index = $2, deleted = $3",
+                &[&account_id, &block_index, &deleted],
+            )?;
+        }
+    }
+    tx.execute(
+        "INSERT INTO checkpoint (id, block_index) VALUES (0, $1)
+         ON CONFLICT (id) DO UPDATE SET block_index = $1",
+        &[&block_index],
+    )?;
+    tx.commit()
+}
+
+/// Starts exporting the blocks on a separate thread, from the one after the last exported
+/// block. The export stops at the first failed write, which is retried after a restart.
+pub fn spawn_exporter(client: Arc<Client>, db_url: String) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let conn = Connection::connect(db_url.as_str(), TlsMode::None)
+            .expect("Failed to connect to the explorer database");
+        conn.batch_execute(SCHEMA).expect("Failed to create the explorer schema");
+        let start_index = last_exported_index(&conn)
+            .expect("Failed to read the explorer checkpoint")
+            .map_or(0, |index| index + 1);
+        info!(target: "indexer", "Exporting blocks to the explorer database from {}", start_index);
+        for message in crate::start(client, start_index).wait() {
+            let message = match message {
+                Ok(message) => message,
+                Err(()) => break,
+            };
+            if let Err(e) = export_block(&conn, &message) {
+                error!(target: "indexer", "Failed to export block {}: {}", message.index(), e);
+                break;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use node_runtime::state_changes::StateChange;
+
+    #[test]
+    fn test_account_updates() {
+        let change = |kind, account_id: &str, value: Option<Vec<u8>>| StateChange {
+            kind,
+            account_id: Some(account_id.to_string()),
+            key: vec![],
+            value,
+        };
+        let shard = IndexerShard {
+            shard_id: 0,
+            transactions: vec![],
+            receipts: vec![],
+            state_changes: vec![
+                change(StateChangeKind::AccountUpdate, "alice.near", Some(vec![1])),
+                change(StateChangeKind::ContractData, "bob.near", Some(vec![2])),
+                change(StateChangeKind::AccountUpdate, "carol.near", Some(vec![3])),
+                change(StateChangeKind::AccountUpdate, "alice.near", None),
+            ],
+        };
+        assert_eq!(
+            account_updates(&shard),
+            vec![("carol.near".to_string(), false), ("alice.near".to_string(), true)]
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Streams the blocks applied by the node together with everything that happened in them,
+//! so that indexers can run in-process instead of scraping the RPC.
+extern crate futures;
+#[cfg(feature = "kafka-publisher")]
+extern crate kafka;
+#[macro_use]
+extern crate log;
+#[cfg(feature = "nats-publisher")]
+extern crate nats;
+#[cfg(feature = "postgres-exporter")]
+extern crate postgres;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+extern crate chain;
+extern crate client;
+extern crate node_runtime;
+extern crate primitives;
+extern crate shard;
+extern crate transaction;
+
+use std::cmp;
+use std::sync::Arc;
+
+use futures::{stream, Stream};
+
+use chain::{SignedBlock, SignedShardBlock, SignedShardBlockHeader};
+use client::Client;
+use node_runtime::state_changes::{classify_changes, StateChange};
+use primitives::types::{BlockId, ShardId};
+use shard::Outcome;
+use transaction::{ReceiptTransaction, SignedTransaction, TransactionResult};
+
+pub mod bus;
+#[cfg(feature = "postgres-exporter")]
+pub mod exporter;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct IndexerTransaction {
+    pub transaction: SignedTransaction,
+    pub result: TransactionResult,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct IndexerReceipt {
+    pub receipt: ReceiptTransaction,
+    pub result: TransactionResult,
+}
+
+/// What happened in one shard of the block.
+#[derive(Clone, Debug, Serialize)]
+pub struct IndexerShard {
+    pub shard_id: ShardId,
+    pub transactions: Vec<IndexerTransaction>,
+    pub receipts: Vec<IndexerReceipt>,
+    /// Empty if the state changes of the block were not recorded.
+    pub state_changes: Vec<StateChange>,
+}
+
+/// Everything an indexer needs to know about an applied block.
+#[derive(Clone, Debug, Serialize)]
+pub struct StreamerMessage {
+    pub header: SignedShardBlockHeader,
+    pub shards: Vec<IndexerShard>,
+}
+
+impl StreamerMessage {
+    pub fn index(&self) -> u64 {
+        self.header.body.index
+    }
+}
+
+/// Builds the message of the block from the outcomes of its receipts and transactions,
+/// which come in the same order as in `shard::BlockEvent`.
+pub fn build_streamer_message(
+    client: &Client,
+    block: &SignedShardBlock,
+    outcomes: &[Outcome],
+) -> StreamerMessage {
+    let receipts: Vec<IndexerReceipt> = block
+        .body
+        .receipts
+        .iter()
+        .flat_map(|b| b.receipts.iter())
+        .zip(outcomes.iter())
+        .map(|(receipt, outcome)| IndexerReceipt {
+            receipt: receipt.clone(),
+            result: outcome.result.clone(),
+        })
+        .collect();
+    let transactions = block
+        .body
+        .transactions
+        .iter()
+        .zip(outcomes.iter().skip(receipts.len()))
+        .map(|(transaction, outcome)| IndexerTransaction {
+            transaction: transaction.clone(),
+            result: outcome.result.clone(),
+        })
+        .collect();
+    let state_changes = client
+        .shard_chain
+        .get_state_changes(&block.hash)
+        .map(|changes| classify_changes(&changes))
+        .unwrap_or_default();
+    StreamerMessage {
+        header: block.header(),
+        shards: vec![IndexerShard {
+            shard_id: block.body.header.shard_id,
+            transactions,
+            receipts,
+            state_changes,
+        }],
+    }
+}
+
+/// Builds the message of a block already in the chain, `None` if there is no such block.
+pub fn streamer_message_at(client: &Client, index: u64) -> Option<StreamerMessage> {
+    let block = client.shard_chain.chain.get_block(&BlockId::Number(index))?;
+    let outcomes = client.shard_chain.get_block_outcomes(&block);
+    Some(build_streamer_message(client, &block, &outcomes))
+}
+
+/// Streams the messages of the blocks starting from `start_index`: first of the blocks
+/// already in the chain, then of the new ones as they are added. Never ends.
+pub fn start(
+    client: Arc<Client>,
+    start_index: u64,
+) -> Box<Stream<Item = StreamerMessage, Error = ()> + Send> {
+    // Subscribes before reading the chain, so that no block is missed in between.
+    let new_blocks = client.shard_chain.subscribe_to_blocks();
+    let best_index = client.shard_chain.chain.best_index();
+    let first_new_index = cmp::max(start_index, best_index + 1);
+    let past_client = client.clone();
+    let past = stream::iter_ok::<_, ()>(start_index..=best_index)
+        .filter_map(move |index| streamer_message_at(&past_client, index));
+    let new = new_blocks
+        .filter(move |event| event.block.index() >= first_new_index)
+        .map(move |event| build_streamer_message(&client, &event.block, &event.outcomes));
+    Box::new(past.chain(new))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::Future;
+
+    use client::test_utils::get_client;
+    use node_runtime::test_utils::signed_transaction;
+    use primitives::hash::CryptoHash;
+    use transaction::{SendMoneyTransaction, TransactionBody, TransactionStatus};
+
+    fn add_block(client: &Client, transactions: Vec<SignedTransaction>) {
+        let chain = &client.shard_chain;
+        let parent_hash = chain.chain.best_block().block_hash();
+        let (block, (db_changes, _, tx_results, new_receipts)) =
+            chain.prepare_new_block(parent_hash, vec![], transactions);
+        chain.insert_block(&block, db_changes, tx_results, new_receipts).unwrap();
+    }
+
+    #[test]
+    fn test_streamer() {
+        let client = Arc::new(get_client());
+        let transaction = signed_transaction(TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce: 1,
+            originator: "alice.near".to_string(),
+            receiver: "bob.near".to_string(),
+            amount: 10,
+            block_hash: CryptoHash::default(),
+            genesis_hash: client.shard_chain.genesis_hash(),
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
+        }));
+        add_block(&client, vec![transaction.clone()]);
+        let messages = start(client.clone(), 1);
+        add_block(&client, vec![]);
+        let messages: Vec<StreamerMessage> = messages.take(2).collect().wait().unwrap();
+        assert_eq!(messages.iter().map(|m| m.index()).collect::<Vec<_>>(), vec![1, 2]);
+        let shard = &messages[0].shards[0];
+        assert_eq!(shard.transactions.len(), 1);
+        assert_eq!(shard.transactions[0].transaction.get_hash(), transaction.get_hash());
+        assert_eq!(shard.transactions[0].result.status, TransactionStatus::Completed);
+        assert!(shard
+            .state_changes
+            .iter()
+            .any(|c| c.account_id == Some("alice.near".to_string())));
+        assert!(messages[1].shards[0].transactions.is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+//! Compression of the encoded messages sent over the wire. Every payload starts with a byte
+//! telling how the rest of it is compressed. The peers advertise the algorithms they support in
+//! the status message, and until the status is received the messages go uncompressed. The sizes
+//! are checked before and after decompression so that a small payload cannot expand into an
+//! arbitrarily large message.
+use std::fmt;
+
+/// Max size of a payload received from a peer.
+pub const MAX_PAYLOAD_SIZE: usize = 16 * 1024 * 1024;
+/// Max size of a decompressed message.
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+/// Smaller messages are not worth compressing.
+const MIN_COMPRESSED_SIZE: usize = 1024;
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    None,
+    Zstd,
+}
+
+impl Compression {
+    fn tag(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => 1,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Algorithms the node can decompress, most preferred first.
+pub fn supported() -> Vec<Compression> {
+    vec![Compression::Zstd]
+}
+
+/// Most preferred algorithm that the peer supports.
+pub fn negotiate(peer_supported: &[Compression]) -> Compression {
+    supported().into_iter().find(|c| peer_supported.contains(c)).unwrap_or(Compression::None)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CompressionError {
+    Empty,
+    UnknownCompression(u8),
+    /// Payload or the decompressed message is larger than the limit.
+    TooLarge { size: usize, limit: usize },
+    Invalid(String),
+}
+
+impl fmt::Display for CompressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompressionError::Empty => write!(f, "Empty payload"),
+            CompressionError::UnknownCompression(tag) => write!(f, "Unknown compression {}", tag),
+            CompressionError::TooLarge { size, limit } => {
+                write!(f, "Payload of {} bytes is over the limit of {} bytes", size, limit)
+            }
+            CompressionError::Invalid(e) => write!(f, "Failed to decompress: {}", e),
+        }
+    }
+}
+
+/// Prefixes the encoded message with the compression byte, compressing it if that makes it
+/// smaller.
+pub fn compress(data: &[u8], compression: Compression) -> Vec<u8> {
+    if compression == Compression::Zstd && data.len() >= MIN_COMPRESSED_SIZE {
+        if let Ok(compressed) = zstd::block::compress(data, ZSTD_LEVEL) {
+            if compressed.len() < data.len() {
+                let mut payload = vec![Compression::Zstd.tag()];
+                payload.extend(compressed);
+                return payload;
+            }
+        }
+    }
+    let mut payload = vec![Compression::None.tag()];
+    payload.extend_from_slice(data);
+    payload
+}
+
+/// Returns the encoded message of the payload.
+pub fn decompress(payload: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    if payload.len() > MAX_PAYLOAD_SIZE {
+        return Err(CompressionError::TooLarge { size: payload.len(), limit: MAX_PAYLOAD_SIZE });
+    }
+    let (tag, data) = payload.split_first().ok_or(CompressionError::Empty)?;
+    match Compression::from_tag(*tag) {
+        Some(Compression::None) => Ok(data.to_vec()),
+        // Decompression fails instead of growing the output past the capacity.
+        Some(Compression::Zstd) => zstd::block::decompress(data, MAX_MESSAGE_SIZE)
+            .map_err(|e| CompressionError::Invalid(e.to_string())),
+        None => Err(CompressionError::UnknownCompression(*tag)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress_decompress() {
+        let small = vec![1, 2, 3];
+        let payload = compress(&small, Compression::Zstd);
+        assert_eq!(payload[0], Compression::None.tag());
+        assert_eq!(decompress(&payload), Ok(small));
+
+        let large = vec![7; 10 * MIN_COMPRESSED_SIZE];
+        let payload = compress(&large, Compression::Zstd);
+        assert_eq!(payload[0], Compression::Zstd.tag());
+        assert!(payload.len() < large.len());
+        assert_eq!(decompress(&payload), Ok(large.clone()));
+        assert_eq!(compress(&large, Compression::None).len(), large.len() + 1);
+
+        assert_eq!(decompress(&[]), Err(CompressionError::Empty));
+        assert_eq!(decompress(&[9, 1]), Err(CompressionError::UnknownCompression(9)));
+    }
+
+    #[test]
+    fn test_decompression_bomb() {
+        let bomb = vec![0; MAX_MESSAGE_SIZE + 1];
+        let mut payload = vec![Compression::Zstd.tag()];
+        payload.extend(zstd::block::compress(&bomb, ZSTD_LEVEL).unwrap());
+        assert!(payload.len() <= MAX_PAYLOAD_SIZE);
+        match decompress(&payload) {
+            Err(CompressionError::Invalid(_)) => {}
+            result => panic!("Expected a failure, got {:?}", result.map(|m| m.len())),
+        }
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate(&[Compression::Zstd]), Compression::Zstd);
+        assert_eq!(negotiate(&[]), Compression::None);
+    }
+}
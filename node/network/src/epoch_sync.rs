@@ -0,0 +1,251 @@
+//! Epoch synchronization. Replaying every header from genesis takes long, so a node far behind its
+//! peers first downloads a proof per epoch: the last block of the epoch, once it's final, with the
+//! authorities of every slot of the next epoch it commits to. Each proof is checked against the
+//! authorities the previous one handed off to, starting from the authorities the node knows, and
+//! once the peers have no further proofs the header sync continues from the last checked block.
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use substrate_network_libp2p::NodeIndex;
+
+use beacon::light_client::{verify_epoch_proof, LightClientError};
+use beacon::types::SignedBeaconBlockHeader;
+use primitives::types::AuthorityStake;
+
+use crate::message::{EpochProofsRequest, EpochProofsResponse, Message, RequestId};
+
+/// Max number of epoch proofs served in response to a request.
+pub const MAX_PROOFS_PER_REQUEST: u64 = 16;
+/// How long, in milliseconds, a peer has to answer before the request goes to another peer.
+const REQUEST_TIMEOUT_MS: u64 = 10_000;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum EpochSyncError {
+    /// Proof of the epoch is not approved by the authorities handed off to or doesn't commit to
+    /// the authorities of the next epoch.
+    InvalidProof { epoch: u64, reason: LightClientError },
+}
+
+impl fmt::Display for EpochSyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EpochSyncError::InvalidProof { epoch, reason } => {
+                write!(f, "Proof of epoch {} is invalid: {}", epoch, reason)
+            }
+        }
+    }
+}
+
+struct Request {
+    id: RequestId,
+    peer: NodeIndex,
+    sent: Instant,
+}
+
+pub struct EpochSync {
+    epoch_length: u64,
+    /// Epoch whose proof is checked next.
+    epoch: u64,
+    /// Authorities of every slot of the epoch, handed off by the previous proof.
+    authorities: Vec<Vec<AuthorityStake>>,
+    /// Last block of the epoch before, once a proof is checked.
+    last_header: Option<SignedBeaconBlockHeader>,
+    /// Best block index announced by each peer that may have the next proofs.
+    peers: HashMap<NodeIndex, u64>,
+    request: Option<Request>,
+    next_request_id: RequestId,
+}
+
+impl EpochSync {
+    /// Starts from the epoch and the authorities of its slots, which the node knows.
+    pub fn new(epoch: u64, epoch_length: u64, authorities: Vec<Vec<AuthorityStake>>) -> Self {
+        EpochSync {
+            epoch_length,
+            epoch,
+            authorities,
+            last_header: None,
+            peers: HashMap::new(),
+            request: None,
+            next_request_id: 0,
+        }
+    }
+
+    /// Index of the last block of the epoch whose proof is checked next.
+    fn epoch_end(&self) -> u64 {
+        (self.epoch + 1) * self.epoch_length
+    }
+
+    /// Records the best block index announced by the peer.
+    pub fn add_peer(&mut self, peer: NodeIndex, best_index: u64) {
+        let peer_best_index = self.peers.entry(peer).or_insert(best_index);
+        *peer_best_index = std::cmp::max(*peer_best_index, best_index);
+    }
+
+    /// Forgets the peer, its request in flight goes to another peer.
+    pub fn remove_peer(&mut self, peer: NodeIndex) {
+        self.peers.remove(&peer);
+        if self.request.as_ref().map_or(false, |r| r.peer == peer) {
+            self.request = None;
+        }
+    }
+
+    /// Whether none of the peers has the next proof, so the node is near the head.
+    pub fn is_done(&self) -> bool {
+        self.request.is_none() && !self.peers.values().any(|index| *index >= self.epoch_end())
+    }
+
+    /// Takes the last checked block, which the header sync continues from.
+    pub fn take_last_header(&mut self) -> Option<SignedBeaconBlockHeader> {
+        self.last_header.take()
+    }
+
+    /// Requests to send to the peers. Drops the request that timed out and asks the best peer
+    /// for the next proofs.
+    pub fn next_requests(&mut self, now: Instant) -> Vec<(NodeIndex, Message)> {
+        let timeout = Duration::from_millis(REQUEST_TIMEOUT_MS);
+        if self.request.as_ref().map_or(false, |r| now > r.sent + timeout) {
+            self.request = None;
+        }
+        if self.request.is_some() {
+            return vec![];
+        }
+        let epoch_end = self.epoch_end();
+        let best_peer = self
+            .peers
+            .iter()
+            .filter(|(_, index)| **index >= epoch_end)
+            .max_by_key(|(_, index)| **index)
+            .map(|(peer, _)| *peer);
+        let peer = match best_peer {
+            Some(peer) => peer,
+            None => return vec![],
+        };
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        self.request = Some(Request { id, peer, sent: now });
+        let request =
+            EpochProofsRequest { id, from_epoch: self.epoch, max: MAX_PROOFS_PER_REQUEST };
+        vec![(peer, Message::EpochProofsRequest(request))]
+    }
+
+    /// Checks the proofs in order, each against the authorities the previous one handed off to.
+    pub fn on_proofs(
+        &mut self,
+        peer: NodeIndex,
+        response: EpochProofsResponse,
+    ) -> Result<(), EpochSyncError> {
+        match &self.request {
+            Some(r) if r.peer == peer && r.id == response.id => self.request = None,
+            // Responses to the requests that timed out are ignored.
+            _ => return Ok(()),
+        }
+        if response.proofs.is_empty() {
+            // The last block of the epoch is not final at the peer yet, don't ask it again.
+            self.peers.remove(&peer);
+            return Ok(());
+        }
+        for proof in response.proofs.into_iter().take(MAX_PROOFS_PER_REQUEST as usize) {
+            verify_epoch_proof(&proof, self.epoch, self.epoch_length, &self.authorities)
+                .map_err(|reason| EpochSyncError::InvalidProof { epoch: self.epoch, reason })?;
+            self.epoch += 1;
+            self.authorities = proof.next_epoch_authorities;
+            self.last_header = Some(proof.header);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use beacon::light_client::{authorities_hash, epoch_authorities_hash, EpochProof};
+    use beacon::types::SignedBeaconBlock;
+    use chain::{SignedBlock, SignedHeader};
+    use primitives::hash::CryptoHash;
+    use primitives::signature::get_key_pair;
+    use primitives::signer::InMemorySigner;
+    use primitives::traits::Signer;
+
+    use super::*;
+
+    const EPOCH_LENGTH: u64 = 2;
+
+    fn signer(account_id: &str) -> InMemorySigner {
+        InMemorySigner { account_id: account_id.to_string(), ..InMemorySigner::default() }
+    }
+
+    fn stake(signer: &InMemorySigner) -> AuthorityStake {
+        AuthorityStake {
+            account_id: signer.account_id(),
+            public_key: get_key_pair().0,
+            bls_public_key: signer.public_key(),
+            amount: 1,
+        }
+    }
+
+    /// Proof of the epoch signed by the signers, handing off to the next signers.
+    fn proof(epoch: u64, signers: &[InMemorySigner], next: &[InMemorySigner]) -> EpochProof {
+        let next_authorities: Vec<_> = next.iter().map(stake).collect();
+        let next_epoch_authorities = vec![next_authorities; EPOCH_LENGTH as usize];
+        let mut block = SignedBeaconBlock::new_with_challenges(
+            (epoch + 1) * EPOCH_LENGTH,
+            CryptoHash::default(),
+            vec![],
+            CryptoHash::default(),
+            authorities_hash(&next_epoch_authorities[0]),
+            epoch_authorities_hash(&next_epoch_authorities),
+            vec![],
+            vec![],
+        );
+        for (i, s) in signers.iter().enumerate() {
+            block.add_signature(&block.sign(s), i);
+        }
+        EpochProof { header: block.header(), next_epoch_authorities }
+    }
+
+    fn proofs_request(messages: &[(NodeIndex, Message)]) -> (NodeIndex, RequestId, u64) {
+        match messages {
+            [(peer, Message::EpochProofsRequest(request))] => {
+                (*peer, request.id, request.from_epoch)
+            }
+            _ => panic!("Expected an epoch proofs request, got {:?}", messages),
+        }
+    }
+
+    #[test]
+    fn test_epoch_sync() {
+        let signers: Vec<Vec<InMemorySigner>> = (0..4)
+            .map(|epoch| (0..3).map(|i| signer(&format!("{}.{}", epoch, i))).collect())
+            .collect();
+        let authorities: Vec<_> = signers[0].iter().map(stake).collect();
+        let now = Instant::now();
+        let mut sync = EpochSync::new(0, EPOCH_LENGTH, vec![authorities; EPOCH_LENGTH as usize]);
+        sync.add_peer(1, 9);
+        sync.add_peer(2, 1);
+        assert!(!sync.is_done());
+
+        let (peer, id, from_epoch) = proofs_request(&sync.next_requests(now));
+        assert_eq!((peer, from_epoch), (1, 0));
+        assert!(sync.next_requests(now).is_empty());
+        // The second proof is signed by the authorities the first one hands off from.
+        let proofs = vec![proof(0, &signers[0], &signers[1]), proof(1, &signers[0], &signers[2])];
+        match sync.on_proofs(1, EpochProofsResponse { id, proofs }) {
+            Err(EpochSyncError::InvalidProof { epoch: 1, .. }) => {}
+            result => panic!("Expected an invalid proof, got {:?}", result),
+        }
+        assert_eq!(sync.take_last_header().map(|h| h.index()), Some(2));
+
+        let (peer, id, from_epoch) = proofs_request(&sync.next_requests(now));
+        assert_eq!((peer, from_epoch), (1, 1));
+        let proofs = vec![proof(1, &signers[1], &signers[2]), proof(2, &signers[2], &signers[3])];
+        sync.on_proofs(1, EpochProofsResponse { id, proofs: proofs.clone() }).unwrap();
+        assert_eq!(sync.take_last_header(), Some(proofs[1].header.clone()));
+
+        // The last block of epoch 3 is not final yet.
+        let (_, id, from_epoch) = proofs_request(&sync.next_requests(now));
+        assert_eq!(from_epoch, 3);
+        sync.on_proofs(1, EpochProofsResponse { id, proofs: vec![] }).unwrap();
+        assert!(sync.is_done());
+        assert!(sync.next_requests(now).is_empty());
+    }
+}
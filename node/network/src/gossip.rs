@@ -0,0 +1,121 @@
+//! Checks that the transactions and receipts gossiped by the peers have to pass before the node
+//! processes and forwards them, and the cache that makes the node forward every item once.
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+use chain::{ReceiptBlock, SignedHeader};
+use client::Client;
+use primitives::hash::{hash_struct, CryptoHash};
+use primitives::types::BlockId;
+use shard::AdmissionError;
+use transaction::SignedTransaction;
+
+/// Number of the most recent hashes remembered by a `SeenCache`.
+pub const SEEN_CACHE_SIZE: usize = 10_000;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum GossipError {
+    /// Transaction would not be admitted into the pool on top of the best block.
+    InvalidTransaction(AdmissionError),
+    /// Hash of the shard block header doesn't match its body.
+    InvalidHeader(CryptoHash),
+    /// No beacon block commits to the shard block with the receipts, which may be because
+    /// the node is behind.
+    UnknownBlock { index: u64, hash: CryptoHash },
+}
+
+impl GossipError {
+    /// Whether the peer that sent the item is misbehaving, rather than e.g. ahead of the node.
+    pub fn is_bad(&self) -> bool {
+        match self {
+            GossipError::InvalidHeader(_) => true,
+            GossipError::InvalidTransaction(_) | GossipError::UnknownBlock { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for GossipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GossipError::InvalidTransaction(e) => write!(f, "{}", e),
+            GossipError::InvalidHeader(hash) => {
+                write!(f, "Hash {:?} doesn't match the shard block header", hash)
+            }
+            GossipError::UnknownBlock { index, hash } => {
+                write!(f, "No beacon block {} commits to shard block {:?}", index, hash)
+            }
+        }
+    }
+}
+
+/// Transactions are checked against the state of the best block, where the keys of the
+/// originator witness the signature.
+pub fn validate_transaction(
+    client: &Client,
+    transaction: &SignedTransaction,
+) -> Result<(), GossipError> {
+    client.shard_chain.check_transaction(transaction).map_err(GossipError::InvalidTransaction)
+}
+
+/// Receipts are checked against the beacon chain, which all the nodes follow regardless of the
+/// shards they track: the beacon block of the same index has to commit to their shard block.
+// TODO: Check the receipts against the header once it commits to them.
+pub fn validate_receipt_block(
+    client: &Client,
+    receipt_block: &ReceiptBlock,
+) -> Result<(), GossipError> {
+    let header = &receipt_block.header;
+    if hash_struct(&header.body) != header.hash {
+        return Err(GossipError::InvalidHeader(header.hash));
+    }
+    let beacon_block = client.beacon_chain.chain.get_block(&BlockId::Number(header.index()));
+    match beacon_block {
+        Some(block) if block.body.header.shard_block_hash == header.hash => Ok(()),
+        _ => Err(GossipError::UnknownBlock { index: header.index(), hash: header.hash }),
+    }
+}
+
+/// Remembers the most recent hashes, forgetting the oldest ones once full.
+pub struct SeenCache {
+    capacity: usize,
+    hashes: HashSet<CryptoHash>,
+    order: VecDeque<CryptoHash>,
+}
+
+impl SeenCache {
+    pub fn new(capacity: usize) -> Self {
+        SeenCache { capacity, hashes: HashSet::new(), order: VecDeque::new() }
+    }
+
+    /// Returns whether the hash was not seen before.
+    pub fn insert(&mut self, hash: CryptoHash) -> bool {
+        if !self.hashes.insert(hash) {
+            return false;
+        }
+        self.order.push_back(hash);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.hashes.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seen_cache() {
+        let mut cache = SeenCache::new(2);
+        let hashes: Vec<_> = (0..3).map(|i| hash_struct(&i)).collect();
+        assert!(cache.insert(hashes[0]));
+        assert!(!cache.insert(hashes[0]));
+        assert!(cache.insert(hashes[1]));
+        assert!(cache.insert(hashes[2]));
+        // The oldest hash is forgotten.
+        assert!(cache.insert(hashes[0]));
+        assert!(!cache.insert(hashes[2]));
+    }
+}
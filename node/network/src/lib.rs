@@ -1,11 +1,13 @@
 extern crate beacon;
 extern crate bincode;
+extern crate bs58;
 extern crate byteorder;
 extern crate bytes;
 extern crate chain;
 extern crate client;
 extern crate env_logger;
 extern crate futures;
+extern crate igd;
 extern crate libp2p;
 #[macro_use]
 extern crate log;
@@ -16,11 +18,22 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate snow;
 extern crate substrate_network_libp2p;
 extern crate tokio;
+extern crate zstd;
 
+pub mod compression;
+pub mod epoch_sync;
 pub mod error;
+pub mod gossip;
 pub mod message;
+pub mod nat;
+pub mod noise;
+pub mod peer_store;
 pub mod protocol;
+pub mod rate_limit;
 pub mod service;
+pub mod state_sync;
+pub mod sync;
 pub mod test_utils;
@@ -1,7 +1,17 @@
-use beacon::types::SignedBeaconBlock;
+use std::net::IpAddr;
+
+use beacon::light_client::EpochProof;
+use beacon::types::{Endorsement, SignedBeaconBlock, SignedBeaconBlockHeader};
 use primitives::hash::CryptoHash;
-use primitives::types::{AccountId, Gossip};
-use chain::{SignedShardBlock, ChainPayload, ReceiptBlock};
+use primitives::signature::bs58_serializer;
+use primitives::types::{AccountId, Gossip, PartialSignature, ShardId};
+use chain::{
+    Challenge, ChainPayload, ReceiptBlock, ShardBlockPart, SignedShardBlock, SignedShardBlockHeader,
+};
+use storage::StatePart;
+use transaction::SignedTransaction;
+
+use crate::compression::Compression;
 
 pub type RequestId = u64;
 
@@ -9,10 +19,39 @@ pub type RequestId = u64;
 pub enum Message {
     // Box is used here because SignedTransaction
     // is significantly larger than other enum members
+    Transaction(Box<SignedTransaction>),
     Receipt(Box<ReceiptBlock>),
     Status(Status),
     BlockAnnounce(Box<(SignedBeaconBlock, SignedShardBlock)>),
     Gossip(Box<Gossip<ChainPayload>>),
+    HeadersRequest(HeadersRequest),
+    HeadersResponse(HeadersResponse),
+    BlocksRequest(BlocksRequest),
+    BlocksResponse(Box<BlocksResponse>),
+    StatePartRequest(StatePartRequest),
+    StatePartResponse(Box<StatePartResponse>),
+    Endorsement(Box<Endorsement>),
+    Challenge(Box<Challenge>),
+    BlockPart(Box<ShardBlockPart>),
+    BlockPartsRequest(BlockPartsRequest),
+    BlockPartsResponse(Box<BlockPartsResponse>),
+    EpochProofsRequest(EpochProofsRequest),
+    EpochProofsResponse(Box<EpochProofsResponse>),
+    PeersRequest,
+    PeersResponse(PeersResponse),
+    /// Message of the Noise handshake, the only one sent unencrypted.
+    Handshake(Vec<u8>),
+    /// Ip the node sees the peer connecting from, so that the peer can learn its external ip.
+    ObservedIp(IpAddr),
+}
+
+/// Account the node runs, with the signature of its node id by the BLS key of the account, so
+/// that the node of an authority can't be claimed by another one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountClaim {
+    pub account_id: AccountId,
+    #[serde(with = "bs58_serializer")]
+    pub signature: PartialSignature,
 }
 
 /// status sent on connection
@@ -20,12 +59,101 @@ pub enum Message {
 pub struct Status {
     /// Protocol version.
     pub version: u32,
+    /// Oldest protocol version the node can talk to.
+    pub min_version: u32,
+    /// Name and version of the node software.
+    pub node_version: String,
+    /// Optional parts of the protocol the node has enabled.
+    pub features: Vec<String>,
     /// Best block index.
     pub best_index: u64,
     /// Best block hash.
     pub best_hash: CryptoHash,
     /// Genesis hash.
     pub genesis_hash: CryptoHash,
-    /// Account id.
-    pub account_id: Option<AccountId>,
+    /// Account id, signed for the node id of the connection.
+    pub account: Option<AccountClaim>,
+    /// Shards whose receipts the node wants to receive.
+    pub tracked_shards: Vec<ShardId>,
+    /// Compression algorithms the node can decompress.
+    pub compression: Vec<Compression>,
+}
+
+/// Request for the consecutive beacon block headers starting at the given index.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeadersRequest {
+    pub id: RequestId,
+    pub from_index: u64,
+    pub max: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeadersResponse {
+    pub id: RequestId,
+    pub headers: Vec<SignedBeaconBlockHeader>,
+}
+
+/// Request for the beacon blocks with the given hashes and their shard blocks.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlocksRequest {
+    pub id: RequestId,
+    pub hashes: Vec<CryptoHash>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlocksResponse {
+    pub id: RequestId,
+    pub blocks: Vec<(SignedBeaconBlock, SignedShardBlock)>,
+}
+
+/// Request for a part of the state of the shard after the shard block with the given hash.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatePartRequest {
+    pub id: RequestId,
+    pub shard_id: ShardId,
+    pub block_hash: CryptoHash,
+    pub part_id: u64,
+    pub num_parts: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatePartResponse {
+    pub id: RequestId,
+    /// Header of the block, with the root the part is checked against, and the part, if the
+    /// peer has them.
+    pub part: Option<(SignedShardBlockHeader, StatePart)>,
+}
+
+/// Request for the parts of the shard block with the given hash that the peer has.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockPartsRequest {
+    pub block_hash: CryptoHash,
+}
+
+/// Parts carry the header they are checked against, so they need no request id.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockPartsResponse {
+    pub parts: Vec<ShardBlockPart>,
+}
+
+/// Request for the proofs of the consecutive epochs starting at the given one.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpochProofsRequest {
+    pub id: RequestId,
+    pub from_epoch: u64,
+    pub max: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpochProofsResponse {
+    pub id: RequestId,
+    /// Proofs of the epochs whose last block is final, in order.
+    pub proofs: Vec<EpochProof>,
+}
+
+/// Addresses of the peers the node knows about, and of the node itself, each ending with the
+/// peer id, e.g. `/ip4/1.2.3.4/tcp/30333/p2p/<peer id>`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeersResponse {
+    pub peers: Vec<String>,
 }
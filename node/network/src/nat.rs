@@ -0,0 +1,316 @@
+//! Reachability of the nodes behind NAT. The node asks the gateway to forward its listening
+//! port, with UPnP or, if the gateway doesn't speak it, NAT-PMP. The peers also tell the node the
+//! ip they see it connecting from, and once peers in enough distinct subnets agree on it, the node
+//! advertises that ip even if no gateway could map the port. Each peer, known by its node id, has
+//! a single vote and the votes of a subnet count once, so a few colluding peers from the same
+//! network cannot make the node advertise an address of their choice.
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use igd::{PortMappingProtocol, SearchOptions};
+use parking_lot::RwLock;
+
+use crate::noise::NodeId;
+
+/// Number of distinct subnets the peers that observe the same ip have to be in before it is
+/// advertised.
+const MIN_OBSERVATIONS: usize = 3;
+/// Lifetime of the port mappings in seconds, they are renewed at half of it.
+const MAPPING_LIFETIME: u32 = 3600;
+/// How long to wait before trying again if no gateway could map the port.
+const RETRY_PERIOD: Duration = Duration::from_secs(300);
+/// Port NAT-PMP gateways listen on.
+const NAT_PMP_PORT: u16 = 5351;
+const NAT_PMP_TIMEOUT: Duration = Duration::from_secs(1);
+const NAT_PMP_ATTEMPTS: usize = 3;
+const MAPPING_DESCRIPTION: &str = "near";
+
+/// Subnet of the ip, the /24 of IPv4 and the /48 of IPv6 addresses, which are usually run by
+/// the same operator.
+fn subnet(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            IpAddr::V6(Ipv6Addr::new(segments[0], segments[1], segments[2], 0, 0, 0, 0, 0))
+        }
+    }
+}
+
+/// Whether the ip can be reached from outside of the network it is in.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !ip.is_unspecified() && !ip.is_loopback() && !ip.is_private() && !ip.is_link_local()
+        }
+        IpAddr::V6(ip) => !ip.is_unspecified() && !ip.is_loopback(),
+    }
+}
+
+/// Address the node is reachable on from outside of its network.
+#[derive(Default)]
+pub struct ExternalAddr {
+    /// External address the gateway forwards to the listening port.
+    mapped: Option<SocketAddr>,
+    /// Ip each peer observed the node at and the subnet of the peer, by the node id of the peer.
+    observed: HashMap<NodeId, (IpAddr, IpAddr)>,
+}
+
+impl ExternalAddr {
+    pub fn set_mapped(&mut self, mapped: Option<SocketAddr>) {
+        self.mapped = mapped;
+    }
+
+    /// Records the ip the peer, connected from `peer_ip`, sees the node at. Only the first
+    /// observation of a peer counts.
+    pub fn observe(&mut self, node_id: NodeId, peer_ip: IpAddr, observed_ip: IpAddr) {
+        if is_public(observed_ip) && is_public(peer_ip) {
+            self.observed.entry(node_id).or_insert((observed_ip, subnet(peer_ip)));
+        }
+    }
+
+    pub fn remove_peer(&mut self, node_id: &NodeId) {
+        self.observed.remove(node_id);
+    }
+
+    /// The ip observed from the most subnets, if there are enough of them.
+    fn observed_ip(&self) -> Option<IpAddr> {
+        let mut subnets: HashMap<IpAddr, HashSet<IpAddr>> = HashMap::new();
+        for (ip, subnet) in self.observed.values() {
+            subnets.entry(*ip).or_insert_with(HashSet::new).insert(*subnet);
+        }
+        subnets
+            .into_iter()
+            .map(|(ip, subnets)| (ip, subnets.len()))
+            .filter(|(_, count)| *count >= MIN_OBSERVATIONS)
+            .max_by_key(|(ip, count)| (*count, *ip))
+            .map(|(ip, _)| ip)
+    }
+
+    /// Address advertised to the peers, if the node knows one besides the listening address.
+    /// Without a port mapping, a node listening on all interfaces is assumed to be reachable on
+    /// its listening port at the observed ip.
+    pub fn advertised(&self, listen_addr: SocketAddr) -> Option<SocketAddr> {
+        if self.mapped.is_some() {
+            return self.mapped;
+        }
+        if listen_addr.ip().is_unspecified() {
+            return self.observed_ip().map(|ip| SocketAddr::new(ip, listen_addr.port()));
+        }
+        None
+    }
+}
+
+/// Whether the address may be behind NAT, so that the port is worth mapping.
+fn needs_mapping(addr: &SocketAddr) -> bool {
+    match addr.ip() {
+        IpAddr::V4(ip) => ip.is_unspecified() || ip.is_private(),
+        IpAddr::V6(_) => false,
+    }
+}
+
+/// Local address of the interface the gateway is reached through.
+fn local_ip_towards(gateway: Ipv4Addr) -> io::Result<Ipv4Addr> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(SocketAddrV4::new(gateway, NAT_PMP_PORT))?;
+    match socket.local_addr()?.ip() {
+        IpAddr::V4(ip) => Ok(ip),
+        IpAddr::V6(_) => Err(Error::new(ErrorKind::Other, "No IPv4 address towards gateway")),
+    }
+}
+
+fn map_port_upnp(listen_addr: &SocketAddr) -> io::Result<SocketAddr> {
+    let options = SearchOptions { timeout: Some(Duration::from_secs(3)), ..Default::default() };
+    let gateway = igd::search_gateway(options).map_err(|e| Error::new(ErrorKind::Other, e))?;
+    let local_ip = match listen_addr.ip() {
+        IpAddr::V4(ip) if !ip.is_unspecified() => ip,
+        _ => local_ip_towards(*gateway.addr.ip())?,
+    };
+    let port = listen_addr.port();
+    let local_addr = SocketAddrV4::new(local_ip, port);
+    gateway
+        .add_port(PortMappingProtocol::TCP, port, local_addr, MAPPING_LIFETIME, MAPPING_DESCRIPTION)
+        .map_err(|e| Error::new(ErrorKind::Other, e))?;
+    let external_ip = gateway.get_external_ip().map_err(|e| Error::new(ErrorKind::Other, e))?;
+    Ok(SocketAddr::new(IpAddr::V4(external_ip), port))
+}
+
+/// Default gateway in the contents of /proc/net/route.
+fn parse_default_gateway(routes: &str) -> Option<Ipv4Addr> {
+    routes.lines().skip(1).find_map(|line| {
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        if columns.len() < 3 || columns[1] != "00000000" {
+            return None;
+        }
+        // The addresses are printed as hex of the bytes in network order read as native u32.
+        let gateway = u32::from_str_radix(columns[2], 16).ok()?;
+        Some(Ipv4Addr::from(gateway.to_le_bytes()))
+    })
+}
+
+fn default_gateway() -> io::Result<Ipv4Addr> {
+    let routes = fs::read_to_string("/proc/net/route")?;
+    parse_default_gateway(&routes)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "No default gateway"))
+}
+
+/// Send the NAT-PMP request to the gateway, retrying until it answers.
+fn nat_pmp_request(gateway: SocketAddr, request: &[u8], response: &mut [u8]) -> io::Result<usize> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(gateway)?;
+    socket.set_read_timeout(Some(NAT_PMP_TIMEOUT))?;
+    for _ in 0..NAT_PMP_ATTEMPTS {
+        socket.send(request)?;
+        match socket.recv(response) {
+            Ok(size) => return Ok(size),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Err(Error::new(ErrorKind::TimedOut, "NAT-PMP gateway didn't answer"))
+}
+
+/// Check the header of the response to the request with the given opcode.
+fn check_nat_pmp_response(response: &[u8], opcode: u8, size: usize) -> io::Result<()> {
+    if response.len() < size || response[0] != 0 || response[1] != 128 + opcode {
+        return Err(Error::new(ErrorKind::InvalidData, "Malformed NAT-PMP response"));
+    }
+    let result = u16::from(response[2]) << 8 | u16::from(response[3]);
+    if result != 0 {
+        return Err(Error::new(ErrorKind::Other, format!("NAT-PMP request failed: {}", result)));
+    }
+    Ok(())
+}
+
+fn parse_external_ip_response(response: &[u8]) -> io::Result<Ipv4Addr> {
+    check_nat_pmp_response(response, 0, 12)?;
+    Ok(Ipv4Addr::new(response[8], response[9], response[10], response[11]))
+}
+
+/// Returns the external port of the mapping.
+fn parse_mapping_response(response: &[u8]) -> io::Result<u16> {
+    check_nat_pmp_response(response, 2, 16)?;
+    Ok(u16::from(response[10]) << 8 | u16::from(response[11]))
+}
+
+fn map_port_nat_pmp(gateway: SocketAddr, port: u16) -> io::Result<SocketAddr> {
+    let mut response = [0u8; 16];
+    let size = nat_pmp_request(gateway, &[0, 0], &mut response)?;
+    let external_ip = parse_external_ip_response(&response[..size])?;
+
+    let mut request = [0u8; 12];
+    request[1] = 2;
+    request[4..6].copy_from_slice(&port.to_be_bytes());
+    request[6..8].copy_from_slice(&port.to_be_bytes());
+    request[8..12].copy_from_slice(&MAPPING_LIFETIME.to_be_bytes());
+    let size = nat_pmp_request(gateway, &request, &mut response)?;
+    let external_port = parse_mapping_response(&response[..size])?;
+    Ok(SocketAddr::new(IpAddr::V4(external_ip), external_port))
+}
+
+fn map_port(listen_addr: &SocketAddr) -> io::Result<SocketAddr> {
+    map_port_upnp(listen_addr).or_else(|e| {
+        info!(target: "network", "UPnP port mapping failed: {}, trying NAT-PMP", e);
+        let gateway = SocketAddr::new(IpAddr::V4(default_gateway()?), NAT_PMP_PORT);
+        map_port_nat_pmp(gateway, listen_addr.port())
+    })
+}
+
+/// Keep the listening port mapped on the gateway in a background thread, if the node may be behind
+/// NAT.
+pub fn spawn_port_mapping(listen_addr: SocketAddr, external_addr: Arc<RwLock<ExternalAddr>>) {
+    if !needs_mapping(&listen_addr) {
+        return;
+    }
+    let renew_period = Duration::from_secs(u64::from(MAPPING_LIFETIME / 2));
+    let spawned = thread::Builder::new().name("port-mapping".to_string()).spawn(move || loop {
+        match map_port(&listen_addr) {
+            Ok(mapped) => {
+                if external_addr.read().mapped != Some(mapped) {
+                    info!(target: "network", "Mapped port {} to {}", listen_addr.port(), mapped);
+                }
+                external_addr.write().set_mapped(Some(mapped));
+                thread::sleep(renew_period);
+            }
+            Err(e) => {
+                warn!(target: "network", "Cannot map port {}: {}", listen_addr.port(), e);
+                external_addr.write().set_mapped(None);
+                thread::sleep(RETRY_PERIOD);
+            }
+        }
+    });
+    if let Err(e) = spawned {
+        warn!(target: "network", "Cannot spawn port mapping thread: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use primitives::hash::hash;
+
+    #[test]
+    fn test_advertised_addr() {
+        let listen_addr = "0.0.0.0:3000".parse::<SocketAddr>().unwrap();
+        let mut external_addr = ExternalAddr::default();
+        assert_eq!(external_addr.advertised(listen_addr), None);
+        let ip = "1.2.3.4".parse::<IpAddr>().unwrap();
+        let other_ip = "5.6.7.8".parse::<IpAddr>().unwrap();
+        let peer_ip = |subnet: u8, host: u8| IpAddr::V4(Ipv4Addr::new(9, 9, subnet, host));
+        external_addr.observe(hash(&[1]), peer_ip(1, 1), ip);
+        external_addr.observe(hash(&[2]), peer_ip(2, 1), other_ip);
+        // The peers of a subnet count once.
+        external_addr.observe(hash(&[3]), peer_ip(1, 2), ip);
+        external_addr.observe(hash(&[4]), peer_ip(1, 3), ip);
+        assert_eq!(external_addr.advertised(listen_addr), None);
+        // A peer only has a single vote.
+        external_addr.observe(hash(&[2]), peer_ip(2, 1), ip);
+        assert_eq!(external_addr.advertised(listen_addr), None);
+        // The local peers don't know the external ip.
+        external_addr.observe(hash(&[5]), "192.168.0.2".parse().unwrap(), ip);
+        assert_eq!(external_addr.advertised(listen_addr), None);
+        external_addr.observe(hash(&[6]), peer_ip(3, 1), ip);
+        external_addr.observe(hash(&[7]), peer_ip(4, 1), ip);
+        assert_eq!(external_addr.advertised(listen_addr), Some(SocketAddr::new(ip, 3000)));
+        external_addr.remove_peer(&hash(&[7]));
+        assert_eq!(external_addr.advertised(listen_addr), None);
+        let mapped = "9.9.9.9:4000".parse::<SocketAddr>().unwrap();
+        external_addr.set_mapped(Some(mapped));
+        assert_eq!(external_addr.advertised(listen_addr), Some(mapped));
+    }
+
+    #[test]
+    fn test_nat_pmp() {
+        let routes = "Iface\tDestination\tGateway\tFlags\n\
+                      eth0\t0000A8C0\t00000000\t0001\n\
+                      eth0\t00000000\t0101A8C0\t0003\n";
+        assert_eq!(parse_default_gateway(routes), Some(Ipv4Addr::new(192, 168, 1, 1)));
+
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut request = [0u8; 12];
+            let (_, from) = gateway.recv_from(&mut request).unwrap();
+            assert_eq!(&request[..2], &[0, 0]);
+            gateway.send_to(&[0, 128, 0, 0, 0, 0, 0, 1, 1, 2, 3, 4], from).unwrap();
+            let (size, from) = gateway.recv_from(&mut request).unwrap();
+            assert_eq!(size, 12);
+            assert_eq!(&request[..8], &[0, 2, 0, 0, 0x0b, 0xb8, 0x0b, 0xb8]);
+            let response = [0, 130, 0, 0, 0, 0, 0, 1, 0x0b, 0xb8, 0x0f, 0xa0, 0, 0, 0x0e, 0x10];
+            gateway.send_to(&response, from).unwrap();
+        });
+        let mapped = map_port_nat_pmp(gateway_addr, 3000).unwrap();
+        assert_eq!(mapped, "1.2.3.4:4000".parse::<SocketAddr>().unwrap());
+
+        let refused = [0, 130, 0, 3, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(parse_mapping_response(&refused).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! Encryption and authentication of the connections with the node key. Once the custom protocol
+//! is open, the peers run a Noise XX handshake in which both prove they own the static key their
+//! node id is derived from, and every message after it is encrypted and authenticated with the
+//! keys of the handshake. Both sides start the handshake as the initiator, the side with the
+//! smaller ephemeral key stays one and the other answers as the responder.
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use snow::{Builder, Session};
+
+use primitives::hash::{hash, CryptoHash};
+use primitives::keystore::{self, KeystoreError};
+
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+/// Max size of a Noise message, the encrypted frames are prefixed with their size as u16.
+const MAX_FRAME_SIZE: usize = 65535;
+/// Size of the authentication tag appended to every encrypted frame.
+const TAG_SIZE: usize = 16;
+/// Size of the first message of the handshake, the ephemeral public key.
+const FIRST_MESSAGE_SIZE: usize = 32;
+
+/// Identifier of the node on the network, the hash of its node key.
+pub type NodeId = CryptoHash;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum NoiseError {
+    /// Handshake message that doesn't fit the state of the handshake.
+    UnexpectedMessage,
+    /// Frames are truncated or the decrypted message is larger than the limit.
+    InvalidFrames,
+    Snow(String),
+}
+
+impl fmt::Display for NoiseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NoiseError::UnexpectedMessage => write!(f, "Unexpected handshake message"),
+            NoiseError::InvalidFrames => write!(f, "Invalid encrypted frames"),
+            NoiseError::Snow(e) => write!(f, "Noise error: {}", e),
+        }
+    }
+}
+
+fn snow_error<E: fmt::Debug>(e: E) -> NoiseError {
+    NoiseError::Snow(format!("{:?}", e))
+}
+
+fn builder<'a>() -> Builder<'a> {
+    Builder::new(NOISE_PARAMS.parse().expect("Invalid Noise parameters"))
+}
+
+/// Static key pair of the node, which the peers know it by.
+#[derive(Clone)]
+pub struct NodeKey {
+    pub public_key: Vec<u8>,
+    secret_key: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NodeKeyFile {
+    public_key: String,
+    secret_key: String,
+}
+
+impl NodeKey {
+    pub fn generate() -> Self {
+        let keypair = builder().generate_keypair().expect("Cannot generate node key");
+        NodeKey { public_key: keypair.public, secret_key: keypair.private }
+    }
+
+    pub fn node_id(&self) -> NodeId {
+        hash(&self.public_key)
+    }
+
+    /// Reads the key written by `write_to_file`, decrypting it with the keystore passphrase.
+    pub fn from_file(path: &Path) -> Result<Self, KeystoreError> {
+        let passphrase = keystore::passphrase_from_env();
+        let key_file: NodeKeyFile =
+            keystore::read_key_file(path, passphrase.as_ref().map(String::as_str))?;
+        let decode = |key: &str| {
+            bs58::decode(key)
+                .into_vec()
+                .map_err(|e| KeystoreError::InvalidFormat(e.to_string()))
+        };
+        Ok(NodeKey {
+            public_key: decode(&key_file.public_key)?,
+            secret_key: decode(&key_file.secret_key)?,
+        })
+    }
+
+    /// Writes the key, encrypted if the keystore passphrase is set.
+    pub fn write_to_file(&self, path: &Path) -> Result<(), KeystoreError> {
+        let key_file = NodeKeyFile {
+            public_key: bs58::encode(&self.public_key).into_string(),
+            secret_key: bs58::encode(&self.secret_key).into_string(),
+        };
+        let passphrase = keystore::passphrase_from_env();
+        let passphrase = passphrase.as_ref().map(String::as_str);
+        keystore::write_key_file(path, &key_file, &key_file.public_key, passphrase)
+    }
+
+    /// Reads the key of the node, generating and writing it on the first start.
+    pub fn load_or_generate(path: &Path) -> Result<Self, KeystoreError> {
+        if path.exists() {
+            return NodeKey::from_file(path);
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| KeystoreError::Io(e.to_string()))?;
+        }
+        let node_key = NodeKey::generate();
+        node_key.write_to_file(path)?;
+        Ok(node_key)
+    }
+}
+
+enum State {
+    /// Sent the ephemeral key, waiting for the response of the peer.
+    Initiated { session: Session, first_message: Vec<u8> },
+    /// Answered the ephemeral key of the peer, waiting for its static key.
+    Responding(Session),
+    Transport(Session),
+    /// Handshake failed, the session is unusable.
+    Failed,
+}
+
+/// Noise session with a peer, through the handshake and then encrypting the messages.
+pub struct NoiseSession {
+    state: State,
+    /// Static key of the peer, once the handshake is done.
+    remote_key: Option<Vec<u8>>,
+}
+
+impl NoiseSession {
+    /// Starts the handshake, returns the session and its first message.
+    pub fn initiate(node_key: &NodeKey) -> Result<(Self, Vec<u8>), NoiseError> {
+        let mut session = builder()
+            .local_private_key(&node_key.secret_key)
+            .build_initiator()
+            .map_err(snow_error)?;
+        let first_message = write_message(&mut session)?;
+        let state = State::Initiated { session, first_message: first_message.clone() };
+        Ok((NoiseSession { state, remote_key: None }, first_message))
+    }
+
+    /// Handles the handshake message of the peer, returns the message to answer with, if any.
+    pub fn on_handshake_message(
+        &mut self,
+        node_key: &NodeKey,
+        message: &[u8],
+    ) -> Result<Option<Vec<u8>>, NoiseError> {
+        if let State::Transport(_) = self.state {
+            return Err(NoiseError::UnexpectedMessage);
+        }
+        let state = std::mem::replace(&mut self.state, State::Failed);
+        let (state, response) = match state {
+            State::Initiated { session, first_message } if message.len() == FIRST_MESSAGE_SIZE => {
+                // -> e, from both sides.
+                if first_message.as_slice() < message {
+                    (State::Initiated { session, first_message }, None)
+                } else {
+                    let mut session = builder()
+                        .local_private_key(&node_key.secret_key)
+                        .build_responder()
+                        .map_err(snow_error)?;
+                    read_message(&mut session, message)?;
+                    let second_message = write_message(&mut session)?;
+                    (State::Responding(session), Some(second_message))
+                }
+            }
+            State::Initiated { mut session, .. } => {
+                // <- e, ee, s, es
+                read_message(&mut session, message)?;
+                // -> s, se
+                let third_message = write_message(&mut session)?;
+                (self.finish(session)?, Some(third_message))
+            }
+            State::Responding(mut session) => {
+                read_message(&mut session, message)?;
+                (self.finish(session)?, None)
+            }
+            State::Transport(_) | State::Failed => return Err(NoiseError::UnexpectedMessage),
+        };
+        self.state = state;
+        Ok(response)
+    }
+
+    fn finish(&mut self, session: Session) -> Result<State, NoiseError> {
+        let remote_key = session
+            .get_remote_static()
+            .ok_or_else(|| NoiseError::Snow("No remote static key".to_string()))?;
+        self.remote_key = Some(remote_key.to_vec());
+        Ok(State::Transport(session.into_transport_mode().map_err(snow_error)?))
+    }
+
+    /// Node id of the peer, proved by the handshake once it's done.
+    pub fn remote_node_id(&self) -> Option<NodeId> {
+        self.remote_key.as_ref().map(|key| hash(key))
+    }
+
+    fn transport(&mut self) -> Result<&mut Session, NoiseError> {
+        match &mut self.state {
+            State::Transport(session) => Ok(session),
+            _ => Err(NoiseError::UnexpectedMessage),
+        }
+    }
+
+    /// Encrypts the data in frames of at most `MAX_FRAME_SIZE` bytes.
+    pub fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        let session = self.transport()?;
+        let mut frames = vec![];
+        for chunk in data.chunks(MAX_FRAME_SIZE - TAG_SIZE) {
+            let mut buf = vec![0; chunk.len() + TAG_SIZE];
+            let size = session.write_message(chunk, &mut buf).map_err(snow_error)?;
+            frames.extend_from_slice(&(size as u16).to_be_bytes());
+            frames.extend_from_slice(&buf[..size]);
+        }
+        Ok(frames)
+    }
+
+    /// Decrypts the frames, failing if the data is over `max_size` bytes.
+    pub fn decrypt(&mut self, mut frames: &[u8], max_size: usize) -> Result<Vec<u8>, NoiseError> {
+        let session = self.transport()?;
+        let mut data = vec![];
+        while !frames.is_empty() {
+            if frames.len() < 2 {
+                return Err(NoiseError::InvalidFrames);
+            }
+            let frame_size = usize::from(u16::from_be_bytes([frames[0], frames[1]]));
+            if frames.len() < 2 + frame_size {
+                return Err(NoiseError::InvalidFrames);
+            }
+            let (frame, rest) = frames[2..].split_at(frame_size);
+            let mut buf = vec![0; frame_size];
+            let size = session.read_message(frame, &mut buf).map_err(snow_error)?;
+            if data.len() + size > max_size {
+                return Err(NoiseError::InvalidFrames);
+            }
+            data.extend_from_slice(&buf[..size]);
+            frames = rest;
+        }
+        Ok(data)
+    }
+}
+
+fn write_message(session: &mut Session) -> Result<Vec<u8>, NoiseError> {
+    let mut buf = vec![0; MAX_FRAME_SIZE];
+    let size = session.write_message(&[], &mut buf).map_err(snow_error)?;
+    buf.truncate(size);
+    Ok(buf)
+}
+
+fn read_message(session: &mut Session, message: &[u8]) -> Result<(), NoiseError> {
+    let mut buf = vec![0; MAX_FRAME_SIZE];
+    session.read_message(message, &mut buf).map_err(snow_error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_key_file() {
+        let path = std::env::temp_dir().join("near_network_test_node_key");
+        let _ = fs::remove_file(&path);
+        let key = NodeKey::load_or_generate(&path).unwrap();
+        let loaded = NodeKey::load_or_generate(&path).unwrap();
+        assert_eq!(loaded.public_key, key.public_key);
+        assert_eq!(loaded.secret_key, key.secret_key);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_handshake() {
+        let (key1, key2) = (NodeKey::generate(), NodeKey::generate());
+        let (session1, first1) = NoiseSession::initiate(&key1).unwrap();
+        let (session2, first2) = NoiseSession::initiate(&key2).unwrap();
+        let ((mut initiator, initiator_key, first), (mut responder, responder_key, other_first)) =
+            if first1 < first2 {
+                ((session1, &key1, first1), (session2, &key2, first2))
+            } else {
+                ((session2, &key2, first2), (session1, &key1, first1))
+            };
+        // The initiator drops the ephemeral key of the responder, which answers its own.
+        assert_eq!(initiator.on_handshake_message(initiator_key, &other_first), Ok(None));
+        let second = responder.on_handshake_message(responder_key, &first).unwrap().unwrap();
+        let third = initiator.on_handshake_message(initiator_key, &second).unwrap().unwrap();
+        assert_eq!(responder.on_handshake_message(responder_key, &third), Ok(None));
+        assert_eq!(initiator.remote_node_id(), Some(responder_key.node_id()));
+        assert_eq!(responder.remote_node_id(), Some(initiator_key.node_id()));
+        assert_eq!(
+            responder.on_handshake_message(responder_key, &third),
+            Err(NoiseError::UnexpectedMessage)
+        );
+
+        let data = vec![7; 100_000];
+        let frames = initiator.encrypt(&data).unwrap();
+        assert_eq!(responder.decrypt(&frames, data.len()), Ok(data.clone()));
+        let frames = initiator.encrypt(&data).unwrap();
+        assert_eq!(responder.decrypt(&frames, data.len() - 1), Err(NoiseError::InvalidFrames));
+    }
+}
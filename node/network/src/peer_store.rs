@@ -0,0 +1,130 @@
+//! Addresses of the peers the node learned about from the other peers. They are kept in a file
+//! in the network config directory, one per line, and are dialed as boot nodes after a restart,
+//! so that the node can reconnect to the network without relying on the configured boot nodes.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use rand::{seq, Rng};
+use substrate_network_libp2p::{parse_str_addr, PeerId};
+
+/// File of the peer store in the network config directory.
+pub const PEER_STORE_FILE: &str = "peers";
+/// Upper bound on the number of stored addresses, so that peers cannot flood the store.
+const MAX_PEERS: usize = 1000;
+
+pub struct PeerStore {
+    /// File the addresses are persisted to, if any.
+    path: Option<PathBuf>,
+    /// Address of each peer, ending with its peer id, by the base58 of the peer id.
+    addrs: BTreeMap<String, String>,
+    /// Whether the addresses changed since they were last saved.
+    dirty: bool,
+}
+
+impl PeerStore {
+    /// Loads the addresses from the file. Missing file means no known peers yet, malformed
+    /// lines are skipped.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut store = PeerStore { path, addrs: BTreeMap::new(), dirty: false };
+        let content = match &store.path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(content) => content,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+                Err(e) => {
+                    warn!(target: "network", "Cannot read peer store {}: {}", path.display(), e);
+                    String::new()
+                }
+            },
+            None => String::new(),
+        };
+        for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            if !store.add(line) {
+                warn!(target: "network", "Skipping peer address {}", line);
+            }
+        }
+        store.dirty = false;
+        store
+    }
+
+    /// Adds the address of the form `/ip4/1.2.3.4/tcp/30333/p2p/<peer id>`, replacing the
+    /// previous address of the peer. Returns whether the address is valid and was stored.
+    pub fn add(&mut self, addr: &str) -> bool {
+        let peer_id = match parse_str_addr(addr) {
+            Ok((peer_id, _)) => peer_id.to_base58(),
+            Err(_) => return false,
+        };
+        if !self.addrs.contains_key(&peer_id) && self.addrs.len() >= MAX_PEERS {
+            return false;
+        }
+        if self.addrs.get(&peer_id).map(String::as_str) != Some(addr) {
+            self.addrs.insert(peer_id, addr.to_string());
+            self.dirty = true;
+        }
+        true
+    }
+
+    pub fn remove(&mut self, peer_id: &PeerId) {
+        if self.addrs.remove(&peer_id.to_base58()).is_some() {
+            self.dirty = true;
+        }
+    }
+
+    pub fn addrs(&self) -> impl Iterator<Item = &String> {
+        self.addrs.values()
+    }
+
+    /// Random addresses of at most `amount` peers that pass the filter.
+    pub fn sample<R: Rng, F: Fn(&str) -> bool>(
+        &self,
+        rng: &mut R,
+        amount: usize,
+        filter: F,
+    ) -> Vec<String> {
+        let addrs = self.addrs.iter().filter(|(peer_id, _)| filter(peer_id)).map(|(_, a)| a);
+        seq::sample_iter(rng, addrs.cloned(), amount).unwrap_or_else(|addrs| addrs)
+    }
+
+    /// Writes the addresses to the file if they changed since the last save.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(path) = &self.path {
+            let content: String = self.addrs.values().map(|addr| format!("{}\n", addr)).collect();
+            fs::write(path, content)?;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{create_secret, raw_key_to_peer_id_str, special_secret};
+
+    #[test]
+    fn test_save_load() {
+        let path = std::env::temp_dir().join("near_network_test_peer_store");
+        let _ = fs::remove_file(&path);
+        let addr = |port, secret| {
+            format!("/ip4/127.0.0.1/tcp/{}/p2p/{}", port, raw_key_to_peer_id_str(secret))
+        };
+        let addr1 = addr(3000, create_secret());
+        let addr2 = addr(3001, special_secret());
+        let mut store = PeerStore::load(Some(path.clone()));
+        assert_eq!(store.addrs().count(), 0);
+        assert!(store.add(&addr1));
+        assert!(store.add(&addr2));
+        assert!(!store.add("/ip4/127.0.0.1/tcp/3002"));
+        let (peer_id, _) = parse_str_addr(&addr2).unwrap();
+        store.remove(&peer_id);
+        store.save().unwrap();
+
+        let store = PeerStore::load(Some(path.clone()));
+        assert_eq!(store.addrs().cloned().collect::<Vec<_>>(), vec![addr1]);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,24 +1,95 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time;
+use std::time::Instant;
 
-use futures::{Future, Sink};
-use futures::sync::mpsc::Sender;
+use futures::{stream, Future, Sink};
+use futures::sync::mpsc::{SendError, Sender};
 use parking_lot::RwLock;
-use substrate_network_libp2p::{NodeIndex, ProtocolId, Severity};
+use rand::{thread_rng, Rng};
+use substrate_network_libp2p::{NodeIndex, PeerId, ProtocolId, Severity};
 
-use beacon::types::SignedBeaconBlock;
+use beacon::types::{Endorsement, SignedBeaconBlock};
 use chain::{SignedBlock, SignedHeader};
-use client::Client;
-use primitives::hash::CryptoHash;
-use primitives::traits::Decode;
-use primitives::types::{AccountId, Gossip, UID};
-use chain::{SignedShardBlock, ChainPayload, ReceiptBlock};
+use client::{Client, ConnectedPeer};
+use primitives::hash::{hash_struct, CryptoHash};
+use primitives::limited::DecodeLimits;
+use primitives::serialize::DecodeLimited;
+use primitives::signer::node_id_message;
+use primitives::traits::Encode;
+use primitives::types::{AccountId, BlockId, Gossip, ShardId, UID};
+use primitives::utils::account_to_shard_id;
+use chain::{Challenge, SignedShardBlock, ChainPayload, ReceiptBlock, ShardBlockPart};
+use transaction::SignedTransaction;
 
-use crate::message::{self, Message, Status};
+use crate::compression::{self, Compression};
+use crate::epoch_sync::{EpochSync, MAX_PROOFS_PER_REQUEST};
+use crate::gossip::{validate_receipt_block, validate_transaction, SeenCache, SEEN_CACHE_SIZE};
+use crate::message::{
+    AccountClaim, BlockPartsRequest, BlockPartsResponse, BlocksRequest, BlocksResponse,
+    EpochProofsRequest, EpochProofsResponse, HeadersRequest,
+    HeadersResponse, Message, PeersResponse, StatePartRequest, StatePartResponse, Status,
+};
+use crate::nat::ExternalAddr;
+use crate::noise::{NodeId, NodeKey, NoiseSession};
+use crate::peer_store::PeerStore;
+use crate::rate_limit::{MessageKind, RateLimiter, RateLimits, Verdict};
+use crate::state_sync::{StateSync, NUM_STATE_PARTS};
+use crate::sync::{BlockSync, MAX_BLOCKS_PER_REQUEST, MAX_HEADERS_PER_REQUEST};
 
 /// current version of the protocol
-pub(crate) const CURRENT_VERSION: u32 = 1;
+pub(crate) const CURRENT_VERSION: u32 = 10;
+/// oldest version of the protocol the node can talk to, the first with the Noise handshake
+pub(crate) const MIN_SUPPORTED_VERSION: u32 = 10;
+/// Name and version of the node software, reported to the peers.
+const NODE_VERSION: &str = concat!("near/", env!("CARGO_PKG_VERSION"));
+/// Limits of the decompressed messages of the peers, whose transactions and receipts are
+/// rejected before they are decoded in full if a field is over them.
+pub const MESSAGE_LIMITS: DecodeLimits = DecodeLimits {
+    max_size: compression::MAX_MESSAGE_SIZE,
+    max_len: 4 * 1024 * 1024,
+    max_depth: 64,
+};
+
+/// Feature of the peers that serve and request state parts.
+pub const FEATURE_STATE_SYNC: &str = "state-sync";
+/// Feature of the peers that gossip the endorsements of the blocks.
+pub const FEATURE_ENDORSEMENTS: &str = "endorsements";
+/// Feature of the peers that gossip the challenges of invalid blocks.
+pub const FEATURE_CHALLENGES: &str = "challenges";
+/// Feature of the peers that keep and serve the erasure-coded parts of the shard blocks.
+pub const FEATURE_BLOCK_PARTS: &str = "block-parts";
+/// Feature of the peers that serve and request the proofs of the epochs.
+pub const FEATURE_EPOCH_SYNC: &str = "epoch-sync";
+/// Feature of the peers that exchange the addresses of the peers they know.
+pub const FEATURE_PEER_EXCHANGE: &str = "peer-exchange";
+/// Feature of the peers that tell each other the ip they see them connecting from.
+pub const FEATURE_EXTERNAL_ADDR: &str = "external-addr";
+/// Optional parts of the protocol. Messages of a feature are only sent to the peers that have it
+/// enabled, so that new message types can be rolled out gradually.
+pub const SUPPORTED_FEATURES: &[&str] = &[
+    FEATURE_STATE_SYNC,
+    FEATURE_ENDORSEMENTS,
+    FEATURE_CHALLENGES,
+    FEATURE_BLOCK_PARTS,
+    FEATURE_EPOCH_SYNC,
+    FEATURE_PEER_EXCHANGE,
+    FEATURE_EXTERNAL_ADDR,
+];
+/// Max number of addresses sent in reply to a peers request.
+const PEERS_RESPONSE_SIZE: usize = 100;
+
+/// Highest protocol version both the node and the peer support, if any.
+fn negotiate_version(peer_min_version: u32, peer_version: u32) -> Option<u32> {
+    let version = std::cmp::min(peer_version, CURRENT_VERSION);
+    if version < std::cmp::max(peer_min_version, MIN_SUPPORTED_VERSION) {
+        None
+    } else {
+        Some(version)
+    }
+}
 
 #[derive(Clone)]
 pub struct ProtocolConfig {
@@ -26,15 +97,37 @@ pub struct ProtocolConfig {
     pub account_id: Option<AccountId>,
     /// Config information goes here.
     pub protocol_id: ProtocolId,
+    /// Enabled features, a subset of `SUPPORTED_FEATURES`.
+    pub features: Vec<String>,
+    /// Limits of the messages each peer can send.
+    pub rate_limits: RateLimits,
+    /// File the addresses of the known peers are kept in across restarts, if any.
+    pub peer_store_path: Option<PathBuf>,
+    /// Number of connections the node dials the known peers to keep.
+    pub target_peers: usize,
+    /// Key the node authenticates the connections with.
+    pub node_key: NodeKey,
+    /// Whether to ask the gateway to forward the listening port, if the node may be behind NAT.
+    pub port_mapping: bool,
 }
 
 impl ProtocolConfig {
     pub fn new(account_id: Option<AccountId>, protocol_id: ProtocolId) -> ProtocolConfig {
-        ProtocolConfig { account_id, protocol_id }
+        let features = SUPPORTED_FEATURES.iter().map(|f| f.to_string()).collect();
+        ProtocolConfig {
+            account_id,
+            protocol_id,
+            features,
+            rate_limits: RateLimits::default(),
+            peer_store_path: None,
+            target_peers: 8,
+            node_key: NodeKey::generate(),
+            port_mapping: false,
+        }
     }
 
     pub fn new_with_default_id(account_id: Option<AccountId>) -> ProtocolConfig {
-        ProtocolConfig { account_id, protocol_id: ProtocolId::default() }
+        ProtocolConfig::new(account_id, ProtocolId::default())
     }
 }
 
@@ -44,17 +137,32 @@ impl Default for ProtocolConfig {
     }
 }
 
+/// Shards the receipts are sent to.
+fn receipt_shards(receipt: &ReceiptBlock) -> Vec<ShardId> {
+    receipt.receipts.iter().map(|r| account_to_shard_id(&r.receiver)).collect()
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 pub(crate) struct PeerInfo {
-    /// Protocol version.
+    /// Protocol version negotiated with the peer.
     protocol_version: u32,
+    /// Name and version of the node software of the peer.
+    node_version: String,
+    /// Features enabled by both the node and the peer.
+    features: HashSet<String>,
     /// best hash from peer.
     best_hash: CryptoHash,
     /// Best block index from peer.
     best_index: u64,
-    /// Optionally, Account id.
+    /// Account of the authority the peer runs, if it signed the node id.
     account_id: Option<AccountId>,
+    /// Shards whose receipts the peer wants to receive.
+    tracked_shards: Vec<ShardId>,
+    /// Compression of the messages sent to the peer.
+    compression: Compression,
+    /// Node id of the peer, proved by the handshake.
+    node_id: NodeId,
 }
 
 pub struct Protocol {
@@ -62,20 +170,52 @@ pub struct Protocol {
     pub config: ProtocolConfig,
     /// Peers that are in the handshaking process.
     handshaking_peers: RwLock<HashMap<NodeIndex, time::Instant>>,
+    /// Noise sessions with the peers, the messages are encrypted once their handshake is done.
+    sessions: RwLock<HashMap<NodeIndex, NoiseSession>>,
     /// Info about peers.
     peer_info: RwLock<HashMap<NodeIndex, PeerInfo>>,
     /// Info for authority peers.
     peer_account_info: RwLock<HashMap<AccountId, NodeIndex>>,
+    /// Account of the node with the signature of its node id, sent in the status.
+    account_claim: Option<AccountClaim>,
     /// Client, for read-only access.
     client: Arc<Client>,
     /// Channel into which the protocol sends the new blocks.
     incoming_block_tx: Sender<(SignedBeaconBlock, SignedShardBlock)>,
+    /// Channel into which the protocol sends the received transactions.
+    transaction_sender: Sender<SignedTransaction>,
     /// Channel into which the protocol sends the received receipts.
     receipt_sender: Sender<ReceiptBlock>,
     /// Channel into which the protocol sends the messages that should be send back to the network.
     message_sender: Sender<(NodeIndex, Message)>,
     /// Channel into which the protocol sends the gossips that should be processed by TxFlow.
     gossip_sender: Sender<Gossip<ChainPayload>>,
+    /// Hashes of the recently gossiped transactions.
+    seen_transactions: RwLock<SeenCache>,
+    /// Hashes of the recently gossiped receipt blocks.
+    seen_receipts: RwLock<SeenCache>,
+    /// Catching up with the peers that are ahead.
+    sync: RwLock<BlockSync>,
+    /// Skipping the epochs given their proofs, started once the node is far behind.
+    epoch_sync: RwLock<Option<EpochSync>>,
+    /// Downloading the state at an epoch boundary, started once the node is far behind.
+    state_sync: RwLock<Option<StateSync>>,
+    /// Limits of the messages received from the peers.
+    rate_limiter: RwLock<RateLimiter>,
+    /// Addresses of the peers learned from the other peers.
+    peer_store: RwLock<PeerStore>,
+    /// Base58 of the peer ids of the connected peers.
+    peer_ids: RwLock<HashMap<NodeIndex, String>>,
+    /// Peers asked for the peers they know, whose response is awaited.
+    peers_requested: RwLock<HashSet<NodeIndex>>,
+    /// Base58 of the peer id of the node, once the network service reported it.
+    local_peer_id: RwLock<Option<String>>,
+    /// Addresses the node advertises to the peers.
+    local_addrs: RwLock<Vec<String>>,
+    /// Ips the connected peers are connecting from, as the network service reported them.
+    peer_ips: RwLock<HashMap<NodeIndex, IpAddr>>,
+    /// External address of the node, mapped on the gateway or observed by the peers.
+    external_addr: Arc<RwLock<ExternalAddr>>,
 }
 
 impl Protocol {
@@ -83,20 +223,51 @@ impl Protocol {
         config: ProtocolConfig,
         client: Arc<Client>,
         incoming_block_tx: Sender<(SignedBeaconBlock, SignedShardBlock)>,
+        transaction_sender: Sender<SignedTransaction>,
         receipt_sender: Sender<ReceiptBlock>,
         message_sender: Sender<(NodeIndex, Message)>,
         gossip_sender: Sender<Gossip<ChainPayload>>,
     ) -> Self {
+        client.network_info.write().protocol_version = Some(CURRENT_VERSION);
+        let best_block = client.beacon_chain.chain.best_block();
+        let sync = BlockSync::new(best_block.index(), best_block.block_hash());
+        let rate_limiter = RateLimiter::new(config.rate_limits.clone());
+        let peer_store = PeerStore::load(config.peer_store_path.clone());
+        let account_claim = config.account_id.clone().and_then(|account_id| {
+            match client.signer.sign_node_id(&config.node_key.node_id()) {
+                Ok(signature) => Some(AccountClaim { account_id, signature }),
+                Err(e) => {
+                    warn!(target: "network", "Cannot sign the node id for {}: {}", account_id, e);
+                    None
+                }
+            }
+        });
         Self {
             config,
             handshaking_peers: RwLock::new(HashMap::new()),
+            sessions: RwLock::new(HashMap::new()),
             peer_info: RwLock::new(HashMap::new()),
             peer_account_info: RwLock::new(HashMap::new()),
+            account_claim,
             client,
             incoming_block_tx,
+            transaction_sender,
             receipt_sender,
             message_sender,
             gossip_sender,
+            seen_transactions: RwLock::new(SeenCache::new(SEEN_CACHE_SIZE)),
+            seen_receipts: RwLock::new(SeenCache::new(SEEN_CACHE_SIZE)),
+            sync: RwLock::new(sync),
+            epoch_sync: RwLock::new(None),
+            state_sync: RwLock::new(None),
+            rate_limiter: RwLock::new(rate_limiter),
+            peer_store: RwLock::new(peer_store),
+            peer_ids: RwLock::new(HashMap::new()),
+            peers_requested: RwLock::new(HashSet::new()),
+            local_peer_id: RwLock::new(None),
+            local_addrs: RwLock::new(vec![]),
+            peer_ips: RwLock::new(HashMap::new()),
+            external_addr: Arc::new(RwLock::new(ExternalAddr::default())),
         }
     }
 
@@ -105,19 +276,76 @@ impl Protocol {
         peer_account_info.get(account_id).cloned()
     }
 
-    pub fn on_peer_connected(&self, peer: NodeIndex) {
+    pub fn external_addr(&self) -> Arc<RwLock<ExternalAddr>> {
+        self.external_addr.clone()
+    }
+
+    /// Starts the handshake with the peer, the status is sent once it's done.
+    pub fn on_peer_connected(&self, peer: NodeIndex, peer_id: &PeerId, peer_ip: Option<IpAddr>) {
         self.handshaking_peers.write().insert(peer, time::Instant::now());
+        self.peer_ids.write().insert(peer, peer_id.to_base58());
+        if let Some(peer_ip) = peer_ip {
+            self.peer_ips.write().insert(peer, peer_ip);
+        }
+        match NoiseSession::initiate(&self.config.node_key) {
+            Ok((session, message)) => {
+                self.sessions.write().insert(peer, session);
+                self.send_message(peer, Message::Handshake(message));
+            }
+            Err(e) => {
+                error!(target: "network", "Cannot start the handshake with {:?}: {}", peer, e)
+            }
+        }
+    }
+
+    fn status(&self) -> Status {
         let best_block_header = self.client.beacon_chain.chain.best_block().header();
-        let status = message::Status {
+        Status {
             version: CURRENT_VERSION,
+            min_version: MIN_SUPPORTED_VERSION,
+            node_version: NODE_VERSION.to_string(),
+            features: self.config.features.clone(),
             best_index: best_block_header.index(),
             best_hash: best_block_header.block_hash(),
             genesis_hash: self.client.beacon_chain.chain.genesis_hash,
-            account_id: self.config.account_id.clone(),
+            account: self.account_claim.clone(),
+            tracked_shards: self.tracked_shards(),
+            compression: compression::supported(),
+        }
+    }
+
+    /// Advances the handshake with the peer. Once it's done, checks the node id the peer proved
+    /// and sends the status, encrypted like all the next messages.
+    fn on_handshake(&self, peer: NodeIndex, message: &[u8]) -> Result<(), (NodeIndex, Severity)> {
+        let (response, node_id) = {
+            let mut sessions = self.sessions.write();
+            let session =
+                sessions.get_mut(&peer).ok_or((peer, Severity::Bad("Unexpected handshake.")))?;
+            let response =
+                session.on_handshake_message(&self.config.node_key, message).map_err(|e| {
+                    warn!(target: "network", "Handshake with {:?} failed: {}", peer, e);
+                    (peer, Severity::Bad("Peer failed the handshake."))
+                })?;
+            (response, session.remote_node_id())
         };
-        debug!(target: "network", "Sending status message to {:?}: {:?}", peer, status);
-        let message = Message::Status(status);
-        self.send_message(peer, message);
+        let mut messages: Vec<Message> = response.into_iter().map(Message::Handshake).collect();
+        if let Some(node_id) = node_id {
+            if node_id == self.config.node_key.node_id() {
+                return Err((peer, Severity::Useless("Peer is the node itself.")));
+            }
+            let connected = self.sessions.read().iter().any(|(other, session)| {
+                *other != peer && session.remote_node_id() == Some(node_id)
+            });
+            if connected {
+                return Err((peer, Severity::Useless("Peer is already connected.")));
+            }
+            let status = self.status();
+            debug!(target: "network", "Sending status message to {:?}: {:?}", peer, status);
+            messages.push(Message::Status(status));
+        }
+        // The last message of the handshake goes unencrypted before the encrypted status.
+        self.send_messages(peer, messages);
+        Ok(())
     }
 
     pub fn on_peer_disconnected(&self, peer: NodeIndex) {
@@ -127,17 +355,135 @@ impl Protocol {
             }
         }
         self.handshaking_peers.write().remove(&peer);
+        if let Some(session) = self.sessions.write().remove(&peer) {
+            if let Some(node_id) = session.remote_node_id() {
+                self.external_addr.write().remove_peer(&node_id);
+            }
+        }
+        self.peer_ips.write().remove(&peer);
         self.peer_info.write().remove(&peer);
+        self.peer_ids.write().remove(&peer);
+        self.peers_requested.write().remove(&peer);
+        self.client.network_info.write().peers.remove(&peer);
+        self.sync.write().remove_peer(peer);
+        if let Some(epoch_sync) = self.epoch_sync.write().as_mut() {
+            epoch_sync.remove_peer(peer);
+        }
+        if let Some(state_sync) = self.state_sync.write().as_mut() {
+            state_sync.remove_peer(peer);
+        }
+        self.rate_limiter.write().remove_peer(peer);
+    }
+
+    /// Shards the node processes the receipts of.
+    fn tracked_shards(&self) -> Vec<ShardId> {
+        vec![self.client.shard_chain.chain.best_block().shard_id()]
+    }
+
+    /// Peers that transactions are gossiped to, so that they reach the authorities producing
+    /// the next block. If the node is one of them, TxFlow spreads the transactions instead.
+    fn transaction_targets(&self, sender: Option<NodeIndex>) -> Vec<NodeIndex> {
+        if self.client.is_authority() {
+            return vec![];
+        }
+        let peer_account_info = self.peer_account_info.read();
+        let authorities: Vec<NodeIndex> = self
+            .client
+            .get_recent_uid_to_authority_map()
+            .values()
+            .filter_map(|authority| peer_account_info.get(&authority.account_id).cloned())
+            .filter(|peer| Some(*peer) != sender)
+            .collect();
+        if !authorities.is_empty() {
+            return authorities;
+        }
+        // Not connected to the authorities, let the other peers relay.
+        self.peer_info.read().keys().cloned().filter(|peer| Some(*peer) != sender).collect()
+    }
+
+    /// Peers that track the shards the receipts are sent to.
+    fn receipt_targets(&self, receipt: &ReceiptBlock, sender: Option<NodeIndex>) -> Vec<NodeIndex> {
+        let shards = receipt_shards(receipt);
+        self.peer_info
+            .read()
+            .iter()
+            .filter(|(peer, info)| {
+                Some(**peer) != sender && info.tracked_shards.iter().any(|s| shards.contains(s))
+            })
+            .map(|(peer, _)| *peer)
+            .collect()
+    }
+
+    /// Gossips the transaction submitted to the node.
+    pub fn on_outgoing_transaction(&self, transaction: SignedTransaction) {
+        // Rebroadcasts are gossiped again, only the echoes of the peers are dropped.
+        self.seen_transactions.write().insert(transaction.get_hash());
+        for peer in self.transaction_targets(None) {
+            self.send_message(peer, Message::Transaction(Box::new(transaction.clone())));
+        }
     }
 
-    pub fn on_receipt(&self, receipt: ReceiptBlock) {
+    fn on_transaction(&self, peer: NodeIndex, transaction: SignedTransaction) {
+        if !self.seen_transactions.write().insert(transaction.get_hash()) {
+            return;
+        }
+        if let Err(e) = validate_transaction(&self.client, &transaction) {
+            debug!(target: "network", "Dropping transaction from {:?}: {}", peer, e);
+            return;
+        }
+        for target in self.transaction_targets(Some(peer)) {
+            self.send_message(target, Message::Transaction(Box::new(transaction.clone())));
+        }
+        let copied_tx = self.transaction_sender.clone();
+        tokio::spawn(
+            copied_tx
+                .send(transaction)
+                .map(|_| ())
+                .map_err(|e| error!("Failure to send the transaction {:?}", e)),
+        );
+    }
+
+    /// Gossips the receipts produced by the node to the peers tracking their shards.
+    pub fn on_outgoing_receipts(&self, receipt: ReceiptBlock) {
+        if !self.seen_receipts.write().insert(hash_struct(&receipt)) {
+            return;
+        }
+        for peer in self.receipt_targets(&receipt, None) {
+            self.send_message(peer, Message::Receipt(Box::new(receipt.clone())));
+        }
+    }
+
+    pub fn on_receipt(
+        &self,
+        peer: NodeIndex,
+        receipt: ReceiptBlock,
+    ) -> Result<(), (NodeIndex, Severity)> {
+        // Validated first, so that receipts ahead of the node are accepted once it catches up.
+        if let Err(e) = validate_receipt_block(&self.client, &receipt) {
+            if e.is_bad() {
+                return Err((peer, Severity::Bad("Peer sent invalid receipts.")));
+            }
+            debug!(target: "network", "Dropping receipts from {:?}: {}", peer, e);
+            return Ok(());
+        }
+        if !self.seen_receipts.write().insert(hash_struct(&receipt)) {
+            return Ok(());
+        }
+        for target in self.receipt_targets(&receipt, Some(peer)) {
+            self.send_message(target, Message::Receipt(Box::new(receipt.clone())));
+        }
+        let tracked_shards = self.tracked_shards();
+        if !receipt_shards(&receipt).iter().any(|shard| tracked_shards.contains(shard)) {
+            return Ok(());
+        }
         let copied_tx = self.receipt_sender.clone();
         tokio::spawn(
             copied_tx
                 .send(receipt)
                 .map(|_| ())
-                .map_err(|e| error!("Failure to send the transactions {:?}", e)),
+                .map_err(|e| error!("Failure to send the receipts {:?}", e)),
         );
+        Ok(())
     }
 
     pub fn on_gossip_message(&self, gossip: Gossip<ChainPayload>) {
@@ -156,31 +502,356 @@ impl Protocol {
         status: &Status,
     ) -> Result<(), (NodeIndex, Severity)> {
         debug!(target: "network", "Status message received from {:?}: {:?}", peer, status);
-        if status.version != CURRENT_VERSION {
-            return Err((peer, Severity::Bad("Peer uses incompatible version.")));
-        }
+        let node_id = match self.sessions.read().get(&peer).and_then(NoiseSession::remote_node_id) {
+            Some(node_id) => node_id,
+            None => return Err((peer, Severity::Bad("Peer sent status before the handshake."))),
+        };
         if status.genesis_hash != self.client.beacon_chain.chain.genesis_hash {
             return Err((peer, Severity::Bad("Peer has different genesis hash.")));
         }
-
-        // request blocks to catch up if necessary
-        let best_index = self.client.beacon_chain.chain.best_index();
-        if status.best_index > best_index {
-            unimplemented!("Block catch-up is not implemented, yet.");
-        }
+        let version = match negotiate_version(status.min_version, status.version) {
+            Some(version) => version,
+            None => {
+                info!(
+                    target: "network",
+                    "Peer {:?} running {} uses incompatible version {}",
+                    peer,
+                    status.node_version,
+                    status.version,
+                );
+                return Err((peer, Severity::Useless("Peer uses incompatible version.")));
+            }
+        };
+        let features = status
+            .features
+            .iter()
+            .filter(|feature| self.config.features.contains(feature))
+            .cloned()
+            .collect();
+        let account_id =
+            status.account.as_ref().and_then(|claim| self.verified_account(claim, &node_id));
 
         let peer_info = PeerInfo {
-            protocol_version: status.version,
+            protocol_version: version,
+            node_version: status.node_version.clone(),
+            features,
             best_hash: status.best_hash,
             best_index: status.best_index,
-            account_id: status.account_id.clone(),
+            account_id: account_id.clone(),
+            tracked_shards: status.tracked_shards.clone(),
+            compression: compression::negotiate(&status.compression),
+            node_id,
         };
-        if let Some(account_id) = status.account_id.clone() {
-            println!("Recording account_id, peer: {}, {}", account_id, peer);
+        if let Some(account_id) = account_id.clone() {
+            debug!(target: "network", "Recording account_id, peer: {}, {:?}", account_id, peer);
             self.peer_account_info.write().insert(account_id, peer);
         }
         self.peer_info.write().insert(peer, peer_info);
+        self.client.network_info.write().peers.insert(
+            peer,
+            ConnectedPeer {
+                account_id,
+                protocol_version: version,
+                node_version: status.node_version.clone(),
+                best_index: status.best_index,
+                best_hash: status.best_hash,
+            },
+        );
         self.handshaking_peers.write().remove(&peer);
+        if self.peer_has_feature(peer, FEATURE_EXTERNAL_ADDR) {
+            if let Some(peer_ip) = self.peer_ips.read().get(&peer).cloned() {
+                self.send_message(peer, Message::ObservedIp(peer_ip));
+            }
+        }
+        // request blocks to catch up if necessary
+        self.add_sync_peer(peer, status.best_index);
+        self.send_sync_requests();
+        Ok(())
+    }
+
+    /// Account of the peer, if it's one of the authorities and its BLS key signed the node id the
+    /// peer authenticated the connection with.
+    fn verified_account(&self, claim: &AccountClaim, node_id: &NodeId) -> Option<AccountId> {
+        let authorities = self.client.get_recent_uid_to_authority_map();
+        let authority = authorities.values().find(|a| a.account_id == claim.account_id)?;
+        if authority.bls_public_key.verify(&node_id_message(node_id), &claim.signature) {
+            Some(claim.account_id.clone())
+        } else {
+            warn!(target: "network", "Invalid signature of the node id by {}", claim.account_id);
+            None
+        }
+    }
+
+    /// Whether both the node and the peer have the feature enabled.
+    fn peer_has_feature(&self, peer: NodeIndex, feature: &str) -> bool {
+        self.peer_info.read().get(&peer).map_or(false, |info| info.features.contains(feature))
+    }
+
+    fn add_sync_peer(&self, peer: NodeIndex, best_index: u64) {
+        self.sync.write().add_peer(peer, best_index);
+        if self.peer_has_feature(peer, FEATURE_EPOCH_SYNC) {
+            if let Some(epoch_sync) = self.epoch_sync.write().as_mut() {
+                epoch_sync.add_peer(peer, best_index);
+            }
+        }
+        if self.peer_has_feature(peer, FEATURE_STATE_SYNC) {
+            if let Some(state_sync) = self.state_sync.write().as_mut() {
+                state_sync.add_peer(peer, best_index);
+            }
+        }
+    }
+
+    /// Epoch sync from the epoch of the block after the best one, whose authorities the node
+    /// knows, if the headers are more than two epochs behind the peers that serve the proofs.
+    fn new_epoch_sync(&self, sync: &BlockSync, best_index: u64) -> Option<EpochSync> {
+        let header_index = sync.progress()?.header_index;
+        let peers: Vec<(NodeIndex, u64)> = self
+            .peer_info
+            .read()
+            .iter()
+            .filter(|(_, peer_info)| peer_info.features.contains(FEATURE_EPOCH_SYNC))
+            .map(|(peer, peer_info)| (*peer, peer_info.best_index))
+            .collect();
+        let target_index = peers.iter().map(|(_, index)| *index).max()?;
+        let authority = self.client.beacon_chain.authority.read();
+        let epoch_length = authority.epoch_length();
+        if target_index < header_index + 2 * epoch_length {
+            return None;
+        }
+        let epoch = best_index / epoch_length;
+        let authorities = authority.get_epoch_authorities(epoch)?;
+        let mut epoch_sync = EpochSync::new(epoch, epoch_length, authorities);
+        for (peer, best_index) in peers {
+            epoch_sync.add_peer(peer, best_index);
+        }
+        info!(target: "network", "Syncing the epochs from epoch {}", epoch);
+        Some(epoch_sync)
+    }
+
+    /// State sync for the last epoch boundary the headers are downloaded for, if the node is
+    /// more than an epoch behind it.
+    fn new_state_sync(&self, sync: &BlockSync, best_index: u64) -> Option<StateSync> {
+        let epoch_length = self.client.beacon_chain.authority.read().epoch_length();
+        let header_index = sync.progress()?.header_index;
+        let index = header_index / epoch_length * epoch_length;
+        if index <= best_index + epoch_length {
+            return None;
+        }
+        let header = sync.header(index)?;
+        let shard_id = self.client.shard_chain.chain.best_block().shard_id();
+        let mut state_sync = StateSync::new(shard_id, index, header.body.shard_block_hash);
+        for (peer, peer_info) in self.peer_info.read().iter() {
+            if peer_info.features.contains(FEATURE_STATE_SYNC) {
+                state_sync.add_peer(*peer, peer_info.best_index);
+            }
+        }
+        info!(target: "network", "Syncing the state of shard {} at block {}", shard_id, index);
+        Some(state_sync)
+    }
+
+    /// Sends the sync requests that are due and records the progress of the sync.
+    fn send_sync_requests(&self) {
+        let best_block = self.client.beacon_chain.chain.best_block();
+        let (requests, progress) = {
+            let mut sync = self.sync.write();
+            let now = Instant::now();
+            sync.on_best_block(best_block.index(), best_block.block_hash(), now);
+            let mut epoch_sync = self.epoch_sync.write();
+            if epoch_sync.is_none() {
+                *epoch_sync = self.new_epoch_sync(&sync, best_block.index());
+            }
+            let epoch_requests =
+                epoch_sync.as_mut().filter(|e| !e.is_done()).map(|e| e.next_requests(now));
+            let mut requests = match epoch_requests {
+                Some(requests) => requests,
+                None => {
+                    // The header sync continues from the last epoch the peers have the proof of.
+                    let last_header = epoch_sync.as_mut().and_then(EpochSync::take_last_header);
+                    if let Some(header) = last_header {
+                        info!(target: "network", "Synced the epochs up to block {}", header.index());
+                        sync.skip_to(header);
+                    }
+                    let mut requests = sync.next_requests(now);
+                    requests.extend(sync.request_parents(self.client.missing_parents(), now));
+                    requests
+                }
+            };
+            let mut state_sync = self.state_sync.write();
+            if state_sync.is_none() {
+                *state_sync = self.new_state_sync(&sync, best_block.index());
+            }
+            if let Some(state_sync) = state_sync.as_mut() {
+                requests.extend(state_sync.next_requests(now));
+            }
+            (requests, sync.progress())
+        };
+        self.client.network_info.write().sync_progress = progress;
+        for (peer, message) in requests {
+            self.send_message(peer, message);
+        }
+    }
+
+    /// Called periodically to retry the sync requests that timed out.
+    pub fn on_sync_tick(&self) {
+        self.send_sync_requests();
+        for challenge in self.client.take_outgoing_challenges() {
+            self.send_challenge(&challenge, None);
+        }
+        if let Some(progress) = self.client.network_info.read().sync_progress.clone() {
+            info!(
+                target: "network",
+                "Syncing to block {}: downloaded headers up to {}, blocks up to {}",
+                progress.target_index,
+                progress.header_index,
+                progress.block_index,
+            );
+        }
+    }
+
+    fn on_headers_request(&self, peer: NodeIndex, request: HeadersRequest) {
+        let max = std::cmp::min(request.max, MAX_HEADERS_PER_REQUEST);
+        let headers = (request.from_index..request.from_index + max)
+            .map(|index| self.client.beacon_chain.chain.get_block(&BlockId::Number(index)))
+            .take_while(Option::is_some)
+            .filter_map(|block| block.map(|block| block.header()))
+            .collect();
+        let response = HeadersResponse { id: request.id, headers };
+        self.send_message(peer, Message::HeadersResponse(response));
+    }
+
+    fn on_epoch_proofs_request(&self, peer: NodeIndex, request: EpochProofsRequest) {
+        if !self.config.features.iter().any(|f| f == FEATURE_EPOCH_SYNC) {
+            return;
+        }
+        let max = std::cmp::min(request.max, MAX_PROOFS_PER_REQUEST);
+        let proofs = (request.from_epoch..request.from_epoch + max)
+            .map(|epoch| self.client.beacon_chain.get_epoch_proof(epoch))
+            .take_while(Option::is_some)
+            .flatten()
+            .collect();
+        let response = EpochProofsResponse { id: request.id, proofs };
+        self.send_message(peer, Message::EpochProofsResponse(Box::new(response)));
+    }
+
+    fn on_epoch_proofs_response(
+        &self,
+        peer: NodeIndex,
+        response: EpochProofsResponse,
+    ) -> Result<(), (NodeIndex, Severity)> {
+        let result = match self.epoch_sync.write().as_mut() {
+            Some(epoch_sync) => epoch_sync.on_proofs(peer, response),
+            None => return Ok(()),
+        };
+        if let Err(e) = result {
+            warn!(target: "network", "Invalid epoch proofs from {:?}: {}", peer, e);
+            return Err((peer, Severity::Bad("Peer sent invalid epoch proofs.")));
+        }
+        self.send_sync_requests();
+        Ok(())
+    }
+
+    fn on_blocks_request(&self, peer: NodeIndex, request: BlocksRequest) {
+        let blocks = request
+            .hashes
+            .iter()
+            .take(MAX_BLOCKS_PER_REQUEST)
+            .filter_map(|hash| {
+                let beacon_block = self.client.beacon_chain.chain.get_block(&BlockId::Hash(*hash))?;
+                let shard_hash = beacon_block.body.header.shard_block_hash;
+                let shard_block =
+                    self.client.shard_chain.chain.get_block(&BlockId::Hash(shard_hash))?;
+                Some((beacon_block, shard_block))
+            })
+            .collect();
+        let response = BlocksResponse { id: request.id, blocks };
+        self.send_message(peer, Message::BlocksResponse(Box::new(response)));
+    }
+
+    fn on_headers_response(
+        &self,
+        peer: NodeIndex,
+        response: HeadersResponse,
+    ) -> Result<(), (NodeIndex, Severity)> {
+        let result = self.sync.write().on_headers(peer, response);
+        if let Err(e) = result {
+            warn!(target: "network", "Invalid headers from {:?}: {}", peer, e);
+            return Err((peer, Severity::Bad("Peer sent invalid headers.")));
+        }
+        self.send_sync_requests();
+        Ok(())
+    }
+
+    fn on_blocks_response(
+        &self,
+        peer: NodeIndex,
+        response: BlocksResponse,
+    ) -> Result<(), (NodeIndex, Severity)> {
+        let result = self.sync.write().on_blocks(peer, response);
+        match result {
+            Ok(blocks) => blocks.into_iter().for_each(|blocks| self.on_incoming_blocks(blocks)),
+            Err(e) => {
+                warn!(target: "network", "Invalid blocks from {:?}: {}", peer, e);
+                return Err((peer, Severity::Bad("Peer sent invalid blocks.")));
+            }
+        }
+        self.send_sync_requests();
+        Ok(())
+    }
+
+    fn on_state_part_request(&self, peer: NodeIndex, request: StatePartRequest) {
+        if !self.config.features.iter().any(|f| f == FEATURE_STATE_SYNC) {
+            return;
+        }
+        let part = self
+            .client
+            .shard_chain
+            .chain
+            .get_block(&BlockId::Hash(request.block_hash))
+            .filter(|block| block.shard_id() == request.shard_id)
+            .and_then(|block| {
+                let root = block.merkle_root_state();
+                let state_db = &self.client.shard_chain.state_db;
+                // The state of old blocks may be pruned already.
+                let part = state_db.get_state_part(&root, request.part_id, request.num_parts).ok()?;
+                Some((block.header(), part))
+            });
+        let response = StatePartResponse { id: request.id, part };
+        self.send_message(peer, Message::StatePartResponse(Box::new(response)));
+    }
+
+    fn on_state_part_response(
+        &self,
+        peer: NodeIndex,
+        response: StatePartResponse,
+    ) -> Result<(), (NodeIndex, Severity)> {
+        let mut state_sync = self.state_sync.write();
+        let state_sync = match state_sync.as_mut() {
+            Some(state_sync) => state_sync,
+            None => return Ok(()),
+        };
+        match state_sync.on_part(peer, response) {
+            Ok(Some((part_id, root, part))) => {
+                let state_db = &self.client.shard_chain.state_db;
+                if let Err(e) = state_db.apply_state_part(&root, part_id, NUM_STATE_PARTS, &part) {
+                    error!(target: "network", "Failed to write state part {}: {}", part_id, e);
+                }
+                if state_sync.is_done() {
+                    // TODO: Continue the block sync from the block instead of replaying the
+                    // blocks before it, which needs the chain to accept it without its parent.
+                    info!(
+                        target: "network",
+                        "Downloaded the state at block {} {:?}",
+                        state_sync.index(),
+                        state_sync.block_hash(),
+                    );
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                warn!(target: "network", "Invalid state part from {:?}: {}", peer, e);
+                return Err((peer, Severity::Bad("Peer sent invalid state part.")));
+            }
+        }
         Ok(())
     }
 
@@ -195,34 +866,318 @@ impl Protocol {
     }
 
     pub fn on_outgoing_blocks(&self, blocks: (SignedBeaconBlock, SignedShardBlock)) {
-        let peers = self.peer_info.read();
-        for peer in peers.keys() {
-            let message = Message::BlockAnnounce(Box::new((blocks.0.clone(), blocks.1.clone())));
-            self.send_message(*peer, message);
+        {
+            let peers = self.peer_info.read();
+            for peer in peers.keys() {
+                let message =
+                    Message::BlockAnnounce(Box::new((blocks.0.clone(), blocks.1.clone())));
+                self.send_message(*peer, message);
+            }
+        }
+        self.send_block_parts(&blocks.1);
+    }
+
+    /// Sends the parts of the produced shard block to the authorities of their seats.
+    fn send_block_parts(&self, shard_block: &SignedShardBlock) {
+        for (account_id, part) in self.client.distribute_parts(shard_block) {
+            let peer = match self.get_node_by_account_id(&account_id) {
+                Some(peer) => peer,
+                None => continue,
+            };
+            let has_feature = self
+                .peer_info
+                .read()
+                .get(&peer)
+                .map_or(false, |info| info.features.contains(FEATURE_BLOCK_PARTS));
+            if has_feature {
+                self.send_message(peer, Message::BlockPart(Box::new(part)));
+            }
+        }
+    }
+
+    /// Asks the peers for the parts of the shard block they keep, to reconstruct the block.
+    pub fn request_block_parts(&self, block_hash: CryptoHash) {
+        let peers: Vec<NodeIndex> = self
+            .peer_info
+            .read()
+            .iter()
+            .filter(|(_, info)| info.features.contains(FEATURE_BLOCK_PARTS))
+            .map(|(peer, _)| *peer)
+            .collect();
+        for peer in peers {
+            let request = BlockPartsRequest { block_hash };
+            self.send_message(peer, Message::BlockPartsRequest(request));
+        }
+    }
+
+    fn on_block_part(&self, peer: NodeIndex, part: ShardBlockPart) {
+        if !self.client.add_block_part(part) {
+            debug!(target: "network", "Ignoring invalid or known block part from {:?}", peer);
+        }
+    }
+
+    fn on_block_parts_request(&self, peer: NodeIndex, request: BlockPartsRequest) {
+        if !self.config.features.iter().any(|f| f == FEATURE_BLOCK_PARTS) {
+            return;
+        }
+        let parts = self.client.get_block_parts(&request.block_hash);
+        let response = BlockPartsResponse { parts };
+        self.send_message(peer, Message::BlockPartsResponse(Box::new(response)));
+    }
+
+    fn on_block_parts_response(
+        &self,
+        peer: NodeIndex,
+        response: BlockPartsResponse,
+    ) -> Result<(), (NodeIndex, Severity)> {
+        let mut hashes = HashSet::new();
+        for part in response.parts {
+            hashes.insert(part.header.hash);
+            if !part.verify() {
+                return Err((peer, Severity::Bad("Peer sent invalid block parts.")));
+            }
+            self.client.add_block_part(part);
+        }
+        for hash in hashes {
+            if self.client.reconstruct_shard_block(&hash).is_some() {
+                info!(target: "network", "Reconstructed shard block {:?} from its parts", hash);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the endorsement to the peers, except the one it came from.
+    fn send_endorsement(&self, endorsement: &Endorsement, sender: Option<NodeIndex>) {
+        let peers: Vec<NodeIndex> = self
+            .peer_info
+            .read()
+            .iter()
+            .filter(|(peer, info)| {
+                Some(**peer) != sender && info.features.contains(FEATURE_ENDORSEMENTS)
+            })
+            .map(|(peer, _)| *peer)
+            .collect();
+        for peer in peers {
+            self.send_message(peer, Message::Endorsement(Box::new(endorsement.clone())));
+        }
+    }
+
+    pub fn on_outgoing_endorsement(&self, endorsement: Endorsement) {
+        self.send_endorsement(&endorsement, None);
+    }
+
+    fn on_endorsement(&self, peer: NodeIndex, endorsement: Endorsement) {
+        if self.client.add_endorsement(endorsement.clone()) {
+            self.send_endorsement(&endorsement, Some(peer));
+        }
+    }
+
+    /// Sends the challenge to the peers, except the one it came from.
+    fn send_challenge(&self, challenge: &Challenge, sender: Option<NodeIndex>) {
+        let peers: Vec<NodeIndex> = self
+            .peer_info
+            .read()
+            .iter()
+            .filter(|(peer, info)| {
+                Some(**peer) != sender && info.features.contains(FEATURE_CHALLENGES)
+            })
+            .map(|(peer, _)| *peer)
+            .collect();
+        for peer in peers {
+            self.send_message(peer, Message::Challenge(Box::new(challenge.clone())));
+        }
+    }
+
+    fn on_challenge(&self, peer: NodeIndex, challenge: Challenge) {
+        if self.client.add_challenge(challenge.clone()) {
+            self.send_challenge(&challenge, Some(peer));
         }
     }
 
     pub fn on_message(&self, peer: NodeIndex, data: &[u8]) -> Result<(), (NodeIndex, Severity)> {
-        let message: Message =
-            Decode::decode(data).map_err(|_| (peer, Severity::Bad("Cannot decode message.")))?;
+        let decrypted = match self.sessions.write().get_mut(&peer) {
+            Some(session) if session.remote_node_id().is_some() => {
+                Some(session.decrypt(data, compression::MAX_PAYLOAD_SIZE).map_err(|e| {
+                    warn!(target: "network", "Invalid encrypted payload from {:?}: {}", peer, e);
+                    (peer, Severity::Bad("Cannot decrypt message."))
+                })?)
+            }
+            _ => None,
+        };
+        let encrypted = decrypted.is_some();
+        let payload = decrypted.as_ref().map_or(data, Vec::as_slice);
+        let data = compression::decompress(payload).map_err(|e| {
+            warn!(target: "network", "Invalid payload from {:?}: {}", peer, e);
+            (peer, Severity::Bad("Cannot decompress message."))
+        })?;
+        let message = Message::decode_limited(&data, &MESSAGE_LIMITS).map_err(|e| {
+            warn!(target: "network", "Invalid message from {:?}: {}", peer, e);
+            (peer, Severity::Bad("Cannot decode message."))
+        })?;
 
         debug!(target: "network", "message received: {:?}", message);
+        // Only the handshake goes unencrypted.
+        match (&message, encrypted) {
+            (Message::Handshake(message), false) => return self.on_handshake(peer, message),
+            (Message::Handshake(_), true) => {
+                return Err((peer, Severity::Bad("Unexpected handshake.")));
+            }
+            (_, false) => {
+                return Err((peer, Severity::Bad("Peer sent a message before the handshake.")));
+            }
+            (_, true) => {}
+        }
+
+        if let Some(kind) = MessageKind::of(&message) {
+            match self.rate_limiter.write().check(peer, kind, Instant::now()) {
+                Verdict::Accept => {}
+                Verdict::Drop => {
+                    debug!(target: "network", "Dropping {:?} from {:?} over the limit", kind, peer);
+                    return Ok(());
+                }
+                Verdict::Ban => return Err((peer, Severity::Bad("Peer exceeds the rate limits."))),
+            }
+        }
 
         match message {
+            Message::Transaction(transaction) => {
+                self.on_transaction(peer, *transaction);
+            }
             Message::Receipt(receipt) => {
-                self.on_receipt(*receipt);
+                self.on_receipt(peer, *receipt)?;
             }
             Message::Status(status) => {
                 self.on_status_message(peer, &status)?;
             }
             Message::BlockAnnounce(blocks) => {
+                self.add_sync_peer(peer, blocks.0.index());
                 self.on_incoming_blocks(*blocks);
             }
             Message::Gossip(gossip) => self.on_gossip_message(*gossip),
+            Message::HeadersRequest(request) => self.on_headers_request(peer, request),
+            Message::HeadersResponse(response) => self.on_headers_response(peer, response)?,
+            Message::BlocksRequest(request) => self.on_blocks_request(peer, request),
+            Message::BlocksResponse(response) => self.on_blocks_response(peer, *response)?,
+            Message::StatePartRequest(request) => self.on_state_part_request(peer, request),
+            Message::StatePartResponse(response) => {
+                self.on_state_part_response(peer, *response)?
+            }
+            Message::Endorsement(endorsement) => self.on_endorsement(peer, *endorsement),
+            Message::Challenge(challenge) => self.on_challenge(peer, *challenge),
+            Message::BlockPart(part) => self.on_block_part(peer, *part),
+            Message::BlockPartsRequest(request) => self.on_block_parts_request(peer, request),
+            Message::BlockPartsResponse(response) => {
+                self.on_block_parts_response(peer, *response)?
+            }
+            Message::EpochProofsRequest(request) => self.on_epoch_proofs_request(peer, request),
+            Message::EpochProofsResponse(response) => {
+                self.on_epoch_proofs_response(peer, *response)?
+            }
+            Message::PeersRequest => self.on_peers_request(peer),
+            Message::PeersResponse(response) => self.on_peers_response(peer, response)?,
+            Message::ObservedIp(ip) => self.on_observed_ip(peer, ip),
+            Message::Handshake(_) => unreachable!("handshake messages are handled above"),
         }
         Ok(())
     }
 
+    /// Records the addresses the node advertises, asks a random peer for the peers it knows and
+    /// saves the peer store. Returns the addresses of the stored peers to dial to keep the target
+    /// number of connections.
+    pub fn on_discovery_tick(
+        &self,
+        local_peer_id: &PeerId,
+        local_addrs: Vec<String>,
+    ) -> Vec<String> {
+        let local_peer_id = local_peer_id.to_base58();
+        *self.local_peer_id.write() = Some(local_peer_id.clone());
+        *self.local_addrs.write() = local_addrs;
+        let mut rng = thread_rng();
+        let peers: Vec<NodeIndex> = self
+            .peer_info
+            .read()
+            .iter()
+            .filter(|(_, info)| info.features.contains(FEATURE_PEER_EXCHANGE))
+            .map(|(peer, _)| *peer)
+            .collect();
+        if let Some(peer) = rng.choose(&peers).cloned() {
+            self.peers_requested.write().insert(peer);
+            self.send_message(peer, Message::PeersRequest);
+        }
+        if let Err(e) = self.peer_store.write().save() {
+            warn!(target: "network", "Cannot save the peer store: {}", e);
+        }
+        let connected: HashSet<String> = self.peer_ids.read().values().cloned().collect();
+        let missing = self.config.target_peers.saturating_sub(connected.len());
+        self.peer_store.read().sample(&mut rng, missing, |peer_id| {
+            peer_id != local_peer_id && !connected.contains(peer_id)
+        })
+    }
+
+    fn on_peers_request(&self, peer: NodeIndex) {
+        let requester = self.peer_ids.read().get(&peer).cloned();
+        let mut peers = self.local_addrs.read().clone();
+        let amount = PEERS_RESPONSE_SIZE.saturating_sub(peers.len());
+        peers.extend(self.peer_store.read().sample(&mut thread_rng(), amount, |peer_id| {
+            requester.as_ref().map(String::as_str) != Some(peer_id)
+        }));
+        self.send_message(peer, Message::PeersResponse(PeersResponse { peers }));
+    }
+
+    fn on_peers_response(
+        &self,
+        peer: NodeIndex,
+        response: PeersResponse,
+    ) -> Result<(), (NodeIndex, Severity)> {
+        if !self.peers_requested.write().remove(&peer) {
+            debug!(target: "network", "Dropping unrequested peers from {:?}", peer);
+            return Ok(());
+        }
+        if response.peers.len() > PEERS_RESPONSE_SIZE {
+            return Err((peer, Severity::Bad("Peer sent too many peers.")));
+        }
+        let local_suffix = self.local_peer_id.read().as_ref().map(|id| format!("/p2p/{}", id));
+        let mut peer_store = self.peer_store.write();
+        for addr in response.peers {
+            if local_suffix.as_ref().map_or(false, |suffix| addr.ends_with(suffix.as_str())) {
+                continue;
+            }
+            if !peer_store.add(&addr) {
+                debug!(target: "network", "Skipping peer address {} from {:?}", addr, peer);
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the ip the peer sees the node at, which counts once per peer.
+    fn on_observed_ip(&self, peer: NodeIndex, ip: IpAddr) {
+        let node_id = self.sessions.read().get(&peer).and_then(NoiseSession::remote_node_id);
+        let peer_ip = self.peer_ips.read().get(&peer).cloned();
+        if let (Some(node_id), Some(peer_ip)) = (node_id, peer_ip) {
+            self.external_addr.write().observe(node_id, peer_ip, ip);
+        }
+    }
+
+    /// Encodes the message for the wire, compressed as negotiated with the peer and encrypted,
+    /// except for the messages of the handshake. The messages to the peers the handshake isn't
+    /// done with are dropped.
+    pub fn encode_message(&self, peer: NodeIndex, message: &Message) -> Option<Vec<u8>> {
+        let data = Encode::encode(message).expect("Error encoding message.");
+        let compression =
+            self.peer_info.read().get(&peer).map_or(Compression::None, |info| info.compression);
+        let payload = compression::compress(&data, compression);
+        if let Message::Handshake(_) = message {
+            return Some(payload);
+        }
+        match self.sessions.write().get_mut(&peer).map(|session| session.encrypt(&payload)) {
+            Some(Ok(frames)) => Some(frames),
+            _ => {
+                debug!(target: "network", "Dropping message to {:?} before the handshake", peer);
+                None
+            }
+        }
+    }
+
     pub fn send_message(&self, receiver_index: NodeIndex, message: Message) {
         let copied_tx = self.message_sender.clone();
         tokio::spawn(
@@ -233,6 +1188,18 @@ impl Protocol {
         );
     }
 
+    /// Sends the messages in order, unlike separate `send_message` calls.
+    fn send_messages(&self, receiver_index: NodeIndex, messages: Vec<Message>) {
+        let copied_tx = self.message_sender.clone();
+        let messages = messages.into_iter().map(move |message| (receiver_index, message));
+        tokio::spawn(
+            copied_tx
+                .send_all(stream::iter_ok::<_, SendError<_>>(messages))
+                .map(|_| ())
+                .map_err(|e| error!("Failure to send the messages {:?}", e)),
+        );
+    }
+
     pub fn get_node_index_by_uid(&self, uid: UID) -> Option<NodeIndex> {
         let auth_map = self.client.get_recent_uid_to_authority_map();
         auth_map
@@ -242,4 +1209,51 @@ impl Protocol {
             )
             .and_then(|account_id| self.peer_account_info.read().get(&account_id).cloned())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{create_secret, get_test_protocol, raw_key_to_peer_id_str};
+
+    #[test]
+    fn test_negotiate_version() {
+        let current = Some(CURRENT_VERSION);
+        assert_eq!(negotiate_version(MIN_SUPPORTED_VERSION, CURRENT_VERSION), current);
+        // Newer peers talk to the node in its version if they still support it.
+        assert_eq!(negotiate_version(CURRENT_VERSION, CURRENT_VERSION + 1), current);
+        assert_eq!(negotiate_version(CURRENT_VERSION + 1, CURRENT_VERSION + 1), None);
+        assert_eq!(negotiate_version(0, MIN_SUPPORTED_VERSION - 1), None);
+    }
+
+    #[test]
+    fn test_verified_account() {
+        let protocol = get_test_protocol();
+        let node_id = NodeKey::generate().node_id();
+        let signature = protocol.client.signer.sign_node_id(&node_id).unwrap();
+        let claim = AccountClaim { account_id: "alice.near".to_string(), signature };
+        assert_eq!(protocol.verified_account(&claim, &node_id), Some("alice.near".to_string()));
+        // The signature doesn't carry over to another node.
+        assert_eq!(protocol.verified_account(&claim, &NodeKey::generate().node_id()), None);
+        // Only the accounts of the authorities are recorded.
+        let claim = AccountClaim { account_id: "bob.near".to_string(), ..claim };
+        assert_eq!(protocol.verified_account(&claim, &node_id), None);
+    }
+
+    #[test]
+    fn test_peers_response() {
+        let protocol = get_test_protocol();
+        let peer_id = raw_key_to_peer_id_str(create_secret());
+        let addr = format!("/ip4/1.2.3.4/tcp/30333/p2p/{}", peer_id);
+        let response = || PeersResponse { peers: vec![addr.clone()] };
+        // Only the peers asked for the peers they know are listened to.
+        assert!(protocol.on_peers_response(0, response()).is_ok());
+        assert_eq!(protocol.peer_store.read().addrs().count(), 0);
+        protocol.peers_requested.write().insert(0);
+        assert!(protocol.on_peers_response(0, response()).is_ok());
+        let stored: Vec<String> = protocol.peer_store.read().addrs().cloned().collect();
+        assert_eq!(stored, vec![addr.clone()]);
+        protocol.peers_requested.write().insert(0);
+        let too_many = PeersResponse { peers: vec![addr.clone(); PEERS_RESPONSE_SIZE + 1] };
+        assert!(protocol.on_peers_response(0, too_many).is_err());
+    }
+}
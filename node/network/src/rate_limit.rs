@@ -0,0 +1,194 @@
+//! Rate limits of the messages the peers send unprompted. Every peer has a token bucket for each
+//! kind of message, the messages it sends once its bucket is empty are dropped. Dropped messages
+//! count against the peer, and a peer that keeps flooding the node is banned.
+use std::collections::HashMap;
+use std::time::Instant;
+
+use substrate_network_libp2p::NodeIndex;
+
+use crate::message::Message;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKind {
+    Transaction,
+    Receipt,
+    BlockAnnounce,
+    Gossip,
+    /// Requests for headers and blocks.
+    SyncRequest,
+    StateRequest,
+    Endorsement,
+    Challenge,
+    BlockPart,
+    PeersRequest,
+}
+
+impl MessageKind {
+    /// Kind of the message, `None` for the messages that are not limited, e.g. the responses
+    /// to the requests of the node.
+    pub fn of(message: &Message) -> Option<Self> {
+        match message {
+            Message::Transaction(_) => Some(MessageKind::Transaction),
+            Message::Receipt(_) => Some(MessageKind::Receipt),
+            Message::BlockAnnounce(_) => Some(MessageKind::BlockAnnounce),
+            Message::Gossip(_) => Some(MessageKind::Gossip),
+            Message::HeadersRequest(_) | Message::BlocksRequest(_) => {
+                Some(MessageKind::SyncRequest)
+            }
+            Message::StatePartRequest(_) => Some(MessageKind::StateRequest),
+            Message::Endorsement(_) => Some(MessageKind::Endorsement),
+            Message::Challenge(_) => Some(MessageKind::Challenge),
+            Message::BlockPart(_) => Some(MessageKind::BlockPart),
+            Message::BlockPartsRequest(_) | Message::EpochProofsRequest(_) => {
+                Some(MessageKind::SyncRequest)
+            }
+            Message::PeersRequest => Some(MessageKind::PeersRequest),
+            Message::Status(_)
+            | Message::HeadersResponse(_)
+            | Message::BlocksResponse(_)
+            | Message::StatePartResponse(_)
+            | Message::BlockPartsResponse(_)
+            | Message::EpochProofsResponse(_)
+            | Message::PeersResponse(_)
+            | Message::Handshake(_)
+            | Message::ObservedIp(_) => None,
+        }
+    }
+}
+
+/// Number of messages a peer can send at once and how many are added per second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    pub burst: f64,
+    pub per_second: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimits {
+    pub limits: HashMap<MessageKind, Limit>,
+    /// Dropped messages a peer is forgiven before it is banned.
+    pub violations: Limit,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        let limit = |burst, per_second| Limit { burst, per_second };
+        let limits = vec![
+            (MessageKind::Transaction, limit(1000.0, 500.0)),
+            (MessageKind::Receipt, limit(100.0, 50.0)),
+            (MessageKind::BlockAnnounce, limit(10.0, 2.0)),
+            (MessageKind::Gossip, limit(1000.0, 500.0)),
+            (MessageKind::SyncRequest, limit(20.0, 5.0)),
+            (MessageKind::StateRequest, limit(32.0, 8.0)),
+            (MessageKind::Endorsement, limit(100.0, 50.0)),
+            // Challenges are large and verifying one applies a block.
+            (MessageKind::Challenge, limit(10.0, 1.0)),
+            (MessageKind::BlockPart, limit(100.0, 50.0)),
+            (MessageKind::PeersRequest, limit(3.0, 0.2)),
+        ];
+        RateLimits { limits: limits.into_iter().collect(), violations: limit(100.0, 1.0) }
+    }
+}
+
+struct TokenBucket {
+    limit: Limit,
+    tokens: f64,
+    last_update: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: Limit, now: Instant) -> Self {
+        TokenBucket { limit, tokens: limit.burst, last_update: now }
+    }
+
+    /// Takes a token if there is one left.
+    fn take(&mut self, now: Instant) -> bool {
+        if now > self.last_update {
+            let elapsed = now.duration_since(self.last_update);
+            let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+            self.tokens = (self.tokens + elapsed * self.limit.per_second).min(self.limit.burst);
+            self.last_update = now;
+        }
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+struct PeerBuckets {
+    messages: HashMap<MessageKind, TokenBucket>,
+    violations: TokenBucket,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Accept,
+    Drop,
+    /// The peer exceeded the limits too many times.
+    Ban,
+}
+
+pub struct RateLimiter {
+    limits: RateLimits,
+    peers: HashMap<NodeIndex, PeerBuckets>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        RateLimiter { limits, peers: HashMap::new() }
+    }
+
+    /// Decides what to do with the message of the given kind the peer sent.
+    pub fn check(&mut self, peer: NodeIndex, kind: MessageKind, now: Instant) -> Verdict {
+        let limit = match self.limits.limits.get(&kind) {
+            Some(limit) => *limit,
+            None => return Verdict::Accept,
+        };
+        let violations = self.limits.violations;
+        let buckets = self.peers.entry(peer).or_insert_with(|| PeerBuckets {
+            messages: HashMap::new(),
+            violations: TokenBucket::new(violations, now),
+        });
+        let bucket = buckets.messages.entry(kind).or_insert_with(|| TokenBucket::new(limit, now));
+        if bucket.take(now) {
+            Verdict::Accept
+        } else if buckets.violations.take(now) {
+            Verdict::Drop
+        } else {
+            Verdict::Ban
+        }
+    }
+
+    pub fn remove_peer(&mut self, peer: NodeIndex) {
+        self.peers.remove(&peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limits = RateLimits::default();
+        limits.limits.insert(MessageKind::Transaction, Limit { burst: 2.0, per_second: 1.0 });
+        limits.violations = Limit { burst: 1.0, per_second: 0.1 };
+        let mut limiter = RateLimiter::new(limits);
+        let now = Instant::now();
+        let kind = MessageKind::Transaction;
+        assert_eq!(limiter.check(0, kind, now), Verdict::Accept);
+        assert_eq!(limiter.check(0, kind, now), Verdict::Accept);
+        assert_eq!(limiter.check(0, kind, now), Verdict::Drop);
+        // Other peers and other kinds of messages have their own limits.
+        assert_eq!(limiter.check(1, kind, now), Verdict::Accept);
+        assert_eq!(limiter.check(0, MessageKind::Receipt, now), Verdict::Accept);
+        // The bucket refills over time.
+        let later = now + Duration::from_secs(1);
+        assert_eq!(limiter.check(0, kind, later), Verdict::Accept);
+        assert_eq!(limiter.check(0, kind, later), Verdict::Ban);
+    }
+}
@@ -1,27 +1,89 @@
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 
 use futures::sync::mpsc::Receiver;
 use futures::{stream, Future, Stream};
+use libp2p::core::ConnectedPoint;
+use libp2p::Multiaddr;
 use parking_lot::Mutex;
+use tokio::timer::Interval;
 pub use substrate_network_libp2p::NetworkConfiguration;
 use substrate_network_libp2p::{
-    start_service, NodeIndex, RegisteredProtocol, Service as NetworkService, ServiceEvent, Severity,
+    parse_str_addr, start_service, NodeIndex, Protocol as AddrProtocol, RegisteredProtocol,
+    Service as NetworkService, ServiceEvent, Severity,
 };
 
-use beacon::types::SignedBeaconBlock;
-use primitives::serialize::Encode;
+use beacon::types::{Endorsement, SignedBeaconBlock};
 use primitives::types::Gossip;
-use chain::{SignedShardBlock, ChainPayload};
+use chain::{SignedShardBlock, ChainPayload, ReceiptBlock};
+use transaction::SignedTransaction;
 
 use crate::message::Message;
+use crate::nat;
+use crate::peer_store::PeerStore;
 use crate::protocol::{self, Protocol, ProtocolConfig};
 
+/// How often, in milliseconds, the sync retries the requests the peers didn't answer.
+const SYNC_TICK_PERIOD_MS: u64 = 1000;
+/// How often, in seconds, the node asks a peer for the peers it knows and dials the known peers.
+const DISCOVERY_PERIOD_SECS: u64 = 10;
+
+/// Whether the peers can dial the address, i.e. it is not the wildcard or the loopback.
+fn is_routable(addr: &Multiaddr) -> bool {
+    match addr.iter().next() {
+        Some(AddrProtocol::Ip4(ip)) => !ip.is_unspecified() && !ip.is_loopback(),
+        Some(AddrProtocol::Ip6(ip)) => !ip.is_unspecified() && !ip.is_loopback(),
+        _ => false,
+    }
+}
+
+fn addr_ip(addr: &Multiaddr) -> Option<IpAddr> {
+    match addr.iter().next() {
+        Some(AddrProtocol::Ip4(ip)) => Some(IpAddr::V4(ip)),
+        Some(AddrProtocol::Ip6(ip)) => Some(IpAddr::V6(ip)),
+        _ => None,
+    }
+}
+
+/// Ip and port of the TCP address.
+fn socket_addr(addr: &Multiaddr) -> Option<SocketAddr> {
+    match addr.iter().nth(1) {
+        Some(AddrProtocol::Tcp(port)) => addr_ip(addr).map(|ip| SocketAddr::new(ip, port)),
+        _ => None,
+    }
+}
+
+fn to_multiaddr(addr: &SocketAddr) -> String {
+    match addr.ip() {
+        IpAddr::V4(ip) => format!("/ip4/{}/tcp/{}", ip, addr.port()),
+        IpAddr::V6(ip) => format!("/ip6/{}/tcp/{}", ip, addr.port()),
+    }
+}
+
+/// Ip the peer connects from, the one it is dialed at or the one it dialed the node from.
+fn remote_ip(endpoint: &ConnectedPoint) -> Option<IpAddr> {
+    match endpoint {
+        ConnectedPoint::Dialer { address } => addr_ip(address),
+        ConnectedPoint::Listener { send_back_addr, .. } => addr_ip(send_back_addr),
+    }
+}
+
+/// Starts the service, which also dials the peers known from the last run as boot nodes.
 pub fn new_network_service(
     protocol_config: &ProtocolConfig,
-    net_config: NetworkConfiguration,
+    mut net_config: NetworkConfiguration,
 ) -> NetworkService {
-    let version = [protocol::CURRENT_VERSION as u8];
-    let registered = RegisteredProtocol::new(protocol_config.protocol_id, &version);
+    let peer_store = PeerStore::load(protocol_config.peer_store_path.clone());
+    for addr in peer_store.addrs() {
+        if !net_config.boot_nodes.contains(addr) {
+            net_config.boot_nodes.push(addr.clone());
+        }
+    }
+    let versions: Vec<u8> = (protocol::MIN_SUPPORTED_VERSION..=protocol::CURRENT_VERSION)
+        .map(|version| version as u8)
+        .collect();
+    let registered = RegisteredProtocol::new(protocol_config.protocol_id, &versions);
     start_service(net_config, Some(registered)).expect("Error starting network service")
 }
 
@@ -31,6 +93,9 @@ pub fn spawn_network_tasks(
     message_receiver: Receiver<(NodeIndex, Message)>,
     outgoing_block_tx: Receiver<(SignedBeaconBlock, SignedShardBlock)>,
     gossip_rx: Receiver<Gossip<ChainPayload>>,
+    outgoing_transaction_rx: Receiver<SignedTransaction>,
+    outgoing_receipt_rx: Receiver<ReceiptBlock>,
+    outgoing_endorsement_rx: Receiver<Endorsement>,
 ) {
     let protocol = Arc::new(protocol_);
 
@@ -63,8 +128,10 @@ pub fn spawn_network_tasks(
                         }
                     }
                 }
-                ServiceEvent::OpenedCustomProtocol { node_index, .. } => {
-                    protocol1.on_peer_connected(node_index);
+                ServiceEvent::OpenedCustomProtocol { peer_id, node_index, .. } => {
+                    let peer_ip =
+                        network_service1.lock().node_endpoint(node_index).and_then(remote_ip);
+                    protocol1.on_peer_connected(node_index, &peer_id, peer_ip);
                 }
                 ServiceEvent::ClosedCustomProtocol { node_index, .. } => {
                     protocol1.on_peer_disconnected(node_index);
@@ -91,10 +158,14 @@ pub fn spawn_network_tasks(
     // Handles messages going into the network.
     let protocol_id = protocol.config.protocol_id;
     let network_service1 = network_service.clone();
+    let protocol6 = protocol.clone();
     let messages_handler = message_receiver
         .for_each(move |(node_index, m)| {
-            let data = Encode::encode(&m).expect("Error encoding message.");
-            network_service1.lock().send_custom_message(node_index, protocol_id, data);
+            // Encrypted under the lock, so that the messages are sent in the order of the nonces.
+            let mut network_service = network_service1.lock();
+            if let Some(data) = protocol6.encode_message(node_index, &m) {
+                network_service.send_custom_message(node_index, protocol_id, data);
+            }
             Ok(())
         })
         .map(|_| ())
@@ -106,18 +177,92 @@ pub fn spawn_network_tasks(
         Ok(())
     });
 
+    let protocol2 = protocol.clone();
+    let transaction_handler = outgoing_transaction_rx.for_each(move |transaction| {
+        protocol2.on_outgoing_transaction(transaction);
+        Ok(())
+    });
+
+    let protocol4 = protocol.clone();
+    let receipt_handler = outgoing_receipt_rx.for_each(move |receipt| {
+        protocol4.on_outgoing_receipts(receipt);
+        Ok(())
+    });
+
+    let protocol7 = protocol.clone();
+    let endorsement_handler = outgoing_endorsement_rx.for_each(move |endorsement| {
+        protocol7.on_outgoing_endorsement(endorsement);
+        Ok(())
+    });
+
+    let protocol5 = protocol.clone();
+    let sync_handler = Interval::new_interval(Duration::from_millis(SYNC_TICK_PERIOD_MS))
+        .map_err(|e| error!("Sync timer error: {}", e))
+        .for_each(move |_| {
+            protocol5.on_sync_tick();
+            Ok(())
+        });
+
+    let external_addr = protocol.external_addr();
+    if protocol.config.port_mapping {
+        let listen_addr = network_service.lock().listeners().filter_map(socket_addr).next();
+        match listen_addr {
+            Some(listen_addr) => nat::spawn_port_mapping(listen_addr, external_addr.clone()),
+            None => warn!(target: "network", "No TCP listening address to map the port of"),
+        }
+    }
+
+    let protocol8 = protocol.clone();
+    let network_service2 = network_service.clone();
+    let discovery_handler = Interval::new_interval(Duration::from_secs(DISCOVERY_PERIOD_SECS))
+        .map_err(|e| error!("Discovery timer error: {}", e))
+        .for_each(move |_| {
+            let mut network_service = network_service2.lock();
+            let local_peer_id = network_service.peer_id().clone();
+            let mut local_addrs: Vec<String> = network_service
+                .listeners()
+                .filter(|addr| is_routable(addr))
+                .map(|addr| format!("{}/p2p/{}", addr, local_peer_id.to_base58()))
+                .collect();
+            let external_addrs: Vec<SocketAddr> = network_service
+                .listeners()
+                .filter_map(socket_addr)
+                .filter_map(|addr| external_addr.read().advertised(addr))
+                .collect();
+            for addr in external_addrs {
+                let addr = format!("{}/p2p/{}", to_multiaddr(&addr), local_peer_id.to_base58());
+                if !local_addrs.contains(&addr) {
+                    local_addrs.push(addr);
+                }
+            }
+            for addr in protocol8.on_discovery_tick(&local_peer_id, local_addrs) {
+                match parse_str_addr(&addr) {
+                    Ok((peer_id, addr)) => network_service.add_reserved_peer(peer_id, addr),
+                    Err(e) => warn!(target: "network", "Cannot dial {}: {:?}", addr, e),
+                }
+            }
+            Ok(())
+        });
+
     tokio::spawn(network);
 
     tokio::spawn(messages_handler);
     tokio::spawn(block_announce_handler);
+    tokio::spawn(transaction_handler);
+    tokio::spawn(receipt_handler);
+    tokio::spawn(endorsement_handler);
+    tokio::spawn(sync_handler);
+    tokio::spawn(discovery_handler);
 
     let protocol3 = protocol.clone();
     let gossip_sender = gossip_rx.for_each(move |g| {
         //println!("About to send gossip {:?}", g);
         if let Some(node_index) = protocol3.get_node_index_by_uid(g.receiver_uid) {
             let m = Message::Gossip(Box::new(g));
-            let data = Encode::encode(&m).expect("Error encoding message.");
-            network_service.lock().send_custom_message(node_index, protocol_id, data);
+            let mut network_service = network_service.lock();
+            if let Some(data) = protocol3.encode_message(node_index, &m) {
+                network_service.send_custom_message(node_index, protocol_id, data);
+            }
         } else {
             error!("Node Index not found for UID: {}", g.receiver_uid);
         }
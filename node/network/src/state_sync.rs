@@ -0,0 +1,262 @@
+//! State synchronization. Instead of replaying the history from genesis, a node far behind its
+//! peers downloads the state of a shard at an epoch boundary. The state is split into parts
+//! requested from all the peers that have the block, each part is checked against the state root
+//! of the block on its own, and the checked parts are written into the local trie.
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use substrate_network_libp2p::NodeIndex;
+
+use primitives::hash::{hash_struct, CryptoHash};
+use primitives::types::{MerkleHash, ShardId};
+use storage::trie::Trie;
+use storage::StatePart;
+
+use crate::message::{Message, RequestId, StatePartRequest, StatePartResponse};
+
+/// Number of parts the state is split into.
+pub const NUM_STATE_PARTS: u64 = 16;
+/// Max number of part requests in flight to a peer.
+const MAX_REQUESTS_PER_PEER: usize = 2;
+/// How long, in milliseconds, a peer has to answer before the request goes to another peer.
+const REQUEST_TIMEOUT_MS: u64 = 10_000;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum StateSyncError {
+    /// Shard block header is not the one of the requested block.
+    UnexpectedHeader(CryptoHash),
+    /// Part doesn't match the state root of the block.
+    InvalidPart { part_id: u64, reason: String },
+}
+
+impl fmt::Display for StateSyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateSyncError::UnexpectedHeader(hash) => {
+                write!(f, "Shard block header {:?} was not requested", hash)
+            }
+            StateSyncError::InvalidPart { part_id, reason } => {
+                write!(f, "State part {} is invalid: {}", part_id, reason)
+            }
+        }
+    }
+}
+
+struct Request {
+    peer: NodeIndex,
+    sent: Instant,
+    part_id: u64,
+}
+
+pub struct StateSync {
+    shard_id: ShardId,
+    /// Index of the block, committed to by the validated beacon chain headers.
+    index: u64,
+    /// Hash of the shard block whose state is downloaded.
+    block_hash: CryptoHash,
+    /// Peers whose best block is at or after the block.
+    peers: HashSet<NodeIndex>,
+    /// Parts that are neither downloaded nor requested.
+    missing: BTreeSet<u64>,
+    requests: HashMap<RequestId, Request>,
+    next_request_id: RequestId,
+}
+
+impl StateSync {
+    pub fn new(shard_id: ShardId, index: u64, block_hash: CryptoHash) -> Self {
+        StateSync {
+            shard_id,
+            index,
+            block_hash,
+            peers: HashSet::new(),
+            missing: (0..NUM_STATE_PARTS).collect(),
+            requests: HashMap::new(),
+            next_request_id: 0,
+        }
+    }
+
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn block_hash(&self) -> CryptoHash {
+        self.block_hash
+    }
+
+    /// Records the peer if its best block is not before the block.
+    pub fn add_peer(&mut self, peer: NodeIndex, best_index: u64) {
+        if best_index >= self.index {
+            self.peers.insert(peer);
+        }
+    }
+
+    /// Forgets the peer, its requests in flight go to the other peers.
+    pub fn remove_peer(&mut self, peer: NodeIndex) {
+        self.peers.remove(&peer);
+        let ids: Vec<RequestId> =
+            self.requests.iter().filter(|(_, r)| r.peer == peer).map(|(id, _)| *id).collect();
+        for id in ids {
+            self.cancel(id);
+        }
+    }
+
+    /// Whether all the parts are downloaded.
+    pub fn is_done(&self) -> bool {
+        self.missing.is_empty() && self.requests.is_empty()
+    }
+
+    fn cancel(&mut self, id: RequestId) -> Option<Request> {
+        let request = self.requests.remove(&id)?;
+        self.missing.insert(request.part_id);
+        Some(request)
+    }
+
+    /// Requests to send to the peers. Drops the requests that timed out and spreads the
+    /// requests for the missing parts over the peers.
+    pub fn next_requests(&mut self, now: Instant) -> Vec<(NodeIndex, Message)> {
+        let timeout = Duration::from_millis(REQUEST_TIMEOUT_MS);
+        let expired: Vec<RequestId> = self
+            .requests
+            .iter()
+            .filter(|(_, r)| now > r.sent + timeout)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            self.cancel(id);
+        }
+
+        let mut load: HashMap<NodeIndex, usize> = self.peers.iter().map(|p| (*p, 0)).collect();
+        for request in self.requests.values() {
+            *load.entry(request.peer).or_insert(0) += 1;
+        }
+        let mut messages = vec![];
+        while let Some(part_id) = self.missing.iter().next().cloned() {
+            let peer = load
+                .iter()
+                .filter(|(_, requests)| **requests < MAX_REQUESTS_PER_PEER)
+                .min_by_key(|(_, requests)| **requests)
+                .map(|(peer, _)| *peer);
+            let peer = match peer {
+                Some(peer) => peer,
+                None => break,
+            };
+            *load.entry(peer).or_insert(0) += 1;
+            self.missing.remove(&part_id);
+            let id = self.next_request_id;
+            self.next_request_id += 1;
+            self.requests.insert(id, Request { peer, sent: now, part_id });
+            let request = StatePartRequest {
+                id,
+                shard_id: self.shard_id,
+                block_hash: self.block_hash,
+                part_id,
+                num_parts: NUM_STATE_PARTS,
+            };
+            messages.push((peer, Message::StatePartRequest(request)));
+        }
+        messages
+    }
+
+    /// Checks the part against the state root of the block and returns it with the root, so
+    /// that it can be written into the trie. A peer without the part is not asked again.
+    pub fn on_part(
+        &mut self,
+        peer: NodeIndex,
+        response: StatePartResponse,
+    ) -> Result<Option<(u64, MerkleHash, StatePart)>, StateSyncError> {
+        let part_id = match self.requests.get(&response.id) {
+            Some(r) if r.peer == peer => r.part_id,
+            // Responses to the requests that timed out are ignored.
+            _ => return Ok(None),
+        };
+        self.cancel(response.id);
+        let (header, part) = match response.part {
+            Some(part) => part,
+            None => {
+                self.peers.remove(&peer);
+                return Ok(None);
+            }
+        };
+        if header.hash != self.block_hash || hash_struct(&header.body) != header.hash {
+            return Err(StateSyncError::UnexpectedHeader(header.hash));
+        }
+        let root = header.body.merkle_root_state;
+        Trie::validate_state_part(&root, part_id, NUM_STATE_PARTS, &part)
+            .map_err(|reason| StateSyncError::InvalidPart { part_id, reason })?;
+        self.missing.remove(&part_id);
+        Ok(Some((part_id, root, part)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chain::{SignedBlock, SignedShardBlock};
+    use storage::test_utils::create_memory_db;
+    use storage::{KeyValueDB, StateDb};
+
+    use super::*;
+
+    fn part_requests(messages: Vec<(NodeIndex, Message)>) -> Vec<(NodeIndex, StatePartRequest)> {
+        messages
+            .into_iter()
+            .map(|(peer, message)| match message {
+                Message::StatePartRequest(request) => (peer, request),
+                message => panic!("Expected a state part request, got {:?}", message),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_state_sync() {
+        let db: Arc<KeyValueDB> = Arc::new(create_memory_db());
+        let trie = Trie::new(db.clone(), storage::COL_STATE);
+        let changes = (0..=255u8).map(|i| (vec![i, 1], Some(vec![i])));
+        let (db_changes, root) = trie.update(&Trie::empty_root(), changes).unwrap();
+        storage::trie::apply_changes(&db, storage::COL_STATE, db_changes).unwrap();
+        let block = SignedShardBlock::new(0, 10, CryptoHash::default(), root, root, vec![], vec![]);
+        let header = block.header();
+        let now = Instant::now();
+
+        let mut sync = StateSync::new(0, 10, block.hash);
+        sync.add_peer(1, 10);
+        sync.add_peer(2, 20);
+        sync.add_peer(3, 5);
+        let requests = part_requests(sync.next_requests(now));
+        assert_eq!(requests.len(), 2 * MAX_REQUESTS_PER_PEER);
+        assert!(requests.iter().all(|(peer, _)| *peer != 3));
+
+        let new_storage: Arc<KeyValueDB> = Arc::new(create_memory_db());
+        let new_state_db = StateDb::new(new_storage.clone());
+        let response = |request: &StatePartRequest, part_id| {
+            let part = trie.get_state_part(&root, part_id, NUM_STATE_PARTS).unwrap();
+            StatePartResponse { id: request.id, part: Some((header.clone(), part)) }
+        };
+        let mut requests = requests.into_iter();
+        let (peer, request) = requests.next().unwrap();
+        match sync.on_part(peer, response(&request, request.part_id + 1)) {
+            Err(StateSyncError::InvalidPart { part_id, .. }) => assert_eq!(part_id, 0),
+            result => panic!("Expected an invalid part, got {:?}", result),
+        }
+
+        // The rejected part is requested again.
+        let mut pending: Vec<_> = requests.collect();
+        let mut parts = 0;
+        while !sync.is_done() {
+            pending.extend(part_requests(sync.next_requests(now)));
+            let (peer, request) = pending.pop().unwrap();
+            let (part_id, part_root, part) =
+                sync.on_part(peer, response(&request, request.part_id)).unwrap().unwrap();
+            assert_eq!(part_root, root);
+            new_state_db.apply_state_part(&root, part_id, NUM_STATE_PARTS, &part).unwrap();
+            parts += 1;
+        }
+        assert_eq!(parts, NUM_STATE_PARTS);
+        let new_trie = Trie::new(new_storage, storage::COL_STATE);
+        for i in 0..=255u8 {
+            assert_eq!(new_trie.get(&root, &[i, 1]), Some(vec![i]));
+        }
+    }
+}
@@ -0,0 +1,553 @@
+//! Headers-first block synchronization. A node behind its peers first downloads the beacon block
+//! headers from the best peer and checks that they link up with its best block, then downloads
+//! the blocks the headers commit to in batches from all the peers that have them, and hands them
+//! over for import in order. Everything downloaded is kept if a peer disconnects, and the sync
+//! starts over from the best block if the handed over blocks are not imported.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use substrate_network_libp2p::NodeIndex;
+
+use beacon::types::{SignedBeaconBlock, SignedBeaconBlockHeader};
+use chain::{SignedBlock, SignedHeader, SignedShardBlock};
+use client::SyncProgress;
+use primitives::hash::{hash_struct, CryptoHash};
+
+use crate::message::{
+    BlocksRequest, BlocksResponse, HeadersRequest, HeadersResponse, Message, RequestId,
+};
+
+/// Max number of headers served in response to a request.
+pub const MAX_HEADERS_PER_REQUEST: u64 = 256;
+/// Max number of blocks served in response to a request.
+pub const MAX_BLOCKS_PER_REQUEST: usize = 16;
+/// Max number of block requests in flight to a peer.
+const MAX_REQUESTS_PER_PEER: usize = 2;
+/// How long, in milliseconds, a peer has to answer before the request goes to another peer.
+const REQUEST_TIMEOUT_MS: u64 = 10_000;
+/// How long, in milliseconds, the handed over blocks may stay not imported before the sync
+/// starts over.
+const STALL_TIMEOUT_MS: u64 = 60_000;
+
+type Blocks = (SignedBeaconBlock, SignedShardBlock);
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SyncError {
+    /// Hash of the header doesn't match its body.
+    InvalidHeaderHash(CryptoHash),
+    /// Header doesn't follow the previous header of the chain.
+    UnlinkedHeader { index: u64, parent_hash: CryptoHash },
+    /// Block doesn't match the requested header of its index.
+    UnexpectedBlock { index: u64, hash: CryptoHash },
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncError::InvalidHeaderHash(hash) => {
+                write!(f, "Hash {:?} doesn't match the header", hash)
+            }
+            SyncError::UnlinkedHeader { index, parent_hash } => write!(
+                f,
+                "Header {} with parent {:?} doesn't follow the downloaded headers",
+                index,
+                parent_hash,
+            ),
+            SyncError::UnexpectedBlock { index, hash } => {
+                write!(f, "Block {} with hash {:?} was not requested", index, hash)
+            }
+        }
+    }
+}
+
+struct Request {
+    peer: NodeIndex,
+    sent: Instant,
+    /// Indices of the requested blocks, empty for a headers request.
+    indices: Vec<u64>,
+    /// Hashes of the requested parents of the orphan blocks.
+    parents: Vec<CryptoHash>,
+}
+
+impl Request {
+    fn is_headers(&self) -> bool {
+        self.indices.is_empty() && self.parents.is_empty()
+    }
+}
+
+pub struct BlockSync {
+    /// Best block index announced by each peer.
+    peers: HashMap<NodeIndex, u64>,
+    /// Index and hash of the last header of the validated chain of headers.
+    last_header: (u64, CryptoHash),
+    /// Validated headers whose blocks are not handed over yet.
+    headers: BTreeMap<u64, SignedBeaconBlockHeader>,
+    /// Downloaded blocks waiting for the blocks before them.
+    blocks: BTreeMap<u64, Blocks>,
+    /// Indices of the blocks in the requests in flight.
+    requested: HashSet<u64>,
+    requests: HashMap<RequestId, Request>,
+    next_request_id: RequestId,
+    /// Index of the next block to hand over for import.
+    next_index: u64,
+    /// Best block index of the chain and when it last advanced.
+    best_index: u64,
+    last_progress: Instant,
+}
+
+impl BlockSync {
+    pub fn new(best_index: u64, best_hash: CryptoHash) -> Self {
+        BlockSync {
+            peers: HashMap::new(),
+            last_header: (best_index, best_hash),
+            headers: BTreeMap::new(),
+            blocks: BTreeMap::new(),
+            requested: HashSet::new(),
+            requests: HashMap::new(),
+            next_request_id: 0,
+            next_index: best_index + 1,
+            best_index,
+            last_progress: Instant::now(),
+        }
+    }
+
+    /// Records the best block index announced by the peer.
+    pub fn add_peer(&mut self, peer: NodeIndex, best_index: u64) {
+        let peer_best_index = self.peers.entry(peer).or_insert(best_index);
+        *peer_best_index = std::cmp::max(*peer_best_index, best_index);
+    }
+
+    /// Forgets the peer, its requests in flight go to the other peers.
+    pub fn remove_peer(&mut self, peer: NodeIndex) {
+        self.peers.remove(&peer);
+        let ids: Vec<RequestId> =
+            self.requests.iter().filter(|(_, r)| r.peer == peer).map(|(id, _)| *id).collect();
+        for id in ids {
+            self.cancel(id);
+        }
+    }
+
+    fn target_index(&self) -> u64 {
+        self.peers.values().cloned().max().unwrap_or(0)
+    }
+
+    pub fn is_syncing(&self) -> bool {
+        self.target_index() > self.best_index
+    }
+
+    pub fn progress(&self) -> Option<SyncProgress> {
+        if !self.is_syncing() {
+            return None;
+        }
+        Some(SyncProgress {
+            target_index: self.target_index(),
+            header_index: self.last_header.0,
+            block_index: self.next_index - 1,
+        })
+    }
+
+    /// Validated header of the given index whose block is not handed over yet.
+    pub fn header(&self, index: u64) -> Option<&SignedBeaconBlockHeader> {
+        self.headers.get(&index)
+    }
+
+    /// Catches up with the best block of the chain, whose blocks may also come from the block
+    /// announcements.
+    pub fn on_best_block(&mut self, best_index: u64, best_hash: CryptoHash, now: Instant) {
+        if best_index > self.best_index {
+            self.best_index = best_index;
+            self.last_progress = now;
+        } else if self.next_index > best_index + 1
+            && now > self.last_progress + Duration::from_millis(STALL_TIMEOUT_MS)
+        {
+            // The handed over blocks were not imported, e.g. because they are invalid.
+            warn!(target: "network", "Sync stalled at block {}, starting over", best_index);
+            let peers = std::mem::replace(&mut self.peers, HashMap::new());
+            let next_request_id = self.next_request_id;
+            *self = BlockSync { peers, next_request_id, ..BlockSync::new(best_index, best_hash) };
+            return;
+        }
+        if best_index >= self.next_index {
+            self.next_index = best_index + 1;
+            self.headers = self.headers.split_off(&self.next_index);
+            self.blocks = self.blocks.split_off(&self.next_index);
+        }
+        if best_index > self.last_header.0 {
+            self.last_header = (best_index, best_hash);
+        }
+    }
+
+    /// Continues the header sync from the header, which the epoch sync verified, instead of the
+    /// headers before it. Its block and the next ones wait for the blocks before them.
+    pub fn skip_to(&mut self, header: SignedBeaconBlockHeader) {
+        // TODO: Import the blocks from the header on once the state at it is downloaded.
+        if header.index() <= self.last_header.0 {
+            return;
+        }
+        self.last_header = (header.index(), header.hash);
+        self.headers.insert(header.index(), header);
+    }
+
+    fn add_request(&mut self, peer: NodeIndex, now: Instant, indices: Vec<u64>) -> RequestId {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        self.requested.extend(indices.iter().cloned());
+        self.requests.insert(id, Request { peer, sent: now, indices, parents: vec![] });
+        id
+    }
+
+    fn cancel(&mut self, id: RequestId) -> Option<Request> {
+        let request = self.requests.remove(&id)?;
+        for index in &request.indices {
+            self.requested.remove(index);
+        }
+        Some(request)
+    }
+
+    /// Requests to send to the peers. Drops the requests that timed out, asks the best peer
+    /// for the next headers and spreads the requests for the blocks over the peers.
+    pub fn next_requests(&mut self, now: Instant) -> Vec<(NodeIndex, Message)> {
+        let timeout = Duration::from_millis(REQUEST_TIMEOUT_MS);
+        let expired: Vec<RequestId> = self
+            .requests
+            .iter()
+            .filter(|(_, r)| now > r.sent + timeout)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            self.cancel(id);
+        }
+
+        let mut messages = vec![];
+        let headers_in_flight = self.requests.values().any(Request::is_headers);
+        let best_peer = self.peers.iter().max_by_key(|(_, index)| **index).map(|(p, i)| (*p, *i));
+        if let Some((peer, best_index)) = best_peer {
+            if !headers_in_flight && best_index > self.last_header.0 {
+                let from_index = self.last_header.0 + 1;
+                let id = self.add_request(peer, now, vec![]);
+                let request = HeadersRequest { id, from_index, max: MAX_HEADERS_PER_REQUEST };
+                messages.push((peer, Message::HeadersRequest(request)));
+            }
+        }
+
+        let mut load: HashMap<NodeIndex, usize> = HashMap::new();
+        for request in self.requests.values().filter(|r| !r.is_headers()) {
+            *load.entry(request.peer).or_insert(0) += 1;
+        }
+        let missing: Vec<u64> = self
+            .headers
+            .keys()
+            .cloned()
+            .filter(|index| !self.requested.contains(index) && !self.blocks.contains_key(index))
+            .collect();
+        let mut batches = vec![];
+        for batch in missing.chunks(MAX_BLOCKS_PER_REQUEST) {
+            let last = batch[batch.len() - 1];
+            let peer = self
+                .peers
+                .iter()
+                .filter(|(_, best_index)| **best_index >= last)
+                .map(|(peer, _)| (*peer, load.get(peer).cloned().unwrap_or(0)))
+                .filter(|(_, requests)| *requests < MAX_REQUESTS_PER_PEER)
+                .min_by_key(|(_, requests)| *requests)
+                .map(|(peer, _)| peer);
+            // The next batches are even further ahead, so no peer has them either.
+            let peer = match peer {
+                Some(peer) => peer,
+                None => break,
+            };
+            *load.entry(peer).or_insert(0) += 1;
+            batches.push((peer, batch.to_vec()));
+        }
+        for (peer, indices) in batches {
+            let hashes = indices.iter().map(|index| self.headers[index].hash).collect();
+            let id = self.add_request(peer, now, indices);
+            messages.push((peer, Message::BlocksRequest(BlocksRequest { id, hashes })));
+        }
+        messages
+    }
+
+    /// Requests the missing parents of the orphan blocks, unless they are already requested or
+    /// the node is syncing and gets them with the headers anyway.
+    pub fn request_parents(
+        &mut self,
+        missing: Vec<CryptoHash>,
+        now: Instant,
+    ) -> Vec<(NodeIndex, Message)> {
+        if self.is_syncing() {
+            return vec![];
+        }
+        let missing: Vec<CryptoHash> = missing
+            .into_iter()
+            .filter(|hash| !self.requests.values().any(|r| r.parents.contains(hash)))
+            .collect();
+        let mut load: HashMap<NodeIndex, usize> =
+            self.peers.keys().map(|peer| (*peer, 0)).collect();
+        for request in self.requests.values().filter(|r| !r.is_headers()) {
+            if let Some(requests) = load.get_mut(&request.peer) {
+                *requests += 1;
+            }
+        }
+        let mut messages = vec![];
+        for batch in missing.chunks(MAX_BLOCKS_PER_REQUEST) {
+            let peer = load
+                .iter()
+                .filter(|(_, requests)| **requests < MAX_REQUESTS_PER_PEER)
+                .min_by_key(|(_, requests)| **requests)
+                .map(|(peer, _)| *peer);
+            let peer = match peer {
+                Some(peer) => peer,
+                None => break,
+            };
+            *load.entry(peer).or_insert(0) += 1;
+            let id = self.next_request_id;
+            self.next_request_id += 1;
+            let request = Request { peer, sent: now, indices: vec![], parents: batch.to_vec() };
+            self.requests.insert(id, request);
+            let hashes = batch.to_vec();
+            messages.push((peer, Message::BlocksRequest(BlocksRequest { id, hashes })));
+        }
+        messages
+    }
+
+    fn take_request(&mut self, peer: NodeIndex, id: RequestId, headers: bool) -> Option<Request> {
+        match self.requests.get(&id) {
+            Some(r) if r.peer == peer && r.is_headers() == headers => self.cancel(id),
+            // Responses to the requests that timed out are ignored.
+            _ => None,
+        }
+    }
+
+    /// Validates the headers and appends them to the chain of headers.
+    pub fn on_headers(
+        &mut self,
+        peer: NodeIndex,
+        response: HeadersResponse,
+    ) -> Result<(), SyncError> {
+        if self.take_request(peer, response.id, true).is_none() {
+            return Ok(());
+        }
+        if response.headers.is_empty() {
+            // The peer has nothing beyond the headers, don't ask it again.
+            self.peers.insert(peer, self.last_header.0);
+            return Ok(());
+        }
+        let (mut index, mut hash) = self.last_header;
+        for header in &response.headers {
+            if hash_struct(&header.body) != header.hash {
+                return Err(SyncError::InvalidHeaderHash(header.hash));
+            }
+            if header.index() != index + 1 || header.parent_hash() != hash {
+                return Err(SyncError::UnlinkedHeader {
+                    index: header.index(),
+                    parent_hash: header.parent_hash(),
+                });
+            }
+            index = header.index();
+            hash = header.hash;
+        }
+        for header in response.headers {
+            self.headers.insert(header.index(), header);
+        }
+        self.last_header = (index, hash);
+        Ok(())
+    }
+
+    /// Checks the blocks against their headers and returns the blocks that can be imported,
+    /// in order. The requested blocks missing from the response are requested again.
+    pub fn on_blocks(
+        &mut self,
+        peer: NodeIndex,
+        response: BlocksResponse,
+    ) -> Result<Vec<Blocks>, SyncError> {
+        let request = match self.take_request(peer, response.id, false) {
+            Some(request) => request,
+            None => return Ok(vec![]),
+        };
+        if !request.parents.is_empty() {
+            // The parents go straight to the import, which adds the orphans waiting for them.
+            for (beacon_block, shard_block) in &response.blocks {
+                let valid = request.parents.contains(&beacon_block.hash)
+                    && hash_struct(&beacon_block.body.header) == beacon_block.hash
+                    && beacon_block.body.header.shard_block_hash == shard_block.hash
+                    && hash_struct(&shard_block.body.header) == shard_block.hash;
+                if !valid {
+                    let index = beacon_block.index();
+                    return Err(SyncError::UnexpectedBlock { index, hash: beacon_block.hash });
+                }
+            }
+            return Ok(response.blocks);
+        }
+        for (beacon_block, shard_block) in response.blocks {
+            let index = beacon_block.index();
+            let header = self.headers.get(&index).filter(|_| request.indices.contains(&index));
+            let valid = header.map_or(false, |header| {
+                header.hash == beacon_block.hash
+                    && hash_struct(&beacon_block.body.header) == beacon_block.hash
+                    && header.body.shard_block_hash == shard_block.hash
+                    && hash_struct(&shard_block.body.header) == shard_block.hash
+            });
+            if !valid {
+                return Err(SyncError::UnexpectedBlock { index, hash: beacon_block.hash });
+            }
+            self.blocks.insert(index, (beacon_block, shard_block));
+        }
+        let mut ready = vec![];
+        while let Some(blocks) = self.blocks.remove(&self.next_index) {
+            self.headers.remove(&self.next_index);
+            self.next_index += 1;
+            ready.push(blocks);
+        }
+        Ok(ready)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use primitives::types::MerkleHash;
+
+    use super::*;
+
+    fn chain(len: u64) -> Vec<Blocks> {
+        let mut beacon_block = SignedBeaconBlock::genesis(CryptoHash::default());
+        let mut shard_block = SignedShardBlock::genesis(MerkleHash::default());
+        let mut blocks = vec![(beacon_block.clone(), shard_block.clone())];
+        for index in 1..=len {
+            shard_block = SignedShardBlock::new(
+                0,
+                index,
+                shard_block.hash,
+                MerkleHash::default(),
+                MerkleHash::default(),
+                vec![],
+                vec![],
+            );
+            beacon_block = SignedBeaconBlock::new(
+                index,
+                beacon_block.hash,
+                vec![],
+                shard_block.hash,
+                CryptoHash::default(),
+            );
+            blocks.push((beacon_block.clone(), shard_block.clone()));
+        }
+        blocks
+    }
+
+    fn headers_request(messages: &[(NodeIndex, Message)]) -> (NodeIndex, RequestId) {
+        match messages {
+            [(peer, Message::HeadersRequest(request))] => {
+                assert_eq!(request.from_index, 1);
+                (*peer, request.id)
+            }
+            _ => panic!("Expected a headers request, got {:?}", messages),
+        }
+    }
+
+    #[test]
+    fn test_sync() {
+        let chain = chain(20);
+        let now = Instant::now();
+        let mut sync = BlockSync::new(0, chain[0].0.hash);
+        sync.add_peer(1, 20);
+        sync.add_peer(2, 20);
+
+        let (peer, id) = headers_request(&sync.next_requests(now));
+        let mut headers: Vec<_> = chain[1..].iter().map(|(b, _)| b.header()).collect();
+        let first = headers.remove(0);
+        assert_eq!(
+            sync.on_headers(peer, HeadersResponse { id, headers: headers.clone() }),
+            Err(SyncError::UnlinkedHeader { index: 2, parent_hash: first.hash })
+        );
+        headers.insert(0, first);
+        let (peer, id) = headers_request(&sync.next_requests(now));
+        sync.on_headers(peer, HeadersResponse { id, headers }).unwrap();
+        let progress = SyncProgress { target_index: 20, header_index: 20, block_index: 0 };
+        assert_eq!(sync.progress(), Some(progress));
+
+        // The blocks are requested from both peers.
+        let requests: Vec<_> = sync
+            .next_requests(now)
+            .into_iter()
+            .map(|(peer, message)| match message {
+                Message::BlocksRequest(request) => (peer, request),
+                message => panic!("Expected a blocks request, got {:?}", message),
+            })
+            .collect();
+        assert_eq!(requests.len(), 2);
+        assert_ne!(requests[0].0, requests[1].0);
+        let response = |request: &BlocksRequest| BlocksResponse {
+            id: request.id,
+            blocks: request
+                .hashes
+                .iter()
+                .map(|hash| chain.iter().find(|(b, _)| b.hash == *hash).unwrap().clone())
+                .collect(),
+        };
+        // Blocks are handed over in order.
+        let (peer, request) = &requests[1];
+        assert_eq!(sync.on_blocks(*peer, response(request)), Ok(vec![]));
+        let (peer, request) = &requests[0];
+        let blocks = sync.on_blocks(*peer, response(request)).unwrap();
+        assert_eq!(blocks, chain[1..].to_vec());
+
+        sync.on_best_block(20, chain[20].0.hash, now);
+        assert_eq!(sync.progress(), None);
+        assert!(sync.next_requests(now).is_empty());
+    }
+
+    #[test]
+    fn test_skip_to() {
+        let chain = chain(20);
+        let now = Instant::now();
+        let mut sync = BlockSync::new(0, chain[0].0.hash);
+        sync.add_peer(1, 20);
+        sync.skip_to(chain[16].0.header());
+        sync.skip_to(chain[8].0.header());
+        let id = match sync.next_requests(now).as_slice() {
+            [(1, Message::HeadersRequest(request)), (1, Message::BlocksRequest(blocks))] => {
+                assert_eq!(request.from_index, 17);
+                assert_eq!(blocks.hashes, vec![chain[16].0.hash]);
+                request.id
+            }
+            messages => panic!("Expected headers and blocks requests, got {:?}", messages),
+        };
+        let headers = chain[17..].iter().map(|(b, _)| b.header()).collect();
+        sync.on_headers(1, HeadersResponse { id, headers }).unwrap();
+        let progress = SyncProgress { target_index: 20, header_index: 20, block_index: 0 };
+        assert_eq!(sync.progress(), Some(progress));
+    }
+
+    #[test]
+    fn test_request_parents() {
+        let chain = chain(5);
+        let now = Instant::now();
+        let mut sync = BlockSync::new(3, chain[3].0.hash);
+        sync.add_peer(1, 3);
+        let parent = chain[4].0.hash;
+        let (id, hashes) = match sync.request_parents(vec![parent], now).as_slice() {
+            [(1, Message::BlocksRequest(request))] => (request.id, request.hashes.clone()),
+            messages => panic!("Expected a blocks request, got {:?}", messages),
+        };
+        assert_eq!(hashes, vec![parent]);
+        // The parent is requested only once.
+        assert!(sync.request_parents(vec![parent], now).is_empty());
+        let response = BlocksResponse { id, blocks: vec![chain[4].clone()] };
+        assert_eq!(sync.on_blocks(1, response), Ok(vec![chain[4].clone()]));
+
+        let id = match sync.request_parents(vec![parent], now).as_slice() {
+            [(1, Message::BlocksRequest(request))] => request.id,
+            messages => panic!("Expected a blocks request, got {:?}", messages),
+        };
+        let response = BlocksResponse { id, blocks: vec![chain[5].clone()] };
+        assert_eq!(
+            sync.on_blocks(1, response),
+            Err(SyncError::UnexpectedBlock { index: 5, hash: chain[5].0.hash })
+        );
+
+        // While syncing the parents come with the headers.
+        sync.add_peer(2, 10);
+        assert!(sync.request_parents(vec![parent], now).is_empty());
+    }
+}
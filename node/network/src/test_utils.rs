@@ -22,6 +22,7 @@ use configs::AuthorityConfig;
 use beacon::types::{BeaconBlockChain, SignedBeaconBlock, SignedBeaconBlockHeader};
 use chain::{SignedBlock, SignedHeader};
 use client::test_utils::get_client;
+use primitives::aggregate_signature::BlsSecretKey;
 use primitives::hash::{CryptoHash, hash_struct};
 use primitives::signature::get_key_pair;
 use primitives::traits::GenericResult;
@@ -32,7 +33,7 @@ use chain::ChainPayload;
 
 use crate::error::Error;
 use crate::message::Message;
-use crate::protocol::{CURRENT_VERSION, Protocol, ProtocolConfig};
+use crate::protocol::{Protocol, ProtocolConfig, CURRENT_VERSION, MIN_SUPPORTED_VERSION};
 
 use self::storage::test_utils::create_memory_db;
 
@@ -92,8 +93,9 @@ pub fn init_logger(debug: bool) {
 
 pub fn default_network_service() -> NetworkService {
     let net_config = NetworkConfiguration::default();
-    let version = [CURRENT_VERSION as u8];
-    let registered = RegisteredProtocol::new(ProtocolId::default(), &version);
+    let versions: Vec<u8> =
+        (MIN_SUPPORTED_VERSION..=CURRENT_VERSION).map(|version| version as u8).collect();
+    let registered = RegisteredProtocol::new(ProtocolId::default(), &versions);
     start_service(net_config, Some(registered)).unwrap()
 }
 
@@ -104,10 +106,19 @@ pub fn get_noop_network_task() -> impl Future<Item = (), Error = ()> {
 pub fn get_test_protocol() -> Protocol {
     let (block_tx, _) = channel(1024);
     let (transaction_tx, _) = channel(1024);
+    let (receipt_tx, _) = channel(1024);
     let (message_tx, _) = channel(1024);
     let (gossip_tx, _) = channel(1024);
     let client = Arc::new(get_client());
-    Protocol::new(ProtocolConfig::default(), client, block_tx, transaction_tx, message_tx, gossip_tx)
+    Protocol::new(
+        ProtocolConfig::default(),
+        client,
+        block_tx,
+        transaction_tx,
+        receipt_tx,
+        message_tx,
+        gossip_tx,
+    )
 }
 
 pub fn get_test_chain_spec(
@@ -118,12 +129,19 @@ pub fn get_test_chain_spec(
     let mut initial_authorities = vec![];
     for i in 0..num_authorities {
         let (public_key, _) = get_key_pair();
-        initial_authorities.push((i.to_string(), public_key.to_string(), 100));
+        let bls_public_key = BlsSecretKey::generate().get_public_key();
+        initial_authorities.push((
+            i.to_string(),
+            public_key.to_string(),
+            bls_public_key.to_string(),
+            100,
+        ));
     }
     ChainSpec {
         accounts: vec![], genesis_wasm: vec![],
         initial_authorities,
         beacon_chain_epoch_length: epoch_length, beacon_chain_num_seats_per_slot: num_seats_per_slot,
-        boot_nodes: vec![]
+        boot_nodes: vec![],
+        transaction_validity_period: None,
     }
 }
@@ -0,0 +1,261 @@
+//! Runtimes of several shards that are advanced in lock-step blocks, for testing the flows
+//! between the accounts of different shards. The receipts a shard spawns in a block are
+//! delivered to the shards of their receivers in the next block, ordered by the shard that
+//! spawned them, so that every run of the same transactions gives the same states. Accounts
+//! are placed by `account_to_shard_id`, which still puts all of them on shard 0.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use node_runtime::state_viewer::{AccountViewCallResult, StateDbViewer};
+use node_runtime::test_utils::{sign_transaction, to_receipt_block};
+use node_runtime::{ApplyState, Runtime};
+use primitives::hash::{hash, CryptoHash};
+use primitives::test_utils::get_key_pair_from_seed;
+use primitives::types::{Balance, BlockIndex, MerkleHash, ShardId};
+use primitives::utils::account_to_shard_id;
+use storage::test_utils::create_memory_db;
+use storage::StateDb;
+use transaction::{
+    FinalTransactionResult, ReceiptTransaction, SendMoneyTransaction, SignedTransaction,
+    TransactionBody, TransactionResult,
+};
+
+use super::{final_result, GENESIS_TX_STAKE};
+
+struct Shard {
+    runtime: Runtime,
+    viewer: StateDbViewer,
+    root: MerkleHash,
+    /// Receipts to apply in the next block.
+    receipts: Vec<ReceiptTransaction>,
+    /// Transactions to apply in the next block.
+    transactions: Vec<SignedTransaction>,
+}
+
+pub struct ShardCluster {
+    shards: Vec<Shard>,
+    block_index: BlockIndex,
+    block_hash: CryptoHash,
+    /// Genesis hash the transactions are signed for, the same on all the shards.
+    genesis_hash: CryptoHash,
+    /// Results of the transactions and the receipts applied so far on all the shards, by the
+    /// transaction hash or the receipt nonce.
+    results: HashMap<CryptoHash, TransactionResult>,
+}
+
+impl ShardCluster {
+    /// Cluster of `num_shards` shards whose genesis has the given accounts with their balances,
+    /// each in the state of its shard, see `account_to_shard_id`. Accounts sign with the keys
+    /// derived from their ids, as in `StandaloneRuntime`.
+    pub fn new(num_shards: ShardId, accounts: &[(&str, Balance)]) -> Self {
+        let mut shards: Vec<Shard> = (0..num_shards)
+            .map(|shard_id| {
+                let state_db = Arc::new(StateDb::new(Arc::new(create_memory_db())));
+                let runtime = Runtime::new(state_db.clone());
+                let balances: Vec<_> = accounts
+                    .iter()
+                    .filter(|(account_id, _)| {
+                        account_to_shard_id(&account_id.to_string()) == shard_id
+                    })
+                    .map(|(account_id, amount)| {
+                        let public_key = get_key_pair_from_seed(account_id).0;
+                        (account_id.to_string(), public_key.to_string(), *amount, GENESIS_TX_STAKE)
+                    })
+                    .collect();
+                let root = runtime.apply_genesis_state(&balances, &[], &[]);
+                Shard {
+                    runtime,
+                    viewer: StateDbViewer::new(state_db),
+                    root,
+                    receipts: vec![],
+                    transactions: vec![],
+                }
+            })
+            .collect();
+        let roots: Vec<u8> =
+            shards.iter().flat_map(|shard| shard.root.as_ref().to_vec()).collect();
+        let genesis_hash = hash(&roots);
+        for shard in shards.iter_mut() {
+            shard.runtime.config.genesis_hash = Some(genesis_hash);
+        }
+        ShardCluster {
+            shards,
+            block_index: 0,
+            block_hash: CryptoHash::default(),
+            genesis_hash,
+            results: HashMap::new(),
+        }
+    }
+
+    /// Index of the last block, the same on all the shards.
+    pub fn block_index(&self) -> BlockIndex {
+        self.block_index
+    }
+
+    /// Root of the state of the shard after the last block.
+    pub fn root(&self, shard_id: ShardId) -> MerkleHash {
+        self.shards[shard_id as usize].root
+    }
+
+    /// Signs the transaction with the key of its originator and adds it to the next block of
+    /// the shard of the originator. Returns its hash.
+    pub fn add_transaction(&mut self, body: TransactionBody) -> CryptoHash {
+        let originator = body.get_originator();
+        let (_, secret_key) = get_key_pair_from_seed(&originator);
+        let transaction = sign_transaction(body, &secret_key);
+        let hash = transaction.get_hash();
+        self.shards[account_to_shard_id(&originator) as usize].transactions.push(transaction);
+        hash
+    }
+
+    /// Adds a transfer to the next block, with the next nonce of the originator.
+    pub fn send_money(&mut self, originator: &str, receiver: &str, amount: Balance) -> CryptoHash {
+        let body = TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce: self.next_nonce(originator),
+            originator: originator.to_string(),
+            receiver: receiver.to_string(),
+            amount,
+            block_hash: self.block_hash,
+            genesis_hash: self.genesis_hash,
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
+        });
+        self.add_transaction(body)
+    }
+
+    /// Applies the next block on every shard, with the transactions added to it and the
+    /// receipts spawned in the block before, and routes the receipts it spawns.
+    pub fn advance_block(&mut self) {
+        self.block_index += 1;
+        let mut spawned = vec![];
+        for (shard_id, shard) in self.shards.iter_mut().enumerate() {
+            let apply_state = ApplyState {
+                root: shard.root,
+                shard_id: shard_id as ShardId,
+                block_index: self.block_index,
+                parent_block_hash: self.block_hash,
+            };
+            let receipts: Vec<_> = shard.receipts.drain(..).collect();
+            let transactions: Vec<_> = shard.transactions.drain(..).collect();
+            let ids: Vec<_> = receipts
+                .iter()
+                .map(|receipt| receipt.nonce)
+                .chain(transactions.iter().map(SignedTransaction::get_hash))
+                .collect();
+            let result = shard
+                .runtime
+                .apply(&apply_state, &[to_receipt_block(receipts)], &transactions)
+                .expect("Failed to apply the block");
+            shard.runtime.state_db.commit(result.db_changes).expect("Failed to commit the block");
+            shard.root = result.root;
+            for (id, tx_result) in ids.into_iter().zip(result.tx_result) {
+                self.results.insert(id, tx_result);
+            }
+            spawned.push(result.new_receipts);
+        }
+        let roots: Vec<u8> =
+            self.shards.iter().flat_map(|shard| shard.root.as_ref().to_vec()).collect();
+        self.block_hash = hash(&[self.block_hash.as_ref(), &roots].concat());
+        for mut new_receipts in spawned {
+            for (shard_id, shard) in self.shards.iter_mut().enumerate() {
+                if let Some(receipts) = new_receipts.remove(&(shard_id as ShardId)) {
+                    shard.receipts.extend(receipts);
+                }
+            }
+            assert!(new_receipts.is_empty(), "Receipts for shards out of the cluster");
+        }
+    }
+
+    /// Advances blocks until no shard has transactions or receipts to apply, at most
+    /// `max_blocks` of them. Returns whether the cluster got idle.
+    pub fn run_until_idle(&mut self, max_blocks: usize) -> bool {
+        for _ in 0..max_blocks {
+            if self.is_idle() {
+                return true;
+            }
+            self.advance_block();
+        }
+        self.is_idle()
+    }
+
+    fn is_idle(&self) -> bool {
+        self.shards.iter().all(|shard| shard.receipts.is_empty() && shard.transactions.is_empty())
+    }
+
+    /// Views the account in the state of its shard.
+    pub fn view_account(&self, account_id: &str) -> Result<AccountViewCallResult, String> {
+        let account_id = account_id.to_string();
+        let shard = &self.shards[account_to_shard_id(&account_id) as usize];
+        shard.viewer.view_account(shard.root, &account_id)
+    }
+
+    /// Sum of the balances and the stakes of the accounts, across their shards. Accounts that
+    /// don't exist hold nothing.
+    pub fn total_balance(&self, account_ids: &[&str]) -> Balance {
+        account_ids
+            .iter()
+            .filter_map(|account_id| self.view_account(account_id).ok())
+            .map(|account| account.amount + account.stake)
+            .sum()
+    }
+
+    /// Result of the transaction or the receipt with the given hash or nonce, if it was applied.
+    pub fn get_result(&self, hash: &CryptoHash) -> Option<&TransactionResult> {
+        self.results.get(hash)
+    }
+
+    /// Status of the transaction together with the logs of it and of its receipts on all the
+    /// shards, ordered depth first.
+    pub fn get_final_result(&self, hash: &CryptoHash) -> FinalTransactionResult {
+        final_result(&self.results, hash)
+    }
+
+    fn next_nonce(&self, account_id: &str) -> u64 {
+        let pending = self.shards[account_to_shard_id(&account_id.to_string()) as usize]
+            .transactions
+            .iter()
+            .filter(|transaction| transaction.body.get_originator() == account_id)
+            .count() as u64;
+        self.view_account(account_id).map(|account| account.nonce + 1).unwrap_or(1) + pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use transaction::FinalTransactionStatus;
+
+    use super::*;
+
+    const ACCOUNTS: [&str; 3] = ["alice.near", "bob.near", "carol.near"];
+
+    #[test]
+    fn test_transfers() {
+        let mut cluster = ShardCluster::new(2, &[("alice.near", 1000), ("bob.near", 100)]);
+        let alice_to_bob = cluster.send_money("alice.near", "bob.near", 10);
+        let alice_to_carol = cluster.send_money("alice.near", "carol.near", 20);
+        let bob_to_alice = cluster.send_money("bob.near", "alice.near", 5);
+        assert!(cluster.run_until_idle(10));
+        let status = |hash: &CryptoHash| cluster.get_final_result(hash).status;
+        assert_eq!(status(&alice_to_bob), FinalTransactionStatus::Completed);
+        // Money sent to an account that doesn't exist is refunded.
+        assert_eq!(status(&alice_to_carol), FinalTransactionStatus::Failed);
+        assert_eq!(status(&bob_to_alice), FinalTransactionStatus::Completed);
+        assert_eq!(cluster.view_account("alice.near").unwrap().amount, 995);
+        assert_eq!(cluster.view_account("bob.near").unwrap().amount, 105);
+        assert_eq!(cluster.total_balance(&ACCOUNTS), 1100);
+    }
+
+    #[test]
+    fn test_lock_step_is_deterministic() {
+        let run = || {
+            let mut cluster = ShardCluster::new(3, &[("alice.near", 1000), ("bob.near", 100)]);
+            cluster.send_money("alice.near", "bob.near", 10);
+            cluster.send_money("bob.near", "alice.near", 5);
+            assert!(cluster.run_until_idle(10));
+            let roots: Vec<_> = (0..3).map(|shard_id| cluster.root(shard_id)).collect();
+            (cluster.block_index(), roots)
+        };
+        assert_eq!(run(), run());
+    }
+}
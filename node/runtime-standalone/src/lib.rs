@@ -0,0 +1,422 @@
+//! Runtime that runs in-process on an in-memory state, for testing contracts and the flows
+//! between them without running a node. Every transaction is applied in a block of its own,
+//! and the receipts it spawns are applied in the blocks after it until there are none left,
+//! so that the outcome of a cross-contract call is final by the time the call returns.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use node_runtime::config::RuntimeConfig;
+use node_runtime::state_viewer::{AccountViewCallResult, StateDbViewer};
+use node_runtime::test_utils::{sign_transaction, to_receipt_block};
+use node_runtime::{ApplyState, Runtime};
+use primitives::hash::{hash, CryptoHash};
+use primitives::test_utils::{get_bls_key_pair_from_seed, get_key_pair_from_seed};
+use primitives::types::{Balance, BlockIndex, MerkleHash};
+use storage::test_utils::create_memory_db;
+use storage::StateDb;
+use transaction::{
+    CreateAccountTransaction, DeployContractTransaction, FinalTransactionResult,
+    FinalTransactionStatus, FunctionCallTransaction, ReceiptTransaction, SendMoneyTransaction,
+    SignedTransaction, StakeTransaction, TransactionBody, TransactionFailure, TransactionLogs,
+    TransactionResult, TransactionStatus,
+};
+
+pub mod cluster;
+#[cfg(test)]
+mod model;
+
+/// Transaction stake of the genesis accounts, the same that the created accounts get.
+const GENESIS_TX_STAKE: Balance = 100;
+
+pub struct StandaloneRuntime {
+    runtime: Runtime,
+    viewer: StateDbViewer,
+    root: MerkleHash,
+    block_index: BlockIndex,
+    block_hash: CryptoHash,
+    /// Genesis hash the transactions are signed for.
+    genesis_hash: CryptoHash,
+    /// Results of the transactions and the receipts applied so far, by the transaction hash or
+    /// the receipt nonce.
+    results: HashMap<CryptoHash, TransactionResult>,
+}
+
+impl StandaloneRuntime {
+    /// Runtime whose genesis has the given accounts with their balances and no contracts. Every
+    /// account, including the ones created later, signs with the key derived from its id, see
+    /// `get_key_pair_from_seed`.
+    pub fn new(accounts: &[(&str, Balance)]) -> Self {
+        Self::with_config(accounts, RuntimeConfig::default())
+    }
+
+    pub fn with_config(accounts: &[(&str, Balance)], config: RuntimeConfig) -> Self {
+        let state_db = Arc::new(StateDb::new(Arc::new(create_memory_db())));
+        let mut runtime = Runtime::new(state_db.clone());
+        runtime.config = config;
+        let balances: Vec<_> = accounts
+            .iter()
+            .map(|(account_id, amount)| {
+                let public_key = get_key_pair_from_seed(account_id).0;
+                (account_id.to_string(), public_key.to_string(), *amount, GENESIS_TX_STAKE)
+            })
+            .collect();
+        let root = runtime.apply_genesis_state(&balances, &[], &[]);
+        // The chain is known by its genesis state, unless the config names another.
+        let genesis_hash = *runtime.config.genesis_hash.get_or_insert(hash(root.as_ref()));
+        StandaloneRuntime {
+            runtime,
+            viewer: StateDbViewer::new(state_db),
+            root,
+            block_index: 0,
+            block_hash: CryptoHash::default(),
+            genesis_hash,
+            results: HashMap::new(),
+        }
+    }
+
+    /// Root of the state after the last block.
+    pub fn root(&self) -> MerkleHash {
+        self.root
+    }
+
+    /// Index of the last block.
+    pub fn block_index(&self) -> BlockIndex {
+        self.block_index
+    }
+
+    /// Result of the transaction or the receipt with the given hash or nonce, if it was applied.
+    pub fn get_result(&self, hash: &CryptoHash) -> Option<&TransactionResult> {
+        self.results.get(hash)
+    }
+
+    pub fn create_account(
+        &mut self,
+        originator: &str,
+        new_account_id: &str,
+        amount: Balance,
+    ) -> FinalTransactionResult {
+        let body = TransactionBody::CreateAccount(CreateAccountTransaction {
+            nonce: self.next_nonce(originator),
+            originator: originator.to_string(),
+            new_account_id: new_account_id.to_string(),
+            amount,
+            public_key: get_key_pair_from_seed(new_account_id).0.to_bytes(),
+            block_hash: self.block_hash,
+            genesis_hash: self.genesis_hash,
+            execute_at: 0,
+            tip: 0,
+        });
+        self.submit(body)
+    }
+
+    pub fn send_money(
+        &mut self,
+        originator: &str,
+        receiver: &str,
+        amount: Balance,
+    ) -> FinalTransactionResult {
+        let body = TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce: self.next_nonce(originator),
+            originator: originator.to_string(),
+            receiver: receiver.to_string(),
+            amount,
+            block_hash: self.block_hash,
+            genesis_hash: self.genesis_hash,
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
+        });
+        self.submit(body)
+    }
+
+    /// Stakes the amount of the account, with the BLS key derived from its id, see
+    /// `get_bls_key_pair_from_seed`.
+    pub fn stake(&mut self, account_id: &str, amount: Balance) -> FinalTransactionResult {
+        let (bls_public_key, bls_secret_key) = get_bls_key_pair_from_seed(account_id);
+        let body = TransactionBody::Stake(StakeTransaction {
+            nonce: self.next_nonce(account_id),
+            originator: account_id.to_string(),
+            amount,
+            block_hash: self.block_hash,
+            genesis_hash: self.genesis_hash,
+            execute_at: 0,
+            tip: 0,
+            bls_public_key: Vec::from(&bls_public_key),
+            bls_proof_of_possession: Vec::from(&bls_secret_key.get_proof_of_possession()),
+        });
+        self.submit(body)
+    }
+
+    /// Deploys the contract to the account, which signs the deploy itself.
+    pub fn deploy(&mut self, account_id: &str, code: &[u8]) -> FinalTransactionResult {
+        let body = TransactionBody::DeployContract(DeployContractTransaction {
+            nonce: self.next_nonce(account_id),
+            originator: account_id.to_string(),
+            contract_id: account_id.to_string(),
+            wasm_byte_array: code.to_vec(),
+            public_key: vec![],
+            block_hash: self.block_hash,
+            genesis_hash: self.genesis_hash,
+            execute_at: 0,
+            tip: 0,
+        });
+        self.submit(body)
+    }
+
+    pub fn call(
+        &mut self,
+        originator: &str,
+        contract_id: &str,
+        method_name: &str,
+        args: &[u8],
+        amount: Balance,
+    ) -> FinalTransactionResult {
+        let body = TransactionBody::FunctionCall(FunctionCallTransaction {
+            nonce: self.next_nonce(originator),
+            originator: originator.to_string(),
+            contract_id: contract_id.to_string(),
+            method_name: method_name.as_bytes().to_vec(),
+            args: args.to_vec(),
+            amount,
+            block_hash: self.block_hash,
+            genesis_hash: self.genesis_hash,
+            execute_at: 0,
+            tip: 0,
+        });
+        self.submit(body)
+    }
+
+    /// Signs the transaction with the key of its originator, applies it and then the receipts
+    /// it spawns until there are none left. Transactions scheduled for a later block are
+    /// executed by `advance_block`, and only the part of their outcome before that is returned.
+    pub fn submit(&mut self, body: TransactionBody) -> FinalTransactionResult {
+        let (_, secret_key) = get_key_pair_from_seed(&body.get_originator());
+        let transaction = sign_transaction(body, &secret_key);
+        let hash = transaction.get_hash();
+        let receipts = self.apply_block(vec![], &[transaction]);
+        self.apply_receipts(receipts);
+        self.get_final_result(&hash)
+    }
+
+    /// Calls the view method of the contract on the state after the last block.
+    pub fn view(
+        &self,
+        contract_id: &str,
+        method_name: &str,
+        args: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        self.viewer.call_function(
+            self.root,
+            self.block_index,
+            &contract_id.to_string(),
+            method_name,
+            args,
+        )
+    }
+
+    pub fn view_account(&self, account_id: &str) -> Result<AccountViewCallResult, String> {
+        self.viewer.view_account(self.root, &account_id.to_string())
+    }
+
+    /// Applies a block without transactions, in which the scheduled transactions that are due
+    /// are executed, and then the receipts they spawn.
+    pub fn advance_block(&mut self) {
+        let receipts = self.apply_block(vec![], &[]);
+        self.apply_receipts(receipts);
+    }
+
+    pub fn advance_blocks(&mut self, count: BlockIndex) {
+        for _ in 0..count {
+            self.advance_block();
+        }
+    }
+
+    /// Status of the transaction together with the logs of it and of its receipts, ordered
+    /// depth first, as the node reports it.
+    pub fn get_final_result(&self, hash: &CryptoHash) -> FinalTransactionResult {
+        final_result(&self.results, hash)
+    }
+
+    fn next_nonce(&self, account_id: &str) -> u64 {
+        self.view_account(account_id).map(|account| account.nonce + 1).unwrap_or(1)
+    }
+
+    fn apply_receipts(&mut self, mut receipts: Vec<ReceiptTransaction>) {
+        while !receipts.is_empty() {
+            receipts = self.apply_block(receipts, &[]);
+        }
+    }
+
+    /// Applies the next block with the receipts and the transactions, commits its state and
+    /// returns the receipts it spawned.
+    fn apply_block(
+        &mut self,
+        receipts: Vec<ReceiptTransaction>,
+        transactions: &[SignedTransaction],
+    ) -> Vec<ReceiptTransaction> {
+        self.block_index += 1;
+        let apply_state = ApplyState {
+            root: self.root,
+            shard_id: 0,
+            block_index: self.block_index,
+            parent_block_hash: self.block_hash,
+        };
+        let ids: Vec<_> = receipts
+            .iter()
+            .map(|receipt| receipt.nonce)
+            .chain(transactions.iter().map(SignedTransaction::get_hash))
+            .collect();
+        let result = self
+            .runtime
+            .apply(&apply_state, &[to_receipt_block(receipts)], transactions)
+            .expect("Failed to apply the block");
+        self.runtime.state_db.commit(result.db_changes).expect("Failed to commit the block");
+        self.root = result.root;
+        self.block_hash = hash(&[self.block_hash.as_ref(), self.root.as_ref()].concat());
+        // The results of the scheduled transactions come last, and are not tracked.
+        for (id, tx_result) in ids.into_iter().zip(result.tx_result) {
+            self.results.insert(id, tx_result);
+        }
+        result.new_receipts.into_iter().flat_map(|(_, receipts)| receipts).collect()
+    }
+}
+
+/// Final result of the transaction from the results of it and of its receipts.
+fn final_result(
+    results: &HashMap<CryptoHash, TransactionResult>,
+    hash: &CryptoHash,
+) -> FinalTransactionResult {
+    let mut result = FinalTransactionResult {
+        status: FinalTransactionStatus::Unknown,
+        logs: vec![],
+        failure: None,
+    };
+    result.status = collect_final_result(results, hash, &mut result.logs, &mut result.failure);
+    result
+}
+
+fn collect_final_result(
+    results: &HashMap<CryptoHash, TransactionResult>,
+    hash: &CryptoHash,
+    logs: &mut Vec<TransactionLogs>,
+    failure: &mut Option<TransactionFailure>,
+) -> FinalTransactionStatus {
+    let result = match results.get(hash) {
+        Some(result) => result,
+        None => return FinalTransactionStatus::Unknown,
+    };
+    logs.push(TransactionLogs {
+        hash: *hash,
+        lines: result.logs.clone(),
+        receipts: result.receipts.clone(),
+    });
+    match result.status {
+        TransactionStatus::Unknown => FinalTransactionStatus::Unknown,
+        TransactionStatus::Failed => {
+            *failure = result.error.clone().map(|error| TransactionFailure { hash: *hash, error });
+            FinalTransactionStatus::Failed
+        }
+        TransactionStatus::Completed => {
+            for receipt in result.receipts.iter() {
+                match collect_final_result(results, receipt, logs, failure) {
+                    FinalTransactionStatus::Completed => {}
+                    FinalTransactionStatus::Failed => return FinalTransactionStatus::Failed,
+                    _ => return FinalTransactionStatus::Started,
+                }
+            }
+            FinalTransactionStatus::Completed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use node_runtime::test_utils::encode_int;
+    use transaction::{ActionError, RuntimeError};
+
+    use super::*;
+
+    const CONTRACT: &[u8] = include_bytes!("../../../core/wasm/runtest/res/wasm_with_mem.wasm");
+
+    fn runtime_with_contract() -> StandaloneRuntime {
+        let mut runtime = StandaloneRuntime::new(&[("alice.near", 1000)]);
+        let result = runtime.create_account("alice.near", "contract.near", 100);
+        assert_eq!(result.status, FinalTransactionStatus::Completed, "{:?}", result);
+        let result = runtime.deploy("contract.near", CONTRACT);
+        assert_eq!(result.status, FinalTransactionStatus::Completed, "{:?}", result);
+        runtime
+    }
+
+    #[test]
+    fn test_create_account_and_send_money() {
+        let mut runtime = StandaloneRuntime::new(&[("alice.near", 1000)]);
+        let result = runtime.create_account("alice.near", "bob.near", 100);
+        assert_eq!(result.status, FinalTransactionStatus::Completed, "{:?}", result);
+        // The transaction and the receipt that creates the account.
+        assert!(result.logs.len() >= 2);
+        assert_eq!(runtime.view_account("bob.near").unwrap().amount, 100);
+
+        // The created account signs with the key derived from its id.
+        let result = runtime.send_money("bob.near", "alice.near", 10);
+        assert_eq!(result.status, FinalTransactionStatus::Completed, "{:?}", result);
+        assert_eq!(runtime.view_account("alice.near").unwrap().amount, 910);
+        assert_eq!(runtime.view_account("bob.near").unwrap().amount, 90);
+
+        let result = runtime.send_money("bob.near", "alice.near", 1000);
+        assert_eq!(result.status, FinalTransactionStatus::Failed);
+        assert!(result.failure.is_some());
+        assert_eq!(runtime.view_account("bob.near").unwrap().amount, 90);
+    }
+
+    #[test]
+    fn test_deploy_call_and_view() {
+        let mut runtime = runtime_with_contract();
+        let result = runtime.view("contract.near", "run_test", &[]).unwrap();
+        assert_eq!(result, encode_int(10).to_vec());
+        let result = runtime.call("alice.near", "contract.near", "log_something", &[], 0);
+        assert_eq!(result.status, FinalTransactionStatus::Completed, "{:?}", result);
+        assert!(result.logs.iter().any(|logs| logs.lines == vec!["LOG: hello".to_string()]));
+        // The receipts were applied in the blocks after the transaction.
+        assert!(runtime.get_result(&result.logs[1].hash).is_some());
+        assert!(runtime.view("contract.near", "unknown", &[]).is_err());
+    }
+
+    #[test]
+    fn test_call_of_missing_contract() {
+        let mut runtime = runtime_with_contract();
+        let result = runtime.call("alice.near", "missing.near", "run_test", &[], 0);
+        assert_eq!(result.status, FinalTransactionStatus::Failed);
+        assert_eq!(
+            result.failure.unwrap().error,
+            RuntimeError::Action(ActionError::ReceiverDoesNotExist("missing.near".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_advance_block() {
+        let mut runtime = runtime_with_contract();
+        let block_index = runtime.block_index();
+        runtime.advance_blocks(3);
+        assert_eq!(runtime.block_index(), block_index + 3);
+        assert_eq!(
+            runtime.view("contract.near", "get_block_index", &[]).unwrap(),
+            (block_index + 3).to_le_bytes().to_vec()
+        );
+
+        let body = TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce: runtime.next_nonce("alice.near"),
+            originator: "alice.near".to_string(),
+            receiver: "contract.near".to_string(),
+            amount: 10,
+            block_hash: CryptoHash::default(),
+            genesis_hash: runtime.genesis_hash,
+            execute_at: runtime.block_index() + 3,
+            tip: 0,
+            memo: vec![],
+        });
+        runtime.submit(body);
+        assert_eq!(runtime.view_account("contract.near").unwrap().amount, 100);
+        runtime.advance_blocks(3);
+        assert_eq!(runtime.view_account("contract.near").unwrap().amount, 110);
+    }
+}
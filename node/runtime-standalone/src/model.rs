@@ -0,0 +1,165 @@
+//! Property tests that apply generated sequences of transfers, stakes, deploys and calls both
+//! to the runtime and to a reference model of the balances, the stakes and the nonces, and
+//! compare them after every transaction. Failing sequences are shrunk by proptest to the
+//! shortest one that still fails.
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use primitives::hash::hash;
+use primitives::types::Balance;
+use transaction::FinalTransactionStatus;
+
+use super::StandaloneRuntime;
+
+const CONTRACT: &[u8] = include_bytes!("../../../core/wasm/runtest/res/wasm_with_mem.wasm");
+/// Account that the transactions can be sent to, but which doesn't exist.
+const MISSING_ACCOUNT: &str = "missing.near";
+
+/// Transaction of the generated sequences. Accounts are indices into the population, the index
+/// past its end is `MISSING_ACCOUNT`.
+#[derive(Debug, Clone)]
+enum Op {
+    Transfer { from: usize, to: usize, amount: Balance },
+    Stake { account: usize, amount: Balance },
+    Deploy { account: usize },
+    Call { from: usize, contract: usize, amount: Balance },
+}
+
+/// Population of 1 to 3 accounts with their balances, and the transactions between them.
+/// Amounts can be larger than the balances, so that some of the transactions fail.
+fn population_and_ops() -> impl Strategy<Value = (Vec<Balance>, Vec<Op>)> {
+    vec(0..1_000u64, 1..4).prop_flat_map(|balances| {
+        let n = balances.len();
+        let op = prop_oneof![
+            (0..n, 0..=n, 0..1_500u64)
+                .prop_map(|(from, to, amount)| Op::Transfer { from, to, amount }),
+            (0..n, 0..1_500u64).prop_map(|(account, amount)| Op::Stake { account, amount }),
+            (0..n).prop_map(|account| Op::Deploy { account }),
+            (0..n, 0..=n, 0..1_500u64)
+                .prop_map(|(from, contract, amount)| Op::Call { from, contract, amount }),
+        ];
+        (Just(balances), vec(op, 1..20))
+    })
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct ModelAccount {
+    amount: Balance,
+    stake: Balance,
+    nonce: u64,
+    has_code: bool,
+}
+
+/// What the runtime is expected to do, in the simplest terms. A transaction that fails before
+/// its receipts changes nothing, not even the nonce. Money sent to an account that doesn't
+/// exist, or to a method of an account without code, is refunded.
+struct Model {
+    accounts: Vec<ModelAccount>,
+}
+
+impl Model {
+    fn new(balances: &[Balance]) -> Self {
+        let accounts = balances
+            .iter()
+            .map(|amount| ModelAccount { amount: *amount, ..Default::default() })
+            .collect();
+        Model { accounts }
+    }
+
+    fn apply(&mut self, op: &Op) -> FinalTransactionStatus {
+        match *op {
+            Op::Transfer { from, to, amount } => {
+                if amount == 0 || self.accounts[from].amount < amount {
+                    return FinalTransactionStatus::Failed;
+                }
+                self.accounts[from].nonce += 1;
+                if to == self.accounts.len() {
+                    return FinalTransactionStatus::Failed;
+                }
+                self.accounts[from].amount -= amount;
+                self.accounts[to].amount += amount;
+                FinalTransactionStatus::Completed
+            }
+            Op::Stake { account, amount } => {
+                let account = &mut self.accounts[account];
+                if account.amount < amount {
+                    return FinalTransactionStatus::Failed;
+                }
+                account.nonce += 1;
+                account.amount -= amount;
+                account.stake += amount;
+                FinalTransactionStatus::Completed
+            }
+            Op::Deploy { account } => {
+                self.accounts[account].nonce += 1;
+                self.accounts[account].has_code = true;
+                FinalTransactionStatus::Completed
+            }
+            Op::Call { from, contract, amount } => {
+                if self.accounts[from].amount < amount {
+                    return FinalTransactionStatus::Failed;
+                }
+                self.accounts[from].nonce += 1;
+                if !self.accounts.get(contract).map_or(false, |account| account.has_code) {
+                    return FinalTransactionStatus::Failed;
+                }
+                self.accounts[from].amount -= amount;
+                self.accounts[contract].amount += amount;
+                FinalTransactionStatus::Completed
+            }
+        }
+    }
+}
+
+fn account_id(index: usize) -> String {
+    format!("account{}.near", index)
+}
+
+/// Applies the transaction to the runtime and returns its status.
+fn apply(runtime: &mut StandaloneRuntime, num_accounts: usize, op: &Op) -> FinalTransactionStatus {
+    let id = |index| {
+        if index < num_accounts {
+            account_id(index)
+        } else {
+            MISSING_ACCOUNT.to_string()
+        }
+    };
+    let result = match *op {
+        Op::Transfer { from, to, amount } => runtime.send_money(&id(from), &id(to), amount),
+        Op::Stake { account, amount } => runtime.stake(&id(account), amount),
+        Op::Deploy { account } => runtime.deploy(&id(account), CONTRACT),
+        Op::Call { from, contract, amount } => {
+            runtime.call(&id(from), &id(contract), "run_test", &[], amount)
+        }
+    };
+    result.status
+}
+
+fn view(runtime: &StandaloneRuntime, index: usize) -> ModelAccount {
+    let account = runtime.view_account(&account_id(index)).unwrap();
+    ModelAccount {
+        amount: account.amount,
+        stake: account.stake,
+        nonce: account.nonce,
+        has_code: account.code_hash == hash(CONTRACT),
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_runtime_matches_model((balances, ops) in population_and_ops()) {
+        let account_ids: Vec<_> = (0..balances.len()).map(account_id).collect();
+        let genesis: Vec<_> =
+            account_ids.iter().map(String::as_str).zip(balances.iter().cloned()).collect();
+        let mut runtime = StandaloneRuntime::new(&genesis);
+        let mut model = Model::new(&balances);
+        for op in ops.iter() {
+            let expected_status = model.apply(op);
+            prop_assert_eq!(apply(&mut runtime, balances.len(), op), expected_status, "{:?}", op);
+            for (index, expected) in model.accounts.iter().enumerate() {
+                prop_assert_eq!(&view(&runtime, index), expected, "{:?}, {}", op, index);
+            }
+            prop_assert!(runtime.view_account(MISSING_ACCOUNT).is_err());
+        }
+    }
+}
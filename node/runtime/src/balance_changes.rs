@@ -0,0 +1,92 @@
+//! Attributes the changes of the account balances made by a transaction or a receipt to
+//! their causes, so that they don't have to be inferred from the transaction types.
+use primitives::serialize::from_state_value;
+use primitives::types::{AccountId, Balance};
+use storage::StateDbUpdate;
+use transaction::{
+    BalanceChange, BalanceChangeCause, ReceiptBody, ReceiptTransaction, TransactionBody,
+};
+
+use super::{Account, COL_ACCOUNT};
+use crate::state_changes::DATA_SEPARATOR;
+
+/// Amount of an account before and after the uncommitted changes.
+pub struct AmountChange {
+    pub account_id: AccountId,
+    pub before: Balance,
+    pub after: Balance,
+}
+
+fn amount(value: Option<&[u8]>) -> Balance {
+    value.and_then(|v| from_state_value(v).ok()).map_or(0, |account: Account| account.amount)
+}
+
+/// Amounts changed by the uncommitted changes. Has to run before they are committed.
+pub fn prospective_amount_changes(state_update: &StateDbUpdate) -> Vec<AmountChange> {
+    state_update
+        .prospective_keys_with_prefix(COL_ACCOUNT)
+        .into_iter()
+        .filter(|key| !key[COL_ACCOUNT.len()..].contains(&DATA_SEPARATOR))
+        .filter_map(|key| {
+            let before = amount(state_update.get_committed(&key).as_ref().map(|v| &v[..]));
+            let after = amount(state_update.get(&key).as_ref().map(|v| &v[..]));
+            if before == after {
+                return None;
+            }
+            let account_id = String::from_utf8_lossy(&key[COL_ACCOUNT.len()..]).to_string();
+            Some(AmountChange { account_id, before, after })
+        })
+        .collect()
+}
+
+fn delta(change: &AmountChange) -> i128 {
+    i128::from(change.after) - i128::from(change.before)
+}
+
+fn balance_change(account_id: &AccountId, cause: BalanceChangeCause, delta: i128) -> BalanceChange {
+    let amount = delta.abs() as Balance;
+    BalanceChange { account_id: account_id.clone(), cause, amount, credit: delta > 0 }
+}
+
+fn transaction_cause(body: &TransactionBody) -> BalanceChangeCause {
+    match body {
+        TransactionBody::Stake(_) => BalanceChangeCause::Stake,
+        TransactionBody::Delegate(t) => transaction_cause(&t.transaction.body),
+        _ => BalanceChangeCause::Transfer,
+    }
+}
+
+/// Splits the tip burnt from the originator off the other changes of the transaction.
+pub fn transaction_balance_changes(
+    body: &TransactionBody,
+    tip: Balance,
+    changes: &[AmountChange],
+) -> Vec<BalanceChange> {
+    let originator = body.get_originator();
+    let mut result = vec![];
+    for change in changes {
+        let mut rest = delta(change);
+        if change.account_id == originator && tip > 0 {
+            result.push(balance_change(&originator, BalanceChangeCause::Fee, -i128::from(tip)));
+            rest += i128::from(tip);
+        }
+        if rest != 0 {
+            result.push(balance_change(&change.account_id, transaction_cause(body), rest));
+        }
+    }
+    result
+}
+
+pub fn receipt_balance_changes(
+    receipt: &ReceiptTransaction,
+    changes: &[AmountChange],
+) -> Vec<BalanceChange> {
+    let cause = match receipt.body {
+        ReceiptBody::Refund(_) => BalanceChangeCause::Refund,
+        _ => BalanceChangeCause::Transfer,
+    };
+    changes
+        .iter()
+        .map(|change| balance_change(&change.account_id, cause, delta(change)))
+        .collect()
+}
@@ -0,0 +1,81 @@
+use primitives::types::{AccountId, Balance};
+use storage::StateDbUpdate;
+use transaction::{ReceiptBody, ReceiptTransaction};
+
+use super::{get, Account, COL_ACCOUNT};
+
+/// Amount of money carried by the receipt.
+pub fn receipt_amount(receipt: &ReceiptTransaction) -> Balance {
+    match &receipt.body {
+        ReceiptBody::NewCall(call) => call.amount,
+        ReceiptBody::Refund(amount) => *amount,
+        ReceiptBody::Callback(_) | ReceiptBody::ManaAccounting(_) => 0,
+    }
+}
+
+fn total_balance(state_update: &mut StateDbUpdate, key: &[u8]) -> u128 {
+    get::<Account>(state_update, key)
+        .map(|account| u128::from(account.amount) + u128::from(account.staked))
+        .unwrap_or(0)
+}
+
+/// Checks that the accounts changed in `final_state` together with the outgoing receipts and
+/// the burnt tips hold exactly as much money as they had in `initial_state` plus the incoming
+/// receipts. Runtime doesn't mint rewards yet, so nothing else can change the total.
+pub fn check_balance_conservation(
+    initial_state: &mut StateDbUpdate,
+    final_state: &mut StateDbUpdate,
+    incoming_amount: Balance,
+    outgoing_amount: Balance,
+    burnt_amount: Balance,
+) -> Result<(), String> {
+    let mut initial_total = u128::from(incoming_amount);
+    let mut final_total = u128::from(outgoing_amount) + u128::from(burnt_amount);
+    let mut changes = vec![];
+    for key in final_state.changed_keys_with_prefix(COL_ACCOUNT) {
+        let initial_balance = total_balance(initial_state, &key);
+        let final_balance = total_balance(final_state, &key);
+        if initial_balance != final_balance {
+            let account_id: AccountId = String::from_utf8_lossy(&key[COL_ACCOUNT.len()..]).to_string();
+            changes.push(format!("{}: {} -> {}", account_id, initial_balance, final_balance));
+        }
+        initial_total += initial_balance;
+        final_total += final_balance;
+    }
+    if initial_total == final_total {
+        Ok(())
+    } else {
+        Err(format!(
+            "balance is not conserved: {} before (incoming receipts {}), {} after (outgoing receipts {}, burnt {}), changed accounts:\n{}",
+            initial_total,
+            incoming_amount,
+            final_total,
+            outgoing_amount,
+            burnt_amount,
+            changes.join("\n"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::get_runtime_and_state_db_viewer;
+    use crate::{account_id_to_bytes, set};
+
+    use super::*;
+
+    #[test]
+    fn test_balance_not_conserved() {
+        let (runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        let key = account_id_to_bytes(COL_ACCOUNT, &"alice.near".to_string());
+        let mut initial_state = StateDbUpdate::new(runtime.state_db.clone(), root);
+        let mut final_state = StateDbUpdate::new(runtime.state_db.clone(), root);
+        let mut account: Account = get(&mut final_state, &key).unwrap();
+        account.amount -= 10;
+        set(&mut final_state, &key, &account);
+        final_state.commit();
+        assert!(check_balance_conservation(&mut initial_state, &mut final_state, 0, 0, 0).is_err());
+        assert!(check_balance_conservation(&mut initial_state, &mut final_state, 0, 10, 0).is_ok());
+        assert!(check_balance_conservation(&mut initial_state, &mut final_state, 0, 5, 5).is_ok());
+    }
+}
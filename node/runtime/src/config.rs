@@ -0,0 +1,102 @@
+use primitives::hash::CryptoHash;
+use primitives::types::{BlockIndex, ProtocolVersion};
+use transaction::PROTOCOL_VERSION;
+
+/// Which transaction nonces are accepted given the nonce of the originator account.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoncePolicy {
+    /// Nonce must be larger than the account nonce. Gaps are allowed.
+    Increasing,
+    /// Nonce must be exactly the account nonce plus one.
+    StrictIncrement,
+    /// Nonce must be larger than the account nonce, but by no more than the given gap.
+    BoundedGap(u64),
+}
+
+impl NoncePolicy {
+    pub fn is_valid(&self, tx_nonce: u64, account_nonce: u64) -> bool {
+        if tx_nonce <= account_nonce {
+            return false;
+        }
+        match self {
+            NoncePolicy::Increasing => true,
+            NoncePolicy::StrictIncrement => tx_nonce == account_nonce + 1,
+            NoncePolicy::BoundedGap(max_gap) => tx_nonce - account_nonce <= *max_gap,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RuntimeConfig {
+    pub nonce_policy: NoncePolicy,
+    /// Whether to check after every apply that no money was created or destroyed.
+    /// Panics with the diagnostics if the check fails.
+    pub check_balance_conservation: bool,
+    /// Number of blocks after the referenced block during which a transaction is valid.
+    /// `None` disables the expiry check.
+    pub transaction_validity_period: Option<BlockIndex>,
+    /// Genesis hash that transactions must be signed for, so that they can't be replayed
+    /// on another chain. `None` accepts transactions for any chain.
+    pub genesis_hash: Option<CryptoHash>,
+    /// Whether transactions must be signed with one of the originator public keys.
+    pub verify_signatures: bool,
+    /// Maximum number of receivers of a single multi send money transaction.
+    pub max_transfers_per_transaction: usize,
+    /// Maximum size in bytes of the memo attached to a send money transaction.
+    pub max_memo_length: usize,
+    /// Number of blocks a recovery proposed by a guardian waits before it replaces the
+    /// account keys. The owner can cancel it during this time.
+    pub recovery_delay: BlockIndex,
+    /// Maximum number of guardians of an account, each of them can propose a recovery.
+    pub max_guardians: usize,
+    /// Maximum number of blocks a transaction can be scheduled ahead of the block it is
+    /// included in, so that the queue of the scheduled transactions can't be filled for good.
+    pub max_schedule_horizon: BlockIndex,
+    /// Maximum size in bytes of a signed transaction encoded as a proto message.
+    pub max_transaction_size: usize,
+    /// Maximum size in bytes of the arguments of a function call or a promise.
+    pub max_args_size: usize,
+    /// Maximum size in bytes of an encoded receipt created by a contract.
+    pub max_receipt_size: usize,
+    /// Protocol version the chain runs. Transactions of the kinds introduced by later versions
+    /// are rejected, the receipts they would create can't appear.
+    pub protocol_version: ProtocolVersion,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        RuntimeConfig {
+            nonce_policy: NoncePolicy::Increasing,
+            check_balance_conservation: cfg!(debug_assertions),
+            transaction_validity_period: None,
+            genesis_hash: None,
+            verify_signatures: true,
+            max_transfers_per_transaction: 100,
+            max_memo_length: 256,
+            recovery_delay: 100,
+            max_guardians: 10,
+            max_schedule_horizon: 10_000_000,
+            max_transaction_size: 4 * 1024 * 1024,
+            max_args_size: 1024 * 1024,
+            max_receipt_size: 4 * 1024 * 1024,
+            protocol_version: PROTOCOL_VERSION,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nonce_policy() {
+        assert!(!NoncePolicy::Increasing.is_valid(5, 5));
+        assert!(NoncePolicy::Increasing.is_valid(100, 5));
+        assert!(NoncePolicy::StrictIncrement.is_valid(6, 5));
+        assert!(!NoncePolicy::StrictIncrement.is_valid(7, 5));
+        assert!(!NoncePolicy::StrictIncrement.is_valid(4, 5));
+        assert!(NoncePolicy::BoundedGap(3).is_valid(8, 5));
+        assert!(!NoncePolicy::BoundedGap(3).is_valid(9, 5));
+        assert!(!NoncePolicy::BoundedGap(3).is_valid(5, 5));
+    }
+}
@@ -3,6 +3,7 @@ use std::iter::Peekable;
 
 use kvdb::DBValue;
 
+use primitives::borsh;
 use primitives::hash::CryptoHash;
 use primitives::types::{
     AccountId, AccountingInfo, Balance, CallbackId,
@@ -12,8 +13,17 @@ use transaction::{AsyncCall, ReceiptTransaction, Callback, CallbackInfo, Receipt
 use storage::{StateDbUpdate, StateDbUpdateIterator};
 use wasm::ext::{External, Result as ExtResult, Error as ExtError};
 
+use crate::config::RuntimeConfig;
+
 use super::{account_id_to_bytes, create_nonce_with_nonce, COL_ACCOUNT, callback_id_to_bytes, set};
 
+/// Prefix of the keys of the contract data of the account.
+pub fn storage_prefix(account_id: &AccountId) -> Vec<u8> {
+    let mut prefix = account_id_to_bytes(COL_ACCOUNT, account_id);
+    prefix.append(&mut b",".to_vec());
+    prefix
+}
+
 pub struct RuntimeExt<'a> {
     state_db_update: &'a mut StateDbUpdate,
     storage_prefix: Vec<u8>,
@@ -25,6 +35,7 @@ pub struct RuntimeExt<'a> {
     transaction_hash: &'a CryptoHash,
     iters: HashMap<u32, Peekable<StateDbUpdateIterator<'a>>>,
     last_iter_id: u32,
+    config: &'a RuntimeConfig,
 }
 
 impl<'a> RuntimeExt<'a> {
@@ -32,13 +43,12 @@ impl<'a> RuntimeExt<'a> {
         state_db_update: &'a mut StateDbUpdate,
         account_id: &AccountId,
         accounting_info: &AccountingInfo,
-        transaction_hash: &'a CryptoHash
+        transaction_hash: &'a CryptoHash,
+        config: &'a RuntimeConfig,
     ) -> Self {
-        let mut prefix = account_id_to_bytes(COL_ACCOUNT, account_id);
-        prefix.append(&mut b",".to_vec());
         RuntimeExt { 
             state_db_update,
-            storage_prefix: prefix,
+            storage_prefix: storage_prefix(account_id),
             receipts: HashMap::new(),
             callbacks: HashMap::new(),
             account_id: account_id.clone(),
@@ -47,6 +57,7 @@ impl<'a> RuntimeExt<'a> {
             transaction_hash,
             iters: HashMap::new(),
             last_iter_id: 0,
+            config,
         }
     }
 
@@ -152,6 +163,9 @@ impl<'a> External for RuntimeExt<'a> {
         mana: Mana,
         amount: Balance,
     ) -> ExtResult<PromiseId> {
+        if arguments.len() > self.config.max_args_size {
+            return Err(ExtError::ArgumentsTooLarge);
+        }
         let nonce = self.create_nonce();
         let receipt = ReceiptTransaction::new(
             self.account_id.clone(),
@@ -165,6 +179,10 @@ impl<'a> External for RuntimeExt<'a> {
                 self.accounting_info.clone(),
             )),
         );
+        let size = borsh::to_vec(&receipt).map_err(|_| ExtError::ReceiptTooLarge)?.len();
+        if size > self.config.max_receipt_size {
+            return Err(ExtError::ReceiptTooLarge);
+        }
         let promise_id = PromiseId::Receipt(nonce.as_ref().to_vec());
         self.receipts.insert(nonce.as_ref().to_vec(), receipt);
         Ok(promise_id)
@@ -177,6 +195,9 @@ impl<'a> External for RuntimeExt<'a> {
         arguments: Vec<u8>,
         mana: Mana,
     ) -> ExtResult<PromiseId> {
+        if arguments.len() > self.config.max_args_size {
+            return Err(ExtError::ArgumentsTooLarge);
+        }
         let callback_id = self.create_nonce();
         let receipt_ids = match promise_id {
             PromiseId::Receipt(r) => vec![r],
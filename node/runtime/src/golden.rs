@@ -0,0 +1,264 @@
+//! Golden state roots: canonical sequences of blocks applied from their genesis, with the state
+//! roots they gave when they were recorded. They are kept in `res/golden`, a file per protocol
+//! version, and are replayed with the runtime at their version, so that a change to the
+//! execution that changes the state is not made unknowingly. The fixtures of the current
+//! protocol version are recorded with `NEAR_UPDATE_GOLDEN=1 cargo test -p node-runtime golden`,
+//! the ones of the earlier versions are never recorded again.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use primitives::hash::CryptoHash;
+use primitives::test_utils::{get_bls_key_pair_from_seed, get_key_pair_from_seed};
+use primitives::types::{AccountId, Balance, MerkleHash, ProtocolVersion};
+use transaction::{
+    CreateAccountTransaction, FunctionCallTransaction, SendMoneyTransaction, SignedTransaction,
+    StakeTransaction, TransactionBody, PROTOCOL_VERSION,
+};
+
+use crate::config::RuntimeConfig;
+use crate::test_utils::{sign_transaction, to_receipt_block, GenesisBuilder};
+use crate::ApplyState;
+
+/// Code of the genesis accounts that have one.
+const CONTRACT: &[u8] = include_bytes!("../../../core/wasm/runtest/res/wasm_with_mem.wasm");
+
+/// Transactions of the blocks of a fixture.
+type Blocks = Vec<Vec<SignedTransaction>>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct FixtureAccount {
+    account_id: AccountId,
+    amount: Balance,
+    stake: Balance,
+    tx_stake: Balance,
+    /// Whether the account has `CONTRACT` deployed.
+    code: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Fixture {
+    name: String,
+    accounts: Vec<FixtureAccount>,
+    /// Transactions of the blocks. The receipts a block spawns are applied in the next one.
+    blocks: Blocks,
+    /// Root of the genesis state, then of the state after each of the blocks.
+    state_roots: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GoldenFile {
+    protocol_version: ProtocolVersion,
+    fixtures: Vec<Fixture>,
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("res/golden")
+}
+
+fn golden_path(protocol_version: ProtocolVersion) -> PathBuf {
+    golden_dir().join(format!("v{}.json", protocol_version))
+}
+
+fn account(account_id: &str, amount: Balance, code: bool) -> FixtureAccount {
+    FixtureAccount { account_id: account_id.to_string(), amount, stake: 0, tx_stake: 1_000, code }
+}
+
+fn sign(body: TransactionBody) -> SignedTransaction {
+    let (_, secret_key) = get_key_pair_from_seed(&body.get_originator());
+    sign_transaction(body, &secret_key)
+}
+
+fn send_money(originator: &str, nonce: u64, receiver: &str, amount: Balance) -> SignedTransaction {
+    sign(TransactionBody::SendMoney(SendMoneyTransaction {
+        nonce,
+        originator: originator.to_string(),
+        receiver: receiver.to_string(),
+        amount,
+        block_hash: CryptoHash::default(),
+        genesis_hash: CryptoHash::default(),
+        execute_at: 0,
+        tip: 0,
+        memo: vec![],
+    }))
+}
+
+fn create_account(originator: &str, nonce: u64, new_account_id: &str) -> SignedTransaction {
+    sign(TransactionBody::CreateAccount(CreateAccountTransaction {
+        nonce,
+        originator: originator.to_string(),
+        new_account_id: new_account_id.to_string(),
+        amount: 30,
+        public_key: get_key_pair_from_seed(new_account_id).0.to_bytes(),
+        block_hash: CryptoHash::default(),
+        genesis_hash: CryptoHash::default(),
+        execute_at: 0,
+        tip: 0,
+    }))
+}
+
+fn stake(originator: &str, nonce: u64, amount: Balance) -> SignedTransaction {
+    let (bls_public_key, bls_secret_key) = get_bls_key_pair_from_seed(originator);
+    sign(TransactionBody::Stake(StakeTransaction {
+        nonce,
+        originator: originator.to_string(),
+        amount,
+        block_hash: CryptoHash::default(),
+        genesis_hash: CryptoHash::default(),
+        execute_at: 0,
+        tip: 0,
+        bls_public_key: Vec::from(&bls_public_key),
+        bls_proof_of_possession: Vec::from(&bls_secret_key.get_proof_of_possession()),
+    }))
+}
+
+fn call(
+    originator: &str,
+    nonce: u64,
+    contract_id: &str,
+    method_name: &str,
+    amount: Balance,
+) -> SignedTransaction {
+    sign(TransactionBody::FunctionCall(FunctionCallTransaction {
+        nonce,
+        originator: originator.to_string(),
+        contract_id: contract_id.to_string(),
+        method_name: method_name.as_bytes().to_vec(),
+        args: vec![],
+        amount,
+        block_hash: CryptoHash::default(),
+        genesis_hash: CryptoHash::default(),
+        execute_at: 0,
+        tip: 0,
+    }))
+}
+
+/// Fixtures recorded for the current protocol version, without their roots. Each ends with
+/// blocks without transactions, in which the receipts of the earlier ones are applied.
+fn canonical_fixtures() -> Vec<(&'static str, Vec<FixtureAccount>, Blocks)> {
+    vec![
+        (
+            "transfers",
+            vec![account("alice.near", 1_000, false), account("bob.near", 100, false)],
+            vec![
+                vec![
+                    send_money("alice.near", 1, "bob.near", 10),
+                    send_money("bob.near", 1, "alice.near", 5),
+                    // Refunded, the receiver doesn't exist.
+                    send_money("alice.near", 2, "missing.near", 20),
+                    // Fails, more than the balance.
+                    send_money("bob.near", 2, "alice.near", 1_000),
+                ],
+                vec![create_account("alice.near", 3, "carol.near"), stake("alice.near", 4, 100)],
+                vec![send_money("alice.near", 5, "carol.near", 7)],
+                vec![],
+                vec![],
+            ],
+        ),
+        (
+            "contracts",
+            vec![account("alice.near", 1_000, true), account("bob.near", 1_000, true)],
+            vec![
+                vec![
+                    call("alice.near", 1, "bob.near", "run_test_with_storage_change", 0),
+                    call("bob.near", 1, "alice.near", "run_test", 10),
+                    // Refunded, the method doesn't exist.
+                    call("alice.near", 2, "bob.near", "missing_method", 20),
+                ],
+                vec![call("alice.near", 3, "bob.near", "run_test_with_storage_change", 0)],
+                vec![],
+                vec![],
+            ],
+        ),
+    ]
+}
+
+/// Roots of the genesis state of the accounts and of the states after the blocks, applied with
+/// the runtime at the protocol version.
+fn replay(
+    protocol_version: ProtocolVersion,
+    accounts: &[FixtureAccount],
+    blocks: &[Vec<SignedTransaction>],
+) -> Vec<MerkleHash> {
+    let mut config = RuntimeConfig::default();
+    config.protocol_version = protocol_version;
+    let genesis = accounts.iter().fold(GenesisBuilder::new().config(config), |genesis, account| {
+        let genesis = genesis
+            .account(&account.account_id, account.amount)
+            .stake(&account.account_id, account.stake)
+            .tx_stake(&account.account_id, account.tx_stake);
+        if account.code {
+            genesis.code(&account.account_id, CONTRACT)
+        } else {
+            genesis
+        }
+    });
+    let (mut runtime, _, mut root) = genesis.build();
+    let mut roots = vec![root];
+    let mut receipts = vec![];
+    for (index, transactions) in blocks.iter().enumerate() {
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            block_index: index as u64 + 1,
+            parent_block_hash: CryptoHash::default(),
+        };
+        let result =
+            runtime.apply(&apply_state, &[to_receipt_block(receipts)], transactions).unwrap();
+        runtime.state_db.commit(result.db_changes).unwrap();
+        root = result.root;
+        roots.push(root);
+        receipts = result.new_receipts.into_iter().flat_map(|(_, receipts)| receipts).collect();
+    }
+    roots
+}
+
+fn record(path: &Path) {
+    let fixtures = canonical_fixtures()
+        .into_iter()
+        .map(|(name, accounts, blocks)| {
+            let state_roots = replay(PROTOCOL_VERSION, &accounts, &blocks)
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            Fixture { name: name.to_string(), accounts, blocks, state_roots }
+        })
+        .collect();
+    let golden = GoldenFile { protocol_version: PROTOCOL_VERSION, fixtures };
+    fs::create_dir_all(golden_dir()).unwrap();
+    fs::write(path, serde_json::to_string_pretty(&golden).unwrap() + "\n").unwrap();
+}
+
+#[test]
+fn test_golden_state_roots() {
+    let path = golden_path(PROTOCOL_VERSION);
+    if env::var("NEAR_UPDATE_GOLDEN").is_ok() {
+        record(&path);
+    }
+    assert!(
+        path.exists(),
+        "No golden state roots for protocol version {}, record them with NEAR_UPDATE_GOLDEN=1",
+        PROTOCOL_VERSION
+    );
+    for entry in fs::read_dir(golden_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        let golden: GoldenFile = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(path, golden_path(golden.protocol_version));
+        for fixture in golden.fixtures.iter() {
+            let roots: Vec<String> =
+                replay(golden.protocol_version, &fixture.accounts, &fixture.blocks)
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+            let divergent =
+                roots.iter().zip(fixture.state_roots.iter()).position(|(root, expected)| {
+                    root != expected
+                });
+            assert_eq!(
+                roots, fixture.state_roots,
+                "Fixture {} of protocol version {} gives other state roots from block {:?}",
+                fixture.name, golden.protocol_version, divergent
+            );
+        }
+    }
+}
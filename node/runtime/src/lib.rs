@@ -13,49 +13,78 @@ extern crate wasm;
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use byteorder::{BigEndian, ByteOrder};
 use serde::{de::DeserializeOwned, Serialize};
 
+use primitives::aggregate_signature::{BlsPublicKey, BlsSignature};
+use primitives::borsh;
 use primitives::hash::{CryptoHash, hash};
-use primitives::signature::PublicKey;
-use primitives::traits::{Decode, Encode};
+use primitives::serialize::{from_state_value, to_state_value};
+use primitives::signature::{verify_batch, PublicKey};
 use primitives::types::{
     AccountId, AccountingInfo, AuthorityStake,
-    Balance, BlockIndex, Mana,
-    ManaAccounting, MerkleHash, PromiseId, ReadablePublicKey, ShardId,
+    Balance, BlockIndex, Gas, Mana,
+    ManaAccounting, MerkleHash, PromiseId, ReadableBlsPublicKey, ReadablePublicKey, ShardId,
 };
 use primitives::utils::{
     account_to_shard_id, index_to_bytes, is_valid_account_id
 };
 use storage::{StateDb, StateDbUpdate};
 use transaction::{
-    AsyncCall, Callback, CallbackInfo, CallbackResult, CreateAccountTransaction,
-    DeployContractTransaction, FunctionCallTransaction, LogEntry, ReceiptBody,
-    ReceiptTransaction, SendMoneyTransaction, SignedTransaction, StakeTransaction,
-    SwapKeyTransaction, TransactionBody, TransactionResult, TransactionStatus
+    ActionError, AsyncCall, Callback, CallbackInfo, CallbackResult, CancelRecoveryTransaction,
+    CreateAccountTransaction, CreateContractAccountTransaction, DelegateTransaction,
+    DeployContractTransaction, FunctionCallTransaction, InvalidTxError, LogEntry,
+    MultiSendMoneyTransaction,
+    ProposeRecoveryTransaction, ReceiptBody, ReceiptTransaction, RotateKeyTransaction, RuntimeError,
+    SendMoneyTransaction, SetGuardiansTransaction, SetKeyPermissionsTransaction,
+    SignaturePolicyTransaction, SignedTransaction, StakeTransaction, SwapKeyTransaction,
+    TransactionBody, TransactionKind, TransactionResult, TransactionStatus, transaction_signers,
 };
 use wasm::executor;
 use wasm::types::{ReturnData, RuntimeContext};
 use chain::ReceiptBlock;
 
+use crate::config::RuntimeConfig;
 use crate::ext::RuntimeExt;
 use crate::tx_stakes::{get_tx_stake_key, TxStakeConfig, TxTotalStake};
 
+pub mod config;
 pub mod test_utils;
+pub mod state_changes;
 pub mod state_viewer;
+pub mod schema;
+#[cfg(test)]
+mod golden;
 mod tx_stakes;
 mod ext;
+mod balance_changes;
+mod balance_checker;
 
 const COL_ACCOUNT: &[u8] = &[0];
 const COL_CALLBACK: &[u8] = &[1];
 const COL_CODE: &[u8] = &[2];
 const COL_TX_STAKE: &[u8] = &[3];
 const COL_TX_STAKE_SEPARATOR: &[u8] = &[4];
+const COL_BLOCK_HASH: &[u8] = &[5];
+const COL_BLOCK_INDEX: &[u8] = &[6];
+const COL_SCHEDULED: &[u8] = &[7];
+const COL_RECOVERY: &[u8] = &[8];
+const COL_RECOVERY_QUEUE: &[u8] = &[9];
 
 /// const does not allow function call, so have to resort to this
 fn system_account() -> AccountId { "system".to_string() }
 
 const SYSTEM_METHOD_CREATE_ACCOUNT: &[u8] = b"_sys:create_account";
 const SYSTEM_METHOD_DEPLOY: &[u8] = b"_sys:deploy";
+const SYSTEM_METHOD_CREATE_CONTRACT_ACCOUNT: &[u8] = b"_sys:create_contract_account";
+const SYSTEM_METHOD_PROPOSE_RECOVERY: &[u8] = b"_sys:propose_recovery";
+
+/// Transaction kinds a public key of an account is restricted to.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct KeyPermission {
+    pub public_key: PublicKey,
+    pub allowed: Vec<TransactionKind>,
+}
 
 /// Per account information stored in the state.
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
@@ -66,11 +95,34 @@ pub struct Account {
     pub amount: u64,
     pub staked: u64,
     pub code_hash: CryptoHash,
+    /// Number of the public keys that have to sign a transaction of this account.
+    pub signature_threshold: u32,
+    /// Accounts that can propose to replace the keys of this account.
+    pub guardians: Vec<AccountId>,
+    /// Restrictions of the public keys. Keys without one may sign any transaction.
+    pub key_permissions: Vec<KeyPermission>,
 }
 
 impl Account {
     pub fn new(public_keys: Vec<PublicKey>, amount: Balance, code_hash: CryptoHash) -> Self {
-        Account { public_keys, nonce: 0, amount, staked: 0, code_hash }
+        Account {
+            public_keys,
+            nonce: 0,
+            amount,
+            staked: 0,
+            code_hash,
+            signature_threshold: 1,
+            guardians: vec![],
+            key_permissions: vec![],
+        }
+    }
+
+    /// Whether the public key may sign transactions of the given kind.
+    pub fn key_allows(&self, public_key: &PublicKey, kind: TransactionKind) -> bool {
+        self.key_permissions
+            .iter()
+            .find(|p| &p.public_key == public_key)
+            .map_or(true, |p| p.allowed.contains(&kind))
     }
 }
 
@@ -86,6 +138,55 @@ fn callback_id_to_bytes(id: &[u8]) -> Vec<u8> {
     key
 }
 
+fn block_hash_to_bytes(block_hash: &CryptoHash) -> Vec<u8> {
+    let mut key = COL_BLOCK_HASH.to_vec();
+    key.extend_from_slice(block_hash.as_ref());
+    key
+}
+
+fn block_index_to_bytes(block_index: BlockIndex) -> Vec<u8> {
+    let mut key = COL_BLOCK_INDEX.to_vec();
+    key.append(&mut index_to_bytes(block_index));
+    key
+}
+
+/// Big-endian index, so that the keys with the index sort by it.
+fn sorted_index_to_bytes(index: BlockIndex) -> [u8; 8] {
+    let mut bytes = [0; 8];
+    BigEndian::write_u64(&mut bytes, index);
+    bytes
+}
+
+fn scheduled_transaction_key(execute_at: BlockIndex, hash: &CryptoHash) -> Vec<u8> {
+    let mut key = COL_SCHEDULED.to_vec();
+    key.extend_from_slice(&sorted_index_to_bytes(execute_at));
+    key.extend_from_slice(hash.as_ref());
+    key
+}
+
+fn recovery_queue_key(finalize_at: BlockIndex, account_id: &AccountId) -> Vec<u8> {
+    let mut key = COL_RECOVERY_QUEUE.to_vec();
+    key.extend_from_slice(&sorted_index_to_bytes(finalize_at));
+    key.extend_from_slice(account_id.as_bytes());
+    key
+}
+
+/// Key replacement proposed by a guardian, waiting for the end of the recovery delay.
+#[derive(Serialize, Deserialize)]
+struct PendingRecovery {
+    guardian: AccountId,
+    public_key: PublicKey,
+    finalize_at: BlockIndex,
+}
+
+/// Transaction that was accepted before its `execute_at` block and waits for it in the state.
+#[derive(Serialize, Deserialize)]
+struct ScheduledTransaction {
+    transaction: SignedTransaction,
+    accounting_info: AccountingInfo,
+    mana: Mana,
+}
+
 fn create_nonce_with_nonce(base: &CryptoHash, salt: u64) -> CryptoHash {
     let mut nonce: Vec<u8> = base.as_ref().to_owned();
     nonce.append(&mut index_to_bytes(salt));
@@ -107,27 +208,117 @@ pub struct ApplyResult {
     pub db_changes: storage::DBChanges,
     pub authority_proposals: Vec<AuthorityStake>,
     pub new_receipts: HashMap<ShardId, Vec<ReceiptTransaction>>,
+    /// Results of the receipts, then of the transactions, then of the scheduled
+    /// transactions that became due.
+    pub tx_result: Vec<TransactionResult>,
+    /// Mana accounting refunds dropped because the TX stake they refund doesn't exist.
+    pub dropped_mana_refunds: usize,
+}
+
+/// Result of `apply_streaming`. Transaction results and new receipts are not included,
+/// since they are handed to the callbacks while applying.
+#[derive(Clone, Debug)]
+pub struct StreamingApplyResult {
+    pub root: MerkleHash,
+    pub shard_id: ShardId,
+    pub db_changes: storage::DBChanges,
+    pub authority_proposals: Vec<AuthorityStake>,
+    /// Mana accounting refunds dropped because the TX stake they refund doesn't exist.
+    pub dropped_mana_refunds: usize,
+}
+
+/// Would-be outcome of applying transactions, without the db changes.
+#[derive(Clone, Debug)]
+pub struct SimulateResult {
+    pub root: MerkleHash,
+    pub shard_id: ShardId,
+    pub authority_proposals: Vec<AuthorityStake>,
+    pub new_receipts: HashMap<ShardId, Vec<ReceiptTransaction>>,
     pub tx_result: Vec<TransactionResult>,
 }
 
+/// Would-be outcome of a function call, see `Runtime::simulate_function_call`.
+#[derive(Clone, Debug)]
+pub struct FunctionCallSimulation {
+    /// Value returned by the method, empty if it returned nothing or a promise.
+    pub result: Vec<u8>,
+    pub logs: Vec<LogEntry>,
+    /// Receipts created by the method.
+    pub receipts: Vec<ReceiptTransaction>,
+    pub gas_used: Gas,
+}
+
 fn get<T: DeserializeOwned>(state_update: &mut StateDbUpdate, key: &[u8]) -> Option<T> {
-    state_update.get(key).and_then(|data| Decode::decode(&data).ok())
+    state_update.get(key).and_then(|data| from_state_value(&data).ok())
+}
+
+/// Adds the key to the keys of the account, which must not have it yet, so that every key
+/// identifies a single entry.
+fn add_public_key(
+    public_keys: &mut Vec<PublicKey>,
+    account_id: &AccountId,
+    public_key: PublicKey,
+) -> Result<(), ActionError> {
+    if public_keys.contains(&public_key) {
+        return Err(ActionError::PublicKeyAlreadyExists {
+            account_id: account_id.clone(),
+            public_key,
+        });
+    }
+    public_keys.push(public_key);
+    Ok(())
 }
 
 fn set<T: Serialize>(state_update: &mut StateDbUpdate, key: &[u8], value: &T) {
-    value
-        .encode().ok()
+    to_state_value(value)
+        .ok()
         .map(|data| state_update.set(key, &storage::DBValue::from_slice(&data)))
         .unwrap_or_else(|| { debug!("set value failed"); })
 }
 
+/// Maximum number of contracts kept in the runtime code cache.
+const CODE_CACHE_SIZE: usize = 128;
+
 pub struct Runtime {
     pub state_db: Arc<StateDb>,
+    /// Contract code by its hash, so that every call doesn't read and copy the code again.
+    code_cache: HashMap<CryptoHash, Arc<[u8]>>,
+    /// Single key of the originator that signed each transaction of the block being applied, by
+    /// the transaction hash, verified in a batch before applying them.
+    verified_signers: HashMap<CryptoHash, PublicKey>,
+    /// Mana accounting refunds dropped in the block being applied.
+    dropped_mana_refunds: usize,
+    pub config: RuntimeConfig,
 }
 
 impl Runtime {
     pub fn new(state_db: Arc<StateDb>) -> Self {
-        Runtime { state_db }
+        Runtime {
+            state_db,
+            code_cache: HashMap::new(),
+            verified_signers: HashMap::new(),
+            dropped_mana_refunds: 0,
+            config: RuntimeConfig::default(),
+        }
+    }
+
+    fn get_code(
+        &mut self,
+        state_update: &mut StateDbUpdate,
+        account_id: &AccountId,
+        code_hash: &CryptoHash,
+    ) -> Result<Arc<[u8]>, ActionError> {
+        if let Some(code) = self.code_cache.get(code_hash) {
+            return Ok(code.clone());
+        }
+        let code: Vec<u8> = get(state_update, &account_id_to_bytes(COL_CODE, account_id))
+            .ok_or_else(|| ActionError::ContractCodeNotFound(account_id.clone()))?;
+        let code = Arc::<[u8]>::from(code);
+        if self.code_cache.len() >= CODE_CACHE_SIZE {
+            self.code_cache.clear();
+        }
+        self.code_cache.insert(*code_hash, code.clone());
+        Ok(code)
     }
 
     fn try_charge_mana(
@@ -177,38 +368,87 @@ impl Runtime {
         hash: CryptoHash,
         sender: &mut Account,
         accounting_info: AccountingInfo,
-    ) -> Result<Vec<ReceiptTransaction>, String> {
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
         if transaction.amount == 0 {
-            return Err("Sending 0 amount of money".to_string());
+            return Err(ActionError::ZeroTransfer);
         }
         if sender.amount >= transaction.amount {
             sender.amount -= transaction.amount;
             set(state_update, &account_id_to_bytes(COL_ACCOUNT, &transaction.originator), sender);
+            let mut deposit = AsyncCall::new(
+                // Empty method name is used for deposit
+                vec![],
+                vec![],
+                transaction.amount,
+                0,
+                accounting_info,
+            );
+            deposit.memo = transaction.memo.clone();
             let receipt = ReceiptTransaction::new(
                 transaction.originator.clone(),
                 transaction.receiver.clone(),
                 create_nonce_with_nonce(&hash, 0),
+                ReceiptBody::NewCall(deposit),
+            );
+            Ok(vec![receipt])
+        } else {
+            Err(ActionError::InsufficientBalance {
+                account_id: transaction.originator.clone(),
+                amount: transaction.amount,
+                balance: sender.amount,
+                staked: sender.staked,
+            })
+        }
+    }
+
+    fn multi_send_money(
+        &self,
+        state_update: &mut StateDbUpdate,
+        transaction: &MultiSendMoneyTransaction,
+        hash: CryptoHash,
+        sender: &mut Account,
+        accounting_info: AccountingInfo,
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
+        let limit = self.config.max_transfers_per_transaction;
+        if transaction.transfers.len() > limit {
+            return Err(ActionError::TooManyTransfers {
+                count: transaction.transfers.len(),
+                limit,
+            });
+        }
+        let mut total_amount: Balance = 0;
+        for transfer in transaction.transfers.iter() {
+            if transfer.amount == 0 {
+                return Err(ActionError::ZeroTransfer);
+            }
+            total_amount = total_amount.saturating_add(transfer.amount);
+        }
+        if sender.amount < total_amount {
+            return Err(ActionError::InsufficientBalance {
+                account_id: transaction.originator.clone(),
+                amount: total_amount,
+                balance: sender.amount,
+                staked: sender.staked,
+            });
+        }
+        sender.amount -= total_amount;
+        set(state_update, &account_id_to_bytes(COL_ACCOUNT, &transaction.originator), sender);
+        let receipts = transaction.transfers.iter().enumerate().map(|(i, transfer)| {
+            ReceiptTransaction::new(
+                transaction.originator.clone(),
+                transfer.receiver.clone(),
+                create_nonce_with_nonce(&hash, i as u64),
                 ReceiptBody::NewCall(AsyncCall::new(
                     // Empty method name is used for deposit
                     vec![],
                     vec![],
-                    transaction.amount,
+                    transfer.amount,
                     0,
-                    accounting_info,
+                    accounting_info.clone(),
                 ))
-            );
-            Ok(vec![receipt])
-        } else {
-            Err(
-                format!(
-                    "Account {} tries to send {}, but has staked {} and only has {}",
-                    transaction.originator,
-                    transaction.amount,
-                    sender.staked,
-                    sender.amount,
-                )
             )
-        }
+        }).collect();
+        Ok(receipts)
     }
 
     fn staking(
@@ -218,11 +458,19 @@ impl Runtime {
         sender_account_id: &AccountId,
         sender: &mut Account,
         authority_proposals: &mut Vec<AuthorityStake>,
-    ) -> Result<Vec<ReceiptTransaction>, String> {
-        if sender.amount >= body.amount && sender.public_keys.is_empty() {
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
+        // The signatures of the authorities are aggregated, so every key must be proven to be
+        // owned rather than derived from the keys of the other authorities.
+        let bls_public_key =
+            BlsPublicKey::from_bytes(&body.bls_public_key).ok_or(ActionError::InvalidPublicKey)?;
+        BlsSignature::from_bytes(&body.bls_proof_of_possession)
+            .filter(|proof| bls_public_key.verify_proof_of_possession(proof))
+            .ok_or_else(|| ActionError::InvalidProofOfPossession(body.originator.clone()))?;
+        if sender.amount >= body.amount && !sender.public_keys.is_empty() {
             authority_proposals.push(AuthorityStake {
                 account_id: sender_account_id.clone(),
                 public_key: sender.public_keys[0],
+                bls_public_key,
                 amount: body.amount,
             });
             sender.amount -= body.amount;
@@ -230,17 +478,43 @@ impl Runtime {
             set(state_update, &account_id_to_bytes(COL_ACCOUNT, sender_account_id), &sender);
             Ok(vec![])
         } else if sender.amount < body.amount {
-            let err_msg = format!(
-                "Account {} tries to stake {}, but has staked {} and only has {}",
-                body.originator,
-                body.amount,
-                sender.staked,
-                sender.amount,
-            );
-            Err(err_msg)
+            Err(ActionError::InsufficientBalance {
+                account_id: body.originator.clone(),
+                amount: body.amount,
+                balance: sender.amount,
+                staked: sender.staked,
+            })
         } else {
-            Err(format!("Account {} already staked", body.originator))
+            Err(ActionError::AlreadyStaked(body.originator.clone()))
+        }
+    }
+
+    /// Proposes the new keys of the staked authority without more stake. The epoch manager
+    /// takes a proposal of no stake for a key change, see `beacon::authority`.
+    fn rotate_key(
+        &self,
+        body: &RotateKeyTransaction,
+        sender_account_id: &AccountId,
+        sender: &Account,
+        authority_proposals: &mut Vec<AuthorityStake>,
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
+        if sender.staked == 0 {
+            return Err(ActionError::NotStaked(body.originator.clone()));
         }
+        let public_key =
+            PublicKey::from_bytes(&body.public_key).map_err(|_| ActionError::InvalidPublicKey)?;
+        let bls_public_key =
+            BlsPublicKey::from_bytes(&body.bls_public_key).ok_or(ActionError::InvalidPublicKey)?;
+        BlsSignature::from_bytes(&body.bls_proof_of_possession)
+            .filter(|proof| bls_public_key.verify_proof_of_possession(proof))
+            .ok_or_else(|| ActionError::InvalidProofOfPossession(body.originator.clone()))?;
+        authority_proposals.push(AuthorityStake {
+            account_id: sender_account_id.clone(),
+            public_key,
+            bls_public_key,
+            amount: 0,
+        });
+        Ok(vec![])
     }
 
     fn create_account(
@@ -250,9 +524,9 @@ impl Runtime {
         hash: CryptoHash,
         sender: &mut Account,
         accounting_info: AccountingInfo,
-    ) -> Result<Vec<ReceiptTransaction>, String> {
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
         if !is_valid_account_id(&body.new_account_id) {
-            return Err(format!("Account {} does not match requirements", body.new_account_id));
+            return Err(ActionError::InvalidNewAccountId(body.new_account_id.clone()));
         }
         if sender.amount >= body.amount {
             sender.amount -= body.amount;
@@ -276,15 +550,60 @@ impl Runtime {
             );
             Ok(vec![receipt])
         } else {
-            Err(
-                format!(
-                    "Account {} tries to create new account with {}, but only has {}",
-                    body.originator,
-                    body.amount,
-                    sender.amount
-                )
-            )
+            Err(ActionError::InsufficientBalance {
+                account_id: body.originator.clone(),
+                amount: body.amount,
+                balance: sender.amount,
+                staked: sender.staked,
+            })
+        }
+    }
+
+    fn create_contract_account(
+        &self,
+        state_update: &mut StateDbUpdate,
+        body: &CreateContractAccountTransaction,
+        hash: CryptoHash,
+        sender: &mut Account,
+        accounting_info: AccountingInfo,
+        mana: Mana,
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
+        if !is_valid_account_id(&body.new_account_id) {
+            return Err(ActionError::InvalidNewAccountId(body.new_account_id.clone()));
         }
+        if sender.amount < body.amount {
+            return Err(ActionError::InsufficientBalance {
+                account_id: body.originator.clone(),
+                amount: body.amount,
+                balance: sender.amount,
+                staked: sender.staked,
+            });
+        }
+        let args = borsh::to_vec(&(
+            &body.public_key,
+            &body.wasm_byte_array,
+            &body.init_method,
+            &body.init_args,
+        )).map_err(|_| ActionError::InvalidSystemCallArgs)?;
+        sender.amount -= body.amount;
+        set(
+            state_update,
+            &account_id_to_bytes(COL_ACCOUNT, &body.originator),
+            &sender
+        );
+        let receipt = ReceiptTransaction::new(
+            body.originator.clone(),
+            body.new_account_id.clone(),
+            create_nonce_with_nonce(&hash, 0),
+            ReceiptBody::NewCall(AsyncCall::new(
+                SYSTEM_METHOD_CREATE_CONTRACT_ACCOUNT.to_vec(),
+                args,
+                body.amount,
+                mana - 1,
+                accounting_info,
+            ))
+        );
+        Ok(vec![receipt])
     }
 
     fn swap_key(
@@ -292,15 +611,116 @@ impl Runtime {
         state_update: &mut StateDbUpdate,
         body: &SwapKeyTransaction,
         account: &mut Account,
-    ) -> Result<Vec<ReceiptTransaction>, String> {
-        let cur_key = Decode::decode(&body.cur_key).map_err(|_| "cannot decode public key")?;
-        let new_key = Decode::decode(&body.new_key).map_err(|_| "cannot decode public key")?;
-        let num_keys = account.public_keys.len();
-        account.public_keys.retain(|&x| x != cur_key);
-        if account.public_keys.len() == num_keys {
-            return Err(format!("Account {} does not have public key {}", body.originator, cur_key));
-        }
-        account.public_keys.push(new_key);
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
+        let cur_key =
+            PublicKey::from_bytes(&body.cur_key).map_err(|_| ActionError::InvalidPublicKey)?;
+        let new_key =
+            PublicKey::from_bytes(&body.new_key).map_err(|_| ActionError::InvalidPublicKey)?;
+        let mut public_keys = account.public_keys.clone();
+        add_public_key(&mut public_keys, &body.originator, new_key)?;
+        let num_keys = public_keys.len();
+        public_keys.retain(|&x| x != cur_key);
+        if public_keys.len() == num_keys {
+            return Err(ActionError::PublicKeyNotFound {
+                account_id: body.originator.clone(),
+                public_key: cur_key,
+            });
+        }
+        account.public_keys = public_keys;
+        // The new key inherits the restriction of the key it replaces.
+        for permission in account.key_permissions.iter_mut() {
+            if permission.public_key == cur_key {
+                permission.public_key = new_key;
+            }
+        }
+        set(
+            state_update,
+            &account_id_to_bytes(COL_ACCOUNT, &body.originator),
+            &account
+        );
+        Ok(vec![])
+    }
+
+    fn set_signature_policy(
+        &self,
+        state_update: &mut StateDbUpdate,
+        body: &SignaturePolicyTransaction,
+        account: &mut Account,
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
+        let mut public_keys: Vec<PublicKey> = vec![];
+        for key in body.public_keys.iter() {
+            let public_key = PublicKey::from_bytes(key).map_err(|_| ActionError::InvalidPublicKey)?;
+            add_public_key(&mut public_keys, &body.originator, public_key)?;
+        }
+        if body.threshold == 0 || body.threshold as usize > public_keys.len() {
+            return Err(ActionError::InvalidThreshold {
+                account_id: body.originator.clone(),
+                threshold: body.threshold,
+                num_keys: public_keys.len(),
+            });
+        }
+        account.public_keys = public_keys;
+        account.signature_threshold = body.threshold;
+        let public_keys = &account.public_keys;
+        account.key_permissions.retain(|p| public_keys.contains(&p.public_key));
+        set(
+            state_update,
+            &account_id_to_bytes(COL_ACCOUNT, &body.originator),
+            &account
+        );
+        Ok(vec![])
+    }
+
+    fn set_key_permissions(
+        &self,
+        state_update: &mut StateDbUpdate,
+        body: &SetKeyPermissionsTransaction,
+        account: &mut Account,
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
+        let public_key =
+            PublicKey::from_bytes(&body.public_key).map_err(|_| ActionError::InvalidPublicKey)?;
+        if !account.public_keys.contains(&public_key) {
+            return Err(ActionError::PublicKeyNotFound {
+                account_id: body.originator.clone(),
+                public_key,
+            });
+        }
+        let mut allowed = vec![];
+        for kind in body.allowed.iter() {
+            let kind: TransactionKind =
+                kind.parse().map_err(|_| ActionError::UnknownTransactionKind(kind.clone()))?;
+            if !allowed.contains(&kind) {
+                allowed.push(kind);
+            }
+        }
+        account.key_permissions.retain(|p| p.public_key != public_key);
+        if !allowed.is_empty() {
+            account.key_permissions.push(KeyPermission { public_key, allowed });
+        }
+        set(
+            state_update,
+            &account_id_to_bytes(COL_ACCOUNT, &body.originator),
+            &account
+        );
+        Ok(vec![])
+    }
+
+    fn set_guardians(
+        &self,
+        state_update: &mut StateDbUpdate,
+        body: &SetGuardiansTransaction,
+        account: &mut Account,
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
+        if body.guardians.len() > self.config.max_guardians {
+            return Err(ActionError::TooManyGuardians {
+                count: body.guardians.len(),
+                limit: self.config.max_guardians,
+            });
+        }
+        if let Some(guardian) = body.guardians.iter().find(|g| !is_valid_account_id(g)) {
+            return Err(ActionError::InvalidGuardian(guardian.clone()));
+        }
+        account.guardians = body.guardians.clone();
         set(
             state_update,
             &account_id_to_bytes(COL_ACCOUNT, &body.originator),
@@ -309,17 +729,51 @@ impl Runtime {
         Ok(vec![])
     }
 
+    fn propose_recovery(
+        &self,
+        body: &ProposeRecoveryTransaction,
+        hash: CryptoHash,
+        accounting_info: AccountingInfo,
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
+        PublicKey::from_bytes(&body.public_key).map_err(|_| ActionError::InvalidPublicKey)?;
+        let receipt = ReceiptTransaction::new(
+            body.originator.clone(),
+            body.account_id.clone(),
+            create_nonce_with_nonce(&hash, 0),
+            ReceiptBody::NewCall(AsyncCall::new(
+                SYSTEM_METHOD_PROPOSE_RECOVERY.to_vec(),
+                body.public_key.clone(),
+                0,
+                0,
+                accounting_info,
+            ))
+        );
+        Ok(vec![receipt])
+    }
+
+    fn cancel_recovery(
+        &self,
+        state_update: &mut StateDbUpdate,
+        body: &CancelRecoveryTransaction,
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
+        let key = account_id_to_bytes(COL_RECOVERY, &body.originator);
+        let pending: PendingRecovery = get(state_update, &key)
+            .ok_or_else(|| ActionError::RecoveryNotFound(body.originator.clone()))?;
+        state_update.remove(&key);
+        state_update.remove(&recovery_queue_key(pending.finalize_at, &body.originator));
+        Ok(vec![])
+    }
+
     fn deploy(
         &self,
         body: &DeployContractTransaction,
         hash: CryptoHash,
         accounting_info: AccountingInfo,
-    ) -> Result<Vec<ReceiptTransaction>, String> {
-        // TODO: check signature
-        
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
         let new_nonce = create_nonce_with_nonce(&hash, 0);
-        let args = Encode::encode(&(&body.public_key, &body.wasm_byte_array))
-            .map_err(|_| "cannot encode args")?;
+        // The key is only read if the contract account doesn't exist yet, see `system_deploy`.
+        let args = borsh::to_vec(&(&body.public_key, &body.wasm_byte_array))
+            .map_err(|_| ActionError::InvalidSystemCallArgs)?;
         let receipt = ReceiptTransaction::new(
             body.originator.clone(),
             body.contract_id.clone(),
@@ -343,7 +797,7 @@ impl Runtime {
         sender: &mut Account,
         accounting_info: AccountingInfo,
         mana: Mana,
-    ) -> Result<Vec<ReceiptTransaction>, String> {
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
         if sender.amount >= transaction.amount {
             sender.amount -= transaction.amount;
             set(state_update, &account_id_to_bytes(COL_ACCOUNT, &transaction.originator), sender);
@@ -361,134 +815,539 @@ impl Runtime {
             );
             Ok(vec![receipt])
         } else {
-            Err(
-                format!(
-                    "Account {} tries to call some contract with the amount {}, but has staked {} and only has {}",
-                    transaction.originator,
-                    transaction.amount,
-                    sender.staked,
-                    sender.amount
-                )
-            )
+            Err(ActionError::InsufficientBalance {
+                account_id: transaction.originator.clone(),
+                amount: transaction.amount,
+                balance: sender.amount,
+                staked: sender.staked,
+            })
         }
     }
 
-    /// node receives signed_transaction, processes it
-    /// and generates the receipt to send to receiver
-    fn apply_signed_transaction(
-        &mut self,
-        state_update: &mut StateDbUpdate,
-        block_index: BlockIndex,
-        transaction: &SignedTransaction,
-        authority_proposals: &mut Vec<AuthorityStake>
-    ) -> Result<Vec<ReceiptTransaction>, String> {
-        let sender_account_id = transaction.body.get_originator();
-        if !is_valid_account_id(&sender_account_id) {
-            return Err("Invalid originator account_id".to_string());
-        }
-        let sender: Option<Account> =
-            get(state_update, &account_id_to_bytes(COL_ACCOUNT, &sender_account_id));
-        match sender {
-            Some(mut sender) => {
-                if transaction.body.get_nonce() <= sender.nonce {
-                    return Err(format!(
-                        "Transaction nonce {} must be larger than sender nonce {}",
-                        transaction.body.get_nonce(),
-                        sender.nonce,
-                    ));
-                }
-                sender.nonce = transaction.body.get_nonce();
-                set(
-                    state_update,
-                    &account_id_to_bytes(COL_ACCOUNT, &sender_account_id),
-                    &sender
-                );
-                let contract_id = transaction.body.get_contract_id();
-                if let Some(ref contract_id) = contract_id {
-                    if !is_valid_account_id(&contract_id) {
-                        return Err("Invalid contract_id".to_string());
-                    }
-                }
-                let mana = transaction.body.get_mana();
-                let accounting_info = self.try_charge_mana(
-                    state_update,
-                    block_index,
-                    &sender_account_id,
-                    &contract_id,
-                    mana,
-                ).ok_or_else(|| format!("sender {} does not have enough mana {}", sender_account_id, mana))?;
-                match transaction.body {
-                    TransactionBody::SendMoney(ref t) => {
-                        self.send_money(
-                            state_update,
-                            &t,
-                            transaction.get_hash(),
-                            &mut sender,
-                            accounting_info,
-                        )
-                    },
-                    TransactionBody::Stake(ref t) => {
-                        self.staking(
-                            state_update,
-                            &t,
-                            &sender_account_id,
-                            &mut sender,
-                            authority_proposals,
-                        )
-                    },
-                    TransactionBody::FunctionCall(ref t) => {
-                        self.call_function(
-                            state_update,
-                            &t,
-                            transaction.get_hash(),
-                            &mut sender,
-                            accounting_info,
-                            mana,
-                        )
-                    },
-                    TransactionBody::DeployContract(ref t) => {
-                        self.deploy(
-                            t,
-                            transaction.get_hash(),
-                            accounting_info,
-                        )
-                    },
-                    TransactionBody::CreateAccount(ref t) => {
-                        self.create_account(
-                            state_update,
-                            t,
-                            transaction.get_hash(),
-                            &mut sender,
-                            accounting_info,
-                        )
-                    },
-                    TransactionBody::SwapKey(ref t) => {
-                        self.swap_key(
-                            state_update,
-                            t,
-                            &mut sender,
-                        )
-                    }
-                }
+    /// Remembers the parent block hash, so that transactions can reference it, and forgets
+    /// the hash that fell out of the validity period. Does nothing if expiry is disabled.
+    fn record_block_hash(&self, state_update: &mut StateDbUpdate, apply_state: &ApplyState) {
+        let period = match self.config.transaction_validity_period {
+            Some(period) => period,
+            None => return,
+        };
+        let parent_index = apply_state.block_index.saturating_sub(1);
+        set(state_update, &block_hash_to_bytes(&apply_state.parent_block_hash), &parent_index);
+        set(state_update, &block_index_to_bytes(parent_index), &apply_state.parent_block_hash);
+        if parent_index > period {
+            let expired_key = block_index_to_bytes(parent_index - period - 1);
+            let expired_hash: Option<CryptoHash> = get(state_update, &expired_key);
+            if let Some(expired_hash) = expired_hash {
+                state_update.remove(&expired_key);
+                state_update.remove(&block_hash_to_bytes(&expired_hash));
             }
-            _ => Err(format!("sender {} does not exist", sender_account_id))
         }
+        state_update.commit();
     }
 
-    fn deposit(
+    /// Checks that the block referenced by the transaction is at most
+    /// `transaction_validity_period` blocks older than the block with the given index.
+    fn check_block_hash(
         &self,
         state_update: &mut StateDbUpdate,
-        amount: u64,
-        receiver_id: &AccountId,
-        receiver: &mut Account
-    ) -> Result<Vec<ReceiptTransaction>, String> {
-        receiver.amount += amount;
-        set(
-            state_update,
-            &account_id_to_bytes(COL_ACCOUNT, &receiver_id),
-            receiver
-        );
-        Ok(vec![])
+        block_index: BlockIndex,
+        transaction: &SignedTransaction,
+    ) -> Result<(), InvalidTxError> {
+        let period = match self.config.transaction_validity_period {
+            Some(period) => period,
+            None => return Ok(()),
+        };
+        let block_hash = transaction.body.get_block_hash();
+        let referenced_index: Option<BlockIndex> =
+            get(state_update, &block_hash_to_bytes(&block_hash));
+        match referenced_index {
+            Some(index) if block_index.saturating_sub(index) <= period => Ok(()),
+            _ => Err(InvalidTxError::Expired(block_hash)),
+        }
+    }
+
+    /// Checks the transaction against the state before applying it and returns the
+    /// originator account.
+    fn check_transaction(
+        &self,
+        state_update: &mut StateDbUpdate,
+        block_index: BlockIndex,
+        transaction: &SignedTransaction,
+    ) -> Result<Account, InvalidTxError> {
+        let size = transaction.get_size();
+        if size > self.config.max_transaction_size {
+            return Err(InvalidTxError::TransactionTooLarge {
+                size,
+                limit: self.config.max_transaction_size,
+            });
+        }
+        let args_size = match &transaction.body {
+            TransactionBody::FunctionCall(t) => t.args.len(),
+            TransactionBody::CreateContractAccount(t) => t.init_args.len(),
+            _ => 0,
+        };
+        if args_size > self.config.max_args_size {
+            return Err(InvalidTxError::ArgsTooLarge {
+                size: args_size,
+                limit: self.config.max_args_size,
+            });
+        }
+        let limit = block_index.saturating_add(self.config.max_schedule_horizon);
+        let execute_at = transaction.body.get_execute_at();
+        if execute_at > limit {
+            return Err(InvalidTxError::ScheduledTooFar { execute_at, limit });
+        }
+        if let Some(genesis_hash) = self.config.genesis_hash {
+            if transaction.body.get_genesis_hash() != genesis_hash {
+                return Err(InvalidTxError::WrongChain {
+                    expected: genesis_hash,
+                    found: transaction.body.get_genesis_hash(),
+                });
+            }
+        }
+        if transaction.body.protocol_version() > self.config.protocol_version {
+            return Err(InvalidTxError::UnsupportedKind {
+                kind: transaction.body.kind(),
+                protocol_version: transaction.body.protocol_version(),
+            });
+        }
+        self.check_block_hash(state_update, block_index, transaction)?;
+        let sender_account_id = transaction.body.get_originator();
+        if !is_valid_account_id(&sender_account_id) {
+            return Err(InvalidTxError::InvalidOriginator(sender_account_id));
+        }
+        let sender: Account =
+            get(state_update, &account_id_to_bytes(COL_ACCOUNT, &sender_account_id))
+                .ok_or_else(|| InvalidTxError::OriginatorDoesNotExist(sender_account_id.clone()))?;
+        if self.config.verify_signatures {
+            let signers = match self.verified_signers.get(&transaction.get_hash()) {
+                // The keys of the originator may have changed since the batch.
+                Some(public_key) if sender.public_keys[..] == [*public_key] => vec![*public_key],
+                _ => transaction_signers(transaction, &sender.public_keys),
+            };
+            if signers.is_empty() {
+                return Err(InvalidTxError::InvalidSignature(sender_account_id));
+            }
+            let kind = transaction.body.kind();
+            let signers = signers.iter().filter(|key| sender.key_allows(key, kind)).count();
+            if signers == 0 {
+                return Err(InvalidTxError::KeyNotAllowed { originator: sender_account_id, kind });
+            }
+            // Any key of the owner can cancel a recovery, even if the current keys are lost.
+            let threshold = match transaction.body {
+                TransactionBody::CancelRecovery(_) => 1,
+                _ => sender.signature_threshold,
+            };
+            if signers < threshold as usize {
+                return Err(InvalidTxError::NotEnoughSignatures {
+                    originator: sender_account_id,
+                    signers,
+                    threshold,
+                });
+            }
+        }
+        if !self.config.nonce_policy.is_valid(transaction.body.get_nonce(), sender.nonce) {
+            return Err(InvalidTxError::InvalidNonce {
+                tx_nonce: transaction.body.get_nonce(),
+                account_nonce: sender.nonce,
+            });
+        }
+        if let Some(contract_id) = transaction.body.get_contract_id() {
+            if !is_valid_account_id(&contract_id) {
+                return Err(InvalidTxError::InvalidContractId(contract_id));
+            }
+        }
+        if let TransactionBody::SendMoney(t) = &transaction.body {
+            if t.memo.len() > self.config.max_memo_length {
+                return Err(InvalidTxError::MemoTooLong {
+                    length: t.memo.len(),
+                    limit: self.config.max_memo_length,
+                });
+            }
+        }
+        Ok(sender)
+    }
+
+    /// Checks whether the transaction can be applied as part of the block described by
+    /// `apply_state`, e.g. before accepting it from the RPC.
+    pub fn validate_tx(
+        &self,
+        apply_state: &ApplyState,
+        transaction: &SignedTransaction,
+    ) -> Result<(), InvalidTxError> {
+        let mut state_update = StateDbUpdate::new(self.state_db.clone(), apply_state.root);
+        self.record_block_hash(&mut state_update, apply_state);
+        self.check_transaction(&mut state_update, apply_state.block_index, transaction)
+            .map(|_| ())
+    }
+
+    /// Checks only that the block referenced by the transaction is still within the validity
+    /// period, which is cheaper than `validate_tx`.
+    pub fn check_expiry(
+        &self,
+        apply_state: &ApplyState,
+        transaction: &SignedTransaction,
+    ) -> Result<(), InvalidTxError> {
+        let mut state_update = StateDbUpdate::new(self.state_db.clone(), apply_state.root);
+        self.record_block_hash(&mut state_update, apply_state);
+        self.check_block_hash(&mut state_update, apply_state.block_index, transaction)
+    }
+
+    /// Verifies the signatures of the transactions in a batch and returns the key that signed
+    /// each transaction whose signatures are all valid. Only the transactions of the originators
+    /// with a single key are batched, as which key made which signature isn't known otherwise.
+    /// The rest is left to `check_transaction` to verify.
+    fn verify_signatures_batch(
+        state_update: &mut StateDbUpdate,
+        transactions: &[SignedTransaction],
+    ) -> HashMap<CryptoHash, PublicKey> {
+        let mut candidates = vec![];
+        for transaction in transactions {
+            let originator = transaction.body.get_originator();
+            let sender: Option<Account> =
+                get(state_update, &account_id_to_bytes(COL_ACCOUNT, &originator));
+            match sender {
+                Some(ref sender) if sender.public_keys.len() == 1 => candidates.push((
+                    transaction.get_hash(),
+                    transaction,
+                    sender.public_keys[0],
+                )),
+                _ => {}
+            }
+        }
+        let mut items = vec![];
+        let mut owners = vec![];
+        for (i, (hash, transaction, public_key)) in candidates.iter().enumerate() {
+            let signatures =
+                std::iter::once(&transaction.signature).chain(&transaction.extra_signatures);
+            for signature in signatures {
+                let data: &[u8] = hash.as_ref();
+                items.push((data, signature, public_key));
+                owners.push(i);
+            }
+        }
+        let mut valid = vec![true; candidates.len()];
+        for (owner, is_valid) in owners.into_iter().zip(verify_batch(&items)) {
+            valid[owner] &= is_valid;
+        }
+        candidates
+            .iter()
+            .zip(valid)
+            .filter(|(_, is_valid)| *is_valid)
+            .map(|((hash, _, public_key), _)| (*hash, *public_key))
+            .collect()
+    }
+
+    /// node receives signed_transaction, processes it
+    /// and generates the receipt to send to receiver
+    fn apply_signed_transaction(
+        &mut self,
+        state_update: &mut StateDbUpdate,
+        block_index: BlockIndex,
+        transaction: &SignedTransaction,
+        authority_proposals: &mut Vec<AuthorityStake>
+    ) -> Result<Vec<ReceiptTransaction>, RuntimeError> {
+        let sender_account_id = transaction.body.get_originator();
+        let mut sender = self.check_transaction(state_update, block_index, transaction)?;
+        sender.nonce = transaction.body.get_nonce();
+        let tip = transaction.body.get_tip();
+        if sender.amount < tip {
+            return Err(ActionError::InsufficientBalance {
+                account_id: sender_account_id,
+                amount: tip,
+                balance: sender.amount,
+                staked: sender.staked,
+            }.into());
+        }
+        sender.amount -= tip;
+        set(
+            state_update,
+            &account_id_to_bytes(COL_ACCOUNT, &sender_account_id),
+            &sender
+        );
+        let contract_id = transaction.body.get_contract_id();
+        let mana = transaction.body.get_mana();
+        let accounting_info = self.try_charge_mana(
+            state_update,
+            block_index,
+            &sender_account_id,
+            &contract_id,
+            mana,
+        ).ok_or_else(|| InvalidTxError::NotEnoughMana {
+            originator: sender_account_id.clone(),
+            mana,
+        })?;
+        let execute_at = transaction.body.get_execute_at();
+        if execute_at > block_index {
+            set(
+                state_update,
+                &scheduled_transaction_key(execute_at, &transaction.get_hash()),
+                &ScheduledTransaction { transaction: transaction.clone(), accounting_info, mana },
+            );
+            return Ok(vec![]);
+        }
+        self.apply_transaction_body(
+            state_update,
+            block_index,
+            &transaction.body,
+            transaction.get_hash(),
+            &sender_account_id,
+            &mut sender,
+            accounting_info,
+            mana,
+            authority_proposals,
+        )
+    }
+
+    /// Executes the actions of the transaction body as `sender`, with mana and gas
+    /// accounted to `accounting_info`.
+    #[allow(clippy::too_many_arguments)]
+    fn apply_transaction_body(
+        &mut self,
+        state_update: &mut StateDbUpdate,
+        block_index: BlockIndex,
+        body: &TransactionBody,
+        hash: CryptoHash,
+        sender_account_id: &AccountId,
+        sender: &mut Account,
+        accounting_info: AccountingInfo,
+        mana: Mana,
+        authority_proposals: &mut Vec<AuthorityStake>,
+    ) -> Result<Vec<ReceiptTransaction>, RuntimeError> {
+        let result = match *body {
+            TransactionBody::SendMoney(ref t) => {
+                self.send_money(
+                    state_update,
+                    t,
+                    hash,
+                    sender,
+                    accounting_info,
+                )
+            },
+            TransactionBody::Stake(ref t) => {
+                self.staking(
+                    state_update,
+                    t,
+                    sender_account_id,
+                    sender,
+                    authority_proposals,
+                )
+            },
+            TransactionBody::RotateKey(ref t) => {
+                self.rotate_key(
+                    t,
+                    sender_account_id,
+                    sender,
+                    authority_proposals,
+                )
+            },
+            TransactionBody::FunctionCall(ref t) => {
+                self.call_function(
+                    state_update,
+                    t,
+                    hash,
+                    sender,
+                    accounting_info,
+                    mana,
+                )
+            },
+            TransactionBody::DeployContract(ref t) => {
+                self.deploy(
+                    t,
+                    hash,
+                    accounting_info,
+                )
+            },
+            TransactionBody::CreateAccount(ref t) => {
+                self.create_account(
+                    state_update,
+                    t,
+                    hash,
+                    sender,
+                    accounting_info,
+                )
+            },
+            TransactionBody::CreateContractAccount(ref t) => {
+                self.create_contract_account(
+                    state_update,
+                    t,
+                    hash,
+                    sender,
+                    accounting_info,
+                    mana,
+                )
+            },
+            TransactionBody::SwapKey(ref t) => {
+                self.swap_key(
+                    state_update,
+                    t,
+                    sender,
+                )
+            },
+            TransactionBody::MultiSendMoney(ref t) => {
+                self.multi_send_money(
+                    state_update,
+                    t,
+                    hash,
+                    sender,
+                    accounting_info,
+                )
+            },
+            TransactionBody::SignaturePolicy(ref t) => {
+                self.set_signature_policy(
+                    state_update,
+                    t,
+                    sender,
+                )
+            },
+            TransactionBody::SetKeyPermissions(ref t) => {
+                self.set_key_permissions(
+                    state_update,
+                    t,
+                    sender,
+                )
+            },
+            TransactionBody::SetGuardians(ref t) => {
+                self.set_guardians(
+                    state_update,
+                    t,
+                    sender,
+                )
+            },
+            TransactionBody::ProposeRecovery(ref t) => {
+                self.propose_recovery(
+                    t,
+                    hash,
+                    accounting_info,
+                )
+            },
+            TransactionBody::CancelRecovery(ref t) => {
+                self.cancel_recovery(
+                    state_update,
+                    t,
+                )
+            },
+            TransactionBody::Delegate(ref t) => {
+                return self.apply_delegate(
+                    state_update,
+                    block_index,
+                    t,
+                    accounting_info,
+                    mana,
+                    authority_proposals,
+                );
+            },
+        };
+        result.map_err(RuntimeError::from)
+    }
+
+    /// Keys of the scheduled transactions that are due at the given block index. The keys are
+    /// sorted by the index, so only the due ones are read.
+    fn due_scheduled_transactions(
+        state_update: &StateDbUpdate,
+        block_index: BlockIndex,
+    ) -> Vec<Vec<u8>> {
+        let mut keys = vec![];
+        state_update.for_keys_with_prefix_while(COL_SCHEDULED, |key| {
+            let execute_at = BigEndian::read_u64(&key[COL_SCHEDULED.len()..]);
+            if execute_at > block_index {
+                return false;
+            }
+            keys.push(key.to_vec());
+            true
+        });
+        keys
+    }
+
+    /// Executes the scheduled transaction stored under the given key and removes it from
+    /// the state. The checks and the mana charge were done when it was accepted.
+    fn apply_scheduled_transaction(
+        &mut self,
+        state_update: &mut StateDbUpdate,
+        block_index: BlockIndex,
+        key: &[u8],
+        scheduled: &ScheduledTransaction,
+        authority_proposals: &mut Vec<AuthorityStake>,
+    ) -> Result<Vec<ReceiptTransaction>, RuntimeError> {
+        state_update.remove(key);
+        let transaction = &scheduled.transaction;
+        let sender_account_id = transaction.body.get_originator();
+        let mut sender: Account =
+            get(state_update, &account_id_to_bytes(COL_ACCOUNT, &sender_account_id))
+                .ok_or_else(|| InvalidTxError::OriginatorDoesNotExist(sender_account_id.clone()))?;
+        self.apply_transaction_body(
+            state_update,
+            block_index,
+            &transaction.body,
+            transaction.get_hash(),
+            &sender_account_id,
+            &mut sender,
+            scheduled.accounting_info.clone(),
+            scheduled.mana,
+            authority_proposals,
+        )
+    }
+
+    /// Checks the user signed inner transaction and executes it on behalf of the user.
+    /// The relayer has already been charged, the inner nonce is tracked on the user account.
+    fn apply_delegate(
+        &mut self,
+        state_update: &mut StateDbUpdate,
+        block_index: BlockIndex,
+        body: &DelegateTransaction,
+        accounting_info: AccountingInfo,
+        mana: Mana,
+        authority_proposals: &mut Vec<AuthorityStake>,
+    ) -> Result<Vec<ReceiptTransaction>, RuntimeError> {
+        let transaction = &body.transaction;
+        if let TransactionBody::Delegate(_) = transaction.body {
+            return Err(ActionError::NestedDelegate.into());
+        }
+        // The relayer schedules and tips the delegate transaction, the inner one is applied
+        // with it.
+        let execute_at = transaction.body.get_execute_at();
+        if execute_at != 0 {
+            return Err(ActionError::ScheduledDelegatedTransaction { execute_at }.into());
+        }
+        let tip = transaction.body.get_tip();
+        if tip != 0 {
+            return Err(ActionError::DelegatedTransactionTip { tip }.into());
+        }
+        let sender_account_id = transaction.body.get_originator();
+        let mut sender = self.check_transaction(state_update, block_index, transaction)?;
+        sender.nonce = transaction.body.get_nonce();
+        set(
+            state_update,
+            &account_id_to_bytes(COL_ACCOUNT, &sender_account_id),
+            &sender
+        );
+        self.apply_transaction_body(
+            state_update,
+            block_index,
+            &transaction.body,
+            transaction.get_hash(),
+            &sender_account_id,
+            &mut sender,
+            accounting_info,
+            mana,
+            authority_proposals,
+        )
+    }
+
+    fn deposit(
+        &self,
+        state_update: &mut StateDbUpdate,
+        amount: u64,
+        receiver_id: &AccountId,
+        receiver: &mut Account
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
+        receiver.amount += amount;
+        set(
+            state_update,
+            &account_id_to_bytes(COL_ACCOUNT, &receiver_id),
+            receiver
+        );
+        Ok(vec![])
     }
 
     fn system_create_account(
@@ -496,13 +1355,14 @@ impl Runtime {
         state_update: &mut StateDbUpdate,
         call: &AsyncCall,
         account_id: &AccountId,
-    ) -> Result<Vec<ReceiptTransaction>, String> {
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
         if !is_valid_account_id(account_id) {
-            return Err(format!("Account {} does not match requirements", account_id));
+            return Err(ActionError::InvalidNewAccountId(account_id.clone()));
         }
         let account_id_bytes = account_id_to_bytes(COL_ACCOUNT, &account_id);
        
-        let public_key = PublicKey::new(&call.args)?;
+        let public_key =
+            PublicKey::from_bytes(&call.args).map_err(|_| ActionError::InvalidPublicKey)?;
         let new_account = Account::new(
             vec![public_key],
             call.amount,
@@ -513,6 +1373,12 @@ impl Runtime {
             &account_id_bytes,
             &new_account
         );
+        Self::set_default_tx_stake(state_update, account_id);
+
+        Ok(vec![])
+    }
+
+    fn set_default_tx_stake(state_update: &mut StateDbUpdate, account_id: &AccountId) {
         // TODO(#347): Remove default TX staking once tx staking is properly implemented
         let mut tx_total_stake = TxTotalStake::new(0);
         tx_total_stake.add_active_stake(100);
@@ -521,8 +1387,53 @@ impl Runtime {
             &get_tx_stake_key(&account_id, &None),
             &tx_total_stake,
         );
+    }
 
-        Ok(vec![])
+    /// Creates the account with the contract and calls its init method. The receipt is
+    /// rolled back as a whole if the call fails, so the account is never left without
+    /// an initialized contract.
+    fn system_create_contract_account(
+        &mut self,
+        state_update: &mut StateDbUpdate,
+        call: &AsyncCall,
+        receipt: &ReceiptTransaction,
+        mana_accounting: &mut ManaAccounting,
+        block_index: BlockIndex,
+        logs: &mut Vec<LogEntry>,
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
+        let account_id = &receipt.receiver;
+        if !is_valid_account_id(account_id) {
+            return Err(ActionError::InvalidNewAccountId(account_id.clone()));
+        }
+        let (public_key, code, init_method, init_args): (Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>) =
+            borsh::from_slice(&call.args).map_err(|_| ActionError::InvalidSystemCallArgs)?;
+        let public_key =
+            PublicKey::from_bytes(&public_key).map_err(|_| ActionError::InvalidPublicKey)?;
+        let mut new_account = Account::new(vec![public_key], call.amount, hash(&code));
+        set(state_update, &account_id_to_bytes(COL_ACCOUNT, account_id), &new_account);
+        set(state_update, &account_id_to_bytes(COL_CODE, account_id), &code);
+        Self::set_default_tx_stake(state_update, account_id);
+        if init_method.is_empty() {
+            return Ok(vec![]);
+        }
+        let init_call = AsyncCall::new(
+            init_method,
+            init_args,
+            0,
+            call.mana,
+            call.accounting_info.clone(),
+        );
+        self.apply_async_call(
+            state_update,
+            &init_call,
+            &receipt.originator,
+            account_id,
+            &receipt.nonce,
+            &mut new_account,
+            mana_accounting,
+            block_index,
+            logs,
+        )
     }
 
     fn system_deploy(
@@ -530,10 +1441,11 @@ impl Runtime {
         state_update: &mut StateDbUpdate,
         call: &AsyncCall,
         account_id: &AccountId,
-    ) -> Result<Vec<ReceiptTransaction>, String> {
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
         let (public_key, code): (Vec<u8>, Vec<u8>) =
-            Decode::decode(&call.args).map_err(|_| "cannot decode public key")?;
-        let public_key = PublicKey::new(&public_key)?;
+            borsh::from_slice(&call.args).map_err(|_| ActionError::InvalidSystemCallArgs)?;
+        let public_key =
+            PublicKey::from_bytes(&public_key).map_err(|_| ActionError::InvalidPublicKey)?;
         let new_account = Account::new(
             vec![public_key],
             call.amount,
@@ -552,13 +1464,95 @@ impl Runtime {
         Ok(vec![])
     }
 
+    /// Stages the key replacement proposed by a guardian of the account. A new proposal
+    /// replaces the previous one and restarts the delay.
+    fn system_propose_recovery(
+        &self,
+        state_update: &mut StateDbUpdate,
+        call: &AsyncCall,
+        guardian: &AccountId,
+        account_id: &AccountId,
+        account: &Account,
+        block_index: BlockIndex,
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
+        if !account.guardians.contains(guardian) {
+            return Err(ActionError::NotGuardian {
+                guardian: guardian.clone(),
+                account_id: account_id.clone(),
+            });
+        }
+        let public_key =
+            PublicKey::from_bytes(&call.args).map_err(|_| ActionError::InvalidPublicKey)?;
+        let key = account_id_to_bytes(COL_RECOVERY, account_id);
+        if let Some(previous) = get::<PendingRecovery>(state_update, &key) {
+            state_update.remove(&recovery_queue_key(previous.finalize_at, account_id));
+        }
+        let finalize_at = block_index + self.config.recovery_delay;
+        set(
+            state_update,
+            &key,
+            &PendingRecovery { guardian: guardian.clone(), public_key, finalize_at },
+        );
+        set(state_update, &recovery_queue_key(finalize_at, account_id), account_id);
+        Ok(vec![])
+    }
+
+    /// Keys of the staged recoveries whose delay is over at the given block index.
+    fn due_recoveries(state_update: &StateDbUpdate, block_index: BlockIndex) -> Vec<Vec<u8>> {
+        let mut keys = vec![];
+        state_update.for_keys_with_prefix_while(COL_RECOVERY_QUEUE, |key| {
+            let finalize_at = BigEndian::read_u64(&key[COL_RECOVERY_QUEUE.len()..]);
+            if finalize_at > block_index {
+                return false;
+            }
+            keys.push(key.to_vec());
+            true
+        });
+        keys
+    }
+
+    /// Replaces the account keys with the key of the staged recovery and resets its
+    /// signature threshold.
+    fn finalize_recovery(
+        state_update: &mut StateDbUpdate,
+        queue_key: &[u8],
+    ) -> Result<(), String> {
+        let account_id: AccountId = match get(state_update, queue_key) {
+            Some(account_id) => account_id,
+            None => {
+                // Can't be finalized, so it is dropped instead of being retried in every block.
+                state_update.remove(queue_key);
+                return Err(format!("Recovery queue entry under {:?} doesn't decode", queue_key));
+            }
+        };
+        state_update.remove(queue_key);
+        let key = account_id_to_bytes(COL_RECOVERY, &account_id);
+        let pending: Option<PendingRecovery> = get(state_update, &key);
+        let account: Option<Account> =
+            get(state_update, &account_id_to_bytes(COL_ACCOUNT, &account_id));
+        if let (Some(pending), Some(mut account)) = (pending, account) {
+            debug!(
+                target: "runtime",
+                "Replacing keys of {} as proposed by guardian {}",
+                account_id,
+                pending.guardian,
+            );
+            account.public_keys = vec![pending.public_key];
+            account.signature_threshold = 1;
+            account.key_permissions = vec![];
+            set(state_update, &account_id_to_bytes(COL_ACCOUNT, &account_id), &account);
+        }
+        state_update.remove(&key);
+        Ok(())
+    }
+
     fn return_data_to_receipts(
         runtime_ext: &mut RuntimeExt,
         return_data: ReturnData,
         callback_info: &Option<CallbackInfo>,
         sender_id: &AccountId,
         receiver_id: &AccountId,
-    ) -> Result<Vec<ReceiptTransaction>, String> {
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
         let callback_info = match callback_info {
             Some(info) => info,
             _ => {
@@ -595,7 +1589,7 @@ impl Runtime {
                 match receipt.body {
                     ReceiptBody::NewCall(ref mut call) => {
                         if call.callback.is_some() {
-                            return Err("receipt already has callback".to_string());
+                            return Err(ActionError::ReceiptAlreadyHasCallback);
                         } else {
                             call.callback = Some(callback_info.clone());
                         }
@@ -604,7 +1598,7 @@ impl Runtime {
                 }
                 None
             }
-            _ => return Err("return data is a non-callback promise".to_string())
+            _ => return Err(ActionError::NonCallbackPromise)
         };
         let mut receipts = runtime_ext.get_receipts();
         if let Some(callback_res) = callback_res {
@@ -631,9 +1625,8 @@ impl Runtime {
         mana_accounting: &mut ManaAccounting,
         block_index: BlockIndex,
         logs: &mut Vec<LogEntry>,
-    ) -> Result<Vec<ReceiptTransaction>, String> {
-        let code: Vec<u8> = get(state_update, &account_id_to_bytes(COL_CODE, receiver_id))
-            .ok_or_else(|| format!("cannot find contract code for account {}", receiver_id.clone()))?;
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
+        let code = self.get_code(state_update, receiver_id, &receiver.code_hash)?;
         mana_accounting.gas_used = 0;
         mana_accounting.mana_refund = async_call.mana;
         mana_accounting.accounting_info = async_call.accounting_info.clone();
@@ -643,6 +1636,7 @@ impl Runtime {
                 receiver_id,
                 &async_call.accounting_info,
                 nonce,
+                &self.config,
             );
             let mut wasm_res = executor::execute(
                 &code,
@@ -660,13 +1654,13 @@ impl Runtime {
                     block_index,
                     nonce.as_ref().to_vec(),
                 ),
-            ).map_err(|e| format!("wasm async call preparation failed with error: {:?}", e))?;
+            ).map_err(|e| ActionError::WasmPreparation(format!("{:?}", e)))?;
             mana_accounting.gas_used = wasm_res.gas_used;
             mana_accounting.mana_refund = wasm_res.mana_left;
             logs.append(&mut wasm_res.logs);
             let balance = wasm_res.balance;
             let return_data = wasm_res.return_data
-                .map_err(|e| format!("wasm async call execution failed with error: {:?}", e))?;
+                .map_err(|e| ActionError::WasmExecution(format!("{:?}", e)))?;
             Self::return_data_to_receipts(
                 &mut runtime_ext,
                 return_data,
@@ -697,12 +1691,11 @@ impl Runtime {
         mana_accounting: &mut ManaAccounting,
         block_index: BlockIndex,
         logs: &mut Vec<String>,
-    ) -> Result<Vec<ReceiptTransaction>, String> {
+    ) -> Result<Vec<ReceiptTransaction>, ActionError> {
         let mut needs_removal = false;
         let mut callback: Option<Callback> = 
                 get(state_update, &callback_id_to_bytes(&callback_res.info.id));
-        let code: Vec<u8> = get(state_update, &account_id_to_bytes(COL_CODE, receiver_id))
-            .ok_or_else(|| format!("account {} does not have contract code", receiver_id.clone()))?;
+        let code = self.get_code(state_update, receiver_id, &receiver.code_hash)?;
         mana_accounting.gas_used = 0;
         mana_accounting.mana_refund = 0;
         let receipts = match callback {
@@ -716,6 +1709,7 @@ impl Runtime {
                         receiver_id,
                         &callback.accounting_info,
                         nonce,
+                        &self.config,
                     );
 
                     mana_accounting.accounting_info = callback.accounting_info.clone();
@@ -738,14 +1732,14 @@ impl Runtime {
                             nonce.as_ref().to_vec(),
                         ),
                     )
-                    .map_err(|e| format!("wasm callback execution failed with error: {:?}", e))
+                    .map_err(|e| ActionError::WasmPreparation(format!("{:?}", e)))
                     .and_then(|mut res| {
                         mana_accounting.gas_used = res.gas_used;
                         mana_accounting.mana_refund = res.mana_left;
                         logs.append(&mut res.logs);
                         let balance = res.balance;
                         res.return_data
-                            .map_err(|e| format!("wasm callback execution failed with error: {:?}", e))
+                            .map_err(|e| ActionError::WasmExecution(format!("{:?}", e)))
                             .and_then(|data|
                                 Self::return_data_to_receipts(
                                     &mut runtime_ext,
@@ -766,7 +1760,7 @@ impl Runtime {
                 }
             },
             _ => {
-                return Err(format!("callback id: {:?} not found", callback_res.info.id));
+                return Err(ActionError::CallbackNotFound(callback_res.info.id.clone()));
             }
         };
         if needs_removal {
@@ -803,7 +1797,7 @@ impl Runtime {
         new_receipts: &mut Vec<ReceiptTransaction>,
         block_index: BlockIndex,
         logs: &mut Vec<String>,
-    ) -> Result<(), String> {
+    ) -> Result<(), ActionError> {
         let receiver: Option<Account> = 
             get(state_update, &account_id_to_bytes(COL_ACCOUNT, &receipt.receiver));
         let mut amount = 0;
@@ -827,7 +1821,9 @@ impl Runtime {
                                 // Transferred amount is 0. Weird.
                                 Ok(vec![])
                             }
-                        } else if async_call.method_name == SYSTEM_METHOD_CREATE_ACCOUNT {
+                        } else if async_call.method_name == SYSTEM_METHOD_CREATE_ACCOUNT
+                            || async_call.method_name == SYSTEM_METHOD_CREATE_CONTRACT_ACCOUNT
+                        {
                             logs.push(format!("Account {} already exists", receipt.receiver));
                             let receipt = ReceiptTransaction::new(
                                 system_account(),
@@ -836,11 +1832,20 @@ impl Runtime {
                                 ReceiptBody::Refund(async_call.amount)
                             );
                             Ok(vec![receipt])
+                        } else if async_call.method_name == SYSTEM_METHOD_PROPOSE_RECOVERY {
+                            self.system_propose_recovery(
+                                state_update,
+                                &async_call,
+                                &receipt.originator,
+                                &receipt.receiver,
+                                &receiver,
+                                block_index,
+                            )
                         } else if async_call.method_name == SYSTEM_METHOD_DEPLOY {
-                            let (pub_key, code): (Vec<u8>, Vec<u8>) = Decode::decode(&async_call.args).map_err(|_| "cannot decode args".to_string())?;
-                            let pub_key = Decode::decode(&pub_key).map_err(|_| "cannot decode public key".to_string())?;
-                            // TODO(#413): Fix security of contract deploy.
-                            if receiver.public_keys.contains(&pub_key) {
+                            let (_, code): (Vec<u8>, Vec<u8>) = borsh::from_slice(&async_call.args).map_err(|_| ActionError::InvalidSystemCallArgs)?;
+                            // Only the account itself can replace its contract. The originator of
+                            // the receipt is the signer of the deploy transaction.
+                            if receipt.originator == receipt.receiver {
                                 receiver.code_hash = hash(&code);
                                 set(
                                     state_update,
@@ -854,7 +1859,10 @@ impl Runtime {
                                 );
                                 Ok(vec![])
                             } else {
-                                Err(format!("Account {} does not contain key {}", receipt.receiver, pub_key))
+                                Err(ActionError::DeployNotAllowed {
+                                    originator: receipt.originator.clone(),
+                                    account_id: receipt.receiver.clone(),
+                                })
                             }
                         } else {
                             callback_info = async_call.callback.clone();
@@ -895,11 +1903,17 @@ impl Runtime {
                         Ok(vec![])
                     },
                     ReceiptBody::ManaAccounting(mana_accounting) => {
-                        let key = get_tx_stake_key(
-                            &mana_accounting.accounting_info.originator,
-                            &mana_accounting.accounting_info.contract_id,
+                        let accounting_info = &mana_accounting.accounting_info;
+                        let mut key = get_tx_stake_key(
+                            &accounting_info.originator,
+                            &accounting_info.contract_id,
                         );
-                        let tx_total_stake: Option<TxTotalStake> = get(state_update, &key);
+                        let mut tx_total_stake: Option<TxTotalStake> = get(state_update, &key);
+                        if tx_total_stake.is_none() && accounting_info.contract_id.is_some() {
+                            // Contract specific quota is gone, falling back to the global quota.
+                            key = get_tx_stake_key(&accounting_info.originator, &None);
+                            tx_total_stake = get(state_update, &key);
+                        }
                         if let Some(mut tx_total_stake) = tx_total_stake {
                             let config = TxStakeConfig::default();
                             tx_total_stake.update(block_index, &config);
@@ -910,8 +1924,14 @@ impl Runtime {
                             );
                             set(state_update, &key, &tx_total_stake);
                         } else {
-                            // TODO(#445): Figure out what to do when the TxStake doesn't exist during mana accounting
-                            panic!("TX stake doesn't exist when mana accounting arrived");
+                            warn!(
+                                target: "runtime",
+                                "TX stake of {} doesn't exist when mana accounting arrived, dropping refund of {} mana and {} gas",
+                                accounting_info.originator,
+                                mana_accounting.mana_refund,
+                                mana_accounting.gas_used,
+                            );
+                            self.dropped_mana_refunds += 1;
                         }
                         Ok(vec![])
                     }
@@ -919,7 +1939,7 @@ impl Runtime {
             }
             _ => {
                 receiver_exists = false;
-                let err = Err(format!("receiver {} does not exist", receipt.receiver));
+                let err = Err(ActionError::ReceiverDoesNotExist(receipt.receiver.clone()));
                 if let ReceiptBody::NewCall(call) = &receipt.body {
                     amount = call.amount;
                     if call.method_name == SYSTEM_METHOD_CREATE_ACCOUNT {
@@ -929,12 +1949,20 @@ impl Runtime {
                             &receipt.receiver,
                         )
                     } else if call.method_name == SYSTEM_METHOD_DEPLOY {
-                        // TODO(#413): Fix security of contract deploy.
                         self.system_deploy(
                             state_update,
                             &call,
                             &receipt.receiver,
                         )
+                    } else if call.method_name == SYSTEM_METHOD_CREATE_CONTRACT_ACCOUNT {
+                        self.system_create_contract_account(
+                            state_update,
+                            &call,
+                            receipt,
+                            &mut mana_accounting,
+                            block_index,
+                            logs,
+                        )
                     } else {
                         err
                     }
@@ -1005,14 +2033,82 @@ impl Runtime {
         new_receipts: &mut HashMap<ShardId, Vec<ReceiptTransaction>>,
         authority_proposals: &mut Vec<AuthorityStake>,
     ) -> TransactionResult {
-        let mut result = TransactionResult::default();
-        match runtime.apply_signed_transaction(
+        let apply_result = runtime.apply_signed_transaction(
             state_update,
             block_index,
             transaction,
             authority_proposals
-        ) {
-            Ok(receipts) => {
+        );
+        let mut result = Self::finish_transaction(
+            state_update,
+            apply_result,
+            new_receipts,
+            &transaction.body,
+            transaction.body.get_tip(),
+        );
+        if result.status == TransactionStatus::Completed {
+            result.tip = transaction.body.get_tip();
+        }
+        if let TransactionBody::SendMoney(t) = &transaction.body {
+            if !t.memo.is_empty() {
+                result.memo = Some(t.memo.clone());
+            }
+        }
+        result
+    }
+
+    fn process_scheduled_transaction(
+        runtime: &mut Self,
+        state_update: &mut StateDbUpdate,
+        block_index: BlockIndex,
+        key: &[u8],
+        new_receipts: &mut HashMap<ShardId, Vec<ReceiptTransaction>>,
+        authority_proposals: &mut Vec<AuthorityStake>,
+    ) -> Result<TransactionResult, String> {
+        let scheduled: ScheduledTransaction = match get(state_update, key) {
+            Some(scheduled) => scheduled,
+            None => {
+                // Can't be executed, so it is dropped instead of being retried in every block.
+                state_update.remove(key);
+                state_update.commit();
+                return Err(format!("Scheduled transaction under {:?} doesn't decode", key));
+            }
+        };
+        let apply_result = runtime.apply_scheduled_transaction(
+            state_update,
+            block_index,
+            key,
+            &scheduled,
+            authority_proposals,
+        );
+        if apply_result.is_err() {
+            // The failed transaction is still removed from the queue.
+            state_update.rollback();
+            state_update.remove(key);
+            state_update.commit();
+        }
+        // The tip was charged when the transaction was scheduled.
+        Ok(Self::finish_transaction(
+            state_update,
+            apply_result,
+            new_receipts,
+            &scheduled.transaction.body,
+            0,
+        ))
+    }
+
+    /// Commits or rolls back the state changes of the applied transaction and
+    /// collects its result.
+    fn finish_transaction(
+        state_update: &mut StateDbUpdate,
+        apply_result: Result<Vec<ReceiptTransaction>, RuntimeError>,
+        new_receipts: &mut HashMap<ShardId, Vec<ReceiptTransaction>>,
+        body: &TransactionBody,
+        tip: Balance,
+    ) -> TransactionResult {
+        let mut result = TransactionResult::default();
+        match apply_result {
+            Ok(receipts) => {
                 for receipt in receipts {
                     result.receipts.push(receipt.nonce);
                     let shard_id = receipt.shard_id();
@@ -1024,13 +2120,17 @@ impl Runtime {
                         new_receipts.insert(shard_id, vec![receipt]);
                     }
                 }
+                let changes = balance_changes::prospective_amount_changes(state_update);
+                result.balance_changes =
+                    balance_changes::transaction_balance_changes(body, tip, &changes);
                 state_update.commit();
                 result.status = TransactionStatus::Completed;
             }
-            Err(s) => {
+            Err(e) => {
                 state_update.rollback();
-                result.logs.push(format!("Runtime error: {}", s));
+                result.logs.push(format!("Runtime error: {}", e));
                 result.status = TransactionStatus::Failed;
+                result.error = Some(e);
             }
         };
         Self::print_log(&result.logs);
@@ -1046,6 +2146,11 @@ impl Runtime {
         new_receipts: &mut HashMap<ShardId, Vec<ReceiptTransaction>>,
     ) -> TransactionResult {
         let mut result = TransactionResult::default();
+        if let ReceiptBody::NewCall(async_call) = &receipt.body {
+            if !async_call.memo.is_empty() {
+                result.memo = Some(async_call.memo.clone());
+            }
+        }
         if account_to_shard_id(&receipt.receiver) == shard_id {
             let mut tmp_new_receipts = vec![];
             let apply_result = runtime.apply_receipt(
@@ -1068,39 +2173,96 @@ impl Runtime {
             }
             match apply_result {
                 Ok(()) => {
+                    let changes = balance_changes::prospective_amount_changes(state_update);
+                    result.balance_changes =
+                        balance_changes::receipt_balance_changes(receipt, &changes);
                     state_update.commit();
                     result.status = TransactionStatus::Completed;
                 }
-                Err(s) => {
+                Err(e) => {
                     state_update.rollback();
-                    result.logs.push(format!("Runtime error: {}", s));
+                    result.logs.push(format!("Runtime error: {}", e));
                     result.status = TransactionStatus::Failed;
+                    result.error = Some(e.into());
                 }
             };
         } else {
             // wrong receipt
             result.status = TransactionStatus::Failed;
             result.logs.push("receipt sent to the wrong shard".to_string());
+            result.error = Some(ActionError::WrongShard.into());
         };
         Self::print_log(&result.logs);
         result
     }
 
-    /// apply receipts from previous block and transactions from this block
+    /// apply receipts from previous block and transactions from this block, fails if the state
+    /// db misses the nodes of the state the changes go through
     pub fn apply(
         &mut self,
         apply_state: &ApplyState,
         prev_receipts: &[ReceiptBlock],
         transactions: &[SignedTransaction],
-    ) -> ApplyResult {
+    ) -> Result<ApplyResult, String> {
+        let mut tx_result = vec![];
+        let mut new_receipts: HashMap<ShardId, Vec<ReceiptTransaction>> = HashMap::new();
+        let result = self.apply_streaming(
+            apply_state,
+            prev_receipts,
+            transactions,
+            usize::max_value(),
+            |result| tx_result.push(result),
+            |shard_id, mut receipts| {
+                new_receipts.entry(shard_id).or_insert_with(Vec::new).append(&mut receipts)
+            },
+        )?;
+        Ok(ApplyResult {
+            root: result.root,
+            db_changes: result.db_changes,
+            authority_proposals: result.authority_proposals,
+            shard_id: result.shard_id,
+            new_receipts,
+            tx_result,
+            dropped_mana_refunds: result.dropped_mana_refunds,
+        })
+    }
+
+    /// Same as `apply`, but instead of accumulating all the results in memory hands them
+    /// to the callbacks as soon as they are produced. New receipts are passed to `on_receipts`
+    /// per destination shard once `receipts_batch_size` of them are buffered for that shard.
+    /// The remaining receipts are flushed at the end.
+    pub fn apply_streaming<F, G>(
+        &mut self,
+        apply_state: &ApplyState,
+        prev_receipts: &[ReceiptBlock],
+        transactions: &[SignedTransaction],
+        receipts_batch_size: usize,
+        mut on_tx_result: F,
+        mut on_receipts: G,
+    ) -> Result<StreamingApplyResult, String>
+    where
+        F: FnMut(TransactionResult),
+        G: FnMut(ShardId, Vec<ReceiptTransaction>),
+    {
         let mut new_receipts = HashMap::new();
         let mut state_update = StateDbUpdate::new(self.state_db.clone(), apply_state.root);
         let mut authority_proposals = vec![];
         let shard_id = apply_state.shard_id;
         let block_index = apply_state.block_index;
-        let mut tx_result = vec![];
+        let mut incoming_amount: Balance = 0;
+        let mut outgoing_amount: Balance = 0;
+        let mut burnt_amount: Balance = 0;
+        self.dropped_mana_refunds = 0;
+        self.record_block_hash(&mut state_update, apply_state);
+        let mut on_new_receipts = |shard_id: ShardId, receipts: Vec<ReceiptTransaction>| {
+            outgoing_amount += receipts.iter().map(balance_checker::receipt_amount).sum::<Balance>();
+            on_receipts(shard_id, receipts);
+        };
         for receipt in prev_receipts.iter().flat_map(|b| &b.receipts) {
-            tx_result.push(Self::process_receipt(
+            if account_to_shard_id(&receipt.receiver) == shard_id {
+                incoming_amount += balance_checker::receipt_amount(receipt);
+            }
+            on_tx_result(Self::process_receipt(
                 self,
                 &mut state_update,
                 shard_id,
@@ -1108,26 +2270,168 @@ impl Runtime {
                 receipt,
                 &mut new_receipts,
             ));
+            Self::flush_receipts(&mut new_receipts, receipts_batch_size, &mut on_new_receipts);
+        }
+        if self.config.verify_signatures {
+            self.verified_signers = Self::verify_signatures_batch(&mut state_update, transactions);
         }
         for transaction in transactions {
-            tx_result.push(Self::process_transaction(
+            let result = Self::process_transaction(
                 self,
                 &mut state_update,
                 block_index,
                 transaction,
                 &mut new_receipts,
                 &mut authority_proposals
-            ));
+            );
+            burnt_amount += result.tip;
+            on_tx_result(result);
+            Self::flush_receipts(&mut new_receipts, receipts_batch_size, &mut on_new_receipts);
+        }
+        for key in Self::due_scheduled_transactions(&state_update, block_index) {
+            match Self::process_scheduled_transaction(
+                self,
+                &mut state_update,
+                block_index,
+                &key,
+                &mut new_receipts,
+                &mut authority_proposals
+            ) {
+                Ok(result) => on_tx_result(result),
+                Err(e) => warn!(target: "runtime", "{}", e),
+            }
+            Self::flush_receipts(&mut new_receipts, receipts_batch_size, &mut on_new_receipts);
+        }
+        for key in Self::due_recoveries(&state_update, block_index) {
+            if let Err(e) = Self::finalize_recovery(&mut state_update, &key) {
+                warn!(target: "runtime", "{}", e);
+            }
+        }
+        self.verified_signers.clear();
+        state_update.commit();
+        Self::flush_receipts(&mut new_receipts, 0, &mut on_new_receipts);
+        if self.config.check_balance_conservation {
+            let mut initial_state = StateDbUpdate::new(self.state_db.clone(), apply_state.root);
+            if let Err(e) = balance_checker::check_balance_conservation(
+                &mut initial_state,
+                &mut state_update,
+                incoming_amount,
+                outgoing_amount,
+                burnt_amount,
+            ) {
+                panic!("Applying block {} on shard {} failed: {}", block_index, shard_id, e);
+            }
         }
-        let (db_changes, root) = state_update.finalize();
-        ApplyResult { 
+        let (db_changes, root) = state_update.finalize()?;
+        Ok(StreamingApplyResult {
             root,
             db_changes,
             authority_proposals,
             shard_id,
-            new_receipts,
-            tx_result,
+            dropped_mana_refunds: self.dropped_mana_refunds,
+        })
+    }
+
+    /// Passes buffered receipts of every shard that has at least `batch_size` of them.
+    fn flush_receipts<G>(
+        new_receipts: &mut HashMap<ShardId, Vec<ReceiptTransaction>>,
+        batch_size: usize,
+        on_receipts: &mut G,
+    ) where
+        G: FnMut(ShardId, Vec<ReceiptTransaction>),
+    {
+        for (shard_id, receipts) in new_receipts.iter_mut() {
+            if !receipts.is_empty() && receipts.len() >= batch_size {
+                on_receipts(*shard_id, receipts.drain(..).collect());
+            }
+        }
+    }
+
+    /// Dry-run of `apply` for the given transactions. The state update is discarded,
+    /// so nothing is ever committed into the state db.
+    pub fn simulate(
+        &mut self,
+        apply_state: &ApplyState,
+        transactions: &[SignedTransaction],
+    ) -> Result<SimulateResult, String> {
+        let apply_result = self.apply(apply_state, &[], transactions)?;
+        Ok(SimulateResult {
+            root: apply_result.root,
+            shard_id: apply_result.shard_id,
+            authority_proposals: apply_result.authority_proposals,
+            new_receipts: apply_result.new_receipts,
+            tx_result: apply_result.tx_result,
+        })
+    }
+
+    /// Dry-run of the function call on top of `apply_state`. The call is executed the way
+    /// the receipt of the transaction would be, but the transaction doesn't have to be signed
+    /// and no mana is charged. Nothing is committed into the state db.
+    pub fn simulate_function_call(
+        &mut self,
+        apply_state: &ApplyState,
+        transaction: &FunctionCallTransaction,
+    ) -> Result<FunctionCallSimulation, RuntimeError> {
+        let mut state_update = StateDbUpdate::new(self.state_db.clone(), apply_state.root);
+        let originator_key = account_id_to_bytes(COL_ACCOUNT, &transaction.originator);
+        let mut originator: Account = get(&mut state_update, &originator_key).ok_or_else(|| {
+            InvalidTxError::OriginatorDoesNotExist(transaction.originator.clone())
+        })?;
+        if originator.amount < transaction.amount {
+            return Err(ActionError::InsufficientBalance {
+                account_id: transaction.originator.clone(),
+                amount: transaction.amount,
+                balance: originator.amount,
+                staked: originator.staked,
+            }.into());
         }
+        originator.amount -= transaction.amount;
+        set(&mut state_update, &originator_key, &originator);
+        let receiver: Account =
+            get(&mut state_update, &account_id_to_bytes(COL_ACCOUNT, &transaction.contract_id))
+                .ok_or_else(|| ActionError::ReceiverDoesNotExist(transaction.contract_id.clone()))?;
+        let code = self.get_code(&mut state_update, &transaction.contract_id, &receiver.code_hash)?;
+        // Same mana as the receipt of the signed transaction gets, see `call_function`.
+        let mana = TransactionBody::FunctionCall(transaction.clone()).get_mana() - 1;
+        let accounting_info =
+            AccountingInfo { originator: transaction.originator.clone(), contract_id: None };
+        // The transaction is not signed yet, so there is no hash to derive the nonces from.
+        let nonce = CryptoHash::default();
+        let mut runtime_ext = RuntimeExt::new(
+            &mut state_update,
+            &transaction.contract_id,
+            &accounting_info,
+            &nonce,
+            &self.config,
+        );
+        let wasm_res = executor::execute(
+            &code,
+            &transaction.method_name,
+            &transaction.args,
+            &[],
+            &mut runtime_ext,
+            &wasm::types::Config::default(),
+            &RuntimeContext::new(
+                receiver.amount,
+                transaction.amount,
+                &transaction.originator,
+                &transaction.contract_id,
+                mana,
+                apply_state.block_index,
+                nonce.as_ref().to_vec(),
+            ),
+        ).map_err(|e| ActionError::WasmPreparation(format!("{:?}", e)))?;
+        let result = match wasm_res.return_data {
+            Ok(ReturnData::Value(value)) => value,
+            Ok(_) => vec![],
+            Err(e) => return Err(ActionError::WasmExecution(format!("{:?}", e)).into()),
+        };
+        Ok(FunctionCallSimulation {
+            result,
+            logs: wasm_res.logs,
+            receipts: runtime_ext.get_receipts(),
+            gas_used: wasm_res.gas_used,
+        })
     }
 
     /// Balances are account, publickey, initial_balance, initial_tx_stake
@@ -1135,7 +2439,7 @@ impl Runtime {
         &self,
         balances: &[(AccountId, ReadablePublicKey, Balance, Balance)],
         wasm_binary: &[u8],
-        initial_authorities: &[(AccountId, ReadablePublicKey, u64)]
+        initial_authorities: &[(AccountId, ReadablePublicKey, ReadableBlsPublicKey, u64)]
     ) -> MerkleHash {
         let mut state_db_update =
             StateDbUpdate::new(self.state_db.clone(), MerkleHash::default());
@@ -1152,6 +2456,9 @@ impl Runtime {
                     nonce: 0,
                     staked: 0,
                     code_hash: hash(wasm_binary),
+                    signature_threshold: 1,
+                    guardians: vec![],
+                    key_permissions: vec![],
                 },
             );
             // Default code
@@ -1174,7 +2481,7 @@ impl Runtime {
             );
             // TODO(#345): Add system TX stake
         });
-        for (account_id, _pk, amount) in initial_authorities {
+        for (account_id, _pk, _bls_pk, amount) in initial_authorities {
             let account_id_bytes = account_id_to_bytes(COL_ACCOUNT, account_id);
             let mut account: Account = get(
                 &mut state_db_update,
@@ -1187,7 +2494,8 @@ impl Runtime {
                 &account
             );
         }
-        let (transaction, genesis_root) = state_db_update.finalize();
+        let (transaction, genesis_root) =
+            state_db_update.finalize().expect("Failed to build genesis state");
         // TODO: check that genesis_root is not yet in the state_db? Also may be can check before doing this?
         self.state_db.commit(transaction).expect("Failed to commit genesis state");
         genesis_root
@@ -1200,12 +2508,16 @@ mod tests {
 
     use primitives::hash::hash;
     use primitives::signature::{DEFAULT_SIGNATURE, get_key_pair, sign};
+    use primitives::test_utils::{get_bls_key_pair_from_seed, get_key_pair_from_seed};
+    use primitives::traits::Encode;
     use storage::test_utils::create_state_db;
     use transaction::{
-        DeployContractTransaction, FunctionCallTransaction,
-        TransactionBody,
+        BalanceChange, BalanceChangeCause, DeployContractTransaction, FunctionCallTransaction,
+        TransactionBody, Transfer,
     };
 
+    use crate::config::{NoncePolicy, RuntimeConfig};
+    use crate::ext::storage_prefix;
     use crate::state_viewer::AccountViewCallResult;
     use crate::test_utils::*;
 
@@ -1223,9 +2535,7 @@ mod tests {
 
     impl Default for Runtime {
         fn default() -> Runtime {
-            Runtime {
-                state_db: Arc::new(create_state_db()),
-            }
+            Runtime::new(Arc::new(create_state_db()))
         }
     }
 
@@ -1271,13 +2581,51 @@ mod tests {
         let test_account = Account::new(vec![], 10, hash(&[]));
         let account_id = bob_account();
         set(&mut state_update, &account_id_to_bytes(COL_ACCOUNT, &account_id), &test_account);
-        let (transaction, new_root) = state_update.finalize();
+        let (transaction, new_root) = state_update.finalize().unwrap();
         state_db.commit(transaction).unwrap();
         let mut new_state_update = StateDbUpdate::new(state_db.clone(), new_root);
         let get_res = get(&mut new_state_update, &account_id_to_bytes(COL_ACCOUNT, &account_id)).unwrap();
         assert_eq!(test_account, get_res);
     }
 
+    #[test]
+    fn test_simulate_function_call() {
+        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+        let call = |method_name: &[u8], contract_id: AccountId| FunctionCallTransaction {
+            nonce: 1,
+            originator: alice_account(),
+            contract_id,
+            method_name: method_name.to_vec(),
+            args: vec![],
+            amount: 10,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+        };
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let simulation = runtime
+            .simulate_function_call(&apply_state, &call(b"run_test", bob_account()))
+            .unwrap();
+        assert_eq!(simulation.result, encode_int(10).to_vec());
+        assert!(simulation.gas_used > 0);
+        assert!(simulation.receipts.is_empty());
+        let simulation = runtime
+            .simulate_function_call(&apply_state, &call(b"log_something", bob_account()))
+            .unwrap();
+        assert_eq!(simulation.logs, vec!["LOG: hello".to_string()]);
+        // Nothing was committed.
+        assert_eq!(viewer.view_account(root, &alice_account()).unwrap().amount, 100);
+        assert!(runtime
+            .simulate_function_call(&apply_state, &call(b"run_test", "unknown.near".to_string()))
+            .is_err());
+    }
+
     #[test]
     fn test_smart_contract_simple() {
         let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
@@ -1288,8 +2636,12 @@ mod tests {
             method_name: b"run_test".to_vec(),
             args: vec![],
             amount: 0,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
         });
-        let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, tx_body);
+        let transaction = signed_transaction(tx_body);
         let apply_state = ApplyState {
             root,
             shard_id: 0,
@@ -1313,6 +2665,39 @@ mod tests {
         assert_ne!(root, apply_results[2].root);
     }
 
+    #[test]
+    fn test_code_cache_reused_across_calls() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        let transactions = (1..3).map(|nonce| {
+            let tx_body = TransactionBody::FunctionCall(FunctionCallTransaction {
+                nonce,
+                originator: alice_account(),
+                contract_id: bob_account(),
+                method_name: b"run_test".to_vec(),
+                args: vec![],
+                amount: 0,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+            });
+            signed_transaction(tx_body)
+        }).collect();
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_results = runtime.apply_all_vec(apply_state, vec![], transactions);
+        assert_eq!(apply_results[1].tx_result.len(), 2);
+        for result in apply_results[1].tx_result.iter() {
+            assert_eq!(result.status, TransactionStatus::Completed);
+        }
+        assert_eq!(runtime.code_cache.len(), 1);
+        assert!(runtime.code_cache.contains_key(&default_code_hash()));
+    }
+
     #[test]
     fn test_smart_contract_bad_method_name() {
         let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
@@ -1323,8 +2708,12 @@ mod tests {
             method_name: b"_run_test".to_vec(),
             args: vec![],
             amount: 0,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
         });
-        let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, tx_body);
+        let transaction = signed_transaction(tx_body);
         let apply_state = ApplyState {
             root,
             shard_id: 0,
@@ -1358,8 +2747,12 @@ mod tests {
             method_name: b"run_test".to_vec(),
             args: (2..4).flat_map(|x| encode_int(x).to_vec()).collect(),
             amount: 0,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
         });
-        let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, tx_body);
+        let transaction = signed_transaction(tx_body);
         let apply_state = ApplyState {
             root,
             shard_id: 0,
@@ -1383,6 +2776,49 @@ mod tests {
         assert_ne!(root, apply_results[2].root);
     }
 
+    #[test]
+    fn test_transaction_size_limits() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        runtime.config.max_args_size = 8;
+        runtime.config.max_transaction_size = 200;
+        let call = |nonce, args: Vec<u8>| {
+            signed_transaction(TransactionBody::FunctionCall(FunctionCallTransaction {
+                nonce,
+                originator: alice_account(),
+                contract_id: bob_account(),
+                method_name: b"run_test".to_vec(),
+                args,
+                amount: 0,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+            }))
+        };
+        let small = call(1, vec![0; 8]);
+        let large_args = call(1, vec![0; 9]);
+        let mut large_transaction = call(1, vec![]);
+        large_transaction.extra_signatures = vec![DEFAULT_SIGNATURE; 3];
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        assert!(runtime.validate_tx(&apply_state, &small).is_ok());
+        assert_eq!(
+            runtime.validate_tx(&apply_state, &large_args),
+            Err(InvalidTxError::ArgsTooLarge { size: 9, limit: 8 })
+        );
+        assert_eq!(
+            runtime.validate_tx(&apply_state, &large_transaction),
+            Err(InvalidTxError::TransactionTooLarge {
+                size: large_transaction.get_size(),
+                limit: 200,
+            })
+        );
+    }
+
     #[test]
     fn test_upload_contract() {
         let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
@@ -1392,10 +2828,14 @@ mod tests {
             nonce: 1,
             originator: alice_account(),
             contract_id: eve_account(),
-            public_key: pub_key.0[..].to_vec(),
+            public_key: pub_key.to_bytes(),
             wasm_byte_array: wasm_binary.to_vec(),
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
         });
-        let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, tx_body);
+        let transaction = signed_transaction(tx_body);
         let apply_state = ApplyState {
             root,
             shard_id: 0,
@@ -1418,22 +2858,125 @@ mod tests {
     }
 
     #[test]
-    fn test_redeploy_contract() {
-        let test_binary = b"test_binary";
+    fn test_create_contract_account() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        let (pub_key, _) = get_key_pair();
+        let wasm_binary = include_bytes!("../../../core/wasm/runtest/res/wasm_with_mem.wasm");
+        let create_contract_account = |nonce, new_account_id: &str, init_method: &[u8]| {
+            signed_transaction(TransactionBody::CreateContractAccount(
+                CreateContractAccountTransaction {
+                    nonce,
+                    originator: alice_account(),
+                    new_account_id: new_account_id.to_string(),
+                    amount: 10,
+                    public_key: pub_key.to_bytes(),
+                    wasm_byte_array: wasm_binary.to_vec(),
+                    init_method: init_method.to_vec(),
+                    init_args: vec![],
+                    block_hash: CryptoHash::default(),
+                    genesis_hash: CryptoHash::default(),
+                    execute_at: 0,
+                    tip: 0,
+                }
+            ))
+        };
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_results = runtime.apply_all_vec(
+            apply_state,
+            vec![],
+            vec![
+                create_contract_account(1, &eve_account(), b"run_test_with_storage_change"),
+                create_contract_account(2, "carol.near", b"_run_test"),
+            ],
+        );
+        assert_eq!(apply_results[1].tx_result[0].status, TransactionStatus::Completed);
+        assert_eq!(apply_results[1].tx_result[1].status, TransactionStatus::Failed);
+        let last_result = apply_results.last().unwrap();
+        runtime.state_db.commit(last_result.db_changes.clone()).unwrap();
+        let mut new_state_update = StateDbUpdate::new(runtime.state_db, last_result.root);
+        let eve: Account =
+            get(&mut new_state_update, &account_id_to_bytes(COL_ACCOUNT, &eve_account())).unwrap();
+        assert_eq!(eve.code_hash, hash(wasm_binary));
+        assert_eq!(eve.amount, 10);
+        let carol = "carol.near".to_string();
+        assert!(get::<Account>(&mut new_state_update, &account_id_to_bytes(COL_ACCOUNT, &carol))
+            .is_none());
+        assert!(get::<Vec<u8>>(&mut new_state_update, &account_id_to_bytes(COL_CODE, &carol))
+            .is_none());
+        let alice: Account =
+            get(&mut new_state_update, &account_id_to_bytes(COL_ACCOUNT, &alice_account()))
+                .unwrap();
+        assert_eq!(alice.amount, 90);
+    }
+
+    #[test]
+    fn test_redeploy_contract_of_other_account() {
         let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
         let mut state_update = StateDbUpdate::new(runtime.state_db.clone(), root);
         let account: Account = get(
             &mut state_update,
             &account_id_to_bytes(COL_ACCOUNT, &bob_account())
         ).unwrap();
+        let tx_body = TransactionBody::DeployContract(DeployContractTransaction{
+            nonce: 1,
+            originator: alice_account(),
+            contract_id: bob_account(),
+            wasm_byte_array: b"test_binary".to_vec(),
+            public_key: account.public_keys[0].encode().unwrap(),
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+        });
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_results = runtime.apply_all_vec(
+            apply_state, vec![], vec![signed_transaction(tx_body)],
+        );
+        let receipt_result = &apply_results[1].tx_result[0];
+        assert_eq!(receipt_result.status, TransactionStatus::Failed);
+        assert_eq!(
+            receipt_result.error,
+            Some(RuntimeError::Action(ActionError::DeployNotAllowed {
+                originator: alice_account(),
+                account_id: bob_account(),
+            }))
+        );
+        runtime.state_db.commit(apply_results[1].db_changes.clone()).unwrap();
+        let mut new_state_update =
+            StateDbUpdate::new(runtime.state_db.clone(), apply_results[1].root);
+        let code: Vec<u8> = get(
+            &mut new_state_update,
+            &account_id_to_bytes(COL_CODE, &bob_account())
+        ).unwrap();
+        assert_eq!(hash(&code), default_code_hash());
+    }
+
+    #[test]
+    fn test_redeploy_contract() {
+        let test_binary = b"test_binary";
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
         let tx_body = TransactionBody::DeployContract(DeployContractTransaction{
             nonce: 1,
             originator: bob_account(),
             contract_id: bob_account(),
             wasm_byte_array: test_binary.to_vec(),
-            public_key: account.public_keys[0].encode().unwrap(),
+            public_key: vec![],
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
         });
-        let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, tx_body);
+        let transaction = signed_transaction(tx_body);
         let apply_state = ApplyState {
             root,
             shard_id: 0,
@@ -1463,8 +3006,13 @@ mod tests {
             originator: alice_account(),
             receiver: bob_account(),
             amount: 10,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
         });
-        let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, tx_body);
+        let transaction = signed_transaction(tx_body);
         let apply_state = ApplyState {
             root,
             shard_id: 0,
@@ -1502,6 +3050,81 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_simulate_send_money() {
+        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+        let tx_body = TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce: 1,
+            originator: alice_account(),
+            receiver: bob_account(),
+            amount: 10,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
+        });
+        let transaction = signed_transaction(tx_body);
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let simulate_result = runtime.simulate(&apply_state, &[transaction.clone()]).unwrap();
+        assert_eq!(simulate_result.tx_result[0].status, TransactionStatus::Completed);
+        assert_eq!(simulate_result.new_receipts.len(), 1);
+        assert_ne!(root, simulate_result.root);
+        // Nothing was committed, so the state at the new root is not available.
+        assert!(viewer.view_account(simulate_result.root, &alice_account()).is_err());
+        assert_eq!(viewer.view_account(root, &alice_account()).unwrap().amount, 100);
+        // Simulation predicts the same root as the real apply.
+        let apply_result = runtime.apply(&apply_state, &[], &[transaction]).unwrap();
+        assert_eq!(simulate_result.root, apply_result.root);
+    }
+
+    #[test]
+    fn test_apply_streaming() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        let transactions: Vec<_> = (1..4).map(|nonce| {
+            let tx_body = TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce,
+                originator: alice_account(),
+                receiver: bob_account(),
+                amount: 10,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+                memo: vec![],
+            });
+            signed_transaction(tx_body)
+        }).collect();
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let mut tx_result = vec![];
+        let mut receipt_batches = vec![];
+        let result = runtime.apply_streaming(
+            &apply_state,
+            &[],
+            &transactions,
+            2,
+            |result| tx_result.push(result),
+            |shard_id, receipts| receipt_batches.push((shard_id, receipts.len())),
+        ).unwrap();
+        assert_eq!(tx_result.len(), 3);
+        assert!(tx_result.iter().all(|r| r.status == TransactionStatus::Completed));
+        // One full batch while applying and the remainder flushed at the end.
+        assert_eq!(receipt_batches, vec![(0, 2), (0, 1)]);
+        let apply_result = runtime.apply(&apply_state, &[], &transactions).unwrap();
+        assert_eq!(result.root, apply_result.root);
+        assert_eq!(apply_result.new_receipts[&0].len(), 3);
+    }
+
     #[test]
     fn test_send_money_over_balance() {
         let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
@@ -1510,8 +3133,13 @@ mod tests {
             originator: alice_account(),
             receiver: bob_account(),
             amount: 1000,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
         });
-        let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, tx_body);
+        let transaction = signed_transaction(tx_body);
         let apply_state = ApplyState {
             root,
             shard_id: 0,
@@ -1520,8 +3148,17 @@ mod tests {
         };
         let apply_result = runtime.apply(
             &apply_state, &[], &[transaction]
-        );
+        ).unwrap();
         assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Failed);
+        assert_eq!(
+            apply_result.tx_result[0].error,
+            Some(RuntimeError::Action(ActionError::InsufficientBalance {
+                account_id: alice_account(),
+                amount: 1000,
+                balance: 100,
+                staked: 50,
+            }))
+        );
         assert_eq!(apply_result.new_receipts.len(), 0);
         assert_eq!(root, apply_result.root);
         runtime.state_db.commit(apply_result.db_changes).unwrap();
@@ -1550,54 +3187,664 @@ mod tests {
     }
 
     #[test]
-    fn test_refund_on_send_money_to_non_existent_account() {
-        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+    fn test_send_money_from_non_existent_account() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
         let tx_body = TransactionBody::SendMoney(SendMoneyTransaction {
             nonce: 1,
-            originator: alice_account(),
-            // Account should not exist
-            receiver: eve_account(),
+            originator: eve_account(),
+            receiver: bob_account(),
             amount: 10,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
         });
-        let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, tx_body);
+        let transaction = signed_transaction(tx_body);
         let apply_state = ApplyState {
             root,
             shard_id: 0,
             parent_block_hash: CryptoHash::default(),
             block_index: 0
         };
-        let apply_result = runtime.apply_all(
-            apply_state, vec![transaction]
-        );
-        assert_ne!(root, apply_result.root);
-        runtime.state_db.commit(apply_result.db_changes).unwrap();
-        let result1 = viewer.view_account(apply_result.root, &alice_account());
+        let apply_result = runtime.apply(
+            &apply_state, &[], &[transaction]
+        ).unwrap();
+        assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Failed);
         assert_eq!(
-            result1.unwrap(),
-            AccountViewCallResult {
-                nonce: 1,
-                account: alice_account(),
-                amount: 100,
-                stake: 50,
-                code_hash: default_code_hash(),
-            }
+            apply_result.tx_result[0].error,
+            Some(RuntimeError::InvalidTx(InvalidTxError::OriginatorDoesNotExist(eve_account())))
         );
-        let result2 = viewer.view_account(apply_result.root, &eve_account());
-        assert!(result2.is_err());
+        assert_eq!(root, apply_result.root);
     }
 
     #[test]
-    fn test_create_account() {
-        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
-        let (pub_key, _) = get_key_pair();
+    fn test_strict_increment_nonce_policy() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        runtime.config.nonce_policy = NoncePolicy::StrictIncrement;
+        let send_money = |nonce| signed_transaction(
+            TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce,
+                originator: alice_account(),
+                receiver: bob_account(),
+                amount: 10,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+                memo: vec![],
+            }),
+        );
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        assert_eq!(
+            runtime.validate_tx(&apply_state, &send_money(2)),
+            Err(InvalidTxError::InvalidNonce { tx_nonce: 2, account_nonce: 0 })
+        );
+        assert_eq!(runtime.validate_tx(&apply_state, &send_money(1)), Ok(()));
+        let apply_result = runtime.apply(
+            &apply_state, &[], &[send_money(2), send_money(1), send_money(3)]
+        ).unwrap();
+        assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Failed);
+        assert_eq!(apply_result.tx_result[1].status, TransactionStatus::Completed);
+        assert_eq!(apply_result.tx_result[2].status, TransactionStatus::Failed);
+    }
+
+    #[test]
+    fn test_transaction_expiry() {
+        let (mut runtime, _viewer, mut root) = get_runtime_and_state_db_viewer();
+        runtime.config.transaction_validity_period = Some(2);
+        let send_money = |nonce, block_hash| signed_transaction(
+            TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce,
+                originator: alice_account(),
+                receiver: bob_account(),
+                amount: 1,
+                block_hash,
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+                memo: vec![],
+            }),
+        );
+        let block_hash = |index| hash(&index_to_bytes(index));
+        for block_index in 1..5 {
+            let apply_state = ApplyState {
+                root,
+                shard_id: 0,
+                parent_block_hash: block_hash(block_index - 1),
+                block_index,
+            };
+            let apply_result = runtime.apply(&apply_state, &[], &[]).unwrap();
+            runtime.state_db.commit(apply_result.db_changes).unwrap();
+            root = apply_result.root;
+        }
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: block_hash(4),
+            block_index: 5,
+        };
+        assert_eq!(runtime.validate_tx(&apply_state, &send_money(1, block_hash(3))), Ok(()));
+        assert_eq!(
+            runtime.validate_tx(&apply_state, &send_money(1, block_hash(2))),
+            Err(InvalidTxError::Expired(block_hash(2)))
+        );
+        assert_eq!(
+            runtime.validate_tx(&apply_state, &send_money(1, CryptoHash::default())),
+            Err(InvalidTxError::Expired(CryptoHash::default()))
+        );
+        let apply_result = runtime.apply(
+            &apply_state, &[], &[send_money(1, block_hash(1)), send_money(2, block_hash(4))]
+        ).unwrap();
+        assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Failed);
+        assert_eq!(
+            apply_result.tx_result[0].error,
+            Some(RuntimeError::InvalidTx(InvalidTxError::Expired(block_hash(1))))
+        );
+        assert_eq!(apply_result.tx_result[1].status, TransactionStatus::Completed);
+    }
+
+    #[test]
+    fn test_transaction_for_other_chain() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        let chain_genesis = hash(b"chain");
+        runtime.config.genesis_hash = Some(chain_genesis);
+        let send_money = |genesis_hash| signed_transaction(
+            TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce: 1,
+                originator: alice_account(),
+                receiver: bob_account(),
+                amount: 1,
+                block_hash: CryptoHash::default(),
+                genesis_hash,
+                execute_at: 0,
+                tip: 0,
+                memo: vec![],
+            }),
+        );
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        assert_eq!(
+            runtime.validate_tx(&apply_state, &send_money(hash(b"other chain"))),
+            Err(InvalidTxError::WrongChain { expected: chain_genesis, found: hash(b"other chain") })
+        );
+        assert_eq!(runtime.validate_tx(&apply_state, &send_money(chain_genesis)), Ok(()));
+        assert_eq!(
+            runtime.validate_tx(&apply_state, &send_money(CryptoHash::default())),
+            Err(InvalidTxError::WrongChain { expected: chain_genesis, found: CryptoHash::default() })
+        );
+    }
+
+    #[test]
+    fn test_transaction_with_wrong_signature() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        let tx_body = TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce: 1,
+            originator: alice_account(),
+            receiver: bob_account(),
+            amount: 10,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
+        });
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let (_, bob_secret_key) = get_key_pair_from_seed(&bob_account());
+        for transaction in vec![
+            SignedTransaction::new(DEFAULT_SIGNATURE, tx_body.clone()),
+            sign_transaction(tx_body.clone(), &bob_secret_key),
+        ] {
+            assert_eq!(
+                runtime.validate_tx(&apply_state, &transaction),
+                Err(InvalidTxError::InvalidSignature(alice_account()))
+            );
+            let apply_result = runtime.apply(&apply_state, &[], &[transaction]).unwrap();
+            assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Failed);
+            assert_eq!(apply_result.root, root);
+        }
+        assert_eq!(runtime.validate_tx(&apply_state, &signed_transaction(tx_body)), Ok(()));
+    }
+
+    #[test]
+    fn test_block_with_wrong_signature() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        let send_money = |originator: AccountId, receiver: AccountId| {
+            TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce: 1,
+                originator,
+                receiver,
+                amount: 10,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+                memo: vec![],
+            })
+        };
+        let (_, alice_secret_key) = get_key_pair_from_seed(&alice_account());
+        // The batch of the block fails, only the transaction with the wrong signature fails.
+        let transactions = vec![
+            signed_transaction(send_money(alice_account(), bob_account())),
+            sign_transaction(send_money(bob_account(), alice_account()), &alice_secret_key),
+            signed_transaction(send_money(bob_account(), alice_account())),
+        ];
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result = runtime.apply(&apply_state, &[], &transactions).unwrap();
+        let statuses: Vec<_> = apply_result.tx_result.iter().map(|r| r.status.clone()).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                TransactionStatus::Completed,
+                TransactionStatus::Failed,
+                TransactionStatus::Completed,
+            ]
+        );
+        assert_eq!(
+            apply_result.tx_result[1].error,
+            Some(RuntimeError::InvalidTx(InvalidTxError::InvalidSignature(bob_account())))
+        );
+    }
+
+    #[test]
+    fn test_delegate_transaction() {
+        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+        let inner = signed_transaction(TransactionBody::FunctionCall(FunctionCallTransaction {
+            nonce: 1,
+            originator: bob_account(),
+            contract_id: bob_account(),
+            method_name: b"run_test".to_vec(),
+            args: vec![],
+            amount: 0,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+        }));
+        let delegate = |nonce| signed_transaction(TransactionBody::Delegate(DelegateTransaction {
+            nonce,
+            originator: alice_account(),
+            transaction: Box::new(inner.clone()),
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+        }));
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result = runtime.apply(&apply_state, &[], &[delegate(1)]).unwrap();
+        assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Completed);
+        let receipts: Vec<_> = apply_result.new_receipts.values().flatten().collect();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].originator, bob_account());
+        match &receipts[0].body {
+            ReceiptBody::NewCall(call) => {
+                assert_eq!(call.accounting_info.originator, alice_account());
+            }
+            _ => panic!("Expected function call receipt"),
+        }
+        runtime.state_db.commit(apply_result.db_changes).unwrap();
+        let root = apply_result.root;
+        assert_eq!(viewer.view_account(root, &alice_account()).unwrap().nonce, 1);
+        assert_eq!(viewer.view_account(root, &bob_account()).unwrap().nonce, 1);
+
+        // The inner transaction can't be replayed by another delegate transaction.
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result = runtime.apply(&apply_state, &[], &[delegate(2)]).unwrap();
+        assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Failed);
+        assert_eq!(
+            apply_result.tx_result[0].error,
+            Some(RuntimeError::InvalidTx(InvalidTxError::InvalidNonce {
+                tx_nonce: 1,
+                account_nonce: 1,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_delegate_rejects_scheduled_and_tipped() {
+        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+        let delegate = |execute_at, tip| {
+            let inner = signed_transaction(TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce: 1,
+                originator: bob_account(),
+                receiver: alice_account(),
+                amount: 1,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at,
+                tip,
+                memo: vec![],
+            }));
+            signed_transaction(TransactionBody::Delegate(DelegateTransaction {
+                nonce: 1,
+                originator: alice_account(),
+                transaction: Box::new(inner),
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+            }))
+        };
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result = runtime.apply(&apply_state, &[], &[delegate(5, 0)]).unwrap();
+        assert_eq!(
+            apply_result.tx_result[0].error,
+            Some(RuntimeError::Action(ActionError::ScheduledDelegatedTransaction {
+                execute_at: 5,
+            }))
+        );
+        let apply_result = runtime.apply(&apply_state, &[], &[delegate(0, 10)]).unwrap();
+        assert_eq!(
+            apply_result.tx_result[0].error,
+            Some(RuntimeError::Action(ActionError::DelegatedTransactionTip { tip: 10 }))
+        );
+        // Bob's tokens and nonce are untouched.
+        runtime.state_db.commit(apply_result.db_changes).unwrap();
+        let bob = viewer.view_account(apply_result.root, &bob_account()).unwrap();
+        assert_eq!(bob.nonce, 0);
+    }
+
+    #[test]
+    fn test_scheduled_transaction() {
+        let (mut runtime, viewer, mut root) = get_runtime_and_state_db_viewer();
+        let transaction = signed_transaction(TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce: 1,
+            originator: alice_account(),
+            receiver: bob_account(),
+            amount: 10,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 2,
+            tip: 0,
+            memo: vec![],
+        }));
+        for block_index in 0..3 {
+            let transactions = if block_index == 0 { vec![transaction.clone()] } else { vec![] };
+            let apply_state = ApplyState {
+                root,
+                shard_id: 0,
+                parent_block_hash: CryptoHash::default(),
+                block_index,
+            };
+            let apply_result = runtime.apply(&apply_state, &[], &transactions).unwrap();
+            runtime.state_db.commit(apply_result.db_changes).unwrap();
+            root = apply_result.root;
+            let account = viewer.view_account(root, &alice_account()).unwrap();
+            if block_index < 2 {
+                assert_eq!(account.nonce, 1);
+                assert_eq!(account.amount, 100);
+                assert!(apply_result.new_receipts.is_empty());
+            } else {
+                assert_eq!(apply_result.tx_result.len(), 1);
+                assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Completed);
+                assert_eq!(account.amount, 90);
+                assert_eq!(apply_result.new_receipts.len(), 1);
+            }
+        }
+        let state_update = StateDbUpdate::new(runtime.state_db.clone(), root);
+        assert!(Runtime::due_scheduled_transactions(&state_update, 100).is_empty());
+    }
+
+    #[test]
+    fn test_scheduled_transactions_order_and_horizon() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        runtime.config.max_schedule_horizon = 1_000;
+        let send_money = |nonce, execute_at| {
+            signed_transaction(TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce,
+                originator: alice_account(),
+                receiver: bob_account(),
+                amount: 1,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at,
+                tip: 0,
+                memo: vec![],
+            }))
+        };
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0,
+        };
+        assert_eq!(
+            runtime.validate_tx(&apply_state, &send_money(1, 1_001)),
+            Err(InvalidTxError::ScheduledTooFar { execute_at: 1_001, limit: 1_000 })
+        );
+        // Little-endian, 256 would sort before 1.
+        let transactions = vec![send_money(1, 256), send_money(2, 1)];
+        let apply_result = runtime.apply(&apply_state, &[], &transactions).unwrap();
+        runtime.state_db.commit(apply_result.db_changes).unwrap();
+        let state_update = StateDbUpdate::new(runtime.state_db.clone(), apply_result.root);
+        assert_eq!(
+            Runtime::due_scheduled_transactions(&state_update, 1),
+            vec![scheduled_transaction_key(1, &transactions[1].get_hash())]
+        );
+        assert_eq!(Runtime::due_scheduled_transactions(&state_update, 256).len(), 2);
+    }
+
+    #[test]
+    fn test_multi_send_money() {
+        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+        runtime.config.max_transfers_per_transaction = 2;
+        let multi_send_money = |nonce, receivers: Vec<AccountId>| {
+            signed_transaction(TransactionBody::MultiSendMoney(MultiSendMoneyTransaction {
+                nonce,
+                originator: alice_account(),
+                transfers: receivers.into_iter()
+                    .map(|receiver| Transfer { receiver, amount: 10 })
+                    .collect(),
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+            }))
+        };
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_results = runtime.apply_all_vec(apply_state, vec![], vec![
+            multi_send_money(1, vec![bob_account(), bob_account(), eve_account()]),
+            multi_send_money(2, vec![bob_account(), eve_account()]),
+        ]);
+        assert_eq!(apply_results[0].tx_result[0].status, TransactionStatus::Failed);
+        assert_eq!(
+            apply_results[0].tx_result[0].error,
+            Some(RuntimeError::Action(ActionError::TooManyTransfers { count: 3, limit: 2 }))
+        );
+        assert_eq!(apply_results[0].tx_result[1].status, TransactionStatus::Completed);
+        assert_eq!(apply_results[0].tx_result[1].receipts.len(), 2);
+        let last_result = apply_results.last().unwrap();
+        runtime.state_db.commit(last_result.db_changes.clone()).unwrap();
+        // Deposit to the non-existent eve.near is refunded.
+        let alice = viewer.view_account(last_result.root, &alice_account()).unwrap();
+        assert_eq!(alice.amount, 90);
+        let bob = viewer.view_account(last_result.root, &bob_account()).unwrap();
+        assert_eq!(bob.amount, 10);
+    }
+
+    #[test]
+    fn test_send_money_with_tip() {
+        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+        let send_money = |nonce, tip| {
+            signed_transaction(TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce,
+                originator: alice_account(),
+                receiver: bob_account(),
+                amount: 10,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip,
+                memo: vec![],
+            }))
+        };
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_results = runtime.apply_all_vec(apply_state, vec![], vec![
+            send_money(1, 5),
+            send_money(2, 1000),
+        ]);
+        assert_eq!(apply_results[0].tx_result[0].status, TransactionStatus::Completed);
+        assert_eq!(apply_results[0].tx_result[0].tip, 5);
+        assert_eq!(apply_results[0].tx_result[1].status, TransactionStatus::Failed);
+        assert_eq!(apply_results[0].tx_result[1].tip, 0);
+        let change = |account_id: AccountId, cause, amount, credit| BalanceChange {
+            account_id,
+            cause,
+            amount,
+            credit,
+        };
+        assert_eq!(apply_results[0].tx_result[0].balance_changes, vec![
+            change(alice_account(), BalanceChangeCause::Fee, 5, false),
+            change(alice_account(), BalanceChangeCause::Transfer, 10, false),
+        ]);
+        assert!(apply_results[0].tx_result[1].balance_changes.is_empty());
+        assert_eq!(apply_results[1].tx_result[0].balance_changes, vec![
+            change(bob_account(), BalanceChangeCause::Transfer, 10, true),
+        ]);
+        let last_result = apply_results.last().unwrap();
+        runtime.state_db.commit(last_result.db_changes.clone()).unwrap();
+        let alice = viewer.view_account(last_result.root, &alice_account()).unwrap();
+        assert_eq!(alice.amount, 85);
+        let bob = viewer.view_account(last_result.root, &bob_account()).unwrap();
+        assert_eq!(bob.amount, 10);
+    }
+
+    #[test]
+    fn test_send_money_with_memo() {
+        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+        runtime.config.max_memo_length = 4;
+        let send_money = |nonce, memo: &[u8]| {
+            signed_transaction(TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce,
+                originator: alice_account(),
+                receiver: bob_account(),
+                amount: 10,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+                memo: memo.to_vec(),
+            }))
+        };
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_results = runtime.apply_all_vec(apply_state, vec![], vec![
+            send_money(1, b"order"),
+            send_money(2, b"id42"),
+        ]);
+        assert_eq!(apply_results[0].tx_result[0].status, TransactionStatus::Failed);
+        assert_eq!(
+            apply_results[0].tx_result[0].error,
+            Some(RuntimeError::InvalidTx(InvalidTxError::MemoTooLong { length: 5, limit: 4 }))
+        );
+        assert_eq!(apply_results[0].tx_result[1].status, TransactionStatus::Completed);
+        assert_eq!(apply_results[0].tx_result[1].memo, Some(b"id42".to_vec()));
+        let deposit = &apply_results[1].tx_result[0];
+        assert_eq!(deposit.status, TransactionStatus::Completed);
+        assert_eq!(deposit.memo, Some(b"id42".to_vec()));
+        let last_result = apply_results.last().unwrap();
+        runtime.state_db.commit(last_result.db_changes.clone()).unwrap();
+        let bob = viewer.view_account(last_result.root, &bob_account()).unwrap();
+        assert_eq!(bob.amount, 10);
+    }
+
+    #[test]
+    fn test_unsupported_transaction_kind() {
+        let (mut runtime, _, root) = get_runtime_and_state_db_viewer();
+        runtime.config.protocol_version = 0;
+        let transaction = signed_transaction(TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce: 1,
+            originator: alice_account(),
+            receiver: bob_account(),
+            amount: 10,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
+        }));
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_results = runtime.apply_all_vec(apply_state, vec![], vec![transaction]);
+        assert_eq!(
+            apply_results[0].tx_result[0].error,
+            Some(RuntimeError::InvalidTx(InvalidTxError::UnsupportedKind {
+                kind: TransactionKind::SendMoney,
+                protocol_version: 1,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_refund_on_send_money_to_non_existent_account() {
+        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+        let tx_body = TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce: 1,
+            originator: alice_account(),
+            // Account should not exist
+            receiver: eve_account(),
+            amount: 10,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
+        });
+        let transaction = signed_transaction(tx_body);
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result = runtime.apply_all(
+            apply_state, vec![transaction]
+        );
+        assert_ne!(root, apply_result.root);
+        runtime.state_db.commit(apply_result.db_changes).unwrap();
+        let result1 = viewer.view_account(apply_result.root, &alice_account());
+        assert_eq!(
+            result1.unwrap(),
+            AccountViewCallResult {
+                nonce: 1,
+                account: alice_account(),
+                amount: 100,
+                stake: 50,
+                code_hash: default_code_hash(),
+            }
+        );
+        let result2 = viewer.view_account(apply_result.root, &eve_account());
+        assert!(result2.is_err());
+    }
+
+    #[test]
+    fn test_create_account() {
+        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+        let (pub_key, _) = get_key_pair();
         let tx_body = TransactionBody::CreateAccount(CreateAccountTransaction {
             nonce: 1,
             originator: alice_account(),
             new_account_id: eve_account(),
             amount: 10,
-            public_key: pub_key.0[..].to_vec(),
+            public_key: pub_key.to_bytes(),
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
         });
-        let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, tx_body);
+        let transaction = signed_transaction(tx_body);
         let apply_state = ApplyState {
             root,
             shard_id: 0,
@@ -1649,9 +3896,13 @@ mod tests {
                 originator: alice_account(),
                 new_account_id: invalid_account_name.to_string(),
                 amount: 10,
-                public_key: pub_key.encode().unwrap()
+                public_key: pub_key.encode().unwrap(),
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
             });
-            let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, tx_body);
+            let transaction = signed_transaction(tx_body);
             let apply_state = ApplyState {
                 root,
                 shard_id: 0,
@@ -1678,103 +3929,505 @@ mod tests {
     }
 
     #[test]
-    fn test_create_account_failure_already_exists() {
-        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
-        let (pub_key, _) = get_key_pair();
-        let tx_body = TransactionBody::CreateAccount(CreateAccountTransaction {
+    fn test_create_account_failure_already_exists() {
+        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+        let (pub_key, _) = get_key_pair();
+        let tx_body = TransactionBody::CreateAccount(CreateAccountTransaction {
+            nonce: 1,
+            originator: alice_account(),
+            new_account_id: bob_account(),
+            amount: 10,
+            public_key: pub_key.encode().unwrap(),
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+        });
+        let transaction = signed_transaction(tx_body);
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result = runtime.apply_all(
+            apply_state, vec![transaction]
+        );
+        assert_ne!(root, apply_result.root);
+        runtime.state_db.commit(apply_result.db_changes).unwrap();
+        let result1 = viewer.view_account(apply_result.root, &alice_account());
+        assert_eq!(
+            result1.unwrap(),
+            AccountViewCallResult {
+                nonce: 1,
+                account: alice_account(),
+                amount: 100,
+                stake: 50,
+                code_hash: default_code_hash(),
+            }
+        );
+        let result2 = viewer.view_account(apply_result.root, &bob_account());
+        assert_eq!(
+            result2.unwrap(),
+            AccountViewCallResult {
+                nonce: 0,
+                account: bob_account(),
+                amount: 0,
+                stake: 0,
+                code_hash: default_code_hash(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_swap_key() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        let (pub_key1, secret_key1) = get_key_pair();
+        let (pub_key2, _) = get_key_pair();
+        let tx_body = TransactionBody::CreateAccount(CreateAccountTransaction {
+            nonce: 1,
+            originator: alice_account(),
+            new_account_id: eve_account(),
+            amount: 10,
+            public_key: pub_key1.to_bytes(),
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+        });
+        let transaction = signed_transaction(tx_body);
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result = runtime.apply_all(
+            apply_state, vec![transaction]
+        );
+        assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Completed);
+        assert_eq!(apply_result.new_receipts.len(), 0);
+        assert_ne!(root, apply_result.root);
+        runtime.state_db.commit(apply_result.db_changes).unwrap();
+        let tx_body = TransactionBody::SwapKey(SwapKeyTransaction {
+            nonce: 2,
+            originator: eve_account(),
+            cur_key: pub_key1.to_bytes(),
+            new_key: pub_key2.to_bytes(),
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+        });
+        let transaction1 = sign_transaction(tx_body, &secret_key1);
+        let apply_state = ApplyState {
+            shard_id: 0,
+            root: apply_result.root,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0,
+        };
+        let apply_result = runtime.apply(
+            &apply_state, &[], &[transaction1],
+        ).unwrap();
+        runtime.state_db.commit(apply_result.db_changes).unwrap();
+        let mut new_state_update = StateDbUpdate::new(runtime.state_db.clone(), apply_result.root);
+        let account = get::<Account>(
+            &mut new_state_update,
+            &account_id_to_bytes(COL_ACCOUNT, &eve_account()),
+        ).unwrap();
+        assert_eq!(account.public_keys, vec![pub_key2]);
+    }
+
+    #[test]
+    fn test_swap_key_to_existing_key() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        let (alice_key, _) = get_key_pair_from_seed(&alice_account());
+        let tx_body = TransactionBody::SwapKey(SwapKeyTransaction {
+            nonce: 1,
+            originator: alice_account(),
+            cur_key: alice_key.to_bytes(),
+            new_key: alice_key.to_bytes(),
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+        });
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result =
+            runtime.apply(&apply_state, &[], &[signed_transaction(tx_body)]).unwrap();
+        assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Failed);
+        assert_eq!(
+            apply_result.tx_result[0].error,
+            Some(RuntimeError::Action(ActionError::PublicKeyAlreadyExists {
+                account_id: alice_account(),
+                public_key: alice_key,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_signature_policy() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        let (alice_key, _) = get_key_pair_from_seed(&alice_account());
+        let (key2, secret_key2) = get_key_pair();
+        let (key3, _) = get_key_pair();
+        let signature_policy = |nonce, threshold| {
+            TransactionBody::SignaturePolicy(SignaturePolicyTransaction {
+                nonce,
+                originator: alice_account(),
+                public_keys: [alice_key, key2, key3].iter().map(|k| k.to_bytes()).collect(),
+                threshold,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+            })
+        };
+        let send_money = |nonce| {
+            TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce,
+                originator: alice_account(),
+                receiver: bob_account(),
+                amount: 10,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+                memo: vec![],
+            })
+        };
+        let mut send_money_2_of_3 = signed_transaction(send_money(2));
+        send_money_2_of_3.extra_signatures =
+            vec![sign(send_money_2_of_3.get_hash().as_ref(), &secret_key2)];
+        let mut invalid_policy = signed_transaction(signature_policy(3, 4));
+        invalid_policy.extra_signatures =
+            vec![sign(invalid_policy.get_hash().as_ref(), &secret_key2)];
+        let transactions = vec![
+            signed_transaction(signature_policy(1, 2)),
+            signed_transaction(send_money(2)),
+            send_money_2_of_3,
+            invalid_policy,
+        ];
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result = runtime.apply(&apply_state, &[], &transactions).unwrap();
+        assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Completed);
+        assert_eq!(
+            apply_result.tx_result[1].error,
+            Some(RuntimeError::InvalidTx(InvalidTxError::NotEnoughSignatures {
+                originator: alice_account(),
+                signers: 1,
+                threshold: 2,
+            }))
+        );
+        assert_eq!(apply_result.tx_result[2].status, TransactionStatus::Completed);
+        assert_eq!(
+            apply_result.tx_result[3].error,
+            Some(RuntimeError::Action(ActionError::InvalidThreshold {
+                account_id: alice_account(),
+                threshold: 4,
+                num_keys: 3,
+            }))
+        );
+        runtime.state_db.commit(apply_result.db_changes).unwrap();
+        let mut new_state_update = StateDbUpdate::new(runtime.state_db.clone(), apply_result.root);
+        let account = get::<Account>(
+            &mut new_state_update,
+            &account_id_to_bytes(COL_ACCOUNT, &alice_account()),
+        ).unwrap();
+        assert_eq!(account.public_keys, vec![alice_key, key2, key3]);
+        assert_eq!(account.signature_threshold, 2);
+    }
+
+    #[test]
+    fn test_signature_policy_with_duplicate_key() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        let (alice_key, _) = get_key_pair_from_seed(&alice_account());
+        let tx_body = TransactionBody::SignaturePolicy(SignaturePolicyTransaction {
             nonce: 1,
             originator: alice_account(),
-            new_account_id: bob_account(),
-            amount: 10,
-            public_key: pub_key.encode().unwrap()
+            public_keys: vec![alice_key.to_bytes(), alice_key.to_bytes()],
+            threshold: 1,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
         });
-        let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, tx_body);
         let apply_state = ApplyState {
             root,
             shard_id: 0,
             parent_block_hash: CryptoHash::default(),
             block_index: 0
         };
-        let apply_result = runtime.apply_all(
-            apply_state, vec![transaction]
-        );
-        assert_ne!(root, apply_result.root);
-        runtime.state_db.commit(apply_result.db_changes).unwrap();
-        let result1 = viewer.view_account(apply_result.root, &alice_account());
+        let apply_result =
+            runtime.apply(&apply_state, &[], &[signed_transaction(tx_body)]).unwrap();
         assert_eq!(
-            result1.unwrap(),
-            AccountViewCallResult {
+            apply_result.tx_result[0].error,
+            Some(RuntimeError::Action(ActionError::PublicKeyAlreadyExists {
+                account_id: alice_account(),
+                public_key: alice_key,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_key_permissions() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        let (alice_key, _) = get_key_pair_from_seed(&alice_account());
+        let (key2, secret_key2) = get_key_pair();
+        let (key3, _) = get_key_pair();
+        let set_key_permissions = |nonce, allowed: &[&str]| {
+            TransactionBody::SetKeyPermissions(SetKeyPermissionsTransaction {
+                nonce,
+                originator: alice_account(),
+                public_key: key2.to_bytes(),
+                allowed: allowed.iter().map(|k| k.to_string()).collect(),
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+            })
+        };
+        let transactions = vec![
+            signed_transaction(TransactionBody::SignaturePolicy(SignaturePolicyTransaction {
                 nonce: 1,
-                account: alice_account(),
-                amount: 100,
-                stake: 50,
-                code_hash: default_code_hash(),
-            }
+                originator: alice_account(),
+                public_keys: [alice_key, key2].iter().map(|k| k.to_bytes()).collect(),
+                threshold: 1,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+            })),
+            signed_transaction(set_key_permissions(2, &["Teleport"])),
+            signed_transaction(set_key_permissions(3, &["SendMoney", "SendMoney"])),
+            sign_transaction(TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce: 4,
+                originator: alice_account(),
+                receiver: bob_account(),
+                amount: 10,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+                memo: vec![],
+            }), &secret_key2),
+            sign_transaction(TransactionBody::Stake(StakeTransaction {
+                nonce: 5,
+                originator: alice_account(),
+                amount: 10,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+                bls_public_key: vec![],
+                bls_proof_of_possession: vec![],
+            }), &secret_key2),
+            sign_transaction(TransactionBody::SwapKey(SwapKeyTransaction {
+                nonce: 5,
+                originator: alice_account(),
+                cur_key: key2.to_bytes(),
+                new_key: key3.to_bytes(),
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+            }), &secret_key2),
+        ];
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result = runtime.apply(&apply_state, &[], &transactions).unwrap();
+        assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Completed);
+        assert_eq!(
+            apply_result.tx_result[1].error,
+            Some(RuntimeError::Action(ActionError::UnknownTransactionKind("Teleport".to_string())))
         );
-        let result2 = viewer.view_account(apply_result.root, &bob_account());
+        assert_eq!(apply_result.tx_result[2].status, TransactionStatus::Completed);
+        assert_eq!(apply_result.tx_result[3].status, TransactionStatus::Completed);
+        for result in &apply_result.tx_result[4..] {
+            assert_eq!(result.status, TransactionStatus::Failed);
+        }
         assert_eq!(
-            result2.unwrap(),
-            AccountViewCallResult {
-                nonce: 0,
-                account: bob_account(),
-                amount: 0,
-                stake: 0,
-                code_hash: default_code_hash(),
-            }
+            apply_result.tx_result[4].error,
+            Some(RuntimeError::InvalidTx(InvalidTxError::KeyNotAllowed {
+                originator: alice_account(),
+                kind: TransactionKind::Stake,
+            }))
+        );
+        assert_eq!(
+            apply_result.tx_result[5].error,
+            Some(RuntimeError::InvalidTx(InvalidTxError::KeyNotAllowed {
+                originator: alice_account(),
+                kind: TransactionKind::SwapKey,
+            }))
+        );
+        runtime.state_db.commit(apply_result.db_changes).unwrap();
+        let mut new_state_update = StateDbUpdate::new(runtime.state_db.clone(), apply_result.root);
+        let account = get::<Account>(
+            &mut new_state_update,
+            &account_id_to_bytes(COL_ACCOUNT, &alice_account()),
+        ).unwrap();
+        assert_eq!(
+            account.key_permissions,
+            vec![KeyPermission { public_key: key2, allowed: vec![TransactionKind::SendMoney] }]
         );
+        assert_eq!(account.public_keys, vec![alice_key, key2]);
     }
 
-    #[test]
-    fn test_swap_key() {
-        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
-        let (pub_key1, secret_key1) = get_key_pair();
-        let (pub_key2, _) = get_key_pair();
-        let tx_body = TransactionBody::CreateAccount(CreateAccountTransaction {
+    /// Makes bob a guardian of alice and lets bob propose the given key for alice.
+    fn propose_recovery_of_alice(
+        runtime: &mut Runtime,
+        root: MerkleHash,
+        public_key: PublicKey,
+    ) -> Vec<ApplyResult> {
+        let set_guardians = TransactionBody::SetGuardians(SetGuardiansTransaction {
             nonce: 1,
             originator: alice_account(),
-            new_account_id: eve_account(),
-            amount: 10,
-            public_key: pub_key1.0[..].to_vec(),
+            guardians: vec![bob_account()],
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
         });
-        let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, tx_body);
+        let propose_recovery = |nonce, originator, account_id| {
+            TransactionBody::ProposeRecovery(ProposeRecoveryTransaction {
+                nonce,
+                originator,
+                account_id,
+                public_key: public_key.to_bytes(),
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+            })
+        };
         let apply_state = ApplyState {
             root,
             shard_id: 0,
             parent_block_hash: CryptoHash::default(),
             block_index: 0
         };
-        let apply_result = runtime.apply_all(
-            apply_state, vec![transaction]
+        let apply_results = runtime.apply_all_vec(apply_state, vec![], vec![
+            signed_transaction(set_guardians),
+            signed_transaction(propose_recovery(1, bob_account(), alice_account())),
+            signed_transaction(propose_recovery(2, alice_account(), bob_account())),
+        ]);
+        runtime.state_db.commit(apply_results[1].db_changes.clone()).unwrap();
+        apply_results
+    }
+
+    fn apply_empty_block(
+        runtime: &mut Runtime,
+        root: MerkleHash,
+        block_index: BlockIndex,
+    ) -> MerkleHash {
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index,
+        };
+        let apply_result = runtime.apply(&apply_state, &[], &[]).unwrap();
+        runtime.state_db.commit(apply_result.db_changes).unwrap();
+        apply_result.root
+    }
+
+    #[test]
+    fn test_recovery() {
+        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+        runtime.config.recovery_delay = 2;
+        let (alice_key, _) = get_key_pair_from_seed(&alice_account());
+        let (new_key, _) = get_key_pair();
+        let apply_results = propose_recovery_of_alice(&mut runtime, root, new_key);
+        assert_eq!(apply_results[1].tx_result[0].status, TransactionStatus::Completed);
+        assert_eq!(
+            apply_results[1].tx_result[1].error,
+            Some(RuntimeError::Action(ActionError::NotGuardian {
+                guardian: alice_account(),
+                account_id: bob_account(),
+            }))
         );
+        let root = apply_empty_block(&mut runtime, apply_results[1].root, 1);
+        assert_eq!(viewer.get_public_keys_for_account(root, &alice_account()), Ok(vec![alice_key]));
+        let root = apply_empty_block(&mut runtime, root, 2);
+        assert_eq!(viewer.get_public_keys_for_account(root, &alice_account()), Ok(vec![new_key]));
+        let state_update = StateDbUpdate::new(runtime.state_db.clone(), root);
+        assert!(Runtime::due_recoveries(&state_update, 100).is_empty());
+    }
+
+    #[test]
+    fn test_cancel_recovery() {
+        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+        runtime.config.recovery_delay = 2;
+        let (alice_key, _) = get_key_pair_from_seed(&alice_account());
+        let (new_key, _) = get_key_pair();
+        let apply_results = propose_recovery_of_alice(&mut runtime, root, new_key);
+        let cancel_recovery = |nonce| {
+            signed_transaction(TransactionBody::CancelRecovery(CancelRecoveryTransaction {
+                nonce,
+                originator: alice_account(),
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+            }))
+        };
+        let apply_state = ApplyState {
+            root: apply_results[1].root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 1,
+        };
+        let transactions = vec![cancel_recovery(3), cancel_recovery(4)];
+        let apply_result = runtime.apply(&apply_state, &[], &transactions).unwrap();
         assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Completed);
-        assert_eq!(apply_result.new_receipts.len(), 0);
-        assert_ne!(root, apply_result.root);
+        assert_eq!(
+            apply_result.tx_result[1].error,
+            Some(RuntimeError::Action(ActionError::RecoveryNotFound(alice_account())))
+        );
         runtime.state_db.commit(apply_result.db_changes).unwrap();
-        let tx_body = TransactionBody::SwapKey(SwapKeyTransaction {
-            nonce: 2,
-            originator: eve_account(),
-            cur_key: pub_key1.encode().unwrap(),
-            new_key: pub_key2.encode().unwrap(),
+        let root = apply_empty_block(&mut runtime, apply_result.root, 2);
+        assert_eq!(viewer.get_public_keys_for_account(root, &alice_account()), Ok(vec![alice_key]));
+    }
+
+    #[test]
+    fn test_too_many_guardians() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        runtime.config.max_guardians = 1;
+        let set_guardians = TransactionBody::SetGuardians(SetGuardiansTransaction {
+            nonce: 1,
+            originator: alice_account(),
+            guardians: vec![bob_account(), eve_account()],
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
         });
-        let data = tx_body.encode().unwrap();
-        let signature = sign(&data, &secret_key1);
-        let transaction1 = SignedTransaction::new(signature, tx_body);
         let apply_state = ApplyState {
+            root,
             shard_id: 0,
-            root: apply_result.root,
             parent_block_hash: CryptoHash::default(),
-            block_index: 0,
+            block_index: 0
         };
-        let apply_result = runtime.apply(
-            &apply_state, &[], &[transaction1],
+        let apply_result =
+            runtime.apply(&apply_state, &[], &[signed_transaction(set_guardians)]).unwrap();
+        assert_eq!(
+            apply_result.tx_result[0].error,
+            Some(RuntimeError::Action(ActionError::TooManyGuardians { count: 2, limit: 1 }))
         );
-        runtime.state_db.commit(apply_result.db_changes).unwrap();
-        let mut new_state_update = StateDbUpdate::new(runtime.state_db.clone(), apply_result.root);
-        let account = get::<Account>(
-            &mut new_state_update,
-            &account_id_to_bytes(COL_ACCOUNT, &eve_account()),
-        ).unwrap();
-        assert_eq!(account.public_keys, vec![pub_key2]);
     }
 
     #[test]
@@ -1816,6 +4469,87 @@ mod tests {
         assert_ne!(root, apply_results[1].root);
     }
 
+    fn mana_accounting_receipt(originator: AccountId, contract_id: Option<AccountId>) -> ReceiptTransaction {
+        ReceiptTransaction::new(
+            bob_account(),
+            originator.clone(),
+            hash(&[4, 5, 6]).into(),
+            ReceiptBody::ManaAccounting(ManaAccounting {
+                accounting_info: AccountingInfo { originator, contract_id },
+                mana_refund: 1,
+                gas_used: 0,
+            })
+        )
+    }
+
+    #[test]
+    fn test_mana_accounting_without_tx_stake() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        // Eve doesn't have any TX stake.
+        let receipt = mana_accounting_receipt(eve_account(), Some(alice_account()));
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result = runtime.apply(
+            &apply_state, &[to_receipt_block(vec![receipt])], &[]
+        ).unwrap();
+        assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Completed);
+        assert_eq!(root, apply_result.root);
+        assert_eq!(apply_result.dropped_mana_refunds, 1);
+    }
+
+    #[test]
+    fn test_mana_accounting_falls_back_to_global_tx_stake() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        // Alice only has the global TX stake.
+        let receipt = mana_accounting_receipt(alice_account(), Some(bob_account()));
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result = runtime.apply(
+            &apply_state, &[to_receipt_block(vec![receipt])], &[]
+        ).unwrap();
+        assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Completed);
+        assert_ne!(root, apply_result.root);
+    }
+
+    #[test]
+    fn test_deposit_args_are_not_memo() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        let receipt = ReceiptTransaction::new(
+            alice_account(),
+            bob_account(),
+            hash(&[1, 2, 3]),
+            ReceiptBody::NewCall(AsyncCall::new(
+                vec![],
+                b"id42".to_vec(),
+                10,
+                0,
+                AccountingInfo {
+                    originator: alice_account(),
+                    contract_id: None,
+                },
+            ))
+        );
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result = runtime.apply(
+            &apply_state, &[to_receipt_block(vec![receipt])], &[]
+        ).unwrap();
+        assert_eq!(apply_result.tx_result[0].status, TransactionStatus::Completed);
+        assert_eq!(apply_result.tx_result[0].memo, None);
+    }
+
     #[test]
     fn test_async_call_with_logs() {
         let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
@@ -1938,7 +4672,7 @@ mod tests {
             &callback_id_to_bytes(&callback_id.clone()),
             &callback
         );
-        let (transaction, new_root) = state_update.finalize();
+        let (transaction, new_root) = state_update.finalize().unwrap();
         runtime.state_db.commit(transaction).unwrap();
         let receipt = ReceiptTransaction::new(
             alice_account(),
@@ -1957,7 +4691,7 @@ mod tests {
         };
         let apply_result = runtime.apply(
             &apply_state, &[to_receipt_block(vec![receipt])], &[]
-        );
+        ).unwrap();
         assert_ne!(new_root, apply_result.root);
         runtime.state_db.commit(apply_result.db_changes).unwrap();
         let mut state_update = StateDbUpdate::new(runtime.state_db.clone(), apply_result.root);
@@ -1986,7 +4720,7 @@ mod tests {
             &callback_id_to_bytes(&callback_id.clone()),
             &callback
         );
-        let (transaction, new_root) = state_update.finalize();
+        let (transaction, new_root) = state_update.finalize().unwrap();
         runtime.state_db.commit(transaction).unwrap();
         let receipt = ReceiptTransaction::new(
             alice_account(),
@@ -2005,7 +4739,7 @@ mod tests {
         };
         let apply_result = runtime.apply(
             &apply_state, &[to_receipt_block(vec![receipt])], &[]
-        );
+        ).unwrap();
         // the callback should be removed
         assert_ne!(new_root, apply_result.root);
         runtime.state_db.commit(apply_result.db_changes).unwrap();
@@ -2025,8 +4759,12 @@ mod tests {
             contract_id: eve_account(),
             public_key: pub_key.encode().unwrap(),
             wasm_byte_array: wasm_binary.to_vec(),
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
         });
-        let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, tx_body);
+        let transaction = signed_transaction(tx_body);
         let apply_state = ApplyState {
             root,
             shard_id: 0,
@@ -2035,7 +4773,7 @@ mod tests {
         };
         let apply_result = runtime.apply(
             &apply_state, &[], &[transaction]
-        );
+        ).unwrap();
         runtime.state_db.commit(apply_result.db_changes).unwrap();
         let mut state_update = StateDbUpdate::new(runtime.state_db.clone(), apply_result.root);
         let account: Account = get(
@@ -2057,4 +4795,141 @@ mod tests {
             assert_eq!(viewer.view_account(root, &format!("account{}", i)).unwrap().amount, 10000)
         }
     }
+
+    #[test]
+    fn test_stake_requires_proof_of_possession() {
+        let (mut runtime, _viewer, root) = get_runtime_and_state_db_viewer();
+        let (bls_public_key, bls_secret_key) = get_bls_key_pair_from_seed(&alice_account());
+        let (_, other_secret_key) = get_bls_key_pair_from_seed(&bob_account());
+        let bls_public_key = Vec::from(&bls_public_key);
+        let cases = vec![
+            (vec![1, 2, 3], bls_secret_key.get_proof_of_possession(), ActionError::InvalidPublicKey),
+            (
+                bls_public_key.clone(),
+                other_secret_key.get_proof_of_possession(),
+                ActionError::InvalidProofOfPossession(alice_account()),
+            ),
+            // Signing the key is not a proof of possession.
+            (
+                bls_public_key.clone(),
+                bls_secret_key.sign(&bls_public_key),
+                ActionError::InvalidProofOfPossession(alice_account()),
+            ),
+        ];
+        for (bls_public_key, proof, error) in cases {
+            let transaction = signed_transaction(TransactionBody::Stake(StakeTransaction {
+                nonce: 1,
+                originator: alice_account(),
+                amount: 10,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
+                bls_public_key,
+                bls_proof_of_possession: Vec::from(&proof),
+            }));
+            let apply_state = ApplyState {
+                root,
+                shard_id: 0,
+                parent_block_hash: CryptoHash::default(),
+                block_index: 0
+            };
+            let apply_result = runtime.apply(&apply_state, &[], &[transaction]).unwrap();
+            assert_eq!(apply_result.tx_result[0].error, Some(RuntimeError::Action(error)));
+            assert!(apply_result.authority_proposals.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_stake() {
+        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+        let (bls_public_key, bls_secret_key) = get_bls_key_pair_from_seed(&alice_account());
+        let transaction = signed_transaction(TransactionBody::Stake(StakeTransaction {
+            nonce: 1,
+            originator: alice_account(),
+            amount: 10,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+            bls_public_key: Vec::from(&bls_public_key),
+            bls_proof_of_possession: Vec::from(&bls_secret_key.get_proof_of_possession()),
+        }));
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result = runtime.apply(&apply_state, &[], &[transaction]).unwrap();
+        assert_eq!(apply_result.tx_result[0].error, None);
+        assert_eq!(apply_result.authority_proposals.len(), 1);
+        runtime.state_db.commit(apply_result.db_changes).unwrap();
+        let account = viewer.view_account(apply_result.root, &alice_account()).unwrap();
+        assert_eq!((account.amount, account.stake), (90, 60));
+    }
+
+    #[test]
+    fn test_genesis_builder() {
+        let wasm_binary = include_bytes!("../../../core/wasm/runtest/res/wasm_with_mem.wasm");
+        let account_id = "test.near".to_string();
+        let config = RuntimeConfig { verify_signatures: false, ..Default::default() };
+        let (runtime, viewer, root) = GenesisBuilder::new()
+            .account(&account_id, 100)
+            .stake(&account_id, 20)
+            .code(&account_id, wasm_binary)
+            .data(&account_id, b"key", b"value")
+            .account("bob.near", 5)
+            .config(config)
+            .build();
+        assert!(!runtime.config.verify_signatures);
+        let account = viewer.view_account(root, &account_id).unwrap();
+        assert_eq!((account.amount, account.stake), (100, 20));
+        assert_eq!(account.code_hash, hash(wasm_binary));
+        let result = viewer.call_function(root, 0, &account_id, "run_test", &[]).unwrap();
+        assert_eq!(result, encode_int(10).to_vec());
+        let mut key = storage_prefix(&account_id);
+        key.extend_from_slice(b"key");
+        assert_eq!(viewer.view_state(root, &account_id).unwrap().values[&key], b"value".to_vec());
+        assert_eq!(viewer.view_account(root, &bob_account()).unwrap().amount, 5);
+    }
+
+    #[test]
+    fn test_rotate_key() {
+        let (mut runtime, viewer, root) = get_runtime_and_state_db_viewer();
+        let public_key = get_key_pair().0;
+        let (bls_public_key, bls_secret_key) = get_bls_key_pair_from_seed("new key");
+        let transaction = signed_transaction(TransactionBody::RotateKey(RotateKeyTransaction {
+            nonce: 1,
+            originator: alice_account(),
+            public_key: public_key.to_bytes(),
+            bls_public_key: Vec::from(&bls_public_key),
+            bls_proof_of_possession: Vec::from(&bls_secret_key.get_proof_of_possession()),
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+        }));
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            parent_block_hash: CryptoHash::default(),
+            block_index: 0
+        };
+        let apply_result = runtime.apply(&apply_state, &[], &[transaction]).unwrap();
+        assert_eq!(apply_result.tx_result[0].error, None);
+        // The keys change, the stake doesn't.
+        assert_eq!(
+            apply_result.authority_proposals,
+            vec![AuthorityStake {
+                account_id: alice_account(),
+                public_key,
+                bls_public_key,
+                amount: 0,
+            }]
+        );
+        runtime.state_db.commit(apply_result.db_changes).unwrap();
+        let account = viewer.view_account(apply_result.root, &alice_account()).unwrap();
+        assert_eq!((account.amount, account.stake), (100, 10));
+    }
 }
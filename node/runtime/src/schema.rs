@@ -0,0 +1,41 @@
+//! Schema of the types that are signed, hashed and stored on chain, from which the SDKs generate
+//! their encoders. It is kept in `res/schema.json`, which is checked against the types and is
+//! updated with `NEAR_UPDATE_SCHEMA=1 cargo test -p node-runtime schema`.
+use primitives::schema::{Registry, Result, Tracer};
+use transaction::{Callback, ReceiptTransaction, SignedTransaction, TransactionResult};
+
+use crate::Account;
+
+/// Formats of the transactions, the receipts, their results and the state of the accounts.
+pub fn schema() -> Result<Registry> {
+    let mut tracer = Tracer::new();
+    tracer.trace::<SignedTransaction>()?;
+    tracer.trace::<ReceiptTransaction>()?;
+    tracer.trace::<TransactionResult>()?;
+    tracer.trace::<Account>()?;
+    tracer.trace::<Callback>()?;
+    tracer.registry()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn test_schema_is_up_to_date() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res/schema.json");
+        let schema = serde_json::to_value(schema().unwrap()).unwrap();
+        if std::env::var("NEAR_UPDATE_SCHEMA").is_ok() {
+            fs::write(&path, serde_json::to_string_pretty(&schema).unwrap() + "\n").unwrap();
+        }
+        let stored: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            schema, stored,
+            "res/schema.json is out of date, update it with NEAR_UPDATE_SCHEMA=1"
+        );
+    }
+}
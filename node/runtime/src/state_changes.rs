@@ -0,0 +1,134 @@
+//! Interprets the raw keys changed by a block in terms of accounts and their records.
+use primitives::signature::PublicKey;
+use primitives::serialize::from_state_value;
+use primitives::types::{AccountId, Balance};
+use storage::KeyChange;
+
+use super::{account_id_to_bytes, Account, KeyPermission, COL_ACCOUNT, COL_CODE};
+
+/// Separates the account id from the key of the contract data, see `RuntimeExt`.
+pub(crate) const DATA_SEPARATOR: u8 = b',';
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StateChangeKind {
+    /// Any field of the account record.
+    AccountUpdate,
+    /// Public keys of the account or their permissions.
+    AccessKey,
+    Code,
+    ContractData,
+    /// Runtime bookkeeping, e.g. callbacks and scheduled transactions.
+    Other,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StateChange {
+    pub kind: StateChangeKind,
+    pub account_id: Option<AccountId>,
+    pub key: Vec<u8>,
+    /// New value of the record, `None` if it was removed.
+    pub value: Option<Vec<u8>>,
+}
+
+fn access_keys(value: &Option<Vec<u8>>) -> Option<(Vec<PublicKey>, Vec<KeyPermission>)> {
+    let account: Account = from_state_value(value.as_ref()?).ok()?;
+    Some((account.public_keys, account.key_permissions))
+}
+
+fn account_id(bytes: &[u8]) -> AccountId {
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+/// Classifies the changed keys. A changed account record whose keys or their permissions
+/// changed is reported both as an account update and as an access key change.
+pub fn classify_changes(changes: &[KeyChange]) -> Vec<StateChange> {
+    let mut result = vec![];
+    for (key, old_value, new_value) in changes {
+        let change = |kind, account_id| StateChange {
+            kind,
+            account_id,
+            key: key.clone(),
+            value: new_value.clone(),
+        };
+        if key.starts_with(COL_ACCOUNT) {
+            let rest = &key[COL_ACCOUNT.len()..];
+            match rest.iter().position(|b| *b == DATA_SEPARATOR) {
+                Some(i) => {
+                    result.push(change(StateChangeKind::ContractData, Some(account_id(&rest[..i]))))
+                }
+                None => {
+                    result.push(change(StateChangeKind::AccountUpdate, Some(account_id(rest))));
+                    if access_keys(old_value) != access_keys(new_value) {
+                        result.push(change(StateChangeKind::AccessKey, Some(account_id(rest))));
+                    }
+                }
+            }
+        } else if key.starts_with(COL_CODE) {
+            result.push(change(StateChangeKind::Code, Some(account_id(&key[COL_CODE.len()..]))));
+        } else {
+            result.push(change(StateChangeKind::Other, None));
+        }
+    }
+    result
+}
+
+fn decode_amount(value: &Option<Vec<u8>>) -> Option<Balance> {
+    let account: Account = from_state_value(value.as_ref()?).ok()?;
+    Some(account.amount)
+}
+
+/// Amount on the account before and after the changes, `None` if the account record didn't
+/// change. Either amount is `None` if the account didn't exist at that point.
+pub fn account_amount_change(
+    changes: &[KeyChange],
+    account_id: &AccountId,
+) -> Option<(Option<Balance>, Option<Balance>)> {
+    let key = account_id_to_bytes(COL_ACCOUNT, account_id);
+    changes
+        .iter()
+        .find(|(changed_key, _, _)| *changed_key == key)
+        .map(|(_, old_value, new_value)| (decode_amount(old_value), decode_amount(new_value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use primitives::hash::CryptoHash;
+    use primitives::signature::get_key_pair;
+    use primitives::serialize::to_state_value;
+
+    use super::*;
+
+    #[test]
+    fn test_classify_changes() {
+        let alice = "alice.near".to_string();
+        let public_key = get_key_pair().0;
+        let account = Account::new(vec![public_key], 10, CryptoHash::default());
+        let paid = Account::new(vec![public_key], 5, CryptoHash::default());
+        let rekeyed = Account::new(vec![get_key_pair().0], 10, CryptoHash::default());
+        let account_key = account_id_to_bytes(COL_ACCOUNT, &alice);
+        let mut data_key = account_key.clone();
+        data_key.extend_from_slice(b",counter");
+        let changes = vec![
+            (account_key.clone(), to_state_value(&account).ok(), to_state_value(&paid).ok()),
+            (account_key.clone(), to_state_value(&account).ok(), to_state_value(&rekeyed).ok()),
+            (data_key, None, Some(vec![1])),
+            (account_id_to_bytes(COL_CODE, &alice), None, Some(vec![2])),
+            (vec![100], Some(vec![3]), None),
+        ];
+        let classified: Vec<_> = classify_changes(&changes)
+            .into_iter()
+            .map(|c| (c.kind, c.account_id))
+            .collect();
+        assert_eq!(classified, vec![
+            (StateChangeKind::AccountUpdate, Some(alice.clone())),
+            (StateChangeKind::AccountUpdate, Some(alice.clone())),
+            (StateChangeKind::AccessKey, Some(alice.clone())),
+            (StateChangeKind::ContractData, Some(alice.clone())),
+            (StateChangeKind::Code, Some(alice.clone())),
+            (StateChangeKind::Other, None),
+        ]);
+        assert_eq!(account_amount_change(&changes, &alice), Some((Some(10), Some(5))));
+        assert_eq!(account_amount_change(&changes, &"bob.near".to_string()), None);
+    }
+}
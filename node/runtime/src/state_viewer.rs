@@ -6,12 +6,15 @@ use primitives::hash::CryptoHash;
 use primitives::utils::is_valid_account_id;
 use primitives::types::{AccountId, Balance, MerkleHash, AccountingInfo};
 use storage::{StateDb, StateDbUpdate};
+use transaction::TransactionKind;
 use wasm::executor;
 use wasm::types::{ReturnData, RuntimeContext};
 
 use super::{
     Account, account_id_to_bytes, get, RuntimeExt, COL_ACCOUNT, COL_CODE,
 };
+use crate::config::RuntimeConfig;
+use crate::ext::storage_prefix;
 use primitives::signature::PublicKey;
 
 #[derive(Serialize, Deserialize)]
@@ -32,6 +35,23 @@ pub struct AccountViewCallResult {
     pub code_hash: CryptoHash,
 }
 
+/// Public key of an account together with the state needed to sign with it.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub struct AccessKeyViewCallResult {
+    pub account: AccountId,
+    pub public_key: PublicKey,
+    pub nonce: u64,
+    /// Kinds of transactions the key may sign. Empty if the key is unrestricted.
+    pub allowed: Vec<TransactionKind>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub struct CodeViewCallResult {
+    pub account: AccountId,
+    pub code_hash: CryptoHash,
+    pub code: Vec<u8>,
+}
+
 impl StateDbViewer {
     pub fn new(state_db: Arc<StateDb>) -> Self {
         StateDbViewer {
@@ -63,6 +83,52 @@ impl StateDbViewer {
         }
     }
 
+    pub fn view_access_key(
+        &self,
+        root: MerkleHash,
+        account_id: &AccountId,
+        public_key: &PublicKey,
+    ) -> Result<AccessKeyViewCallResult, String> {
+        if !is_valid_account_id(account_id) {
+            return Err(format!("Account ID '{}' is not valid", account_id));
+        }
+        let mut state_update = StateDbUpdate::new(self.state_db.clone(), root);
+        let account =
+            get::<Account>(&mut state_update, &account_id_to_bytes(COL_ACCOUNT, account_id))
+                .ok_or_else(|| format!("account {} does not exist while viewing", account_id))?;
+        if !account.public_keys.contains(public_key) {
+            return Err(format!("account {} does not have key {}", account_id, public_key));
+        }
+        let allowed = account
+            .key_permissions
+            .into_iter()
+            .find(|p| &p.public_key == public_key)
+            .map_or(vec![], |p| p.allowed);
+        Ok(AccessKeyViewCallResult {
+            account: account_id.clone(),
+            public_key: *public_key,
+            nonce: account.nonce,
+            allowed,
+        })
+    }
+
+    pub fn view_code(
+        &self,
+        root: MerkleHash,
+        account_id: &AccountId,
+    ) -> Result<CodeViewCallResult, String> {
+        if !is_valid_account_id(account_id) {
+            return Err(format!("Account ID '{}' is not valid", account_id));
+        }
+        let mut state_update = StateDbUpdate::new(self.state_db.clone(), root);
+        let account =
+            get::<Account>(&mut state_update, &account_id_to_bytes(COL_ACCOUNT, account_id))
+                .ok_or_else(|| format!("account {} does not exist while viewing", account_id))?;
+        let code: Vec<u8> = get(&mut state_update, &account_id_to_bytes(COL_CODE, account_id))
+            .ok_or_else(|| format!("account {} does not have contract code", account_id))?;
+        Ok(CodeViewCallResult { account: account_id.clone(), code_hash: account.code_hash, code })
+    }
+
     pub fn get_public_keys_for_account(
         &self,
         root: MerkleHash,
@@ -88,9 +154,7 @@ impl StateDbViewer {
         }
         let mut values = HashMap::default();
         let state_update = StateDbUpdate::new(self.state_db.clone(), root);
-        let mut prefix = account_id_to_bytes(COL_ACCOUNT, account_id);
-        prefix.append(&mut b",".to_vec());
-        state_update.for_keys_with_prefix(&prefix, |key| {
+        state_update.for_keys_with_prefix(&storage_prefix(account_id), |key| {
             if let Some(value) = state_update.get(key) {
                 values.insert(key.to_vec(), value.to_vec());
             }
@@ -117,6 +181,7 @@ impl StateDbViewer {
         let wasm_res = match get::<Account>(&mut state_update, &account_id_to_bytes(COL_ACCOUNT, contract_id)) {
             Some(account) => {
                 let empty_hash = CryptoHash::default();
+                let config = RuntimeConfig::default();
                 let mut runtime_ext = RuntimeExt::new(
                     &mut state_update,
                     contract_id,
@@ -125,6 +190,7 @@ impl StateDbViewer {
                         contract_id: None,
                     },
                     &empty_hash,
+                    &config,
                 );
                 executor::execute(
                     &code,
@@ -151,7 +217,7 @@ impl StateDbViewer {
                 debug!(target: "runtime", "result of execution: {:?}", res);
                 match res.return_data {
                     Ok(return_data) => {
-                        let (_, root_after) = state_update.finalize();
+                        let (_, root_after) = state_update.finalize()?;
                         if root_after != root {
                             return Err("function call for viewing tried to change storage".to_string());
                         }
@@ -179,6 +245,7 @@ impl StateDbViewer {
 
 #[cfg(test)]
 mod tests {
+    use primitives::test_utils::get_key_pair_from_seed;
     use primitives::types::AccountId;
     use std::collections::HashMap;
     use crate::test_utils::*;
@@ -201,6 +268,27 @@ mod tests {
         assert_eq!(result.unwrap(), encode_int(10));
     }
 
+    #[test]
+    fn test_view_access_key() {
+        let (viewer, root) = get_test_state_db_viewer();
+        let public_key = get_key_pair_from_seed("alice.near").0;
+        let result = viewer.view_access_key(root, &alice_account(), &public_key).unwrap();
+        assert_eq!(result.nonce, 0);
+        assert!(result.allowed.is_empty());
+
+        let other_key = get_key_pair_from_seed("bob.near").0;
+        assert!(viewer.view_access_key(root, &alice_account(), &other_key).is_err());
+    }
+
+    #[test]
+    fn test_view_code() {
+        let (viewer, root) = get_test_state_db_viewer();
+        let result = viewer.view_code(root, &alice_account()).unwrap();
+        let (chain_spec, _) = generate_test_chain_spec();
+        assert_eq!(result.code, chain_spec.genesis_wasm);
+        assert!(viewer.view_code(root, &"unknown.near".to_string()).is_err());
+    }
+
     #[test]
     fn test_view_call_bad_contract_id() {
         let (viewer, root) = get_test_state_db_viewer();
@@ -3,12 +3,12 @@ use std::sync::Arc;
 use byteorder::{ByteOrder, LittleEndian};
 
 use primitives::aggregate_signature::BlsSecretKey;
-use primitives::types::{MerkleHash, GroupSignature};
-use primitives::signature::{get_key_pair, DEFAULT_SIGNATURE};
+use primitives::signature::{get_key_pair, sign, SecretKey, DEFAULT_SIGNATURE};
 use primitives::signer::InMemorySigner;
-use primitives::hash::CryptoHash;
+use primitives::hash::{hash, CryptoHash};
 use primitives::test_utils::get_key_pair_from_seed;
-use storage::StateDb;
+use primitives::types::{AccountId, Balance, GroupSignature, MerkleHash};
+use storage::{DBValue, StateDb, StateDbUpdate};
 use storage::test_utils::create_memory_db;
 use transaction::{
     SignedTransaction, ReceiptTransaction, TransactionBody, TransactionStatus,
@@ -17,9 +17,14 @@ use transaction::{
 use chain::{SignedShardBlockHeader, ShardBlockHeader, ReceiptBlock};
 
 use configs::ChainSpec;
+use crate::config::RuntimeConfig;
+use crate::ext::storage_prefix;
 use crate::state_viewer::StateDbViewer;
+use crate::tx_stakes::{get_tx_stake_key, TxTotalStake};
 
-use super::{ApplyResult, ApplyState, Runtime};
+use super::{
+    account_id_to_bytes, set, Account, ApplyResult, ApplyState, Runtime, COL_ACCOUNT, COL_CODE,
+};
 
 pub fn generate_test_chain_spec() -> (ChainSpec, InMemorySigner) {
     use rand::{SeedableRng, XorShiftRng};
@@ -29,7 +34,8 @@ pub fn generate_test_chain_spec() -> (ChainSpec, InMemorySigner) {
     let mut rng = XorShiftRng::from_seed([11111, 22222, 33333, 44444]);
     let secret_key = BlsSecretKey::generate_from_rng(&mut rng);
     let public_key = secret_key.get_public_key();
-    let authority = public_key.to_string();
+    let authority = get_key_pair_from_seed(account_id).0.to_string();
+    let bls_authority = public_key.to_string();
     let signer = InMemorySigner {
         account_id: account_id.to_string(),
         public_key,
@@ -41,11 +47,12 @@ pub fn generate_test_chain_spec() -> (ChainSpec, InMemorySigner) {
             ("bob.near".to_string(), get_key_pair_from_seed("bob.near").0.to_string(), 0, 10),
             ("system".to_string(), get_key_pair_from_seed("system").0.to_string(), 0, 0),
         ],
-        initial_authorities: vec![(account_id.to_string(), authority, 50)],
+        initial_authorities: vec![(account_id.to_string(), authority, bls_authority, 50)],
         genesis_wasm,
         beacon_chain_epoch_length: 2,
         beacon_chain_num_seats_per_slot: 10,
         boot_nodes: vec![],
+        transaction_validity_period: None,
     }, signer)
 }
 
@@ -75,6 +82,119 @@ pub fn get_test_state_db_viewer() -> (StateDbViewer, MerkleHash) {
     (state_db_viewer, root)
 }
 
+struct GenesisAccount {
+    account_id: AccountId,
+    amount: Balance,
+    staked: Balance,
+    tx_stake: Balance,
+    code: Vec<u8>,
+    data: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// Genesis state for the tests, with the accounts, their code and their contract data
+/// chosen one by one. Accounts sign with the keys derived from their ids, see
+/// `get_key_pair_from_seed`.
+#[derive(Default)]
+pub struct GenesisBuilder {
+    accounts: Vec<GenesisAccount>,
+    config: RuntimeConfig,
+}
+
+impl GenesisBuilder {
+    pub fn new() -> Self {
+        GenesisBuilder::default()
+    }
+
+    /// Adds the account with the balance, no code and the transaction stake of the accounts in
+    /// `generate_test_chain_spec`.
+    pub fn account(mut self, account_id: &str, amount: Balance) -> Self {
+        self.accounts.push(GenesisAccount {
+            account_id: account_id.to_string(),
+            amount,
+            staked: 0,
+            tx_stake: 10,
+            code: vec![],
+            data: vec![],
+        });
+        self
+    }
+
+    pub fn stake(mut self, account_id: &str, staked: Balance) -> Self {
+        self.get_account(account_id).staked = staked;
+        self
+    }
+
+    pub fn tx_stake(mut self, account_id: &str, tx_stake: Balance) -> Self {
+        self.get_account(account_id).tx_stake = tx_stake;
+        self
+    }
+
+    pub fn code(mut self, account_id: &str, code: &[u8]) -> Self {
+        self.get_account(account_id).code = code.to_vec();
+        self
+    }
+
+    /// Adds a record of the contract data of the account, as if the contract wrote it.
+    pub fn data(mut self, account_id: &str, key: &[u8], value: &[u8]) -> Self {
+        self.get_account(account_id).data.push((key.to_vec(), value.to_vec()));
+        self
+    }
+
+    /// Config of the runtime, instead of the default one.
+    pub fn config(mut self, config: RuntimeConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Commits the genesis state to a new in-memory state and returns the runtime, the viewer
+    /// and the root of the state.
+    pub fn build(self) -> (Runtime, StateDbViewer, MerkleHash) {
+        let state_db = Arc::new(StateDb::new(Arc::new(create_memory_db())));
+        let mut runtime = Runtime::new(state_db.clone());
+        runtime.config = self.config;
+        let mut state_update = StateDbUpdate::new(state_db.clone(), MerkleHash::default());
+        for genesis_account in self.accounts {
+            let account_id = &genesis_account.account_id;
+            let public_key = get_key_pair_from_seed(account_id).0;
+            let code_hash = hash(&genesis_account.code);
+            let mut account = Account::new(vec![public_key], genesis_account.amount, code_hash);
+            account.staked = genesis_account.staked;
+            set(&mut state_update, &account_id_to_bytes(COL_ACCOUNT, account_id), &account);
+            let code_key = account_id_to_bytes(COL_CODE, account_id);
+            set(&mut state_update, &code_key, &genesis_account.code);
+            let mut tx_total_stake = TxTotalStake::new(0);
+            tx_total_stake.add_active_stake(genesis_account.tx_stake);
+            set(&mut state_update, &get_tx_stake_key(account_id, &None), &tx_total_stake);
+            for (key, value) in genesis_account.data {
+                let mut storage_key = storage_prefix(account_id);
+                storage_key.extend_from_slice(&key);
+                state_update.set(&storage_key, &DBValue::from_slice(&value));
+            }
+        }
+        let (db_changes, root) = state_update.finalize().expect("Failed to build genesis state");
+        state_db.commit(db_changes).expect("Failed to commit genesis state");
+        (runtime, StateDbViewer::new(state_db), root)
+    }
+
+    fn get_account(&mut self, account_id: &str) -> &mut GenesisAccount {
+        self.accounts
+            .iter_mut()
+            .find(|account| account.account_id == account_id)
+            .expect("Account must be added before its stake, code or data")
+    }
+}
+
+pub fn sign_transaction(body: TransactionBody, secret_key: &SecretKey) -> SignedTransaction {
+    let hash = SignedTransaction::new(DEFAULT_SIGNATURE, body.clone()).get_hash();
+    SignedTransaction::new(sign(hash.as_ref(), secret_key), body)
+}
+
+/// Signs the transaction with the key the originator has in `generate_test_chain_spec`.
+pub fn signed_transaction(body: TransactionBody) -> SignedTransaction {
+    let (_, secret_key) = get_key_pair_from_seed(&body.get_originator());
+    sign_transaction(body, &secret_key)
+}
+
 pub fn encode_int(val: i32) -> [u8; 4] {
     let mut tmp = [0u8; 4];
     LittleEndian::write_i32(&mut tmp, val);
@@ -88,6 +208,10 @@ pub fn to_receipt_block(receipts: Vec<ReceiptTransaction>) -> ReceiptBlock {
             shard_id: 0,
             index: 0,
             merkle_root_state: CryptoHash::default(),
+            outcome_root: CryptoHash::default(),
+            transaction_root: CryptoHash::default(),
+            parts_root: CryptoHash::default(),
+            total_parts: 1,
         },
         hash: CryptoHash::default(),
         signature: GroupSignature::default(),
@@ -111,7 +235,7 @@ impl Runtime {
         let mut txs = transactions;
         let mut results = vec![];
         loop {
-            let mut apply_result = self.apply(&cur_apply_state, &receipts, &txs);
+            let mut apply_result = self.apply(&cur_apply_state, &receipts, &txs).unwrap();
             results.push(apply_result.clone());
             if apply_result.new_receipts.is_empty() {
                 return results;
@@ -151,7 +275,7 @@ impl User {
     }
 
     fn send_tx(&mut self, root: CryptoHash, tx_body: TransactionBody) -> MerkleHash {
-        let transaction = SignedTransaction::new(DEFAULT_SIGNATURE, tx_body);
+        let transaction = signed_transaction(tx_body);
         let apply_state = ApplyState {
             root,
             shard_id: 0,
@@ -175,6 +299,11 @@ impl User {
             originator: self.account_id.clone(),
             receiver: destination.to_string(),
             amount,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
         });
         self.nonce += 1;
         self.send_tx(root, tx_body)
@@ -186,8 +315,12 @@ impl User {
             nonce: self.nonce,
             originator: self.account_id.clone(),
             contract_id: contract_id.to_string(),
-            public_key: pk.0[..].to_vec(),
+            public_key: pk.to_bytes(),
             wasm_byte_array: wasm_binary.to_vec(),
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 0,
         });
         self.nonce += 1;
         self.send_tx(root, tx_body)
@@ -200,7 +333,11 @@ impl User {
                 contract_id: contract_id.to_string(),
                 method_name: method_name.as_bytes().to_vec(),
                 args: args.as_bytes().to_vec(),
-                amount: 0
+                amount: 0,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip: 0,
         });
         self.nonce += 1;
         self.send_tx(root, tx_body)
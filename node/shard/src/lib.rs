@@ -7,26 +7,161 @@ extern crate rand;
 extern crate serde_derive;
 extern crate storage;
 
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
+use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
 use parking_lot::RwLock;
 
-use chain::{SignedBlock, SignedHeader, SignedShardBlock, ReceiptBlock};
+use chain::{
+    Challenge, HeadUpdate, ReceiptBlock, SignedBlock, SignedHeader, SignedShardBlock,
+    SignedShardBlockHeader,
+};
 use configs::chain_spec::ChainSpec;
-use node_runtime::{ApplyState, Runtime};
+use near_protos::outcome as outcome_proto;
+use node_runtime::{ApplyState, FunctionCallSimulation, Runtime};
 use node_runtime::state_viewer::StateDbViewer;
-use primitives::hash::CryptoHash;
-use primitives::types::{AuthorityStake, BlockId, ShardId, BlockIndex};
-use storage::{extend_with_cache, read_with_cache, StateDb};
+use primitives::hash::{hash, hash_struct, CryptoHash};
+use primitives::merkle::{merklize, verify_path, MerklePath};
+use primitives::types::{
+    AccountId, AuthorityStake, Balance, BlockId, BlockIndex, MerkleHash, ShardId,
+};
+use storage::{
+    extend_with_cache, read_with_cache, remove_with_cache, write_with_cache, KeyChange, StateDb,
+};
 use transaction::{
-    FinalTransactionResult, FinalTransactionStatus, SignedTransaction,
-    TransactionLogs, TransactionResult, TransactionStatus,
-    ReceiptTransaction
+    FinalTransactionResult, FinalTransactionStatus, FunctionCallTransaction, InvalidTxError,
+    RuntimeError, SignedTransaction, TransactionFailure, TransactionLogs, TransactionResult,
+    TransactionStatus, ReceiptTransaction
 };
 
+use crate::pool::{PoolError, TransactionPool, TransactionPoolConfig};
+
+pub mod pool;
+
 type H264 = [u8; 33];
 
+/// Orders transactions by decreasing tip. Transactions of the same originator keep their
+/// order, so that their nonces still increase.
+fn order_by_tip(transactions: Vec<SignedTransaction>) -> Vec<SignedTransaction> {
+    let mut queues: Vec<VecDeque<SignedTransaction>> = vec![];
+    let mut queue_by_originator: HashMap<AccountId, usize> = HashMap::new();
+    for transaction in transactions {
+        let originator = transaction.body.get_originator();
+        let index = *queue_by_originator.entry(originator).or_insert_with(|| {
+            queues.push(VecDeque::new());
+            queues.len() - 1
+        });
+        queues[index].push_back(transaction);
+    }
+    // Next transaction of every originator, by its tip, on ties the originator seen first.
+    let mut heap: BinaryHeap<(Balance, Reverse<usize>)> = queues
+        .iter()
+        .enumerate()
+        .map(|(i, queue)| (queue[0].body.get_tip(), Reverse(i)))
+        .collect();
+    let mut result = vec![];
+    while let Some((_, Reverse(index))) = heap.pop() {
+        result.extend(queues[index].pop_front());
+        if let Some(next) = queues[index].front() {
+            heap.push((next.body.get_tip(), Reverse(index)));
+        }
+    }
+    result
+}
+
+/// Pairs the results with the receipts and the transactions of a block, in the order in
+/// which they were applied.
+pub fn outcomes(
+    receipts: &[ReceiptBlock],
+    transactions: &[SignedTransaction],
+    results: &[TransactionResult],
+) -> Vec<Outcome> {
+    receipts
+        .iter()
+        .flat_map(|b| b.receipts.iter().map(|r| (r.nonce, r.receiver.clone())))
+        .chain(transactions.iter().map(|t| (t.get_hash(), t.body.get_originator())))
+        .zip(results.iter())
+        .map(|((id, account_id), result)| Outcome { id, account_id, result: result.clone() })
+        .collect()
+}
+
+/// Reason why a transaction is not admitted into the transaction pool.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum AdmissionError {
+    /// Transaction can't be applied on top of the best block.
+    Invalid(InvalidTxError),
+    /// Pool does not accept the transaction.
+    Pool(PoolError),
+}
+
+impl fmt::Display for AdmissionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AdmissionError::Invalid(e) => write!(f, "Invalid transaction: {}", e),
+            AdmissionError::Pool(e) => write!(f, "Transaction rejected by the pool: {}", e),
+        }
+    }
+}
+
+/// Reason why a challenge doesn't prove that the shard block is invalid.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ChallengeError {
+    /// Hash of the block or of the parent header doesn't match the header.
+    InvalidHash(CryptoHash),
+    /// Parent of the block is not a known block.
+    UnknownParent(CryptoHash),
+    /// Block is not the child of the parent header.
+    NotChild(CryptoHash),
+    /// Block is not signed by the authorities of its index, so they cannot be blamed for it.
+    InvalidSignature(CryptoHash),
+    /// Witness misses trie nodes needed to apply the block.
+    IncompleteWitness,
+    /// Applying the block gives the state root it declares.
+    ValidBlock(CryptoHash),
+}
+
+impl fmt::Display for ChallengeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChallengeError::InvalidHash(hash) => {
+                write!(f, "Hash {:?} doesn't match the header", hash)
+            }
+            ChallengeError::UnknownParent(hash) => write!(f, "Parent {:?} is not known", hash),
+            ChallengeError::NotChild(hash) => {
+                write!(f, "Block {:?} is not the child of the parent header", hash)
+            }
+            ChallengeError::InvalidSignature(hash) => {
+                write!(f, "Block {:?} is not signed by its authorities", hash)
+            }
+            ChallengeError::IncompleteWitness => {
+                write!(f, "Witness misses nodes needed to apply the block")
+            }
+            ChallengeError::ValidBlock(hash) => {
+                write!(f, "Block {:?} has the state root it declares", hash)
+            }
+        }
+    }
+}
+
+/// Validates the transaction for the pool. A nonce too far ahead is accepted, since the
+/// transactions filling the gap may still arrive.
+fn validate_for_pool(
+    runtime: &Runtime,
+    apply_state: &ApplyState,
+    transaction: &SignedTransaction,
+) -> Result<(), InvalidTxError> {
+    match runtime.validate_tx(apply_state, transaction) {
+        Err(InvalidTxError::InvalidNonce { tx_nonce, account_nonce })
+            if tx_nonce > account_nonce => Ok(()),
+        result => result,
+    }
+}
+
 /// Represents index of extra data in database
 #[derive(Copy, Debug, Hash, Eq, PartialEq, Clone)]
 pub enum ExtrasIndex {
@@ -34,6 +169,16 @@ pub enum ExtrasIndex {
     TransactionAddress = 0,
     /// Transaction result index
     TransactionResult = 1,
+    /// Index of the transactions and receipts that involve an account
+    AccountTransactions = 2,
+    /// Keys of the state changed by a block
+    StateChanges = 3,
+    /// Results and new receipts of a block, kept to index the block again after a reorg
+    BlockResults = 4,
+    /// State nodes replaced by a block, removed once the block is final
+    ReplacedNodes = 5,
+    /// Blocks that replaced a state node which no block wrote again since
+    NodeReplacedBy = 6,
 }
 
 fn with_index(hash: &CryptoHash, i: ExtrasIndex) -> H264 {
@@ -43,6 +188,32 @@ fn with_index(hash: &CryptoHash, i: ExtrasIndex) -> H264 {
     result
 }
 
+fn account_index_key(account_id: &AccountId) -> H264 {
+    with_index(&hash(account_id.as_bytes()), ExtrasIndex::AccountTransactions)
+}
+
+/// Pairs the ids of the receipts and the transactions of the block with the accounts they
+/// involve: receivers of the receipts, originators and contracts of the transactions.
+fn involved_accounts(block: &SignedShardBlock) -> Vec<(AccountId, CryptoHash)> {
+    let mut result: Vec<(AccountId, CryptoHash)> = block
+        .body
+        .receipts
+        .iter()
+        .flat_map(|b| b.receipts.iter().map(|r| (r.receiver.clone(), r.nonce)))
+        .collect();
+    for transaction in &block.body.transactions {
+        let originator = transaction.body.get_originator();
+        match transaction.body.get_contract_id() {
+            Some(ref contract_id) if *contract_id != originator => {
+                result.push((contract_id.clone(), transaction.get_hash()))
+            }
+            _ => {}
+        }
+        result.push((originator, transaction.get_hash()));
+    }
+    result
+}
+
 /// Represents address of certain transaction within block
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct TransactionAddress {
@@ -52,6 +223,75 @@ pub struct TransactionAddress {
     pub index: usize
 }
 
+/// Result of a transaction or a receipt included into a block.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Outcome {
+    /// Hash of the transaction or nonce of the receipt.
+    pub id: CryptoHash,
+    /// Originator of the transaction or receiver of the receipt, i.e. the account that
+    /// executed it.
+    pub account_id: AccountId,
+    pub result: TransactionResult,
+}
+
+impl Into<outcome_proto::ExecutionOutcome> for Outcome {
+    fn into(self) -> outcome_proto::ExecutionOutcome {
+        let result: outcome_proto::TransactionResult = self.result.into();
+        outcome_proto::ExecutionOutcome {
+            id: self.id.as_ref().to_vec(),
+            account_id: self.account_id,
+            result: Some(result).into(),
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
+/// Outcome with the path that proves it against the outcome root of the block it is in. The
+/// hash of the header is the shard block hash the beacon block commits to.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OutcomeProof {
+    pub outcome: Outcome,
+    pub proof: MerklePath,
+    pub header: SignedShardBlockHeader,
+}
+
+impl OutcomeProof {
+    pub fn verify(&self) -> bool {
+        hash_struct(&self.header.body) == self.header.hash
+            && verify_path(self.header.body.outcome_root, &self.proof, &self.outcome)
+    }
+}
+
+/// Transaction with the path that proves it against the transaction root of the block it is in.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TransactionProof {
+    pub transaction: SignedTransaction,
+    pub proof: MerklePath,
+    pub header: SignedShardBlockHeader,
+}
+
+impl TransactionProof {
+    pub fn verify(&self) -> bool {
+        hash_struct(&self.header.body) == self.header.hash
+            && verify_path(self.header.body.transaction_root, &self.proof, &self.transaction)
+    }
+}
+
+/// Notification about a block added to the chain.
+#[derive(Clone, Debug)]
+pub struct BlockEvent {
+    pub block: SignedShardBlock,
+    pub outcomes: Vec<Outcome>,
+}
+
+/// Sends the items to every subscriber and forgets the subscribers that are gone.
+fn notify<T: Clone>(subscribers: &RwLock<Vec<UnboundedSender<T>>>, items: &[T]) {
+    subscribers.write().retain(|subscriber| {
+        items.iter().all(|item| subscriber.unbounded_send(item.clone()).is_ok())
+    });
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct SignedTransactionInfo {
     pub transaction: SignedTransaction,
@@ -59,6 +299,15 @@ pub struct SignedTransactionInfo {
     pub result: TransactionResult,
 }
 
+pub struct ReceiptInfo {
+    pub receipt: ReceiptTransaction,
+    pub block_index: u64,
+    pub result: TransactionResult,
+}
+
+/// Results of the receipts and the transactions of a block and the receipts it produced.
+type BlockResults = (Vec<TransactionResult>, HashMap<ShardId, Vec<ReceiptTransaction>>);
+
 type ShardBlockExtraInfo = (
     storage::DBChanges,
     Vec<AuthorityStake>,
@@ -71,14 +320,36 @@ pub struct ShardBlockChain {
     storage: Arc<storage::Storage>,
     transaction_addresses: RwLock<HashMap<Vec<u8>, TransactionAddress>>,
     transaction_results: RwLock<HashMap<Vec<u8>, TransactionResult>>,
+    account_transactions: RwLock<HashMap<Vec<u8>, Vec<CryptoHash>>>,
+    state_changes: RwLock<HashMap<Vec<u8>, Vec<KeyChange>>>,
+    block_results: RwLock<HashMap<Vec<u8>, BlockResults>>,
+    replaced_nodes: RwLock<HashMap<Vec<u8>, Vec<CryptoHash>>>,
+    node_replaced_by: RwLock<HashMap<Vec<u8>, Vec<CryptoHash>>>,
     pub receipts: RwLock<HashMap<BlockIndex, HashMap<ShardId, Vec<ReceiptTransaction>>>>,
     pub state_db: Arc<StateDb>,
     pub runtime: RwLock<Runtime>,
     pub statedb_viewer: StateDbViewer,
+    pub pool: RwLock<TransactionPool>,
+    /// Receivers of the pending transactions that were dropped because they expired.
+    expired_subscribers: RwLock<Vec<UnboundedSender<SignedTransaction>>>,
+    /// Receivers of the blocks added to the chain.
+    block_subscribers: RwLock<Vec<UnboundedSender<BlockEvent>>>,
+    /// Receivers of the receipts a reorg took out of the chain.
+    reverted_receipt_subscribers: RwLock<Vec<UnboundedSender<ReceiptBlock>>>,
+    /// Mana accounting refunds dropped while applying the blocks since the start.
+    dropped_mana_refunds: AtomicUsize,
 }
 
 impl ShardBlockChain {
     pub fn new(chain_spec: &ChainSpec, storage: Arc<storage::Storage>) -> Self {
+        Self::new_with_pool_config(chain_spec, storage, TransactionPoolConfig::default())
+    }
+
+    pub fn new_with_pool_config(
+        chain_spec: &ChainSpec,
+        storage: Arc<storage::Storage>,
+        pool_config: TransactionPoolConfig,
+    ) -> Self {
         let state_db = Arc::new(StateDb::new(storage.clone()));
         let runtime = RwLock::new(Runtime::new(state_db.clone()));
         let genesis_root = runtime.write().apply_genesis_state(
@@ -87,38 +358,427 @@ impl ShardBlockChain {
             &chain_spec.initial_authorities,
         );
         let genesis = SignedShardBlock::genesis(genesis_root);
+        runtime.write().config.genesis_hash = Some(genesis.hash);
+        runtime.write().config.transaction_validity_period = chain_spec.transaction_validity_period;
 
         let chain = chain::BlockChain::<SignedShardBlock>::new(genesis, storage.clone());
         let statedb_viewer = StateDbViewer::new(state_db.clone());
-        Self {
+        let pool = RwLock::new(TransactionPool::new(
+            runtime.read().config.nonce_policy,
+            pool_config,
+            storage.clone(),
+        ));
+        let shard_chain = Self {
             chain,
             storage,
             transaction_addresses: RwLock::new(HashMap::new()),
             transaction_results: RwLock::new(HashMap::new()),
+            account_transactions: RwLock::new(HashMap::new()),
+            state_changes: RwLock::new(HashMap::new()),
+            block_results: RwLock::new(HashMap::new()),
+            replaced_nodes: RwLock::new(HashMap::new()),
+            node_replaced_by: RwLock::new(HashMap::new()),
             receipts: RwLock::new(HashMap::new()),
             state_db,
             runtime,
-            statedb_viewer
+            statedb_viewer,
+            pool,
+            expired_subscribers: RwLock::new(vec![]),
+            block_subscribers: RwLock::new(vec![]),
+            reverted_receipt_subscribers: RwLock::new(vec![]),
+            dropped_mana_refunds: AtomicUsize::new(0),
+        };
+        shard_chain.load_pool();
+        shard_chain
+    }
+
+    /// State to validate transactions against, the one of the block after the best block.
+    fn next_apply_state(&self) -> ApplyState {
+        let best_block = self.chain.best_block();
+        ApplyState {
+            root: best_block.body.header.merkle_root_state,
+            parent_block_hash: best_block.hash,
+            block_index: best_block.body.header.index + 1,
+            shard_id: best_block.body.header.shard_id,
         }
     }
 
+    /// Loads the persisted pending transactions that are still valid on top of the best block.
+    fn load_pool(&self) {
+        let apply_state = self.next_apply_state();
+        let runtime = self.runtime.read();
+        self.pool.write().load(|transaction| {
+            validate_for_pool(&runtime, &apply_state, transaction).is_ok()
+        });
+    }
+
+    /// Checks whether the transaction would be admitted into the pool.
+    pub fn check_transaction(&self, transaction: &SignedTransaction) -> Result<(), AdmissionError> {
+        validate_for_pool(&self.runtime.read(), &self.next_apply_state(), transaction)
+            .map_err(AdmissionError::Invalid)?;
+        self.pool.read().check(transaction).map_err(AdmissionError::Pool)
+    }
+
+    /// Dry-runs the function call on top of the best block, see
+    /// `Runtime::simulate_function_call`.
+    pub fn simulate_function_call(
+        &self,
+        transaction: &FunctionCallTransaction,
+    ) -> Result<FunctionCallSimulation, RuntimeError> {
+        self.runtime.write().simulate_function_call(&self.next_apply_state(), transaction)
+    }
+
     #[inline]
     pub fn genesis_hash(&self) -> CryptoHash {
         self.chain.genesis_hash
     }
 
+    /// Inserts the applied block. If the block moves the best block to another fork, the
+    /// indices follow the new canonical chain. If its state can't be written, the block is not
+    /// inserted and nothing else is recorded for it.
     pub fn insert_block(
         &self,
         block: &SignedShardBlock,
         db_transaction: storage::DBChanges,
         tx_result: Vec<TransactionResult>,
         new_receipts: HashMap<ShardId, Vec<ReceiptTransaction>>
+    ) -> std::io::Result<()> {
+        // The nodes the block replaced are kept until it is final, since the states of the
+        // other forks and of the blocks a reorg goes back to still reference them.
+        let new_nodes: storage::DBChanges = db_transaction
+            .iter()
+            .filter(|(_, value)| value.is_some())
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        self.state_db.commit(new_nodes)?;
+        self.record_state_changes(block, &db_transaction);
+        self.record_replaced_nodes(block, &db_transaction);
+        write_with_cache(
+            &self.storage,
+            storage::COL_EXTRA,
+            &self.block_results,
+            &with_index(&block.hash, ExtrasIndex::BlockResults),
+            &(tx_result.clone(), new_receipts.clone()),
+        );
+        match self.chain.insert_block_with_update(block.clone()) {
+            HeadUpdate::Unchanged => {}
+            HeadUpdate::Extended => self.on_canonical_block(block, tx_result, new_receipts),
+            HeadUpdate::Reorg { reverted, applied } => self.on_reorg(&reverted, &applied),
+        }
+        self.remove_expired_transactions();
+        Ok(())
+    }
+
+    /// Records the state nodes the block replaced, to remove them once the block is final.
+    fn record_replaced_nodes(&self, block: &SignedShardBlock, db_changes: &storage::DBChanges) {
+        let mut replaced = vec![];
+        let mut written = vec![];
+        let mut replaced_by = HashMap::new();
+        for (key, value) in db_changes.iter() {
+            let node = CryptoHash::new(key);
+            let node_key = with_index(&node, ExtrasIndex::NodeReplacedBy).to_vec();
+            if value.is_some() {
+                written.push(node_key);
+                continue;
+            }
+            let mut blocks: Vec<CryptoHash> = read_with_cache(
+                &self.storage,
+                storage::COL_EXTRA,
+                &self.node_replaced_by,
+                &node_key,
+            )
+            .unwrap_or_default();
+            blocks.push(block.hash);
+            replaced_by.insert(node_key, blocks);
+            replaced.push(node);
+        }
+        // A node written again is referenced by the new state, whichever blocks replaced it.
+        remove_with_cache(&self.storage, storage::COL_EXTRA, &self.node_replaced_by, &written);
+        extend_with_cache(&self.storage, storage::COL_EXTRA, &self.node_replaced_by, replaced_by);
+        write_with_cache(
+            &self.storage,
+            storage::COL_EXTRA,
+            &self.replaced_nodes,
+            &with_index(&block.hash, ExtrasIndex::ReplacedNodes),
+            &replaced,
+        );
+    }
+
+    /// Marks the canonical block as final. The chain never reverts the blocks up to it, so the
+    /// state nodes they replaced and the results kept for the reorgs are removed.
+    pub fn finalize(&self, hash: &CryptoHash) -> bool {
+        let from_index = self.chain.finalized_index() + 1;
+        if !self.chain.finalize(hash) {
+            return false;
+        }
+        for index in from_index..=self.chain.finalized_index() {
+            if let Some(header) = self.chain.get_header(&BlockId::Number(index)) {
+                self.prune_block(&header.hash);
+            }
+        }
+        true
+    }
+
+    fn prune_block(&self, hash: &CryptoHash) {
+        let key = with_index(hash, ExtrasIndex::ReplacedNodes);
+        let replaced: Vec<CryptoHash> =
+            read_with_cache(&self.storage, storage::COL_EXTRA, &self.replaced_nodes, &key)
+                .unwrap_or_default();
+        let mut removed_nodes = storage::DBChanges::default();
+        let mut removed_keys = vec![];
+        for node in replaced {
+            let node_key = with_index(&node, ExtrasIndex::NodeReplacedBy).to_vec();
+            let blocks: Option<Vec<CryptoHash>> = read_with_cache(
+                &self.storage,
+                storage::COL_EXTRA,
+                &self.node_replaced_by,
+                &node_key,
+            );
+            // Only the forks that the finality ruled out may still reference the node.
+            if blocks.map_or(false, |blocks| blocks.contains(hash)) {
+                removed_nodes.insert(node.as_ref().to_vec(), None);
+                removed_keys.push(node_key);
+            }
+        }
+        if let Err(e) = self.state_db.commit(removed_nodes) {
+            // Keeps the records of the replaced nodes, the nodes are still in the state.
+            error!("Failed to prune the state of block {}: {}", hash, e);
+            return;
+        }
+        remove_with_cache(&self.storage, storage::COL_EXTRA, &self.node_replaced_by, &removed_keys);
+        remove_with_cache(&self.storage, storage::COL_EXTRA, &self.replaced_nodes, &[key.to_vec()]);
+        let results_key = with_index(hash, ExtrasIndex::BlockResults).to_vec();
+        remove_with_cache(&self.storage, storage::COL_EXTRA, &self.block_results, &[results_key]);
+    }
+
+    /// Indexes the block that became canonical and drops its transactions from the pool.
+    fn on_canonical_block(
+        &self,
+        block: &SignedShardBlock,
+        tx_result: Vec<TransactionResult>,
+        new_receipts: HashMap<ShardId, Vec<ReceiptTransaction>>,
     ) {
-        self.state_db.commit(db_transaction).ok();
-        self.chain.insert_block(block.clone());
-        self.update_for_inserted_block(&block.clone(), tx_result);
-        let index = block.index();
-        self.receipts.write().insert(index, new_receipts);
+        if !self.block_subscribers.read().is_empty() {
+            let outcomes = outcomes(&block.body.receipts, &block.body.transactions, &tx_result);
+            let event = BlockEvent { block: block.clone(), outcomes };
+            notify(&self.block_subscribers, &[event]);
+        }
+        self.update_for_inserted_block(block, tx_result);
+        self.receipts.write().insert(block.index(), new_receipts);
+        let root = block.body.header.merkle_root_state;
+        self.pool.write().on_block_applied(&block.body.transactions, |account_id| {
+            self.statedb_viewer.view_account(root, account_id).ok().map(|a| a.nonce)
+        });
+    }
+
+    fn get_block_results(&self, block: &SignedShardBlock) -> BlockResults {
+        let results = read_with_cache(
+            &self.storage,
+            storage::COL_EXTRA,
+            &self.block_results,
+            &with_index(&block.hash, ExtrasIndex::BlockResults),
+        );
+        results.unwrap_or_else(|| {
+            let num_receipts: usize = block.body.receipts.iter().map(|b| b.receipts.len()).sum();
+            let num_results = num_receipts + block.body.transactions.len();
+            (vec![TransactionResult::default(); num_results], HashMap::new())
+        })
+    }
+
+    /// Moves the indices from the reverted blocks to the applied ones. The states of the
+    /// blocks are kept by their roots, so the next blocks are applied on top of the new best
+    /// block. The transactions of the reverted blocks that didn't make it into the new chain
+    /// go back to the pool and their receipts are re-emitted to be included again.
+    fn on_reorg(&self, reverted: &[SignedShardBlock], applied: &[SignedShardBlock]) {
+        info!(
+            "Reorg to shard block {}, reverting {} blocks and applying {}",
+            applied[applied.len() - 1].hash,
+            reverted.len(),
+            applied.len()
+        );
+        for block in reverted {
+            self.remove_for_reverted_block(block);
+        }
+        for block in applied {
+            let (tx_result, new_receipts) = self.get_block_results(block);
+            self.on_canonical_block(block, tx_result, new_receipts);
+        }
+
+        let included: HashSet<CryptoHash> = applied
+            .iter()
+            .flat_map(|block| block.body.transactions.iter().map(|t| t.get_hash()))
+            .collect();
+        let transactions: Vec<SignedTransaction> = reverted
+            .iter()
+            .rev()
+            .flat_map(|block| block.body.transactions.iter())
+            .filter(|t| !included.contains(&t.get_hash()))
+            .cloned()
+            .collect();
+        self.add_transactions(transactions);
+        let receipts: Vec<ReceiptBlock> = reverted
+            .iter()
+            .rev()
+            .flat_map(|block| block.body.receipts.iter())
+            .filter(|r| !applied.iter().any(|block| block.body.receipts.contains(r)))
+            .cloned()
+            .collect();
+        if !receipts.is_empty() {
+            notify(&self.reverted_receipt_subscribers, &receipts);
+        }
+    }
+
+    /// Removes the indices of the block that is no longer canonical.
+    fn remove_for_reverted_block(&self, block: &SignedShardBlock) {
+        let ids: Vec<CryptoHash> = block
+            .body
+            .receipts
+            .iter()
+            .flat_map(|b| b.receipts.iter().map(|r| r.nonce))
+            .chain(block.body.transactions.iter().map(|t| t.get_hash()))
+            .collect();
+        let keys = |index: ExtrasIndex| -> Vec<Vec<u8>> {
+            ids.iter().map(|id| with_index(id, index).to_vec()).collect()
+        };
+        remove_with_cache(
+            &self.storage,
+            storage::COL_EXTRA,
+            &self.transaction_addresses,
+            &keys(ExtrasIndex::TransactionAddress),
+        );
+        remove_with_cache(
+            &self.storage,
+            storage::COL_EXTRA,
+            &self.transaction_results,
+            &keys(ExtrasIndex::TransactionResult),
+        );
+
+        let mut updates: HashMap<Vec<u8>, Vec<CryptoHash>> = HashMap::new();
+        for (account_id, id) in involved_accounts(block) {
+            updates
+                .entry(account_index_key(&account_id).to_vec())
+                .or_insert_with(|| self.get_account_transactions(&account_id))
+                .retain(|other| *other != id);
+        }
+        extend_with_cache(
+            &self.storage,
+            storage::COL_EXTRA,
+            &self.account_transactions,
+            updates,
+        );
+        self.receipts.write().remove(&block.index());
+    }
+
+    /// Returns a stream of the receipts that were included into the blocks a reorg reverted
+    /// and not into the new canonical blocks.
+    pub fn subscribe_to_reverted_receipts(&self) -> UnboundedReceiver<ReceiptBlock> {
+        let (tx, rx) = unbounded();
+        self.reverted_receipt_subscribers.write().push(tx);
+        rx
+    }
+
+    /// Persists the keys of the state changed by the block.
+    fn record_state_changes(&self, block: &SignedShardBlock, db_changes: &storage::DBChanges) {
+        let parent = match self.chain.get_block(&BlockId::Hash(block.body.header.parent_hash)) {
+            Some(parent) => parent,
+            None => return,
+        };
+        let diff =
+            self.state_db.diff(&parent.merkle_root_state(), &block.merkle_root_state(), db_changes);
+        match diff {
+            Ok(changes) => write_with_cache(
+                &self.storage,
+                storage::COL_EXTRA,
+                &self.state_changes,
+                &with_index(&block.hash, ExtrasIndex::StateChanges),
+                &changes,
+            ),
+            Err(e) => warn!("Failed to find the state changes of block {}: {}", block.hash, e),
+        }
+    }
+
+    /// Returns the keys of the state changed by the block with their old and new values,
+    /// `None` if they were not recorded.
+    pub fn get_state_changes(&self, block_hash: &CryptoHash) -> Option<Vec<KeyChange>> {
+        read_with_cache(
+            &self.storage.clone(),
+            storage::COL_EXTRA,
+            &self.state_changes,
+            &with_index(block_hash, ExtrasIndex::StateChanges),
+        )
+    }
+
+    /// Returns a stream of the pending transactions that get dropped from the pool because
+    /// their anchor block fell out of the validity period, so that they can be re-signed.
+    pub fn subscribe_to_expired_transactions(&self) -> UnboundedReceiver<SignedTransaction> {
+        let (tx, rx) = unbounded();
+        self.expired_subscribers.write().push(tx);
+        rx
+    }
+
+    fn remove_expired_transactions(&self) {
+        let apply_state = self.next_apply_state();
+        let expired = {
+            let runtime = self.runtime.read();
+            self.pool.write().remove_expired(|transaction| {
+                match runtime.check_expiry(&apply_state, transaction) {
+                    Err(InvalidTxError::Expired(_)) => true,
+                    _ => false,
+                }
+            })
+        };
+        if expired.is_empty() {
+            return;
+        }
+        debug!("Dropping {} expired transactions", expired.len());
+        notify(&self.expired_subscribers, &expired);
+    }
+
+    /// Returns a stream of the blocks added to the chain, with the outcomes of their
+    /// transactions and receipts.
+    pub fn subscribe_to_blocks(&self) -> UnboundedReceiver<BlockEvent> {
+        let (tx, rx) = unbounded();
+        self.block_subscribers.write().push(tx);
+        rx
+    }
+
+    /// Adds the transactions to the pool. Invalid transactions and the ones the pool rejects
+    /// are dropped.
+    pub fn add_transactions(&self, transactions: Vec<SignedTransaction>) {
+        let apply_state = self.next_apply_state();
+        let runtime = self.runtime.read();
+        let mut pool = self.pool.write();
+        for transaction in transactions {
+            let result = validate_for_pool(&runtime, &apply_state, &transaction)
+                .map_err(AdmissionError::Invalid)
+                .and_then(|_| pool.insert(transaction).map_err(AdmissionError::Pool));
+            if let Err(e) = result {
+                debug!("Dropping transaction: {}", e);
+            }
+        }
+    }
+
+    /// Pending transactions that are due for a rebroadcast and are still valid on top of the
+    /// best block.
+    pub fn transactions_to_rebroadcast(&self) -> Vec<SignedTransaction> {
+        let transactions = self.pool.write().take_rebroadcast(Instant::now());
+        let apply_state = self.next_apply_state();
+        let runtime = self.runtime.read();
+        transactions
+            .into_iter()
+            .filter(|transaction| validate_for_pool(&runtime, &apply_state, transaction).is_ok())
+            .collect()
+    }
+
+    /// Pending transactions that can be included into the block following the given one.
+    pub fn prepare_transactions(&self, last_block_hash: CryptoHash) -> Vec<SignedTransaction> {
+        let root = match self.chain.get_block(&BlockId::Hash(last_block_hash)) {
+            Some(block) => block.body.header.merkle_root_state,
+            None => return vec![],
+        };
+        self.pool.read().prepare_transactions(|account_id| {
+            self.statedb_viewer.view_account(root, account_id).ok().map(|a| a.nonce)
+        })
     }
 
     pub fn prepare_new_block(
@@ -126,29 +786,46 @@ impl ShardBlockChain {
         last_block_hash: CryptoHash,
         prev_receipts: Vec<ReceiptBlock>,
         transactions: Vec<SignedTransaction>
+    ) -> (SignedShardBlock, ShardBlockExtraInfo) {
+        self.prepare_new_block_with_parts(last_block_hash, prev_receipts, transactions, 1)
+    }
+
+    /// Prepares the block, erasure-coded into `total_parts` parts for the authorities.
+    pub fn prepare_new_block_with_parts(
+        &self,
+        last_block_hash: CryptoHash,
+        prev_receipts: Vec<ReceiptBlock>,
+        transactions: Vec<SignedTransaction>,
+        total_parts: u64,
     ) -> (SignedShardBlock, ShardBlockExtraInfo) {
         let last_block = self
             .chain
             .get_block(&BlockId::Hash(last_block_hash))
             .expect("At the moment we should have given shard block present");
+        let transactions = order_by_tip(transactions);
         let apply_state = ApplyState {
             root: last_block.body.header.merkle_root_state,
             parent_block_hash: last_block_hash,
             block_index: last_block.body.header.index + 1,
             shard_id: last_block.body.header.shard_id,
         };
-        let apply_result = self.runtime.write().apply(
-            &apply_state,
-            &prev_receipts,
-            &transactions,
-        );
-        let shard_block = SignedShardBlock::new(
+        let apply_result = self
+            .runtime
+            .write()
+            .apply(&apply_state, &prev_receipts, &transactions)
+            .expect("The state of the last block should be present");
+        self.dropped_mana_refunds.fetch_add(apply_result.dropped_mana_refunds, Ordering::Relaxed);
+        let (outcome_root, _) =
+            merklize(&outcomes(&prev_receipts, &transactions, &apply_result.tx_result));
+        let shard_block = SignedShardBlock::new_with_parts(
             last_block.body.header.shard_id,
             last_block.body.header.index + 1,
             last_block.block_hash(),
             apply_result.root,
+            outcome_root,
             transactions,
             prev_receipts,
+            total_parts,
         );
         let shard_block_extra = (
             apply_result.db_changes,
@@ -174,9 +851,16 @@ impl ShardBlockChain {
         };
         let apply_result = self.runtime.write().apply(
             &apply_state,
-            &[],
+            &block.body.receipts,
             &block.body.transactions,
         );
+        let apply_result = match apply_result {
+            Ok(apply_result) => apply_result,
+            Err(e) => {
+                warn!("Failed to read the state to apply block {}: {}", block.hash, e);
+                return false;
+            }
+        };
         if apply_result.root != block.body.header.merkle_root_state {
             info!(
                 "Merkle root {} is not equal to received {} after applying the transactions from {:?}",
@@ -184,16 +868,127 @@ impl ShardBlockChain {
                 apply_result.root,
                 block
             );
+            return false;
+        }
+        let block_outcomes =
+            outcomes(&block.body.receipts, &block.body.transactions, &apply_result.tx_result);
+        let (outcome_root, _) = merklize(&block_outcomes);
+        if outcome_root != block.body.header.outcome_root {
+            info!(
+                "Outcome root {} is not equal to received {} for block {}",
+                outcome_root,
+                block.body.header.outcome_root,
+                block.hash
+            );
             false
         } else {
-            self.insert_block(
+            let inserted = self.insert_block(
                 &block,
                 apply_result.db_changes,
                 apply_result.tx_result,
                 apply_result.new_receipts
             );
-            true
+            match inserted {
+                Ok(()) => {
+                    self.dropped_mana_refunds
+                        .fetch_add(apply_result.dropped_mana_refunds, Ordering::Relaxed);
+                    true
+                }
+                Err(e) => {
+                    warn!("Failed to write the state of block {}: {}", block.hash, e);
+                    false
+                }
+            }
+        }
+    }
+
+    /// Mana accounting refunds dropped because their TX stake was gone, in the blocks the node
+    /// produced or applied since the start.
+    pub fn dropped_mana_refunds(&self) -> usize {
+        self.dropped_mana_refunds.load(Ordering::Relaxed)
+    }
+
+    /// Root of the state after applying the block to the state of its parent, with the nodes of
+    /// the state read from the given state db. Fails if the state db misses the nodes.
+    fn apply_to_parent(
+        &self,
+        state_db: Arc<StateDb>,
+        parent: &SignedShardBlockHeader,
+        block: &SignedShardBlock,
+    ) -> Result<MerkleHash, String> {
+        let mut runtime = Runtime::new(state_db);
+        runtime.config = self.runtime.read().config.clone();
+        let apply_state = ApplyState {
+            root: parent.body.merkle_root_state,
+            block_index: parent.body.index + 1,
+            parent_block_hash: parent.hash,
+            shard_id: block.body.header.shard_id,
+        };
+        runtime
+            .apply(&apply_state, &block.body.receipts, &block.body.transactions)
+            .map(|apply_result| apply_result.root)
+    }
+
+    /// Applies the block again, recording the nodes of the state it reads. Returns the challenge
+    /// for the other nodes if the block doesn't have the resulting state root.
+    pub fn challenge_block(&self, block: &SignedShardBlock) -> Option<Challenge> {
+        let parent = self.chain.get_header(&BlockId::Hash(block.body.header.parent_hash))?;
+        let state_db = Arc::new(StateDb::new_recording(self.storage.clone()));
+        let root = match self.apply_to_parent(state_db.clone(), &parent, block) {
+            Ok(root) => root,
+            Err(e) => {
+                warn!("Failed to read the state to challenge block {}: {}", block.hash, e);
+                return None;
+            }
+        };
+        if root == block.body.header.merkle_root_state {
+            return None;
         }
+        Some(Challenge {
+            parent_header: parent,
+            block: block.clone(),
+            witness: state_db.recorded_nodes(),
+        })
+    }
+
+    /// Checks that the block of the challenge is signed by the seats of `authorities`, the
+    /// authorities of its index, and that applying it to the state of its parent, as given by
+    /// the witness, doesn't give the state root the block declares.
+    pub fn verify_challenge(
+        &self,
+        challenge: &Challenge,
+        authorities: &[AuthorityStake],
+    ) -> Result<(), ChallengeError> {
+        let (parent, block) = (&challenge.parent_header, &challenge.block);
+        if hash_struct(&parent.body) != parent.hash {
+            return Err(ChallengeError::InvalidHash(parent.hash));
+        }
+        if hash_struct(&block.body.header) != block.hash {
+            return Err(ChallengeError::InvalidHash(block.hash));
+        }
+        if !self.chain.is_known(&parent.hash) {
+            return Err(ChallengeError::UnknownParent(parent.hash));
+        }
+        if block.body.header.parent_hash != parent.hash || block.index() != parent.index() + 1 {
+            return Err(ChallengeError::NotChild(block.hash));
+        }
+        let signature = &block.signature;
+        if signature.authority_count() == 0 || !signature.verify(authorities, block.hash.as_ref()) {
+            return Err(ChallengeError::InvalidSignature(block.hash));
+        }
+        let state_db = StateDb::from_witness(&challenge.witness)
+            .map_err(|_| ChallengeError::IncompleteWitness)?;
+        let state_db = Arc::new(state_db);
+        let root = self
+            .apply_to_parent(state_db.clone(), parent, block)
+            .map_err(|_| ChallengeError::IncompleteWitness)?;
+        if state_db.has_missing_nodes() {
+            return Err(ChallengeError::IncompleteWitness);
+        }
+        if root == block.body.header.merkle_root_state {
+            return Err(ChallengeError::ValidBlock(block.hash));
+        }
+        Ok(())
     }
 
     pub fn get_transaction_result(&self, hash: &CryptoHash) -> TransactionResult {
@@ -223,21 +1018,99 @@ impl ShardBlockChain {
         &self,
         hash: &CryptoHash,
     ) -> Option<SignedTransactionInfo> {
-        self.get_transaction_address(&hash).map(|address| {
+        self.get_transaction_address(&hash).and_then(|address| {
             let block_id = BlockId::Hash(address.block_hash);
             let block = self.chain.get_block(&block_id)
                 .expect("transaction address points to non-existent block");
-            let transaction = block.body.transactions.get(address.index)
+            // Receipts are indexed before the transactions of the block.
+            let num_receipts: usize = block.body.receipts.iter().map(|b| b.receipts.len()).sum();
+            let transaction = block.body.transactions.get(address.index.checked_sub(num_receipts)?)
                 .expect("transaction address points to invalid index inside block");
             let result = self.get_transaction_result(&hash);
-            SignedTransactionInfo {
+            Some(SignedTransactionInfo {
                 transaction: transaction.clone(),
                 block_index: block.header().index(),
                 result,
-            }
+            })
+        })
+    }
+
+    /// Returns the stored outcomes of the receipts and the transactions of the block.
+    pub fn get_block_outcomes(&self, block: &SignedShardBlock) -> Vec<Outcome> {
+        let results: Vec<TransactionResult> = block
+            .body
+            .receipts
+            .iter()
+            .flat_map(|b| b.receipts.iter().map(|r| r.nonce))
+            .chain(block.body.transactions.iter().map(|t| t.get_hash()))
+            .map(|id| self.get_transaction_result(&id))
+            .collect();
+        outcomes(&block.body.receipts, &block.body.transactions, &results)
+    }
+
+    /// Returns the block that includes the transaction or the receipt.
+    pub fn get_outcome_block(&self, id: &CryptoHash) -> Option<SignedShardBlock> {
+        let address = self.get_transaction_address(id)?;
+        self.chain.get_block(&BlockId::Hash(address.block_hash))
+    }
+
+    /// Returns the outcome of the transaction or the receipt with the proof of its inclusion
+    /// into the block.
+    pub fn get_outcome_proof(&self, id: &CryptoHash) -> Option<OutcomeProof> {
+        let address = self.get_transaction_address(id)?;
+        let block = self.chain.get_block(&BlockId::Hash(address.block_hash))?;
+        let mut outcomes = self.get_block_outcomes(&block);
+        let (_, mut paths) = merklize(&outcomes);
+        if address.index >= outcomes.len() {
+            return None;
+        }
+        Some(OutcomeProof {
+            outcome: outcomes.swap_remove(address.index),
+            proof: paths.swap_remove(address.index),
+            header: block.header(),
+        })
+    }
+
+    /// Returns the transaction with the proof of its inclusion into the block.
+    pub fn get_transaction_proof(&self, hash: &CryptoHash) -> Option<TransactionProof> {
+        let address = self.get_transaction_address(hash)?;
+        let block = self.chain.get_block(&BlockId::Hash(address.block_hash))?;
+        let position = block.body.transactions.iter().position(|t| t.get_hash() == *hash)?;
+        let (_, mut paths) = merklize(&block.body.transactions);
+        Some(TransactionProof {
+            transaction: block.body.transactions[position].clone(),
+            proof: paths.swap_remove(position),
+            header: block.header(),
+        })
+    }
+
+    /// Returns the receipt with the given nonce, if it was included into a block.
+    pub fn get_receipt_info(&self, nonce: &CryptoHash) -> Option<ReceiptInfo> {
+        let address = self.get_transaction_address(nonce)?;
+        let block = self.chain.get_block(&BlockId::Hash(address.block_hash))?;
+        // Receipts are indexed before the transactions of the block.
+        let receipt = block.body.receipts.iter()
+            .flat_map(|b| b.receipts.iter())
+            .nth(address.index)
+            .filter(|r| r.nonce == *nonce)?;
+        Some(ReceiptInfo {
+            receipt: receipt.clone(),
+            block_index: block.index(),
+            result: self.get_transaction_result(nonce),
         })
     }
 
+    /// Returns the hashes of the transactions and the nonces of the receipts that involve the
+    /// account, in the order they were included into blocks.
+    pub fn get_account_transactions(&self, account_id: &AccountId) -> Vec<CryptoHash> {
+        read_with_cache(
+            &self.storage.clone(),
+            storage::COL_EXTRA,
+            &self.account_transactions,
+            &account_index_key(account_id),
+        ).unwrap_or_default()
+    }
+
     pub fn update_for_inserted_block(&self, block: &SignedShardBlock, tx_result: Vec<TransactionResult>) {
         let updates: HashMap<Vec<u8>, TransactionAddress> = block.body.receipts.iter()
             .flat_map(|b| b.receipts.iter()
@@ -277,17 +1150,41 @@ impl ShardBlockChain {
             &self.transaction_results,
             updates,
         );
+
+        let mut updates: HashMap<Vec<u8>, Vec<CryptoHash>> = HashMap::new();
+        for (account_id, id) in involved_accounts(block) {
+            updates
+                .entry(account_index_key(&account_id).to_vec())
+                .or_insert_with(|| self.get_account_transactions(&account_id))
+                .push(id);
+        }
+        extend_with_cache(
+            &self.storage.clone(),
+            storage::COL_EXTRA,
+            &self.account_transactions,
+            updates,
+        );
     }
 
-    fn collect_transaction_final_result(&self, transaction_result: &TransactionResult, logs: &mut Vec<TransactionLogs>) -> FinalTransactionStatus {
+    fn collect_transaction_final_result(
+        &self,
+        hash: &CryptoHash,
+        transaction_result: &TransactionResult,
+        logs: &mut Vec<TransactionLogs>,
+        failure: &mut Option<TransactionFailure>,
+    ) -> FinalTransactionStatus {
         match transaction_result.status {
             TransactionStatus::Unknown => FinalTransactionStatus::Unknown,
-            TransactionStatus::Failed => FinalTransactionStatus::Failed,
+            TransactionStatus::Failed => {
+                *failure = transaction_result.error.clone()
+                    .map(|error| TransactionFailure { hash: *hash, error });
+                FinalTransactionStatus::Failed
+            }
             TransactionStatus::Completed => {
                 for r in transaction_result.receipts.iter() {
                     let receipt_result = self.get_transaction_result(&r);
                     logs.push(TransactionLogs{ hash: *r, lines: receipt_result.logs.clone(), receipts: receipt_result.receipts.clone() });
-                    match self.collect_transaction_final_result(&receipt_result, logs) {
+                    match self.collect_transaction_final_result(&r, &receipt_result, logs, failure) {
                         FinalTransactionStatus::Failed => return FinalTransactionStatus::Failed,
                         FinalTransactionStatus::Completed => {},
                         _ => return FinalTransactionStatus::Started,
@@ -302,8 +1199,15 @@ impl ShardBlockChain {
         let transaction_result = self.get_transaction_result(hash);
         let mut result = FinalTransactionResult {
             status: FinalTransactionStatus::Unknown,
-            logs: vec![TransactionLogs{ hash: *hash, lines: transaction_result.logs.clone(), receipts: transaction_result.receipts.clone() }] };
-        result.status = self.collect_transaction_final_result(&transaction_result, &mut result.logs);
+            logs: vec![TransactionLogs{ hash: *hash, lines: transaction_result.logs.clone(), receipts: transaction_result.receipts.clone() }],
+            failure: None,
+        };
+        result.status = self.collect_transaction_final_result(
+            hash,
+            &transaction_result,
+            &mut result.logs,
+            &mut result.failure,
+        );
         result
     }
 
@@ -326,11 +1230,18 @@ impl ShardBlockChain {
 
 #[cfg(test)]
 mod tests {
-    use node_runtime::test_utils::generate_test_chain_spec;
-    use primitives::signature::DEFAULT_SIGNATURE;
+    use std::time::Duration;
+
+    use futures::{Future, Stream};
+    use node_runtime::test_utils::{generate_test_chain_spec, signed_transaction};
+    use primitives::signature::{get_key_pair, DEFAULT_SIGNATURE};
+    use primitives::signer::InMemorySigner;
     use primitives::types::Balance;
-    use storage::test_utils::create_memory_db;
-    use transaction::{SendMoneyTransaction, SignedTransaction, TransactionBody, TransactionStatus};
+    use storage::test_utils::{create_memory_db, FaultyStorage, Faults};
+    use transaction::{
+        InvalidTxError, RuntimeError, SendMoneyTransaction, SignedTransaction, TransactionBody,
+        TransactionStatus,
+    };
 
     use super::*;
 
@@ -339,12 +1250,103 @@ mod tests {
         ShardBlockChain::new(&chain_spec, Arc::new(create_memory_db()))
     }
 
-    fn send_money_tx(originator: &str, receiver: &str, amount: Balance) -> SignedTransaction {
-        SignedTransaction::new(
-            DEFAULT_SIGNATURE,
-            TransactionBody::SendMoney(SendMoneyTransaction {
-                nonce: 1, originator: originator.to_string(), receiver: receiver.to_string(), amount
-            }), )
+    fn send_money_tx(
+        chain: &ShardBlockChain,
+        originator: &str,
+        receiver: &str,
+        amount: Balance,
+    ) -> SignedTransaction {
+        signed_transaction(TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce: 1,
+            originator: originator.to_string(),
+            receiver: receiver.to_string(),
+            amount,
+            block_hash: CryptoHash::default(),
+            genesis_hash: chain.genesis_hash(),
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
+        }))
+    }
+
+    #[test]
+    fn test_transaction_validity_period() {
+        let (mut chain_spec, _) = generate_test_chain_spec();
+        chain_spec.transaction_validity_period = Some(5);
+        let chain = ShardBlockChain::new(&chain_spec, Arc::new(create_memory_db()));
+        let send_money = |block_hash| {
+            signed_transaction(TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce: 1,
+                originator: "alice.near".to_string(),
+                receiver: "bob.near".to_string(),
+                amount: 10,
+                block_hash,
+                genesis_hash: chain.genesis_hash(),
+                execute_at: 0,
+                tip: 0,
+                memo: vec![],
+            }))
+        };
+        assert_eq!(chain.check_transaction(&send_money(chain.genesis_hash())), Ok(()));
+        assert_eq!(
+            chain.check_transaction(&send_money(CryptoHash::default())),
+            Err(AdmissionError::Invalid(InvalidTxError::Expired(CryptoHash::default())))
+        );
+    }
+
+    #[test]
+    fn test_check_transaction() {
+        let chain = get_test_chain();
+        let transaction = send_money_tx(&chain, "alice.near", "bob.near", 10);
+        assert_eq!(chain.check_transaction(&transaction), Ok(()));
+        chain.add_transactions(vec![transaction.clone()]);
+        assert_eq!(
+            chain.check_transaction(&transaction),
+            Err(AdmissionError::Pool(PoolError::Duplicate(transaction.get_hash())))
+        );
+        let unknown = send_money_tx(&chain, "unknown.near", "bob.near", 10);
+        assert_eq!(
+            chain.check_transaction(&unknown),
+            Err(AdmissionError::Invalid(InvalidTxError::OriginatorDoesNotExist(
+                "unknown.near".to_string()
+            )))
+        );
+        chain.add_transactions(vec![unknown]);
+        assert_eq!(chain.pool.read().len(), 1);
+    }
+
+    #[test]
+    fn test_order_by_tip() {
+        let transaction = |originator: &str, nonce, tip| {
+            let body = TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce,
+                originator: originator.to_string(),
+                receiver: "bob.near".to_string(),
+                amount: 1,
+                block_hash: CryptoHash::default(),
+                genesis_hash: CryptoHash::default(),
+                execute_at: 0,
+                tip,
+                memo: vec![],
+            });
+            SignedTransaction::new(DEFAULT_SIGNATURE, body)
+        };
+        let transactions = vec![
+            transaction("alice.near", 1, 0),
+            transaction("alice.near", 2, 10),
+            transaction("bob.near", 1, 5),
+            transaction("eve.near", 1, 5),
+        ];
+        let ordered: Vec<_> = order_by_tip(transactions)
+            .iter()
+            .map(|t| (t.body.get_originator(), t.body.get_nonce()))
+            .collect();
+        assert_eq!(ordered, vec![
+            ("bob.near".to_string(), 1),
+            ("eve.near".to_string(), 1),
+            ("alice.near".to_string(), 1),
+            ("alice.near".to_string(), 2),
+        ]);
     }
 
     #[test]
@@ -357,28 +1359,39 @@ mod tests {
     #[test]
     fn test_transaction_failed() {
         let chain = get_test_chain();
-        let tx = send_money_tx("xyz.near", "bob.near", 100);
+        let tx = send_money_tx(&chain, "xyz.near", "bob.near", 100);
         let (block, (db_changes, _, tx_status, receipts)) = chain.prepare_new_block(
             chain.genesis_hash(), 
             vec![],
             vec![tx.clone()]
         );
-        chain.insert_block(&block, db_changes, tx_status, receipts);
+        chain.insert_block(&block, db_changes, tx_status, receipts).unwrap();
 
         let result = chain.get_transaction_result(&tx.get_hash());
         assert_eq!(result.status, TransactionStatus::Failed);
+        let final_result = chain.get_transaction_final_result(&tx.get_hash());
+        assert_eq!(final_result.status, FinalTransactionStatus::Failed);
+        assert_eq!(
+            final_result.failure,
+            Some(TransactionFailure {
+                hash: tx.get_hash(),
+                error: RuntimeError::InvalidTx(
+                    InvalidTxError::OriginatorDoesNotExist("xyz.near".to_string())
+                ),
+            })
+        );
     }
 
     #[test]
     fn test_get_transaction_status_complete() {
         let chain = get_test_chain();
-        let tx = send_money_tx("alice.near", "bob.near", 10);
+        let tx = send_money_tx(&chain, "alice.near", "bob.near", 10);
         let (block, (db_changes, _, tx_status, new_receipts)) = chain.prepare_new_block(
             chain.genesis_hash(),
             vec![],
             vec![tx.clone()]
         );
-        chain.insert_block(&block, db_changes, tx_status, new_receipts);
+        chain.insert_block(&block, db_changes, tx_status, new_receipts).unwrap();
 
         let result = chain.get_transaction_result(&tx.get_hash());
         assert_eq!(result.status, TransactionStatus::Completed);
@@ -395,7 +1408,7 @@ mod tests {
         let (block2, (db_changes2, _, tx_status2, receipts)) = chain.prepare_new_block(
             block.hash, vec![receipt_block], vec![]
         );
-        chain.insert_block(&block2, db_changes2, tx_status2, receipts);
+        chain.insert_block(&block2, db_changes2, tx_status2, receipts).unwrap();
 
         let result2 = chain.get_transaction_result(&result.receipts[0]);
         assert_eq!(result2.status, TransactionStatus::Completed);
@@ -414,14 +1427,17 @@ mod tests {
         let transaction = SignedTransaction::empty();
         let block = SignedShardBlock::new(
             0,
-            0,
+            1,
+            chain.genesis_hash(),
             CryptoHash::default(),
             CryptoHash::default(),
             vec![transaction],
             vec![],
         );
         let db_changes = HashMap::default();
-        chain.insert_block(&block, db_changes, vec![TransactionResult::default()], HashMap::new());
+        chain
+            .insert_block(&block, db_changes, vec![TransactionResult::default()], HashMap::new())
+            .unwrap();
         let address = chain.get_transaction_address(&t.get_hash());
         let expected = TransactionAddress {
             block_hash: block.hash,
@@ -438,5 +1454,257 @@ mod tests {
         assert_eq!(v.unwrap(), &expected.clone());
     }
 
+    #[test]
+    fn test_account_transactions() {
+        let chain = get_test_chain();
+        let tx = send_money_tx(&chain, "alice.near", "bob.near", 10);
+        let (block, (db_changes, _, tx_status, new_receipts)) =
+            chain.prepare_new_block(chain.genesis_hash(), vec![], vec![tx.clone()]);
+        chain.insert_block(&block, db_changes, tx_status, new_receipts).unwrap();
+        let receipt_nonce = chain.get_transaction_result(&tx.get_hash()).receipts[0];
+
+        let receipt_block = chain.get_receipt_block(block.index(), block.shard_id()).unwrap();
+        let (block2, (db_changes2, _, tx_status2, receipts)) =
+            chain.prepare_new_block(block.hash, vec![receipt_block], vec![]);
+        chain.insert_block(&block2, db_changes2, tx_status2, receipts).unwrap();
+
+        let alice = chain.get_account_transactions(&"alice.near".to_string());
+        assert_eq!(alice, vec![tx.get_hash()]);
+        let bob = chain.get_account_transactions(&"bob.near".to_string());
+        assert_eq!(bob, vec![tx.get_hash(), receipt_nonce]);
+        assert!(chain.get_account_transactions(&"eve.near".to_string()).is_empty());
+
+        let info = chain.get_receipt_info(&receipt_nonce).unwrap();
+        assert_eq!(info.receipt.receiver, "bob.near".to_string());
+        assert_eq!(info.block_index, block2.index());
+        assert_eq!(info.result.status, TransactionStatus::Completed);
+        assert!(chain.get_receipt_info(&tx.get_hash()).is_none());
+        assert!(chain.get_transaction_info(&receipt_nonce).is_none());
+    }
+
+    #[test]
+    fn test_outcome_proof() {
+        let chain = get_test_chain();
+        let tx = send_money_tx(&chain, "alice.near", "bob.near", 10);
+        let (block, (db_changes, _, tx_status, new_receipts)) =
+            chain.prepare_new_block(chain.genesis_hash(), vec![], vec![tx.clone()]);
+        chain.insert_block(&block, db_changes, tx_status, new_receipts).unwrap();
+        let receipt_nonce = chain.get_transaction_result(&tx.get_hash()).receipts[0];
+        let receipt_block = chain.get_receipt_block(block.index(), block.shard_id()).unwrap();
+        let tx2 = send_money_tx(&chain, "bob.near", "alice.near", 5);
+        let (block2, (db_changes2, _, tx_status2, receipts)) =
+            chain.prepare_new_block(block.hash, vec![receipt_block], vec![tx2.clone()]);
+        chain.insert_block(&block2, db_changes2, tx_status2, receipts).unwrap();
+
+        for id in &[tx.get_hash(), receipt_nonce, tx2.get_hash()] {
+            let proof = chain.get_outcome_proof(id).unwrap();
+            assert_eq!(proof.outcome.id, *id);
+            assert!(proof.verify());
+        }
+        let mut proof = chain.get_outcome_proof(&receipt_nonce).unwrap();
+        assert_eq!(proof.header.hash, block2.hash);
+        proof.outcome.result.status = TransactionStatus::Failed;
+        assert!(!proof.verify());
+        assert!(chain.get_outcome_proof(&CryptoHash::default()).is_none());
+    }
+
+    #[test]
+    fn test_challenge() {
+        let chain = get_test_chain();
+        let (_, signer) = generate_test_chain_spec();
+        let authorities = vec![AuthorityStake {
+            account_id: signer.account_id.clone(),
+            public_key: get_key_pair().0,
+            bls_public_key: signer.public_key.clone(),
+            amount: 1,
+        }];
+        let tx = send_money_tx(&chain, "alice.near", "bob.near", 10);
+        let (mut block, _) =
+            chain.prepare_new_block(chain.genesis_hash(), vec![], vec![tx.clone()]);
+        block.add_signature(&block.sign(&signer), 0);
+        assert!(chain.challenge_block(&block).is_none());
+
+        // The producer declares the state without the transfer.
+        let mut invalid = SignedShardBlock::new(
+            0,
+            1,
+            chain.genesis_hash(),
+            chain.chain.best_block().merkle_root_state(),
+            block.body.header.outcome_root,
+            vec![tx],
+            vec![],
+        );
+        invalid.add_signature(&invalid.sign(&signer), 0);
+        let challenge = chain.challenge_block(&invalid).unwrap();
+        assert_eq!(chain.verify_challenge(&challenge, &authorities), Ok(()));
+
+        let mut valid = challenge.clone();
+        valid.block = block.clone();
+        let error = ChallengeError::ValidBlock(block.hash);
+        assert_eq!(chain.verify_challenge(&valid, &authorities), Err(error));
+        let mut incomplete = challenge.clone();
+        incomplete.witness.clear();
+        let error = ChallengeError::IncompleteWitness;
+        assert_eq!(chain.verify_challenge(&incomplete, &authorities), Err(error));
+        // The unsigned block and the block signed by someone else don't blame the authorities.
+        let mut unsigned = challenge.clone();
+        unsigned.block.signature = Default::default();
+        let error = ChallengeError::InvalidSignature(invalid.hash);
+        assert_eq!(chain.verify_challenge(&unsigned, &authorities), Err(error.clone()));
+        let mut forged = unsigned.clone();
+        forged.block.add_signature(&forged.block.sign(&InMemorySigner::default()), 0);
+        assert_eq!(chain.verify_challenge(&forged, &authorities), Err(error));
+        let mut unknown = challenge;
+        unknown.parent_header = block.header();
+        let error = ChallengeError::UnknownParent(block.hash);
+        assert_eq!(chain.verify_challenge(&unknown, &authorities), Err(error));
+    }
+
+    #[test]
+    fn test_transaction_proof() {
+        let chain = get_test_chain();
+        let tx = send_money_tx(&chain, "alice.near", "bob.near", 10);
+        let tx2 = send_money_tx(&chain, "bob.near", "alice.near", 5);
+        let (block, (db_changes, _, tx_status, new_receipts)) =
+            chain.prepare_new_block(chain.genesis_hash(), vec![], vec![tx.clone(), tx2.clone()]);
+        chain.insert_block(&block, db_changes, tx_status, new_receipts).unwrap();
+        let receipt_nonce = chain.get_transaction_result(&tx.get_hash()).receipts[0];
+
+        for tx in &[tx, tx2] {
+            let proof = chain.get_transaction_proof(&tx.get_hash()).unwrap();
+            assert_eq!(proof.transaction, *tx);
+            assert_eq!(proof.header.hash, block.hash);
+            assert!(proof.verify());
+        }
+        let mut proof = chain.get_transaction_proof(&tx2.get_hash()).unwrap();
+        proof.header.body.transaction_root = CryptoHash::default();
+        assert!(!proof.verify());
+        assert!(chain.get_transaction_proof(&receipt_nonce).is_none());
+    }
+
+    #[test]
+    fn test_state_changes() {
+        let chain = get_test_chain();
+        let tx = send_money_tx(&chain, "alice.near", "bob.near", 10);
+        let (block, (db_changes, _, tx_status, new_receipts)) =
+            chain.prepare_new_block(chain.genesis_hash(), vec![], vec![tx]);
+        chain.insert_block(&block, db_changes, tx_status, new_receipts).unwrap();
+
+        let changes = chain.get_state_changes(&block.hash).unwrap();
+        let state = storage::StateDbUpdate::new(chain.state_db.clone(), block.merkle_root_state());
+        for (key, old_value, new_value) in changes.iter() {
+            assert_ne!(old_value, new_value);
+            assert_eq!(&state.get(key).map(|v| v.to_vec()), new_value);
+        }
+        let changed_accounts: Vec<_> = node_runtime::state_changes::classify_changes(&changes)
+            .into_iter()
+            .filter_map(|c| c.account_id)
+            .collect();
+        assert!(changed_accounts.contains(&"alice.near".to_string()));
+        assert!(chain.get_state_changes(&CryptoHash::default()).is_none());
+    }
+
     // TODO(472): Add extensive testing for ShardBlockChain.
+
+    #[test]
+    fn test_reorg() {
+        let chain = get_test_chain();
+        let reverted_receipts = chain.subscribe_to_reverted_receipts();
+        let genesis = chain.chain.best_block();
+        let tx = send_money_tx(&chain, "alice.near", "bob.near", 10);
+        let (block, (db_changes, _, tx_status, new_receipts)) =
+            chain.prepare_new_block(genesis.hash, vec![], vec![tx.clone()]);
+        chain.insert_block(&block, db_changes, tx_status, new_receipts).unwrap();
+        let receipt_block = chain.get_receipt_block(block.index(), block.shard_id()).unwrap();
+        let (block2, (db_changes2, _, tx_status2, receipts2)) =
+            chain.prepare_new_block(block.hash, vec![receipt_block.clone()], vec![]);
+        chain.insert_block(&block2, db_changes2, tx_status2, receipts2).unwrap();
+        let status = chain.get_transaction_result(&tx.get_hash()).status;
+        assert_eq!(status, TransactionStatus::Completed);
+        let bob = "bob.near".to_string();
+        assert_eq!(chain.get_account_transactions(&bob).len(), 2);
+        let balance = |root| chain.statedb_viewer.view_account(root, &bob).unwrap().amount;
+        assert_ne!(balance(chain.next_apply_state().root), balance(genesis.merkle_root_state()));
+
+        // A competing fork without the transaction takes over once it is longer.
+        let mut parent_hash = genesis.hash;
+        for _ in 0..3 {
+            let (fork_block, (db_changes, _, tx_status, new_receipts)) =
+                chain.prepare_new_block(parent_hash, vec![], vec![]);
+            chain.insert_block(&fork_block, db_changes, tx_status, new_receipts).unwrap();
+            parent_hash = fork_block.hash;
+        }
+        assert_eq!(chain.chain.best_hash(), parent_hash);
+        // The state is the one of the fork, where the money was never sent.
+        assert_eq!(balance(chain.next_apply_state().root), balance(genesis.merkle_root_state()));
+        let status = chain.get_transaction_result(&tx.get_hash()).status;
+        assert_eq!(status, TransactionStatus::Unknown);
+        assert!(chain.get_transaction_info(&tx.get_hash()).is_none());
+        assert!(chain.get_account_transactions(&bob).is_empty());
+        assert!(chain.get_receipt_block(1, 0).unwrap().receipts.is_empty());
+        // The transaction goes back to the pool and the receipts are re-emitted.
+        assert!(chain.pool.read().contains(&tx.get_hash()));
+        let (receipt, _) = reverted_receipts.into_future().wait().ok().unwrap();
+        assert_eq!(receipt, Some(receipt_block));
+    }
+
+    #[test]
+    fn test_finalize() {
+        let chain = get_test_chain();
+        let genesis = chain.chain.best_block();
+        let node = hash(b"replaced node").as_ref().to_vec();
+        let mut db_changes = storage::DBChanges::default();
+        db_changes.insert(node.clone(), Some(vec![1]));
+        chain.state_db.commit(db_changes).unwrap();
+        let is_stored = || chain.storage.get(storage::COL_STATE, &node).unwrap().is_some();
+
+        let tx = send_money_tx(&chain, "alice.near", "bob.near", 10);
+        let (block, (mut db_changes, _, tx_status, new_receipts)) =
+            chain.prepare_new_block(genesis.hash, vec![], vec![tx]);
+        db_changes.insert(node.clone(), None);
+        chain.insert_block(&block, db_changes, tx_status, new_receipts).unwrap();
+        // The replaced node is kept while a fork may still go back to the state before.
+        assert!(is_stored());
+
+        assert!(chain.finalize(&block.hash));
+        assert!(!chain.finalize(&genesis.hash));
+        assert_eq!(chain.chain.finalized_hash(), block.hash);
+        assert!(!is_stored());
+        let bob = "bob.near".to_string();
+        assert!(chain.statedb_viewer.view_account(block.merkle_root_state(), &bob).is_ok());
+    }
+
+    #[test]
+    fn test_state_faults() {
+        let (chain_spec, _) = generate_test_chain_spec();
+        let storage = Arc::new(FaultyStorage::new(storage::COL_STATE));
+        let chain = ShardBlockChain::new(&chain_spec, storage.clone());
+        let genesis = chain.chain.best_block();
+        let tx = send_money_tx(&chain, "alice.near", "bob.near", 10);
+        let (block, _) = chain.prepare_new_block(genesis.hash, vec![], vec![tx.clone()]);
+
+        // The block whose state can't be written is not inserted, nor its results recorded.
+        storage.set_faults(Faults { writes_before_failure: Some(0), ..Default::default() });
+        assert!(!chain.apply_block(&block));
+        assert_eq!(chain.chain.best_block().hash, genesis.hash);
+        assert!(chain.chain.get_block(&BlockId::Hash(block.hash)).is_none());
+        let status = chain.get_transaction_result(&tx.get_hash()).status;
+        assert_eq!(status, TransactionStatus::Unknown);
+        let bob = "bob.near".to_string();
+        assert!(chain.statedb_viewer.view_account(block.merkle_root_state(), &bob).is_err());
+
+        // Nor is the block applied on a state that can't be read.
+        storage.set_faults(Faults { reads_before_failure: Some(0), ..Default::default() });
+        assert!(!chain.apply_block(&block));
+        assert_eq!(chain.chain.best_block().hash, genesis.hash);
+
+        // Slow storage only delays the block.
+        storage.set_faults(Faults { latency: Duration::from_millis(1), ..Default::default() });
+        assert!(chain.apply_block(&block));
+        storage.heal();
+        assert_eq!(chain.chain.best_block().hash, block.hash);
+        let status = chain.get_transaction_result(&tx.get_hash()).status;
+        assert_eq!(status, TransactionStatus::Completed);
+        assert!(chain.statedb_viewer.view_account(block.merkle_root_state(), &bob).is_ok());
+    }
 }
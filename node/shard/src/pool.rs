@@ -0,0 +1,894 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::ops::Bound::{Excluded, Unbounded};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use node_runtime::config::NoncePolicy;
+use primitives::hash::CryptoHash;
+use primitives::traits::{Decode, Encode};
+use primitives::types::{AccountId, Balance};
+use storage::{Storage, COL_TRANSACTION_POOL};
+use transaction::SignedTransaction;
+
+/// When a pending transaction can be replaced by another one with the same signer and nonce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplacementPolicy {
+    /// Pending transactions are never replaced.
+    Never,
+    /// The new transaction must pay a tip larger by at least the given percent.
+    MinTipIncrease(u64),
+}
+
+impl ReplacementPolicy {
+    pub fn min_replacement_tip(&self, tip: Balance) -> Option<Balance> {
+        match self {
+            ReplacementPolicy::Never => None,
+            ReplacementPolicy::MinTipIncrease(percent) => {
+                let increase = (u128::from(tip) * u128::from(*percent) / 100) as Balance;
+                tip.checked_add(std::cmp::max(increase, 1))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransactionPoolConfig {
+    pub replacement_policy: ReplacementPolicy,
+    /// Whether to keep the pending transactions in the storage, so that they survive restarts.
+    pub persist: bool,
+    /// Maximum number of pending transactions of a single signer.
+    pub max_transactions_per_signer: usize,
+    /// Maximum total size in bytes of the pending transactions. When it is exceeded, the
+    /// transactions with the lowest tip are evicted, the oldest first.
+    pub max_size: usize,
+    /// Delay before the first rebroadcast of a pending transaction. It doubles after every
+    /// rebroadcast.
+    pub rebroadcast_interval: Duration,
+    /// Pending transactions older than this are not rebroadcast anymore.
+    pub max_rebroadcast_age: Duration,
+    /// Maximum number of transactions selected for a block.
+    pub max_block_transactions: usize,
+    /// Maximum total size in bytes of the transactions selected for a block.
+    pub max_block_size: usize,
+}
+
+impl Default for TransactionPoolConfig {
+    fn default() -> Self {
+        TransactionPoolConfig {
+            replacement_policy: ReplacementPolicy::MinTipIncrease(10),
+            persist: false,
+            max_transactions_per_signer: 100,
+            max_size: 64 * 1024 * 1024,
+            rebroadcast_interval: Duration::from_secs(5),
+            max_rebroadcast_age: Duration::from_secs(10 * 60),
+            max_block_transactions: 10_000,
+            max_block_size: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// Reason why a transaction was not added to the pool.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum PoolError {
+    /// Transaction is already pending.
+    Duplicate(CryptoHash),
+    /// Another transaction with the same signer and nonce is pending and can't be replaced
+    /// with a transaction paying this tip.
+    Underpriced { signer: AccountId, nonce: u64, tip: Balance, min_tip: Option<Balance> },
+    /// Signer already has the maximum number of pending transactions.
+    TooManyTransactions { signer: AccountId, limit: usize },
+    /// Pool is full and the tip of the transaction is too low to evict any other.
+    PoolFull { tip: Balance },
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PoolError::Duplicate(hash) => write!(f, "Transaction {} is already pending", hash),
+            PoolError::Underpriced { signer, nonce, tip, min_tip: Some(min_tip) } => write!(
+                f,
+                "Transaction of {} with nonce {} is pending, replacing it needs a tip of {}, \
+                 got {}",
+                signer, nonce, min_tip, tip,
+            ),
+            PoolError::Underpriced { signer, nonce, min_tip: None, .. } => write!(
+                f,
+                "Transaction of {} with nonce {} is pending and can't be replaced",
+                signer, nonce,
+            ),
+            PoolError::TooManyTransactions { signer, limit } => {
+                write!(f, "Signer {} already has {} pending transactions", signer, limit)
+            }
+            PoolError::PoolFull { tip } => {
+                write!(f, "Transaction pool is full, tip of {} is too low to get in", tip)
+            }
+        }
+    }
+}
+
+/// Pending transaction as reported to the operators.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingTransactionInfo {
+    pub hash: CryptoHash,
+    pub nonce: u64,
+    pub tip: Balance,
+    pub size: usize,
+    /// Time since the transaction was added to the pool.
+    pub age: Duration,
+}
+
+/// Aggregate numbers of the pool.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolStats {
+    pub num_transactions: usize,
+    pub num_signers: usize,
+    pub size: usize,
+    pub max_size: usize,
+}
+
+struct PoolEntry {
+    transaction: SignedTransaction,
+    size: usize,
+    added: Instant,
+    /// Number of times the transaction was rebroadcast.
+    rebroadcasts: u32,
+    next_rebroadcast: Instant,
+}
+
+/// Transactions waiting to be included into a shard block, grouped by the signer and
+/// ordered by nonce.
+pub struct TransactionPool {
+    nonce_policy: NoncePolicy,
+    config: TransactionPoolConfig,
+    storage: Arc<Storage>,
+    /// Pending transactions of every signer by nonce.
+    transactions: BTreeMap<AccountId, BTreeMap<u64, PoolEntry>>,
+    /// Hashes of the pending transactions.
+    known: HashSet<CryptoHash>,
+    /// Total size in bytes of the pending transactions.
+    size: usize,
+}
+
+impl TransactionPool {
+    pub fn new(
+        nonce_policy: NoncePolicy,
+        config: TransactionPoolConfig,
+        storage: Arc<Storage>,
+    ) -> Self {
+        TransactionPool {
+            nonce_policy,
+            config,
+            storage,
+            transactions: BTreeMap::new(),
+            known: HashSet::new(),
+            size: 0,
+        }
+    }
+
+    /// Loads the transactions persisted before the restart. The ones for which `is_valid`
+    /// returns false, e.g. because they expired or their nonce was used, are dropped.
+    pub fn load<F>(&mut self, is_valid: F)
+    where
+        F: Fn(&SignedTransaction) -> bool,
+    {
+        if !self.config.persist {
+            return;
+        }
+        let storage = self.storage.clone();
+        let mut db_transaction = storage.transaction();
+        for (key, value) in storage.iter(COL_TRANSACTION_POOL) {
+            let transaction: Option<SignedTransaction> = Decode::decode(&value).ok();
+            match transaction {
+                Some(transaction) if is_valid(&transaction) => {
+                    if self.add(transaction).is_err() {
+                        db_transaction.delete(COL_TRANSACTION_POOL, &key);
+                    }
+                }
+                _ => db_transaction.delete(COL_TRANSACTION_POOL, &key),
+            }
+        }
+        storage.write(db_transaction).expect("Database write failed");
+        info!("Loaded {} pending transactions", self.len());
+    }
+
+    pub fn len(&self) -> usize {
+        self.known.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.known.is_empty()
+    }
+
+    pub fn contains(&self, hash: &CryptoHash) -> bool {
+        self.known.contains(hash)
+    }
+
+    /// Total size in bytes of the pending transactions.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            num_transactions: self.len(),
+            num_signers: self.transactions.len(),
+            size: self.size,
+            max_size: self.config.max_size,
+        }
+    }
+
+    /// Pending transactions of every signer, or only of the given one, ordered by nonce.
+    pub fn pending(
+        &self,
+        signer: Option<&AccountId>,
+    ) -> BTreeMap<AccountId, Vec<PendingTransactionInfo>> {
+        let now = Instant::now();
+        self.transactions
+            .iter()
+            .filter(|(s, _)| signer.map_or(true, |signer| signer == *s))
+            .map(|(s, signer_transactions)| {
+                let infos = signer_transactions
+                    .iter()
+                    .map(|(&nonce, entry)| PendingTransactionInfo {
+                        hash: entry.transaction.get_hash(),
+                        nonce,
+                        tip: entry.transaction.body.get_tip(),
+                        size: entry.size,
+                        age: now.duration_since(entry.added),
+                    })
+                    .collect();
+                (s.clone(), infos)
+            })
+            .collect()
+    }
+
+    /// Adds the transaction to the pool. A pending transaction with the same signer and nonce
+    /// is replaced if the replacement policy allows it, and then returned.
+    pub fn insert(
+        &mut self,
+        transaction: SignedTransaction,
+    ) -> Result<Option<SignedTransaction>, PoolError> {
+        let data = if self.config.persist {
+            Some(Encode::encode(&transaction).expect("Error serializing data"))
+        } else {
+            None
+        };
+        let hash = transaction.get_hash();
+        let replaced = self.add(transaction)?;
+        if let Some(data) = data {
+            let mut db_transaction = self.storage.transaction();
+            db_transaction.put(COL_TRANSACTION_POOL, hash.as_ref(), &data);
+            self.storage.write(db_transaction).expect("Database write failed");
+        }
+        Ok(replaced)
+    }
+
+    /// Checks whether the transaction would be admitted, without adding it. A transaction
+    /// that passes can still be rejected as `PoolFull` when the pool is full.
+    pub fn check(&self, transaction: &SignedTransaction) -> Result<(), PoolError> {
+        let hash = transaction.get_hash();
+        if self.known.contains(&hash) {
+            return Err(PoolError::Duplicate(hash));
+        }
+        let signer = transaction.body.get_originator();
+        let nonce = transaction.body.get_nonce();
+        let tip = transaction.body.get_tip();
+        let signer_transactions = self.transactions.get(&signer);
+        if let Some(pending) = signer_transactions.and_then(|t| t.get(&nonce)) {
+            let pending_tip = pending.transaction.body.get_tip();
+            let min_tip = self.config.replacement_policy.min_replacement_tip(pending_tip);
+            if min_tip.map_or(true, |min_tip| tip < min_tip) {
+                return Err(PoolError::Underpriced { signer, nonce, tip, min_tip });
+            }
+        } else {
+            let limit = self.config.max_transactions_per_signer;
+            if signer_transactions.map_or(0, BTreeMap::len) >= limit {
+                return Err(PoolError::TooManyTransactions { signer, limit });
+            }
+        }
+        Ok(())
+    }
+
+    fn add(
+        &mut self,
+        transaction: SignedTransaction,
+    ) -> Result<Option<SignedTransaction>, PoolError> {
+        self.check(&transaction)?;
+        let hash = transaction.get_hash();
+        let tip = transaction.body.get_tip();
+        let now = Instant::now();
+        let entry = PoolEntry {
+            size: transaction.get_size(),
+            added: now,
+            rebroadcasts: 0,
+            next_rebroadcast: now + self.config.rebroadcast_interval,
+            transaction,
+        };
+        let replaced = self.put_entry(entry);
+        let mut evicted = vec![];
+        while self.size > self.config.max_size {
+            let (signer, nonce) = match self.eviction_candidate() {
+                Some(candidate) => candidate,
+                None => break,
+            };
+            let entry = self.take_entry(&signer, nonce).expect("Candidate is in the pool");
+            if entry.transaction.get_hash() == hash {
+                // The pool is left as it was, with the replaced and the evicted transactions.
+                for entry in evicted.into_iter().chain(replaced) {
+                    self.put_entry(entry);
+                }
+                return Err(PoolError::PoolFull { tip });
+            }
+            evicted.push(entry);
+        }
+        for entry in replaced.iter().chain(evicted.iter()) {
+            self.delete_persisted(&entry.transaction.get_hash());
+        }
+        Ok(replaced.map(|entry| entry.transaction))
+    }
+
+    /// Puts the entry into the pool, returning the one with the same signer and nonce it
+    /// replaces. The persisted transactions are left as they are.
+    fn put_entry(&mut self, entry: PoolEntry) -> Option<PoolEntry> {
+        let signer = entry.transaction.body.get_originator();
+        let nonce = entry.transaction.body.get_nonce();
+        self.known.insert(entry.transaction.get_hash());
+        self.size += entry.size;
+        let replaced =
+            self.transactions.entry(signer).or_insert_with(BTreeMap::new).insert(nonce, entry);
+        if let Some(replaced) = &replaced {
+            self.known.remove(&replaced.transaction.get_hash());
+            self.size -= replaced.size;
+        }
+        replaced
+    }
+
+    /// Takes the entry out of the pool, leaving the persisted transactions as they are.
+    fn take_entry(&mut self, signer: &AccountId, nonce: u64) -> Option<PoolEntry> {
+        let signer_transactions = self.transactions.get_mut(signer)?;
+        let entry = signer_transactions.remove(&nonce)?;
+        if signer_transactions.is_empty() {
+            self.transactions.remove(signer);
+        }
+        self.known.remove(&entry.transaction.get_hash());
+        self.size -= entry.size;
+        Some(entry)
+    }
+
+    /// The transaction to evict when the pool is full: the last transaction of some signer,
+    /// so that the remaining nonces stay contiguous, with the lowest tip, the oldest first.
+    fn eviction_candidate(&self) -> Option<(AccountId, u64)> {
+        self.transactions
+            .iter()
+            .filter_map(|(signer, signer_transactions)| {
+                signer_transactions.iter().next_back().map(|(&nonce, entry)| {
+                    (entry.transaction.body.get_tip(), entry.added, signer, nonce)
+                })
+            })
+            .min()
+            .map(|(_, _, signer, nonce)| (signer.clone(), nonce))
+    }
+
+    fn delete_persisted(&self, hash: &CryptoHash) {
+        if self.config.persist {
+            let mut db_transaction = self.storage.transaction();
+            db_transaction.delete(COL_TRANSACTION_POOL, hash.as_ref());
+            self.storage.write(db_transaction).expect("Database write failed");
+        }
+    }
+
+    /// Removes the transaction of the signer with the given nonce.
+    pub fn remove(&mut self, signer: &AccountId, nonce: u64) -> Option<SignedTransaction> {
+        let entry = self.take_entry(signer, nonce)?;
+        self.delete_persisted(&entry.transaction.get_hash());
+        Some(entry.transaction)
+    }
+
+    /// Removes and returns the transactions for which `is_expired` holds.
+    pub fn remove_expired<F>(&mut self, is_expired: F) -> Vec<SignedTransaction>
+    where
+        F: Fn(&SignedTransaction) -> bool,
+    {
+        let expired: Vec<(AccountId, u64)> = self
+            .transactions
+            .iter()
+            .flat_map(|(signer, t)| t.iter().map(move |(&nonce, e)| (signer, nonce, e)))
+            .filter(|(_, _, entry)| is_expired(&entry.transaction))
+            .map(|(signer, nonce, _)| (signer.clone(), nonce))
+            .collect();
+        expired.into_iter().filter_map(|(signer, nonce)| self.remove(&signer, nonce)).collect()
+    }
+
+    /// Transactions that can be applied on top of the state with the given account nonces.
+    /// For every signer, only the run of transactions starting at the account nonce in which
+    /// each nonce is valid after the previous one is considered. Signers without an account
+    /// are skipped.
+    ///
+    /// If these don't fit into a block, they are selected greedily to maximize the tips: at
+    /// every step, among the prefixes of the signers' remaining runs that fit, the one with the
+    /// highest average tip per transaction is taken. This way a high tip pays for the
+    /// transactions that precede it, while a high tip followed by many low ones doesn't get
+    /// them all in. Ties go to the signer that is first in order, then to the shorter prefix.
+    pub fn prepare_transactions<F>(&self, account_nonce: F) -> Vec<SignedTransaction>
+    where
+        F: Fn(&AccountId) -> Option<u64>,
+    {
+        let mut runs: Vec<Vec<&PoolEntry>> = vec![];
+        for (signer, signer_transactions) in self.transactions.iter() {
+            let mut nonce = match account_nonce(signer) {
+                Some(nonce) => nonce,
+                None => continue,
+            };
+            let mut run = vec![];
+            let pending = signer_transactions.range((Excluded(nonce), Unbounded));
+            for (&tx_nonce, entry) in pending {
+                if !self.nonce_policy.is_valid(tx_nonce, nonce) {
+                    break;
+                }
+                run.push(entry);
+                nonce = tx_nonce;
+            }
+            if !run.is_empty() {
+                runs.push(run);
+            }
+        }
+        // Number of the selected transactions of every run.
+        let mut taken = vec![0; runs.len()];
+
+        let mut result = vec![];
+        let mut num_left = self.config.max_block_transactions;
+        let mut size_left = self.config.max_block_size;
+        loop {
+            // Index of the run, length of the prefix, its total tip and size.
+            let mut best: Option<(usize, usize, u128, usize)> = None;
+            for (index, run) in runs.iter().enumerate() {
+                let mut tip = 0u128;
+                let mut size = 0;
+                for (i, entry) in run[taken[index]..].iter().take(num_left).enumerate() {
+                    size += entry.size;
+                    if size > size_left {
+                        break;
+                    }
+                    tip += u128::from(entry.transaction.body.get_tip());
+                    let len = i + 1;
+                    let better = match best {
+                        // Compares the averages tip / len and best_tip / best_len.
+                        Some((_, best_len, best_tip, _)) => {
+                            tip * best_len as u128 > best_tip * len as u128
+                        }
+                        None => true,
+                    };
+                    if better {
+                        best = Some((index, len, tip, size));
+                    }
+                }
+            }
+            let (index, len, _, size) = match best {
+                Some(best) => best,
+                None => break,
+            };
+            let start = taken[index];
+            result.extend(runs[index][start..start + len].iter().map(|e| e.transaction.clone()));
+            taken[index] += len;
+            num_left -= len;
+            size_left -= size;
+        }
+        result
+    }
+
+    /// Returns the pending transactions that are due for a rebroadcast at `now` and schedules
+    /// their next one, with the delay doubled.
+    pub fn take_rebroadcast(&mut self, now: Instant) -> Vec<SignedTransaction> {
+        let interval = self.config.rebroadcast_interval;
+        let max_age = self.config.max_rebroadcast_age;
+        let mut result = vec![];
+        for entry in self.transactions.values_mut().flat_map(BTreeMap::values_mut) {
+            if entry.next_rebroadcast > now || now.duration_since(entry.added) > max_age {
+                continue;
+            }
+            entry.rebroadcasts += 1;
+            let delay = interval * 2u32.saturating_pow(entry.rebroadcasts.min(16));
+            entry.next_rebroadcast = now + std::cmp::min(delay, max_age);
+            result.push(entry.transaction.clone());
+        }
+        result
+    }
+
+    /// Next nonce the signer can use, given the committed account nonce. Pending transactions
+    /// that would be included on top of it are taken into account.
+    pub fn next_nonce(&self, signer: &AccountId, account_nonce: u64) -> u64 {
+        let mut nonce = account_nonce;
+        if let Some(signer_transactions) = self.transactions.get(signer) {
+            for (&tx_nonce, _) in signer_transactions.range((Excluded(nonce), Unbounded)) {
+                if !self.nonce_policy.is_valid(tx_nonce, nonce) {
+                    break;
+                }
+                nonce = tx_nonce;
+            }
+        }
+        nonce + 1
+    }
+
+    /// Called after a block is applied. Removes the transactions included into it and the
+    /// transactions of the same signers whose nonce is not above the new account nonce.
+    pub fn on_block_applied<F>(&mut self, transactions: &[SignedTransaction], account_nonce: F)
+    where
+        F: Fn(&AccountId) -> Option<u64>,
+    {
+        let mut signers = HashSet::new();
+        for transaction in transactions {
+            let signer = transaction.body.get_originator();
+            let nonce = transaction.body.get_nonce();
+            let included = self
+                .transactions
+                .get(&signer)
+                .and_then(|t| t.get(&nonce))
+                .map_or(false, |e| e.transaction.get_hash() == transaction.get_hash());
+            if included {
+                self.remove(&signer, nonce);
+            }
+            signers.insert(signer);
+        }
+        for signer in signers {
+            let nonce = match account_nonce(&signer) {
+                Some(nonce) => nonce,
+                None => continue,
+            };
+            let stale: Vec<u64> = match self.transactions.get(&signer) {
+                Some(t) => t.range(..=nonce).map(|(&n, _)| n).collect(),
+                None => continue,
+            };
+            for n in stale {
+                self.remove(&signer, n);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use node_runtime::test_utils::signed_transaction;
+    use storage::test_utils::create_memory_db;
+    use transaction::{SendMoneyTransaction, TransactionBody};
+
+    use super::*;
+
+    fn transaction_with_tip(
+        originator: &str,
+        nonce: u64,
+        amount: u64,
+        tip: Balance,
+    ) -> SignedTransaction {
+        signed_transaction(TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce,
+            originator: originator.to_string(),
+            receiver: "bob.near".to_string(),
+            amount,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip,
+            memo: vec![],
+        }))
+    }
+
+    fn transaction(originator: &str, nonce: u64, amount: u64) -> SignedTransaction {
+        transaction_with_tip(originator, nonce, amount, 0)
+    }
+
+    fn new_pool_with_config(config: TransactionPoolConfig) -> TransactionPool {
+        TransactionPool::new(NoncePolicy::Increasing, config, Arc::new(create_memory_db()))
+    }
+
+    fn new_pool(nonce_policy: NoncePolicy) -> TransactionPool {
+        let storage = Arc::new(create_memory_db());
+        TransactionPool::new(nonce_policy, TransactionPoolConfig::default(), storage)
+    }
+
+    fn nonces(transactions: &[SignedTransaction]) -> Vec<(AccountId, u64)> {
+        transactions.iter().map(|t| (t.body.get_originator(), t.body.get_nonce())).collect()
+    }
+
+    #[test]
+    fn test_pool_deduplicates() {
+        let mut pool = new_pool(NoncePolicy::StrictIncrement);
+        assert_eq!(pool.insert(transaction("alice.near", 1, 1)), Ok(None));
+        assert_eq!(
+            pool.insert(transaction("alice.near", 1, 1)),
+            Err(PoolError::Duplicate(transaction("alice.near", 1, 1).get_hash()))
+        );
+        assert!(pool.insert(transaction("alice.near", 1, 2)).is_err());
+        assert_eq!(pool.insert(transaction("alice.near", 2, 1)), Ok(None));
+        assert_eq!(pool.len(), 2);
+        assert!(pool.contains(&transaction("alice.near", 2, 1).get_hash()));
+    }
+
+    #[test]
+    fn test_pool_prepare_transactions() {
+        let mut pool = new_pool(NoncePolicy::StrictIncrement);
+        let pending = [
+            ("alice.near", 3),
+            ("alice.near", 2),
+            ("alice.near", 5),
+            ("bob.near", 1),
+            ("eve.near", 1),
+        ];
+        for (originator, nonce) in pending.iter() {
+            pool.insert(transaction(originator, *nonce, 1)).unwrap();
+        }
+        let account_nonces: HashMap<AccountId, u64> =
+            vec![("alice.near".to_string(), 1), ("bob.near".to_string(), 1)].into_iter().collect();
+        let prepared = pool.prepare_transactions(|signer| account_nonces.get(signer).cloned());
+        // Alice's nonce 5 waits for 4, bob's nonce 1 is already used and eve has no account.
+        assert_eq!(
+            nonces(&prepared),
+            vec![("alice.near".to_string(), 2), ("alice.near".to_string(), 3)]
+        );
+
+        let mut pool_with_gaps = new_pool(NoncePolicy::Increasing);
+        pool_with_gaps.insert(transaction("alice.near", 3, 1)).unwrap();
+        pool_with_gaps.insert(transaction("alice.near", 5, 1)).unwrap();
+        let prepared = pool_with_gaps.prepare_transactions(|_| Some(1));
+        assert_eq!(
+            nonces(&prepared),
+            vec![("alice.near".to_string(), 3), ("alice.near".to_string(), 5)]
+        );
+    }
+
+    #[test]
+    fn test_pool_on_block_applied() {
+        let mut pool = new_pool(NoncePolicy::Increasing);
+        for nonce in 1..5 {
+            pool.insert(transaction("alice.near", nonce, 1)).unwrap();
+        }
+        pool.insert(transaction("bob.near", 1, 1)).unwrap();
+        // A different transaction with alice's nonce 3 was included, so the pending one
+        // with that nonce can no longer be applied.
+        let block_transactions =
+            vec![transaction("alice.near", 1, 1), transaction("alice.near", 3, 100)];
+        pool.on_block_applied(&block_transactions, |signer| {
+            if signer == "alice.near" { Some(3) } else { Some(0) }
+        });
+        assert_eq!(pool.len(), 2);
+        assert!(pool.contains(&transaction("alice.near", 4, 1).get_hash()));
+        assert!(pool.contains(&transaction("bob.near", 1, 1).get_hash()));
+    }
+
+    #[test]
+    fn test_pool_replace_by_fee() {
+        let mut pool = new_pool(NoncePolicy::Increasing);
+        let original = transaction_with_tip("alice.near", 1, 1, 100);
+        pool.insert(original.clone()).unwrap();
+        assert_eq!(
+            pool.insert(transaction_with_tip("alice.near", 1, 2, 109)),
+            Err(PoolError::Underpriced {
+                signer: "alice.near".to_string(),
+                nonce: 1,
+                tip: 109,
+                min_tip: Some(110),
+            })
+        );
+        let replacement = transaction_with_tip("alice.near", 1, 2, 110);
+        assert_eq!(pool.insert(replacement.clone()), Ok(Some(original.clone())));
+        assert_eq!(pool.len(), 1);
+        assert!(!pool.contains(&original.get_hash()));
+        assert!(pool.contains(&replacement.get_hash()));
+
+        let config = TransactionPoolConfig {
+            replacement_policy: ReplacementPolicy::Never,
+            ..Default::default()
+        };
+        let storage = Arc::new(create_memory_db());
+        let mut pool = TransactionPool::new(NoncePolicy::Increasing, config, storage);
+        pool.insert(original).unwrap();
+        assert!(pool.insert(replacement).is_err());
+    }
+
+    #[test]
+    fn test_pool_persistence() {
+        let storage: Arc<Storage> = Arc::new(create_memory_db());
+        let config = TransactionPoolConfig { persist: true, ..Default::default() };
+        let mut pool =
+            TransactionPool::new(NoncePolicy::Increasing, config.clone(), storage.clone());
+        for nonce in 1..4 {
+            pool.insert(transaction("alice.near", nonce, 1)).unwrap();
+        }
+        pool.insert(transaction_with_tip("alice.near", 3, 1, 10)).unwrap();
+        pool.remove(&"alice.near".to_string(), 2);
+
+        let mut restarted = TransactionPool::new(NoncePolicy::Increasing, config, storage.clone());
+        restarted.load(|t| t.body.get_nonce() > 1);
+        assert_eq!(restarted.len(), 1);
+        assert!(restarted.contains(&transaction_with_tip("alice.near", 3, 1, 10).get_hash()));
+        // Dropped transaction is also removed from the storage.
+        assert_eq!(storage.iter(COL_TRANSACTION_POOL).count(), 1);
+    }
+
+    #[test]
+    fn test_pool_limits() {
+        let config = TransactionPoolConfig { max_transactions_per_signer: 2, ..Default::default() };
+        let mut pool = new_pool_with_config(config);
+        pool.insert(transaction("alice.near", 1, 1)).unwrap();
+        pool.insert(transaction("alice.near", 2, 1)).unwrap();
+        assert_eq!(
+            pool.insert(transaction("alice.near", 3, 1)),
+            Err(PoolError::TooManyTransactions { signer: "alice.near".to_string(), limit: 2 })
+        );
+        // Replacing a pending transaction doesn't count towards the limit.
+        assert!(pool.insert(transaction_with_tip("alice.near", 2, 1, 10)).is_ok());
+
+        let alice = transaction_with_tip("alice.near", 1, 1, 5);
+        let bob = transaction_with_tip("bob.near", 1, 1, 1);
+        let carol = transaction_with_tip("carol.near", 1, 1, 3);
+        let config = TransactionPoolConfig {
+            max_size: alice.get_size() + carol.get_size(),
+            ..Default::default()
+        };
+        let mut pool = new_pool_with_config(config);
+        pool.insert(alice.clone()).unwrap();
+        pool.insert(bob.clone()).unwrap();
+        pool.insert(carol.clone()).unwrap();
+        // Bob pays the lowest tip and is evicted to make room.
+        assert!(!pool.contains(&bob.get_hash()));
+        assert_eq!(pool.size(), alice.get_size() + carol.get_size());
+        assert_eq!(
+            pool.insert(transaction_with_tip("dave.near", 1, 1, 0)),
+            Err(PoolError::PoolFull { tip: 0 })
+        );
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn test_pool_full_keeps_replaced() {
+        let alice = transaction_with_tip("alice.near", 1, 1, 100);
+        let bob = transaction_with_tip("bob.near", 1, 1, 1);
+        let max_size = alice.get_size() + bob.get_size();
+        let storage: Arc<Storage> = Arc::new(create_memory_db());
+        let config = TransactionPoolConfig { persist: true, max_size, ..Default::default() };
+        let mut pool = TransactionPool::new(NoncePolicy::Increasing, config, storage.clone());
+        pool.insert(alice.clone()).unwrap();
+        pool.insert(bob.clone()).unwrap();
+        // The replacement doesn't fit even with bob evicted.
+        let replacement = signed_transaction(TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce: 1,
+            originator: "alice.near".to_string(),
+            receiver: "bob.near".to_string(),
+            amount: 1,
+            block_hash: CryptoHash::default(),
+            genesis_hash: CryptoHash::default(),
+            execute_at: 0,
+            tip: 110,
+            memo: vec![0; max_size],
+        }));
+        assert_eq!(pool.insert(replacement), Err(PoolError::PoolFull { tip: 110 }));
+        assert!(pool.contains(&alice.get_hash()));
+        assert!(pool.contains(&bob.get_hash()));
+        assert_eq!(pool.size(), max_size);
+        assert_eq!(storage.iter(COL_TRANSACTION_POOL).count(), 2);
+    }
+
+    #[test]
+    fn test_pool_inspection() {
+        let mut pool = new_pool(NoncePolicy::Increasing);
+        let transactions = vec![
+            transaction_with_tip("alice.near", 2, 1, 3),
+            transaction_with_tip("alice.near", 1, 1, 5),
+            transaction_with_tip("bob.near", 1, 1, 0),
+        ];
+        for transaction in transactions.iter() {
+            pool.insert(transaction.clone()).unwrap();
+        }
+        let stats = pool.stats();
+        assert_eq!(stats.num_transactions, 3);
+        assert_eq!(stats.num_signers, 2);
+        assert_eq!(stats.size, transactions.iter().map(SignedTransaction::get_size).sum());
+        let alice = "alice.near".to_string();
+        let pending = pool.pending(Some(&alice));
+        assert_eq!(pending.len(), 1);
+        let alice_pending: Vec<_> =
+            pending[&alice].iter().map(|info| (info.hash, info.nonce, info.tip)).collect();
+        assert_eq!(
+            alice_pending,
+            vec![(transactions[1].get_hash(), 1, 5), (transactions[0].get_hash(), 2, 3)]
+        );
+        assert_eq!(pool.pending(None).len(), 2);
+    }
+
+    #[test]
+    fn test_pool_next_nonce() {
+        let mut pool = new_pool(NoncePolicy::StrictIncrement);
+        let alice = "alice.near".to_string();
+        assert_eq!(pool.next_nonce(&alice, 3), 4);
+        for nonce in &[4, 5, 7] {
+            pool.insert(transaction("alice.near", *nonce, 1)).unwrap();
+        }
+        assert_eq!(pool.next_nonce(&alice, 3), 6);
+        assert_eq!(pool.next_nonce(&alice, 5), 6);
+        assert_eq!(pool.next_nonce(&alice, 10), 11);
+        assert_eq!(pool.next_nonce(&"bob.near".to_string(), 0), 1);
+    }
+
+    #[test]
+    fn test_pool_rebroadcast() {
+        let interval = Duration::from_secs(5);
+        let mut pool = new_pool_with_config(TransactionPoolConfig {
+            rebroadcast_interval: interval,
+            max_rebroadcast_age: Duration::from_secs(60),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let alice = transaction("alice.near", 1, 1);
+        pool.insert(alice.clone()).unwrap();
+        assert!(pool.take_rebroadcast(start).is_empty());
+        assert_eq!(pool.take_rebroadcast(start + interval * 2), vec![alice.clone()]);
+        // The delay before the next rebroadcast is doubled.
+        assert!(pool.take_rebroadcast(start + interval * 3).is_empty());
+        assert_eq!(pool.take_rebroadcast(start + interval * 4), vec![alice.clone()]);
+        assert!(pool.take_rebroadcast(start + Duration::from_secs(120)).is_empty());
+    }
+
+    #[test]
+    fn test_pool_remove_expired() {
+        let mut pool = new_pool(NoncePolicy::Increasing);
+        let alice = transaction("alice.near", 1, 1);
+        let bob = transaction("bob.near", 1, 1);
+        pool.insert(alice.clone()).unwrap();
+        pool.insert(bob.clone()).unwrap();
+        let bob_hash = bob.get_hash();
+        assert_eq!(pool.remove_expired(|t| t.get_hash() == bob_hash), vec![bob.clone()]);
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.size(), alice.get_size());
+        // Once removed, the transaction can be added again.
+        assert!(pool.insert(bob).is_ok());
+    }
+
+    #[test]
+    fn test_pool_prepare_maximizes_tips() {
+        let mut pool = new_pool_with_config(TransactionPoolConfig {
+            max_block_transactions: 4,
+            ..Default::default()
+        });
+        let pending = [
+            // A high tip that pays for the transaction before it.
+            ("alice.near", 1, 0),
+            ("alice.near", 2, 100),
+            // A high tip followed by a long run of transactions without tips.
+            ("eve.near", 1, 60),
+            ("eve.near", 2, 0),
+            ("eve.near", 3, 0),
+            ("eve.near", 4, 0),
+            ("bob.near", 1, 10),
+            ("bob.near", 2, 10),
+            ("carol.near", 1, 10),
+        ];
+        for (originator, nonce, tip) in pending.iter().rev() {
+            pool.insert(transaction_with_tip(originator, *nonce, 1, *tip)).unwrap();
+        }
+        let prepared = pool.prepare_transactions(|_| Some(0));
+        assert_eq!(
+            nonces(&prepared),
+            vec![
+                ("eve.near".to_string(), 1),
+                ("alice.near".to_string(), 1),
+                ("alice.near".to_string(), 2),
+                ("bob.near".to_string(), 1),
+            ]
+        );
+        let tips: Balance = prepared.iter().map(|t| t.body.get_tip()).sum();
+        assert_eq!(tips, 170);
+
+        // Only whole transactions that fit into the block size are selected.
+        let size = transaction_with_tip("bob.near", 1, 1, 10).get_size();
+        let mut pool = new_pool_with_config(TransactionPoolConfig {
+            max_block_size: size * 3 / 2,
+            ..Default::default()
+        });
+        pool.insert(transaction_with_tip("alice.near", 1, 1, 5)).unwrap();
+        pool.insert(transaction_with_tip("bob.near", 1, 1, 10)).unwrap();
+        let prepared = pool.prepare_transactions(|_| Some(0));
+        assert_eq!(nonces(&prepared), vec![("bob.near".to_string(), 1)]);
+    }
+}
@@ -1,15 +1,18 @@
 //! Starts TestNet either from args or the provided configs.
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
 use std::sync::Arc;
 
-use futures::future;
 use futures::sync::mpsc::{channel, Receiver, Sender};
+use futures::{future, Future, Sink, Stream};
 use parking_lot::Mutex;
 
-use beacon::types::SignedBeaconBlock;
+use beacon::types::{Endorsement, SignedBeaconBlock};
 use client::Client;
 use configs::{get_testnet_configs, ClientConfig, NetworkConfig, RPCConfig};
 use consensus::adapters::transaction_to_payload;
+use network::noise::NodeKey;
+use network::peer_store::PEER_STORE_FILE;
 use network::protocol::{Protocol, ProtocolConfig};
 use primitives::types::{AccountId, Gossip};
 use chain::{SignedShardBlock, ChainPayload, ReceiptBlock};
@@ -27,7 +30,31 @@ fn start_from_configs(client_cfg: ClientConfig, network_cfg: NetworkConfig, rpc_
         // TODO: TxFlow should be listening on these transactions.
         let (transactions_tx, transactions_rx) = channel(1024);
         let (receipts_tx, receipts_rx) = channel(1024);
-        spawn_rpc_server_task(transactions_tx.clone(), &rpc_cfg, client.clone());
+        // Transactions submitted to the node are also gossiped to the peers.
+        let (submitted_transactions_tx, submitted_transactions_rx) = channel(1024);
+        let (outgoing_transactions_tx, outgoing_transactions_rx) = channel(1024);
+        spawn_fanout(submitted_transactions_rx, transactions_tx.clone(), outgoing_transactions_tx);
+        spawn_rpc_server_task(submitted_transactions_tx.clone(), &rpc_cfg, client.clone());
+        coroutines::rebroadcaster::spawn_rebroadcaster(client.clone(), submitted_transactions_tx);
+        if let Some(db_url) = client_cfg.explorer_db_url.clone() {
+            #[cfg(feature = "postgres-exporter")]
+            indexer::exporter::spawn_exporter(client.clone(), db_url);
+            #[cfg(not(feature = "postgres-exporter"))]
+            panic!("Node is built without the postgres-exporter feature to export to {}", db_url);
+        }
+        if let Some(url) = client_cfg.event_bus_url.clone() {
+            let publisher =
+                indexer::bus::connect(&url).expect("Failed to connect to the event bus");
+            let high_water_mark = indexer::bus::HighWaterMark::new(
+                client_cfg.base_path.join(indexer::bus::HIGH_WATER_MARK_PATH),
+            );
+            indexer::bus::spawn_publisher(
+                client.clone(),
+                publisher,
+                client_cfg.event_bus_topic_prefix.clone(),
+                high_water_mark,
+            );
+        }
 
         let (consensus_control_tx, consensus_control_rx) = channel(1024);
 
@@ -36,13 +63,19 @@ fn start_from_configs(client_cfg: ClientConfig, network_cfg: NetworkConfig, rpc_
         let (beacon_block_consensus_body_tx, beacon_block_consensus_body_rx) = channel(1024);
         let (outgoing_block_tx, outgoing_block_rx) = channel(1024);
         // Block producer is also responsible for re-submitting receipts from the previous block
-        // into the next block.
+        // into the next block. They are also gossiped to the peers tracking their shards.
+        let (produced_receipts_tx, produced_receipts_rx) = channel(1024);
+        let (outgoing_receipts_tx, outgoing_receipts_rx) = channel(1024);
+        spawn_fanout(produced_receipts_rx, receipts_tx.clone(), outgoing_receipts_tx);
+        // The producer endorses the blocks it adopts, the endorsements are gossiped to the peers.
+        let (outgoing_endorsements_tx, outgoing_endorsements_rx) = channel(1024);
         coroutines::producer::spawn_block_producer(
             client.clone(),
             beacon_block_consensus_body_rx,
             outgoing_block_tx,
-            receipts_tx.clone(),
+            produced_receipts_tx,
             consensus_control_tx,
+            outgoing_endorsements_tx,
         );
 
         // Create task that can import beacon chain blocks from other peers.
@@ -58,11 +91,15 @@ fn start_from_configs(client_cfg: ClientConfig, network_cfg: NetworkConfig, rpc_
             client_cfg.account_id,
             network_cfg,
             client.clone(),
+            transactions_tx.clone(),
             receipts_tx.clone(),
             inc_gossip_tx.clone(),
             out_gossip_rx,
             incoming_block_tx,
             outgoing_block_rx,
+            outgoing_transactions_rx,
+            outgoing_receipts_rx,
+            outgoing_endorsements_rx,
         );
 
         // Spawn consensus tasks.
@@ -88,12 +125,28 @@ fn start_from_configs(client_cfg: ClientConfig, network_cfg: NetworkConfig, rpc_
     }));
 }
 
+/// Sends everything the receiver receives into both senders.
+fn spawn_fanout<T: Clone + Send + 'static>(
+    receiver: Receiver<T>,
+    sender1: Sender<T>,
+    sender2: Sender<T>,
+) {
+    let task = receiver
+        .forward(sender1.fanout(sender2).sink_map_err(|_| ()))
+        .map(|_| ());
+    tokio::spawn(task);
+}
+
 fn spawn_rpc_server_task(
     transactions_tx: Sender<SignedTransaction>,
     rpc_config: &RPCConfig,
     client: Arc<Client>,
 ) {
     let http_addr = Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), rpc_config.rpc_port));
+    if let Some(grpc_port) = rpc_config.grpc_port {
+        let grpc_api = node_http::api::HttpApi::new(client.clone(), transactions_tx.clone());
+        node_grpc::spawn_grpc_server(grpc_api, grpc_port);
+    }
     let http_api = node_http::api::HttpApi::new(client, transactions_tx);
     node_http::server::spawn_server(http_api, http_addr);
 }
@@ -102,29 +155,49 @@ fn spawn_network_tasks(
     account_id: AccountId,
     network_cfg: NetworkConfig,
     client: Arc<Client>,
-    transactions_tx: Sender<ReceiptBlock>,
+    transactions_tx: Sender<SignedTransaction>,
+    receipts_tx: Sender<ReceiptBlock>,
     inc_gossip_tx: Sender<Gossip<ChainPayload>>,
     out_gossip_rx: Receiver<Gossip<ChainPayload>>,
     incoming_block_tx: Sender<(SignedBeaconBlock, SignedShardBlock)>,
     outgoing_block_rx: Receiver<(SignedBeaconBlock, SignedShardBlock)>,
+    outgoing_transactions_rx: Receiver<SignedTransaction>,
+    outgoing_receipts_rx: Receiver<ReceiptBlock>,
+    outgoing_endorsements_rx: Receiver<Endorsement>,
 ) {
     let (net_messages_tx, net_messages_rx) = channel(1024);
-    let protocol_config = ProtocolConfig::new_with_default_id(Some(account_id));
+    let mut protocol_config = ProtocolConfig::new_with_default_id(Some(account_id));
+    protocol_config.peer_store_path = network_cfg
+        .service
+        .net_config_path
+        .as_ref()
+        .map(|path| Path::new(path).join(PEER_STORE_FILE));
+    protocol_config.target_peers = network_cfg.service.out_peers as usize;
+    protocol_config.node_key = NodeKey::load_or_generate(&network_cfg.node_key_path)
+        .unwrap_or_else(|e| {
+            panic!("Cannot load the node key {}: {}", network_cfg.node_key_path.display(), e)
+        });
+    protocol_config.port_mapping = network_cfg.port_mapping;
     let protocol = Protocol::new(
         protocol_config.clone(),
         client,
         incoming_block_tx,
         transactions_tx,
+        receipts_tx,
         net_messages_tx.clone(),
         inc_gossip_tx,
     );
 
-    let network_service = network::service::new_network_service(&protocol_config, network_cfg);
+    let network_service =
+        network::service::new_network_service(&protocol_config, network_cfg.service);
     network::service::spawn_network_tasks(
         Arc::new(Mutex::new(network_service)),
         protocol,
         net_messages_rx,
         outgoing_block_rx,
         out_gossip_rx,
+        outgoing_transactions_rx,
+        outgoing_receipts_rx,
+        outgoing_endorsements_rx,
     );
 }
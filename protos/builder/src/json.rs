@@ -0,0 +1,490 @@
+//! Generates the serde impls of the messages and enums of a proto file, with the proto3 JSON
+//! mapping: fields are named in lowerCamelCase, 64-bit integers are strings, bytes are base64,
+//! enums are the names of their values and fields with default values are left out. Parsing also
+//! accepts the names of the fields in the proto file. The impls use `near_protos::json`.
+//!
+//! Only the subset of proto3 the protos use is parsed: top-level messages and enums, with scalar,
+//! message, enum and repeated fields, and oneofs. Maps and nested definitions are not supported.
+#[derive(Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number,
+    Str(String),
+    Symbol(char),
+}
+
+fn tokenize(proto: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = proto.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '/' {
+            chars.next();
+            match chars.next() {
+                Some('/') => {
+                    while chars.peek().map_or(false, |&c| c != '\n') {
+                        chars.next();
+                    }
+                }
+                Some('*') => {
+                    let mut last = ' ';
+                    while let Some(c) = chars.next() {
+                        if last == '*' && c == '/' {
+                            break;
+                        }
+                        last = c;
+                    }
+                }
+                _ => panic!("unexpected / in proto"),
+            }
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut s = String::new();
+            while let Some(next) = chars.next() {
+                if next == c {
+                    break;
+                }
+                s.push(next);
+            }
+            tokens.push(Token::Str(s));
+        } else if c.is_ascii_digit() || c == '-' {
+            while chars.peek().map_or(false, |&c| c.is_ascii_alphanumeric() || c == '-') {
+                chars.next();
+            }
+            tokens.push(Token::Number);
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let mut s = String::new();
+            let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+            while chars.peek().map_or(false, |&c| is_ident(c)) {
+                s.push(chars.next().unwrap());
+            }
+            tokens.push(Token::Ident(s));
+        } else {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        }
+    }
+    tokens
+}
+
+#[derive(Debug, PartialEq)]
+struct Field {
+    name: String,
+}
+
+#[derive(Debug, PartialEq)]
+struct Oneof {
+    name: String,
+    fields: Vec<Field>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Message {
+    name: String,
+    fields: Vec<Field>,
+    oneofs: Vec<Oneof>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Enum {
+    name: String,
+    values: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Default)]
+struct ProtoFile {
+    imports: Vec<String>,
+    messages: Vec<Message>,
+    enums: Vec<Enum>,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> &Token {
+        let token = self.tokens.get(self.position).expect("unexpected end of proto");
+        self.position += 1;
+        token
+    }
+
+    fn ident(&mut self) -> String {
+        match self.next() {
+            Token::Ident(ident) => ident.clone(),
+            token => panic!("expected identifier in proto, found {:?}", token),
+        }
+    }
+
+    fn expect(&mut self, symbol: char) {
+        let token = self.next();
+        if *token != Token::Symbol(symbol) {
+            panic!("expected {} in proto, found {:?}", symbol, token);
+        }
+    }
+
+    fn peek_symbol(&self, symbol: char) -> bool {
+        self.tokens.get(self.position) == Some(&Token::Symbol(symbol))
+    }
+
+    fn skip_statement(&mut self) {
+        while *self.next() != Token::Symbol(';') {}
+    }
+
+    fn skip_block(&mut self) {
+        while *self.next() != Token::Symbol('{') {}
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Token::Symbol('{') => depth += 1,
+                Token::Symbol('}') => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// Field of a message or a oneof, after its type or the `repeated` label. The mapping of the
+    /// value follows from the type of the generated field, so only the name is kept.
+    fn field(&mut self, first: String) -> Field {
+        let type_name = if first == "repeated" { self.ident() } else { first };
+        if type_name == "map" {
+            panic!("map fields are not supported");
+        }
+        let name = self.ident();
+        self.expect('=');
+        self.next();
+        if self.peek_symbol('[') {
+            while *self.next() != Token::Symbol(']') {}
+        }
+        self.expect(';');
+        Field { name }
+    }
+
+    fn message(&mut self) -> Message {
+        let name = self.ident();
+        self.expect('{');
+        let mut message = Message { name, fields: vec![], oneofs: vec![] };
+        while !self.peek_symbol('}') {
+            let first = self.ident();
+            match first.as_str() {
+                "oneof" => {
+                    let name = self.ident();
+                    self.expect('{');
+                    let mut fields = vec![];
+                    while !self.peek_symbol('}') {
+                        let first = self.ident();
+                        fields.push(self.field(first));
+                    }
+                    self.expect('}');
+                    message.oneofs.push(Oneof { name, fields });
+                }
+                "message" | "enum" => panic!("nested definitions are not supported"),
+                "option" | "reserved" => self.skip_statement(),
+                _ => {
+                    let field = self.field(first);
+                    message.fields.push(field);
+                }
+            }
+        }
+        self.expect('}');
+        message
+    }
+
+    fn enumeration(&mut self) -> Enum {
+        let name = self.ident();
+        self.expect('{');
+        let mut values = vec![];
+        while !self.peek_symbol('}') {
+            let value = self.ident();
+            if value == "option" || value == "reserved" {
+                self.skip_statement();
+                continue;
+            }
+            values.push(value);
+            self.skip_statement();
+        }
+        self.expect('}');
+        Enum { name, values }
+    }
+
+    fn file(&mut self) -> ProtoFile {
+        let mut file = ProtoFile::default();
+        while self.position < self.tokens.len() {
+            match self.ident().as_str() {
+                "message" => file.messages.push(self.message()),
+                "enum" => file.enums.push(self.enumeration()),
+                "service" => self.skip_block(),
+                "import" => {
+                    if let Token::Str(path) = self.next() {
+                        file.imports.push(path.clone());
+                    }
+                    self.skip_statement();
+                }
+                _ => self.skip_statement(),
+            }
+        }
+        file
+    }
+}
+
+/// Name of the field in the JSON mapping, as protoc derives it.
+fn json_name(name: &str) -> String {
+    let mut json_name = String::new();
+    let mut capitalize = false;
+    for c in name.chars() {
+        if c == '_' {
+            capitalize = true;
+        } else if capitalize {
+            json_name.extend(c.to_uppercase());
+            capitalize = false;
+        } else {
+            json_name.push(c);
+        }
+    }
+    json_name
+}
+
+/// Pattern of the keys of the field when parsing.
+fn key_pattern(name: &str) -> String {
+    let json_name = json_name(name);
+    if json_name == name {
+        format!("\"{}\"", name)
+    } else {
+        format!("\"{}\" | \"{}\"", json_name, name)
+    }
+}
+
+/// Name of the module generated from the proto file.
+fn module_name(path: &str) -> String {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    file_name.trim_end_matches(".proto").to_string()
+}
+
+const MESSAGE_TEMPLATE: &str = r#"impl JsonValue for $name {
+    fn is_default(&self) -> bool {
+        false
+    }
+
+    fn serialize_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+$serialize_fields        map.end()
+    }
+
+    fn deserialize_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        const FIELDS: &[&str] = &[$field_names];
+
+        struct MessageVisitor;
+
+        impl<'de> Visitor<'de> for MessageVisitor {
+            type Value = $name;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "message $name")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<$name, A::Error> {
+                let mut message = $name::new();
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+$deserialize_fields                        _ => return Err(de::Error::unknown_field(&key, FIELDS)),
+                    }
+                }
+                Ok(message)
+            }
+        }
+
+        deserializer.deserialize_map(MessageVisitor)
+    }
+}
+
+"#;
+
+const FIELD_SERIALIZE_TEMPLATE: &str = r#"if !self.$field.is_default() {
+    map.serialize_entry("$json_name", &Json(&self.$field))?;
+}
+"#;
+
+const FIELD_DESERIALIZE_TEMPLATE: &str =
+    r#"$keys => message.$field = map.next_value::<FromJson<_>>()?.0,
+"#;
+
+const ONEOF_SERIALIZE_TEMPLATE: &str = r#"match self.$oneof {
+$members    None => {}
+}
+"#;
+
+const ONEOF_MEMBER_SERIALIZE_TEMPLATE: &str =
+    r#"Some($oneof_type::$field(ref value)) => map.serialize_entry("$json_name", &Json(value))?,
+"#;
+
+const ONEOF_DESERIALIZE_TEMPLATE: &str = r#"$keys => {
+    let value = map.next_value::<FromJson<_>>()?.0;
+    message.$oneof = Some($oneof_type::$field(value));
+}
+"#;
+
+const ENUM_TEMPLATE: &str = r#"impl JsonValue for $name {
+    fn is_default(&self) -> bool {
+        *self == $name::$default
+    }
+
+    fn serialize_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_enum(self, VALUES_$name, serializer)
+    }
+
+    fn deserialize_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_enum(VALUES_$name, deserializer)
+    }
+}
+
+const VALUES_$name: &[(&str, $name)] = &[$values];
+
+"#;
+
+/// Serde impls of the types of the module generated by rust-protobuf, delegating to the JSON
+/// mapping.
+const SERDE_TEMPLATE: &str = r#"impl Serialize for $name {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.serialize_json(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for $name {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::deserialize_json(deserializer)
+    }
+}
+
+"#;
+
+const HEADER_TEMPLATE: &str = r#"// This file is generated by protos-autogen from $path. Do not edit
+// @generated
+
+#![cfg_attr(rustfmt, rustfmt_skip)]
+
+#![allow(non_upper_case_globals)]
+#![allow(unused_imports)]
+
+use std::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::json::{deserialize_enum, serialize_enum, FromJson, Json, JsonValue};
+$imports
+"#;
+
+/// Indents the lines of the code by the number of spaces.
+fn indent(code: &str, spaces: usize) -> String {
+    code.lines().map(|line| format!("{:1$}{2}\n", "", spaces, line)).collect()
+}
+
+fn write_message(out: &mut String, message: &Message) {
+    let mut fields: Vec<&Field> = message.fields.iter().collect();
+    let mut serialize_fields = String::new();
+    let mut deserialize_fields = String::new();
+    for field in &message.fields {
+        serialize_fields += &FIELD_SERIALIZE_TEMPLATE
+            .replace("$json_name", &json_name(&field.name))
+            .replace("$field", &field.name);
+        deserialize_fields += &FIELD_DESERIALIZE_TEMPLATE
+            .replace("$keys", &key_pattern(&field.name))
+            .replace("$field", &field.name);
+    }
+    for oneof in &message.oneofs {
+        let oneof_type = format!("{}_oneof_{}", message.name, oneof.name);
+        let mut members = String::new();
+        for field in &oneof.fields {
+            fields.push(field);
+            members += &ONEOF_MEMBER_SERIALIZE_TEMPLATE
+                .replace("$oneof_type", &oneof_type)
+                .replace("$json_name", &json_name(&field.name))
+                .replace("$field", &field.name);
+            deserialize_fields += &ONEOF_DESERIALIZE_TEMPLATE
+                .replace("$keys", &key_pattern(&field.name))
+                .replace("$oneof_type", &oneof_type)
+                .replace("$oneof", &oneof.name)
+                .replace("$field", &field.name);
+        }
+        serialize_fields += &ONEOF_SERIALIZE_TEMPLATE
+            .replace("$members", &indent(&members, 4))
+            .replace("$oneof", &oneof.name);
+    }
+    let field_names: Vec<String> =
+        fields.iter().map(|field| format!("\"{}\"", json_name(&field.name))).collect();
+    *out += &MESSAGE_TEMPLATE
+        .replace("$serialize_fields", &indent(&serialize_fields, 8))
+        .replace("$deserialize_fields", &indent(&deserialize_fields, 24))
+        .replace("$field_names", &field_names.join(", "))
+        .replace("$name", &message.name);
+    *out += &SERDE_TEMPLATE.replace("$name", &message.name);
+}
+
+fn write_enum(out: &mut String, enumeration: &Enum) {
+    let values: Vec<String> = enumeration
+        .values
+        .iter()
+        .map(|value| format!("(\"{0}\", {1}::{0})", value, enumeration.name))
+        .collect();
+    *out += &ENUM_TEMPLATE
+        .replace("$default", &enumeration.values[0])
+        .replace("$values", &values.join(", "))
+        .replace("$name", &enumeration.name);
+    *out += &SERDE_TEMPLATE.replace("$name", &enumeration.name);
+}
+
+/// Code of the `<name>_json.rs` module of the proto file at the path.
+pub fn generate(path: &str, proto: &str) -> String {
+    let file = Parser { tokens: tokenize(proto), position: 0 }.file();
+    let mut imports = format!("use super::{}::*;\n", module_name(path));
+    for import in &file.imports {
+        imports += &format!("use super::{}::*;\n", module_name(import));
+    }
+    let mut out = HEADER_TEMPLATE.replace("$path", path).replace("$imports", &imports);
+    for message in &file.messages {
+        write_message(&mut out, message);
+    }
+    for enumeration in &file.enums {
+        write_enum(&mut out, enumeration);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let proto = r#"
+            syntax = "proto3";
+            // Comment.
+            enum Status { UNKNOWN = 0; DONE = 1; }
+            message Call {
+                uint64 gas_used = 1;
+                repeated bytes args = 2 [packed = true];
+                oneof optional_result { bytes result = 3; }
+            }
+            service Api { rpc Get(Call) returns (Call); }
+        "#;
+        let file = Parser { tokens: tokenize(proto), position: 0 }.file();
+        assert_eq!(file.enums, vec![Enum {
+            name: "Status".to_string(),
+            values: vec!["UNKNOWN".to_string(), "DONE".to_string()],
+        }]);
+        let field = |name: &str| Field { name: name.to_string() };
+        assert_eq!(file.messages, vec![Message {
+            name: "Call".to_string(),
+            fields: vec![field("gas_used"), field("args")],
+            oneofs: vec![Oneof {
+                name: "optional_result".to_string(),
+                fields: vec![field("result")],
+            }],
+        }]);
+        assert_eq!(json_name("gas_used"), "gasUsed");
+        assert_eq!(key_pattern("gas_used"), "\"gasUsed\" | \"gas_used\"");
+        assert_eq!(module_name("protos/protos/receipt.proto"), "receipt");
+    }
+}
@@ -3,11 +3,64 @@ use std::path::Path;
 
 use protoc_rust::Customize;
 
+mod json;
+
+const PROTO_INPUT_DIR: &str = "protos/protos";
 const PROTO_OUTPUT_DIR: &str = "core/protos/src/autogenerated";
+/// Protos that define gRPC services, relative to `protos/protos`.
+const GRPC_PROTOS: &[&str] = &["node_api.proto"];
+/// Descriptors of the protos, for the tools that read them instead of the proto files.
+const DESCRIPTOR_SET_FILE: &str = "core/protos/src/autogenerated/descriptor_set.pb";
+/// Digest of the inputs of the generated code, kept next to it.
+const DIGEST_FILE: &str = "core/protos/src/autogenerated/protos.digest";
+/// Options the code is generated with, part of the digest. Update it when changing them.
+const OPTIONS: &str = "includes=protos;expose_oneof=true";
+
+/// FNV-1a, which is stable across toolchains unlike the hasher of the standard library.
+fn fnv1a(digest: u64, bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(digest, |digest, byte| (digest ^ u64::from(*byte)).wrapping_mul(0x1000_0000_01b3))
+}
+
+/// Digest of the protos, the options and the generator of the JSON mapping.
+fn inputs_digest(input_files: &[String]) -> String {
+    let mut digest = fnv1a(0xcbf2_9ce4_8422_2325, OPTIONS.as_bytes());
+    digest = fnv1a(digest, include_str!("json.rs").as_bytes());
+    for grpc_proto in GRPC_PROTOS {
+        digest = fnv1a(digest, grpc_proto.as_bytes());
+    }
+    for input_file in input_files {
+        let contents = fs::read(input_file).expect("could not read proto");
+        digest = fnv1a(digest, input_file.as_bytes());
+        digest = fnv1a(digest, &(contents.len() as u64).to_le_bytes());
+        digest = fnv1a(digest, &contents);
+    }
+    format!("{:016x}\n", digest)
+}
+
+/// Whether the code generated from the protos is there and has the digest of the inputs.
+fn is_up_to_date(input_files: &[String], digest: &str) -> bool {
+    let outputs_exist = input_files.iter().all(|input_file| {
+        let stem = Path::new(input_file).file_stem().expect("proto has a name");
+        let json_file_name = format!("{}_json.rs", stem.to_string_lossy());
+        Path::new(PROTO_OUTPUT_DIR).join(stem).with_extension("rs").exists()
+            && Path::new(PROTO_OUTPUT_DIR).join(json_file_name).exists()
+    }) && GRPC_PROTOS.iter().all(|grpc_proto| {
+        let stem = Path::new(grpc_proto).file_stem().expect("proto has a name");
+        let file_name = format!("{}_grpc.rs", stem.to_string_lossy());
+        Path::new(PROTO_OUTPUT_DIR).join(file_name).exists()
+    });
+    outputs_exist
+        && Path::new(DESCRIPTOR_SET_FILE).exists()
+        && fs::read_to_string(DIGEST_FILE).map(|stored| stored == digest).unwrap_or(false)
+}
 
+/// Generates the code of the protos, unless the protos and the options haven't changed since it
+/// was generated, so that the crates that depend on it aren't rebuilt.
 pub fn autogenerate() {
     // dumb vector hack because https://bit.ly/2RJcIH1
-    let input_files: Vec<String> = fs::read_dir(Path::new("protos/protos"))
+    let mut input_files: Vec<String> = fs::read_dir(Path::new(PROTO_INPUT_DIR))
         .expect("could not read protos directory")
         .map(|dir_entry| {
             dir_entry.expect("unable to get entry")
@@ -16,16 +69,41 @@ pub fn autogenerate() {
                 .to_string()
         })
         .collect();
-    let input_files: Vec<&str> = input_files.iter()
+    input_files.sort();
+    let digest = inputs_digest(&input_files);
+    if is_up_to_date(&input_files, &digest) {
+        return;
+    }
+    // Generates `*_grpc.rs` service stubs. The messages are regenerated below with our settings.
+    protoc_grpcio::compile_grpc_protos(GRPC_PROTOS, &[PROTO_INPUT_DIR], PROTO_OUTPUT_DIR, None)
+        .expect("protoc-grpcio");
+    let input_paths: Vec<&str> = input_files.iter()
         .map(|x| x.as_ref())
         .collect();
     protoc_rust::run(protoc_rust::Args {
         out_dir: PROTO_OUTPUT_DIR,
-        input: input_files.as_slice(),
+        input: input_paths.as_slice(),
         includes: &["protos"],
         customize: Customize {
             expose_oneof: Some(true),
             ..Default::default()
         },
     }).expect("protoc");
+    protoc::Protoc::from_env_path()
+        .write_descriptor_set(protoc::DescriptorSetOutArgs {
+            out: DESCRIPTOR_SET_FILE,
+            includes: &["protos"],
+            input: input_paths.as_slice(),
+            include_imports: true,
+        })
+        .expect("protoc descriptor set");
+    // Serde impls with the proto3 JSON mapping, in `*_json.rs` next to the messages.
+    for input_file in &input_files {
+        let proto = fs::read_to_string(input_file).expect("could not read proto");
+        let stem = Path::new(input_file).file_stem().expect("proto has a name");
+        let output_file = format!("{}/{}_json.rs", PROTO_OUTPUT_DIR, stem.to_string_lossy());
+        fs::write(output_file, json::generate(input_file, &proto))
+            .expect("could not write the JSON mapping");
+    }
+    fs::write(DIGEST_FILE, digest).expect("could not write the digest of the protos");
 }
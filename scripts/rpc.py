@@ -99,29 +99,142 @@ def b58decode(s):
     return b'\x00' * pad + res
 
 
+# Tags of the key types in front of the keys in the transactions.
+KEY_TYPE_TAGS = {'ed25519': b'\x00', 'secp256k1': b'\x01'}
+
+
+def decode_public_key(s):
+    """Decodes the `<key type>:<base58>` public key into the bytes
+    prefixed with the tag of its type. Keys without the type are ed25519."""
+    key_type, _, data = s.rpartition(':')
+    if key_type not in KEY_TYPE_TAGS and key_type != '':
+        raise Exception("Unknown key type {}".format(key_type))
+    return KEY_TYPE_TAGS[key_type or 'ed25519'] + b58decode(data)
+
+
 def _get_account_id(account_alias):
     return account_alias
 
 
+def _keystore_args(keystore_binary):
+    if keystore_binary is not None:
+        return [keystore_binary]
+    return 'cargo run -p keystore --'.split()
+
+
+def _run_keystore(args):
+    null = open(os.devnull, 'w')
+    process = subprocess.Popen(args, stdout=subprocess.PIPE, stderr=null)
+    stdout = process.communicate()[0].decode('utf-8')
+    return process.returncode, stdout
+
+
+class KeystoreSigner(object):
+    """Signs the transactions with a key from the keystore"""
+
+    def __init__(self, keystore_binary=None, keystore_path=None, public_key=None):
+        self._keystore_binary = keystore_binary
+        self._keystore_path = keystore_path
+
+        # This may be None, use 'self.public_key' in order
+        # to check against the keystore
+        self._public_key = public_key
+
+    def sign(self, body):
+        m = hashlib.sha256()
+        m.update(body)
+        hashed = m.digest()
+        data = base64.b64encode(hashed)
+        args = _keystore_args(self._keystore_binary) + [
+            'sign',
+            '--data',
+            data,
+            '--keystore-path',
+            self._keystore_path,
+        ]
+
+        if self._public_key is not None:
+            args += ['--public-key', self._public_key]
+
+        returncode, stdout = _run_keystore(args)
+        if returncode != 0:
+            sys.stdout.write(stdout)
+            exit(1)
+
+        return base64.b64decode(stdout)
+
+    def public_key(self):
+        if self._public_key is None:
+            args = _keystore_args(self._keystore_binary)
+            args += ['get_public_key', '--keystore-path', self._keystore_path]
+
+            returncode, stdout = _run_keystore(args)
+            if returncode != 0:
+                sys.stdout.write(stdout)
+
+                if returncode == 3:
+                    _help = "To create, run:\ncargo run -p keystore " \
+                           "-- keygen -p {keystore_path}"
+                    print(_help.format(keystore_path=self._keystore_path))
+
+                exit(1)
+
+            self._public_key = stdout
+        return self._public_key
+
+
+class LedgerSigner(object):
+    """Signs the transactions on a Ledger device, the secret key never
+    leaves the device"""
+
+    def __init__(self, keystore_binary=None, hd_path=None):
+        self._keystore_binary = keystore_binary
+        self._hd_path = hd_path
+        self._public_key = None
+
+    def _args(self, command):
+        args = _keystore_args(self._keystore_binary) + [command]
+        if self._hd_path is not None:
+            args += ['--hd-path', self._hd_path]
+        return args
+
+    def sign(self, body):
+        # The device hashes the body itself, so that it can show
+        # the transaction to approve
+        args = self._args('ledger_sign') + ['--data', base64.b64encode(body)]
+        sys.stderr.write("Confirm the transaction on the Ledger\n")
+        returncode, stdout = _run_keystore(args)
+        if returncode != 0:
+            sys.stdout.write(stdout)
+            exit(1)
+
+        return base64.b64decode(stdout)
+
+    def public_key(self):
+        if self._public_key is None:
+            returncode, stdout = _run_keystore(self._args('ledger_get_public_key'))
+            if returncode != 0:
+                sys.stdout.write(stdout)
+                exit(1)
+
+            self._public_key = stdout
+        return self._public_key
+
+
 class NearRPC(object):
     def __init__(
             self,
             server_url,
+            signer=None,
             keystore_binary=None,
-            keystore_path=None,
-            public_key=None,
             debug=False,
     ):
         self._server_url = server_url
+        self._signer = signer
         self._keystore_binary = keystore_binary
-        self._keystore_path = keystore_path
         self._nonces = {}
         self._debug = debug
 
-        # This may be None, use 'self._get_public_key' in order
-        # to check against the keystore
-        self._public_key = public_key
-
     def _get_nonce(self, sender):
         if sender not in self._nonces:
             view_result = self.view_account(sender)
@@ -156,35 +269,12 @@ class NearRPC(object):
             exit(1)
 
     def _sign_transaction_body(self, body):
-        if self._keystore_binary is not None:
-            args = [self._keystore_binary]
-        else:
-            args = 'cargo run -p keystore --'.split()
-
-        body = body.SerializeToString()
-        m = hashlib.sha256()
-        m.update(body)
-        hashed = m.digest()
-        data = base64.b64encode(hashed)
-        args += [
-            'sign',
-            '--data',
-            data,
-            '--keystore-path',
-            self._keystore_path,
-        ]
-
-        if self._public_key is not None:
-            args += ['--public-key', self._public_key]
-
-        null = open(os.devnull, 'w')
-        process = subprocess.Popen(args, stdout=subprocess.PIPE, stderr=null)
-        stdout = process.communicate()[0].decode('utf-8')
-        if process.returncode != 0:
-            sys.stdout.write(stdout)
-            exit(1)
-
-        return base64.b64decode(stdout)
+        # The node rejects the transactions signed for another chain, and
+        # the ones that reference a block older than the validity period
+        status = self._call_rpc('status')
+        body.genesis_hash = b58decode(status['genesis_hash'])
+        body.block_hash = b58decode(status['sync_info']['latest_block_hash'])
+        return self._signer.sign(body.SerializeToString())
 
     def _submit_transaction(self, transaction):
         transaction = transaction.SerializeToString()
@@ -193,29 +283,7 @@ class NearRPC(object):
         return self._call_rpc('submit_transaction', params)
 
     def _get_public_key(self):
-        if self._public_key is None:
-            if self._keystore_binary is not None:
-                args = [self._keystore_binary]
-            else:
-                args = 'cargo run -p keystore --'.split()
-
-            args += ['get_public_key', '--keystore-path', self._keystore_path]
-
-            null = open(os.devnull, 'w')
-            process = subprocess.Popen(args, stdout=subprocess.PIPE, stderr=null)
-            stdout = process.communicate()[0]
-            if process.returncode != 0:
-                sys.stdout.write(stdout)
-
-                if process.returncode == 3:
-                    _help = "To create, run:\ncargo run -p keystore " \
-                           "-- keygen -p {keystore_path}"
-                    print(_help.format(keystore_path=self._keystore_path))
-
-                exit(1)
-
-            self._public_key = stdout.decode('utf-8')
-        return self._public_key
+        return self._signer.public_key()
 
     def deploy_contract(self, sender, contract_name, wasm_file):
         with open(wasm_file, 'rb') as f:
@@ -228,7 +296,10 @@ class NearRPC(object):
         deploy_contract.originator = _get_account_id(sender)
         deploy_contract.contract_id = _get_account_id(contract_name)
         deploy_contract.wasm_byte_array = wasm_byte_array
-        deploy_contract.public_key = b58decode(self._get_public_key())
+        # The key is only of the account that the deploy creates, an account redeploys its own
+        # contract without one.
+        if deploy_contract.contract_id != deploy_contract.originator:
+            deploy_contract.public_key = decode_public_key(self._get_public_key())
 
         signature = self._sign_transaction_body(deploy_contract)
 
@@ -257,13 +328,38 @@ class NearRPC(object):
         self._update_nonce(sender)
         return self._submit_transaction(signed_transaction)
 
-    def stake(self, sender, amount):
+    def _get_bls_proof_of_possession(self, bls_keystore_path):
+        args = _keystore_args(self._keystore_binary) + [
+            'bls_proof_of_possession',
+            '--keystore-path',
+            bls_keystore_path,
+        ]
+
+        returncode, stdout = _run_keystore(args)
+        if returncode != 0:
+            sys.stdout.write(stdout)
+
+            if returncode == 3:
+                _help = "To create, run:\ncargo run -p keystore " \
+                       "-- bls_keygen -p {keystore_path}"
+                print(_help.format(keystore_path=bls_keystore_path))
+
+            exit(1)
+
+        public_key, proof = stdout.split()
+        return base64.b64decode(public_key), base64.b64decode(proof)
+
+    def stake(self, sender, amount, bls_keystore_path):
         nonce = self._get_nonce(sender)
+        bls_public_key, bls_proof_of_possession = \
+            self._get_bls_proof_of_possession(bls_keystore_path)
 
         stake = signed_transaction_pb2.StakeTransaction()
         stake.nonce = nonce
         stake.originator = _get_account_id(sender)
         stake.amount = amount
+        stake.bls_public_key = bls_public_key
+        stake.bls_proof_of_possession = bls_proof_of_possession
 
         signature = self._sign_transaction_body(stake)
 
@@ -274,6 +370,27 @@ class NearRPC(object):
         self._update_nonce(sender)
         return self._submit_transaction(signed_transaction)
 
+    def rotate_key(self, sender, new_key, bls_keystore_path):
+        nonce = self._get_nonce(sender)
+        bls_public_key, bls_proof_of_possession = \
+            self._get_bls_proof_of_possession(bls_keystore_path)
+
+        rotate_key = signed_transaction_pb2.RotateKeyTransaction()
+        rotate_key.nonce = nonce
+        rotate_key.originator = _get_account_id(sender)
+        rotate_key.public_key = decode_public_key(new_key)
+        rotate_key.bls_public_key = bls_public_key
+        rotate_key.bls_proof_of_possession = bls_proof_of_possession
+
+        signature = self._sign_transaction_body(rotate_key)
+
+        signed_transaction = signed_transaction_pb2.SignedTransaction()
+        signed_transaction.rotate_key.CopyFrom(rotate_key)
+        signed_transaction.signature = signature
+
+        self._update_nonce(sender)
+        return self._submit_transaction(signed_transaction)
+
     def schedule_function_call(
         self,
         sender,
@@ -338,7 +455,7 @@ class NearRPC(object):
         create_account.originator = _get_account_id(sender)
         create_account.new_account_id = _get_account_id(account_alias)
         create_account.amount = amount
-        create_account.public_key = b58decode(account_public_key)
+        create_account.public_key = decode_public_key(account_public_key)
 
         signature = self._sign_transaction_body(create_account)
 
@@ -360,8 +477,8 @@ class NearRPC(object):
         swap_key = signed_transaction_pb2.SwapKeyTransaction()
         swap_key.nonce = nonce
         swap_key.originator = _get_account_id(account)
-        swap_key.cur_key = b58decode(current_key)
-        swap_key.new_key = b58decode(new_key)
+        swap_key.cur_key = decode_public_key(current_key)
+        swap_key.new_key = decode_public_key(new_key)
 
         signature = self._sign_transaction_body(swap_key)
 
@@ -413,6 +530,7 @@ schedule_function_call    {}
 view_account              {}
 view_state                {}
 stake                     {}
+rotate_key                {}
 create_account            {}
 swap_key                  {}
 view_latest_beacon_block  {}
@@ -427,6 +545,7 @@ get_beacon_block_by_hash  {}
                 self.view_account.__doc__,
                 self.view_state.__doc__,
                 self.stake.__doc__,
+                self.rotate_key.__doc__,
                 self.create_account.__doc__,
                 self.swap_key.__doc__,
                 self.view_latest_beacon_block.__doc__,
@@ -496,6 +615,17 @@ get_beacon_block_by_hash  {}
             type=str,
             help='public key for signing transactions',
         )
+        parser.add_argument(
+            '--ledger',
+            action="store_true",
+            default=False,
+            help='set to sign transactions on a Ledger device',
+        )
+        parser.add_argument(
+            '--hd-path',
+            type=str,
+            help='HD path of the key on the Ledger',
+        )
 
     @staticmethod
     def _get_command_args(parser):
@@ -508,11 +638,15 @@ get_beacon_block_by_hash  {}
         keystore_binary = getattr(command_args, 'keystore_binary', None)
         keystore_path = getattr(command_args, 'keystore_path', None)
         public_key = getattr(command_args, 'public_key', None)
+        if getattr(command_args, 'ledger', False):
+            hd_path = getattr(command_args, 'hd_path', None)
+            signer = LedgerSigner(keystore_binary, hd_path)
+        else:
+            signer = KeystoreSigner(keystore_binary, keystore_path, public_key)
         return NearRPC(
             command_args.server_url,
+            signer,
             keystore_binary,
-            keystore_path,
-            public_key,
             command_args.debug,
         )
 
@@ -646,9 +780,30 @@ get_beacon_block_by_hash  {}
             default=0,
             help='amount of money to stake',
         )
+        parser.add_argument(
+            '--bls-keystore-path',
+            type=str,
+            default='storage/keystore/',
+            help='location of keystore with the BLS key to sign blocks with',
+        )
+        args = self._get_command_args(parser)
+        client = self._get_rpc_client(args)
+        return client.stake(args.sender, args.amount, args.bls_keystore_path)
+
+    def rotate_key(self):
+        """Change the keys of a staked validator from the next epoch"""
+        parser = self._get_command_parser(self.rotate_key.__doc__)
+        self._add_transaction_args(parser)
+        parser.add_argument('new_key', type=str)
+        parser.add_argument(
+            '--bls-keystore-path',
+            type=str,
+            default='storage/keystore/',
+            help='location of keystore with the new BLS key to sign blocks with',
+        )
         args = self._get_command_args(parser)
         client = self._get_rpc_client(args)
-        return client.stake(args.sender, args.amount)
+        return client.rotate_key(args.sender, args.new_key, args.bls_keystore_path)
 
     def view_state(self):
         """View state of the contract."""
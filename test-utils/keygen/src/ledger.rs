@@ -0,0 +1,198 @@
+//! Signing with a Ledger device. The secret key is derived on the device and never leaves it: the
+//! device returns the public key and signs the SHA-256 hash of the transaction bodies it is sent,
+//! once the user approves them, which is what the keystore signs with a key on disk.
+//!
+//! The APDUs are exchanged over the HID interface of the device through hidapi.
+use std::io::{self, Error, ErrorKind};
+
+use hidapi::{HidApi, HidDevice, HidDeviceInfo, HidError};
+
+use primitives::hash::hash;
+use primitives::signature::{verify, PublicKey, Signature};
+
+const LEDGER_VENDOR_ID: u16 = 0x2c97;
+/// Vendor defined usage page of the APDU interface. Backends that don't report the usage page
+/// expose the APDU interface as the first one.
+const APDU_USAGE_PAGE: u16 = 0xffa0;
+/// How long to wait for the user to approve on the device, in milliseconds.
+const READ_TIMEOUT_MS: i32 = 5 * 60 * 1000;
+
+const PACKET_SIZE: usize = 64;
+const CHANNEL: u16 = 0x0101;
+const TAG_APDU: u8 = 0x05;
+
+const CLA: u8 = 0x80;
+const INS_SIGN: u8 = 0x02;
+const INS_GET_PUBLIC_KEY: u8 = 0x04;
+/// Set in P1 of the last chunk of the data to sign.
+const P1_LAST_CHUNK: u8 = 0x80;
+/// Max size of the data in an APDU.
+const CHUNK_SIZE: usize = 128;
+
+const SIGNATURE_SIZE: usize = 64;
+
+const SW_OK: u16 = 0x9000;
+const SW_REJECTED: u16 = 0x6985;
+
+fn hd_path_bytes(hd_path: &[u32]) -> Vec<u8> {
+    hd_path.iter().flat_map(|index| index.to_be_bytes().to_vec()).collect()
+}
+
+fn apdu(ins: u8, p1: u8, data: &[u8]) -> Vec<u8> {
+    let mut apdu = vec![CLA, ins, p1, 0, data.len() as u8];
+    apdu.extend_from_slice(data);
+    apdu
+}
+
+/// Splits the APDU into the HID packets, the first one prefixed with the size of the APDU.
+fn to_packets(apdu: &[u8]) -> Vec<[u8; PACKET_SIZE]> {
+    let mut data = (apdu.len() as u16).to_be_bytes().to_vec();
+    data.extend_from_slice(apdu);
+    data.chunks(PACKET_SIZE - 5)
+        .enumerate()
+        .map(|(sequence, chunk)| {
+            let mut packet = [0; PACKET_SIZE];
+            packet[..2].copy_from_slice(&CHANNEL.to_be_bytes());
+            packet[2] = TAG_APDU;
+            packet[3..5].copy_from_slice(&(sequence as u16).to_be_bytes());
+            packet[5..5 + chunk.len()].copy_from_slice(chunk);
+            packet
+        })
+        .collect()
+}
+
+/// Collects the response from the HID packets, returns None while more packets are expected.
+fn from_packets(packets: &[[u8; PACKET_SIZE]]) -> io::Result<Option<Vec<u8>>> {
+    let mut data = vec![];
+    for (sequence, packet) in packets.iter().enumerate() {
+        if packet[..2] != CHANNEL.to_be_bytes()
+            || packet[2] != TAG_APDU
+            || packet[3..5] != (sequence as u16).to_be_bytes()
+        {
+            return Err(Error::new(ErrorKind::InvalidData, "Unexpected packet from the Ledger"));
+        }
+        data.extend_from_slice(&packet[5..]);
+    }
+    if data.len() < 2 {
+        return Ok(None);
+    }
+    let size = usize::from(u16::from_be_bytes([data[0], data[1]]));
+    if data.len() < 2 + size {
+        return Ok(None);
+    }
+    Ok(Some(data[2..2 + size].to_vec()))
+}
+
+/// Splits the response into its data and checks the status word.
+fn check_status(mut response: Vec<u8>) -> io::Result<Vec<u8>> {
+    if response.len() < 2 {
+        return Err(Error::new(ErrorKind::InvalidData, "Response from the Ledger is too short"));
+    }
+    let status = response.split_off(response.len() - 2);
+    match u16::from_be_bytes([status[0], status[1]]) {
+        SW_OK => Ok(response),
+        SW_REJECTED => Err(Error::new(ErrorKind::PermissionDenied, "Rejected on the Ledger")),
+        status => Err(Error::new(
+            ErrorKind::Other,
+            format!("Ledger error {:#06x}, is the NEAR app open?", status),
+        )),
+    }
+}
+
+fn hid_error(e: HidError) -> Error {
+    Error::new(ErrorKind::Other, format!("HID error: {:?}", e))
+}
+
+fn is_ledger_apdu_interface(info: &HidDeviceInfo) -> bool {
+    info.vendor_id == LEDGER_VENDOR_ID
+        && (info.usage_page == APDU_USAGE_PAGE || info.interface_number == 0)
+}
+
+pub struct Ledger {
+    device: HidDevice,
+}
+
+impl Ledger {
+    /// Opens the first Ledger device connected.
+    pub fn open() -> io::Result<Self> {
+        let api = HidApi::new().map_err(hid_error)?;
+        let info = api
+            .devices()
+            .iter()
+            .find(|info| is_ledger_apdu_interface(info))
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "No Ledger device connected"))?;
+        let device = info.open_device(&api).map_err(hid_error)?;
+        Ok(Ledger { device })
+    }
+
+    fn exchange(&mut self, apdu: &[u8]) -> io::Result<Vec<u8>> {
+        for packet in to_packets(apdu) {
+            // Reports are prefixed with the report id, which is 0.
+            let mut report = vec![0];
+            report.extend_from_slice(&packet);
+            self.device.write(&report).map_err(hid_error)?;
+        }
+        let mut packets = vec![];
+        loop {
+            let mut packet = [0; PACKET_SIZE];
+            let size = self.device.read_timeout(&mut packet, READ_TIMEOUT_MS).map_err(hid_error)?;
+            if size == 0 {
+                return Err(Error::new(ErrorKind::TimedOut, "Ledger didn't answer"));
+            }
+            packets.push(packet);
+            if let Some(response) = from_packets(&packets)? {
+                return check_status(response);
+            }
+        }
+    }
+
+    /// Public key of the HD path.
+    pub fn get_public_key(&mut self, hd_path: &[u32]) -> io::Result<PublicKey> {
+        let response = self.exchange(&apdu(INS_GET_PUBLIC_KEY, 0, &hd_path_bytes(hd_path)))?;
+        PublicKey::new(&response).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Signs the transaction body with the key of the HD path, once the user approves it on the
+    /// device. The signature is checked against the public key of the device.
+    pub fn sign(&mut self, hd_path: &[u32], body: &[u8]) -> io::Result<Signature> {
+        let public_key = self.get_public_key(hd_path)?;
+        let mut data = hd_path_bytes(hd_path);
+        data.extend_from_slice(body);
+        let chunks: Vec<_> = data.chunks(CHUNK_SIZE).collect();
+        let mut response = vec![];
+        for (i, chunk) in chunks.iter().enumerate() {
+            let p1 = if i + 1 == chunks.len() { P1_LAST_CHUNK } else { 0 };
+            response = self.exchange(&apdu(INS_SIGN, p1, chunk))?;
+        }
+        if response.len() != SIGNATURE_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid signature from the Ledger"));
+        }
+        let signature = Signature::new(&response);
+        if !verify(hash(body).as_ref(), &signature, &public_key) {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid signature from the Ledger"));
+        }
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packets() {
+        let apdu = apdu(INS_SIGN, P1_LAST_CHUNK, &[7; 100]);
+        let packets = to_packets(&apdu);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0][..7], [0x01, 0x01, TAG_APDU, 0, 0, 0, 105]);
+        assert_eq!(packets[1][..5], [0x01, 0x01, TAG_APDU, 0, 1]);
+        assert_eq!(from_packets(&packets[..1]).unwrap(), None);
+        assert_eq!(from_packets(&packets).unwrap(), Some(apdu));
+
+        let mut response = vec![1, 2, 3];
+        response.extend_from_slice(&SW_OK.to_be_bytes());
+        assert_eq!(check_status(response).unwrap(), vec![1, 2, 3]);
+        let rejected = check_status(SW_REJECTED.to_be_bytes().to_vec()).unwrap_err();
+        assert_eq!(rejected.kind(), ErrorKind::PermissionDenied);
+    }
+}
@@ -1,10 +1,24 @@
 use clap::{App, Arg, ArgMatches, SubCommand};
-use primitives::signature::{get_key_pair, sign};
-use primitives::signer::{get_key_file, write_key_file};
-use primitives::test_utils::get_key_pair_from_seed;
+use network::noise::NodeKey;
+use primitives::aggregate_signature::BlsSecretKey;
+use primitives::hd_key::{derive_key_pair, generate_mnemonic, parse_hd_path, DEFAULT_HD_PATH};
+use primitives::remote_signer::{generate_auth_key, read_auth_key, write_auth_key, SigningService};
+use primitives::signature::{get_key_pair, get_secp256k1_key_pair, sign, PublicKey, SecretKey};
+use primitives::signer::{
+    export_key_file, get_bls_key_file, get_key_file, import_bls_key_file, import_key_file,
+    write_bls_key_file, write_key_file, InMemorySigner,
+};
+use primitives::test_utils::{get_bls_key_pair_from_seed, get_key_pair_from_seed};
+use std::env;
+use std::io::{self, BufRead};
+use std::net::TcpListener;
 use std::path::PathBuf;
 use std::process;
 
+use crate::ledger::Ledger;
+
+mod ledger;
+
 fn get_key_store_path(matches: &ArgMatches) -> PathBuf {
     matches
         .value_of("key_store_path")
@@ -21,19 +35,81 @@ fn sign_data(matches: &ArgMatches) {
     let data = matches.value_of("data").unwrap();
     let bytes = base64::decode(data).unwrap();
     let signature = sign(&bytes, &key_file.secret_key);
-    let encoded = base64::encode(&signature);
+    let encoded = base64::encode(&signature.to_bytes());
     print!("{}", encoded);
 }
 
+/// Environment variable with the passphrase of the mnemonic, if it has one.
+const MNEMONIC_PASSPHRASE_ENV_VAR: &str = "NEAR_MNEMONIC_PASSPHRASE";
+
+/// Derives the key pair at the HD path from the mnemonic read from the standard input.
+fn derive_key_from_mnemonic(matches: &ArgMatches) -> (PublicKey, SecretKey) {
+    let mut mnemonic = String::new();
+    io::stdin().lock().read_line(&mut mnemonic).unwrap();
+    let passphrase = env::var(MNEMONIC_PASSPHRASE_ENV_VAR).unwrap_or_default();
+    let hd_path = matches.value_of("hd_path").unwrap();
+    derive_key_pair(&mnemonic, &passphrase, hd_path).unwrap_or_else(|e| {
+        println!("{}", e);
+        process::exit(1);
+    })
+}
+
 fn generate_key(matches: &ArgMatches) {
     let key_store_path = get_key_store_path(matches);
-    let (public_key, secret_key) = match matches.value_of("test_seed") {
-        Some(seed_string) => get_key_pair_from_seed(&seed_string),
-        None => get_key_pair()
+    let key_type = matches.value_of("key_type");
+    let (public_key, secret_key) = match (matches.value_of("test_seed"), key_type) {
+        (Some(seed_string), _) => get_key_pair_from_seed(&seed_string),
+        (None, _) if matches.is_present("from_mnemonic") => derive_key_from_mnemonic(matches),
+        (None, Some("secp256k1")) => get_secp256k1_key_pair(),
+        (None, _) => get_key_pair()
     };
     write_key_file(&key_store_path, public_key, secret_key);
 }
 
+/// Prints a new mnemonic to back up and derive the keys from.
+fn generate_mnemonic_phrase(matches: &ArgMatches) {
+    let words = matches.value_of("words").unwrap().parse().unwrap_or(0);
+    match generate_mnemonic(words) {
+        Ok(mnemonic) => print!("{}", mnemonic),
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn generate_bls_key(matches: &ArgMatches) {
+    let key_store_path = get_key_store_path(matches);
+    let (public_key, secret_key) = match matches.value_of("test_seed") {
+        Some(seed_string) => get_bls_key_pair_from_seed(&seed_string),
+        None => {
+            let secret_key = BlsSecretKey::generate();
+            (secret_key.get_public_key(), secret_key)
+        }
+    };
+    print!("{}", write_bls_key_file(&key_store_path, public_key, secret_key));
+}
+
+/// Prints the base64 encoded BLS public key and its proof of possession, to be staked with.
+fn get_bls_proof_of_possession(matches: &ArgMatches) {
+    let key_store_path = get_key_store_path(matches);
+    let public_key = matches.value_of("public_key").map(String::from);
+    let key_file = get_bls_key_file(&key_store_path, public_key);
+    let proof = key_file.secret_key.get_proof_of_possession();
+    print!(
+        "{} {}",
+        base64::encode(&Vec::from(&key_file.public_key)),
+        base64::encode(&Vec::from(&proof)),
+    );
+}
+
+fn generate_node_key(matches: &ArgMatches) {
+    let node_key_path = matches.value_of("node_key_path").map(PathBuf::from).unwrap();
+    let node_key = NodeKey::generate();
+    node_key.write_to_file(&node_key_path).unwrap();
+    print!("{}", node_key.node_id());
+}
+
 fn get_public_key(matches: &ArgMatches) {
     let key_store_path = get_key_store_path(matches);
     let public_key = None;
@@ -41,6 +117,94 @@ fn get_public_key(matches: &ArgMatches) {
     print!("{}", key_file.public_key);
 }
 
+/// Prints the key file in the plain format, decrypted if it's encrypted.
+fn export_key(matches: &ArgMatches) {
+    let key_store_path = get_key_store_path(matches);
+    let public_key = matches.value_of("public_key").map(String::from);
+    print!("{}", export_key_file(&key_store_path, public_key));
+}
+
+/// Imports the key file into the key store, encrypted if the passphrase is set.
+fn import_key(matches: &ArgMatches) {
+    let key_store_path = get_key_store_path(matches);
+    let key_file_path = matches.value_of("key_file").map(PathBuf::from).unwrap();
+    let public_key = if matches.is_present("bls") {
+        import_bls_key_file(&key_store_path, &key_file_path)
+    } else {
+        import_key_file(&key_store_path, &key_file_path)
+    };
+    print!("{}", public_key);
+}
+
+fn generate_remote_signer_auth_key(matches: &ArgMatches) {
+    let auth_key_path = matches.value_of("auth_key_file").map(PathBuf::from).unwrap();
+    write_auth_key(&auth_key_path, &generate_auth_key()).unwrap();
+}
+
+/// Signs the blocks of the node with the BLS key from the keystore, at most one block of a kind
+/// at an index.
+fn run_remote_signer(matches: &ArgMatches) {
+    let key_store_path = get_key_store_path(matches);
+    let public_key = matches.value_of("public_key").map(String::from);
+    let key_file = get_bls_key_file(&key_store_path, public_key);
+    let signer = InMemorySigner {
+        account_id: String::new(),
+        public_key: key_file.public_key,
+        secret_key: key_file.secret_key,
+    };
+    let auth_key_path = matches.value_of("auth_key_file").map(PathBuf::from).unwrap();
+    let auth_key = read_auth_key(&auth_key_path).unwrap_or_else(|e| {
+        println!("Cannot read the auth key: {}", e);
+        process::exit(1);
+    });
+    let state_path = matches.value_of("state_file").map(PathBuf::from).unwrap();
+    let service = SigningService::new(signer, auth_key, &state_path).unwrap_or_else(|e| {
+        println!("Cannot read the signed blocks: {}", e);
+        process::exit(1);
+    });
+    let addr = matches.value_of("addr").unwrap();
+    let listener = TcpListener::bind(addr).unwrap();
+    println!("Signing the blocks at {}", addr);
+    service.serve(listener, |e| eprintln!("Failed to serve the signing request: {}", e));
+}
+
+fn open_ledger(matches: &ArgMatches) -> (Ledger, Vec<u32>) {
+    let hd_path = parse_hd_path(matches.value_of("hd_path").unwrap()).unwrap_or_else(|e| {
+        println!("{}", e);
+        process::exit(1);
+    });
+    let ledger = Ledger::open().unwrap_or_else(|e| {
+        println!("Cannot open the Ledger: {}", e);
+        process::exit(6);
+    });
+    (ledger, hd_path)
+}
+
+fn get_ledger_public_key(matches: &ArgMatches) {
+    let (mut ledger, hd_path) = open_ledger(matches);
+    match ledger.get_public_key(&hd_path) {
+        Ok(public_key) => print!("{}", public_key),
+        Err(e) => {
+            println!("{}", e);
+            process::exit(6);
+        }
+    }
+}
+
+/// Signs the transaction body on the Ledger, which signs its hash like `sign` does.
+fn ledger_sign_data(matches: &ArgMatches) {
+    let (mut ledger, hd_path) = open_ledger(matches);
+    let data = matches.value_of("data").unwrap();
+    let body = base64::decode(data).unwrap();
+    match ledger.sign(&hd_path, &body) {
+        Ok(signature) => print!("{}", base64::encode(&signature.to_bytes())),
+        Err(e) => {
+            println!("{}", e);
+            process::exit(6);
+        }
+    }
+}
+
 fn main() {
     let key_store_path_arg = &Arg::with_name("key_store_path")
         .short("p")
@@ -50,6 +214,20 @@ fn main() {
         .default_value("keystore")
         .required(true)
         .takes_value(true);
+    let auth_key_file_arg = &Arg::with_name("auth_key_file")
+        .long("auth-key-file")
+        .value_name("AUTH_KEY_FILE")
+        .help("Sets a file location for the key shared by the node and the signer")
+        .default_value("signer_auth_key")
+        .required(true)
+        .takes_value(true);
+    let hd_path_arg = &Arg::with_name("hd_path")
+        .long("hd-path")
+        .value_name("HD_PATH")
+        .help("Sets the HD path of the key on the Ledger or derived from the mnemonic")
+        .default_value(DEFAULT_HD_PATH)
+        .required(true)
+        .takes_value(true);
     let matches = App::new("keystore")
         .subcommand(SubCommand::with_name("keygen")
             .arg(key_store_path_arg)
@@ -62,9 +240,126 @@ fn main() {
                      creating key pairs during tests.",
                      )
                      .takes_value(true),
+            )
+            .arg(Arg::with_name("key_type")
+                     .long("key-type")
+                     .value_name("KEY_TYPE")
+                     .help("Sets the type of the key, secp256k1 keys are the keys of Ethereum")
+                     .possible_values(&["ed25519", "secp256k1"])
+                     .default_value("ed25519")
+                     .takes_value(true),
+            )
+            .arg(Arg::with_name("from_mnemonic")
+                     .long("from-mnemonic")
+                     .help("Derives the key at the HD path from the mnemonic read from stdin"),
+            )
+            .arg(hd_path_arg))
+        .subcommand(SubCommand::with_name("generate_mnemonic")
+            .arg(Arg::with_name("words")
+                     .long("words")
+                     .value_name("WORDS")
+                     .help("Sets the number of words of the mnemonic")
+                     .possible_values(&["12", "15", "18", "21", "24"])
+                     .default_value("24")
+                     .takes_value(true),
+            ))
+        .subcommand(SubCommand::with_name("bls_keygen")
+            .arg(key_store_path_arg)
+            .arg(Arg::with_name("test_seed")
+                     .long("test-seed")
+                     .value_name("TEST_SEED")
+                     .help(
+                         "Specify a seed for generating a BLS key pair.\
+                     This should only be used for deterministically \
+                     creating key pairs during tests.",
+                     )
+                     .takes_value(true),
+            ))
+        .subcommand(SubCommand::with_name("bls_proof_of_possession")
+            .arg(key_store_path_arg)
+            .arg(Arg::with_name("public_key")
+                .short("k")
+                .long("public-key")
+                .value_name("PUBLIC_KEY")
+                .help("Sets BLS public key to prove, \
+                    can be omitted with 1 file in keystore")
+                .takes_value(true)
+            ))
+        .subcommand(SubCommand::with_name("network_keygen")
+            .arg(Arg::with_name("node_key_path")
+                .short("n")
+                .long("node-key-path")
+                .value_name("NODE_KEY_PATH")
+                .help("Sets a file location for the node key")
+                .default_value("node_key.json")
+                .required(true)
+                .takes_value(true)
             ))
         .subcommand(SubCommand::with_name("get_public_key")
             .arg(key_store_path_arg))
+        .subcommand(SubCommand::with_name("export_key")
+            .arg(key_store_path_arg)
+            .arg(Arg::with_name("public_key")
+                .short("k")
+                .long("public-key")
+                .value_name("PUBLIC_KEY")
+                .help("Sets public key to export, \
+                    can be omitted with 1 file in keystore")
+                .takes_value(true)
+            ))
+        .subcommand(SubCommand::with_name("remote_signer_keygen")
+            .arg(auth_key_file_arg))
+        .subcommand(SubCommand::with_name("remote_signer")
+            .arg(key_store_path_arg)
+            .arg(auth_key_file_arg)
+            .arg(Arg::with_name("public_key")
+                .short("k")
+                .long("public-key")
+                .value_name("PUBLIC_KEY")
+                .help("Sets BLS public key to sign with, \
+                    can be omitted with 1 file in keystore")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("addr")
+                .long("addr")
+                .value_name("HOST:PORT")
+                .help("Sets the address to serve the node at")
+                .default_value("127.0.0.1:3040")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("state_file")
+                .long("state-file")
+                .value_name("STATE_FILE")
+                .help("Sets a file location for the last signed blocks")
+                .default_value("signer_state.json")
+                .takes_value(true)
+            ))
+        .subcommand(SubCommand::with_name("ledger_get_public_key")
+            .arg(hd_path_arg))
+        .subcommand(SubCommand::with_name("ledger_sign")
+            .arg(hd_path_arg)
+            .arg(Arg::with_name("data")
+                .short("d")
+                .long("data")
+                .value_name("DATA")
+                .help("base64 encoded transaction body")
+                .required(true)
+                .takes_value(true)
+            ))
+        .subcommand(SubCommand::with_name("import_key")
+            .arg(key_store_path_arg)
+            .arg(Arg::with_name("key_file")
+                .short("f")
+                .long("key-file")
+                .value_name("KEY_FILE")
+                .help("Sets the key file to import")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("bls")
+                .long("bls")
+                .help("Imports a BLS key file")
+            ))
         .subcommand(SubCommand::with_name("sign")
             .arg(key_store_path_arg)
             .arg(Arg::with_name("data")
@@ -87,10 +382,30 @@ fn main() {
 
     if let Some(sub) = matches.subcommand_matches("keygen") {
         generate_key(sub);
+    } else if let Some(sub) = matches.subcommand_matches("generate_mnemonic") {
+        generate_mnemonic_phrase(sub);
+    } else if let Some(sub) = matches.subcommand_matches("bls_keygen") {
+        generate_bls_key(sub);
+    } else if let Some(sub) = matches.subcommand_matches("bls_proof_of_possession") {
+        get_bls_proof_of_possession(sub);
+    } else if let Some(sub) = matches.subcommand_matches("network_keygen") {
+        generate_node_key(sub);
     } else if let Some(sub) = matches.subcommand_matches("sign") {
         sign_data(sub);
     } else if let Some(sub) = matches.subcommand_matches("get_public_key") {
         get_public_key(sub);
+    } else if let Some(sub) = matches.subcommand_matches("export_key") {
+        export_key(sub);
+    } else if let Some(sub) = matches.subcommand_matches("import_key") {
+        import_key(sub);
+    } else if let Some(sub) = matches.subcommand_matches("remote_signer_keygen") {
+        generate_remote_signer_auth_key(sub);
+    } else if let Some(sub) = matches.subcommand_matches("remote_signer") {
+        run_remote_signer(sub);
+    } else if let Some(sub) = matches.subcommand_matches("ledger_get_public_key") {
+        get_ledger_public_key(sub);
+    } else if let Some(sub) = matches.subcommand_matches("ledger_sign") {
+        ledger_sign_data(sub);
     } else {
         println!("Incorrect usage. See usage with: keystore --help");
         process::exit(1);
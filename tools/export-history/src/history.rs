@@ -0,0 +1,185 @@
+//! Collects the transactions and receipts of an account from the persisted indexes.
+use chain::SignedBlock;
+use node_runtime::state_changes::account_amount_change;
+use primitives::hash::CryptoHash;
+use primitives::types::{AccountId, Balance, BlockId};
+use shard::ShardBlockChain;
+use transaction::ReceiptBody;
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub block_index: u64,
+    /// Hash of the transaction or nonce of the receipt.
+    pub id: String,
+    /// Kind of the transaction, or `Receipt`.
+    pub kind: String,
+    pub originator: AccountId,
+    pub receiver: Option<AccountId>,
+    pub amount: Balance,
+    /// Tip paid by the account.
+    pub fee: Balance,
+    pub status: String,
+    /// Amount on the account before and after the block, if the block changed it.
+    pub balance_before: Option<Balance>,
+    pub balance_after: Option<Balance>,
+}
+
+pub const CSV_HEADER: &str =
+    "block_index,id,kind,originator,receiver,amount,fee,status,balance_before,balance_after";
+
+fn csv_field(value: &str) -> String {
+    if value.contains(|c| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+impl HistoryEntry {
+    pub fn to_csv(&self) -> String {
+        [
+            self.block_index.to_string(),
+            self.id.clone(),
+            self.kind.clone(),
+            self.originator.clone(),
+            optional(&self.receiver),
+            self.amount.to_string(),
+            self.fee.to_string(),
+            self.status.clone(),
+            optional(&self.balance_before),
+            optional(&self.balance_after),
+        ]
+        .iter()
+        .map(|field| csv_field(field))
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+}
+
+/// Amount on the account before and after the block with the given index.
+fn balance_change(
+    chain: &ShardBlockChain,
+    account_id: &AccountId,
+    block_index: u64,
+) -> (Option<Balance>, Option<Balance>) {
+    chain
+        .chain
+        .get_block(&BlockId::Number(block_index))
+        .and_then(|block| chain.get_state_changes(&block.block_hash()))
+        .and_then(|changes| account_amount_change(&changes, account_id))
+        .unwrap_or((None, None))
+}
+
+fn entry(
+    chain: &ShardBlockChain,
+    account_id: &AccountId,
+    id: &CryptoHash,
+) -> Option<HistoryEntry> {
+    let mut entry = if let Some(info) = chain.get_transaction_info(id) {
+        let body = &info.transaction.body;
+        let originator = body.get_originator();
+        HistoryEntry {
+            block_index: info.block_index,
+            id: String::from(id),
+            kind: body.kind().to_string(),
+            fee: if originator == *account_id { info.result.tip } else { 0 },
+            originator,
+            receiver: body.get_contract_id(),
+            amount: body.get_amount(),
+            status: format!("{:?}", info.result.status),
+            balance_before: None,
+            balance_after: None,
+        }
+    } else {
+        let info = chain.get_receipt_info(id)?;
+        let amount = match &info.receipt.body {
+            ReceiptBody::NewCall(call) => call.amount,
+            ReceiptBody::Refund(amount) => *amount,
+            ReceiptBody::Callback(_) | ReceiptBody::ManaAccounting(_) => 0,
+        };
+        HistoryEntry {
+            block_index: info.block_index,
+            id: String::from(id),
+            kind: "Receipt".to_string(),
+            originator: info.receipt.originator,
+            receiver: Some(info.receipt.receiver),
+            amount,
+            fee: 0,
+            status: format!("{:?}", info.result.status),
+            balance_before: None,
+            balance_after: None,
+        }
+    };
+    let (before, after) = balance_change(chain, account_id, entry.block_index);
+    entry.balance_before = before;
+    entry.balance_after = after;
+    Some(entry)
+}
+
+/// Transactions and receipts of the account included into the blocks from `from_index` to
+/// `to_index`, both inclusive, in the order they were included.
+pub fn account_history(
+    chain: &ShardBlockChain,
+    account_id: &AccountId,
+    from_index: u64,
+    to_index: u64,
+) -> Vec<HistoryEntry> {
+    chain
+        .get_account_transactions(account_id)
+        .iter()
+        .filter_map(|id| entry(chain, account_id, id))
+        .filter(|entry| entry.block_index >= from_index && entry.block_index <= to_index)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use node_runtime::test_utils::{generate_test_chain_spec, signed_transaction};
+    use storage::test_utils::create_memory_db;
+    use transaction::{SendMoneyTransaction, TransactionBody};
+
+    use super::*;
+
+    #[test]
+    fn test_account_history() {
+        let (chain_spec, _) = generate_test_chain_spec();
+        let chain = ShardBlockChain::new(&chain_spec, Arc::new(create_memory_db()));
+        let transaction = signed_transaction(TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce: 1,
+            originator: "alice.near".to_string(),
+            receiver: "bob.near".to_string(),
+            amount: 10,
+            block_hash: CryptoHash::default(),
+            genesis_hash: chain.genesis_hash(),
+            execute_at: 0,
+            tip: 2,
+            memo: vec![],
+        }));
+        let (block, (db_changes, _, tx_results, new_receipts)) =
+            chain.prepare_new_block(chain.genesis_hash(), vec![], vec![transaction.clone()]);
+        chain.insert_block(&block, db_changes, tx_results, new_receipts).unwrap();
+
+        let alice = "alice.near".to_string();
+        let history = account_history(&chain, &alice, 0, 10);
+        assert_eq!(history.len(), 1);
+        let entry = &history[0];
+        assert_eq!(entry.id, String::from(&transaction.get_hash()));
+        assert_eq!((entry.kind.as_str(), entry.amount, entry.fee), ("SendMoney", 10, 2));
+        assert_eq!(entry.receiver, Some("bob.near".to_string()));
+        let (before, after) = (entry.balance_before.unwrap(), entry.balance_after.unwrap());
+        assert_eq!(before - after, 12);
+        assert!(account_history(&chain, &alice, 2, 10).is_empty());
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("alice.near"), "alice.near");
+        assert_eq!(csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+}
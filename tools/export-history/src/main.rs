@@ -0,0 +1,98 @@
+//! Exports the transactions, receipts and balance changes of an account as CSV or JSON.
+//! Reads the storage of a stopped node.
+extern crate clap;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+extern crate chain;
+extern crate client;
+extern crate configs;
+extern crate node_runtime;
+extern crate primitives;
+extern crate shard;
+extern crate transaction;
+
+use std::path::{Path, PathBuf};
+
+use clap::{App, Arg};
+
+use configs::chain_spec::read_or_default_chain_spec;
+use shard::ShardBlockChain;
+
+mod history;
+
+fn main() {
+    let matches = App::new("export-history")
+        .arg(
+            Arg::with_name("base_path")
+                .short("d")
+                .long("base-path")
+                .value_name("PATH")
+                .help("Base path of the node.")
+                .default_value(".")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("chain_spec_file")
+                .short("c")
+                .long("chain-spec-file")
+                .value_name("CHAIN_SPEC")
+                .help("Chain spec of the node, if it doesn't run the default one.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("account_id")
+                .short("a")
+                .long("account-id")
+                .value_name("ACCOUNT_ID")
+                .help("Account to export the history of.")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("from_block")
+                .long("from-block")
+                .value_name("INDEX")
+                .help("First block to export.")
+                .default_value("0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("to_block")
+                .long("to-block")
+                .value_name("INDEX")
+                .help("Last block to export, the best block if not set.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .possible_values(&["csv", "json"])
+                .default_value("csv")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let base_path = matches.value_of("base_path").map(Path::new).unwrap();
+    let chain_spec_path = matches.value_of("chain_spec_file").map(PathBuf::from);
+    let chain_spec = read_or_default_chain_spec(&chain_spec_path);
+    let chain = ShardBlockChain::new(&chain_spec, client::get_storage(base_path));
+
+    let account_id = matches.value_of("account_id").unwrap().to_string();
+    let parse_index = |name| {
+        matches.value_of(name).map(|v| v.parse::<u64>().expect("Block index must be a number"))
+    };
+    let from_index = parse_index("from_block").unwrap();
+    let to_index = parse_index("to_block").unwrap_or_else(|| chain.chain.best_index());
+    let entries = history::account_history(&chain, &account_id, from_index, to_index);
+    match matches.value_of("format").unwrap() {
+        "json" => println!("{}", serde_json::to_string_pretty(&entries).unwrap()),
+        _ => {
+            println!("{}", history::CSV_HEADER);
+            for entry in entries {
+                println!("{}", entry.to_csv());
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Generates configurable traffic against a running node or directly against the runtime and
+//! reports the throughput and the latencies of the transactions.
+extern crate clap;
+extern crate futures;
+extern crate hyper;
+extern crate serde;
+extern crate serde_json;
+extern crate tokio;
+
+extern crate node_http;
+extern crate node_runtime;
+extern crate primitives;
+extern crate transaction;
+
+use std::process;
+use std::time::Duration;
+
+use clap::{App, Arg, ArgMatches};
+
+use crate::runtime::RuntimeLoad;
+use crate::traffic::TrafficMix;
+
+mod node;
+mod report;
+mod runtime;
+mod traffic;
+
+fn parse<T: std::str::FromStr>(matches: &ArgMatches, name: &str) -> T {
+    matches
+        .value_of(name)
+        .unwrap()
+        .parse()
+        .unwrap_or_else(|_| panic!("{} must be a number", name))
+}
+
+fn main() {
+    let matches = App::new("loadtester")
+        .arg(
+            Arg::with_name("rpc_url")
+                .long("rpc-url")
+                .value_name("URL")
+                .help("RPC of the node to send the transactions to, the runtime if not set.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tps")
+                .long("tps")
+                .value_name("TPS")
+                .help("Transactions to send per second.")
+                .default_value("100")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("duration_secs")
+                .long("duration-secs")
+                .value_name("SECS")
+                .help("How long to send the transactions for.")
+                .default_value("10")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("transfers")
+                .long("transfers")
+                .value_name("WEIGHT")
+                .help("Weight of the transfers in the traffic.")
+                .default_value("1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("calls")
+                .long("calls")
+                .value_name("WEIGHT")
+                .help("Weight of the contract calls in the traffic.")
+                .default_value("1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("deploys")
+                .long("deploys")
+                .value_name("WEIGHT")
+                .help("Weight of the contract deploys in the traffic.")
+                .default_value("0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("args_size")
+                .long("args-size")
+                .value_name("BYTES")
+                .help("Size of the arguments of the contract calls.")
+                .default_value("0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("account")
+                .long("account")
+                .value_name("ACCOUNT_ID")
+                .help("Account that sends the transactions to the node, signing with its test key.")
+                .multiple(true)
+                .default_value("alice.near,bob.near")
+                .use_delimiter(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("num_accounts")
+                .long("num-accounts")
+                .value_name("NUM")
+                .help("Accounts that send the transactions on the runtime.")
+                .default_value("10")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("block_period_ms")
+                .long("block-period-ms")
+                .value_name("MS")
+                .help("Time between the blocks on the runtime.")
+                .default_value("1000")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let mix = TrafficMix {
+        transfers: parse(&matches, "transfers"),
+        calls: parse(&matches, "calls"),
+        deploys: parse(&matches, "deploys"),
+        args_size: parse(&matches, "args_size"),
+    };
+    let tps = parse(&matches, "tps");
+    let duration = Duration::from_secs(parse(&matches, "duration_secs"));
+    let report = match matches.value_of("rpc_url") {
+        Some(rpc_url) => {
+            let account_ids: Vec<_> =
+                matches.values_of("account").unwrap().map(String::from).collect();
+            match node::run(mix, rpc_url, tps, duration, &account_ids) {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        None => {
+            let load = RuntimeLoad {
+                tps,
+                duration,
+                block_period: Duration::from_millis(parse(&matches, "block_period_ms")),
+                num_accounts: parse(&matches, "num_accounts"),
+            };
+            runtime::run(mix, &load)
+        }
+    };
+    print!("{}", report);
+}
@@ -0,0 +1,139 @@
+//! Load sent to a running node through its RPC. The transactions are signed upfront and sent at
+//! a fixed rate with `broadcast_tx_commit`, which returns once they are executed. The RPC
+//! doesn't tell the gas used by a block, so only the latencies are reported. The transactions
+//! reference the latest block at the start, so a run must end within the validity period of the
+//! chain.
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::{stream, Future, Stream};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Request};
+use serde::Serialize;
+use tokio::timer::Interval;
+
+use node_http::types::{
+    encode_transaction, BroadcastTxCommitRequest, StatusResponse, TransactionResultResponse,
+    ViewAccountRequest, ViewAccountResponse,
+};
+use primitives::types::AccountId;
+use transaction::FinalTransactionStatus;
+
+use crate::report::Report;
+use crate::traffic::{TrafficGenerator, TrafficMix};
+
+/// Posts the request to the endpoint of the RPC and returns the body of a successful response.
+fn post<T: Serialize>(
+    client: &Client<HttpConnector>,
+    rpc_url: &str,
+    path: &str,
+    request: &T,
+) -> impl Future<Item = Vec<u8>, Error = String> {
+    let request = Request::post(format!("{}{}", rpc_url, path))
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(request).unwrap()))
+        .unwrap();
+    send(client, request)
+}
+
+/// Status of the node, with the genesis hash and the latest block the transactions are signed
+/// for.
+fn status(
+    client: &Client<HttpConnector>,
+    rpc_url: &str,
+) -> impl Future<Item = StatusResponse, Error = String> {
+    let request = Request::get(format!("{}/status", rpc_url)).body(Body::empty()).unwrap();
+    send(client, request)
+        .and_then(|body| serde_json::from_slice(&body).map_err(|e| e.to_string()))
+}
+
+fn send(
+    client: &Client<HttpConnector>,
+    request: Request<Body>,
+) -> impl Future<Item = Vec<u8>, Error = String> {
+    client.request(request).map_err(|e| e.to_string()).and_then(|response| {
+        let status = response.status();
+        response.into_body().concat2().map_err(|e| e.to_string()).and_then(move |body| {
+            if status.is_success() {
+                Ok(body.to_vec())
+            } else {
+                Err(format!("{}: {}", status, String::from_utf8_lossy(&body)))
+            }
+        })
+    })
+}
+
+/// Sends `tps` transactions per second for the duration, between the given accounts, which
+/// must exist on the node and sign with the keys derived from their ids.
+pub fn run(
+    mix: TrafficMix,
+    rpc_url: &str,
+    tps: u64,
+    duration: Duration,
+    account_ids: &[AccountId],
+) -> Result<Report, String> {
+    let mut runtime = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+    let client = Client::new();
+    let mut accounts = vec![];
+    for account_id in account_ids {
+        let request = ViewAccountRequest { account_id: account_id.clone() };
+        let body = runtime.block_on(post(&client, rpc_url, "/view_account", &request))?;
+        let account: ViewAccountResponse =
+            serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+        accounts.push((account.account_id, account.nonce));
+    }
+    let status = runtime.block_on(status(&client, rpc_url))?;
+    let mut generator = TrafficGenerator::new(
+        mix,
+        accounts,
+        status.genesis_hash,
+        status.sync_info.latest_block_hash,
+    );
+    let transactions: Vec<_> =
+        (0..tps * duration.as_secs()).map(|_| generator.next_transaction()).collect();
+
+    let report = Arc::new(Mutex::new(Report::default()));
+    let started = Instant::now();
+    let period = Duration::from_secs(1) / tps.max(1) as u32;
+    let sending = {
+        let report = report.clone();
+        let rpc_url = rpc_url.to_string();
+        Interval::new(started, period)
+            .zip(stream::iter_ok(transactions))
+            .map_err(|e| e.to_string())
+            .for_each(move |(_, transaction)| {
+                let request = BroadcastTxCommitRequest {
+                    transaction: encode_transaction(transaction),
+                    timeout_ms: None,
+                    wait_until: None,
+                };
+                let report = report.clone();
+                let sent = Instant::now();
+                let response = post(&client, &rpc_url, "/broadcast_tx_commit", &request);
+                tokio::spawn(response.then(move |body| {
+                    let result = body.and_then(|body| {
+                        serde_json::from_slice::<TransactionResultResponse>(&body)
+                            .map_err(|e| e.to_string())
+                    });
+                    let mut report = report.lock().unwrap();
+                    match result {
+                        Ok(ref response)
+                            if response.result.status == FinalTransactionStatus::Completed =>
+                        {
+                            report.latencies.push(sent.elapsed())
+                        }
+                        _ => report.failed += 1,
+                    }
+                    Ok(())
+                }));
+                Ok(())
+            })
+    };
+    runtime.block_on(sending)?;
+    // Waits for the responses to the transactions sent last.
+    runtime.shutdown_on_idle().wait().unwrap();
+
+    let mut report = Arc::try_unwrap(report).unwrap().into_inner().unwrap();
+    report.elapsed = started.elapsed();
+    Ok(report)
+}
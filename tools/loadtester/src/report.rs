@@ -0,0 +1,78 @@
+//! Latencies of the transactions and gas used by the blocks during a load test.
+use std::fmt;
+use std::time::Duration;
+
+use primitives::types::{BlockIndex, Gas};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockReport {
+    pub index: BlockIndex,
+    pub transactions: usize,
+    /// Gas used by the contract calls executed in the block.
+    pub gas_used: Gas,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Time from sending each of the transactions until it was executed.
+    pub latencies: Vec<Duration>,
+    /// Transactions that failed or were not executed.
+    pub failed: usize,
+    /// Blocks of the test, if they are known.
+    pub blocks: Vec<BlockReport>,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    /// Latency that the given percent of the transactions didn't exceed, by the nearest rank.
+    pub fn percentile(&self, percent: u32) -> Option<Duration> {
+        let mut latencies = self.latencies.clone();
+        latencies.sort();
+        let rank = (latencies.len() * percent as usize + 99) / 100;
+        latencies.get(rank.max(1) - 1).cloned()
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let executed = self.latencies.len() as u64;
+        let elapsed = millis(self.elapsed).max(1);
+        let tps = executed * 1000 / elapsed;
+        writeln!(f, "Executed {} transactions in {} ms, {} TPS", executed, elapsed, tps)?;
+        writeln!(f, "Failed {} transactions", self.failed)?;
+        for percent in &[50, 90, 99, 100] {
+            if let Some(latency) = self.percentile(*percent) {
+                writeln!(f, "Latency p{}: {} ms", percent, millis(latency))?;
+            }
+        }
+        for block in self.blocks.iter() {
+            writeln!(
+                f,
+                "Block {}: {} transactions, {} gas",
+                block.index, block.transactions, block.gas_used
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let mut report = Report::default();
+        assert_eq!(report.percentile(50), None);
+        report.latencies = (1..=10).rev().map(Duration::from_millis).collect();
+        assert_eq!(report.percentile(0), Some(Duration::from_millis(1)));
+        assert_eq!(report.percentile(50), Some(Duration::from_millis(5)));
+        assert_eq!(report.percentile(90), Some(Duration::from_millis(9)));
+        assert_eq!(report.percentile(99), Some(Duration::from_millis(10)));
+        assert_eq!(report.percentile(100), Some(Duration::from_millis(10)));
+    }
+}
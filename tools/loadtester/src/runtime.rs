@@ -0,0 +1,127 @@
+//! Load applied directly with `Runtime::apply`, without a node. Blocks are produced once per
+//! block period of a simulated clock with the transactions that arrived during it, and take on
+//! it as long as applying them takes. A block that takes longer than the period delays the
+//! next ones, as on a node that can't keep up.
+use std::cmp::max;
+use std::time::{Duration, Instant};
+
+use node_runtime::test_utils::{to_receipt_block, GenesisBuilder};
+use node_runtime::ApplyState;
+use primitives::hash::{hash, CryptoHash};
+use primitives::types::{AccountId, Balance, Gas};
+use transaction::{ReceiptBody, ReceiptTransaction, TransactionStatus};
+
+use crate::report::{BlockReport, Report};
+use crate::traffic::{TrafficGenerator, TrafficMix, CONTRACT};
+
+/// Balance and transaction stake of the accounts, enough for any test.
+const GENESIS_BALANCE: Balance = 1_000_000_000;
+
+#[derive(Debug, Clone)]
+pub struct RuntimeLoad {
+    /// Transactions that arrive per second.
+    pub tps: u64,
+    pub duration: Duration,
+    pub block_period: Duration,
+    /// Number of the accounts that send the transactions to each other.
+    pub num_accounts: usize,
+}
+
+fn gas_used(receipts: &[ReceiptTransaction]) -> Gas {
+    receipts
+        .iter()
+        .map(|receipt| match &receipt.body {
+            ReceiptBody::ManaAccounting(accounting) => accounting.gas_used,
+            _ => 0,
+        })
+        .sum()
+}
+
+pub fn run(mix: TrafficMix, load: &RuntimeLoad) -> Report {
+    let account_ids: Vec<AccountId> =
+        (0..load.num_accounts).map(|i| format!("load{}.near", i)).collect();
+    let genesis = account_ids.iter().fold(GenesisBuilder::new(), |genesis, account_id| {
+        genesis
+            .account(account_id, GENESIS_BALANCE)
+            .tx_stake(account_id, GENESIS_BALANCE)
+            .code(account_id, CONTRACT)
+    });
+    let (mut runtime, _, mut root) = genesis.build();
+    let genesis_hash = hash(root.as_ref());
+    runtime.config.genesis_hash = Some(genesis_hash);
+    let accounts = account_ids.into_iter().map(|id| (id, 0)).collect();
+    // Expiry is disabled, the blocks have no hashes to reference.
+    let mut generator = TrafficGenerator::new(mix, accounts, genesis_hash, CryptoHash::default());
+    let block_period_ms = load.block_period.as_secs() * 1000
+        + u64::from(load.block_period.subsec_millis());
+    let per_block = (load.tps * block_period_ms / 1000) as u32;
+    let num_blocks = (load.duration.as_secs() * 1000 / block_period_ms.max(1)) as u32;
+
+    let mut report = Report::default();
+    let mut receipts = vec![];
+    // End of the previous block on the simulated clock.
+    let mut clock = Duration::default();
+    for index in 1..=num_blocks {
+        let period_end = load.block_period * index;
+        let transactions: Vec<_> = (0..per_block).map(|_| generator.next_transaction()).collect();
+        let apply_state = ApplyState {
+            root,
+            shard_id: 0,
+            block_index: u64::from(index),
+            parent_block_hash: CryptoHash::default(),
+        };
+        let num_receipts = receipts.len();
+        let started = Instant::now();
+        let result = runtime
+            .apply(&apply_state, &[to_receipt_block(receipts)], &transactions)
+            .expect("Failed to apply the block");
+        runtime.state_db.commit(result.db_changes).expect("Failed to commit the block");
+        clock = max(clock, period_end) + started.elapsed();
+        root = result.root;
+
+        let period_start = period_end - load.block_period;
+        let tx_results = result.tx_result[num_receipts..].iter().take(transactions.len());
+        for (i, tx_result) in tx_results.enumerate() {
+            if tx_result.status == TransactionStatus::Completed {
+                // Transactions arrive evenly during the block period.
+                let arrival = period_start + load.block_period * i as u32 / per_block;
+                report.latencies.push(clock - arrival);
+            } else {
+                report.failed += 1;
+            }
+        }
+        receipts = result.new_receipts.into_iter().flat_map(|(_, receipts)| receipts).collect();
+        report.blocks.push(BlockReport {
+            index: u64::from(index),
+            transactions: transactions.len(),
+            gas_used: gas_used(&receipts),
+        });
+    }
+    report.elapsed = clock;
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runtime_load() {
+        let mix = TrafficMix { transfers: 1, calls: 1, deploys: 0, args_size: 100 };
+        let load = RuntimeLoad {
+            tps: 20,
+            duration: Duration::from_secs(3),
+            block_period: Duration::from_millis(500),
+            num_accounts: 4,
+        };
+        let report = run(mix, &load);
+        assert_eq!(report.blocks.len(), 6);
+        assert!(report.blocks.iter().all(|block| block.transactions == 10));
+        assert_eq!(report.failed, 0);
+        assert_eq!(report.latencies.len(), 60);
+        // The calls are executed in the block after the one with their transactions.
+        assert_eq!(report.blocks[0].gas_used, 0);
+        assert!(report.blocks[1..].iter().all(|block| block.gas_used > 0));
+        assert!(report.percentile(100).unwrap() >= report.percentile(50).unwrap());
+    }
+}
@@ -0,0 +1,140 @@
+//! Signed transactions of a mix of kinds, sent by the accounts in turn. Accounts sign with the
+//! keys derived from their ids, see `get_key_pair_from_seed`.
+use node_runtime::test_utils::sign_transaction;
+use primitives::hash::CryptoHash;
+use primitives::test_utils::get_key_pair_from_seed;
+use primitives::types::AccountId;
+use transaction::{
+    DeployContractTransaction, FunctionCallTransaction, SendMoneyTransaction, SignedTransaction,
+    TransactionBody,
+};
+
+/// Contract that the calls are made to and that is deployed, the one in the test chain spec.
+pub const CONTRACT: &[u8] = include_bytes!("../../../core/wasm/runtest/res/wasm_with_mem.wasm");
+/// Method of `CONTRACT` that the calls are made to.
+const METHOD: &str = "run_test_with_storage_change";
+
+/// Weights of the kinds of transactions in the traffic.
+#[derive(Debug, Clone)]
+pub struct TrafficMix {
+    pub transfers: u32,
+    pub calls: u32,
+    pub deploys: u32,
+    /// Size of the arguments of the calls, in bytes.
+    pub args_size: usize,
+}
+
+pub struct TrafficGenerator {
+    mix: TrafficMix,
+    accounts: Vec<AccountId>,
+    /// Last nonce of each of the accounts.
+    nonces: Vec<u64>,
+    /// Genesis hash of the chain the transactions are signed for.
+    genesis_hash: CryptoHash,
+    /// Block the transactions reference, they expire some blocks after it.
+    block_hash: CryptoHash,
+    generated: u64,
+}
+
+impl TrafficGenerator {
+    /// Generator of the traffic between the accounts, given with their current nonces, for the
+    /// chain with the genesis hash, referencing the block with the block hash.
+    pub fn new(
+        mix: TrafficMix,
+        accounts: Vec<(AccountId, u64)>,
+        genesis_hash: CryptoHash,
+        block_hash: CryptoHash,
+    ) -> Self {
+        assert!(!accounts.is_empty(), "Traffic needs at least one account");
+        assert!(mix.transfers + mix.calls + mix.deploys > 0, "Traffic mix is empty");
+        let (accounts, nonces) = accounts.into_iter().unzip();
+        TrafficGenerator { mix, accounts, nonces, genesis_hash, block_hash, generated: 0 }
+    }
+
+    /// Next transaction of the mix. The kinds come in turn in the proportions of their weights,
+    /// the originators in turn, and every transaction goes to the account after its originator.
+    pub fn next_transaction(&mut self) -> SignedTransaction {
+        let total = u64::from(self.mix.transfers + self.mix.calls + self.mix.deploys);
+        let slot = self.generated % total;
+        let index = self.generated as usize % self.accounts.len();
+        self.generated += 1;
+        self.nonces[index] += 1;
+        let nonce = self.nonces[index];
+        let originator = self.accounts[index].clone();
+        let receiver = self.accounts[(index + 1) % self.accounts.len()].clone();
+        let body = if slot < u64::from(self.mix.transfers) {
+            TransactionBody::SendMoney(SendMoneyTransaction {
+                nonce,
+                originator: originator.clone(),
+                receiver,
+                amount: 1,
+                block_hash: self.block_hash,
+                genesis_hash: self.genesis_hash,
+                execute_at: 0,
+                tip: 0,
+                memo: vec![],
+            })
+        } else if slot < u64::from(self.mix.transfers + self.mix.calls) {
+            TransactionBody::FunctionCall(FunctionCallTransaction {
+                nonce,
+                originator: originator.clone(),
+                contract_id: receiver,
+                method_name: METHOD.as_bytes().to_vec(),
+                args: vec![0; self.mix.args_size],
+                amount: 0,
+                block_hash: self.block_hash,
+                genesis_hash: self.genesis_hash,
+                execute_at: 0,
+                tip: 0,
+            })
+        } else {
+            TransactionBody::DeployContract(DeployContractTransaction {
+                nonce,
+                originator: originator.clone(),
+                contract_id: originator.clone(),
+                wasm_byte_array: CONTRACT.to_vec(),
+                public_key: vec![],
+                block_hash: self.block_hash,
+                genesis_hash: self.genesis_hash,
+                execute_at: 0,
+                tip: 0,
+            })
+        };
+        sign_transaction(body, &get_key_pair_from_seed(&originator).1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traffic_mix() {
+        let mix = TrafficMix { transfers: 2, calls: 1, deploys: 1, args_size: 10 };
+        let accounts = vec![("alice.near".to_string(), 5), ("bob.near".to_string(), 0)];
+        let mut generator =
+            TrafficGenerator::new(mix, accounts, CryptoHash::default(), CryptoHash::default());
+        let transactions: Vec<_> = (0..8).map(|_| generator.next_transaction()).collect();
+        let kinds: Vec<_> = transactions
+            .iter()
+            .map(|transaction| match &transaction.body {
+                TransactionBody::SendMoney(_) => "transfer",
+                TransactionBody::FunctionCall(t) => {
+                    assert_eq!(t.args.len(), 10);
+                    "call"
+                }
+                TransactionBody::DeployContract(_) => "deploy",
+                _ => unreachable!(),
+            })
+            .collect();
+        let mix = ["transfer", "transfer", "call", "deploy"];
+        assert_eq!(kinds, mix.iter().chain(mix.iter()).cloned().collect::<Vec<_>>());
+        // Nonces of every account go on from the ones it had.
+        let nonces: Vec<_> = transactions
+            .iter()
+            .filter(|transaction| transaction.body.get_originator() == "alice.near")
+            .map(|transaction| transaction.body.get_nonce())
+            .collect();
+        assert_eq!(nonces, vec![6, 7, 8, 9]);
+    }
+}
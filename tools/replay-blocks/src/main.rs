@@ -0,0 +1,80 @@
+//! Replays the blocks stored by a stopped node through the runtime and reports the first block
+//! whose state root or outcome root is not the one in its header, to debug consensus bugs.
+extern crate clap;
+
+extern crate chain;
+extern crate client;
+extern crate configs;
+extern crate node_runtime;
+extern crate primitives;
+extern crate shard;
+extern crate storage;
+
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::{App, Arg};
+
+use configs::chain_spec::read_or_default_chain_spec;
+use shard::ShardBlockChain;
+
+mod replay;
+
+fn main() {
+    let matches = App::new("replay-blocks")
+        .arg(
+            Arg::with_name("base_path")
+                .short("d")
+                .long("base-path")
+                .value_name("PATH")
+                .help("Base path of the node.")
+                .default_value(".")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("chain_spec_file")
+                .short("c")
+                .long("chain-spec-file")
+                .value_name("CHAIN_SPEC")
+                .help("Chain spec of the node, if it doesn't run the default one.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("from_block")
+                .long("from-block")
+                .value_name("INDEX")
+                .help("First block to replay, on the stored state of the block before it.")
+                .default_value("0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("to_block")
+                .long("to-block")
+                .value_name("INDEX")
+                .help("Last block to replay, the best block if not set.")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let base_path = matches.value_of("base_path").map(Path::new).unwrap();
+    let chain_spec_path = matches.value_of("chain_spec_file").map(PathBuf::from);
+    let chain_spec = read_or_default_chain_spec(&chain_spec_path);
+    let chain = ShardBlockChain::new(&chain_spec, client::get_storage(base_path));
+
+    let parse_index = |name| {
+        matches.value_of(name).map(|v| v.parse::<u64>().expect("Block index must be a number"))
+    };
+    let from_index = parse_index("from_block").unwrap();
+    let to_index = parse_index("to_block").unwrap_or_else(|| chain.chain.best_index());
+    match replay::replay(&chain, &chain_spec, from_index, to_index) {
+        Ok(None) => println!("Blocks {} to {} replay to the stored roots", from_index, to_index),
+        Ok(Some(divergence)) => {
+            println!("{}", divergence);
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    }
+}
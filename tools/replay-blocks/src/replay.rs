@@ -0,0 +1,218 @@
+//! Applies the stored blocks again on a state of its own and compares the roots they give with
+//! the ones in their headers.
+use std::fmt;
+use std::sync::Arc;
+
+use chain::{SignedBlock, SignedShardBlock};
+use configs::chain_spec::ChainSpec;
+use node_runtime::{ApplyState, Runtime};
+use primitives::hash::CryptoHash;
+use primitives::merkle::merklize;
+use primitives::types::{BlockId, BlockIndex, MerkleHash};
+use shard::{outcomes, ShardBlockChain};
+use storage::test_utils::create_memory_db;
+use storage::StateDb;
+
+/// First block whose replay doesn't give the roots in its header.
+#[derive(Debug, PartialEq)]
+pub struct Divergence {
+    pub block_index: BlockIndex,
+    pub block_hash: CryptoHash,
+    pub expected_state_root: MerkleHash,
+    pub state_root: MerkleHash,
+    pub expected_outcome_root: MerkleHash,
+    pub outcome_root: MerkleHash,
+    /// Hash of the first transaction or nonce of the first receipt whose replayed outcome is not
+    /// the stored one.
+    pub outcome_id: Option<CryptoHash>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Block {} ({}) diverges", self.block_index, self.block_hash)?;
+        writeln!(f, "  state root: {}, stored {}", self.state_root, self.expected_state_root)?;
+        write!(f, "  outcome root: {}, stored {}", self.outcome_root, self.expected_outcome_root)?;
+        if let Some(id) = &self.outcome_id {
+            write!(f, "\n  first outcome that differs: {}", id)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReplayError {
+    /// Block with the index is not in the storage.
+    MissingBlock(BlockIndex),
+    /// State of the block to start from can't be copied.
+    Snapshot(String),
+    /// Block can't be applied, the replayed state misses the nodes it changes.
+    Apply(BlockIndex, String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::MissingBlock(index) => write!(f, "Block {} is not stored", index),
+            ReplayError::Snapshot(e) => write!(f, "Failed to copy the state: {}", e),
+            ReplayError::Apply(index, e) => write!(f, "Failed to apply block {}: {}", index, e),
+        }
+    }
+}
+
+/// Replays the blocks from `from_index` to `to_index`. From the genesis, the state is built from
+/// the chain spec, otherwise it is copied from the stored state of the block before
+/// `from_index`. The stored state is never written to. Stops at the first divergent block.
+pub fn replay(
+    chain: &ShardBlockChain,
+    chain_spec: &ChainSpec,
+    from_index: BlockIndex,
+    to_index: BlockIndex,
+) -> Result<Option<Divergence>, ReplayError> {
+    let get_block = |index| {
+        chain.chain.get_block(&BlockId::Number(index)).ok_or(ReplayError::MissingBlock(index))
+    };
+    let state_db = Arc::new(StateDb::new(Arc::new(create_memory_db())));
+    let mut runtime = Runtime::new(state_db.clone());
+    runtime.config = chain.runtime.read().config.clone();
+    let mut root = if from_index == 0 {
+        let genesis = get_block(0)?;
+        let root = runtime.apply_genesis_state(
+            &chain_spec.accounts,
+            &chain_spec.genesis_wasm,
+            &chain_spec.initial_authorities,
+        );
+        let expected_root = genesis.body.header.merkle_root_state;
+        if root != expected_root {
+            return Ok(Some(Divergence {
+                block_index: 0,
+                block_hash: genesis.block_hash(),
+                expected_state_root: expected_root,
+                state_root: root,
+                expected_outcome_root: genesis.body.header.outcome_root,
+                outcome_root: MerkleHash::default(),
+                outcome_id: None,
+            }));
+        }
+        root
+    } else {
+        let root = get_block(from_index - 1)?.body.header.merkle_root_state;
+        let part = chain.state_db.get_state_part(&root, 0, 1).map_err(ReplayError::Snapshot)?;
+        state_db.apply_state_part(&root, 0, 1, &part).map_err(ReplayError::Snapshot)?;
+        root
+    };
+    for index in from_index.max(1)..=to_index {
+        let block = get_block(index)?;
+        if let Some(divergence) = apply_block(&mut runtime, chain, &block, &mut root)? {
+            return Ok(Some(divergence));
+        }
+    }
+    Ok(None)
+}
+
+/// Applies the block on top of `root` and moves it to the new root, unless the block diverges.
+fn apply_block(
+    runtime: &mut Runtime,
+    chain: &ShardBlockChain,
+    block: &SignedShardBlock,
+    root: &mut MerkleHash,
+) -> Result<Option<Divergence>, ReplayError> {
+    let header = &block.body.header;
+    let apply_state = ApplyState {
+        root: *root,
+        shard_id: header.shard_id,
+        block_index: header.index,
+        parent_block_hash: header.parent_hash,
+    };
+    let result = runtime
+        .apply(&apply_state, &block.body.receipts, &block.body.transactions)
+        .map_err(|e| ReplayError::Apply(header.index, e))?;
+    let block_outcomes =
+        outcomes(&block.body.receipts, &block.body.transactions, &result.tx_result);
+    let (outcome_root, _) = merklize(&block_outcomes);
+    if result.root != header.merkle_root_state || outcome_root != header.outcome_root {
+        let stored_outcomes = chain.get_block_outcomes(block);
+        let outcome_id = block_outcomes
+            .iter()
+            .zip(stored_outcomes.iter())
+            .find(|(outcome, stored)| outcome != stored)
+            .map(|(outcome, _)| outcome.id);
+        return Ok(Some(Divergence {
+            block_index: header.index,
+            block_hash: block.block_hash(),
+            expected_state_root: header.merkle_root_state,
+            state_root: result.root,
+            expected_outcome_root: header.outcome_root,
+            outcome_root,
+            outcome_id,
+        }));
+    }
+    runtime.state_db.commit(result.db_changes).expect("Failed to commit the replayed block");
+    *root = result.root;
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use node_runtime::test_utils::{generate_test_chain_spec, signed_transaction};
+    use transaction::{SendMoneyTransaction, TransactionBody};
+
+    use super::*;
+
+    fn send_money(chain: &ShardBlockChain, nonce: u64) -> TransactionBody {
+        TransactionBody::SendMoney(SendMoneyTransaction {
+            nonce,
+            originator: "alice.near".to_string(),
+            receiver: "bob.near".to_string(),
+            amount: 10,
+            block_hash: CryptoHash::default(),
+            genesis_hash: chain.genesis_hash(),
+            execute_at: 0,
+            tip: 0,
+            memo: vec![],
+        })
+    }
+
+    /// Chain with a block of a transaction and a block of its receipt. `tamper` changes the
+    /// header of the second block before it is stored.
+    fn test_chain<F: FnOnce(&mut SignedShardBlock)>(tamper: F) -> (ShardBlockChain, ChainSpec) {
+        let (chain_spec, _) = generate_test_chain_spec();
+        let chain = ShardBlockChain::new(&chain_spec, Arc::new(create_memory_db()));
+        let transaction = signed_transaction(send_money(&chain, 1));
+        let (block, (db_changes, _, tx_results, new_receipts)) =
+            chain.prepare_new_block(chain.genesis_hash(), vec![], vec![transaction]);
+        chain.insert_block(&block, db_changes, tx_results, new_receipts).unwrap();
+        let receipts = chain.get_receipt_block(1, 0).unwrap();
+        let (mut block, (db_changes, _, tx_results, new_receipts)) =
+            chain.prepare_new_block(block.hash, vec![receipts], vec![]);
+        tamper(&mut block);
+        chain.insert_block(&block, db_changes, tx_results, new_receipts).unwrap();
+        (chain, chain_spec)
+    }
+
+    #[test]
+    fn test_replay_matches() {
+        let (chain, chain_spec) = test_chain(|_| {});
+        assert_eq!(replay(&chain, &chain_spec, 0, 2), Ok(None));
+        // From the stored state of the first block.
+        assert_eq!(replay(&chain, &chain_spec, 2, 2), Ok(None));
+        assert_eq!(replay(&chain, &chain_spec, 0, 3), Err(ReplayError::MissingBlock(3)));
+    }
+
+    #[test]
+    fn test_replay_finds_divergent_block() {
+        let (chain, chain_spec) = test_chain(|block| {
+            block.body.header.merkle_root_state = CryptoHash::default();
+        });
+        let divergence = replay(&chain, &chain_spec, 0, 2).unwrap().unwrap();
+        assert_eq!(divergence.block_index, 2);
+        assert_eq!(divergence.expected_state_root, CryptoHash::default());
+        assert_ne!(divergence.state_root, CryptoHash::default());
+        assert_eq!(divergence.outcome_root, divergence.expected_outcome_root);
+        assert_eq!(divergence.outcome_id, None);
+
+        let mut chain_spec = chain_spec;
+        chain_spec.accounts[0].2 += 1;
+        let divergence = replay(&chain, &chain_spec, 0, 2).unwrap().unwrap();
+        assert_eq!(divergence.block_index, 0);
+    }
+}